@@ -0,0 +1,51 @@
+//! Sequential `insert_many` against `insert_many_concurrent` over every core
+//!
+//! Run with `cargo bench --bench concurrent_insert`.
+
+use std::thread;
+
+use ark_bn254::Fr;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use merkle_poseidon::{MerklePath, SparseMerkleTree};
+
+const DEPTH: usize = 32;
+const LEAVES: u64 = 1 << 10;
+
+fn entries() -> Vec<(MerklePath, Fr)> {
+    // Spread the paths over the tree with an odd multiplier
+    (0..LEAVES)
+        .map(|leaf| {
+            let path = leaf.wrapping_mul(0x9e37_79b9) % (1 << DEPTH);
+            (Fr::from(path), Fr::from(leaf + 1))
+        })
+        .collect()
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let entries = entries();
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut group = c.benchmark_group(format!("insert {LEAVES} leaves at depth {DEPTH}"));
+    group.sample_size(10);
+
+    group.bench_function("insert_many", |b| {
+        b.iter_batched(
+            || SparseMerkleTree::new(DEPTH).unwrap(),
+            |mut tree| tree.insert_many(entries.clone()).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function(format!("insert_many_concurrent, {threads} threads"), |b| {
+        b.iter_batched(
+            || SparseMerkleTree::new(DEPTH).unwrap(),
+            |mut tree| {
+                tree.insert_many_concurrent(entries.clone(), threads)
+                    .unwrap()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_insert);
+criterion_main!(benches);
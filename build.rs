@@ -0,0 +1,32 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+/// Write the C header of `src/ffi.rs` to `$OUT_DIR/merkle_poseidon.h`
+///
+/// The build never writes to the source tree, `include/merkle_poseidon.h` is the committed copy
+/// that `tests/ffi.rs` keeps up to date with this one.
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    use std::{env, path::PathBuf};
+
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+
+    let crate_dir =
+        PathBuf::from(env::var("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR"));
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("cargo sets OUT_DIR"));
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        header: Some("/* Generated by cbindgen from src/ffi.rs, do not edit */".into()),
+        include_guard: Some("MERKLE_POSEIDON_H".into()),
+        usize_is_size_t: true,
+        ..Default::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/ffi.rs"))
+        .generate()
+        .expect("src/ffi.rs can't be turned into a C header")
+        .write_to_file(out_dir.join("merkle_poseidon.h"));
+}
@@ -0,0 +1,170 @@
+//! A tonic service proving and verifying the leaves of a tree loaded from a snapshot
+//!
+//! Run with `cargo run --example proof_server --features proto,sync -- tree.bin`, where
+//! `tree.bin` is a snapshot such as the one `merkle-poseidon build` writes. Without an argument
+//! a small demo tree is served. The service is `ProofService` of `proto/merkle_poseidon.proto`;
+//! this file writes by hand the server code `tonic-prost-build` would generate from it, so the
+//! example builds without `protoc`.
+
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use merkle_poseidon::{
+    proto::{LeafRequest, Proof, ProofRequest, ProtoError, TreeSnapshot},
+    MerkleProof, PoseidonMerkleError, SparseMerkleTree,
+};
+use tonic::{
+    codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError},
+    server::{Grpc, NamedService, UnaryService},
+    Code, Request, Response, Status,
+};
+
+type Tree = SparseMerkleTree<Poseidon<Fr>>;
+
+/// The reply of `Verify`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VerifyReply {
+    #[prost(bool, tag = "1")]
+    pub valid: bool,
+}
+
+/// Answers the RPCs from one tree, the `sync` feature makes it shareable across connections
+struct ProofService {
+    tree: Mutex<Tree>,
+}
+
+/// Conversion errors are the client's, a missing leaf is not found and anything else internal
+fn status(error: ProtoError) -> Status {
+    let code = match &error {
+        ProtoError::Tree(PoseidonMerkleError::LeafNotFound { .. }) => Code::NotFound,
+        ProtoError::Tree(_) => Code::Internal,
+        _ => Code::InvalidArgument,
+    };
+    Status::new(code, error.to_string())
+}
+
+impl ProofService {
+    fn prove(&self, request: ProofRequest) -> Result<Proof, ProtoError> {
+        let request = LeafRequest::try_from(request)?;
+        let tree = self.tree.lock().expect("no RPC panics holding the tree");
+        if request.depth != tree.depth {
+            return Err(ProtoError::DepthMismatch {
+                expected: request.depth,
+                actual: tree.depth,
+            });
+        }
+
+        Proof::try_from(&tree.generate_proof(&request.path())?)
+    }
+
+    fn verify(&self, proof: Proof) -> Result<VerifyReply, ProtoError> {
+        let proof = MerkleProof::try_from(proof)?;
+        let tree = self.tree.lock().expect("no RPC panics holding the tree");
+
+        Ok(VerifyReply {
+            valid: tree.verify_proof(&proof)?,
+        })
+    }
+}
+
+/// One RPC of the service, `handle` mapping its request to its reply
+struct Rpc<M, R> {
+    service: Arc<ProofService>,
+    handle: fn(&ProofService, M) -> Result<R, ProtoError>,
+}
+
+impl<M: Send + 'static, R: Send + 'static> UnaryService<M> for Rpc<M, R> {
+    type Response = R;
+    type Future = BoxFuture<Response<R>, Status>;
+
+    fn call(&mut self, request: Request<M>) -> Self::Future {
+        let reply = (self.handle)(&self.service, request.into_inner());
+        Box::pin(async move { reply.map(Response::new).map_err(status) })
+    }
+}
+
+/// The `ProofService` routes, what `tonic-prost-build` generates as `ProofServiceServer`
+#[derive(Clone)]
+struct ProofServiceServer(Arc<ProofService>);
+
+impl NamedService for ProofServiceServer {
+    const NAME: &'static str = "merkle_poseidon.ProofService";
+}
+
+impl<B> Service<http::Request<B>> for ProofServiceServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let service = self.0.clone();
+        match request.uri().path() {
+            "/merkle_poseidon.ProofService/Prove" => Box::pin(async move {
+                let rpc = Rpc {
+                    service,
+                    handle: ProofService::prove,
+                };
+                let mut grpc = Grpc::new(tonic_prost::ProstCodec::default());
+                Ok(grpc.unary(rpc, request).await)
+            }),
+            "/merkle_poseidon.ProofService/Verify" => Box::pin(async move {
+                let rpc = Rpc {
+                    service,
+                    handle: ProofService::verify,
+                };
+                let mut grpc = Grpc::new(tonic_prost::ProstCodec::default());
+                Ok(grpc.unary(rpc, request).await)
+            }),
+            _ => Box::pin(async move { Ok(Status::unimplemented("unknown method").into_http()) }),
+        }
+    }
+}
+
+/// The tree of the snapshot named on the command line, or a demo tree of depth 20
+fn load_tree() -> Result<Tree, Box<dyn std::error::Error>> {
+    let Some(file) = std::env::args().nth(1) else {
+        let mut tree = Tree::new(20)?;
+        for index in 0..8u64 {
+            tree.insert_at_path(&Fr::from(index), &Fr::from(index + 100))?;
+        }
+        return Ok(tree);
+    };
+
+    // Snapshots sent to a service come as `TreeSnapshot` messages, converting checks depth and root
+    let tree = Tree::from_snapshot_bytes(&std::fs::read(file)?)?;
+    let message = TreeSnapshot::try_from(&tree)?;
+    Ok(Tree::try_from(message)?)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let tree = load_tree()?;
+    let root = tree.root()?;
+    let address = "127.0.0.1:50051".parse()?;
+    println!(
+        "serving a tree of depth {} and root {root} on {address}",
+        tree.depth
+    );
+
+    let service = ProofService {
+        tree: Mutex::new(tree),
+    };
+    tonic::transport::Server::builder()
+        .add_service(ProofServiceServer(Arc::new(service)))
+        .serve(address)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#ifndef MERKLE_POSEIDON_H
+#define MERKLE_POSEIDON_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Bytes of a field element
+ */
+#define MERKLE_POSEIDON_ELEMENT_BYTES 32
+
+/**
+ * Most siblings a proof can have, one per bit of a path
+ */
+#define MERKLE_POSEIDON_MAX_SIBLINGS 254
+
+/**
+ * The proof folds into the root
+ */
+#define MERKLE_POSEIDON_OK 0
+
+/**
+ * The proof is well-formed but doesn't fold into the root
+ */
+#define MERKLE_POSEIDON_ERR_MISMATCH -1
+
+/**
+ * A pointer is null
+ */
+#define MERKLE_POSEIDON_ERR_NULL_POINTER -2
+
+/**
+ * There are no siblings, or more than `MERKLE_POSEIDON_MAX_SIBLINGS`
+ */
+#define MERKLE_POSEIDON_ERR_LENGTH -3
+
+/**
+ * A field element is not below the modulus
+ */
+#define MERKLE_POSEIDON_ERR_FIELD_ELEMENT -4
+
+/**
+ * The path has bits set past the proof depth or the leaf is the empty node hash
+ */
+#define MERKLE_POSEIDON_ERR_INVALID_PROOF -5
+
+/**
+ * The hasher failed
+ */
+#define MERKLE_POSEIDON_ERR_HASH -6
+
+/**
+ * Verification panicked, this is a bug
+ */
+#define MERKLE_POSEIDON_ERR_PANIC -7
+
+/**
+ * Verify the proof that `leaf` sits at `path` below `root`
+ *
+ * `siblings` points to `siblings_len` field elements, one per level from the root down, like
+ * `MerkleProof::siblings`. Returns `MERKLE_POSEIDON_OK` when the proof verifies and one of the
+ * negative `MERKLE_POSEIDON_ERR_*` codes otherwise.
+ *
+ * # Safety
+ *
+ * `root`, `leaf` and `path` must be null or point to `MERKLE_POSEIDON_ELEMENT_BYTES` readable
+ * bytes, and `siblings` must be null or point to `siblings_len * MERKLE_POSEIDON_ELEMENT_BYTES`
+ * readable bytes.
+ */
+int32_t merkle_poseidon_verify(const uint8_t *root,
+                               const uint8_t *leaf,
+                               const uint8_t *path,
+                               const uint8_t *siblings,
+                               size_t siblings_len);
+
+#endif  /* MERKLE_POSEIDON_H */
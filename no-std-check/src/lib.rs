@@ -0,0 +1,43 @@
+//! Uses merkle-poseidon from a `no_std` crate, with its `std` feature disabled
+//!
+//! Building this crate checks that proofs can be verified and small trees maintained with only
+//! `core` and `alloc`.
+
+#![no_std]
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use merkle_poseidon::{verify, MerkleProof, PoseidonMerkleError, SparseMerkleTree};
+
+/// Verify a proof with the circom Poseidon hasher
+pub fn verify_proof(proof: &MerkleProof) -> Result<bool, PoseidonMerkleError> {
+    let mut hasher = Poseidon::<Fr>::new_circom(2)
+        .map_err(|source| PoseidonMerkleError::InvalidHasherWidth { source })?;
+    proof.verify_proof(&mut hasher)
+}
+
+/// Recompute a binary root with the stateless helpers
+pub fn fold_path(
+    leaf: &Fr,
+    path_bits: &[bool],
+    siblings: &[Fr],
+) -> Result<Fr, PoseidonMerkleError> {
+    let mut hasher = Poseidon::<Fr>::new_circom(2)
+        .map_err(|source| PoseidonMerkleError::InvalidHasherWidth { source })?;
+    verify::fold_path(&mut hasher, leaf, path_bits, siblings)
+}
+
+/// Build a small tree from `(path, value)` entries and prove one of them
+pub fn prove(
+    depth: usize,
+    entries: &[(Fr, Fr)],
+    path: &Fr,
+) -> Result<MerkleProof, PoseidonMerkleError> {
+    let mut tree = SparseMerkleTree::new(depth)?;
+    for (path, value) in entries {
+        tree.insert_at_path(path, value)?;
+    }
+    tree.root()?;
+
+    tree.generate_proof(path)
+}
@@ -0,0 +1,24 @@
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use no_std_check::{fold_path, prove, verify_proof};
+
+#[test]
+fn test_prove_and_verify_without_std() {
+    let entries = [
+        (Fr::from(3u64), Fr::from(30u64)),
+        (Fr::from(9u64), Fr::from(90u64)),
+    ];
+    let proof = prove(8, &entries, &Fr::from(9u64)).unwrap();
+    assert!(verify_proof(&proof).unwrap());
+
+    let mut path_bits = Fr::from(9u64).into_bigint().to_bits_le();
+    path_bits.truncate(8);
+    assert_eq!(
+        fold_path(&proof.leaf_value, &path_bits, &proof.siblings).unwrap(),
+        proof.root_hash
+    );
+
+    let mut tampered = proof.clone();
+    tampered.leaf_value = Fr::from(91u64);
+    assert!(!verify_proof(&tampered).unwrap());
+}
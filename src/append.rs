@@ -0,0 +1,174 @@
+use alloc::{vec, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+
+use crate::{
+    shared::CheckedBorrow, BitOrder, InnerHash, MerkleHasher, MerkleProof, NodeType,
+    PoseidonMerkleError, SparseMerkleTree,
+};
+
+/// A tree filled left to right, each value appended at the next free index
+///
+/// The tree must use `BitOrder::MsbFirst`, so that the index of a leaf is its position from the
+/// left and its path. The hashes left of the path of the next leaf, the frontier, are kept at
+/// every level: an append takes them as its siblings, with empty subtrees on the right, and
+/// hashes its path without reading any other node. The root is the one of the same values
+/// inserted with `insert_at_index`.
+pub struct IndexedAppendTree<H: MerkleHasher<F>, F: PrimeField = Fr> {
+    tree: SparseMerkleTree<H, F>,
+    next_index: u64,
+    /// The hashes left of the node on the path of the next leaf, at each level from the root
+    frontier: Vec<Vec<InnerHash<F>>>,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> IndexedAppendTree<H, F> {
+    /// Append to a tree, after its rightmost leaf
+    ///
+    /// A tree loaded from a snapshot picks up where it was left, the leaves left of its rightmost
+    /// one being taken whether they hold a value or not.
+    pub fn new(tree: SparseMerkleTree<H, F>) -> Result<Self, PoseidonMerkleError<F>> {
+        if tree.bit_order() != BitOrder::MsbFirst {
+            return Err(PoseidonMerkleError::BitOrderMismatch {
+                expected: BitOrder::MsbFirst,
+                actual: tree.bit_order(),
+            });
+        }
+
+        let mut append_tree = IndexedAppendTree {
+            frontier: vec![Vec::new(); tree.depth],
+            next_index: 0,
+            tree,
+        };
+        let Some(last) = append_tree.last_index()? else {
+            return Ok(append_tree);
+        };
+        append_tree.next_index = last.checked_add(1).ok_or(PoseidonMerkleError::TreeFull)?;
+        if let Ok(merkle_path) = append_tree.tree.index_path(append_tree.next_index) {
+            // The siblings are read from the cached hashes, which a batch may have left stale
+            append_tree.tree.root()?;
+            let siblings = append_tree.tree.path_siblings(&merkle_path)?;
+            let per_level = append_tree.tree.arity().children() - 1;
+            for level in 0..append_tree.tree.depth {
+                let digit = append_tree.digit(append_tree.next_index, level);
+                append_tree.frontier[level] =
+                    siblings[level * per_level..level * per_level + digit].to_vec();
+            }
+        }
+
+        Ok(append_tree)
+    }
+
+    /// Append a value at the next index, returning that index
+    ///
+    /// Fails with `PoseidonMerkleError::TreeFull` once every leaf is taken.
+    pub fn append(&mut self, value: &F) -> Result<u64, PoseidonMerkleError<F>> {
+        let index = self.next_index;
+        let merkle_path = self
+            .tree
+            .index_path(index)
+            .map_err(|_| PoseidonMerkleError::TreeFull)?;
+
+        // Left of the path are the frontier hashes, right of it empty subtrees
+        let children = self.tree.arity().children();
+        let config = self.tree.hash_config();
+        let mut siblings = Vec::with_capacity(self.tree.depth * (children - 1));
+        {
+            let hasher = &mut *self.tree.hasher.checked_borrow_mut()?;
+            for level in 0..self.tree.depth {
+                let empty =
+                    config.empty_hash_at(hasher, level + 1, level + 1 == self.tree.depth)?;
+                siblings.extend_from_slice(&self.frontier[level]);
+                siblings.resize((level + 1) * (children - 1), empty);
+            }
+        }
+        let hashes = self
+            .tree
+            .insert_with_siblings(&merkle_path, value, &siblings)?;
+
+        // A node whose subtree is now full joins the frontier of the next leaf
+        let next_index = index.checked_add(1).ok_or(PoseidonMerkleError::TreeFull)?;
+        for level in 0..self.tree.depth {
+            let digit = self.digit(next_index, level);
+            if digit == 0 {
+                self.frontier[level].clear();
+            } else if digit != self.digit(index, level) {
+                self.frontier[level].push(hashes[level + 1]);
+            }
+        }
+        self.next_index = next_index;
+
+        Ok(index)
+    }
+
+    /// The index the next value is appended at, the number of leaves taken
+    pub fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    pub fn root(&self) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        self.tree.root()
+    }
+
+    pub fn get(&self, index: u64) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        self.tree.try_get_value(&self.tree.index_path(index)?)
+    }
+
+    /// Generate the proof of the leaf at `index`, whose path is the index
+    pub fn generate_proof_at_index(
+        &self,
+        index: u64,
+    ) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        self.tree.generate_proof(&self.tree.index_path(index)?)
+    }
+
+    /// The tree, read only so that the frontier stays in step with it
+    pub fn tree(&self) -> &SparseMerkleTree<H, F> {
+        &self.tree
+    }
+
+    pub fn into_tree(self) -> SparseMerkleTree<H, F> {
+        self.tree
+    }
+
+    /// The child index taken at `level` by the path of the leaf at `index`
+    fn digit(&self, index: u64, level: usize) -> usize {
+        let arity = self.tree.arity();
+        let offset = BitOrder::MsbFirst.level_offset(level, self.tree.depth, arity);
+        index
+            .checked_shr(offset as u32)
+            .map_or(0, |shifted| (shifted as usize) & (arity.children() - 1))
+    }
+
+    /// The index of the rightmost materialized leaf, `None` in an empty tree
+    fn last_index(&self) -> Result<Option<u64>, PoseidonMerkleError<F>> {
+        let children = self.tree.arity().children();
+        let mut index = 0u64;
+        let mut current = self.tree.root.clone();
+        for level in 0..self.tree.depth {
+            let next = {
+                let current_ref = current.checked_borrow()?;
+                if let NodeType::Leaf(_) = current_ref.node_type {
+                    return Err(PoseidonMerkleError::InvalidNodeType { level, path: None });
+                }
+                let Some((digit, child)) = current_ref.children[..children]
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find_map(|(digit, child)| child.clone().map(|child| (digit, child)))
+                else {
+                    return Ok(None);
+                };
+                index = index
+                    .checked_mul(children as u64)
+                    .ok_or(PoseidonMerkleError::TreeFull)?
+                    + digit as u64;
+                child
+            };
+
+            current = next;
+        }
+
+        Ok(Some(index))
+    }
+}
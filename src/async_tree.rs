@@ -0,0 +1,130 @@
+use std::{panic, sync::Arc};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use tokio::{
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    task::{self, JoinError},
+};
+
+use crate::{
+    InnerHash, MerkleHasher, MerklePath, MerkleProof, PoseidonMerkleError, SparseMerkleTree,
+};
+
+/// Batches from this size on are hashed on the blocking thread pool, smaller ones on the task
+const BLOCKING_BATCH: usize = 64;
+
+/// A tree behind a tokio `RwLock`, for async services awaiting its operations
+///
+/// Single inserts, values, proofs and the root are computed on the calling task once the lock is
+/// taken. Bulk inserts and proof batches of `BLOCKING_BATCH` entries or more move to
+/// `spawn_blocking` with the lock, so they don't stall the executor, and need a tokio runtime.
+///
+/// The futures are cancel safe: an insert is staged and committed without awaiting, see
+/// `SparseMerkleTree::insert_at_path`, so cancelling one either leaves the tree untouched or
+/// finds it done. A bulk insert moved to the blocking pool runs to its end, or its first failure,
+/// even when its future is dropped, and the operations after it see every insert.
+pub struct AsyncTree<H: MerkleHasher<F>, F: PrimeField = Fr> {
+    tree: Arc<RwLock<SparseMerkleTree<H, F>>>,
+}
+
+impl<H, F> AsyncTree<H, F>
+where
+    H: MerkleHasher<F> + Send + Sync + 'static,
+    F: PrimeField,
+{
+    pub fn new(tree: SparseMerkleTree<H, F>) -> Self {
+        AsyncTree {
+            tree: Arc::new(RwLock::new(tree)),
+        }
+    }
+
+    /// Insert a value at a path, see `SparseMerkleTree::insert_at_path`
+    pub async fn insert(
+        &self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        self.tree.write().await.insert_at_path(merkle_path, value)
+    }
+
+    /// Insert every value at its path in order, see `SparseMerkleTree::insert_many`
+    pub async fn insert_many(
+        &self,
+        entries: Vec<(MerklePath<F>, F)>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        if entries.len() < BLOCKING_BATCH {
+            return self.tree.write().await.insert_many(entries);
+        }
+
+        let mut tree = self.tree.clone().write_owned().await;
+        blocking(task::spawn_blocking(move || tree.insert_many(entries)).await)
+    }
+
+    /// Delete the value at a path, see `SparseMerkleTree::delete_at_path`
+    pub async fn delete(&self, merkle_path: &MerklePath<F>) -> Result<(), PoseidonMerkleError<F>> {
+        self.tree.write().await.delete_at_path(merkle_path)
+    }
+
+    /// The value at a path, see `SparseMerkleTree::try_get_value`
+    pub async fn get(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        self.tree.read().await.try_get_value(merkle_path)
+    }
+
+    /// Generate a proof of the value at a path, see `SparseMerkleTree::generate_proof`
+    pub async fn prove(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        self.tree.read().await.generate_proof(merkle_path)
+    }
+
+    /// Generate the proof of every path, see `SparseMerkleTree::generate_proofs`
+    pub async fn prove_many(
+        &self,
+        merkle_paths: Vec<MerklePath<F>>,
+    ) -> Result<Vec<MerkleProof<F>>, PoseidonMerkleError<F>> {
+        if merkle_paths.len() < BLOCKING_BATCH {
+            return self.tree.read().await.generate_proofs(&merkle_paths);
+        }
+
+        let tree = self.tree.clone().read_owned().await;
+        blocking(task::spawn_blocking(move || tree.generate_proofs(&merkle_paths)).await)
+    }
+
+    /// The root hash of the tree, see `SparseMerkleTree::root`
+    pub async fn root(&self) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        self.tree.read().await.root()
+    }
+
+    /// Shared access to the tree, writers wait until the guard is dropped
+    pub async fn read(&self) -> RwLockReadGuard<'_, SparseMerkleTree<H, F>> {
+        self.tree.read().await
+    }
+
+    /// Exclusive access to the tree, readers and writers wait until the guard is dropped
+    pub async fn write(&self) -> RwLockWriteGuard<'_, SparseMerkleTree<H, F>> {
+        self.tree.write().await
+    }
+}
+
+impl<H, F> From<SparseMerkleTree<H, F>> for AsyncTree<H, F>
+where
+    H: MerkleHasher<F> + Send + Sync + 'static,
+    F: PrimeField,
+{
+    fn from(tree: SparseMerkleTree<H, F>) -> Self {
+        AsyncTree::new(tree)
+    }
+}
+
+/// The result of a blocking task, resuming its panic
+fn blocking<T>(result: Result<T, JoinError>) -> T {
+    result.unwrap_or_else(|err| match err.try_into_panic() {
+        Ok(payload) => panic::resume_unwind(payload),
+        Err(err) => panic!("the runtime shut down before the tree operation ended: {err}"),
+    })
+}
@@ -0,0 +1,280 @@
+use std::{
+    mem,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard},
+    thread::{self, JoinHandle},
+};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+
+use crate::{
+    telemetry, InnerHash, MerkleHasher, MerklePath, MerkleProof, PoseidonMerkleError,
+    SparseMerkleTree,
+};
+
+/// Writes waiting for the worker, and whether the handle is gone
+#[derive(Debug)]
+struct Queue<F: PrimeField> {
+    /// Canonical paths written since the worker last took the writes, with their value or `None`
+    /// for a delete, oldest first
+    pending: Vec<(MerklePath<F>, Option<F>)>,
+    /// Whether the worker is placing or hashing the writes it took
+    hashing: bool,
+    /// The first write the worker failed to place since the last `flush`
+    failure: Option<PoseidonMerkleError<F>>,
+    closed: bool,
+}
+
+impl<F: PrimeField> Default for Queue<F> {
+    fn default() -> Self {
+        Queue {
+            pending: Vec::new(),
+            hashing: false,
+            failure: None,
+            closed: false,
+        }
+    }
+}
+
+/// The tree and the queue, shared by the handle and its worker
+struct State<H: MerkleHasher<F>, F: PrimeField> {
+    tree: RwLock<SparseMerkleTree<H, F>>,
+    queue: Mutex<Queue<F>>,
+    /// Signalled when leaves are queued, when the worker is done hashing and when closing
+    changed: Condvar,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> State<H, F> {
+    fn queue(&self) -> MutexGuard<'_, Queue<F>> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, queue: MutexGuard<'a, Queue<F>>) -> MutexGuard<'a, Queue<F>> {
+        self.changed
+            .wait(queue)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Place and hash the queued writes until the handle is dropped, then the last ones and stop
+    fn work(&self) {
+        loop {
+            let mut queue = self.queue();
+            while queue.pending.is_empty() && !queue.closed {
+                queue = self.wait(queue);
+            }
+            if queue.pending.is_empty() {
+                return;
+            }
+            drop(queue);
+
+            // The writes are taken with the tree locked, so that a read missing them in the queue
+            // waits for them to be placed
+            let failure = {
+                let mut tree = self.tree.write().unwrap_or_else(PoisonError::into_inner);
+                let mut queue = self.queue();
+                let writes = mem::take(&mut queue.pending);
+                queue.hashing = true;
+                drop(queue);
+
+                let mut failure = None;
+                for (merkle_path, value) in writes {
+                    let placed = match value {
+                        Some(value) => tree.place_leaf(&merkle_path, &value),
+                        None => tree.remove_leaf(&merkle_path),
+                    };
+                    if let Err(err) = placed {
+                        failure.get_or_insert(err);
+                    }
+                }
+                failure
+            };
+
+            // Only the hashing holds the tree for a whole pass, shared with the reads and the
+            // writes queued meanwhile. A failing hasher leaves the nodes dirty, for `flush` to
+            // report
+            let _ = self
+                .tree
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .root();
+
+            let mut queue = self.queue();
+            queue.hashing = false;
+            if queue.failure.is_none() {
+                queue.failure = failure;
+            }
+            self.changed.notify_all();
+        }
+    }
+}
+
+/// A tree whose inserts queue the leaf and return, see `SparseMerkleTree::with_background_hashing`
+///
+/// A worker thread places the queued leaves and rehashes the nodes above them, each dirty node
+/// once however many leaves were placed below it since the last pass. Inserts don't wait for a
+/// pass, they are queued for the next one. `flush` waits for the worker and returns the root.
+/// Values can be read right away, the reads needing hashes, `flush` and `generate_proof`, wait for
+/// the worker instead of seeing a stale root. Dropping the handle lets the worker hash the last
+/// leaves and joins it.
+pub struct BackgroundHashing<H: MerkleHasher<F>, F: PrimeField = Fr> {
+    state: Arc<State<H, F>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<H, F> SparseMerkleTree<H, F>
+where
+    H: MerkleHasher<F> + Send + Sync + 'static,
+    F: PrimeField,
+{
+    /// Move the tree behind a handle hashing on a background thread, see `BackgroundHashing`
+    ///
+    /// Inserts through the handle aren't told to the observer or recorded in the changelog, which
+    /// both need the root of every insert.
+    pub fn with_background_hashing(self) -> BackgroundHashing<H, F> {
+        let state = Arc::new(State {
+            tree: RwLock::new(self),
+            queue: Mutex::new(Queue::default()),
+            changed: Condvar::new(),
+        });
+        let worker = {
+            let state = state.clone();
+            thread::spawn(move || state.work())
+        };
+
+        BackgroundHashing {
+            state,
+            worker: Some(worker),
+        }
+    }
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> BackgroundHashing<H, F> {
+    /// Queue a value for a path, for the worker to place and rehash the nodes above it
+    ///
+    /// Fails like `SparseMerkleTree::insert_at_path` for a tree of several values per leaf. A leaf
+    /// the worker fails to place, past the node budget or at a node borrowed elsewhere, and hasher
+    /// failures are reported by `flush`.
+    pub fn insert_at_path(
+        &self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let merkle_path = {
+            let tree = self.read();
+            if tree.leaf_width() != 1 {
+                return Err(PoseidonMerkleError::LeafWidthMismatch {
+                    expected: tree.leaf_width(),
+                    actual: 1,
+                });
+            }
+            tree.canonicalize_path(merkle_path)
+        };
+        telemetry::record_insert();
+        self.enqueue(merkle_path, Some(*value));
+
+        Ok(())
+    }
+
+    /// Queue the removal of the leaf at a path, pruning like `SparseMerkleTree::delete_at_path`,
+    /// see `insert_at_path`
+    pub fn delete_at_path(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let merkle_path = self.read().canonicalize_path(merkle_path);
+        telemetry::record_delete();
+        self.enqueue(merkle_path, None);
+
+        Ok(())
+    }
+
+    /// The value at a path, queued leaves are read without waiting for the worker
+    pub fn try_get_value(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        let tree = self.read();
+        let merkle_path = tree.canonicalize_path(merkle_path);
+        drop(tree);
+
+        let queued = self
+            .state
+            .queue()
+            .pending
+            .iter()
+            .rev()
+            .find(|(path, _)| *path == merkle_path)
+            .map(|(_, value)| *value);
+        match queued {
+            Some(value) => Ok(value),
+            None => self.read().try_get_value(&merkle_path),
+        }
+    }
+
+    /// Wait until the worker placed and hashed every queued leaf and return the root
+    ///
+    /// The first leaf the worker failed to place since the last `flush` is reported here, as are
+    /// hasher failures, the root being hashed again first.
+    pub fn flush(&self) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        let mut queue = self.state.queue();
+        while !queue.pending.is_empty() || queue.hashing {
+            queue = self.state.wait(queue);
+        }
+        let failure = queue.failure.take();
+        drop(queue);
+
+        if let Some(err) = failure {
+            return Err(err);
+        }
+        self.read().root()
+    }
+
+    /// Generate a proof of the value at a path, once the queued leaves are hashed
+    pub fn generate_proof(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        self.flush()?;
+        self.read().generate_proof(merkle_path)
+    }
+
+    /// Stop the worker once the queued leaves are hashed and return the tree
+    pub fn into_inner(self) -> SparseMerkleTree<H, F> {
+        let state = self.state.clone();
+        drop(self);
+
+        Arc::into_inner(state)
+            .expect("the worker dropped its state when joined")
+            .tree
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, SparseMerkleTree<H, F>> {
+        self.state
+            .tree
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn enqueue(&self, merkle_path: MerklePath<F>, value: Option<F>) {
+        self.state.queue().pending.push((merkle_path, value));
+        self.state.changed.notify_all();
+    }
+
+    /// Let the worker hash the last leaves and join it
+    fn close(&mut self) {
+        self.state.queue().closed = true;
+        self.state.changed.notify_all();
+        if let Some(worker) = self.worker.take() {
+            // The worker only panics with a panicking hasher, the tree is left dirty then
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> Drop for BackgroundHashing<H, F> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
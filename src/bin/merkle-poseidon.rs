@@ -0,0 +1,213 @@
+//! Build binary circom Poseidon trees, prove and verify their leaves from files
+//!
+//! Trees are stored as snapshots, see `SparseMerkleTree::to_snapshot_bytes`, and proofs as the
+//! JSON object of `MerkleProof::to_json`, decimal strings unless `prove --encoding` says
+//! otherwise. The exit code is 0 on success, 1 for a proof that doesn't verify and 2 for any
+//! error, reported on stderr.
+
+use std::{fs, path::PathBuf, process::ExitCode};
+
+use ark_bn254::Fr;
+use clap::{Parser, Subcommand};
+use light_poseidon::Poseidon;
+use merkle_poseidon::{
+    Arity, BitOrder, FrEncoding, HashingScheme, LeafHashing, MerkleProof, PoseidonMerkleError,
+    SparseMerkleTree,
+};
+use serde_json::Value;
+
+type Tree = SparseMerkleTree<Poseidon<Fr>>;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Build Poseidon sparse Merkle trees, prove and verify their leaves"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build a tree from a JSON file of leaves and write its snapshot
+    ///
+    /// The leaves are an array of values, the leaf at index i holding the i-th, or an object of
+    /// values by index. Values are decimal or 0x-prefixed hex strings, arrays of big-endian
+    /// bytes or integers.
+    Build {
+        /// Number of levels of the tree
+        #[arg(long)]
+        depth: usize,
+        /// The JSON file of leaves
+        leaves: PathBuf,
+        /// Where to write the snapshot
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Write the proof of a leaf as JSON
+    Prove {
+        /// The snapshot of the tree
+        #[arg(long)]
+        tree: PathBuf,
+        /// Index of the leaf
+        #[arg(long)]
+        index: u64,
+        /// Where to write the proof, stdout by default
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// How field elements are written: decimal, hex-be, hex-le or bytes
+        #[arg(long, default_value_t = FrEncoding::Decimal)]
+        encoding: FrEncoding,
+    },
+    /// Verify a proof against the root it carries, or `--root`
+    Verify {
+        /// The JSON file of the proof
+        proof: PathBuf,
+        /// The root the proof must have, as a decimal or 0x-prefixed hex string
+        #[arg(long)]
+        root: Option<String>,
+    },
+    /// Print the root of a tree as a decimal string
+    Root {
+        /// The snapshot of the tree
+        tree: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Run a command, `Ok(false)` for a proof that doesn't verify
+fn run(command: Command) -> Result<bool, String> {
+    match command {
+        Command::Build { depth, leaves, out } => {
+            let leaves: Value = serde_json::from_str(&read(&leaves)?)
+                .map_err(|error| format!("{}: {error}", leaves.display()))?;
+            let mut tree = Tree::new(depth).map_err(|error| error.to_string())?;
+            for (index, value) in entries(&leaves)? {
+                tree.insert_at_path(&path(&tree, index)?, &value)
+                    .map_err(|error| error.to_string())?;
+            }
+
+            let snapshot = tree
+                .to_snapshot_bytes()
+                .map_err(|error| error.to_string())?;
+            fs::write(&out, snapshot).map_err(|error| format!("{}: {error}", out.display()))?;
+            println!("{}", tree.root().map_err(|error| error.to_string())?);
+        }
+        Command::Prove {
+            tree,
+            index,
+            out,
+            encoding,
+        } => {
+            let tree = load(&tree)?;
+            ensure_default_conventions(&tree)?;
+            let proof = tree
+                .generate_proof(&path(&tree, index)?)
+                .map_err(|error| error.to_string())?;
+            let proof = serde_json::to_string_pretty(&proof.to_json(encoding))
+                .expect("JSON values serialize");
+            match out {
+                Some(out) => {
+                    fs::write(&out, proof).map_err(|error| format!("{}: {error}", out.display()))?
+                }
+                None => println!("{proof}"),
+            }
+        }
+        Command::Verify { proof, root } => {
+            let proof: Value = serde_json::from_str(&read(&proof)?)
+                .map_err(|error| format!("{}: {error}", proof.display()))?;
+            let proof = MerkleProof::from_json(&proof).map_err(|error| error.to_string())?;
+            let root_matches = match root {
+                Some(root) => field(&Value::String(root))? == proof.root_hash,
+                None => true,
+            };
+
+            let mut hasher = Poseidon::<Fr>::new_circom(2).map_err(|error| error.to_string())?;
+            let valid = root_matches
+                && proof
+                    .verify_proof(&mut hasher)
+                    .map_err(|error| error.to_string())?;
+            println!("{}", if valid { "valid" } else { "invalid" });
+            return Ok(valid);
+        }
+        Command::Root { tree } => {
+            let tree = load(&tree)?;
+            println!("{}", tree.root().map_err(|error| error.to_string())?);
+        }
+    }
+
+    Ok(true)
+}
+
+fn read(file: &PathBuf) -> Result<String, String> {
+    fs::read_to_string(file).map_err(|error| format!("{}: {error}", file.display()))
+}
+
+fn load(file: &PathBuf) -> Result<Tree, String> {
+    let bytes = fs::read(file).map_err(|error| format!("{}: {error}", file.display()))?;
+    Tree::from_snapshot_bytes(&bytes).map_err(|error| format!("{}: {error}", file.display()))
+}
+
+/// Proofs are written without their conventions, so only trees of the default ones are proven
+fn ensure_default_conventions(tree: &Tree) -> Result<(), String> {
+    let default = tree.arity() == Arity::Binary
+        && tree.leaf_hashing() == LeafHashing::Raw
+        && tree.hashing_scheme() == HashingScheme::Legacy
+        && tree.bit_order() == BitOrder::LsbFirst
+        && tree.leaf_width() == 1
+        && tree.empty_leaf() == Fr::from(0u64);
+    match default {
+        true => Ok(()),
+        false => Err("only binary trees with the default conventions can be proven".into()),
+    }
+}
+
+/// The path of the leaf at `index`, rejecting indices past the last leaf
+fn path(tree: &Tree, index: u64) -> Result<Fr, String> {
+    if tree.depth < 64 && index >> tree.depth != 0 {
+        let error = PoseidonMerkleError::<Fr>::IndexOutOfRange {
+            index: usize::try_from(index).unwrap_or(usize::MAX),
+            size: 1usize.checked_shl(tree.depth as u32).unwrap_or(usize::MAX),
+        };
+        return Err(error.to_string());
+    }
+
+    Ok(Fr::from(index))
+}
+
+/// The `(index, value)` entries of a JSON array or object of leaves
+fn entries(leaves: &Value) -> Result<Vec<(u64, Fr)>, String> {
+    match leaves {
+        Value::Array(values) => values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| Ok((index as u64, field(value)?)))
+            .collect(),
+        Value::Object(values) => values
+            .iter()
+            .map(|(index, value)| {
+                let index = index
+                    .parse()
+                    .map_err(|_| format!("invalid leaf index {index:?}"))?;
+                Ok((index, field(value)?))
+            })
+            .collect(),
+        _ => Err("the leaves must be an array or an object of values".into()),
+    }
+}
+
+/// A field element in any encoding `FrEncoding::parse` reads
+fn field(value: &Value) -> Result<Fr, String> {
+    FrEncoding::parse(value).map_err(|error| error.to_string())
+}
@@ -0,0 +1,99 @@
+use alloc::{collections::VecDeque, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+
+use crate::{path::path_index, InnerHash, MerklePath, MerkleProof};
+
+/// A leaf update recorded by a tree built `with_changelog`, like a light-protocol changelog entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeLogEntry<F: PrimeField = Fr> {
+    /// Canonical path of the updated leaf
+    pub leaf_index: MerklePath<F>,
+    /// The value the leaf was set to, the empty leaf value for a delete
+    pub new_leaf: F,
+    /// The root after the update
+    pub new_root: InnerHash<F>,
+    /// New hashes of the nodes on the path, the leaf contribution first, the root excluded
+    pub path_nodes: Vec<InnerHash<F>>,
+}
+
+impl<F: PrimeField> ChangeLogEntry<F> {
+    /// Bring a proof of another leaf, valid before this update, to `new_root`
+    ///
+    /// The sibling at the level where the paths part becomes the new hash of the updated node.
+    /// Applying the entries in order fast-forwards a proof through several updates. Returns
+    /// `false`, leaving the proof untouched, when it proves the updated leaf itself or has another
+    /// depth.
+    pub fn fast_forward(&self, proof: &mut MerkleProof<F>) -> bool {
+        let depth = proof.depth();
+        if self.path_nodes.len() != depth {
+            return false;
+        }
+
+        let position = |level| {
+            let offset = proof.bit_order.level_offset(level, depth, proof.arity);
+            path_index(&self.leaf_index, offset, proof.arity)
+        };
+        let Some(level) = (0..depth).find(|level| position(*level) != proof.position(*level))
+        else {
+            return false;
+        };
+
+        // The siblings of a level are the other children in index order
+        let (updated, own) = (position(level), proof.position(level));
+        let slot = if updated < own { updated } else { updated - 1 };
+        let per_level = proof.arity.children() - 1;
+        proof.siblings[level * per_level + slot] = self.path_nodes[depth - 1 - level];
+        proof.root_hash = self.new_root;
+
+        true
+    }
+}
+
+/// The last updates of a tree, oldest first, see `SparseMerkleTree::with_changelog`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeLog<F: PrimeField = Fr> {
+    capacity: usize,
+    entries: VecDeque<ChangeLogEntry<F>>,
+}
+
+impl<F: PrimeField> ChangeLog<F> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        ChangeLog {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Record an update, dropping the oldest one when full
+    pub(crate) fn push(&mut self, entry: ChangeLogEntry<F>) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Number of updates kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of updates recorded, at most `capacity`
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The recorded updates, oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &ChangeLogEntry<F>> {
+        self.entries.iter()
+    }
+}
@@ -0,0 +1,399 @@
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, BigInteger, Field, PrimeField, Zero};
+
+use crate::{hasher::hash_bn254_circom, HashOperation, PoseidonMerkleError, MAX_PATH_BITS};
+
+/// hash0 of circomlib, the hash of an inner node
+fn hash0(left: Fr, right: Fr) -> Result<Fr, PoseidonMerkleError> {
+    hash_bn254_circom(&[left, right], HashOperation::InnerNode { level: None })
+}
+
+/// hash1 of circomlib, the hash of a leaf with the appended 1
+fn hash1(key: Fr, value: Fr) -> Result<Fr, PoseidonMerkleError> {
+    hash_bn254_circom(&[key, value, Fr::ONE], HashOperation::LeafHash)
+}
+
+/// Bits of a key from the root down, bit 0 picks the child of the root and `true` means right
+fn key_bits(key: &Fr) -> Vec<bool> {
+    key.into_bigint().to_bits_le()
+}
+
+/// Node of a circomlib tree, its hash is cached
+#[derive(Debug, Clone, PartialEq)]
+enum SmtNode {
+    /// An empty subtree, hashed as zero
+    Empty,
+    /// A leaf, hashed as hash1(key, value)
+    Leaf { key: Fr, value: Fr, hash: Fr },
+    /// An inner node holding at least two leaves, hashed as hash0(left, right)
+    Inner {
+        hash: Fr,
+        children: Box<[SmtNode; 2]>,
+    },
+}
+
+impl SmtNode {
+    fn leaf(key: Fr, value: Fr) -> Result<Self, PoseidonMerkleError> {
+        Ok(SmtNode::Leaf {
+            key,
+            value,
+            hash: hash1(key, value)?,
+        })
+    }
+
+    fn inner(children: [SmtNode; 2]) -> Result<Self, PoseidonMerkleError> {
+        Ok(SmtNode::Inner {
+            hash: hash0(children[0].hash(), children[1].hash())?,
+            children: Box::new(children),
+        })
+    }
+
+    fn hash(&self) -> Fr {
+        match self {
+            SmtNode::Empty => Fr::ZERO,
+            SmtNode::Leaf { hash, .. } | SmtNode::Inner { hash, .. } => *hash,
+        }
+    }
+}
+
+/// Sparse merkle tree laid out after circomlib's `smt.js`, its proofs in the shape of `SMTVerifier`
+///
+/// Unlike `SparseMerkleTree`, a leaf stores its key and sits at the shallowest level where no
+/// other key shares its path, with `levels` the most levels a key can descend. Leaves hash as
+/// poseidon(key, value, 1), inner nodes as poseidon(left, right) and empty subtrees as zero, with
+/// the circom parameters. The bit `i` of the key picks the child at level `i`, 1 meaning right.
+/// Its roots and proofs haven't been checked against circomlibjs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircomSmtCompat {
+    root: SmtNode,
+    levels: usize,
+}
+
+impl CircomSmtCompat {
+    /// Create an empty tree whose proofs have `levels` siblings, the `nLevels` of the circuit
+    pub fn new(levels: usize) -> Result<Self, PoseidonMerkleError> {
+        if levels == 0 {
+            return Err(PoseidonMerkleError::InvalidDepth);
+        }
+        if levels > MAX_PATH_BITS {
+            return Err(PoseidonMerkleError::InvalidBitsPathHash {
+                len: levels,
+                max: MAX_PATH_BITS,
+            });
+        }
+
+        Ok(CircomSmtCompat {
+            root: SmtNode::Empty,
+            levels,
+        })
+    }
+
+    /// Most levels a key can descend, and number of siblings of a proof
+    pub fn levels(&self) -> usize {
+        self.levels
+    }
+
+    /// The root hash, zero for an empty tree
+    pub fn root(&self) -> Fr {
+        self.root.hash()
+    }
+
+    /// Check if the tree has no leaf
+    pub fn is_empty(&self) -> bool {
+        self.root == SmtNode::Empty
+    }
+
+    /// Get the value of a key, `None` if the key isn't in the tree
+    pub fn get(&self, key: &Fr) -> Option<Fr> {
+        let bits = key_bits(key);
+        let mut node = &self.root;
+        for bit in bits {
+            match node {
+                SmtNode::Inner { children, .. } => node = &children[bit as usize],
+                SmtNode::Leaf {
+                    key: found, value, ..
+                } if found == key => return Some(*value),
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    /// The keys and values of the tree, from the leftmost leaf
+    pub fn entries(&self) -> Vec<(Fr, Fr)> {
+        let mut entries = Vec::new();
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match node {
+                SmtNode::Empty => {}
+                SmtNode::Leaf { key, value, .. } => entries.push((*key, *value)),
+                SmtNode::Inner { children, .. } => stack.extend(children.iter().rev()),
+            }
+        }
+
+        entries
+    }
+
+    /// Insert a new key, failing with `KeyExists` if it is already in the tree
+    ///
+    /// A key sharing its first `levels` bits with another key of the tree can't be told apart
+    /// from it, and is rejected with `LevelsExceeded`. Both are caught before the tree changes.
+    pub fn insert(&mut self, key: &Fr, value: &Fr) -> Result<(), PoseidonMerkleError> {
+        let bits = key_bits(key);
+        Self::insert_below(&mut self.root, *key, *value, &bits, 0, self.levels)
+    }
+
+    fn insert_below(
+        node: &mut SmtNode,
+        key: Fr,
+        value: Fr,
+        bits: &[bool],
+        level: usize,
+        levels: usize,
+    ) -> Result<(), PoseidonMerkleError> {
+        match node {
+            SmtNode::Empty => *node = SmtNode::leaf(key, value)?,
+            SmtNode::Leaf { key: old_key, .. } if *old_key == key => {
+                return Err(PoseidonMerkleError::KeyExists { key });
+            }
+            SmtNode::Leaf { key: old_key, .. } => {
+                let old_bits = key_bits(old_key);
+                let split = (level..levels)
+                    .find(|&level| old_bits[level] != bits[level])
+                    .ok_or(PoseidonMerkleError::LevelsExceeded { levels })?;
+
+                // Both leaves go below the first level their paths part at
+                let new_leaf = SmtNode::leaf(key, value)?;
+                let old_leaf = core::mem::replace(node, SmtNode::Empty);
+                let mut children = [SmtNode::Empty, SmtNode::Empty];
+                children[bits[split] as usize] = new_leaf;
+                children[old_bits[split] as usize] = old_leaf;
+                let mut subtree = SmtNode::inner(children)?;
+                for level in (level..split).rev() {
+                    let mut children = [SmtNode::Empty, SmtNode::Empty];
+                    children[bits[level] as usize] = subtree;
+                    subtree = SmtNode::inner(children)?;
+                }
+                *node = subtree;
+            }
+            SmtNode::Inner { hash, children } => {
+                Self::insert_below(
+                    &mut children[bits[level] as usize],
+                    key,
+                    value,
+                    bits,
+                    level + 1,
+                    levels,
+                )?;
+                *hash = hash0(children[0].hash(), children[1].hash())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Change the value of a key already in the tree, failing with `LeafNotFound` otherwise
+    pub fn update(&mut self, key: &Fr, value: &Fr) -> Result<(), PoseidonMerkleError> {
+        let bits = key_bits(key);
+        Self::update_below(&mut self.root, *key, *value, &bits, 0)
+    }
+
+    fn update_below(
+        node: &mut SmtNode,
+        key: Fr,
+        value: Fr,
+        bits: &[bool],
+        level: usize,
+    ) -> Result<(), PoseidonMerkleError> {
+        match node {
+            SmtNode::Leaf { key: found, .. } if *found == key => {
+                *node = SmtNode::leaf(key, value)?;
+            }
+            SmtNode::Inner { hash, children } => {
+                Self::update_below(
+                    &mut children[bits[level] as usize],
+                    key,
+                    value,
+                    bits,
+                    level + 1,
+                )?;
+                *hash = hash0(children[0].hash(), children[1].hash())?;
+            }
+            _ => return Err(PoseidonMerkleError::LeafNotFound { path: key }),
+        }
+
+        Ok(())
+    }
+
+    /// Remove a key, failing with `LeafNotFound` if it isn't in the tree
+    ///
+    /// A leaf left alone in its subtree moves up to where it's again the only one on its path,
+    /// like circomlib does.
+    pub fn delete(&mut self, key: &Fr) -> Result<(), PoseidonMerkleError> {
+        let bits = key_bits(key);
+        Self::delete_below(&mut self.root, *key, &bits, 0)
+    }
+
+    fn delete_below(
+        node: &mut SmtNode,
+        key: Fr,
+        bits: &[bool],
+        level: usize,
+    ) -> Result<(), PoseidonMerkleError> {
+        match node {
+            SmtNode::Leaf { key: found, .. } if *found == key => *node = SmtNode::Empty,
+            SmtNode::Inner { hash, children } => {
+                Self::delete_below(&mut children[bits[level] as usize], key, bits, level + 1)?;
+                let collapsed = match &mut **children {
+                    [SmtNode::Empty, SmtNode::Empty] => Some(SmtNode::Empty),
+                    [leaf @ SmtNode::Leaf { .. }, SmtNode::Empty]
+                    | [SmtNode::Empty, leaf @ SmtNode::Leaf { .. }] => {
+                        Some(core::mem::replace(leaf, SmtNode::Empty))
+                    }
+                    _ => {
+                        *hash = hash0(children[0].hash(), children[1].hash())?;
+                        None
+                    }
+                };
+                if let Some(collapsed) = collapsed {
+                    *node = collapsed;
+                }
+            }
+            _ => return Err(PoseidonMerkleError::LeafNotFound { path: key }),
+        }
+
+        Ok(())
+    }
+
+    /// Generate a proof that the key is in the tree, or that it isn't if it is missing
+    ///
+    /// The siblings are padded with zeros up to `levels`, as `SMTVerifier` expects them.
+    pub fn generate_proof(&self, key: &Fr) -> CircomSmtProof {
+        let bits = key_bits(key);
+        let mut siblings = Vec::with_capacity(self.levels);
+        let mut node = &self.root;
+        while let SmtNode::Inner { children, .. } = node {
+            let bit = bits[siblings.len()] as usize;
+            siblings.push(children[1 - bit].hash());
+            node = &children[bit];
+        }
+        siblings.resize(self.levels, Fr::ZERO);
+
+        let mut proof = CircomSmtProof {
+            root: self.root(),
+            siblings,
+            key: *key,
+            value: Fr::ZERO,
+            found: false,
+            old_key: Fr::ZERO,
+            old_value: Fr::ZERO,
+            is_old0: false,
+        };
+        match node {
+            SmtNode::Leaf {
+                key: found, value, ..
+            } if found == key => {
+                proof.value = *value;
+                proof.found = true;
+            }
+            SmtNode::Leaf { key, value, .. } => {
+                proof.old_key = *key;
+                proof.old_value = *value;
+            }
+            _ => proof.is_old0 = true,
+        }
+
+        proof
+    }
+}
+
+/// Inclusion or exclusion proof of a `CircomSmtCompat` tree, laid out like the inputs of
+/// circomlib's `SMTVerifier`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircomSmtProof {
+    /// The root hash of the tree
+    pub root: Fr,
+    /// Sibling of the path at every level from the root down, zero past the leaf
+    pub siblings: Vec<Fr>,
+    /// The key the proof is about
+    pub key: Fr,
+    /// The value of the key, zero for an exclusion proof
+    pub value: Fr,
+    /// Whether the key is in the tree, `fnc` is 0 for an inclusion proof and 1 otherwise
+    pub found: bool,
+    /// Key of the other leaf found on the path of a missing key
+    pub old_key: Fr,
+    /// Value of the other leaf found on the path of a missing key
+    pub old_value: Fr,
+    /// Whether the path of a missing key ends in an empty subtree rather than another leaf
+    pub is_old0: bool,
+}
+
+impl CircomSmtProof {
+    /// Recompute the root like `SMTVerifier` does and compare it with the one of the proof
+    ///
+    /// The leaf sits below the deepest non-zero sibling. An exclusion proof ends either in an
+    /// empty subtree or in the leaf of another key.
+    pub fn verify(&self) -> Result<bool, PoseidonMerkleError> {
+        let mut hash = if self.found {
+            hash1(self.key, self.value)?
+        } else if self.is_old0 {
+            Fr::ZERO
+        } else if self.old_key == self.key {
+            return Ok(false);
+        } else {
+            hash1(self.old_key, self.old_value)?
+        };
+
+        let bits = key_bits(&self.key);
+        let levels = self
+            .siblings
+            .iter()
+            .rposition(|sibling| !sibling.is_zero())
+            .map_or(0, |level| level + 1);
+        for level in (0..levels).rev() {
+            let sibling = self.siblings[level];
+            hash = if bits[level] {
+                hash0(sibling, hash)?
+            } else {
+                hash0(hash, sibling)?
+            };
+        }
+
+        Ok(hash == self.root)
+    }
+
+    /// The input signals of `SMTVerifier(nLevels)` as snarkjs reads them, a JSON object of
+    /// decimal strings
+    ///
+    /// An inclusion proof has `fnc` 0 and zero `oldKey`, `oldValue` and `isOld0`, an exclusion
+    /// proof has `fnc` 1 and a zero `value`, as in circomlib's verifier tests.
+    pub fn to_circuit_inputs(&self) -> String {
+        let siblings = self
+            .siblings
+            .iter()
+            .map(|sibling| format!("\"{sibling}\""))
+            .collect::<Vec<_>>()
+            .join(",");
+        let (old_key, old_value) = match (self.found, self.is_old0) {
+            (false, false) => (self.old_key, self.old_value),
+            _ => (Fr::ZERO, Fr::ZERO),
+        };
+
+        format!(
+            "{{\"enabled\":\"1\",\"fnc\":\"{}\",\"root\":\"{}\",\"siblings\":[{}],\
+             \"oldKey\":\"{}\",\"oldValue\":\"{}\",\"isOld0\":\"{}\",\"key\":\"{}\",\"value\":\"{}\"}}",
+            u8::from(!self.found),
+            self.root,
+            siblings,
+            old_key,
+            old_value,
+            u8::from(!self.found && self.is_old0),
+            self.key,
+            self.value,
+        )
+    }
+}
@@ -0,0 +1,297 @@
+#[cfg(feature = "json")]
+use alloc::string::ToString;
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt;
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+#[cfg(feature = "json")]
+use ark_ff::{Field, Zero};
+use light_poseidon::{Poseidon, PoseidonHasher};
+#[cfg(feature = "json")]
+use serde_json::{json, Value};
+
+#[cfg(all(feature = "json", feature = "tree"))]
+use crate::CircomSmtProof;
+use crate::{
+    zero_hash_for, Arity, BitOrder, HashFamily, HashOperation, HashingScheme, MerkleProof,
+    PoseidonMerkleError, ProofError, MAX_ZERO_HASH_HEIGHT,
+};
+
+/// A verifier circuit whose inputs `to_circuit_inputs` lays out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CircuitTemplate {
+    /// `MerkleTreeInclusionProof(nLevels)` of zk-kit and Semaphore v3
+    ///
+    /// Signals `leaf`, `pathIndices` and `siblings`, one entry per level from the leaf up. The
+    /// template has no padding, `nLevels` must be the depth of the proof.
+    Inclusion,
+    /// `BinaryMerkleRoot(MAX_DEPTH)` of zk-kit
+    ///
+    /// Signals `leaf`, `depth`, `indices` and `siblings`, from the leaf up and padded with zeros
+    /// to `MAX_DEPTH`, which can exceed the depth of the proof.
+    BinaryMerkleRoot,
+    /// `SMTVerifier(nLevels)` of circomlib
+    ///
+    /// Signals `enabled`, `root`, `siblings`, `oldKey`, `oldValue`, `isOld0`, `key`, `value` and
+    /// `fnc`, siblings from the root down and padded with zeros to `nLevels`. Only proofs of
+    /// trees hashing like circomlib's SMT fit, see `CircomSmtProof::to_padded_circuit_inputs`.
+    SmtVerifier,
+    /// A Noir circuit folding `hash_path` with `std::hash::poseidon::bn254::hash_2`
+    ///
+    /// Inputs `leaf`, `root`, `index` and `hash_path`, see `MerkleProof::to_noir_inputs`.
+    Noir,
+}
+
+impl fmt::Display for CircuitTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitTemplate::Inclusion => write!(f, "MerkleTreeInclusionProof"),
+            CircuitTemplate::BinaryMerkleRoot => write!(f, "BinaryMerkleRoot"),
+            CircuitTemplate::SmtVerifier => write!(f, "SMTVerifier"),
+            CircuitTemplate::Noir => write!(f, "Noir"),
+        }
+    }
+}
+
+/// Decimal strings, the way snarkjs reads field elements
+#[cfg(feature = "json")]
+fn decimal_strings<'a>(values: impl IntoIterator<Item = &'a Fr>) -> Vec<Value> {
+    values
+        .into_iter()
+        .map(|value| Value::String(value.to_string()))
+        .collect()
+}
+
+impl MerkleProof<Fr> {
+    /// The inputs of a Noir circuit with `circuit_depth` levels folding the proof with Poseidon
+    ///
+    /// The circuit is expected to fold `hash_path` from the leaf up with `hash_2` of
+    /// `std::hash::poseidon::bn254`, which is circom's Poseidon, so like `to_circuit_inputs` the
+    /// proof must come from a binary Poseidon tree that doesn't bind levels and `leaf` is the leaf
+    /// contribution. `index` follows the bit order of the proof: with `BitOrder::MsbFirst` it is
+    /// the position of the leaf from the left, whose `to_le_bits` pick the side at each level from
+    /// the leaf up, with `BitOrder::LsbFirst` its bit 0 picks the child of the root.
+    ///
+    /// A circuit deeper than the proof takes the tree as the leftmost subtree of an otherwise
+    /// empty tree of `circuit_depth` levels: `hash_path` is padded with the hashes of empty
+    /// subtrees of the height of each level, see `zero_hash_for`, the index gets zero bits on the
+    /// side of the root and `root` is the root of that deeper tree. A circuit shallower than the
+    /// proof fails with `ProofError::CircuitDepthMismatch`.
+    pub fn to_noir_inputs(&self, circuit_depth: usize) -> Result<NoirInputs, PoseidonMerkleError> {
+        self.check_circuit(CircuitTemplate::Noir, circuit_depth)?;
+        let depth = self.depth();
+
+        // From the leaf up, then the empty subtrees above the root of the proof
+        let mut hasher = Poseidon::<Fr>::new_circom(2)
+            .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+        let mut hash_path: Vec<Fr> = self.siblings.iter().rev().copied().collect();
+        let mut root = self.root_hash;
+        for height in depth..circuit_depth {
+            let empty = zero_hash_for(
+                circuit_depth - height,
+                circuit_depth,
+                self.leaf_hashing,
+                HashFamily::Poseidon,
+                self.scheme,
+            );
+            root = hasher
+                .hash(&[root, empty])
+                .map_err(PoseidonMerkleError::hasher(HashOperation::InnerNode {
+                    level: Some(circuit_depth - height - 1),
+                }))?;
+            hash_path.push(empty);
+        }
+
+        let mut index = vec![false; circuit_depth];
+        for level in 0..depth {
+            let bit = match self.bit_order {
+                BitOrder::LsbFirst => circuit_depth - depth + level,
+                BitOrder::MsbFirst => depth - 1 - level,
+            };
+            index[bit] = self.position(level) == 1;
+        }
+
+        Ok(NoirInputs {
+            leaf: self.leaf_contribution()?,
+            root,
+            index: Fr::from_bigint(BigInteger::from_bits_le(&index))
+                .expect("the circuit depth is below the modulus size"),
+            hash_path,
+        })
+    }
+
+    /// Reject proofs `template` can't verify and circuits of the wrong depth
+    fn check_circuit(
+        &self,
+        template: CircuitTemplate,
+        circuit_depth: usize,
+    ) -> Result<(), PoseidonMerkleError> {
+        if template == CircuitTemplate::SmtVerifier
+            || self.hash_family != HashFamily::Poseidon
+            || self.scheme == HashingScheme::LevelBound
+        {
+            return Err(ProofError::UnsupportedTemplate { template }.into());
+        }
+        if self.arity != Arity::Binary {
+            return Err(ProofError::ArityMismatch {
+                expected: Arity::Binary,
+                actual: self.arity,
+            }
+            .into());
+        }
+        let depth = self.depth();
+        if circuit_depth < depth
+            || circuit_depth > MAX_ZERO_HASH_HEIGHT
+            || template == CircuitTemplate::Inclusion && circuit_depth != depth
+        {
+            return Err(ProofError::CircuitDepthMismatch {
+                template,
+                circuit_depth,
+                depth,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// The leaf value after `LeafHashing` and `HashingScheme`, what the circuits start folding
+    fn leaf_contribution(&self) -> Result<Fr, PoseidonMerkleError> {
+        let mut hasher = Poseidon::<Fr>::new_circom(2)
+            .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+        let leaf = self.leaf_hashing.leaf_hash(&self.leaf_value)?;
+
+        self.scheme.leaf_hash(&mut hasher, leaf, self.arity)
+    }
+
+    /// The input signals of `template` with `circuit_depth` levels, as snarkjs reads them
+    ///
+    /// The templates hash with circom's Poseidon and fold from the leaf up whatever the bit order,
+    /// so the proof must come from a binary Poseidon tree that doesn't bind levels, and the `leaf`
+    /// signal is the leaf contribution: the value after `LeafHashing` and `HashingScheme`. Output
+    /// signals such as the root are left out. `circuit_depth` must be the proof depth for
+    /// `CircuitTemplate::Inclusion` and at least that for `CircuitTemplate::BinaryMerkleRoot`,
+    /// `ProofError::CircuitDepthMismatch` reports it otherwise. `CircuitTemplate::Noir` gives
+    /// `to_noir_inputs` as JSON.
+    #[cfg(feature = "json")]
+    pub fn to_circuit_inputs(
+        &self,
+        template: CircuitTemplate,
+        circuit_depth: usize,
+    ) -> Result<Value, PoseidonMerkleError> {
+        if template == CircuitTemplate::Noir {
+            return Ok(self.to_noir_inputs(circuit_depth)?.to_json());
+        }
+        self.check_circuit(template, circuit_depth)?;
+        let depth = self.depth();
+
+        // From the leaf up, padded past the proof depth
+        let mut indices = vec![Fr::zero(); circuit_depth];
+        let mut siblings = vec![Fr::zero(); circuit_depth];
+        for level in 0..depth {
+            if self.position(level) == 1 {
+                indices[depth - 1 - level] = Fr::ONE;
+            }
+            siblings[depth - 1 - level] = self.siblings[level];
+        }
+
+        let leaf = self.leaf_contribution()?;
+        Ok(match template {
+            CircuitTemplate::Inclusion => json!({
+                "leaf": leaf.to_string(),
+                "pathIndices": decimal_strings(&indices),
+                "siblings": decimal_strings(&siblings),
+            }),
+            _ => json!({
+                "leaf": leaf.to_string(),
+                "depth": depth.to_string(),
+                "indices": decimal_strings(&indices),
+                "siblings": decimal_strings(&siblings),
+            }),
+        })
+    }
+}
+
+/// The inputs of a Noir Merkle circuit, see `MerkleProof::to_noir_inputs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoirInputs {
+    /// The leaf contribution the circuit starts folding from
+    pub leaf: Fr,
+    /// The root the circuit folds into
+    pub root: Fr,
+    /// The side taken at each level, one bit per level
+    pub index: Fr,
+    /// Sibling at each level from the leaf up, `circuit_depth` of them
+    pub hash_path: Vec<Fr>,
+}
+
+impl NoirInputs {
+    /// The inputs as a `Prover.toml`, field elements as decimal strings
+    pub fn to_toml(&self) -> String {
+        let hash_path = self
+            .hash_path
+            .iter()
+            .map(|sibling| format!("\"{sibling}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            "leaf = \"{}\"\nroot = \"{}\"\nindex = \"{}\"\nhash_path = [{}]\n",
+            self.leaf, self.root, self.index, hash_path,
+        )
+    }
+
+    /// The inputs as a JSON object of decimal strings, with the names of `to_toml`
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Value {
+        json!({
+            "leaf": self.leaf.to_string(),
+            "root": self.root.to_string(),
+            "index": self.index.to_string(),
+            "hash_path": decimal_strings(&self.hash_path),
+        })
+    }
+}
+
+#[cfg(all(feature = "json", feature = "tree"))]
+impl CircomSmtProof {
+    /// The input signals of `SMTVerifier(circuit_depth)`, siblings padded with zeros
+    ///
+    /// Like `to_circuit_inputs` for a circuit that may have more levels than the tree. The levels
+    /// down to the deepest non-zero sibling must fit in the circuit, or
+    /// `ProofError::CircuitDepthMismatch` is returned.
+    pub fn to_padded_circuit_inputs(&self, circuit_depth: usize) -> Result<Value, ProofError> {
+        let depth = self
+            .siblings
+            .iter()
+            .rposition(|sibling| !sibling.is_zero())
+            .map_or(0, |level| level + 1);
+        if circuit_depth < depth {
+            return Err(ProofError::CircuitDepthMismatch {
+                template: CircuitTemplate::SmtVerifier,
+                circuit_depth,
+                depth,
+            });
+        }
+
+        let mut siblings = self.siblings[..depth].to_vec();
+        siblings.resize(circuit_depth, Fr::zero());
+        let (old_key, old_value) = match (self.found, self.is_old0) {
+            (false, false) => (self.old_key, self.old_value),
+            _ => (Fr::zero(), Fr::zero()),
+        };
+
+        Ok(json!({
+            "enabled": "1",
+            "fnc": u8::from(!self.found).to_string(),
+            "root": self.root.to_string(),
+            "siblings": decimal_strings(&siblings),
+            "oldKey": old_key.to_string(),
+            "oldValue": old_value.to_string(),
+            "isOld0": u8::from(!self.found && self.is_old0).to_string(),
+            "key": self.key.to_string(),
+            "value": self.value.to_string(),
+        }))
+    }
+}
@@ -0,0 +1,277 @@
+use alloc::{vec, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+
+use crate::{
+    get_empty_inner_hash, get_empty_leaf_hash, Arity, BitOrder, EmptySubtrees, HashFamily,
+    HashOperation, HashingScheme, LeafHashing, MerkleProof, PoseidonMerkleError, ProofError,
+};
+
+/// Version of the layout, its first byte
+const COMPACT_VERSION: u8 = 1;
+
+/// Flag of leaves hashed with `LeafHashing::Hashed`, the two bits above it hold the scheme
+const HASHED_LEAF_FLAG: u8 = 1;
+
+/// Flag of trees with `EmptySubtrees::Folded`, whose omitted siblings depend on their level
+const FOLDED_FLAG: u8 = 1 << 3;
+
+/// Bytes of a field element
+pub(crate) const ELEMENT_BYTES: usize = 32;
+
+/// Bytes before the bitmaps: version, flags, depth and leaf value
+const HEADER_BYTES: usize = 3 + ELEMENT_BYTES;
+
+/// A proof in the compact format, borrowing the bytes
+pub(crate) struct CompactProof<'a> {
+    pub(crate) leaf_hashing: LeafHashing,
+    pub(crate) scheme: HashingScheme,
+    pub(crate) empty_subtrees: EmptySubtrees,
+    pub(crate) depth: usize,
+    pub(crate) leaf: &'a [u8],
+    pub(crate) path: &'a [u8],
+    empty: &'a [u8],
+    pub(crate) siblings: &'a [u8],
+}
+
+impl<'a> CompactProof<'a> {
+    pub(crate) fn parse(data: &'a [u8]) -> Result<Self, ProofError> {
+        let malformed = ProofError::MalformedCompactProof;
+        let [version, flags, depth, ..] = *data else {
+            return Err(malformed("truncated header"));
+        };
+        if version != COMPACT_VERSION {
+            return Err(malformed("unknown version"));
+        }
+        let leaf_hashing = match flags & HASHED_LEAF_FLAG {
+            0 => LeafHashing::Raw,
+            _ => LeafHashing::Hashed,
+        };
+        let scheme = match flags >> 1 & 0b11 {
+            0 => HashingScheme::Legacy,
+            1 => HashingScheme::DomainSeparated,
+            2 => HashingScheme::LevelBound,
+            _ => return Err(malformed("unknown flags")),
+        };
+        if flags >> 4 != 0 {
+            return Err(malformed("unknown flags"));
+        }
+        let empty_subtrees = match flags & FOLDED_FLAG {
+            0 => EmptySubtrees::Flat,
+            _ => EmptySubtrees::Folded,
+        };
+        let depth = usize::from(depth);
+        if depth == 0 {
+            return Err(malformed("no siblings"));
+        }
+
+        let bitmap_bytes = depth.div_ceil(8);
+        let bitmaps_end = HEADER_BYTES + 2 * bitmap_bytes;
+        if data.len() < bitmaps_end {
+            return Err(malformed("truncated bitmaps"));
+        }
+        let empty = &data[HEADER_BYTES + bitmap_bytes..bitmaps_end];
+        let omitted = (0..depth).filter(|level| bit(empty, *level)).count();
+        if data.len() != bitmaps_end + (depth - omitted) * ELEMENT_BYTES {
+            return Err(malformed("sibling count doesn't match the bitmap"));
+        }
+
+        Ok(CompactProof {
+            leaf_hashing,
+            scheme,
+            empty_subtrees,
+            depth,
+            leaf: &data[3..HEADER_BYTES],
+            path: &data[HEADER_BYTES..HEADER_BYTES + bitmap_bytes],
+            empty,
+            siblings: &data[bitmaps_end..],
+        })
+    }
+
+    /// The sibling at `level`, `explicit` being the siblings not omitted from `level` on
+    pub(crate) fn sibling<'b>(
+        &self,
+        level: usize,
+        explicit: &mut impl Iterator<Item = &'a [u8]>,
+        empty: &'b [u8],
+    ) -> Result<&'b [u8], ProofError>
+    where
+        'a: 'b,
+    {
+        match bit(self.empty, level) {
+            true => Ok(empty),
+            false => explicit
+                .next()
+                .ok_or(ProofError::MalformedCompactProof("missing sibling")),
+        }
+    }
+}
+
+/// Bit `index` of a little-endian bitmap
+pub(crate) fn bit(bitmap: &[u8], index: usize) -> bool {
+    bitmap[index / 8] >> (index % 8) & 1 == 1
+}
+
+/// A bn254 element as 32 big-endian bytes
+pub(crate) fn be_bytes(value: &Fr) -> [u8; ELEMENT_BYTES] {
+    let mut bytes = [0; ELEMENT_BYTES];
+    bytes.copy_from_slice(&value.into_bigint().to_bytes_be());
+    bytes
+}
+
+/// The element of 32 big-endian bytes, which must be below the modulus
+fn field_element(bytes: &[u8]) -> Result<Fr, ProofError> {
+    let value = Fr::from_be_bytes_mod_order(bytes);
+    match be_bytes(&value) == bytes {
+        true => Ok(value),
+        false => Err(ProofError::MalformedCompactProof(
+            "field element above the modulus",
+        )),
+    }
+}
+
+/// The omitted value of a sibling at the leaf level, the empty leaf contribution
+fn empty_leaf_sibling(
+    leaf_hashing: LeafHashing,
+    scheme: HashingScheme,
+) -> Result<Fr, PoseidonMerkleError> {
+    let leaf = match leaf_hashing {
+        LeafHashing::Raw => Fr::ZERO,
+        LeafHashing::Hashed => *get_empty_leaf_hash(),
+    };
+    if scheme != HashingScheme::DomainSeparated {
+        return Ok(leaf);
+    }
+    let mut hasher = Poseidon::<Fr>::new_circom(2)
+        .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+
+    scheme.leaf_hash(&mut hasher, leaf, Arity::Binary)
+}
+
+/// The omitted values of the siblings from the root down, the hashes of missing nodes
+fn empty_siblings(
+    leaf_hashing: LeafHashing,
+    scheme: HashingScheme,
+    empty_subtrees: EmptySubtrees,
+    depth: usize,
+) -> Result<Vec<Fr>, PoseidonMerkleError> {
+    let mut siblings = vec![*get_empty_inner_hash(); depth];
+    let Some(leaf) = siblings.last_mut() else {
+        return Ok(siblings);
+    };
+    *leaf = empty_leaf_sibling(leaf_hashing, scheme)?;
+    if empty_subtrees == EmptySubtrees::Folded {
+        let mut hasher = Poseidon::<Fr>::new_circom(2)
+            .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+        // The sibling at `level` is a node one level below, an empty subtree of its height
+        for level in (0..depth - 1).rev() {
+            let child = siblings[level + 1];
+            siblings[level] = scheme.inner_hash(
+                &mut hasher,
+                HashFamily::Poseidon,
+                level + 1,
+                &[child, child],
+            )?;
+        }
+    }
+
+    Ok(siblings)
+}
+
+impl MerkleProof<Fr> {
+    /// Pack the proof into the compact byte format
+    ///
+    /// The layout is a version byte, a flags byte (bit 0 for hashed leaves, the scheme in bits 1
+    /// and 2, bit 3 for `EmptySubtrees::Folded`), the depth, the leaf value, a bitmap of the path and a bitmap of the omitted siblings, both
+    /// with one bit per level from the root, then the other siblings from the root down. Bitmaps
+    /// are little-endian and field elements 32 big-endian bytes. A sibling is omitted when it is
+    /// the empty leaf contribution at the leaf level or poseidon(0, 0) above it, the hashes of
+    /// missing nodes, or for a folded tree the empty subtree of its level, so a proof of depth `d` with `n` other siblings takes `35 + 2 * ceil(d / 8) +
+    /// 32 * n` bytes. The root is left out, the verifier compares with the one it keeps.
+    ///
+    /// Only binary Poseidon proofs of at most 255 levels can be packed, others fail with
+    /// `PoseidonMerkleError::UnsupportedCircuit`.
+    pub fn to_compact_bytes(&self) -> Result<Vec<u8>, PoseidonMerkleError> {
+        let depth = self.depth();
+        if self.arity != Arity::Binary || self.hash_family != HashFamily::Poseidon || depth > 255 {
+            return Err(PoseidonMerkleError::UnsupportedCircuit);
+        }
+
+        let flags = u8::from(self.leaf_hashing == LeafHashing::Hashed)
+            | match self.scheme {
+                HashingScheme::Legacy => 0,
+                HashingScheme::DomainSeparated => 1,
+                HashingScheme::LevelBound => 2,
+            } << 1
+            | match self.empty_subtrees {
+                EmptySubtrees::Flat => 0,
+                EmptySubtrees::Folded => FOLDED_FLAG,
+            };
+        let omitted = empty_siblings(self.leaf_hashing, self.scheme, self.empty_subtrees, depth)?;
+
+        let bitmap_bytes = depth.div_ceil(8);
+        let mut path = vec![0; bitmap_bytes];
+        let mut empty = vec![0; bitmap_bytes];
+        let mut siblings = Vec::new();
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            path[level / 8] |= u8::from(self.position(level) == 1) << (level % 8);
+            if *sibling == omitted[level] {
+                empty[level / 8] |= 1 << (level % 8);
+            } else {
+                siblings.extend_from_slice(&be_bytes(sibling));
+            }
+        }
+
+        let mut data = Vec::with_capacity(HEADER_BYTES + 2 * bitmap_bytes + siblings.len());
+        data.extend_from_slice(&[COMPACT_VERSION, flags, depth as u8]);
+        data.extend_from_slice(&be_bytes(&self.leaf_value));
+        data.extend_from_slice(&path);
+        data.extend_from_slice(&empty);
+        data.extend_from_slice(&siblings);
+
+        Ok(data)
+    }
+
+    /// Unpack the proof of `to_compact_bytes`, whose root is `root_hash`
+    ///
+    /// The path is read in `BitOrder::LsbFirst`, whatever the order of the tree it comes from.
+    pub fn from_compact_bytes(data: &[u8], root_hash: Fr) -> Result<Self, PoseidonMerkleError> {
+        let parsed = CompactProof::parse(data)?;
+        let omitted: Vec<_> = empty_siblings(
+            parsed.leaf_hashing,
+            parsed.scheme,
+            parsed.empty_subtrees,
+            parsed.depth,
+        )?
+        .iter()
+        .map(be_bytes)
+        .collect();
+
+        let mut explicit = parsed.siblings.chunks_exact(ELEMENT_BYTES);
+        let mut siblings = Vec::with_capacity(parsed.depth);
+        let mut path = Vec::with_capacity(parsed.depth);
+        for (level, empty) in omitted.iter().enumerate() {
+            siblings.push(field_element(parsed.sibling(
+                level,
+                &mut explicit,
+                empty,
+            )?)?);
+            path.push(bit(parsed.path, level));
+        }
+
+        let merkle_path =
+            Fr::from_bigint(BigInteger::from_bits_le(&path)).expect("the depth is below 256");
+        Ok(MerkleProof::try_new(
+            siblings,
+            merkle_path,
+            field_element(parsed.leaf)?,
+            root_hash,
+        )?
+        .with_leaf_hashing(parsed.leaf_hashing)
+        .with_scheme(parsed.scheme)
+        .with_empty_subtrees(parsed.empty_subtrees)
+        .with_bit_order(BitOrder::LsbFirst))
+    }
+}
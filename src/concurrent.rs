@@ -0,0 +1,486 @@
+use std::{sync::Mutex, thread};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use light_poseidon::Poseidon;
+
+use crate::{
+    path::path_index,
+    shared::{BorrowConflict, CheckedBorrow},
+    telemetry,
+    telemetry::operation_span,
+    BitOrder, HashConfig, HasherInfo, MerkleHasher, MerklePath, Node, NodeRef, NodeType,
+    PoseidonMerkleError, SparseMerkleTree,
+};
+
+/// A subtree built apart from the tree, on a worker thread
+#[derive(Debug)]
+enum Subtree<F: PrimeField> {
+    Leaf(F),
+    Inner {
+        hash: F,
+        children: Vec<Option<Subtree<F>>>,
+        /// Whether an insert went through the node since it was last hashed
+        stale: bool,
+    },
+    /// A materialized inner node of the tree no entry reaches, standing for it by its hash
+    Kept(F),
+}
+
+impl<F: PrimeField> Subtree<F> {
+    fn inner(hash: F, children: usize) -> Self {
+        Subtree::Inner {
+            hash,
+            children: (0..children).map(|_| None).collect(),
+            stale: false,
+        }
+    }
+
+    /// A node of the tree with its children kept, a leaf child being copied with its value
+    fn expand<H: MerkleHasher<F>>(
+        node: &NodeRef<H, F>,
+        children: usize,
+    ) -> Result<Self, BorrowConflict> {
+        let node = node.checked_borrow()?;
+        let subtree = match node.node_type {
+            NodeType::Leaf(value) => Subtree::Leaf(value),
+            NodeType::Inner(hash) => Subtree::Inner {
+                hash,
+                children: node.children[..children]
+                    .iter()
+                    .map(|child| {
+                        child
+                            .as_ref()
+                            .map(|child| {
+                                Ok(match child.checked_borrow()?.node_type {
+                                    NodeType::Leaf(value) => Subtree::Leaf(value),
+                                    NodeType::Inner(hash) => Subtree::Kept(hash),
+                                })
+                            })
+                            .transpose()
+                    })
+                    .collect::<Result<_, _>>()?,
+                stale: false,
+            },
+        };
+        Ok(subtree)
+    }
+}
+
+/// What the workers need to know of the tree, which itself stays on the calling thread
+struct Layout<'a, F: PrimeField> {
+    config: &'a HashConfig<F>,
+    depth: usize,
+    bit_order: BitOrder,
+    /// Hash of a missing node at each level, the root first
+    empty_hashes: Vec<F>,
+    hasher_info: HasherInfo<F>,
+}
+
+impl<F: PrimeField> Layout<'_, F> {
+    fn child_index(&self, merkle_path: &MerklePath<F>, level: usize) -> usize {
+        let offset = self
+            .bit_order
+            .level_offset(level, self.depth, self.config.arity);
+        path_index(merkle_path, offset, self.config.arity)
+    }
+}
+
+/// The entries falling under one node of the top levels, and the subtree already there
+struct Shard<F: PrimeField> {
+    /// The child index taken at each level from the root to the subtree
+    prefix: Vec<usize>,
+    subtree: Option<Subtree<F>>,
+    entries: Vec<(MerklePath<F>, F)>,
+}
+
+/// A hashed shard, to graft on the tree, with the nodes it materialized and its change in set
+/// leaves
+type Built<F> = (Vec<usize>, Subtree<F>, usize, isize);
+
+impl<F: PrimeField> Shard<F> {
+    /// Insert the entries and hash the subtree, returning it with the nodes it materialized and
+    /// the change in set leaves
+    fn build<H: MerkleHasher<F>>(
+        self,
+        hasher: &mut H,
+        layout: &Layout<'_, F>,
+    ) -> Result<Built<F>, PoseidonMerkleError<F>> {
+        let top = self.prefix.len();
+        let children = layout.config.arity.children();
+        let empty_leaf = layout.config.empty_leaf;
+        let mut created = self.subtree.is_none() as usize;
+        let mut root = self
+            .subtree
+            .unwrap_or_else(|| Subtree::inner(layout.config.empty_inner_hash_at(top), children));
+
+        let mut set_leaves = 0;
+        for (merkle_path, value) in self.entries {
+            let mut node = &mut root;
+            let mut materialized = true;
+            for level in top..layout.depth {
+                let Subtree::Inner {
+                    children: slots,
+                    stale,
+                    ..
+                } = node
+                else {
+                    return Err(PoseidonMerkleError::InvalidNodeType {
+                        level,
+                        path: Some(merkle_path),
+                    });
+                };
+                *stale = true;
+
+                let slot = &mut slots[layout.child_index(&merkle_path, level)];
+                materialized &= slot.is_some();
+                created += slot.is_none() as usize;
+                node = slot.get_or_insert_with(|| {
+                    if level == layout.depth - 1 {
+                        Subtree::Leaf(F::zero())
+                    } else {
+                        Subtree::inner(layout.config.empty_inner_hash_at(level + 1), children)
+                    }
+                });
+            }
+
+            let Subtree::Leaf(leaf) = node else {
+                return Err(PoseidonMerkleError::InvalidNodeType {
+                    level: layout.depth,
+                    path: Some(merkle_path),
+                });
+            };
+            let was_set = materialized && *leaf != empty_leaf;
+            set_leaves += (value != empty_leaf) as isize - was_set as isize;
+            *leaf = value;
+        }
+
+        rehash(hasher, layout, &mut root, top)?;
+        Ok((self.prefix, root, created, set_leaves))
+    }
+}
+
+/// Hash the nodes of a subtree that inserts went through, returning the hash it contributes
+fn rehash<H: MerkleHasher<F>, F: PrimeField>(
+    hasher: &mut H,
+    layout: &Layout<'_, F>,
+    node: &mut Subtree<F>,
+    level: usize,
+) -> Result<F, PoseidonMerkleError<F>> {
+    match node {
+        Subtree::Leaf(value) => layout.config.leaf_hash(hasher, value),
+        Subtree::Inner {
+            hash,
+            children,
+            stale,
+        } => {
+            if *stale {
+                let inputs = children
+                    .iter_mut()
+                    .map(|child| match child {
+                        Some(child) => rehash(hasher, layout, child, level + 1),
+                        None => Ok(layout.empty_hashes[level + 1]),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                *hash = layout.config.inner_hash(hasher, level, &inputs)?;
+                *stale = false;
+            }
+
+            Ok(*hash)
+        }
+        Subtree::Kept(hash) => Ok(*hash),
+    }
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> SparseMerkleTree<H, F> {
+    /// `insert_many` spread over `threads` worker threads, each hashing with its own hasher
+    ///
+    /// The entries are split by the child index they take in the top levels of the tree, so that
+    /// every worker builds whole subtrees below them. A worker gets the nodes on the paths of its
+    /// entries, the other nodes of the tree standing in by their hash, and the hashed subtrees are
+    /// written back over the tree and the top levels hashed on the calling thread. The root is the one of `insert_many`, and a
+    /// later entry at the same path wins.
+    ///
+    /// `new_hasher` is called once per worker, its hashers must have the parameters fingerprint of
+    /// the tree hasher. A failing worker leaves the tree untouched. With a single thread, and with
+    /// an observer, a changelog or a trace sink that follow the inserts one by one, this is
+    /// `insert_many`.
+    pub fn insert_many_concurrent_with(
+        &mut self,
+        entries: impl IntoIterator<Item = (MerklePath<F>, F)>,
+        threads: usize,
+        new_hasher: impl Fn() -> Result<H, PoseidonMerkleError<F>> + Sync,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let _span = operation_span!("insert_many_concurrent", depth = self.depth, threads);
+        #[cfg(feature = "hash-trace")]
+        let traced = self.trace.is_set();
+        #[cfg(not(feature = "hash-trace"))]
+        let traced = false;
+        if threads <= 1
+            || self.depth == 1
+            || self.observer.is_set()
+            || self.changelog().is_some()
+            || traced
+        {
+            return self.insert_many(entries);
+        }
+        if self.leaf_width() != 1 {
+            return Err(PoseidonMerkleError::LeafWidthMismatch {
+                expected: self.leaf_width(),
+                actual: 1,
+            });
+        }
+
+        // Enough top levels for a subtree per thread, the leaf level left to the workers
+        let children = self.hash_config().arity.children();
+        let mut top = 1;
+        while children.pow(top as u32) < threads && top < self.depth - 1 {
+            top += 1;
+        }
+
+        // Cached hashes are trusted below the top levels, bring them up to date first
+        self.root()?;
+        let mut shards: Vec<Vec<(MerklePath<F>, F)>> = vec![Vec::new(); children.pow(top as u32)];
+        let mut inserted = 0;
+        for (merkle_path, value) in entries {
+            let merkle_path = self.canonicalize_path(&merkle_path);
+            let shard = (0..top).fold(0, |shard, level| {
+                shard * children + self.child_index(&merkle_path, level)
+            });
+            shards[shard].push((merkle_path, value));
+            inserted += 1;
+        }
+        self.check_node_budget(shards.iter().flatten().map(|(merkle_path, _)| merkle_path))?;
+        let shards: Vec<Shard<F>> = shards
+            .into_iter()
+            .enumerate()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|(shard, entries)| {
+                let prefix: Vec<usize> = (0..top)
+                    .rev()
+                    .map(|level| shard / children.pow(level as u32) % children)
+                    .collect();
+                let subtree = match self.subtree_at(&prefix)? {
+                    Some(node) => {
+                        let mut subtree = Subtree::expand(&node, children)?;
+                        for (merkle_path, _) in &entries {
+                            self.expand_path(&node, &mut subtree, merkle_path, top)?;
+                        }
+                        Some(subtree)
+                    }
+                    None => None,
+                };
+                Ok(Shard {
+                    prefix,
+                    subtree,
+                    entries,
+                })
+            })
+            .collect::<Result<_, PoseidonMerkleError<F>>>()?;
+
+        let layout = Layout {
+            config: self.hash_config(),
+            depth: self.depth,
+            bit_order: self.bit_order(),
+            empty_hashes: {
+                let hasher = &mut *self.hasher.checked_borrow_mut()?;
+                (0..=self.depth)
+                    .map(|level| {
+                        self.hash_config()
+                            .empty_hash_at(hasher, level, level == self.depth)
+                    })
+                    .collect::<Result<_, _>>()?
+            },
+            hasher_info: self.hasher_info(),
+        };
+        let workers = threads.min(shards.len());
+        let queue = Mutex::new(shards);
+        let built = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut hasher = new_hasher()?;
+                        let fingerprint = hasher
+                            .params_fingerprint(layout.config.arity)
+                            .map_err(|source| PoseidonMerkleError::InvalidHasherWidth { source })?;
+                        HasherInfo {
+                            fingerprint,
+                            ..layout.hasher_info
+                        }
+                        .ensure_matches(&layout.hasher_info)?;
+
+                        let mut built = Vec::new();
+                        loop {
+                            let Some(shard) = queue.lock().expect("no worker panics").pop() else {
+                                break;
+                            };
+                            built.push(shard.build(&mut hasher, &layout)?);
+                        }
+                        Ok(built)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Result<Vec<Vec<_>>, PoseidonMerkleError<F>>>()
+        })?;
+
+        for (prefix, subtree, created, _set_leaves) in built.into_iter().flatten() {
+            self.nodes += created + self.graft(&prefix, subtree)?;
+            #[cfg(feature = "metrics")]
+            {
+                self.leaves = self.leaves.saturating_add_signed(_set_leaves as _);
+            }
+        }
+        for _ in 0..inserted {
+            telemetry::record_insert();
+        }
+        #[cfg(feature = "metrics")]
+        telemetry::record_leaves(self.leaves);
+
+        self.root().map(|_| ())
+    }
+
+    /// The node at the end of a prefix of child indexes, if materialized
+    fn subtree_at(&self, prefix: &[usize]) -> Result<Option<NodeRef<H, F>>, BorrowConflict> {
+        let mut current = self.root.clone();
+        for index in prefix {
+            let Some(next) = current.checked_borrow()?.children[*index].clone() else {
+                return Ok(None);
+            };
+            current = next;
+        }
+
+        Ok(Some(current))
+    }
+
+    /// Expand the nodes of a subtree of the tree on the path of an entry, from the level `top` of
+    /// its root, so that the workers only meet kept nodes off the paths of their entries
+    fn expand_path(
+        &self,
+        node: &NodeRef<H, F>,
+        mut subtree: &mut Subtree<F>,
+        merkle_path: &MerklePath<F>,
+        top: usize,
+    ) -> Result<(), BorrowConflict> {
+        let children = self.hash_config().arity.children();
+        let mut node = node.clone();
+        for level in top..self.depth - 1 {
+            let Subtree::Inner {
+                children: slots, ..
+            } = subtree
+            else {
+                return Ok(());
+            };
+            let index = self.child_index(merkle_path, level);
+            let Some(child) = node.checked_borrow()?.children[index].clone() else {
+                return Ok(());
+            };
+            let slot = slots[index].as_mut().expect("the subtree mirrors the tree");
+            if let Subtree::Kept(_) = slot {
+                *slot = Subtree::expand(&child, children)?;
+            }
+            subtree = slot;
+            node = child;
+        }
+
+        Ok(())
+    }
+
+    /// Put a subtree at the end of a prefix, marking the nodes above it dirty, and return the
+    /// number of nodes materialized above it
+    fn graft(&mut self, prefix: &[usize], subtree: Subtree<F>) -> Result<usize, BorrowConflict> {
+        let (last, above) = prefix.split_last().expect("grafts are below the root");
+        let mut created = 0;
+        let mut current = self.root.clone();
+        for (level, index) in above.iter().enumerate() {
+            let next = {
+                let mut current_ref = current.checked_borrow_mut()?;
+                current_ref.mark_dirty();
+                current_ref.children[*index]
+                    .get_or_insert_with(|| {
+                        created += 1;
+                        Node::new_borrowed_inner(self.hash_config().empty_inner_hash_at(level + 1))
+                    })
+                    .clone()
+            };
+            current = next;
+        }
+
+        let mut parent = current.checked_borrow_mut()?;
+        parent.mark_dirty();
+        merge(&mut parent.children[*last], subtree)?;
+        Ok(created)
+    }
+}
+
+impl SparseMerkleTree<Poseidon<Fr>> {
+    /// `insert_many_concurrent_with` circom hashers of the arity of the tree
+    ///
+    /// A tree built with other Poseidon parameters fails with `PoseidonMerkleError::HasherMismatch`,
+    /// give `insert_many_concurrent_with` hashers with its parameters instead.
+    pub fn insert_many_concurrent(
+        &mut self,
+        entries: impl IntoIterator<Item = (MerklePath, Fr)>,
+        threads: usize,
+    ) -> Result<(), PoseidonMerkleError> {
+        let width = self.arity().children();
+        self.insert_many_concurrent_with(entries, threads, || {
+            Poseidon::<Fr>::new_circom(width)
+                .map_err(|source| PoseidonMerkleError::InvalidHasherWidth { source })
+        })
+    }
+}
+
+/// Write a hashed subtree over the nodes of the tree, the kept ones left as they are
+fn merge<H: MerkleHasher<F>, F: PrimeField>(
+    slot: &mut Option<NodeRef<H, F>>,
+    subtree: Subtree<F>,
+) -> Result<(), BorrowConflict> {
+    let Some(node) = slot else {
+        *slot = Some(into_node(subtree)?);
+        return Ok(());
+    };
+
+    match subtree {
+        Subtree::Leaf(value) => node.checked_borrow_mut()?.node_type = NodeType::Leaf(value),
+        Subtree::Inner { hash, children, .. } => {
+            let mut node_ref = node.checked_borrow_mut()?;
+            node_ref.node_type = NodeType::Inner(hash);
+            for (slot, child) in node_ref.children.iter_mut().zip(children) {
+                if let Some(child) = child {
+                    merge(slot, child)?;
+                }
+            }
+        }
+        Subtree::Kept(_) => {}
+    }
+    Ok(())
+}
+
+/// The nodes of a hashed subtree, to graft on the tree
+fn into_node<H: MerkleHasher<F>, F: PrimeField>(
+    subtree: Subtree<F>,
+) -> Result<NodeRef<H, F>, BorrowConflict> {
+    let node = match subtree {
+        Subtree::Leaf(value) => Node::new_borrowed_leaf(value),
+        Subtree::Inner { hash, children, .. } => {
+            let node = Node::new_borrowed_inner(hash);
+            {
+                let mut node_ref = node.checked_borrow_mut()?;
+                for (slot, child) in node_ref.children.iter_mut().zip(children) {
+                    *slot = child.map(into_node).transpose()?;
+                }
+            }
+            node
+        }
+        Subtree::Kept(_) => unreachable!("kept nodes are in the tree"),
+    };
+    Ok(node)
+}
@@ -1,23 +1,358 @@
-use ark_bn254::{Fr, FrConfig};
-use ark_ff::{Fp, MontBackend};
-use std::{str::FromStr, sync::OnceLock};
+use alloc::vec::Vec;
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, MontFp, PrimeField};
+#[cfg(all(feature = "tree", not(target_os = "solana")))]
+use core::sync::atomic::{AtomicBool, Ordering};
+use light_poseidon::{Poseidon, PoseidonHasher};
+#[cfg(all(feature = "std", not(target_os = "solana")))]
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock, PoisonError},
+};
 
-const EMPTY_LEAF_HASH_BN: &str =
-    "19014214495641488759237505126948346942972912379615652741039992445865937985820";
+#[cfg(feature = "mimc")]
+use crate::MimcHasher;
+#[cfg(feature = "poseidon2")]
+use crate::Poseidon2Hasher;
+#[cfg(feature = "rescue")]
+use crate::RescueHasher;
+use crate::{
+    Arity, HashFamily, HashOperation, HashingScheme, InnerHash, LeafHashing, PoseidonMerkleError,
+};
 
-const EMPTY_INNER_HASH_BN: &str =
-    "10447686833432518214645507207530993719569269870494442919228205482093666444588";
+/// Depth of a tree built with `SparseMerkleTree::try_default()`
+pub const DEFAULT_DEPTH: usize = 20;
 
-static EMPTY_LEAF_HASH: OnceLock<Fp<MontBackend<FrConfig, 4>, 4>> = OnceLock::new();
+/// Pre-computed hashes are plain statics rather than lazily parsed, on-chain programs can't hold
+/// writable static data.
+static EMPTY_LEAF_HASH: Fr =
+    MontFp!("19014214495641488759237505126948346942972912379615652741039992445865937985820");
+
+static EMPTY_INNER_HASH: Fr =
+    MontFp!("14744269619966411208579211824598458697587494354926760081771325075741142829156");
+
+static QUATERNARY_EMPTY_INNER_HASH: Fr =
+    MontFp!("2351654555892372227640888372176282444150254868378439619268573230312091195718");
+
+static OCTAL_EMPTY_INNER_HASH: Fr =
+    MontFp!("1524321216038799095937469147836866648978083754965002674906449126183454401066");
+
+#[cfg(feature = "poseidon2")]
+static POSEIDON2_EMPTY_INNER_HASH: Fr =
+    MontFp!("21177166670744647784289648293577786481357446166129397094207318338605633126018");
+
+#[cfg(feature = "mimc")]
+static MIMC_EMPTY_INNER_HASH: Fr =
+    MontFp!("3089049976446759283073903078838002107081160427222305800976141688008169211302");
+
+#[cfg(feature = "rescue")]
+static RESCUE_EMPTY_INNER_HASH: Fr =
+    MontFp!("13855904132142100019612279939665152373054890470938817463992671751993375865267");
+
+/// Deepest tree whose empty root is pre-computed, see `get_empty_root`
+pub const PRECOMPUTED_EMPTY_ROOTS: usize = 32;
+
+/// Roots of empty binary circom Poseidon trees with folded empty subtrees, indexed by depth
+static EMPTY_ROOTS: [Fr; PRECOMPUTED_EMPTY_ROOTS + 1] = [
+    MontFp!("0"),
+    MontFp!("14744269619966411208579211824598458697587494354926760081771325075741142829156"),
+    MontFp!("7423237065226347324353380772367382631490014989348495481811164164159255474657"),
+    MontFp!("11286972368698509976183087595462810875513684078608517520839298933882497716792"),
+    MontFp!("3607627140608796879659380071776844901612302623152076817094415224584923813162"),
+    MontFp!("19712377064642672829441595136074946683621277828620209496774504837737984048981"),
+    MontFp!("20775607673010627194014556968476266066927294572720319469184847051418138353016"),
+    MontFp!("3396914609616007258851405644437304192397291162432396347162513310381425243293"),
+    MontFp!("21551820661461729022865262380882070649935529853313286572328683688269863701601"),
+    MontFp!("6573136701248752079028194407151022595060682063033565181951145966236778420039"),
+    MontFp!("12413880268183407374852357075976609371175688755676981206018884971008854919922"),
+    MontFp!("14271763308400718165336499097156975241954733520325982997864342600795471836726"),
+    MontFp!("20066985985293572387227381049700832219069292839614107140851619262827735677018"),
+    MontFp!("9394776414966240069580838672673694685292165040808226440647796406499139370960"),
+    MontFp!("11331146992410411304059858900317123658895005918277453009197229807340014528524"),
+    MontFp!("15819538789928229930262697811477882737253464456578333862691129291651619515538"),
+    MontFp!("19217088683336594659449020493828377907203207941212636669271704950158751593251"),
+    MontFp!("21035245323335827719745544373081896983162834604456827698288649288827293579666"),
+    MontFp!("6939770416153240137322503476966641397417391950902474480970945462551409848591"),
+    MontFp!("10941962436777715901943463195175331263348098796018438960955633645115732864202"),
+    MontFp!("15019797232609675441998260052101280400536945603062888308240081994073687793470"),
+    MontFp!("11702828337982203149177882813338547876343922920234831094975924378932809409969"),
+    MontFp!("11217067736778784455593535811108456786943573747466706329920902520905755780395"),
+    MontFp!("16072238744996205792852194127671441602062027943016727953216607508365787157389"),
+    MontFp!("17681057402012993898104192736393849603097507831571622013521167331642182653248"),
+    MontFp!("21694045479371014653083846597424257852691458318143380497809004364947786214945"),
+    MontFp!("8163447297445169709687354538480474434591144168767135863541048304198280615192"),
+    MontFp!("14081762237856300239452543304351251708585712948734528663957353575674639038357"),
+    MontFp!("16619959921569409661790279042024627172199214148318086837362003702249041851090"),
+    MontFp!("7022159125197495734384997711896547675021391130223237843255817587255104160365"),
+    MontFp!("4114686047564160449611603615418567457008101555090703535405891656262658644463"),
+    MontFp!("12549363297364877722388257367377629555213421373705596078299904496781819142130"),
+    MontFp!("21443572485391568159800782191812935835534334817699172242223315142338162256601"),
+];
 
 /// Pre-computed poseidon(0) to mimic an empty leaf node
-pub fn get_empty_leaf_hash() -> &'static Fp<MontBackend<FrConfig, 4>, 4> {
-    EMPTY_LEAF_HASH.get_or_init(|| Fr::from_str(EMPTY_LEAF_HASH_BN).unwrap())
+///
+/// Computed with the circom bn254 parameters for one input. This is the empty leaf of trees using
+/// `LeafHashing::Hashed`, with the default raw leaves an empty leaf is `Fr::ZERO`.
+pub fn get_empty_leaf_hash() -> &'static Fr {
+    &EMPTY_LEAF_HASH
 }
 
-static EMPTY_INNER_HASH: OnceLock<Fp<MontBackend<FrConfig, 4>, 4>> = OnceLock::new();
-
 /// Pre-computed poseidon(0, 0) to mimic an empty inner node
-pub fn get_empty_inner_hash() -> &'static Fp<MontBackend<FrConfig, 4>, 4> {
-    EMPTY_INNER_HASH.get_or_init(|| Fr::from_str(EMPTY_INNER_HASH_BN).unwrap())
+///
+/// Computed with the circom bn254 parameters for two inputs. The tree uses it for every missing
+/// inner node.
+pub fn get_empty_inner_hash() -> &'static Fr {
+    &EMPTY_INNER_HASH
+}
+
+/// Poseidon of one zero per child, the empty inner node of a tree of the given arity
+///
+/// The binary one is `get_empty_inner_hash`, the wider ones are computed with the circom
+/// parameters for four and eight inputs.
+pub fn get_empty_inner_hash_for(arity: Arity) -> &'static Fr {
+    match arity {
+        Arity::Binary => get_empty_inner_hash(),
+        Arity::Quaternary => &QUATERNARY_EMPTY_INNER_HASH,
+        Arity::Octal => &OCTAL_EMPTY_INNER_HASH,
+    }
+}
+
+/// Pre-computed Poseidon2 hash(0, 0), the empty inner node of trees hashed with `Poseidon2Hasher`
+#[cfg(feature = "poseidon2")]
+pub fn get_poseidon2_empty_inner_hash() -> &'static Fr {
+    &POSEIDON2_EMPTY_INNER_HASH
+}
+
+/// Pre-computed MiMC hash(0, 0), the empty inner node of trees hashed with `MimcHasher`
+#[cfg(feature = "mimc")]
+pub fn get_mimc_empty_inner_hash() -> &'static Fr {
+    &MIMC_EMPTY_INNER_HASH
+}
+
+/// Pre-computed Rescue-Prime hash(0, 0), the empty inner node of trees hashed with `RescueHasher`
+#[cfg(feature = "rescue")]
+pub fn get_rescue_empty_inner_hash() -> &'static Fr {
+    &RESCUE_EMPTY_INNER_HASH
+}
+
+/// Round constants from a keccak256 chain, the way circomlib derives the MiMC ones
+///
+/// The seed is hashed once, then every constant hashes the previous digest again and reads it
+/// big-endian modulo the field order.
+#[cfg(any(feature = "mimc", feature = "rescue"))]
+pub(crate) fn keccak_round_constants(seed: &str, count: usize) -> Vec<Fr> {
+    use sha3::{Digest, Keccak256};
+
+    let mut digest = Keccak256::digest(seed.as_bytes());
+    (0..count)
+        .map(|_| {
+            digest = Keccak256::digest(digest);
+            Fr::from_be_bytes_mod_order(&digest)
+        })
+        .collect()
+}
+
+/// Tallest empty subtree in the zero-hash tables, a path can't address more levels than Fr has bits
+pub const MAX_ZERO_HASH_HEIGHT: usize = Fr::MODULUS_BIT_SIZE as usize;
+
+/// Hashing conventions a zero-hash table is computed for
+type ZeroHashKey = (HashFamily, LeafHashing, HashingScheme);
+
+/// Zero-hash tables, each computed on first use
+#[cfg(all(feature = "std", not(target_os = "solana")))]
+static ZERO_HASHES: OnceLock<Mutex<HashMap<ZeroHashKey, Vec<Fr>>>> = OnceLock::new();
+
+/// Hashes of empty subtrees up to `max_height`, indexed by height
+fn zero_hash_table((family, leaf_hashing, scheme): ZeroHashKey, max_height: usize) -> Vec<Fr> {
+    match family {
+        HashFamily::Poseidon => zero_hash_table_with(
+            leaf_hashing,
+            scheme,
+            max_height,
+            &mut Poseidon::<Fr>::new_circom(2).expect("circom parameters for two inputs exist"),
+        ),
+        #[cfg(feature = "poseidon2")]
+        HashFamily::Poseidon2 => zero_hash_table_with(
+            leaf_hashing,
+            scheme,
+            max_height,
+            &mut Poseidon2Hasher::new(),
+        ),
+        #[cfg(feature = "mimc")]
+        HashFamily::Mimc => {
+            zero_hash_table_with(leaf_hashing, scheme, max_height, &mut MimcHasher::new())
+        }
+        #[cfg(feature = "rescue")]
+        HashFamily::Rescue => {
+            zero_hash_table_with(leaf_hashing, scheme, max_height, &mut RescueHasher::new())
+        }
+        HashFamily::Custom => panic!("zero hashes of a custom hash family are unknown"),
+    }
+}
+
+/// Hashes of empty subtrees indexed by height, starting from the empty leaf of the conventions
+fn zero_hash_table_with(
+    leaf_hashing: LeafHashing,
+    scheme: HashingScheme,
+    max_height: usize,
+    hasher: &mut impl PoseidonHasher<Fr>,
+) -> Vec<Fr> {
+    let empty_leaf = leaf_hashing
+        .empty_leaf_hash()
+        .expect("circom parameters for one input exist");
+    let mut table = Vec::with_capacity(max_height + 1);
+    table.push(
+        scheme
+            .leaf_hash(hasher, empty_leaf, Arity::Binary)
+            .expect("two inputs match the hasher width"),
+    );
+    for height in 1..=max_height {
+        let child = table[height - 1];
+        table.push(
+            hasher
+                .hash(&[child, child])
+                .expect("two inputs match the hasher width"),
+        );
+    }
+
+    table
+}
+
+/// Canonical hash of an empty subtree rooted at `level` in a binary tree of `depth`, with raw leaves
+///
+/// `zero_hash(depth, depth)` is the zero leaf, `zero_hash(depth - 1, depth)` is poseidon(0, 0)
+/// and every level above hashes two copies of the level below.
+///
+/// Note that with a zero empty leaf a tree with `EmptySubtrees::Flat` only follows this convention
+/// for the last two levels: any missing inner node contributes poseidon(0, 0) whatever its level.
+/// Trees of `SparseMerkleTree::new` fold their empty subtrees and follow it.
+///
+/// Panics if `level > depth` or if the subtree is taller than `MAX_ZERO_HASH_HEIGHT`.
+pub fn zero_hash(level: usize, depth: usize) -> InnerHash {
+    zero_hash_with(level, depth, LeafHashing::Raw)
+}
+
+/// Hash of a missing node at `level` of a binary circom Poseidon tree of `depth` levels with
+/// `EmptySubtrees::Folded`, the root of an empty one at level 0
+///
+/// This is `zero_hash`, the root of an empty tree of `depth - level` levels, and panics alike.
+pub fn get_empty_hash_at_level(level: usize, depth: usize) -> InnerHash {
+    assert!(level <= depth, "level {level} is below the depth {depth}");
+    get_empty_root(depth - level)
+}
+
+/// Root of an empty binary circom Poseidon tree of `depth` levels with `EmptySubtrees::Folded`
+///
+/// The roots of up to `PRECOMPUTED_EMPTY_ROOTS` levels are pre-computed, deeper ones are read from
+/// the zero-hash table of `zero_hash`. A tree of 0 levels is the zero leaf.
+///
+/// Panics past `MAX_ZERO_HASH_HEIGHT` levels.
+pub fn get_empty_root(depth: usize) -> InnerHash {
+    match EMPTY_ROOTS.get(depth) {
+        Some(root) => *root,
+        None => zero_hash(0, depth),
+    }
+}
+
+/// Canonical hash of an empty subtree rooted at `level`, leaves being hashed per `leaf_hashing`
+///
+/// With `LeafHashing::Hashed` the empty leaf is poseidon(0), see `get_empty_leaf_hash`.
+pub fn zero_hash_with(level: usize, depth: usize, leaf_hashing: LeafHashing) -> InnerHash {
+    zero_hash_for(
+        level,
+        depth,
+        leaf_hashing,
+        HashFamily::Poseidon,
+        HashingScheme::Legacy,
+    )
+}
+
+/// Canonical hash of an empty subtree rooted at `level`, inner nodes being hashed with `family`
+///
+/// Each combination of family, leaf hashing and scheme has its own table, computed on first use
+/// (or on every call without the `std` feature).
+/// Leaves are hashed per `leaf_hashing` whatever the family, then per `scheme` with the family
+/// hasher: under `HashingScheme::DomainSeparated` the empty leaf is hasher(0, 1).
+///
+/// Panics for `HashFamily::Custom`, whose hash function the crate can't compute.
+pub fn zero_hash_for(
+    level: usize,
+    depth: usize,
+    leaf_hashing: LeafHashing,
+    family: HashFamily,
+    scheme: HashingScheme,
+) -> InnerHash {
+    assert!(level <= depth, "level {level} is below the depth {depth}");
+    let key = (family, leaf_hashing, scheme);
+
+    // On-chain or without std there is no cache, only the heights up to the requested one are
+    // hashed
+    #[cfg(any(not(feature = "std"), target_os = "solana"))]
+    return zero_hash_table(key, depth - level)[depth - level];
+
+    #[cfg(all(feature = "std", not(target_os = "solana")))]
+    {
+        let mut tables = ZERO_HASHES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let table = tables
+            .entry(key)
+            .or_insert_with(|| zero_hash_table(key, MAX_ZERO_HASH_HEIGHT));
+
+        table[depth - level]
+    }
+}
+
+/// Recompute the pre-computed empty hashes and compare them against the constants
+///
+/// `hasher` is the two-input hasher used for inner nodes, poseidon(0) is computed with the circom
+/// one-input parameters.
+pub fn validate_constants<H: PoseidonHasher<Fr>>(
+    hasher: &mut H,
+) -> Result<(), PoseidonMerkleError> {
+    let mut leaf_hasher = Poseidon::<Fr>::new_circom(1)
+        .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+    let empty_leaf_hash = leaf_hasher
+        .hash(&[Fr::ZERO])
+        .map_err(PoseidonMerkleError::hasher(
+            HashOperation::ConstantValidation,
+        ))?;
+    if empty_leaf_hash != *get_empty_leaf_hash() {
+        return Err(PoseidonMerkleError::ConstantMismatch("empty leaf hash"));
+    }
+
+    let empty_inner_hash =
+        hasher
+            .hash(&[Fr::ZERO, Fr::ZERO])
+            .map_err(PoseidonMerkleError::hasher(
+                HashOperation::ConstantValidation,
+            ))?;
+    if empty_inner_hash != *get_empty_inner_hash() {
+        return Err(PoseidonMerkleError::ConstantMismatch("empty inner hash"));
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "tree", not(target_os = "solana")))]
+static CONSTANTS_VALIDATED: AtomicBool = AtomicBool::new(false);
+
+/// Validate the constants once per process, a failed check is retried on the next call
+///
+/// On-chain there is no process-wide state to remember the check in, it runs on every call.
+#[cfg(feature = "tree")]
+pub(crate) fn validate_constants_once<H: PoseidonHasher<Fr>>(
+    hasher: &mut H,
+) -> Result<(), PoseidonMerkleError> {
+    #[cfg(not(target_os = "solana"))]
+    if CONSTANTS_VALIDATED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    validate_constants(hasher)?;
+    #[cfg(not(target_os = "solana"))]
+    CONSTANTS_VALIDATED.store(true, Ordering::Relaxed);
+
+    Ok(())
 }
@@ -0,0 +1,237 @@
+//! Deltas replicating a tree from the updates in its changelog
+//!
+//! A delta holds the root it applies to, the root it leads to and the last value of every leaf
+//! updated in between. As bytes it's the magic `MPSD`, the version, the base and target roots,
+//! then the path and value of each leaf, every element 32 big-endian bytes like in a snapshot.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::mem;
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use thiserror::Error;
+
+use crate::{
+    eth::{from_bytes32, to_bytes32},
+    ChangeLog, InnerHash, MerkleHasher, MerklePath, PoseidonMerkleError, SparseMerkleTree,
+};
+
+const MAGIC: &[u8; 4] = b"MPSD";
+
+/// Version of the layout, the byte after the magic
+const DELTA_VERSION: u8 = 1;
+
+/// Bytes of the header: magic, version, base and target roots
+const HEADER_BYTES: usize = 4 + 1 + 2 * 32;
+
+/// Why a delta wasn't exported or applied
+#[derive(Error, Debug, PartialEq)]
+pub enum DeltaError<F: PrimeField = Fr> {
+    #[error("the changelog holds no update leading to the root {root}")]
+    UnknownBaseRoot { root: InnerHash<F> },
+    #[error("the delta applies to the root {expected}, the tree is at {actual}")]
+    BaseRootMismatch {
+        expected: InnerHash<F>,
+        actual: InnerHash<F>,
+    },
+    #[error("the delta leads to the root {actual}, not the declared {expected}")]
+    DeltaMismatch {
+        expected: InnerHash<F>,
+        actual: InnerHash<F>,
+    },
+    #[error(transparent)]
+    Tree(#[from] PoseidonMerkleError<F>),
+}
+
+/// The leaves updated between two roots, see `SparseMerkleTree::export_delta`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeDelta<F: PrimeField = Fr> {
+    /// The root of the tree the delta applies to
+    pub base_root: InnerHash<F>,
+    /// The root of the tree once applied
+    pub target_root: InnerHash<F>,
+    /// The last value of each updated leaf by canonical path, the empty leaf for a delete
+    pub leaves: Vec<(MerklePath<F>, F)>,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField, M> SparseMerkleTree<H, F, M> {
+    /// The leaves updated since the tree was at `since_root`, for a replica at that root
+    ///
+    /// The base root is the current one or the root after one of the updates of the changelog, so
+    /// a tree built without `with_changelog` only exports empty deltas. A root the changelog
+    /// dropped, or never had, fails with `DeltaError::UnknownBaseRoot`.
+    pub fn export_delta(&self, since_root: &InnerHash<F>) -> Result<TreeDelta<F>, DeltaError<F>> {
+        let target_root = self.root()?;
+        let entries: Vec<_> = self
+            .changelog()
+            .map(|changelog| changelog.entries().collect())
+            .unwrap_or_default();
+        let start = if *since_root == target_root {
+            entries.len()
+        } else {
+            entries
+                .iter()
+                .rposition(|entry| entry.new_root == *since_root)
+                .map(|position| position + 1)
+                .ok_or(DeltaError::UnknownBaseRoot { root: *since_root })?
+        };
+
+        let leaves: BTreeMap<_, _> = entries[start..]
+            .iter()
+            .map(|entry| (entry.leaf_index, entry.new_leaf))
+            .collect();
+
+        Ok(TreeDelta {
+            base_root: *since_root,
+            target_root,
+            leaves: leaves.into_iter().collect(),
+        })
+    }
+
+    /// Apply a delta exported at the root of this tree, returning the new root
+    ///
+    /// The tree must be at the base root of the delta, and the leaves must hash to its target
+    /// root. Otherwise the leaves already written get their previous values back, restoring the
+    /// root, and the error is returned: `DeltaError::DeltaMismatch` for another root, the tree
+    /// error if a write failed. The observer and the changelog only hear of the leaves of a delta
+    /// reaching its target root.
+    pub fn apply_delta(&mut self, delta: &TreeDelta<F>) -> Result<InnerHash<F>, DeltaError<F>> {
+        let actual = self.root()?;
+        if actual != delta.base_root {
+            return Err(DeltaError::BaseRootMismatch {
+                expected: delta.base_root,
+                actual,
+            });
+        }
+
+        let previous = delta
+            .leaves
+            .iter()
+            .map(|(merkle_path, _)| Ok((*merkle_path, self.try_get_value(merkle_path)?)))
+            .collect::<Result<Vec<_>, PoseidonMerkleError<F>>>()?;
+        // The updates are staged in a changelog of their own, and told once the root is checked
+        let observer = self.observer.replace(None);
+        let changelog = self.changelog.replace(ChangeLog::new(delta.leaves.len()));
+        let mut written = 0;
+        let applied = delta
+            .leaves
+            .iter()
+            .try_for_each(|(merkle_path, value)| {
+                if *value == self.empty_leaf() {
+                    self.delete_at_path(merkle_path)?;
+                } else {
+                    self.insert_at_path(merkle_path, value)?;
+                }
+                written += 1;
+                Ok(())
+            })
+            .and_then(|()| self.root());
+        let staged = mem::replace(&mut self.changelog, changelog).expect("the staged changelog");
+        self.observer.replace(observer);
+
+        let error = match applied {
+            Ok(root) if root == delta.target_root => {
+                let updates = delta.leaves.iter().zip(&previous).zip(staged.entries());
+                for (((merkle_path, value), (_, old)), entry) in updates {
+                    if *value == self.empty_leaf() {
+                        self.observer.notify(|observer| {
+                            observer.on_delete(merkle_path, *old, &entry.new_root)
+                        });
+                    } else {
+                        self.observer.notify(|observer| {
+                            observer.on_insert(merkle_path, *old, value, &entry.new_root)
+                        });
+                    }
+                    if let Some(changelog) = self.changelog.as_mut() {
+                        changelog.push(entry.clone());
+                    }
+                }
+                return Ok(root);
+            }
+            Ok(root) => DeltaError::DeltaMismatch {
+                expected: delta.target_root,
+                actual: root,
+            },
+            Err(error) => DeltaError::Tree(error),
+        };
+        // Undone without telling the observer or the changelog: a leaf that wasn't materialized is
+        // removed, leaving the nodes of the base root, and a zero one is placed back
+        for (merkle_path, value) in previous[..written].iter().rev() {
+            match value {
+                Some(value) => self.place_leaf(merkle_path, value)?,
+                None => self.remove_leaf(merkle_path)?,
+            };
+        }
+
+        Err(error)
+    }
+}
+
+impl TreeDelta {
+    /// Serialize the delta, see the `delta` module for the layout
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_BYTES + self.leaves.len() * 64);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(DELTA_VERSION);
+        bytes.extend_from_slice(&to_bytes32(&self.base_root));
+        bytes.extend_from_slice(&to_bytes32(&self.target_root));
+        for (merkle_path, value) in &self.leaves {
+            bytes.extend_from_slice(&to_bytes32(merkle_path));
+            bytes.extend_from_slice(&to_bytes32(value));
+        }
+
+        bytes
+    }
+
+    /// Parse the bytes of `to_bytes`, rejecting malformed ones with
+    /// `PoseidonMerkleError::MalformedDelta`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PoseidonMerkleError> {
+        let malformed = PoseidonMerkleError::MalformedDelta;
+        if bytes.len() < HEADER_BYTES {
+            return Err(malformed("truncated header"));
+        }
+        if &bytes[..4] != MAGIC {
+            return Err(malformed("not a delta"));
+        }
+        if bytes[4] != DELTA_VERSION {
+            return Err(malformed("unknown version"));
+        }
+        if !(bytes.len() - HEADER_BYTES).is_multiple_of(64) {
+            return Err(malformed("truncated leaves"));
+        }
+
+        let mut elements = bytes[5..].chunks_exact(32).map(|word| {
+            from_bytes32(word.try_into().expect("32 bytes"))
+                .map_err(|_| malformed("field element not below the modulus"))
+        });
+        let base_root = elements.next().expect("header")?;
+        let target_root = elements.next().expect("header")?;
+        let mut leaves = Vec::with_capacity((bytes.len() - HEADER_BYTES) / 64);
+        while let (Some(merkle_path), Some(value)) = (elements.next(), elements.next()) {
+            leaves.push((merkle_path?, value?));
+        }
+
+        Ok(TreeDelta {
+            base_root,
+            target_root,
+            leaves,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TreeDelta {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TreeDelta {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(crate::snapshot::BytesVisitor {
+            expecting: "the bytes of a tree delta",
+            parse: TreeDelta::from_bytes,
+        })
+    }
+}
@@ -0,0 +1,257 @@
+//! Encodings of field elements in JSON
+//!
+//! Decimal strings are the default, the way snarkjs and circom read field elements. Ethereum
+//! tooling wants `0x`-prefixed big-endian hex, arkworks serializes little-endian bytes and some
+//! consumers take raw byte arrays, so JSON writers take a `FrEncoding`. `FrEncoding::parse` reads
+//! any encoding that can't be mistaken for another: decimal strings, `0x` hex as big-endian, byte
+//! arrays and JSON integers. Little-endian hex reads like big-endian hex, so it is only read by
+//! `FrEncoding::HexLe.decode`.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, str::FromStr};
+
+use ark_ff::{BigInteger, PrimeField};
+use serde_json::{json, Value};
+
+use crate::{MerkleProof, PoseidonMerkleError};
+
+/// How a field element is written in JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FrEncoding {
+    /// A decimal string, the default for circom compatibility
+    #[default]
+    Decimal,
+    /// `0x` and big-endian hex digits, two per byte of the field, like Solidity's `bytes32`
+    HexBe,
+    /// `0x` and little-endian hex digits, the byte order of arkworks serialization
+    HexLe,
+    /// An array of big-endian bytes, one per byte of the field
+    Bytes,
+}
+
+impl fmt::Display for FrEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrEncoding::Decimal => write!(f, "decimal"),
+            FrEncoding::HexBe => write!(f, "hex-be"),
+            FrEncoding::HexLe => write!(f, "hex-le"),
+            FrEncoding::Bytes => write!(f, "bytes"),
+        }
+    }
+}
+
+impl FromStr for FrEncoding {
+    type Err = String;
+
+    /// The name printed by `Display`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "decimal" => Ok(FrEncoding::Decimal),
+            "hex-be" => Ok(FrEncoding::HexBe),
+            "hex-le" => Ok(FrEncoding::HexLe),
+            "bytes" => Ok(FrEncoding::Bytes),
+            _ => Err(format!(
+                "unknown encoding {name:?}, expected decimal, hex-be, hex-le or bytes"
+            )),
+        }
+    }
+}
+
+/// Bytes of a field element, 32 for bn254
+fn byte_len<F: PrimeField>() -> usize {
+    <F::BigInt as BigInteger>::NUM_LIMBS * 8
+}
+
+fn invalid<F: PrimeField>(value: &Value, reason: &'static str) -> PoseidonMerkleError<F> {
+    PoseidonMerkleError::InvalidFieldElement {
+        input: value.to_string(),
+        reason,
+    }
+}
+
+/// The field element of big-endian bytes, at most `byte_len` of them
+fn from_bytes_be<F: PrimeField>(bytes: &[u8]) -> Option<F> {
+    let bits: Vec<bool> = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 1 == 1))
+        .collect();
+    F::from_bigint(F::BigInt::from_bits_be(&bits))
+}
+
+impl FrEncoding {
+    /// Write a field element in this encoding
+    pub fn encode<F: PrimeField>(self, value: &F) -> Value {
+        let mut bytes = value.into_bigint().to_bytes_be();
+        match self {
+            FrEncoding::Decimal => Value::String(value.into_bigint().to_string()),
+            FrEncoding::HexBe | FrEncoding::HexLe => {
+                if self == FrEncoding::HexLe {
+                    bytes.reverse();
+                }
+                let mut hex = String::with_capacity(2 + 2 * bytes.len());
+                hex.push_str("0x");
+                for byte in bytes {
+                    hex.push_str(&format!("{byte:02x}"));
+                }
+                Value::String(hex)
+            }
+            FrEncoding::Bytes => json!(bytes),
+        }
+    }
+
+    /// Read a field element written in this encoding, which must be below the modulus
+    ///
+    /// Hex may have fewer digits than the field has, big-endian hex is then padded on the left
+    /// and little-endian hex, which must have whole bytes, on the right. Byte arrays must have
+    /// exactly one entry per byte.
+    pub fn decode<F: PrimeField>(self, value: &Value) -> Result<F, PoseidonMerkleError<F>> {
+        let len = byte_len::<F>();
+        let below_modulus =
+            |element: Option<F>| element.ok_or_else(|| invalid(value, "not below the modulus"));
+
+        match self {
+            FrEncoding::Decimal => {
+                let text = value
+                    .as_str()
+                    .ok_or_else(|| invalid(value, "not a string"))?;
+                if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return Err(invalid(value, "not a decimal number"));
+                }
+
+                // Accumulate big-endian bytes, with one more to catch numbers past the width
+                let mut bytes = vec![0u8; len + 1];
+                for digit in text.bytes() {
+                    let mut carry = u16::from(digit - b'0');
+                    for byte in bytes.iter_mut().rev() {
+                        let product = u16::from(*byte) * 10 + carry;
+                        *byte = product as u8;
+                        carry = product >> 8;
+                    }
+                    if carry != 0 || bytes[0] != 0 {
+                        return Err(invalid(value, "not below the modulus"));
+                    }
+                }
+                below_modulus(from_bytes_be(&bytes[1..]))
+            }
+            FrEncoding::HexBe | FrEncoding::HexLe => {
+                let text = value
+                    .as_str()
+                    .ok_or_else(|| invalid(value, "not a string"))?;
+                let digits = text
+                    .strip_prefix("0x")
+                    .ok_or_else(|| invalid(value, "no 0x prefix"))?;
+                if digits.is_empty() || digits.len() > 2 * len {
+                    return Err(invalid(
+                        value,
+                        "not 1 to 2 hex digits per byte of the field",
+                    ));
+                }
+                if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+                    return Err(invalid(value, "not a hex digit"));
+                }
+
+                let mut bytes = match self {
+                    FrEncoding::HexBe => format!("{digits:0>width$}", width = 2 * len),
+                    _ if digits.len() % 2 == 1 => {
+                        return Err(invalid(value, "odd number of little-endian hex digits"))
+                    }
+                    _ => format!("{digits:0<width$}", width = 2 * len),
+                }
+                .as_bytes()
+                .chunks(2)
+                .map(|pair| {
+                    let pair = core::str::from_utf8(pair).expect("ASCII hex digits");
+                    u8::from_str_radix(pair, 16).expect("hex digits")
+                })
+                .collect::<Vec<_>>();
+                if self == FrEncoding::HexLe {
+                    bytes.reverse();
+                }
+                below_modulus(from_bytes_be(&bytes))
+            }
+            FrEncoding::Bytes => {
+                let array = value
+                    .as_array()
+                    .ok_or_else(|| invalid(value, "not an array"))?;
+                if array.len() != len {
+                    return Err(invalid(value, "not one byte per byte of the field"));
+                }
+                let bytes = array
+                    .iter()
+                    .map(|byte| {
+                        byte.as_u64()
+                            .and_then(|byte| u8::try_from(byte).ok())
+                            .ok_or_else(|| invalid(value, "not an array of bytes"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                below_modulus(from_bytes_be(&bytes))
+            }
+        }
+    }
+
+    /// Read a field element in any encoding but little-endian hex, or a JSON integer
+    ///
+    /// `0x`-prefixed strings are big-endian hex, other strings decimal and arrays bytes.
+    pub fn parse<F: PrimeField>(value: &Value) -> Result<F, PoseidonMerkleError<F>> {
+        match value {
+            Value::Number(number) => number
+                .as_u64()
+                .map(F::from)
+                .ok_or_else(|| invalid(value, "not an unsigned integer")),
+            Value::String(text) if text.starts_with("0x") => FrEncoding::HexBe.decode(value),
+            Value::String(_) => FrEncoding::Decimal.decode(value),
+            Value::Array(_) => FrEncoding::Bytes.decode(value),
+            _ => Err(invalid(value, "not a string, number or array")),
+        }
+    }
+}
+
+impl<F: PrimeField> MerkleProof<F> {
+    /// The proof as the JSON object `{ leaf, root, path, siblings }` in the given encoding
+    ///
+    /// Only the raw parts are written, the conventions of the tree, `LeafHashing`,
+    /// `HashingScheme`, arity and bit order, are left to the reader.
+    pub fn to_json(&self, encoding: FrEncoding) -> Value {
+        json!({
+            "leaf": encoding.encode(&self.leaf_value),
+            "root": encoding.encode(&self.root_hash),
+            "path": encoding.encode(&self.merkle_path),
+            "siblings": self
+                .siblings
+                .iter()
+                .map(|sibling| encoding.encode(sibling))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// The proof of a `to_json` object, in any encoding `FrEncoding::parse` reads
+    ///
+    /// The proof has the default conventions and is checked like `MerkleProof::try_new`.
+    pub fn from_json(json: &Value) -> Result<Self, PoseidonMerkleError<F>> {
+        let item = |name: &str| {
+            json.get(name).ok_or_else(|| {
+                PoseidonMerkleError::InvalidProofJson(format!("missing key {name:?}"))
+            })
+        };
+        let siblings = item("siblings")?
+            .as_array()
+            .ok_or_else(|| {
+                PoseidonMerkleError::InvalidProofJson("siblings is not an array".into())
+            })?
+            .iter()
+            .map(FrEncoding::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(MerkleProof::try_new(
+            siblings,
+            FrEncoding::parse(item("path")?)?,
+            FrEncoding::parse(item("leaf")?)?,
+            FrEncoding::parse(item("root")?)?,
+        )?)
+    }
+}
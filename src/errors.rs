@@ -1,28 +1,226 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::fmt;
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
 use light_poseidon::PoseidonError;
 use thiserror::Error;
 
+use crate::{
+    Arity, BitOrder, CircuitTemplate, HashFamily, HasherInfo, HashingScheme, InnerHash, MerklePath,
+};
+
 #[derive(Error, Debug, PartialEq)]
-pub enum PoseidonMerkleError {
+pub enum PoseidonMerkleError<F: PrimeField = Fr> {
     #[error("depth size should be greater than 0")]
     InvalidDepth,
-    #[error("poseidon hasher error: {0}")]
-    HasherError(#[from] PoseidonError),
-    #[error("invalid node type")]
-    InvalidNodeType,
-    #[error("invalid bits for path hash")]
-    InvalidBitsPathHash,
+    #[error("poseidon hasher error while {operation}: {source}")]
+    HasherError {
+        operation: HashOperation,
+        source: PoseidonError,
+    },
+    #[error("invalid node type at level {level}{}", fmt_path(.path))]
+    InvalidNodeType {
+        level: usize,
+        path: Option<MerklePath<F>>,
+    },
+    #[error("no leaf on path {path}")]
+    LeafNotFound { path: MerklePath<F> },
+    #[error("no leaf on path {path}, the level {level} node has no child on it")]
+    LeafNotReached { path: MerklePath<F>, level: usize },
+    #[error("leaf on path {path} holds a value")]
+    LeafNotEmpty { path: MerklePath<F> },
+    #[error("invalid bits for path hash: got {len} bits, at most {max} are allowed")]
+    InvalidBitsPathHash { len: usize, max: usize },
     #[error("proof error: {0}")]
-    SiblingNotFound(#[from] ProofError),
+    ProofError(#[from] ProofError),
     #[error("invalid level")]
     InvalidLevel,
+    #[error("pre-computed {0} does not match the hasher output")]
+    ConstantMismatch(&'static str),
+    #[error("the hasher must hash exactly two inputs: {source}")]
+    InvalidHasherWidth { source: PoseidonError },
+    #[error("pre-hashed leaves need the circom one-input parameters, only available for bn254")]
+    UnsupportedLeafHashing,
+    #[error("hasher parameters of width {width} don't give 2, 4 or 8 children")]
+    UnsupportedWidth { width: usize },
+    #[error("leaves can hold 1 to {max} values, not {width}")]
+    UnsupportedLeafWidth { width: usize, max: usize },
+    #[error("got {actual} values for a leaf of a tree holding {expected} per leaf")]
+    LeafWidthMismatch { expected: usize, actual: usize },
+    #[error("key {key} is already in the tree")]
+    KeyExists { key: F },
+    #[error("key shares all of the {levels} levels of the tree with another key")]
+    LevelsExceeded { levels: usize },
+    #[error("leaf index {index} is out of range for a tree of {size} leaves")]
+    IndexOutOfRange { index: usize, size: usize },
+    #[error("value is not below the modulus")]
+    NonCanonicalValue,
+    #[error("value {value} doesn't fit in {bits} bits")]
+    ValueOutOfRange { value: F, bits: u32 },
+    #[error("the tree holds {current} nodes and needs {requested} more, over its budget of {max}")]
+    NodeBudgetExceeded {
+        current: usize,
+        requested: usize,
+        max: usize,
+    },
+    #[error("every leaf of the tree is taken")]
+    TreeFull,
+    #[error("the tree uses {actual}, {expected} are needed")]
+    BitOrderMismatch {
+        expected: BitOrder,
+        actual: BitOrder,
+    },
+    #[error("only binary Poseidon proofs can be packed or verified in a circuit or on-chain")]
+    UnsupportedCircuit,
+    #[error("the entries hash to the root {actual}, not {expected}")]
+    RootMismatch { expected: F, actual: F },
+    #[error("malformed tree snapshot: {0}")]
+    MalformedSnapshot(&'static str),
+    #[error("malformed tree delta: {0}")]
+    MalformedDelta(&'static str),
+    #[cfg(feature = "json")]
+    #[error("invalid zk-kit SMT JSON: {0}")]
+    InvalidZkKitJson(String),
+    #[cfg(feature = "json")]
+    #[error("invalid field element {input}: {reason}")]
+    InvalidFieldElement { input: String, reason: &'static str },
+    #[cfg(feature = "json")]
+    #[error("invalid proof: {0}")]
+    InvalidProofJson(String),
+    #[error("expected the hasher {expected}, got {actual}")]
+    HasherMismatch {
+        expected: Box<HasherInfo<F>>,
+        actual: Box<HasherInfo<F>>,
+    },
+    #[error(
+        "a node of the tree is already borrowed, a node reference is held across the operation"
+    )]
+    ConcurrentAccess,
+    #[error("{} cached hashes don't match their subtrees, first {}", .0.len(), .0[0])]
+    IntegrityViolations(Vec<IntegrityViolation<F>>),
+}
+
+impl<F: PrimeField> PoseidonMerkleError<F> {
+    /// Wrap a hasher error with the operation that was being performed
+    pub(crate) fn hasher(operation: HashOperation) -> impl FnOnce(PoseidonError) -> Self {
+        move |source| PoseidonMerkleError::HasherError { operation, source }
+    }
+}
+
+/// The hashing step during which a hasher error occurred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashOperation {
+    /// Creating the hasher
+    HasherSetup,
+    /// Recomputing the pre-computed constants
+    ConstantValidation,
+    /// Hashing a leaf value on its own
+    LeafHash,
+    /// Hashing the children of an inner node, the level is unknown for detached nodes
+    InnerNode { level: Option<usize> },
+    /// Folding a proof sibling at the given level
+    ProofVerification { level: usize },
+    /// Hashing a byte key into a path
+    KeyHash,
+}
+
+impl fmt::Display for HashOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashOperation::HasherSetup => write!(f, "creating the hasher"),
+            HashOperation::ConstantValidation => write!(f, "validating the constants"),
+            HashOperation::LeafHash => write!(f, "hashing a leaf value"),
+            HashOperation::InnerNode { level: Some(level) } => {
+                write!(f, "hashing an inner node at level {level}")
+            }
+            HashOperation::InnerNode { level: None } => write!(f, "hashing an inner node"),
+            HashOperation::ProofVerification { level } => {
+                write!(f, "verifying the proof at level {level}")
+            }
+            HashOperation::KeyHash => write!(f, "hashing a key"),
+        }
+    }
+}
+
+fn fmt_path<F: PrimeField>(path: &Option<MerklePath<F>>) -> String {
+    path.map(|path| format!(" on path {path}"))
+        .unwrap_or_default()
 }
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ProofError {
-    #[error("sibling at position {0} not found to generate proof")]
-    SiblingNotFound(usize),
-    #[error("encountered a leaf node where an inner node was expected")]
-    InnerNodeExpected,
+    #[error("sibling at level {level} not found to generate proof")]
+    SiblingNotFound { level: usize },
+    #[error("encountered a leaf node at level {level} where an inner node was expected")]
+    InnerNodeExpected { level: usize },
     #[error("empty node hash")]
     EmptyNodeHash,
+    #[error("proof covers {actual} levels but the tree has depth {expected}")]
+    DepthMismatch { expected: usize, actual: usize },
+    #[error("proof has no siblings")]
+    NoSiblings,
+    #[error("proof has {len} siblings but a path can address at most {max} levels")]
+    TooManySiblings { len: usize, max: usize },
+    #[error("proof path has bits set at or above level {depth}")]
+    NonCanonicalPath { depth: usize },
+    #[error("proof was built with {expected} but is verified with {actual}")]
+    HashFamilyMismatch {
+        expected: HashFamily,
+        actual: HashFamily,
+    },
+    #[error("proof comes from a {expected} tree but is verified against a {actual} tree")]
+    ArityMismatch { expected: Arity, actual: Arity },
+    #[error("proof has {len} siblings, not a multiple of the {per_level} siblings per level")]
+    UnevenSiblings { len: usize, per_level: usize },
+    #[error("proof was built with {expected} but is verified against a tree using {actual}")]
+    SchemeMismatch {
+        expected: HashingScheme,
+        actual: HashingScheme,
+    },
+    #[error("proof was built with other hasher parameters than the ones verifying it")]
+    ParamsFingerprintMismatch,
+    #[error("proof path uses {expected} but is verified against a tree using {actual}")]
+    BitOrderMismatch {
+        expected: BitOrder,
+        actual: BitOrder,
+    },
+    #[error("proof node at level {level} is a left sibling after a right one")]
+    InterleavedNodes { level: usize },
+    #[error("proof has different values for the same leaf")]
+    ConflictingLeaves,
+    #[error("proof claims an empty subtree at level {level} below its {depth} levels")]
+    EmptyLevelOutOfRange { level: usize, depth: usize },
+    #[error("field element is not below the modulus")]
+    NonCanonicalFieldElement,
+    #[error("malformed compact proof: {0}")]
+    MalformedCompactProof(&'static str),
+    #[error("{template} can't verify this proof")]
+    UnsupportedTemplate { template: CircuitTemplate },
+    #[error("{template} circuit with {circuit_depth} levels can't take a proof of depth {depth}")]
+    CircuitDepthMismatch {
+        template: CircuitTemplate,
+        circuit_depth: usize,
+        depth: usize,
+    },
+}
+
+/// A cached inner hash that doesn't match the hash recomputed from the subtree below it
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("cached hash {cached} at level {level} (path {}) should be {recomputed}", fmt_bits(.path))]
+pub struct IntegrityViolation<F: PrimeField = Fr> {
+    /// Level of the node, the root is level 0
+    pub level: usize,
+    /// Directions taken from the root to reach the node, `true` means right
+    pub path: Vec<bool>,
+    /// The hash stored in the node
+    pub cached: InnerHash<F>,
+    /// The hash recomputed from the subtree
+    pub recomputed: InnerHash<F>,
+}
+
+fn fmt_bits(bits: &[bool]) -> String {
+    bits.iter()
+        .map(|bit| if *bit { '1' } else { '0' })
+        .collect()
 }
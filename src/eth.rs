@@ -0,0 +1,103 @@
+//! Roots and proofs as the big-endian `bytes32` words of Solidity calldata
+//!
+//! A field element is the `uint256` of its canonical value, big-endian. Parsing rejects words not
+//! below the modulus with `ProofError::NonCanonicalFieldElement`, instead of reducing them.
+
+use alloc::vec::Vec;
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+
+use crate::{Arity, BitOrder, MerkleProof, ProofError};
+
+/// A field element as a `bytes32` word
+pub fn to_bytes32(value: &Fr) -> [u8; 32] {
+    let mut word = [0; 32];
+    word.copy_from_slice(&value.into_bigint().to_bytes_be());
+    word
+}
+
+/// The field element of a `bytes32` word, which must be below the modulus
+pub fn from_bytes32(word: &[u8; 32]) -> Result<Fr, ProofError> {
+    let value = Fr::from_be_bytes_mod_order(word);
+    if to_bytes32(&value) != *word {
+        return Err(ProofError::NonCanonicalFieldElement);
+    }
+
+    Ok(value)
+}
+
+/// A root as a `bytes32` word
+pub fn root_to_bytes32(root: &Fr) -> [u8; 32] {
+    to_bytes32(root)
+}
+
+/// The root of a `bytes32` word, which must be below the modulus
+pub fn root_from_bytes32(word: &[u8; 32]) -> Result<Fr, ProofError> {
+    from_bytes32(word)
+}
+
+/// The proof as calldata words: the leaf value, the leaf index, then the siblings from the leaf up
+///
+/// The index is the position of the leaf from the left, whatever the bit order of the tree: the
+/// lowest `arity.bits()` bits pick the child at the leaf level, the next ones at the level above
+/// and so on. Each level has its `arity - 1` siblings in child index order, the leaf level first.
+/// The leaf value is the one inserted, a verifier hashes it like the tree does. The root is left
+/// out, a contract compares with the one it stores.
+pub fn proof_to_calldata(proof: &MerkleProof) -> Vec<[u8; 32]> {
+    let depth = proof.depth();
+    let bits = proof.arity.bits();
+    let mut index = Vec::with_capacity(depth * bits);
+    for level in (0..depth).rev() {
+        let position = proof.position(level);
+        index.extend((0..bits).map(|bit| position >> bit & 1 == 1));
+    }
+
+    let mut words = Vec::with_capacity(2 + proof.siblings.len());
+    words.push(to_bytes32(&proof.leaf_value));
+    words.push(to_bytes32(
+        &Fr::from_bigint(BigInteger::from_bits_le(&index)).expect("a path fits in the field"),
+    ));
+    for level in (0..depth).rev() {
+        words.extend(proof.level_siblings(level).iter().map(to_bytes32));
+    }
+
+    words
+}
+
+/// The proof of `proof_to_calldata` words, whose tree has `arity` and root `root`
+///
+/// The proof reads its path in `BitOrder::MsbFirst`, where the path is the leaf index, and is
+/// checked like `MerkleProof::try_new` does. The leaf hashing and scheme are not part of the
+/// calldata, set them on the proof if the tree doesn't use the defaults.
+pub fn proof_from_calldata(
+    words: &[[u8; 32]],
+    root: Fr,
+    arity: Arity,
+) -> Result<MerkleProof, ProofError> {
+    let [leaf, index, siblings @ ..] = words else {
+        return Err(ProofError::NoSiblings);
+    };
+    let per_level = arity.children() - 1;
+    if siblings.is_empty() {
+        return Err(ProofError::NoSiblings);
+    }
+    if !siblings.len().is_multiple_of(per_level) {
+        return Err(ProofError::UnevenSiblings {
+            len: siblings.len(),
+            per_level,
+        });
+    }
+
+    // Back to the levels from the root down
+    let mut root_first = Vec::with_capacity(siblings.len());
+    for level in siblings.chunks_exact(per_level).rev() {
+        for sibling in level {
+            root_first.push(from_bytes32(sibling)?);
+        }
+    }
+
+    MerkleProof::new(root_first, from_bytes32(index)?, from_bytes32(leaf)?, root)
+        .with_bit_order(BitOrder::MsbFirst)
+        .try_with_arity(arity)
+}
@@ -0,0 +1,117 @@
+//! C interface for verifying proofs of binary circom Poseidon trees
+//!
+//! Field elements are passed as 32 bytes, big-endian, and must be below the modulus. The build
+//! script generates the header from this module into `OUT_DIR`, `include/merkle_poseidon.h` is
+//! the committed copy.
+
+use core::slice;
+use std::panic;
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+
+use crate::MerkleProof;
+
+/// Bytes of a field element
+pub const MERKLE_POSEIDON_ELEMENT_BYTES: usize = 32;
+
+/// Most siblings a proof can have, one per bit of a path
+pub const MERKLE_POSEIDON_MAX_SIBLINGS: usize = 254;
+
+/// The proof folds into the root
+pub const MERKLE_POSEIDON_OK: i32 = 0;
+/// The proof is well-formed but doesn't fold into the root
+pub const MERKLE_POSEIDON_ERR_MISMATCH: i32 = -1;
+/// A pointer is null
+pub const MERKLE_POSEIDON_ERR_NULL_POINTER: i32 = -2;
+/// There are no siblings, or more than `MERKLE_POSEIDON_MAX_SIBLINGS`
+pub const MERKLE_POSEIDON_ERR_LENGTH: i32 = -3;
+/// A field element is not below the modulus
+pub const MERKLE_POSEIDON_ERR_FIELD_ELEMENT: i32 = -4;
+/// The path has bits set past the proof depth or the leaf is the empty node hash
+pub const MERKLE_POSEIDON_ERR_INVALID_PROOF: i32 = -5;
+/// The hasher failed
+pub const MERKLE_POSEIDON_ERR_HASH: i32 = -6;
+/// Verification panicked, this is a bug
+pub const MERKLE_POSEIDON_ERR_PANIC: i32 = -7;
+
+/// Verify the proof that `leaf` sits at `path` below `root`
+///
+/// `siblings` points to `siblings_len` field elements, one per level from the root down, like
+/// `MerkleProof::siblings`. Returns `MERKLE_POSEIDON_OK` when the proof verifies and one of the
+/// negative `MERKLE_POSEIDON_ERR_*` codes otherwise.
+///
+/// # Safety
+///
+/// `root`, `leaf` and `path` must be null or point to `MERKLE_POSEIDON_ELEMENT_BYTES` readable
+/// bytes, and `siblings` must be null or point to `siblings_len * MERKLE_POSEIDON_ELEMENT_BYTES`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn merkle_poseidon_verify(
+    root: *const u8,
+    leaf: *const u8,
+    path: *const u8,
+    siblings: *const u8,
+    siblings_len: usize,
+) -> i32 {
+    let verified =
+        panic::catch_unwind(|| unsafe { verify(root, leaf, path, siblings, siblings_len) });
+    match verified {
+        Ok(Ok(())) => MERKLE_POSEIDON_OK,
+        Ok(Err(code)) => code,
+        Err(_) => MERKLE_POSEIDON_ERR_PANIC,
+    }
+}
+
+/// `merkle_poseidon_verify` with the error code as error
+///
+/// # Safety
+///
+/// See `merkle_poseidon_verify`.
+unsafe fn verify(
+    root: *const u8,
+    leaf: *const u8,
+    path: *const u8,
+    siblings: *const u8,
+    siblings_len: usize,
+) -> Result<(), i32> {
+    if [root, leaf, path, siblings]
+        .iter()
+        .any(|pointer| pointer.is_null())
+    {
+        return Err(MERKLE_POSEIDON_ERR_NULL_POINTER);
+    }
+    // Checked before the length in bytes is computed, so it can't overflow
+    if siblings_len == 0 || siblings_len > MERKLE_POSEIDON_MAX_SIBLINGS {
+        return Err(MERKLE_POSEIDON_ERR_LENGTH);
+    }
+
+    let element = |pointer| {
+        field_element(unsafe { slice::from_raw_parts(pointer, MERKLE_POSEIDON_ELEMENT_BYTES) })
+    };
+    let siblings =
+        unsafe { slice::from_raw_parts(siblings, siblings_len * MERKLE_POSEIDON_ELEMENT_BYTES) }
+            .chunks_exact(MERKLE_POSEIDON_ELEMENT_BYTES)
+            .map(field_element)
+            .collect::<Result<Vec<_>, _>>()?;
+    let proof = MerkleProof::try_new(siblings, element(path)?, element(leaf)?, element(root)?)
+        .map_err(|_| MERKLE_POSEIDON_ERR_INVALID_PROOF)?;
+
+    let mut hasher = Poseidon::<Fr>::new_circom(2).map_err(|_| MERKLE_POSEIDON_ERR_HASH)?;
+    match proof.verify_proof(&mut hasher) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(MERKLE_POSEIDON_ERR_MISMATCH),
+        Err(_) => Err(MERKLE_POSEIDON_ERR_HASH),
+    }
+}
+
+/// The field element of 32 big-endian bytes, rejecting values not below the modulus
+fn field_element(bytes: &[u8]) -> Result<Fr, i32> {
+    let value = Fr::from_be_bytes_mod_order(bytes);
+    if value.into_bigint().to_bytes_be() != bytes {
+        return Err(MERKLE_POSEIDON_ERR_FIELD_ELEMENT);
+    }
+
+    Ok(value)
+}
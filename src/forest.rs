@@ -0,0 +1,205 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use thiserror::Error;
+
+use crate::{
+    verify::hash_key, Arity, BitOrder, HashOperation, InnerHash, MerkleHasher, MerklePath,
+    MerkleProof, PoseidonMerkleError, SparseMerkleTree,
+};
+
+/// Why a `Forest` operation failed
+#[derive(Error, Debug, PartialEq)]
+pub enum ForestError {
+    #[error("no tree is named {name:?}")]
+    UnknownTree { name: String },
+    #[error(transparent)]
+    Tree(#[from] PoseidonMerkleError),
+}
+
+/// A tree of a forest with the hash of its name
+struct NamedTree {
+    name_hash: Fr,
+    tree: SparseMerkleTree<Poseidon<Fr>>,
+}
+
+/// Named circom Poseidon trees, of any depth, under a single commitment
+///
+/// The forest root is the root of a binary circom Poseidon tree over the trees, one leaf per tree
+/// in the order of their name hash. The leaf of a tree is hash(name hash, root), the name hash
+/// being `verify::hash_key` of the UTF-8 name, and the forest tree is as deep as needed for the
+/// number of trees, one level at least. The root only depends on the names and roots of the trees,
+/// not on the order they were added in.
+#[derive(Default)]
+pub struct Forest {
+    trees: BTreeMap<String, NamedTree>,
+}
+
+impl Forest {
+    pub fn new() -> Self {
+        Forest::default()
+    }
+
+    /// Add a tree under a name, returning the tree it replaces
+    pub fn insert_tree(
+        &mut self,
+        name: &str,
+        tree: SparseMerkleTree<Poseidon<Fr>>,
+    ) -> Result<Option<SparseMerkleTree<Poseidon<Fr>>>, ForestError> {
+        let name_hash = hash_key(&mut hasher()?, name.as_bytes(), Arity::Binary)?;
+        let previous = self
+            .trees
+            .insert(name.to_string(), NamedTree { name_hash, tree });
+
+        Ok(previous.map(|previous| previous.tree))
+    }
+
+    pub fn remove_tree(&mut self, name: &str) -> Option<SparseMerkleTree<Poseidon<Fr>>> {
+        self.trees.remove(name).map(|named| named.tree)
+    }
+
+    pub fn tree(&self, name: &str) -> Option<&SparseMerkleTree<Poseidon<Fr>>> {
+        self.trees.get(name).map(|named| &named.tree)
+    }
+
+    /// The tree of a name, to update it, the forest root follows its root
+    pub fn tree_mut(&mut self, name: &str) -> Option<&mut SparseMerkleTree<Poseidon<Fr>>> {
+        self.trees.get_mut(name).map(|named| &mut named.tree)
+    }
+
+    /// The names of the trees, in lexicographic order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.trees.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.trees.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trees.is_empty()
+    }
+
+    /// The commitment to every tree, refreshing their roots first
+    pub fn forest_root(&self) -> Result<InnerHash, ForestError> {
+        Ok(self.forest_tree()?.root()?)
+    }
+
+    /// Prove the value at a path of a named tree against the forest root
+    pub fn generate_forest_proof(
+        &self,
+        name: &str,
+        leaf_path: &MerklePath,
+    ) -> Result<ForestProof, ForestError> {
+        let named = self
+            .trees
+            .get(name)
+            .ok_or_else(|| ForestError::UnknownTree {
+                name: name.to_string(),
+            })?;
+        let tree_proof = named.tree.generate_proof(leaf_path)?;
+        let position = self
+            .sorted()?
+            .iter()
+            .position(|(name_hash, _)| *name_hash == named.name_hash)
+            .expect("the tree is in the forest");
+        let forest_proof = self
+            .forest_tree()?
+            .generate_proof(&Fr::from(position as u64))?;
+
+        Ok(ForestProof {
+            name: name.to_string(),
+            tree_proof,
+            forest_proof,
+        })
+    }
+
+    /// The name hash and root of every tree, ordered by name hash
+    fn sorted(&self) -> Result<Vec<(Fr, InnerHash)>, ForestError> {
+        let mut pairs = self
+            .trees
+            .values()
+            .map(|named| Ok((named.name_hash, named.tree.root()?)))
+            .collect::<Result<Vec<_>, ForestError>>()?;
+        pairs.sort();
+
+        Ok(pairs)
+    }
+
+    /// The tree whose leaves commit to the trees, the leaf of a tree at its position in `sorted`
+    fn forest_tree(&self) -> Result<SparseMerkleTree<Poseidon<Fr>>, ForestError> {
+        let pairs = self.sorted()?;
+        let depth = pairs.len().next_power_of_two().trailing_zeros().max(1) as usize;
+        let mut hasher = hasher()?;
+        let entries = pairs
+            .iter()
+            .enumerate()
+            .map(|(position, (name_hash, root))| {
+                Ok((
+                    Fr::from(position as u64),
+                    tree_leaf(&mut hasher, name_hash, root)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, ForestError>>()?;
+
+        let mut forest = SparseMerkleTree::new(depth)?.with_bit_order(BitOrder::MsbFirst);
+        forest.insert_batch(&entries)?;
+
+        Ok(forest)
+    }
+}
+
+/// A proof of a value of a named tree against a forest root, see `Forest::generate_forest_proof`
+#[derive(Debug, Clone)]
+pub struct ForestProof {
+    /// The name of the tree
+    pub name: String,
+    /// The value against the root of the tree
+    pub tree_proof: MerkleProof,
+    /// The leaf of the tree, its name hash and root, against the forest root
+    pub forest_proof: MerkleProof,
+}
+
+impl ForestProof {
+    /// Verify the value against a trusted forest root
+    ///
+    /// The tree proof must verify, its root and the name must hash to the leaf of the forest
+    /// proof, and the forest proof must verify against `forest_root`.
+    pub fn verify(&self, forest_root: &InnerHash) -> Result<bool, PoseidonMerkleError> {
+        let mut hasher = hasher()?;
+        let name_hash = hash_key(&mut hasher, self.name.as_bytes(), Arity::Binary)?;
+        let leaf = tree_leaf(&mut hasher, &name_hash, &self.tree_proof.root_hash)?;
+        if self.forest_proof.leaf_value != leaf {
+            return Ok(false);
+        }
+        if !self.forest_proof.verify_against(&mut hasher, forest_root)? {
+            return Ok(false);
+        }
+
+        let mut tree_hasher = Poseidon::<Fr>::new_circom(self.tree_proof.arity.children())
+            .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+        self.tree_proof.verify_proof(&mut tree_hasher)
+    }
+}
+
+fn hasher() -> Result<Poseidon<Fr>, PoseidonMerkleError> {
+    Poseidon::<Fr>::new_circom(2).map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))
+}
+
+/// The leaf of a tree in the forest tree
+fn tree_leaf(
+    hasher: &mut Poseidon<Fr>,
+    name_hash: &Fr,
+    root: &InnerHash,
+) -> Result<Fr, PoseidonMerkleError> {
+    hasher
+        .hash_children(&[*name_hash, *root])
+        .map_err(PoseidonMerkleError::hasher(HashOperation::InnerNode {
+            level: None,
+        }))
+}
@@ -0,0 +1,306 @@
+#[cfg(feature = "sync")]
+use alloc::collections::BTreeSet;
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+
+use crate::{
+    path::path_index, shared::CheckedBorrow, BitOrder, HashConfig, InnerHash, MerkleHasher,
+    MerklePath, MerkleProof, Node, NodeRef, NodeType, PoseidonMerkleError, SparseMerkleTree,
+};
+
+/// A node of a `FrozenView`, holding the hash it contributes to its parent
+#[derive(Debug)]
+pub(crate) enum FrozenNode<F: PrimeField> {
+    Leaf {
+        value: F,
+        /// The value as hashed into the parent, see `LeafHashing`
+        hash: F,
+    },
+    Inner {
+        hash: InnerHash<F>,
+        children: Box<[Option<Arc<FrozenNode<F>>>]>,
+    },
+}
+
+impl<F: PrimeField> FrozenNode<F> {
+    fn hash(&self) -> F {
+        match self {
+            FrozenNode::Leaf { hash, .. } | FrozenNode::Inner { hash, .. } => *hash,
+        }
+    }
+
+    /// Whether the node holds what `node` of a tree does, leaves by value and inner nodes by hash
+    fn matches<H: MerkleHasher<F>, M>(&self, node: &Node<H, F, M>) -> bool {
+        match (self, &node.node_type) {
+            (FrozenNode::Leaf { value, .. }, NodeType::Leaf(tree_value)) => value == tree_value,
+            (FrozenNode::Inner { hash, .. }, NodeType::Inner(tree_hash)) => hash == tree_hash,
+            _ => false,
+        }
+    }
+}
+
+/// The number of distinct nodes the views hold, the nodes they share counted once
+#[cfg(feature = "sync")]
+pub(crate) fn count_nodes<'a, F: PrimeField + 'a>(
+    roots: impl IntoIterator<Item = &'a Arc<FrozenNode<F>>>,
+) -> usize {
+    let mut seen = BTreeSet::new();
+    let mut stack: Vec<_> = roots.into_iter().collect();
+    while let Some(node) = stack.pop() {
+        if !seen.insert(Arc::as_ptr(node)) {
+            continue;
+        }
+        if let FrozenNode::Inner { children, .. } = &**node {
+            stack.extend(children.iter().flatten());
+        }
+    }
+    seen.len()
+}
+
+/// An immutable copy of a tree, see `SparseMerkleTree::freeze`
+///
+/// It holds every hash a proof needs, so reading it neither hashes nor locks anything, and it is
+/// `Send` and `Sync` whatever the features. Cloning it is cheap, clones share the nodes.
+#[derive(Debug, Clone)]
+pub struct FrozenView<F: PrimeField = Fr> {
+    root: Arc<FrozenNode<F>>,
+    depth: usize,
+    config: HashConfig<F>,
+    params_fingerprint: F,
+    bit_order: BitOrder,
+    /// Hash of a missing node at each level, the root first
+    empty_hashes: Vec<F>,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField, M> SparseMerkleTree<H, F, M> {
+    /// Copy the tree into an immutable view, for readers the updates of the tree don't affect
+    ///
+    /// Dirty nodes are refreshed first, and the leaves are hashed once so the view doesn't need
+    /// the hasher. The leaf payloads of `with_metadata` aren't copied.
+    pub fn freeze(&self) -> Result<FrozenView<F>, PoseidonMerkleError<F>> {
+        self.freeze_onto(None)
+    }
+
+    /// Freeze the tree, sharing with `previous` the subtrees that didn't change since it was taken
+    ///
+    /// A view of another tree, or of a tree hashing differently, shares nothing.
+    pub(crate) fn freeze_onto(
+        &self,
+        previous: Option<&FrozenView<F>>,
+    ) -> Result<FrozenView<F>, PoseidonMerkleError<F>> {
+        self.root()?;
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        let empty_hashes = (0..=self.depth)
+            .map(|level| {
+                self.hash_config()
+                    .empty_hash_at(hasher, level, level == self.depth)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let previous = previous
+            .filter(|previous| {
+                previous.empty_hashes == empty_hashes
+                    && previous.params_fingerprint == self.params_fingerprint()
+                    && previous.bit_order == self.bit_order()
+                    && previous.config.leaf_hashing == self.hash_config().leaf_hashing
+            })
+            .map(|previous| &previous.root);
+
+        Ok(FrozenView {
+            root: self.freeze_node(hasher, &self.root, previous)?,
+            depth: self.depth,
+            config: self.hash_config().clone(),
+            params_fingerprint: self.params_fingerprint(),
+            bit_order: self.bit_order(),
+            empty_hashes,
+        })
+    }
+
+    /// Copy a subtree, recursing once per level into the nodes that differ from `previous`
+    fn freeze_node(
+        &self,
+        hasher: &mut H,
+        node: &NodeRef<H, F, M>,
+        previous: Option<&Arc<FrozenNode<F>>>,
+    ) -> Result<Arc<FrozenNode<F>>, PoseidonMerkleError<F>> {
+        let node = node.checked_borrow()?;
+        if let Some(previous) = previous.filter(|previous| previous.matches(&node)) {
+            return Ok(previous.clone());
+        }
+
+        let node = match node.node_type {
+            NodeType::Leaf(value) => FrozenNode::Leaf {
+                value,
+                hash: self.hash_config().leaf_hash(hasher, &value)?,
+            },
+            NodeType::Inner(hash) => {
+                let previous_children = match previous.map(|previous| &**previous) {
+                    Some(FrozenNode::Inner { children, .. }) => Some(children),
+                    _ => None,
+                };
+                let children = node.children[..self.hash_config().arity.children()]
+                    .iter()
+                    .enumerate()
+                    .map(|(index, child)| {
+                        let previous =
+                            previous_children.and_then(|children| children[index].as_ref());
+                        child
+                            .as_ref()
+                            .map(|child| self.freeze_node(hasher, child, previous))
+                            .transpose()
+                    })
+                    .collect::<Result<_, _>>()?;
+                FrozenNode::Inner { hash, children }
+            }
+        };
+        Ok(Arc::new(node))
+    }
+}
+
+impl<F: PrimeField> FrozenView<F> {
+    /// The root hash of the tree when it was frozen
+    pub fn root(&self) -> InnerHash<F> {
+        self.root.hash()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn root_node(&self) -> &Arc<FrozenNode<F>> {
+        &self.root
+    }
+
+    /// A view of another version of the same tree
+    #[cfg(feature = "sync")]
+    pub(crate) fn with_root_node(&self, root: Arc<FrozenNode<F>>) -> Self {
+        FrozenView {
+            root,
+            ..self.clone()
+        }
+    }
+
+    /// The value at a path, `None` if no leaf was materialized there, see
+    /// `SparseMerkleTree::try_get_value`
+    pub fn get(&self, merkle_path: &MerklePath<F>) -> Option<F> {
+        let merkle_path = self.canonicalize_path(merkle_path);
+        let mut current = &self.root;
+        for level in 0..self.depth {
+            let FrozenNode::Inner { children, .. } = &**current else {
+                return None;
+            };
+            current = children[self.child_index(&merkle_path, level)].as_ref()?;
+        }
+
+        match &**current {
+            FrozenNode::Leaf { value, .. } => Some(*value),
+            FrozenNode::Inner { .. } => None,
+        }
+    }
+
+    /// Generate a proof of the value at a path, like the tree did when it was frozen
+    pub fn generate_proof(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        let merkle_path = self.canonicalize_path(merkle_path);
+        let leaf_value = self
+            .get(&merkle_path)
+            .ok_or(PoseidonMerkleError::LeafNotFound { path: merkle_path })?;
+
+        // Siblings are stored in the order they will be used during verification
+        let per_level = self.config.arity.children() - 1;
+        let mut siblings = Vec::with_capacity(self.depth * per_level);
+        let mut current = Some(&self.root);
+        for level in 0..self.depth {
+            let empty = self.empty_hashes[level + 1];
+            let Some(node) = current else {
+                siblings.extend(vec![empty; per_level]);
+                continue;
+            };
+            let FrozenNode::Inner { children, .. } = &**node else {
+                return Err(PoseidonMerkleError::InvalidNodeType {
+                    level,
+                    path: Some(merkle_path),
+                });
+            };
+
+            let index = self.child_index(&merkle_path, level);
+            for (sibling_index, sibling) in children.iter().enumerate() {
+                if sibling_index != index {
+                    siblings.push(sibling.as_ref().map_or(empty, |sibling| sibling.hash()));
+                }
+            }
+            current = children[index].as_ref();
+        }
+
+        Ok(
+            MerkleProof::new(siblings, merkle_path, leaf_value, self.root())
+                .try_with_arity(self.config.arity)?
+                .with_leaf_hashing(self.config.leaf_hashing)
+                .with_hash_family(self.config.family)
+                .with_scheme(self.config.scheme)
+                .with_params_fingerprint(self.params_fingerprint)
+                .with_bit_order(self.bit_order)
+                .with_empty_subtrees(self.config.empty_subtrees),
+        )
+    }
+
+    /// The materialized leaves and their path, in child index order
+    pub fn leaves(&self) -> impl Iterator<Item = (MerklePath<F>, F)> + '_ {
+        let mut stack = vec![(&self.root, Vec::new())];
+        core::iter::from_fn(move || {
+            while let Some((node, digits)) = stack.pop() {
+                match &**node {
+                    FrozenNode::Leaf { value, .. } => return Some((self.path_of(&digits), *value)),
+                    FrozenNode::Inner { children, .. } => {
+                        // Push the last child first so that the first one comes out first
+                        for (index, child) in children.iter().enumerate().rev() {
+                            if let Some(child) = child {
+                                let mut child_digits = digits.clone();
+                                child_digits.push(index);
+                                stack.push((child, child_digits));
+                            }
+                        }
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    fn path_bits(&self) -> usize {
+        self.depth * self.config.arity.bits()
+    }
+
+    fn canonicalize_path(&self, merkle_path: &MerklePath<F>) -> MerklePath<F> {
+        let mut bits = merkle_path.into_bigint().to_bits_le();
+        bits.truncate(self.path_bits());
+
+        F::from_bigint(F::BigInt::from_bits_le(&bits)).expect("masked path is below the modulus")
+    }
+
+    fn child_index(&self, merkle_path: &MerklePath<F>, level: usize) -> usize {
+        let offset = self
+            .bit_order
+            .level_offset(level, self.depth, self.config.arity);
+        path_index(merkle_path, offset, self.config.arity)
+    }
+
+    /// The path of the leaf reached through `digits`
+    fn path_of(&self, digits: &[usize]) -> MerklePath<F> {
+        let mut bits = vec![false; self.path_bits()];
+        for (level, digit) in digits.iter().enumerate() {
+            let offset = self
+                .bit_order
+                .level_offset(level, self.depth, self.config.arity);
+            for bit in 0..self.config.arity.bits() {
+                bits[offset + bit] = digit & (1 << bit) != 0;
+            }
+        }
+
+        F::from_bigint(F::BigInt::from_bits_le(&bits)).expect("a path is below the modulus")
+    }
+}
@@ -0,0 +1,148 @@
+//! Fuzzing harnesses of the parsers and of proof verification, run by the targets in `fuzz/`
+//!
+//! Each harness takes arbitrary bytes and must neither panic nor allocate more than the input
+//! calls for: malformed input is an error, never a crash. Inputs that parse are serialized again
+//! and must give back what was read. The harnesses panic on a broken round trip, which the fuzzer
+//! reports like any crash.
+
+use alloc::vec::Vec;
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use light_poseidon::Poseidon;
+
+#[cfg(feature = "tree")]
+use crate::SparseMerkleTree;
+use crate::{
+    eth::{from_bytes32, proof_from_calldata, proof_to_calldata},
+    Arity, BitOrder, HashingScheme, LeafHashing, MerkleProof,
+};
+
+/// The arity picked by a byte of the input
+fn arity(byte: u8) -> Arity {
+    match byte % 3 {
+        0 => Arity::Binary,
+        1 => Arity::Quaternary,
+        _ => Arity::Octal,
+    }
+}
+
+/// The input as 32-byte words, a trailing partial word dropped
+fn words(data: &[u8]) -> Vec<[u8; 32]> {
+    data.chunks_exact(32)
+        .map(|word| word.try_into().expect("32 bytes"))
+        .collect()
+}
+
+/// Parse calldata words with `eth::proof_from_calldata`, the first byte picking the arity and the
+/// first word being the root
+///
+/// A proof that parses must give back the same words.
+pub fn proof_calldata(data: &[u8]) {
+    let Some((&arity_byte, data)) = data.split_first() else {
+        return;
+    };
+    let [root, words @ ..] = &words(data)[..] else {
+        return;
+    };
+    let Ok(root) = from_bytes32(root) else {
+        return;
+    };
+
+    if let Ok(proof) = proof_from_calldata(words, root, arity(arity_byte)) {
+        assert_eq!(
+            proof_to_calldata(&proof),
+            words,
+            "the calldata of a parsed proof differs from the input"
+        );
+    }
+}
+
+/// Verify a proof built unchecked from the input with `MerkleProof::new`
+///
+/// The first byte picks the arity, the second the leaf hashing, scheme and bit order, then come
+/// the leaf, the root, the path and the siblings as big-endian words reduced into the field. The
+/// proof is verified with the circom Poseidon hasher of its arity, which must answer or fail but
+/// never panic.
+pub fn verify_proof(data: &[u8]) {
+    let [arity_byte, conventions, data @ ..] = data else {
+        return;
+    };
+    let elements: Vec<Fr> = words(data)
+        .iter()
+        .map(|word| Fr::from_be_bytes_mod_order(word))
+        .collect();
+    let [leaf, root, path, siblings @ ..] = &elements[..] else {
+        return;
+    };
+
+    let arity = arity(*arity_byte);
+    let proof = MerkleProof {
+        arity,
+        ..MerkleProof::new(siblings.to_vec(), *path, *leaf, *root)
+            .with_leaf_hashing(match conventions & 1 {
+                0 => LeafHashing::Raw,
+                _ => LeafHashing::Hashed,
+            })
+            .with_scheme(match conventions >> 1 & 3 {
+                0 => HashingScheme::Legacy,
+                1 => HashingScheme::DomainSeparated,
+                _ => HashingScheme::LevelBound,
+            })
+            .with_bit_order(match conventions >> 3 & 1 {
+                0 => BitOrder::LsbFirst,
+                _ => BitOrder::MsbFirst,
+            })
+    };
+    let Ok(mut hasher) = Poseidon::<Fr>::new_circom(arity.children()) else {
+        return;
+    };
+
+    let _ = proof.verify_proof(&mut hasher);
+}
+
+/// Rebuild a tree with `SparseMerkleTree::from_snapshot_bytes`
+///
+/// A tree that loads must snapshot to the same bytes.
+#[cfg(feature = "tree")]
+pub fn snapshot_bytes(data: &[u8]) {
+    let Ok(tree) = SparseMerkleTree::<Poseidon<Fr>>::from_snapshot_bytes(data) else {
+        return;
+    };
+
+    let bytes = tree
+        .to_snapshot_bytes()
+        .expect("a loaded tree has its hashes");
+    assert_eq!(
+        bytes, data,
+        "the snapshot of a loaded tree differs from the input"
+    );
+}
+
+/// Parse a JSON proof with `MerkleProof::from_json`
+///
+/// A proof that parses must parse again, to the same proof, from its `to_json` object in every
+/// encoding `FrEncoding::parse` reads.
+#[cfg(feature = "json")]
+pub fn proof_json(data: &[u8]) {
+    use alloc::format;
+
+    use crate::FrEncoding;
+
+    let Ok(json) = serde_json::from_slice(data) else {
+        return;
+    };
+    let Ok(proof) = MerkleProof::<Fr>::from_json(&json) else {
+        return;
+    };
+
+    for encoding in [FrEncoding::Decimal, FrEncoding::HexBe, FrEncoding::Bytes] {
+        let reparsed = MerkleProof::<Fr>::from_json(&proof.to_json(encoding))
+            .expect("the JSON of a parsed proof parses");
+        assert_eq!(
+            format!("{reparsed:?}"),
+            format!("{proof:?}"),
+            "the JSON of a parsed proof reads back to another proof"
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Proof verification for zkVM guests such as RISC Zero and SP1
+//!
+//! Build with `default-features = false, features = ["guest-verify"]` to leave out the trees and
+//! everything tied to them: only the proof, the fold and the hasher are compiled. A level costs
+//! one circom Poseidon hash of two inputs, about 830 bn254 multiplications in the guest.
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{eth::from_bytes32, MerkleProof};
+
+/// Whether `proof_bytes`, a proof of `MerkleProof::to_compact_bytes`, folds into `root`
+///
+/// `root` is 32 big-endian bytes. A root not below the modulus, malformed bytes or a failing
+/// hasher all give `false`, a guest has nothing to recover from them.
+pub fn verify_commitment(root: [u8; 32], proof_bytes: &[u8]) -> bool {
+    let Ok(root) = from_bytes32(&root) else {
+        return false;
+    };
+    let Ok(proof) = MerkleProof::from_compact_bytes(proof_bytes, root) else {
+        return false;
+    };
+    let Ok(mut hasher) = Poseidon::<Fr>::new_circom(2) else {
+        return false;
+    };
+
+    proof.verify_proof(&mut hasher).unwrap_or(false)
+}
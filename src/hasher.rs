@@ -1,4 +1,586 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{any::Any, fmt};
+#[cfg(all(feature = "std", not(all(feature = "solana", target_os = "solana"))))]
+use std::{
+    cell::RefCell,
+    collections::{btree_map::Entry, BTreeMap},
+};
+
 use ark_bn254::Fr;
-use light_poseidon::PoseidonHasher;
+use ark_ff::{PrimeField, Zero};
+#[cfg(not(all(feature = "solana", target_os = "solana")))]
+use light_poseidon::Poseidon;
+use light_poseidon::{PoseidonError, PoseidonHasher};
+
+#[cfg(feature = "mimc")]
+use crate::get_mimc_empty_inner_hash;
+#[cfg(feature = "poseidon2")]
+use crate::get_poseidon2_empty_inner_hash;
+#[cfg(feature = "rescue")]
+use crate::get_rescue_empty_inner_hash;
+use crate::{
+    get_empty_inner_hash_for, get_empty_leaf_hash, telemetry, HashOperation, InnerHash,
+    PoseidonMerkleError,
+};
 
 pub type Hasher = dyn PoseidonHasher<Fr>;
+
+/// A hash function the tree can hash its nodes with
+///
+/// Every `PoseidonHasher` is a `MerkleHasher`. Implement it directly to plug in another hash
+/// function, such as the blake3-based `TestHasher` of the `test-utils` feature.
+pub trait MerkleHasher<F: PrimeField = Fr> {
+    /// Hash the children of an inner node, one input per child
+    fn hash_children(&mut self, children: &[F]) -> Result<F, PoseidonError>;
+
+    /// Hash the two children of a binary inner node
+    fn hash_two(&mut self, left: &F, right: &F) -> Result<F, PoseidonError> {
+        self.hash_children(&[*left, *right])
+    }
+
+    /// Hash of an empty inner node with `arity` children, one zero per child by default
+    ///
+    /// Trees probe their hasher with it, a hasher not supporting the arity must fail.
+    fn empty_hash(&mut self, arity: Arity) -> Result<F, PoseidonError> {
+        self.hash_children(&vec![F::zero(); arity.children()])
+    }
+
+    /// The family of the hash function, `None` to have it identified from its `empty_hash`
+    fn hash_family(&self) -> Option<HashFamily> {
+        None
+    }
+
+    /// Hash of the probe 1, 2, .., `arity`, which tells apart hashers with other parameters
+    ///
+    /// Unlike `empty_hash`, it depends on every input being mixed in, so two parameter sets of the
+    /// same family and width yield different fingerprints.
+    fn params_fingerprint(&mut self, arity: Arity) -> Result<F, PoseidonError> {
+        let probe: Vec<F> = (1..=arity.children() as u64).map(F::from).collect();
+        self.hash_children(&probe)
+    }
+}
+
+impl<F: PrimeField, H: PoseidonHasher<F> + ?Sized> MerkleHasher<F> for H {
+    fn hash_children(&mut self, children: &[F]) -> Result<F, PoseidonError> {
+        self.hash(children)
+    }
+}
+
+#[cfg(all(feature = "std", not(all(feature = "solana", target_os = "solana"))))]
+thread_local! {
+    /// Circom hashers used for leaves by number of inputs, their parameters are costly to build
+    static LEAF_HASHERS: RefCell<BTreeMap<usize, Poseidon<Fr>>> = const {
+        RefCell::new(BTreeMap::new())
+    };
+}
+
+/// Most values a leaf can hold, the widest circom parameters take 12 inputs
+pub const MAX_LEAF_WIDTH: usize = 12;
+
+/// View an element of a generic field as an element of `G`, if both are the same field
+pub(crate) fn as_field<F: PrimeField, G: PrimeField>(value: &F) -> Option<&G> {
+    (value as &dyn Any).downcast_ref::<G>()
+}
+
+/// How a leaf value contributes to the hash of its parent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LeafHashing {
+    /// The raw value is hashed together with its sibling
+    #[default]
+    Raw,
+    /// The value is first hashed on its own as poseidon(value), so a leaf can't pass for a subtree
+    ///
+    /// An empty (zero) leaf contributes the pre-computed empty leaf hash. This relies on the
+    /// circom one-input parameters, so it is only available over the bn254 scalar field.
+    Hashed,
+}
+
+impl LeafHashing {
+    /// The hash a leaf holding `value` contributes to its parent
+    pub fn leaf_hash<F: PrimeField>(self, value: &F) -> Result<F, PoseidonMerkleError<F>> {
+        match self {
+            LeafHashing::Raw => Ok(*value),
+            LeafHashing::Hashed => {
+                let value: &Fr =
+                    as_field(value).ok_or(PoseidonMerkleError::UnsupportedLeafHashing)?;
+                let hash = hash_bn254_leaf(value)?;
+
+                Ok(*as_field(&hash).expect("the field is bn254"))
+            }
+        }
+    }
+
+    /// The hash contributed by a missing leaf
+    pub fn empty_leaf_hash<F: PrimeField>(self) -> Result<F, PoseidonMerkleError<F>> {
+        self.leaf_hash(&F::zero())
+    }
+}
+
+/// poseidon(value) with the circom parameters, the empty leaf hash for zero
+fn hash_bn254_leaf<F: PrimeField>(value: &Fr) -> Result<Fr, PoseidonMerkleError<F>> {
+    if value.is_zero() {
+        return Ok(*get_empty_leaf_hash());
+    }
+
+    hash_bn254_circom(&[*value], HashOperation::LeafHash)
+}
+
+/// Commitment of a leaf holding several values, poseidon(values..) with the circom parameters
+///
+/// Only available over the bn254 scalar field, for 1 to `MAX_LEAF_WIDTH` values.
+pub fn hash_leaf_values<F: PrimeField>(values: &[F]) -> Result<F, PoseidonMerkleError<F>> {
+    let values = values
+        .iter()
+        .map(|value| as_field::<F, Fr>(value).copied())
+        .collect::<Option<Vec<Fr>>>()
+        .ok_or(PoseidonMerkleError::UnsupportedLeafHashing)?;
+    let hash = hash_bn254_circom(&values, HashOperation::LeafHash)?;
+
+    Ok(*as_field(&hash).expect("the field is bn254"))
+}
+
+/// Hash inputs with the circom parameters of their width, errors reporting `operation`
+pub(crate) fn hash_bn254_circom<F: PrimeField>(
+    inputs: &[Fr],
+    operation: HashOperation,
+) -> Result<Fr, PoseidonMerkleError<F>> {
+    // A program can't keep a cached hasher in a static, and the syscall is cheaper anyway
+    #[cfg(all(feature = "solana", target_os = "solana"))]
+    return crate::SolanaPoseidonHasher::new()
+        .hash(inputs)
+        .map_err(PoseidonMerkleError::hasher(operation));
+
+    // Without std there is no thread local to cache the hashers in
+    #[cfg(not(feature = "std"))]
+    return Poseidon::<Fr>::new_circom(inputs.len())
+        .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?
+        .hash(inputs)
+        .map_err(PoseidonMerkleError::hasher(operation));
+
+    #[cfg(all(feature = "std", not(all(feature = "solana", target_os = "solana"))))]
+    LEAF_HASHERS.with(|cell| {
+        let mut hashers = cell.borrow_mut();
+        let hasher = match hashers.entry(inputs.len()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                Poseidon::<Fr>::new_circom(inputs.len())
+                    .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?,
+            ),
+        };
+
+        hasher
+            .hash(inputs)
+            .map_err(PoseidonMerkleError::hasher(operation))
+    })
+}
+
+/// The hash function a tree or a proof hashes its inner nodes with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HashFamily {
+    /// Poseidon with the circom parameters
+    #[default]
+    Poseidon,
+    /// Poseidon2 with the HorizenLabs bn254 parameters, see `Poseidon2Hasher`
+    #[cfg(feature = "poseidon2")]
+    Poseidon2,
+    /// MiMC-7 with the circomlib parameters, see `MimcHasher`
+    #[cfg(feature = "mimc")]
+    Mimc,
+    /// Rescue-Prime with the parameters of `RescueHasher`
+    #[cfg(feature = "rescue")]
+    Rescue,
+    /// A hash function the crate knows nothing about, plugged in through `MerkleHasher`
+    Custom,
+}
+
+impl HashFamily {
+    /// Pre-computed hash of `arity` zeros of the family over bn254, if the family has that width
+    pub fn empty_inner_hash(self, arity: Arity) -> Option<&'static Fr> {
+        match self {
+            HashFamily::Poseidon => Some(get_empty_inner_hash_for(arity)),
+            #[cfg(feature = "poseidon2")]
+            HashFamily::Poseidon2 => (arity == Arity::Binary).then(get_poseidon2_empty_inner_hash),
+            #[cfg(feature = "mimc")]
+            HashFamily::Mimc => (arity == Arity::Binary).then(get_mimc_empty_inner_hash),
+            #[cfg(feature = "rescue")]
+            HashFamily::Rescue => (arity == Arity::Binary).then(get_rescue_empty_inner_hash),
+            HashFamily::Custom => None,
+        }
+    }
+
+    /// The family of a bn254 hasher of the given arity, given its hash of zeros
+    pub fn identify(empty_inner_hash: &Fr, arity: Arity) -> Option<Self> {
+        [
+            HashFamily::Poseidon,
+            #[cfg(feature = "poseidon2")]
+            HashFamily::Poseidon2,
+            #[cfg(feature = "mimc")]
+            HashFamily::Mimc,
+            #[cfg(feature = "rescue")]
+            HashFamily::Rescue,
+        ]
+        .into_iter()
+        .find(|family| family.empty_inner_hash(arity) == Some(empty_inner_hash))
+    }
+}
+
+impl fmt::Display for HashFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashFamily::Poseidon => write!(f, "poseidon"),
+            #[cfg(feature = "poseidon2")]
+            HashFamily::Poseidon2 => write!(f, "poseidon2"),
+            #[cfg(feature = "mimc")]
+            HashFamily::Mimc => write!(f, "mimc"),
+            #[cfg(feature = "rescue")]
+            HashFamily::Rescue => write!(f, "rescue"),
+            HashFamily::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+/// Which instantiation of a hash function a tree uses, for audits and reproducibility
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HasherInfo<F: PrimeField = Fr> {
+    /// The hash function of the inner nodes
+    pub family: HashFamily,
+    /// Inputs of an inner node hash plus one, the state width of Poseidon
+    pub width: usize,
+    /// Modulus of the field the tree hashes over
+    pub field: F::BigInt,
+    /// Hash of the probe 1, 2, .. of the inner nodes, see `MerkleHasher::params_fingerprint`
+    ///
+    /// Binary circom Poseidon trees have poseidon(1, 2) as fingerprint.
+    pub fingerprint: F,
+}
+
+impl<F: PrimeField> HasherInfo<F> {
+    /// Check that these are the hasher parameters a snapshot or a proof was recorded with
+    pub fn ensure_matches(&self, expected: &HasherInfo<F>) -> Result<(), PoseidonMerkleError<F>> {
+        if self != expected {
+            return Err(PoseidonMerkleError::HasherMismatch {
+                expected: Box::new(*expected),
+                actual: Box::new(*self),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: PrimeField> fmt::Display for HasherInfo<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of width {} over the field of modulus {} (fingerprint {})",
+            self.family, self.width, self.field, self.fingerprint
+        )
+    }
+}
+
+/// Tag hashed next to a leaf under `HashingScheme::DomainSeparated`
+pub const LEAF_DOMAIN_TAG: u64 = 1;
+
+/// Whether leaves and inner nodes are hashed with distinguishable inputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HashingScheme {
+    /// A leaf contributes its value (after `LeafHashing`) as is, like an inner node its hash
+    #[default]
+    Legacy,
+    /// A leaf contributes hasher(value, 1), inner nodes stay hasher(left, right)
+    ///
+    /// This is circomlib's hash1 for leaves, a leaf can never pass for an inner node. Wider
+    /// hashers pad the tag with zeros, hasher(value, 1, 0, ..).
+    DomainSeparated,
+    /// Inner nodes at `level` hash as hasher(level, children..), leaves contribute like `Legacy`
+    ///
+    /// Binding the level rules out reusing a subtree at another level. Poseidon trees over bn254
+    /// use the circom parameters of one more input, poseidon(level, left, right) for binary trees,
+    /// other hashers must accept one input more than the arity. The empty subtrees then differ
+    /// at each level, trees keep their hashes in `HashConfig::empty_hashes`.
+    LevelBound,
+}
+
+impl HashingScheme {
+    /// The hash a leaf contributes to its parent, `leaf` being its value after `LeafHashing`
+    pub fn leaf_hash<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+        self,
+        hasher: &mut H,
+        leaf: F,
+        arity: Arity,
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        match self {
+            HashingScheme::Legacy | HashingScheme::LevelBound => Ok(leaf),
+            HashingScheme::DomainSeparated => {
+                let mut inputs = vec![F::zero(); arity.children()];
+                inputs[0] = leaf;
+                inputs[1] = F::from(LEAF_DOMAIN_TAG);
+
+                telemetry::record_hash();
+                hasher
+                    .hash_children(&inputs)
+                    .map_err(PoseidonMerkleError::hasher(HashOperation::LeafHash))
+            }
+        }
+    }
+
+    /// The hash of an inner node at `level` of a tree of `family`, given the hashes of its children
+    pub fn inner_hash<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+        self,
+        hasher: &mut H,
+        family: HashFamily,
+        level: usize,
+        children: &[F],
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        let hash = self.hash_inner(hasher, family, level, children)?;
+        #[cfg(feature = "hash-trace")]
+        if telemetry::reporting() {
+            crate::trace::record(level, children, &hash);
+        }
+
+        Ok(hash)
+    }
+
+    fn hash_inner<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+        self,
+        hasher: &mut H,
+        family: HashFamily,
+        level: usize,
+        children: &[F],
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        let operation = HashOperation::InnerNode { level: Some(level) };
+        telemetry::record_hash();
+        if self != HashingScheme::LevelBound {
+            return hasher
+                .hash_children(children)
+                .map_err(PoseidonMerkleError::hasher(operation));
+        }
+
+        let mut inputs = Vec::with_capacity(children.len() + 1);
+        inputs.push(F::from(level as u64));
+        inputs.extend_from_slice(children);
+        if family == HashFamily::Poseidon {
+            let bn254_inputs = inputs
+                .iter()
+                .map(|input| as_field::<F, Fr>(input).copied())
+                .collect::<Option<Vec<Fr>>>();
+            if let Some(bn254_inputs) = bn254_inputs {
+                let hash = hash_bn254_circom(&bn254_inputs, operation)?;
+                return Ok(*as_field(&hash).expect("the field is bn254"));
+            }
+        }
+
+        hasher
+            .hash_children(&inputs)
+            .map_err(PoseidonMerkleError::hasher(operation))
+    }
+}
+
+impl fmt::Display for HashingScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashingScheme::Legacy => write!(f, "legacy hashing"),
+            HashingScheme::DomainSeparated => write!(f, "domain separated hashing"),
+            HashingScheme::LevelBound => write!(f, "level bound hashing"),
+        }
+    }
+}
+
+/// How a missing inner node hashes, the empty subtree below it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum EmptySubtrees {
+    /// Every missing inner node is the hasher of one zero per child, poseidon(0, 0) whatever its
+    /// level
+    ///
+    /// The setting of proofs that don't record one. Trees fold unless chained with
+    /// `with_empty_subtrees(EmptySubtrees::Flat)`.
+    #[default]
+    Flat,
+    /// A missing inner node hashes copies of the empty subtree one level down, from the empty leaf
+    /// up, like circomlibjs and most other sparse Merkle trees
+    ///
+    /// An empty subtree then differs at each level, trees keep their hashes in
+    /// `HashConfig::empty_hashes`. The binary circom Poseidon ones are `get_empty_hash_at_level`.
+    Folded,
+}
+
+impl fmt::Display for EmptySubtrees {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmptySubtrees::Flat => write!(f, "flat empty subtrees"),
+            EmptySubtrees::Folded => write!(f, "folded empty subtrees"),
+        }
+    }
+}
+
+/// Number of children of an inner node
+///
+/// Each level of a path consumes `bits()` bits, read little-endian into the index of the child.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arity {
+    /// Two children, hashed as hasher(left, right)
+    #[default]
+    Binary,
+    /// Four children, the hasher must take four inputs
+    Quaternary,
+    /// Eight children, the hasher must take eight inputs
+    Octal,
+}
+
+impl Arity {
+    /// Number of children of an inner node
+    pub fn children(self) -> usize {
+        1 << self.bits()
+    }
+
+    /// Number of path bits consumed per level
+    pub fn bits(self) -> usize {
+        match self {
+            Arity::Binary => 1,
+            Arity::Quaternary => 2,
+            Arity::Octal => 3,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-ary", self.children())
+    }
+}
+
+/// The hashing conventions of a tree, handed to its nodes when they rehash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashConfig<F: PrimeField = Fr> {
+    /// How leaf values contribute to their parent hash
+    pub leaf_hashing: LeafHashing,
+    /// Hash of an empty inner node, the hasher applied to one zero per child
+    pub empty_inner_hash: InnerHash<F>,
+    /// The hash function of the inner nodes
+    pub family: HashFamily,
+    /// Number of children of an inner node
+    pub arity: Arity,
+    /// Whether leaves are hashed apart from inner nodes
+    pub scheme: HashingScheme,
+    /// Value of an empty leaf, zero unless the tree was given another one
+    pub empty_leaf: F,
+    /// How missing inner nodes hash
+    pub empty_subtrees: EmptySubtrees,
+    /// Hash of an empty subtree at every level, the root first and the empty leaf contribution
+    /// last. Left empty unless `has_level_empty_hashes`, every missing inner node is then
+    /// `empty_inner_hash`.
+    pub empty_hashes: Vec<InnerHash<F>>,
+}
+
+impl<F: PrimeField> HashConfig<F> {
+    pub fn new(leaf_hashing: LeafHashing, empty_inner_hash: InnerHash<F>) -> Self {
+        HashConfig {
+            leaf_hashing,
+            empty_inner_hash,
+            family: HashFamily::default(),
+            arity: Arity::default(),
+            scheme: HashingScheme::default(),
+            empty_leaf: F::zero(),
+            empty_subtrees: EmptySubtrees::default(),
+            empty_hashes: Vec::new(),
+        }
+    }
+
+    /// Set the hash function of the inner nodes
+    pub fn with_family(self, family: HashFamily) -> Self {
+        Self { family, ..self }
+    }
+
+    /// Set the number of children of an inner node
+    pub fn with_arity(self, arity: Arity) -> Self {
+        Self { arity, ..self }
+    }
+
+    /// Set whether leaves are hashed apart from inner nodes
+    pub fn with_scheme(self, scheme: HashingScheme) -> Self {
+        Self { scheme, ..self }
+    }
+
+    /// Set how missing inner nodes hash
+    pub fn with_empty_subtrees(self, empty_subtrees: EmptySubtrees) -> Self {
+        Self {
+            empty_subtrees,
+            ..self
+        }
+    }
+
+    /// The hash a leaf holding `value` contributes to its parent
+    pub fn leaf_hash<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        value: &F,
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        let leaf = self.leaf_hashing.leaf_hash(value)?;
+        self.scheme.leaf_hash(hasher, leaf, self.arity)
+    }
+
+    /// The hash of an inner node at `level` from the hashes of its children
+    pub fn inner_hash<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        level: usize,
+        children: &[F],
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        self.scheme.inner_hash(hasher, self.family, level, children)
+    }
+
+    /// Whether empty subtrees hash differently at each level, so `empty_hashes` must be filled
+    pub fn has_level_empty_hashes(&self) -> bool {
+        !self.empty_leaf.is_zero()
+            || self.scheme == HashingScheme::LevelBound
+            || self.empty_subtrees == EmptySubtrees::Folded
+    }
+
+    /// The hash contributed by a missing leaf
+    pub fn empty_leaf_hash<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        self.leaf_hash(hasher, &self.empty_leaf)
+    }
+
+    /// Hash of an empty inner node at `level`, the root being level 0
+    pub fn empty_inner_hash_at(&self, level: usize) -> InnerHash<F> {
+        self.empty_hashes
+            .get(level)
+            .copied()
+            .unwrap_or(self.empty_inner_hash)
+    }
+
+    /// The hash contributed by a missing node at `level`, `is_leaf` telling if it'd be a leaf
+    pub fn empty_hash_at<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        level: usize,
+        is_leaf: bool,
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        match self.empty_hashes.get(level) {
+            Some(hash) => Ok(*hash),
+            None if is_leaf => self.empty_leaf_hash(hasher),
+            None => Ok(self.empty_inner_hash),
+        }
+    }
+
+    /// Hash the empty subtrees of a tree of `depth` levels from the empty leaf up
+    pub(crate) fn empty_hash_chain<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        depth: usize,
+    ) -> Result<Vec<InnerHash<F>>, PoseidonMerkleError<F>> {
+        let mut hashes = vec![self.empty_leaf_hash(hasher)?; depth + 1];
+        for level in (0..depth).rev() {
+            hashes[level] = self.inner_hash(
+                hasher,
+                level,
+                &vec![hashes[level + 1]; self.arity.children()],
+            )?;
+        }
+
+        Ok(hashes)
+    }
+}
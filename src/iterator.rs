@@ -1,88 +1,130 @@
-use std::{cell::RefCell, rc::Rc};
-
+use crate::{
+    shared::CheckedBorrow, MerkleHasher, NodeRef, NodeType, PoseidonMerkleError, SparseMerkleTree,
+};
+use alloc::{vec, vec::Vec};
 use ark_bn254::Fr;
-use light_poseidon::PoseidonHasher;
-
-use crate::{Node, NodeType, SparseMerkleTree};
+use ark_ff::PrimeField;
 
 // Owned iterator struct
 #[derive(Debug, Clone)]
-pub struct SparseTreeIterator<H: PoseidonHasher<Fr>> {
+pub struct SparseTreeIterator<H: MerkleHasher<F>, F: PrimeField = Fr, M = ()> {
     // Stack for DFS traversal
-    stack: Vec<Rc<RefCell<Node<H>>>>,
-    _phantom: std::marker::PhantomData<H>,
+    stack: Vec<NodeRef<H, F, M>>,
+    _phantom: core::marker::PhantomData<H>,
 }
 
 // Borrowed iterator struct
 #[derive(Debug, Clone)]
-pub struct SparseTreeRefIterator<H: PoseidonHasher<Fr>> {
-    stack: Vec<Rc<RefCell<Node<H>>>>,
-    _phantom: std::marker::PhantomData<H>,
+pub struct SparseTreeRefIterator<H: MerkleHasher<F>, F: PrimeField = Fr, M = ()> {
+    stack: Vec<NodeRef<H, F, M>>,
+    _phantom: core::marker::PhantomData<H>,
+}
+
+/// Borrowed iterator over the leaf values and their payload, see `SparseMerkleTree::iter_with_meta`
+#[derive(Debug, Clone)]
+pub struct SparseTreeMetaIterator<H: MerkleHasher<F>, F: PrimeField = Fr, M = ()> {
+    stack: Vec<NodeRef<H, F, M>>,
 }
 
 /// DFS Iterator implementation for borrowed tree
-impl<H: PoseidonHasher<Fr>> Iterator for SparseTreeRefIterator<H> {
-    type Item = Fr;
+impl<H: MerkleHasher<F>, F: PrimeField, M> Iterator for SparseTreeRefIterator<H, F, M> {
+    type Item = Result<F, PoseidonMerkleError<F>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(node) = self.stack.pop() {
-            let node_ref = node.borrow();
+            let Ok(node_ref) = node.checked_borrow() else {
+                self.stack.clear();
+                return Some(Err(PoseidonMerkleError::ConcurrentAccess));
+            };
             if let NodeType::Leaf(value) = node_ref.node_type {
-                return Some(value);
+                return Some(Ok(value));
             }
 
-            if let Some(right) = node_ref.right.as_ref() {
-                self.stack.push(right.clone());
-            }
-            if let Some(left) = node_ref.left.as_ref() {
-                self.stack.push(left.clone());
-            }
+            // Push the children in reverse so that the first one is visited first
+            self.stack
+                .extend(node_ref.children.iter().rev().flatten().cloned());
         }
         None
     }
 }
 
 /// DFS Iterator implementation for owned tree
-impl<H: PoseidonHasher<Fr>> Iterator for SparseTreeIterator<H> {
-    type Item = Fr;
+impl<H: MerkleHasher<F>, F: PrimeField, M> Iterator for SparseTreeIterator<H, F, M> {
+    type Item = Result<F, PoseidonMerkleError<F>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(node) = self.stack.pop() {
-            let node_ref = node.borrow();
+            let Ok(node_ref) = node.checked_borrow() else {
+                self.stack.clear();
+                return Some(Err(PoseidonMerkleError::ConcurrentAccess));
+            };
             if let NodeType::Leaf(value) = node_ref.node_type {
-                return Some(value);
+                return Some(Ok(value));
             }
 
-            if let Some(right) = node_ref.right.as_ref() {
-                self.stack.push(right.clone());
-            }
-            if let Some(left) = node_ref.left.as_ref() {
-                self.stack.push(left.clone());
+            // Push the children in reverse so that the first one is visited first
+            self.stack
+                .extend(node_ref.children.iter().rev().flatten().cloned());
+        }
+        None
+    }
+}
+
+/// DFS Iterator implementation yielding the payloads along the values
+impl<H: MerkleHasher<F>, F: PrimeField, M: Clone> Iterator for SparseTreeMetaIterator<H, F, M> {
+    type Item = Result<(F, Option<M>), PoseidonMerkleError<F>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            let Ok(node_ref) = node.checked_borrow() else {
+                self.stack.clear();
+                return Some(Err(PoseidonMerkleError::ConcurrentAccess));
+            };
+            if let NodeType::Leaf(value) = node_ref.node_type {
+                return Some(Ok((value, node_ref.meta.clone())));
             }
+
+            // Push the children in reverse so that the first one is visited first
+            self.stack
+                .extend(node_ref.children.iter().rev().flatten().cloned());
         }
         None
     }
 }
 
 // owned iteration implementation
-impl<H: PoseidonHasher<Fr>> IntoIterator for SparseMerkleTree<H> {
-    type Item = Fr;
-    type IntoIter = SparseTreeIterator<H>;
+impl<H: MerkleHasher<F>, F: PrimeField, M> IntoIterator for SparseMerkleTree<H, F, M> {
+    type Item = Result<F, PoseidonMerkleError<F>>;
+    type IntoIter = SparseTreeIterator<H, F, M>;
 
     fn into_iter(self) -> Self::IntoIter {
         SparseTreeIterator {
             stack: vec![self.root.clone()],
-            _phantom: std::marker::PhantomData,
+            _phantom: core::marker::PhantomData,
         }
     }
 }
 
 // reference-based iteration implementation
-impl<H: PoseidonHasher<Fr>> SparseMerkleTree<H> {
-    pub fn iter(&self) -> SparseTreeRefIterator<H> {
+impl<H: MerkleHasher<F>, F: PrimeField, M> SparseMerkleTree<H, F, M> {
+    /// Iterate over the leaf values depth-first, children in index order
+    ///
+    /// A node borrowed elsewhere, through a `NodeRef` the caller holds, ends the iteration with
+    /// `PoseidonMerkleError::ConcurrentAccess`.
+    pub fn iter(&self) -> SparseTreeRefIterator<H, F, M> {
         SparseTreeRefIterator {
             stack: vec![self.root.clone()],
-            _phantom: std::marker::PhantomData,
+            _phantom: core::marker::PhantomData,
+        }
+    }
+
+    /// Iterate over the leaf values with their payload, in the order of `iter`
+    pub fn iter_with_meta(&self) -> SparseTreeMetaIterator<H, F, M>
+    where
+        M: Clone,
+    {
+        SparseTreeMetaIterator {
+            stack: vec![self.root.clone()],
         }
     }
 }
@@ -0,0 +1,243 @@
+use alloc::{vec, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+
+use crate::{
+    tree::probe_family, Arity, HashFamily, HashOperation, MerkleHasher, MerkleProof,
+    PoseidonMerkleError, ProofError,
+};
+
+/// Incremental merkle tree whose nodes without a right child pass their left child through
+///
+/// Leaves are appended from the left and the tree grows one level whenever it fills up, its
+/// depth being ceil(log2(size)). A node with two children hashes as hasher(left, right), a node
+/// without a right child takes the value of its left one, so a single leaf is its own root. The
+/// design is the one of zk-kit's LeanIMT, but its roots and proofs haven't been checked against it.
+#[derive(Debug, Clone)]
+pub struct LeanIncrementalTree<H: MerkleHasher<F>, F: PrimeField = Fr> {
+    hasher: H,
+    /// Nodes by level, the leaves first and the root last
+    nodes: Vec<Vec<F>>,
+    /// The hash function of the nodes, recorded in the proofs of the tree
+    family: HashFamily,
+    /// Fingerprint of the hasher parameters, recorded in the proofs of the tree
+    params_fingerprint: F,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> LeanIncrementalTree<H, F> {
+    /// Create an empty tree hashing with a two-input hasher, probed like in
+    /// `SparseMerkleTree::new_with_hasher`
+    pub fn new_with_hasher(mut hasher: H) -> Result<Self, PoseidonMerkleError<F>> {
+        let empty_inner_hash = hasher
+            .empty_hash(Arity::Binary)
+            .map_err(|source| PoseidonMerkleError::InvalidHasherWidth { source })?;
+        let params_fingerprint = hasher
+            .params_fingerprint(Arity::Binary)
+            .map_err(|source| PoseidonMerkleError::InvalidHasherWidth { source })?;
+        let family = probe_family(&hasher, &empty_inner_hash, Arity::Binary, false)?;
+
+        Ok(LeanIncrementalTree {
+            hasher,
+            nodes: vec![Vec::new()],
+            family,
+            params_fingerprint,
+        })
+    }
+
+    /// Number of leaves
+    pub fn size(&self) -> usize {
+        self.nodes[0].len()
+    }
+
+    /// Number of levels above the leaves, zero for a tree of at most one leaf
+    pub fn depth(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// Check if the tree has no leaf
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// The root hash, `None` for an empty tree
+    pub fn root(&self) -> Option<F> {
+        self.nodes[self.depth()].first().copied()
+    }
+
+    /// The leaves in insertion order
+    pub fn leaves(&self) -> &[F] {
+        &self.nodes[0]
+    }
+
+    /// The hash function of the nodes
+    pub fn hash_family(&self) -> HashFamily {
+        self.family
+    }
+
+    /// Index of the first leaf holding `value`
+    pub fn index_of(&self, value: &F) -> Option<usize> {
+        self.nodes[0].iter().position(|leaf| leaf == value)
+    }
+
+    /// Hash two nodes into their parent, `level` counting from the root
+    fn hash_node(
+        &mut self,
+        left: &F,
+        right: &F,
+        level: usize,
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        self.hasher
+            .hash_two(left, right)
+            .map_err(PoseidonMerkleError::hasher(HashOperation::InnerNode {
+                level: Some(level),
+            }))
+    }
+
+    /// Append a leaf and return its index
+    pub fn insert(&mut self, value: &F) -> Result<usize, PoseidonMerkleError<F>> {
+        let index = self.size();
+        let depth = (index + 1).next_power_of_two().trailing_zeros() as usize;
+
+        // Stage the new node of every level, so that a failing hasher leaves the tree untouched
+        let mut path = vec![*value];
+        let mut position = index;
+        for level in 0..depth {
+            let node = path[level];
+            // The new leaf is the last one, so its nodes never have a right sibling
+            path.push(if position & 1 == 1 {
+                let sibling = self.nodes[level][position - 1];
+                self.hash_node(&sibling, &node, depth - level - 1)?
+            } else {
+                node
+            });
+            position >>= 1;
+        }
+
+        self.nodes.resize(depth + 1, Vec::new());
+        let mut position = index;
+        for (level, node) in path.into_iter().enumerate() {
+            let nodes = &mut self.nodes[level];
+            match nodes.get_mut(position) {
+                Some(slot) => *slot = node,
+                None => nodes.push(node),
+            }
+            position >>= 1;
+        }
+
+        Ok(index)
+    }
+
+    /// Replace the leaf at `index`, failing with `IndexOutOfRange` past the last leaf
+    pub fn update(&mut self, index: usize, value: &F) -> Result<(), PoseidonMerkleError<F>> {
+        self.check_index(index)?;
+
+        let depth = self.depth();
+        let mut path = vec![*value];
+        let mut position = index;
+        for level in 0..depth {
+            let node = path[level];
+            path.push(if position & 1 == 1 {
+                let sibling = self.nodes[level][position - 1];
+                self.hash_node(&sibling, &node, depth - level - 1)?
+            } else {
+                match self.nodes[level].get(position + 1).copied() {
+                    Some(sibling) => self.hash_node(&node, &sibling, depth - level - 1)?,
+                    None => node,
+                }
+            });
+            position >>= 1;
+        }
+
+        let mut position = index;
+        for (level, node) in path.into_iter().enumerate() {
+            self.nodes[level][position] = node;
+            position >>= 1;
+        }
+
+        Ok(())
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), PoseidonMerkleError<F>> {
+        if index >= self.size() {
+            return Err(PoseidonMerkleError::IndexOutOfRange {
+                index,
+                size: self.size(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Generate a proof for the leaf at `index`
+    ///
+    /// Levels where the node has no sibling are skipped, so the proof has one sibling per level
+    /// actually hashed. Its path keeps the matching directions with the root level first, which
+    /// makes it an ordinary binary `MerkleProof`: the index bits from the leaf up are the path
+    /// read with the bits reversed.
+    pub fn generate_proof(&self, index: usize) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        self.check_index(index)?;
+
+        // Siblings and directions from the leaf up
+        let mut siblings = Vec::new();
+        let mut directions = Vec::new();
+        let mut position = index;
+        for level in 0..self.depth() {
+            let is_right = position & 1 == 1;
+            let sibling_position = if is_right { position - 1 } else { position + 1 };
+            if let Some(sibling) = self.nodes[level].get(sibling_position) {
+                siblings.push(*sibling);
+                directions.push(is_right);
+            }
+            position >>= 1;
+        }
+        siblings.reverse();
+        directions.reverse();
+
+        let merkle_path = F::from_bigint(F::BigInt::from_bits_le(&directions))
+            .expect("a path of at most usize::BITS bits is below the modulus");
+        let root = self.root().expect("the tree has the leaf at index");
+
+        Ok(
+            MerkleProof::new(siblings, merkle_path, self.nodes[0][index], root)
+                .with_hash_family(self.family)
+                .with_params_fingerprint(self.params_fingerprint),
+        )
+    }
+
+    /// Verify a proof against the current root of the tree
+    ///
+    /// The proof must come from a lean tree of the same hash family. It's folded like any binary
+    /// proof, one level per sibling.
+    pub fn verify_proof(&mut self, proof: &MerkleProof<F>) -> Result<bool, PoseidonMerkleError<F>> {
+        if proof.hash_family != self.family {
+            return Err(ProofError::HashFamilyMismatch {
+                expected: proof.hash_family,
+                actual: self.family,
+            }
+            .into());
+        }
+        if proof.arity != Arity::Binary {
+            return Err(ProofError::ArityMismatch {
+                expected: proof.arity,
+                actual: Arity::Binary,
+            }
+            .into());
+        }
+        if Some(proof.root_hash) != self.root() {
+            return Ok(false);
+        }
+
+        proof.verify_proof(&mut self.hasher)
+    }
+}
+
+impl LeanIncrementalTree<Poseidon<Fr>> {
+    /// Create an empty tree hashing with circom Poseidon, like Semaphore groups
+    pub fn new() -> Result<Self, PoseidonMerkleError> {
+        let poseidon = Poseidon::<Fr>::new_circom(2)
+            .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+        Self::new_with_hasher(poseidon)
+    }
+}
@@ -1,22 +1,162 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "tree")]
+mod append;
+#[cfg(feature = "tokio")]
+mod async_tree;
+#[cfg(feature = "sync")]
+mod background;
+#[cfg(feature = "tree")]
+mod changelog;
+#[cfg(feature = "tree")]
+mod circom_smt;
+mod circuit;
+mod compact;
+#[cfg(all(feature = "std", feature = "tree"))]
+mod concurrent;
 mod constants;
+#[cfg(feature = "tree")]
+mod delta;
+#[cfg(feature = "json")]
+mod encoding;
 mod errors;
+pub mod eth;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "tree")]
+mod forest;
+#[cfg(feature = "tree")]
+mod frozen;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod fuzz;
+#[cfg(feature = "guest-verify")]
+pub mod guest;
 mod hasher;
+#[cfg(feature = "tree")]
 mod iterator;
+#[cfg(feature = "tree")]
+mod lean_imt;
+#[cfg(feature = "mimc")]
+mod mimc;
+#[cfg(feature = "tree")]
 mod node;
+#[cfg(feature = "tree")]
+mod nullifier;
+#[cfg(feature = "tree")]
+mod observer;
+mod path;
+#[cfg(feature = "poseidon2")]
+mod poseidon2;
 mod proof;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "r1cs")]
+mod r1cs;
+#[cfg(feature = "std")]
+mod registry;
+#[cfg(feature = "rescue")]
+mod rescue;
+#[cfg(feature = "tree")]
+mod shared;
+#[cfg(feature = "tree")]
+mod snapshot;
+#[cfg(feature = "solana")]
+mod solana;
+#[cfg(feature = "tree")]
+mod stats;
+#[cfg(all(feature = "json", feature = "tree"))]
+mod structure;
+#[cfg(feature = "sync")]
+mod sync_tree;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-utils"))]
+mod test_utils;
+#[cfg(feature = "hash-trace")]
+mod trace;
+#[cfg(feature = "tree")]
 mod tree;
+pub mod verify;
+#[cfg(feature = "sync")]
+mod versioned;
 #[cfg(feature = "visualize")]
 mod visualizer;
+#[cfg(any(feature = "visualize", all(feature = "json", feature = "tree")))]
+mod walk;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(all(feature = "async", feature = "tree"))]
+mod yielding;
+#[cfg(all(feature = "json", feature = "tree"))]
+mod zkkit;
 
+#[cfg(feature = "tree")]
+pub use append::*;
+#[cfg(feature = "tokio")]
+pub use async_tree::*;
+#[cfg(feature = "sync")]
+pub use background::*;
+#[cfg(feature = "tree")]
+pub use changelog::*;
+#[cfg(feature = "tree")]
+pub use circom_smt::*;
+pub use circuit::*;
 pub use constants::*;
+#[cfg(feature = "tree")]
+pub use delta::*;
+#[cfg(feature = "json")]
+pub use encoding::*;
 pub use errors::*;
+#[cfg(feature = "tree")]
+pub use forest::*;
+#[cfg(feature = "tree")]
+pub use frozen::*;
 pub use hasher::*;
+#[cfg(feature = "tree")]
 pub use iterator::*;
+#[cfg(feature = "tree")]
+pub use lean_imt::*;
+#[cfg(feature = "mimc")]
+pub use mimc::*;
+#[cfg(feature = "tree")]
 pub use node::*;
+#[cfg(feature = "tree")]
+pub use nullifier::*;
+#[cfg(feature = "tree")]
+pub use observer::*;
+pub use path::*;
+#[cfg(feature = "poseidon2")]
+pub use poseidon2::*;
 pub use proof::*;
+#[cfg(feature = "r1cs")]
+pub use r1cs::*;
+#[cfg(feature = "std")]
+pub use registry::*;
+#[cfg(feature = "rescue")]
+pub use rescue::*;
+#[cfg(feature = "tree")]
+pub use shared::*;
+#[cfg(feature = "solana")]
+pub use solana::*;
+#[cfg(feature = "tree")]
+pub use stats::*;
+#[cfg(feature = "sync")]
+pub use sync_tree::*;
+#[cfg(any(test, feature = "test-utils"))]
+pub use test_utils::*;
+#[cfg(feature = "hash-trace")]
+pub use trace::*;
+#[cfg(feature = "tree")]
 pub use tree::*;
+#[cfg(feature = "sync")]
+pub use versioned::*;
 #[cfg(feature = "visualize")]
 pub use visualizer::*;
+#[cfg(all(feature = "async", feature = "tree"))]
+pub use yielding::*;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "tree"))]
 mod tests;
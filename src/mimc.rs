@@ -0,0 +1,72 @@
+use alloc::{vec, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, Field};
+use light_poseidon::{PoseidonError, PoseidonHasher};
+
+use crate::{keccak_round_constants, MAX_ARITY};
+
+/// Rounds of the MiMC-7 permutation
+const MIMC_ROUNDS: usize = 91;
+
+/// Seed of the keccak256 chain the round constants are derived from
+const MIMC_SEED: &str = "mimc";
+
+/// MiMC-7 over bn254 behind the `PoseidonHasher` interface of the tree
+///
+/// Uses the circomlib parameters: 91 rounds of x^7, the first round constant being zero and the
+/// others derived from keccak256("mimc"). Inputs are chained like circomlib's `multiHash` with a
+/// zero key, r = r + x + mimc7(x, r) for every input x, so trees hash like circomlib's `Mimc7`
+/// template. It accepts one to `MAX_ARITY` inputs.
+#[derive(Debug, Clone)]
+pub struct MimcHasher {
+    constants: Vec<Fr>,
+}
+
+impl MimcHasher {
+    pub fn new() -> Self {
+        let mut constants = vec![Fr::ZERO];
+        constants.extend(keccak_round_constants(MIMC_SEED, MIMC_ROUNDS - 1));
+
+        MimcHasher { constants }
+    }
+
+    /// The MiMC-7 permutation of `x` keyed with `k`, circomlib's `mimc7.hash`
+    pub fn permute(&self, x: &Fr, k: &Fr) -> Fr {
+        let mut r = *x;
+        for (round, constant) in self.constants.iter().enumerate() {
+            let t = match round {
+                0 => *x + k,
+                _ => r + k + constant,
+            };
+            r = t.pow([7]);
+        }
+
+        r + k
+    }
+}
+
+impl Default for MimcHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PoseidonHasher<Fr> for MimcHasher {
+    fn hash(&mut self, inputs: &[Fr]) -> Result<Fr, PoseidonError> {
+        if inputs.is_empty() {
+            return Err(PoseidonError::EmptyInput);
+        }
+        if inputs.len() > MAX_ARITY {
+            return Err(PoseidonError::InvalidNumberOfInputs {
+                inputs: inputs.len(),
+                max_limit: MAX_ARITY,
+                width: MAX_ARITY + 1,
+            });
+        }
+
+        Ok(inputs
+            .iter()
+            .fold(Fr::ZERO, |r, input| r + input + self.permute(input, &r)))
+    }
+}
@@ -1,25 +1,26 @@
-use std::{cell::RefCell, rc::Rc};
-
+use crate::{
+    get_empty_inner_hash, shared::CheckedBorrow, telemetry, HashConfig, HashOperation, InnerHash,
+    LeafHashing, MerkleHasher, NodeCell, PoseidonMerkleError, Shared, MAX_ARITY,
+};
+use alloc::{format, string::String, vec::Vec};
 use ark_bn254::Fr;
-use ark_ff::AdditiveGroup;
-use light_poseidon::PoseidonHasher;
-
-use crate::{get_empty_inner_hash, PoseidonMerkleError};
+use ark_ff::{BigInteger, PrimeField};
+use core::fmt;
 
-/// Poseidon(left, right)
-pub type InnerHash = Fr;
+/// Shared, mutable handle to a node
+pub type NodeRef<H, F = Fr, M = ()> = Shared<NodeCell<Node<H, F, M>>>;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum NodeType {
-    /// A leaf node with a raw value (no hash) this is Fr::ZERO if the node is empty
-    Leaf(Fr),
+pub enum NodeType<F: PrimeField = Fr> {
+    /// A leaf node with a raw value (no hash) this is zero if the node is empty
+    Leaf(F),
     /// An inner node with a hash
-    Inner(InnerHash),
+    Inner(InnerHash<F>),
 }
 
-impl NodeType {
+impl<F: PrimeField> NodeType<F> {
     /// Get the hash of an inner node, None if the node is a leaf node
-    pub fn hash(&self) -> Option<&InnerHash> {
+    pub fn hash(&self) -> Option<&InnerHash<F>> {
         match self {
             NodeType::Leaf(_) => None,
             NodeType::Inner(hash) => Some(hash),
@@ -27,7 +28,7 @@ impl NodeType {
     }
 
     /// Get the value of a leaf node, None if the node is an inner node
-    pub fn value(&self) -> Option<&Fr> {
+    pub fn value(&self) -> Option<&F> {
         match self {
             NodeType::Leaf(value) => Some(value),
             NodeType::Inner(_) => None,
@@ -35,7 +36,7 @@ impl NodeType {
     }
 
     /// Get either the value or the hash of the node
-    pub fn data(&self) -> &Fr {
+    pub fn data(&self) -> &F {
         match self {
             NodeType::Leaf(value) => value,
             NodeType::Inner(hash) => hash,
@@ -44,135 +45,362 @@ impl NodeType {
 }
 
 // TODO: add path hash, depth level and sibling hash
-#[derive(Debug, Clone)]
-pub struct Node<H: PoseidonHasher<Fr>> {
-    pub node_type: NodeType,
-    pub left: Option<Rc<RefCell<Node<H>>>>,
-    pub right: Option<Rc<RefCell<Node<H>>>>,
+#[derive(Clone)]
+pub struct Node<H: MerkleHasher<F>, F: PrimeField = Fr, M = ()> {
+    pub node_type: NodeType<F>,
+    /// Children by index, only the first `arity` slots of the tree are used
+    pub children: [Option<NodeRef<H, F, M>>; MAX_ARITY],
+    /// Whether the cached hash is stale and must be recomputed from the children
+    pub dirty: bool,
+    /// The values a leaf commits to when the tree holds several values per leaf
+    pub values: Option<Vec<F>>,
+    /// The payload of a leaf, kept next to its value but never hashed
+    pub meta: Option<M>,
 }
 
-impl<H: PoseidonHasher<Fr>> Node<H> {
-    pub fn new_empty_leaf() -> Self {
-        Node {
-            node_type: NodeType::Leaf(Fr::ZERO),
-            left: None,
-            right: None,
+/// How the visualizations and `Debug` summaries write field elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FrFormat {
+    /// Decimal, shortened to the first and last 5 digits like `12314..12314` past 10 digits
+    #[default]
+    ShortDecimal,
+    /// Every decimal digit
+    FullDecimal,
+    /// `0x` and hex digits without leading zeros, shortened like `ShortDecimal`
+    ShortHex,
+    /// `0x` and big-endian hex digits, two per byte of the field, as block explorers show them
+    FullHex,
+}
+
+impl FrFormat {
+    /// Write a field element in this format
+    pub fn format<F: PrimeField>(self, fr: &F) -> String {
+        match self {
+            FrFormat::ShortDecimal => shorten(&fr.to_string()),
+            FrFormat::FullDecimal => fr.to_string(),
+            FrFormat::ShortHex => {
+                let digits = hex_digits(fr);
+                let significant = digits.trim_start_matches('0');
+                match significant {
+                    "" => String::from("0x0"),
+                    significant => format!("0x{}", shorten(significant)),
+                }
+            }
+            FrFormat::FullHex => format!("0x{}", hex_digits(fr)),
+        }
+    }
+}
+
+/// The first 5 and last 5 characters of a string longer than 10
+fn shorten(digits: &str) -> String {
+    if digits.len() > 10 {
+        format!("{}..{}", &digits[..5], &digits[digits.len() - 5..])
+    } else {
+        String::from(digits)
+    }
+}
+
+/// Big-endian hex digits of a field element, two per byte
+fn hex_digits<F: PrimeField>(fr: &F) -> String {
+    fr.into_bigint()
+        .to_bytes_be()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The summary `Debug` of a node in the given format, whatever the formatter it is printed in
+pub(crate) struct NodeSummary<'a, H: MerkleHasher<F>, F: PrimeField, M>(
+    pub(crate) &'a NodeRef<H, F, M>,
+    pub(crate) FrFormat,
+);
+
+impl<H: MerkleHasher<F>, F: PrimeField, M> fmt::Debug for NodeSummary<'_, H, F, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Like the `Debug` of a `RefCell`, a node borrowed elsewhere isn't read
+        match self.0.checked_borrow() {
+            Ok(node) => write!(f, "{:?}", node.debug_with(self.1)),
+            Err(_) => f.write_str("<borrowed>"),
         }
     }
+}
 
+/// The `Debug` of a node with its field elements in a given format, see `Node::debug_with`
+pub struct NodeDebug<'a, H: MerkleHasher<F>, F: PrimeField, M = ()> {
+    node: &'a Node<H, F, M>,
+    format: FrFormat,
+}
+
+/// The node type, hash or value shortened, which children are present and whether it is dirty
+///
+/// Children are not expanded, so printing a node doesn't walk its subtree. The alternate form,
+/// `{:#?}`, adds the summary of each child.
+impl<H: MerkleHasher<F>, F: PrimeField, M> fmt::Debug for NodeDebug<'_, H, F, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let NodeDebug { node, format } = self;
+        let expand = f.alternate();
+        let mut debug = f.debug_struct("Node");
+        match &node.node_type {
+            NodeType::Leaf(value) => debug.field("leaf", &format_args!("{}", format.format(value))),
+            NodeType::Inner(hash) => debug.field("inner", &format_args!("{}", format.format(hash))),
+        };
+        let present: Vec<usize> = (0..MAX_ARITY)
+            .filter(|index| node.children[*index].is_some())
+            .collect();
+        debug
+            .field("children", &present)
+            .field("dirty", &node.dirty);
+        if let Some(values) = &node.values {
+            debug.field("values", &values.len());
+        }
+        if expand {
+            let children: Vec<_> = node
+                .children
+                .iter()
+                .flatten()
+                .map(|child| NodeSummary(child, *format))
+                .collect();
+            debug.field("child_nodes", &children);
+        }
+        debug.finish()
+    }
+}
+
+/// The `Debug` of `debug_with` in the `FrFormat::ShortDecimal` format
+impl<H: MerkleHasher<F>, F: PrimeField, M> fmt::Debug for Node<H, F, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.debug_with(FrFormat::default()).fmt(f)
+    }
+}
+
+impl<H: MerkleHasher<Fr>, M> Node<H, Fr, M> {
+    /// An inner node holding the bn254 circom empty inner hash, see `get_empty_inner_hash`
     pub fn new_empty_inner() -> Self {
+        Node::new_inner(*get_empty_inner_hash())
+    }
+
+    pub fn new_borrowed_empty_inner() -> NodeRef<H, Fr, M> {
+        Shared::new(NodeCell::new(Node::new_empty_inner()))
+    }
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField, M> Node<H, F, M> {
+    pub fn new_empty_leaf() -> Self {
         Node {
-            node_type: NodeType::Inner(*get_empty_inner_hash()),
-            left: None,
-            right: None,
+            node_type: NodeType::Leaf(F::zero()),
+            children: Default::default(),
+            dirty: false,
+            values: None,
+            meta: None,
         }
     }
 
-    pub fn new_leaf(value: Fr) -> Self {
+    pub fn new_leaf(value: F) -> Self {
         Node {
             node_type: NodeType::Leaf(value),
-            left: None,
-            right: None,
+            children: Default::default(),
+            dirty: false,
+            values: None,
+            meta: None,
         }
     }
 
-    pub fn new_inner(hash: InnerHash) -> Self {
+    pub fn new_inner(hash: InnerHash<F>) -> Self {
         Node {
             node_type: NodeType::Inner(hash),
-            left: None,
-            right: None,
+            children: Default::default(),
+            dirty: false,
+            values: None,
+            meta: None,
         }
     }
 
-    pub fn new_borrowed_inner(hash: InnerHash) -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Node::new_inner(hash)))
+    pub fn new_borrowed_inner(hash: InnerHash<F>) -> NodeRef<H, F, M> {
+        Shared::new(NodeCell::new(Node::new_inner(hash)))
     }
 
-    pub fn new_borrowed_leaf(value: Fr) -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Node::new_leaf(value)))
+    pub fn new_borrowed_leaf(value: F) -> NodeRef<H, F, M> {
+        Shared::new(NodeCell::new(Node::new_leaf(value)))
     }
 
-    pub fn new_borrowed_empty_leaf() -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Node::new_empty_leaf()))
+    pub fn new_borrowed_empty_leaf() -> NodeRef<H, F, M> {
+        Shared::new(NodeCell::new(Node::new_empty_leaf()))
     }
 
-    pub fn new_borrowed_empty_inner() -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Node::new_empty_inner()))
+    /// The left child of a binary node
+    pub fn left(&self) -> Option<&NodeRef<H, F, M>> {
+        self.children[0].as_ref()
     }
 
-    /// Check if the node is the last inner node (either left or right is a leaf)
-    pub fn is_last_inner(&self) -> bool {
-        let left_is_leaf = self
-            .left
-            .as_ref()
-            .map(|node| matches!(node.borrow().node_type, NodeType::Leaf(_)))
-            .unwrap_or(false);
+    /// The right child of a binary node
+    pub fn right(&self) -> Option<&NodeRef<H, F, M>> {
+        self.children[1].as_ref()
+    }
 
-        let right_is_leaf = self
-            .right
-            .as_ref()
-            .map(|node| matches!(node.borrow().node_type, NodeType::Leaf(_)))
-            .unwrap_or(false);
+    /// Check if the node is the last inner node (any of its children is a leaf)
+    ///
+    /// A child borrowed elsewhere fails with `PoseidonMerkleError::ConcurrentAccess`.
+    pub fn is_last_inner(&self) -> Result<bool, PoseidonMerkleError<F>> {
+        for node in self.children.iter().flatten() {
+            if let NodeType::Leaf(_) = node.checked_borrow()?.node_type {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
 
-        left_is_leaf || right_is_leaf
+    /// The summary `Debug` of the node with its hash or value in `format`
+    pub fn debug_with(&self, format: FrFormat) -> NodeDebug<'_, H, F, M> {
+        NodeDebug { node: self, format }
     }
 
-    /// Computes the hash of the node
+    /// Computes the hash of a binary node
     ///
     /// If it's an inner node, we first check if our left/right are inners or leaves
     /// If they are inners, we recursively compute their hash
     /// If they are leaves, we hash the raw values.
-    pub fn compute_hash(&self, hasher: &mut H) -> Result<InnerHash, PoseidonMerkleError> {
+    pub fn compute_hash(&self, hasher: &mut H) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        let empty_inner_hash =
+            hasher
+                .hash_children(&[F::zero(), F::zero()])
+                .map_err(PoseidonMerkleError::hasher(HashOperation::InnerNode {
+                    level: None,
+                }))?;
+
+        self.compute_hash_with(
+            hasher,
+            &HashConfig::new(LeafHashing::Raw, empty_inner_hash),
+            0,
+        )
+    }
+
+    /// Computes the hash the node at `level` contributes to its parent, following the tree conventions
+    ///
+    /// A childless inner node, the root of an empty tree, is the empty subtree of its level.
+    pub fn compute_hash_with(
+        &self,
+        hasher: &mut H,
+        config: &HashConfig<F>,
+        level: usize,
+    ) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
         match &self.node_type {
+            NodeType::Inner(_) if self.children.iter().all(Option::is_none) => {
+                Ok(config.empty_inner_hash_at(level))
+            }
             NodeType::Inner(_) => {
-                let is_last_inner = self.is_last_inner();
-
-                let left_hash_or_zero = self
-                    .left
-                    .as_ref()
-                    .map(|node| node.borrow().compute_hash(hasher))
-                    .transpose()?
-                    .unwrap_or(if is_last_inner {
-                        Fr::ZERO
-                    } else {
-                        *get_empty_inner_hash()
-                    });
-
-                let right_hash_or_zero = self
-                    .right
-                    .as_ref()
-                    .map(|node| node.borrow().compute_hash(hasher))
-                    .transpose()?
-                    .unwrap_or(if is_last_inner {
-                        Fr::ZERO
-                    } else {
-                        *get_empty_inner_hash()
-                    });
-
-                Ok(hasher.hash(&[left_hash_or_zero, right_hash_or_zero])?)
+                let empty_child_hash = self.empty_child_hash(hasher, config, level)?;
+                let child_hashes = self.children[..config.arity.children()]
+                    .iter()
+                    .map(|child| match child {
+                        Some(node) => {
+                            node.checked_borrow()?
+                                .compute_hash_with(hasher, config, level + 1)
+                        }
+                        None => Ok(empty_child_hash),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                config.inner_hash(hasher, level, &child_hashes)
             }
-            NodeType::Leaf(value) => {
-                // For leaf nodes, we return the value
-                Ok(*value)
+            NodeType::Leaf(value) => config.leaf_hash(hasher, value),
+        }
+    }
+
+    /// Hash of a missing child of the node at `level`, see `HashConfig::empty_hash_at`
+    ///
+    /// Without per-level empty hashes, that's an empty leaf below the last inner level and an empty
+    /// inner node above it.
+    fn empty_child_hash(
+        &self,
+        hasher: &mut H,
+        config: &HashConfig<F>,
+        level: usize,
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        config.empty_hash_at(hasher, level + 1, self.is_last_inner()?)
+    }
+
+    /// Hash the node at `level` from the cached hashes (or values) of its direct children
+    ///
+    /// Unlike `compute_hash`, this does not descend into the subtree, so the children must be fresh.
+    pub fn hash_children(
+        &self,
+        hasher: &mut H,
+        config: &HashConfig<F>,
+        level: usize,
+    ) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        match &self.node_type {
+            NodeType::Inner(_) => {
+                let empty_child_hash = self.empty_child_hash(hasher, config, level)?;
+                let child_hashes = self.children[..config.arity.children()]
+                    .iter()
+                    .map(|child| match child {
+                        Some(node) => match &node.checked_borrow()?.node_type {
+                            NodeType::Leaf(value) => config.leaf_hash(hasher, value),
+                            NodeType::Inner(hash) => Ok(*hash),
+                        },
+                        None => Ok(empty_child_hash),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                config.inner_hash(hasher, level, &child_hashes)
             }
+            NodeType::Leaf(value) => config.leaf_hash(hasher, value),
         }
     }
 
-    /// Invalidate and recalculate the hash of the node
-    pub fn recalculate_hash(&mut self, hasher: &mut H) -> Result<(), PoseidonMerkleError> {
+    /// Invalidate and recalculate the hash of the node at `level` from its (fresh) children
+    pub fn recalculate_hash(
+        &mut self,
+        hasher: &mut H,
+        config: &HashConfig<F>,
+        level: usize,
+    ) -> Result<(), PoseidonMerkleError<F>> {
         self.node_type = match &self.node_type {
             NodeType::Leaf(value) => NodeType::Leaf(*value),
-            NodeType::Inner(_) => NodeType::Inner(self.compute_hash(hasher)?),
+            NodeType::Inner(_) => NodeType::Inner(self.hash_children(hasher, config, level)?),
         };
+        self.dirty = false;
+        telemetry::record_rehash();
 
         Ok(())
     }
+
+    /// Mark the cached hash of the node as stale
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Bring the cached hash of the node at `level` up to date and return it
+    ///
+    /// Only dirty nodes are rehashed, clean subtrees are trusted as-is.
+    pub fn refresh_hash(
+        &mut self,
+        hasher: &mut H,
+        config: &HashConfig<F>,
+        level: usize,
+    ) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        if self.dirty {
+            // Clean children are only read
+            for child in self.children.iter().flatten() {
+                if child.checked_borrow()?.dirty {
+                    child
+                        .checked_borrow_mut()?
+                        .refresh_hash(hasher, config, level + 1)?;
+                }
+            }
+
+            self.recalculate_hash(hasher, config, level)?;
+        }
+
+        Ok(*self.node_type.data())
+    }
 }
 
-impl<H: PoseidonHasher<Fr>> PartialEq for Node<H> {
+impl<H: MerkleHasher<F>, F: PrimeField, M> PartialEq for Node<H, F, M> {
     fn eq(&self, other: &Self) -> bool {
         self.node_type == other.node_type
     }
 }
 
-impl<H: PoseidonHasher<Fr>> Eq for Node<H> {}
+impl<H: MerkleHasher<F>, F: PrimeField, M> Eq for Node<H, F, M> {}
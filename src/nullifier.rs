@@ -0,0 +1,135 @@
+use alloc::{collections::BTreeSet, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::{Field, PrimeField};
+use light_poseidon::Poseidon;
+use thiserror::Error;
+
+use crate::{
+    MerkleHasher, MerklePath, MerkleProof, NonMembershipProof, PoseidonMerkleError,
+    SparseMerkleTree,
+};
+
+/// Depth of the tree of a `NullifierSet`, one level per bit of a bn254 element
+pub const NULLIFIER_TREE_DEPTH: usize = Fr::MODULUS_BIT_SIZE as usize;
+
+/// Why a `NullifierSet` didn't insert a nullifier
+#[derive(Error, Debug, PartialEq)]
+pub enum NullifierError {
+    #[error("nullifier {nullifier} is already spent")]
+    AlreadyExists { nullifier: Fr },
+    #[error(transparent)]
+    Tree(#[from] PoseidonMerkleError),
+}
+
+/// Spent nullifiers, each inserted once
+///
+/// A nullifier is its own path in a tree of `NULLIFIER_TREE_DEPTH` levels, so no two nullifiers
+/// share a leaf, and a spent one holds 1. A spent nullifier is proven with a proof of 1 at its
+/// path, an unspent one with a non-membership proof of the zero leaf, and `verify_spent` and
+/// `verify_unspent` check the path and value of a proof against the nullifier.
+pub struct NullifierSet<H: MerkleHasher<Fr> = Poseidon<Fr>> {
+    tree: SparseMerkleTree<H>,
+}
+
+impl NullifierSet {
+    /// Create an empty set over the circom Poseidon hasher
+    pub fn new() -> Result<Self, PoseidonMerkleError> {
+        Ok(NullifierSet {
+            tree: SparseMerkleTree::new(NULLIFIER_TREE_DEPTH)?,
+        })
+    }
+}
+
+impl<H: MerkleHasher<Fr>> NullifierSet<H> {
+    /// The value held at the path of a spent nullifier
+    pub const SPENT: Fr = Fr::ONE;
+
+    pub fn new_with_hasher(hasher: H) -> Result<Self, PoseidonMerkleError> {
+        Ok(NullifierSet {
+            tree: SparseMerkleTree::new_with_hasher(NULLIFIER_TREE_DEPTH, hasher)?,
+        })
+    }
+
+    /// Spend a nullifier, failing if it already is
+    pub fn insert(&mut self, nullifier: Fr) -> Result<(), NullifierError> {
+        if self.is_spent(&nullifier)? {
+            return Err(NullifierError::AlreadyExists { nullifier });
+        }
+
+        Ok(self.tree.insert_at_path(&nullifier, &Self::SPENT)?)
+    }
+
+    /// Spend every nullifier, or none of them if one is spent already or comes twice
+    pub fn insert_batch(&mut self, nullifiers: &[Fr]) -> Result<(), NullifierError> {
+        let mut batch = BTreeSet::new();
+        for nullifier in nullifiers {
+            if !batch.insert(*nullifier) || self.is_spent(nullifier)? {
+                return Err(NullifierError::AlreadyExists {
+                    nullifier: *nullifier,
+                });
+            }
+        }
+
+        let entries: Vec<(MerklePath, Fr)> = nullifiers
+            .iter()
+            .map(|nullifier| (*nullifier, Self::SPENT))
+            .collect();
+        Ok(self.tree.insert_batch(&entries)?)
+    }
+
+    pub fn is_spent(&self, nullifier: &Fr) -> Result<bool, PoseidonMerkleError> {
+        Ok(self.tree.try_get_value(nullifier)? == Some(Self::SPENT))
+    }
+
+    /// Prove that a nullifier is spent, a proof of `SPENT` at its path
+    pub fn prove_spent(&self, nullifier: &Fr) -> Result<MerkleProof, PoseidonMerkleError> {
+        self.tree.generate_proof(nullifier)
+    }
+
+    /// Prove that a nullifier is unspent, failing with `PoseidonMerkleError::LeafNotEmpty` if it
+    /// is spent
+    pub fn prove_unspent(&self, nullifier: &Fr) -> Result<NonMembershipProof, PoseidonMerkleError> {
+        self.tree.generate_non_membership_proof(nullifier)
+    }
+
+    /// Verify a proof of `prove_spent` for `nullifier` against the current root
+    ///
+    /// A proof of another path or of another value than `SPENT` fails.
+    pub fn verify_spent(
+        &self,
+        nullifier: &Fr,
+        proof: &MerkleProof,
+    ) -> Result<bool, PoseidonMerkleError> {
+        if proof.merkle_path != *nullifier || proof.leaf_value != Self::SPENT {
+            return Ok(false);
+        }
+
+        self.tree.verify_proof(proof)
+    }
+
+    /// Verify a proof of `prove_unspent` for `nullifier` against the current root
+    ///
+    /// The path must hold the empty leaf of the tree: a proof of another path fails, and so does
+    /// a proof of a spent nullifier, whatever leaf value it claims.
+    pub fn verify_unspent(
+        &self,
+        nullifier: &Fr,
+        proof: &NonMembershipProof,
+    ) -> Result<bool, PoseidonMerkleError> {
+        if proof.proof.merkle_path != *nullifier {
+            return Ok(false);
+        }
+
+        self.tree.verify_non_membership_proof(proof)
+    }
+
+    pub fn root(&self) -> Result<Fr, PoseidonMerkleError> {
+        self.tree.root()
+    }
+
+    /// The tree of the set, read only so that nullifiers can't be removed
+    pub fn tree(&self) -> &SparseMerkleTree<H> {
+        &self.tree
+    }
+}
@@ -0,0 +1,83 @@
+use alloc::boxed::Box;
+use core::fmt;
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+
+use crate::{InnerHash, MerklePath};
+
+/// Receives the mutations of a tree once they are committed, see `SparseMerkleTree::set_observer`
+///
+/// The callbacks get no reference to the tree, so they can't mutate it while it is being updated.
+/// A failed operation leaves the tree untouched and is not reported. Every callback does nothing
+/// by default.
+pub trait TreeObserver<F: PrimeField = Fr> {
+    /// A leaf was set, `old` is its previous value or `None` if it was never materialized
+    ///
+    /// The path is canonical, see `SparseMerkleTree::canonicalize_path`, and a leaf of several
+    /// values reports their commitment.
+    fn on_insert(
+        &mut self,
+        _path: &MerklePath<F>,
+        _old: Option<F>,
+        _new: &F,
+        _new_root: &InnerHash<F>,
+    ) {
+    }
+
+    /// A leaf was deleted, its path now empty
+    fn on_delete(&mut self, _path: &MerklePath<F>, _old: Option<F>, _new_root: &InnerHash<F>) {}
+
+    /// The tree was cleared
+    fn on_clear(&mut self) {}
+}
+
+/// A boxed observer, `Send` and `Sync` with the `sync` feature like the rest of the tree
+#[cfg(not(feature = "sync"))]
+pub type BoxedObserver<F = Fr> = Box<dyn TreeObserver<F>>;
+#[cfg(feature = "sync")]
+pub type BoxedObserver<F = Fr> = Box<dyn TreeObserver<F> + Send + Sync>;
+
+/// The observer of a tree, if any
+///
+/// A clone of the tree starts without one, so an observer never hears of mutations on a copy.
+pub(crate) struct ObserverSlot<F: PrimeField>(Option<BoxedObserver<F>>);
+
+impl<F: PrimeField> ObserverSlot<F> {
+    pub(crate) fn new() -> Self {
+        ObserverSlot(None)
+    }
+
+    pub(crate) fn replace(
+        &mut self,
+        observer: Option<BoxedObserver<F>>,
+    ) -> Option<BoxedObserver<F>> {
+        core::mem::replace(&mut self.0, observer)
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Run `event` on the observer, if any
+    pub(crate) fn notify(&mut self, event: impl FnOnce(&mut dyn TreeObserver<F>)) {
+        if let Some(observer) = self.0.as_mut() {
+            event(observer.as_mut());
+        }
+    }
+}
+
+impl<F: PrimeField> Clone for ObserverSlot<F> {
+    fn clone(&self) -> Self {
+        ObserverSlot::new()
+    }
+}
+
+impl<F: PrimeField> fmt::Debug for ObserverSlot<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Some(TreeObserver)"),
+            None => write!(f, "None"),
+        }
+    }
+}
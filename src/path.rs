@@ -0,0 +1,88 @@
+use core::fmt;
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+
+use crate::Arity;
+
+/// Longest bit path that always fits in a field element, one bit less than the modulus
+pub const MAX_PATH_BITS: usize = Fr::MODULUS_BIT_SIZE as usize - 1;
+
+/// A path in the merkle tree as a field element
+///
+/// The path corresponds to the bits of the field element (F::into_bigint().to_bits_le())
+pub type MerklePath<F = Fr> = F;
+
+pub type Sibling<F = Fr> = F;
+
+/// A sibling tagged with its side of the node on the path, see `MerkleProof::nodes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofNode<F = Fr> {
+    /// The sibling is left of the node on the path, it comes first in the hash
+    Left(Sibling<F>),
+    /// The sibling is right of the node on the path
+    Right(Sibling<F>),
+}
+
+impl<F> ProofNode<F> {
+    pub fn hash(&self) -> &Sibling<F> {
+        match self {
+            ProofNode::Left(hash) | ProofNode::Right(hash) => hash,
+        }
+    }
+
+    pub fn is_left(&self) -> bool {
+        matches!(self, ProofNode::Left(_))
+    }
+}
+
+/// Poseidon of the children hashes
+pub type InnerHash<F = Fr> = F;
+
+/// Widest supported node, see `Arity`
+pub const MAX_ARITY: usize = 8;
+
+/// Which end of a path picks the child of the root
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BitOrder {
+    /// The lowest bits pick the child of the root, bit `i` decides level `i` of a binary tree
+    #[default]
+    LsbFirst,
+    /// The highest of the `path_bits()` bits pick the child of the root, the path is the leaf index
+    ///
+    /// Circuits splitting the index with `Num2Bits` and folding from the leaf up, as many circom
+    /// and Noir templates do, read paths in this order.
+    MsbFirst,
+}
+
+impl BitOrder {
+    /// Position in the path of the lowest bit of the child index at `level` of a `depth` tree
+    pub fn level_offset(self, level: usize, depth: usize, arity: Arity) -> usize {
+        match self {
+            BitOrder::LsbFirst => level * arity.bits(),
+            BitOrder::MsbFirst => (depth - 1 - level) * arity.bits(),
+        }
+    }
+}
+
+impl fmt::Display for BitOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitOrder::LsbFirst => write!(f, "LSB-first paths"),
+            BitOrder::MsbFirst => write!(f, "MSB-first paths"),
+        }
+    }
+}
+
+/// Index of the child taken by the `arity.bits()` bits of the path from `offset`, read little-endian
+pub(crate) fn path_index<F: PrimeField>(
+    merkle_path: &MerklePath<F>,
+    offset: usize,
+    arity: Arity,
+) -> usize {
+    let bits = merkle_path.into_bigint().to_bits_le();
+    (0..arity.bits())
+        .filter(|bit| bits[offset + bit])
+        .map(|bit| 1 << bit)
+        .sum()
+}
@@ -0,0 +1,66 @@
+use ark_bn254::Fr;
+use ark_ff::{BigInt, PrimeField};
+use light_poseidon::{PoseidonError, PoseidonHasher};
+use zkhash::{
+    ark_ff::{BigInt as ZkBigInt, PrimeField as ZkPrimeField, Zero},
+    fields::bn256::FpBN256,
+    poseidon2::{poseidon2::Poseidon2, poseidon2_instance_bn256::POSEIDON2_BN256_PARAMS},
+};
+
+/// Width of the bn254 Poseidon2 permutation
+const POSEIDON2_WIDTH: usize = 3;
+
+/// Poseidon2 over bn254 behind the `PoseidonHasher` interface of the tree
+///
+/// Uses the HorizenLabs reference parameters (width 3, 8 full and 56 partial rounds). Inputs are
+/// absorbed into a zeroed state and the first element of the permutation is the hash, so two
+/// inputs hash like the reference Merkle compression.
+#[derive(Debug, Clone)]
+pub struct Poseidon2Hasher {
+    permutation: Poseidon2<FpBN256>,
+}
+
+impl Poseidon2Hasher {
+    pub fn new() -> Self {
+        Poseidon2Hasher {
+            permutation: Poseidon2::new(&POSEIDON2_BN256_PARAMS),
+        }
+    }
+}
+
+impl Default for Poseidon2Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PoseidonHasher<Fr> for Poseidon2Hasher {
+    fn hash(&mut self, inputs: &[Fr]) -> Result<Fr, PoseidonError> {
+        if inputs.is_empty() {
+            return Err(PoseidonError::EmptyInput);
+        }
+        if inputs.len() >= POSEIDON2_WIDTH {
+            return Err(PoseidonError::InvalidNumberOfInputs {
+                inputs: inputs.len(),
+                max_limit: POSEIDON2_WIDTH - 1,
+                width: POSEIDON2_WIDTH,
+            });
+        }
+
+        let mut state = vec![FpBN256::zero(); POSEIDON2_WIDTH];
+        for (slot, input) in state.iter_mut().zip(inputs) {
+            *slot = to_zkhash(input);
+        }
+
+        Ok(from_zkhash(&self.permutation.permutation(&state)[0]))
+    }
+}
+
+/// Both crates use the same modulus, only the arkworks versions differ
+fn to_zkhash(value: &Fr) -> FpBN256 {
+    FpBN256::from_bigint(ZkBigInt(value.into_bigint().0)).expect("same bn254 modulus")
+}
+
+fn from_zkhash(value: &FpBN256) -> Fr {
+    Fr::from_bigint(BigInt(value.into_bigint().0)).expect("same bn254 modulus")
+}
@@ -1,25 +1,59 @@
+use crate::{
+    get_empty_inner_hash_for, hash_leaf_values, hasher::as_field, path::path_index, telemetry,
+    verify, Arity, BitOrder, EmptySubtrees, HashConfig, HashFamily, HashOperation, HashingScheme,
+    InnerHash, LeafHashing, MerkleHasher, MerklePath, PoseidonMerkleError, ProofError, ProofNode,
+    Sibling,
+};
+use alloc::{
+    collections::{btree_map::Entry, BTreeMap},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
 use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
 
-use crate::{Hasher, InnerHash, MerklePath, PoseidonMerkleError, Sibling, SparseMerkleTree};
-
+/// A proof of a leaf value against a root
+///
+/// It is plain data, `Send` and `Sync`, so it can be handed to other threads and async tasks.
 #[derive(Debug, Clone)]
-pub struct MerkleProof {
+pub struct MerkleProof<F: PrimeField = Fr> {
     /// The siblings of the proof
-    pub siblings: Vec<Sibling>,
+    pub siblings: Vec<Sibling<F>>,
     /// The path of the proof
-    pub merkle_path: MerklePath,
+    pub merkle_path: MerklePath<F>,
     /// The leaf value of the proof
-    pub leaf_value: Fr,
+    pub leaf_value: F,
     /// The root hash of the proof
-    pub root_hash: InnerHash,
+    pub root_hash: InnerHash<F>,
+    /// How the tree the proof comes from hashes its leaves
+    pub leaf_hashing: LeafHashing,
+    /// The hash function of the inner nodes of the tree the proof comes from
+    pub hash_family: HashFamily,
+    /// Number of children of an inner node in the tree the proof comes from
+    pub arity: Arity,
+    /// Whether the tree the proof comes from hashes its leaves apart from its inner nodes
+    pub scheme: HashingScheme,
+    /// Fingerprint of the hasher parameters of the tree the proof comes from, if recorded
+    pub params_fingerprint: Option<F>,
+    /// The values the leaf value commits to, for trees holding several values per leaf
+    pub leaf_preimage: Option<Vec<F>>,
+    /// Which end of the path picks the child of the root
+    pub bit_order: BitOrder,
+    /// How missing inner nodes hash in the tree the proof comes from
+    pub empty_subtrees: EmptySubtrees,
 }
 
-impl MerkleProof {
+impl<F: PrimeField> MerkleProof<F> {
+    /// Build a proof from its raw parts without any validation
+    ///
+    /// This is meant for low-level use, prefer `try_new` for proofs coming from untrusted input.
     pub fn new(
-        siblings: Vec<Sibling>,
-        merkle_path: MerklePath,
-        leaf_value: Fr,
-        root_hash: InnerHash,
+        siblings: Vec<Sibling<F>>,
+        merkle_path: MerklePath<F>,
+        leaf_value: F,
+        root_hash: InnerHash<F>,
     ) -> Self {
         Self {
             siblings,
@@ -27,34 +61,603 @@ impl MerkleProof {
 
             leaf_value,
             root_hash,
+            leaf_hashing: LeafHashing::default(),
+            hash_family: HashFamily::default(),
+            arity: Arity::default(),
+            scheme: HashingScheme::default(),
+            params_fingerprint: None,
+            leaf_preimage: None,
+            bit_order: BitOrder::default(),
+            empty_subtrees: EmptySubtrees::default(),
+        }
+    }
+
+    /// Set how the leaf value is hashed before being folded with the siblings
+    pub fn with_leaf_hashing(self, leaf_hashing: LeafHashing) -> Self {
+        Self {
+            leaf_hashing,
+            ..self
+        }
+    }
+
+    /// Set the hash function the siblings and the root were computed with
+    pub fn with_hash_family(self, hash_family: HashFamily) -> Self {
+        Self {
+            hash_family,
+            ..self
+        }
+    }
+
+    /// Set whether the leaf is hashed apart from the inner nodes, see `HashingScheme`
+    pub fn with_scheme(self, scheme: HashingScheme) -> Self {
+        Self { scheme, ..self }
+    }
+
+    /// Record the fingerprint of the hasher parameters, see `MerkleHasher::params_fingerprint`
+    pub fn with_params_fingerprint(self, params_fingerprint: F) -> Self {
+        Self {
+            params_fingerprint: Some(params_fingerprint),
+            ..self
         }
     }
 
+    /// Set which end of the path picks the child of the root, see `BitOrder`
+    pub fn with_bit_order(self, bit_order: BitOrder) -> Self {
+        Self { bit_order, ..self }
+    }
+
+    /// Set how missing inner nodes hash, see `EmptySubtrees`
+    ///
+    /// Only non-membership proofs depend on it, they fold from an empty subtree.
+    pub fn with_empty_subtrees(self, empty_subtrees: EmptySubtrees) -> Self {
+        Self {
+            empty_subtrees,
+            ..self
+        }
+    }
+
+    /// Attach the values the leaf value commits to, see `hash_leaf_values`
+    pub fn with_leaf_preimage(self, leaf_preimage: Vec<F>) -> Self {
+        Self {
+            leaf_preimage: Some(leaf_preimage),
+            ..self
+        }
+    }
+
+    /// Build a proof from its raw parts, rejecting structurally invalid ones
+    ///
+    /// The siblings must be non-empty and no more than a path can address, the path must not have
+    /// bits set beyond the proof depth, and over bn254 the leaf value can't be the empty inner hash
+    /// sentinel.
+    pub fn try_new(
+        siblings: Vec<Sibling<F>>,
+        merkle_path: MerklePath<F>,
+        leaf_value: F,
+        root_hash: InnerHash<F>,
+    ) -> Result<Self, ProofError> {
+        let proof = Self::new(siblings, merkle_path, leaf_value, root_hash);
+        proof.validate()?;
+
+        Ok(proof)
+    }
+
+    /// Set the arity of the tree the proof comes from, checking the proof like `try_new` does
+    ///
+    /// The siblings must then come in groups of `arity - 1` per level.
+    pub fn try_with_arity(self, arity: Arity) -> Result<Self, ProofError> {
+        let proof = Self { arity, ..self };
+        proof.validate()?;
+
+        Ok(proof)
+    }
+
+    /// Structural checks shared by `try_new` and `try_with_arity`
+    fn validate(&self) -> Result<(), ProofError> {
+        let per_level = self.arity.children() - 1;
+        let max = self.max_siblings();
+        let len = self.siblings.len();
+        if len == 0 {
+            return Err(ProofError::NoSiblings);
+        }
+        if len > max {
+            return Err(ProofError::TooManySiblings { len, max });
+        }
+        if !len.is_multiple_of(per_level) {
+            return Err(ProofError::UnevenSiblings { len, per_level });
+        }
+        let depth = self.depth();
+        if self.merkle_path.into_bigint().to_bits_le()[depth * self.arity.bits()..].contains(&true)
+        {
+            return Err(ProofError::NonCanonicalPath { depth });
+        }
+        if as_field::<F, Fr>(&self.leaf_value) == Some(get_empty_inner_hash_for(self.arity)) {
+            return Err(ProofError::EmptyNodeHash);
+        }
+
+        Ok(())
+    }
+
+    /// Number of siblings of the deepest proof a path can address
+    fn max_siblings(&self) -> usize {
+        F::MODULUS_BIT_SIZE as usize / self.arity.bits() * (self.arity.children() - 1)
+    }
+
+    /// Number of levels covered by the proof
+    pub fn depth(&self) -> usize {
+        self.siblings.len() / (self.arity.children() - 1)
+    }
+
+    /// The siblings at `level` in child index order, `arity - 1` of them
+    pub fn level_siblings(&self, level: usize) -> &[Sibling<F>] {
+        let per_level = self.arity.children() - 1;
+        &self.siblings[level * per_level..(level + 1) * per_level]
+    }
+
+    /// Index among its siblings of the node on the path at `level`
+    pub fn position(&self, level: usize) -> usize {
+        let offset = self.bit_order.level_offset(level, self.depth(), self.arity);
+        path_index(&self.merkle_path, offset, self.arity)
+    }
+
+    /// The siblings from the leaf up, each tagged with its side of the node on the path
+    ///
+    /// A level gives its `arity - 1` siblings in child index order, those left of the node on the
+    /// path first. `verify::fold_nodes_with_scheme` folds them into the root without the path or
+    /// the bit order, like `verify_proof` does from the path bits.
+    pub fn nodes(&self) -> Vec<ProofNode<F>> {
+        (0..self.depth())
+            .rev()
+            .flat_map(|level| {
+                let position = self.position(level);
+                self.level_siblings(level)
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, sibling)| {
+                        if index < position {
+                            ProofNode::Left(*sibling)
+                        } else {
+                            ProofNode::Right(*sibling)
+                        }
+                    })
+            })
+            .collect()
+    }
+
+    /// The inputs of a circom inclusion template as snarkjs reads them, a JSON object of decimal
+    /// strings
+    ///
+    /// `leaf`, `root`, then `pathIndices` and `siblings` with one entry per level, in the order
+    /// `Num2Bits` splits the path: the root level first for `BitOrder::LsbFirst`, the leaf level
+    /// first for `BitOrder::MsbFirst`. The latter is the layout of templates folding from the leaf
+    /// like zk-kit's `MerkleTreeInclusionProof`. A level of a wider tree has its child index as
+    /// path index and its `arity - 1` siblings in index order.
+    pub fn to_circom_inputs(&self) -> String {
+        let mut levels: Vec<usize> = (0..self.depth()).collect();
+        if self.bit_order == BitOrder::MsbFirst {
+            levels.reverse();
+        }
+
+        let path_indices = levels
+            .iter()
+            .map(|level| format!("\"{}\"", self.position(*level)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let siblings = levels
+            .iter()
+            .flat_map(|level| self.level_siblings(*level))
+            .map(|sibling| format!("\"{sibling}\""))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            "{{\"leaf\":\"{}\",\"root\":\"{}\",\"pathIndices\":[{}],\"siblings\":[{}]}}",
+            self.leaf_value, self.root_hash, path_indices, siblings,
+        )
+    }
+
     /// Verify the proof bottom up
-    pub fn verify_proof(&self, hasher: &mut Hasher) -> Result<bool, PoseidonMerkleError> {
-        // Start with the leaf value
-        let mut current_hash = self.leaf_value;
-
-        // Traverse the path from bottom to top
-        // We need to iterate in reverse order (from leaf to root)
-        // but keep the correct path bit positions
-        let siblings_len = self.siblings.len();
-        for (idx, sibling) in self.siblings.iter().rev().enumerate() {
-            let position = siblings_len - idx - 1;
-            let go_right = SparseMerkleTree::get_path_bit(&self.merkle_path, position);
-            let (left, right) = if go_right {
-                (*sibling, current_hash)
-            } else {
-                (current_hash, *sibling)
+    ///
+    /// A hasher of another family than the proof is rejected with `ProofError::HashFamilyMismatch`,
+    /// over bn254 a hasher not reporting its family is identified from its hash of zeros. When the
+    /// proof records a parameters fingerprint, a hasher with another one is rejected with
+    /// `ProofError::ParamsFingerprintMismatch`. A proof carrying a leaf preimage only verifies if
+    /// the preimage hashes to the leaf value.
+    pub fn verify_proof<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        self.check_verifiable(hasher)?;
+        if let Some(preimage) = &self.leaf_preimage {
+            if hash_leaf_values(preimage)? != self.leaf_value {
+                telemetry::record_verification(false);
+                return Ok(false);
+            }
+        }
+
+        // Start with the leaf contribution and fold it up to the root
+        let leaf = self.leaf_hashing.leaf_hash(&self.leaf_value)?;
+        let leaf = self.scheme.leaf_hash(hasher, leaf, self.arity)?;
+        let root = self.fold_from(hasher, &leaf, self.depth())?;
+
+        let valid = root == self.root_hash;
+        telemetry::record_verification(valid);
+        Ok(valid)
+    }
+
+    /// The structural, family and fingerprint checks run before folding the proof
+    fn check_verifiable<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let per_level = self.arity.children() - 1;
+        if !self.siblings.len().is_multiple_of(per_level) {
+            return Err(ProofError::UnevenSiblings {
+                len: self.siblings.len(),
+                per_level,
+            }
+            .into());
+        }
+        // An unchecked proof may have more levels than the path has bits to pick a child with
+        if self.siblings.len() > self.max_siblings() {
+            return Err(ProofError::TooManySiblings {
+                len: self.siblings.len(),
+                max: self.max_siblings(),
+            }
+            .into());
+        }
+        check_hash_family(hasher, self.hash_family, self.arity)?;
+        check_params_fingerprint(hasher, self.params_fingerprint, self.arity)?;
+
+        Ok(())
+    }
+
+    /// Fold the hash of the node on the path at `level` up to the root with the siblings above it
+    fn fold_from<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        hash: &F,
+        level: usize,
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        let per_level = self.arity.children() - 1;
+        verify::fold_path_with_scheme(
+            hasher,
+            hash,
+            &self.level_path_bits(),
+            &self.siblings[..level * per_level],
+            self.arity,
+            self.scheme,
+            self.hash_family,
+        )
+    }
+
+    /// Verify the proof against a trusted root rather than the one it carries
+    ///
+    /// A proof carrying another root is invalid without hashing, otherwise this is `verify_proof`.
+    pub fn verify_against<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        root: &InnerHash<F>,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        if self.root_hash != *root {
+            telemetry::record_verification(false);
+            return Ok(false);
+        }
+
+        self.verify_proof(hasher)
+    }
+
+    /// The bits of the path level by level from the root, `arity.bits()` per level
+    fn level_path_bits(&self) -> Vec<bool> {
+        (0..self.depth())
+            .flat_map(|level| {
+                let position = self.position(level);
+                (0..self.arity.bits()).map(move |bit| position >> bit & 1 == 1)
+            })
+            .collect()
+    }
+}
+
+/// Reject a hasher reporting, or over bn254 recognized as, another family than a proof
+fn check_hash_family<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    expected: HashFamily,
+    arity: Arity,
+) -> Result<(), ProofError> {
+    let actual = match hasher.hash_family() {
+        Some(family) => Some(family),
+        None if as_field::<F, Fr>(&F::zero()).is_none() => return Ok(()),
+        None => {
+            // A hasher failing the probe fails again in the fold, which reports the level
+            let Ok(empty_inner_hash) = hasher.empty_hash(arity) else {
+                return Ok(());
             };
+            as_field::<F, Fr>(&empty_inner_hash).and_then(|hash| HashFamily::identify(hash, arity))
+        }
+    };
+    match actual {
+        Some(actual) if actual != expected => {
+            Err(ProofError::HashFamilyMismatch { expected, actual })
+        }
+        _ => Ok(()),
+    }
+}
 
-            current_hash = hasher.hash(&[left, right])?;
-            println!(
-                "current_hash: ({:?}, {:?}) = {:?}",
-                left, right, current_hash
-            );
+/// Reject a hasher whose parameters fingerprint differs from the one recorded in a proof
+fn check_params_fingerprint<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    expected: Option<F>,
+    arity: Arity,
+) -> Result<(), ProofError> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    // Like the family probe, a failing hasher is left to the fold
+    match hasher.params_fingerprint(arity) {
+        Ok(actual) if actual != expected => Err(ProofError::ParamsFingerprintMismatch),
+        _ => Ok(()),
+    }
+}
+
+/// A proof that a path holds the empty leaf, see `SparseMerkleTree::generate_non_membership_proof`
+///
+/// The path leads into an empty subtree at `empty_level`, which is the depth when only the leaf is
+/// empty. The proof is verified from the hash of an empty subtree of that level, folded with the
+/// siblings above it, so it holds for paths no node was ever created on.
+#[derive(Debug, Clone)]
+pub struct NonMembershipProof<F: PrimeField = Fr> {
+    /// The proof of the empty leaf, its siblings below `empty_level` being empty subtrees
+    pub proof: MerkleProof<F>,
+    /// Level of the highest empty subtree on the path, the root being level 0
+    pub empty_level: usize,
+}
+
+impl<F: PrimeField> NonMembershipProof<F> {
+    /// Verify that the path holds the zero leaf under the root of the proof
+    ///
+    /// The hasher is checked like in `MerkleProof::verify_proof`. A proof whose leaf value isn't
+    /// zero fails, a path holding another value doesn't lead into an empty subtree either, so no
+    /// proof of it verifies. Trees with another empty leaf are verified with
+    /// `verify_proof_with_empty_leaf`.
+    pub fn verify_proof<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        self.verify_proof_with_empty_leaf(hasher, &F::ZERO)
+    }
+
+    /// Verify that the path holds `empty_leaf` under the root of the proof
+    ///
+    /// The empty leaf is the verifier's, not the leaf value the proof claims: a proof of another
+    /// value fails before it is folded.
+    pub fn verify_proof_with_empty_leaf<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        empty_leaf: &F,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        let proof = &self.proof;
+        proof.check_verifiable(hasher)?;
+        let depth = proof.depth();
+        if self.empty_level > depth {
+            return Err(ProofError::EmptyLevelOutOfRange {
+                level: self.empty_level,
+                depth,
+            }
+            .into());
+        }
+
+        let empty_inner_hash =
+            hasher
+                .empty_hash(proof.arity)
+                .map_err(PoseidonMerkleError::hasher(HashOperation::InnerNode {
+                    level: Some(self.empty_level),
+                }))?;
+        let mut config = HashConfig::new(proof.leaf_hashing, empty_inner_hash)
+            .with_family(proof.hash_family)
+            .with_arity(proof.arity)
+            .with_scheme(proof.scheme)
+            .with_empty_subtrees(proof.empty_subtrees);
+        if proof.leaf_value != *empty_leaf {
+            telemetry::record_verification(false);
+            return Ok(false);
+        }
+        config.empty_leaf = *empty_leaf;
+        if config.has_level_empty_hashes() {
+            config.empty_hashes = config.empty_hash_chain(hasher, depth)?;
         }
+        let empty = config.empty_hash_at(hasher, self.empty_level, self.empty_level == depth)?;
+        let root = proof.fold_from(hasher, &empty, self.empty_level)?;
 
-        Ok(current_hash == self.root_hash)
+        let valid = root == proof.root_hash;
+        telemetry::record_verification(valid);
+        Ok(valid)
     }
+
+    /// Verify the proof against a trusted root rather than the one it carries
+    pub fn verify_against<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        root: &InnerHash<F>,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        if self.proof.root_hash != *root {
+            telemetry::record_verification(false);
+            return Ok(false);
+        }
+
+        self.verify_proof(hasher)
+    }
+}
+
+/// A proof of several leaves against one root, each sibling hash given once
+///
+/// The leaves are folded up together, level by level, and the hashes are the children the leaves
+/// don't give, in the order the fold needs them: from the leaf level up, by path prefix within a
+/// level and by child index within a node. Siblings shared by several paths, most of the upper
+/// levels for leaves close to each other, are thus given and hashed once.
+#[derive(Debug, Clone)]
+pub struct MultiProof<F: PrimeField = Fr> {
+    /// The paths and values of the leaves, ordered by path prefix from the root
+    pub leaves: Vec<(MerklePath<F>, F)>,
+    /// The children missing from the fold of the leaves
+    pub hashes: Vec<Sibling<F>>,
+    /// The root hash of the proof
+    pub root_hash: InnerHash<F>,
+    /// Number of levels of the tree the proof comes from
+    pub depth: usize,
+    /// How the tree the proof comes from hashes its leaves
+    pub leaf_hashing: LeafHashing,
+    /// The hash function of the inner nodes of the tree the proof comes from
+    pub hash_family: HashFamily,
+    /// Number of children of an inner node in the tree the proof comes from
+    pub arity: Arity,
+    /// Whether the tree the proof comes from hashes its leaves apart from its inner nodes
+    pub scheme: HashingScheme,
+    /// Fingerprint of the hasher parameters of the tree the proof comes from, if recorded
+    pub params_fingerprint: Option<F>,
+    /// Which end of the paths picks the child of the root
+    pub bit_order: BitOrder,
+}
+
+impl<F: PrimeField> MultiProof<F> {
+    /// Verify every leaf at once against the root of the proof
+    ///
+    /// The hasher is checked like in `MerkleProof::verify_proof`. A proof whose hashes run out
+    /// before the root, or are left over after it, doesn't verify. Two leaves at the same path
+    /// with different values fail with `ProofError::ConflictingLeaves`.
+    pub fn verify<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        self.verify_leaves(hasher, &self.leaves, &self.root_hash)
+    }
+
+    /// Fold `leaves` with the hashes of the proof and compare with `root`
+    fn verify_leaves<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        leaves: &[(MerklePath<F>, F)],
+        root: &InnerHash<F>,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        let per_level = self.arity.children() - 1;
+        let max = F::MODULUS_BIT_SIZE as usize / self.arity.bits();
+        if self.depth == 0 {
+            return Err(ProofError::NoSiblings.into());
+        }
+        if self.depth > max {
+            return Err(ProofError::TooManySiblings {
+                len: self.depth * per_level,
+                max: max * per_level,
+            }
+            .into());
+        }
+        check_hash_family(hasher, self.hash_family, self.arity)?;
+        check_params_fingerprint(hasher, self.params_fingerprint, self.arity)?;
+
+        let valid = self.fold(hasher, leaves)? == Some(*root);
+        telemetry::record_verification(valid);
+        Ok(valid)
+    }
+
+    /// Verify the proof against a trusted root rather than the one it carries
+    pub fn verify_against<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        root: &InnerHash<F>,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        if self.root_hash != *root {
+            telemetry::record_verification(false);
+            return Ok(false);
+        }
+
+        self.verify(hasher)
+    }
+
+    /// The child index taken at every level by a path, from the root
+    pub(crate) fn digits(&self, merkle_path: &MerklePath<F>) -> Vec<usize> {
+        (0..self.depth)
+            .map(|level| {
+                let offset = self.bit_order.level_offset(level, self.depth, self.arity);
+                path_index(merkle_path, offset, self.arity)
+            })
+            .collect()
+    }
+
+    /// Fold the leaves up to the root, `None` if the hashes don't match the leaves
+    fn fold<H: MerkleHasher<F> + ?Sized>(
+        &self,
+        hasher: &mut H,
+        leaves: &[(MerklePath<F>, F)],
+    ) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        let mut nodes = BTreeMap::new();
+        for (merkle_path, value) in leaves {
+            if merkle_path.into_bigint().to_bits_le()[self.depth * self.arity.bits()..]
+                .contains(&true)
+            {
+                return Err(ProofError::NonCanonicalPath { depth: self.depth }.into());
+            }
+            let leaf = self.leaf_hashing.leaf_hash(value)?;
+            let leaf = self.scheme.leaf_hash(hasher, leaf, self.arity)?;
+            match nodes.entry(self.digits(merkle_path)) {
+                Entry::Vacant(entry) => {
+                    entry.insert(leaf);
+                }
+                Entry::Occupied(entry) if *entry.get() != leaf => {
+                    return Err(ProofError::ConflictingLeaves.into());
+                }
+                Entry::Occupied(_) => {}
+            }
+        }
+
+        let mut hashes = self.hashes.iter();
+        for level in (0..self.depth).rev() {
+            let mut parents = BTreeMap::new();
+            for (mut digits, hash) in nodes {
+                let digit = digits.pop().expect("a node below the root has a digit");
+                parents
+                    .entry(digits)
+                    .or_insert_with(|| vec![None; self.arity.children()])[digit] = Some(hash);
+            }
+
+            nodes = BTreeMap::new();
+            for (prefix, children) in parents {
+                let Some(inputs) = children
+                    .into_iter()
+                    .map(|child| child.or_else(|| hashes.next().copied()))
+                    .collect::<Option<Vec<F>>>()
+                else {
+                    return Ok(None);
+                };
+                let hash = self
+                    .scheme
+                    .inner_hash(hasher, self.hash_family, level, &inputs)?;
+                nodes.insert(prefix, hash);
+            }
+        }
+        if hashes.next().is_some() {
+            return Ok(None);
+        }
+
+        Ok(nodes.remove(&Vec::new()))
+    }
+}
+
+/// Verify claimed leaf values against a trusted root, with the hashes of a multiproof of them
+///
+/// The claims are leaf indices, the paths `SparseMerkleTree::insert_at_index` takes, with their
+/// values. They stand for the leaves of the multiproof, which must prove the same indices, and are
+/// folded with its hashes and conventions whatever their order. A repeated index must claim the
+/// same value, otherwise the claims fail with `ProofError::ConflictingLeaves`. No tree is needed,
+/// and each hash on the way to the root is computed once.
+pub fn verify_leaves_against_root<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    root: &InnerHash<F>,
+    claims: &[(u64, F)],
+    multiproof: &MultiProof<F>,
+    hasher: &mut H,
+) -> Result<bool, PoseidonMerkleError<F>> {
+    let leaves: Vec<_> = claims
+        .iter()
+        .map(|(index, value)| (F::from(*index), *value))
+        .collect();
+
+    multiproof.verify_leaves(hasher, &leaves, root)
 }
@@ -0,0 +1,248 @@
+//! Protobuf messages of proofs, tree snapshots and proof requests for gRPC services
+//!
+//! The messages derive `prost::Message`, so no `.proto` compilation is needed on the Rust side,
+//! and `proto/merkle_poseidon.proto` describes them for other languages. Field elements are
+//! 32-byte big-endian words, like Solidity's `bytes32`, and must be below the modulus. Proofs and
+//! snapshots are of binary circom Poseidon trees; proofs have the default conventions since the
+//! message doesn't carry them.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use thiserror::Error;
+
+use crate::{
+    eth::{from_bytes32, to_bytes32},
+    Arity, BitOrder, HashFamily, HashingScheme, LeafHashing, MerklePath, MerkleProof,
+    PoseidonMerkleError, ProofError, SparseMerkleTree, MAX_PATH_BITS,
+};
+
+/// A proof of a binary circom Poseidon tree, see `MerkleProof`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Proof {
+    #[prost(bytes = "vec", tag = "1")]
+    pub leaf: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub root: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub path: Vec<u8>,
+    /// One per level, from the root down
+    #[prost(bytes = "vec", repeated, tag = "4")]
+    pub siblings: Vec<Vec<u8>>,
+    /// Number of levels, which the siblings must match
+    #[prost(uint32, tag = "5")]
+    pub depth: u32,
+}
+
+/// A tree as the bytes of `SparseMerkleTree::to_snapshot_bytes`, with its depth and root
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TreeSnapshot {
+    #[prost(uint32, tag = "1")]
+    pub depth: u32,
+    #[prost(bytes = "vec", tag = "2")]
+    pub root: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub snapshot: Vec<u8>,
+}
+
+/// The proof of the leaf at `index` of a binary tree of `depth` levels
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ProofRequest {
+    #[prost(uint64, tag = "1")]
+    pub index: u64,
+    #[prost(uint32, tag = "2")]
+    pub depth: u32,
+}
+
+/// Why a message doesn't convert
+#[derive(Error, Debug, PartialEq)]
+pub enum ProtoError {
+    #[error("{field} has {len} bytes, a field element takes 32")]
+    ByteLength { field: String, len: usize },
+    #[error("{field} is not below the modulus")]
+    NonCanonical { field: String },
+    #[error("proof of depth {depth} has {siblings} siblings")]
+    SiblingCount { depth: usize, siblings: usize },
+    #[error("depth {depth} is not between 1 and {max}")]
+    InvalidDepth { depth: u32, max: usize },
+    #[error("leaf index {index} is out of range for a tree of depth {depth}")]
+    IndexOutOfRange { index: u64, depth: usize },
+    #[error("the tree has depth {actual}, the message says {expected}")]
+    DepthMismatch { expected: usize, actual: usize },
+    #[error("only proofs of binary Poseidon trees with the default conventions have a message")]
+    UnsupportedProof,
+    #[error(transparent)]
+    Proof(#[from] ProofError),
+    #[error(transparent)]
+    Tree(#[from] PoseidonMerkleError),
+}
+
+/// The field element of a 32-byte word, `field` naming it in errors
+fn element(field: impl ToString, bytes: &[u8]) -> Result<Fr, ProtoError> {
+    let word: &[u8; 32] = bytes.try_into().map_err(|_| ProtoError::ByteLength {
+        field: field.to_string(),
+        len: bytes.len(),
+    })?;
+
+    from_bytes32(word).map_err(|_| ProtoError::NonCanonical {
+        field: field.to_string(),
+    })
+}
+
+/// A message depth as a number of levels, at least one and at most a path can address
+fn levels(depth: u32) -> Result<usize, ProtoError> {
+    match depth as usize {
+        levels @ 1..=MAX_PATH_BITS => Ok(levels),
+        _ => Err(ProtoError::InvalidDepth {
+            depth,
+            max: MAX_PATH_BITS,
+        }),
+    }
+}
+
+impl TryFrom<Proof> for MerkleProof {
+    type Error = ProtoError;
+
+    /// Check every word and the number of siblings, then the proof like `MerkleProof::try_new`
+    fn try_from(message: Proof) -> Result<Self, Self::Error> {
+        let depth = levels(message.depth)?;
+        if message.siblings.len() != depth {
+            return Err(ProtoError::SiblingCount {
+                depth,
+                siblings: message.siblings.len(),
+            });
+        }
+        let siblings = message
+            .siblings
+            .iter()
+            .enumerate()
+            .map(|(level, sibling)| element(format!("siblings[{level}]"), sibling))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(MerkleProof::try_new(
+            siblings,
+            element("path", &message.path)?,
+            element("leaf", &message.leaf)?,
+            element("root", &message.root)?,
+        )?)
+    }
+}
+
+impl TryFrom<&MerkleProof> for Proof {
+    type Error = ProtoError;
+
+    /// Fails with `ProtoError::UnsupportedProof` for a proof the message can't describe
+    fn try_from(proof: &MerkleProof) -> Result<Self, Self::Error> {
+        let default = proof.arity == Arity::Binary
+            && proof.hash_family == HashFamily::Poseidon
+            && proof.leaf_hashing == LeafHashing::Raw
+            && proof.scheme == HashingScheme::Legacy
+            && proof.bit_order == BitOrder::LsbFirst
+            && proof.leaf_preimage.is_none();
+        if !default {
+            return Err(ProtoError::UnsupportedProof);
+        }
+
+        Ok(Proof {
+            leaf: to_bytes32(&proof.leaf_value).to_vec(),
+            root: to_bytes32(&proof.root_hash).to_vec(),
+            path: to_bytes32(&proof.merkle_path).to_vec(),
+            siblings: proof
+                .siblings
+                .iter()
+                .map(|sibling| to_bytes32(sibling).to_vec())
+                .collect(),
+            depth: proof.depth() as u32,
+        })
+    }
+}
+
+impl TryFrom<TreeSnapshot> for SparseMerkleTree<Poseidon<Fr>> {
+    type Error = ProtoError;
+
+    /// Rebuild the tree with `from_snapshot_bytes`, checking it has the depth and root of the message
+    fn try_from(message: TreeSnapshot) -> Result<Self, Self::Error> {
+        let expected = levels(message.depth)?;
+        let root = element("root", &message.root)?;
+        let tree = SparseMerkleTree::from_snapshot_bytes(&message.snapshot)?;
+        if tree.depth != expected {
+            return Err(ProtoError::DepthMismatch {
+                expected,
+                actual: tree.depth,
+            });
+        }
+
+        let actual = tree.root()?;
+        if actual != root {
+            return Err(PoseidonMerkleError::RootMismatch {
+                expected: root,
+                actual,
+            }
+            .into());
+        }
+
+        Ok(tree)
+    }
+}
+
+impl TryFrom<&SparseMerkleTree<Poseidon<Fr>>> for TreeSnapshot {
+    type Error = ProtoError;
+
+    /// Snapshot the tree, refreshing its root first
+    fn try_from(tree: &SparseMerkleTree<Poseidon<Fr>>) -> Result<Self, Self::Error> {
+        let snapshot = tree.to_snapshot_bytes()?;
+
+        Ok(TreeSnapshot {
+            depth: u32::try_from(tree.depth).expect("snapshots have at most MAX_PATH_BITS levels"),
+            root: to_bytes32(&tree.root()?).to_vec(),
+            snapshot,
+        })
+    }
+}
+
+/// A checked `ProofRequest`: a leaf index in range for a binary tree of `depth` levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeafRequest {
+    pub index: u64,
+    pub depth: usize,
+}
+
+impl LeafRequest {
+    /// The path of the leaf in a binary tree with `BitOrder::LsbFirst`, the index itself
+    pub fn path(&self) -> MerklePath {
+        Fr::from(self.index)
+    }
+}
+
+impl TryFrom<ProofRequest> for LeafRequest {
+    type Error = ProtoError;
+
+    fn try_from(message: ProofRequest) -> Result<Self, Self::Error> {
+        let depth = levels(message.depth)?;
+        if depth < 64 && message.index >> depth != 0 {
+            return Err(ProtoError::IndexOutOfRange {
+                index: message.index,
+                depth,
+            });
+        }
+
+        Ok(LeafRequest {
+            index: message.index,
+            depth,
+        })
+    }
+}
+
+impl From<LeafRequest> for ProofRequest {
+    fn from(request: LeafRequest) -> Self {
+        ProofRequest {
+            index: request.index,
+            depth: request.depth as u32,
+        }
+    }
+}
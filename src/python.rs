@@ -0,0 +1,185 @@
+//! PyO3 bindings for building trees and verifying proofs from Python
+//!
+//! Field elements cross the boundary as decimal strings. Errors of the tree are raised as
+//! `MerkleError`, an index past the last leaf as `IndexError` and a malformed field element or
+//! proof as `ValueError`. The trees are binary circom Poseidon trees with the default conventions,
+//! and `verify_proof` assumes them. Hashing runs with the GIL released.
+
+use alloc::{format, string::String, vec::Vec};
+use core::str::FromStr;
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyIndexError, PyValueError},
+    prelude::*,
+    types::{PyDict, PyList},
+};
+
+use crate::{HashOperation, MerkleProof, PoseidonMerkleError, SparseMerkleTree};
+
+create_exception!(
+    merkle_poseidon,
+    MerkleError,
+    PyException,
+    "Raised for the errors of a tree or a proof"
+);
+
+impl From<PoseidonMerkleError> for PyErr {
+    fn from(error: PoseidonMerkleError) -> Self {
+        match error {
+            PoseidonMerkleError::IndexOutOfRange { .. } => PyIndexError::new_err(error.to_string()),
+            _ => MerkleError::new_err(error.to_string()),
+        }
+    }
+}
+
+/// Parse a field element from a decimal string, which must be below the modulus
+pub fn fr_from_decimal(input: &str) -> PyResult<Fr> {
+    let invalid =
+        |reason| PyValueError::new_err(format!("invalid field element {input:?}: {reason}"));
+    if input.is_empty() || !input.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid("not a decimal number"));
+    }
+
+    // Fr::from_str reduces modulo the field, a canonical value prints back the same
+    let value = Fr::from_str(input).map_err(|_| invalid("not a decimal number"))?;
+    let digits = match input.trim_start_matches('0') {
+        "" => "0",
+        digits => digits,
+    };
+    match value.to_string() == digits {
+        true => Ok(value),
+        false => Err(invalid("not below the modulus")),
+    }
+}
+
+/// A proof as the dict `{ leaf, root, path, siblings }` of decimal strings
+fn proof_to_dict<'py>(py: Python<'py>, proof: &MerkleProof) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("leaf", proof.leaf_value.to_string())?;
+    dict.set_item("root", proof.root_hash.to_string())?;
+    dict.set_item("path", proof.merkle_path.to_string())?;
+    let siblings: Vec<String> = proof.siblings.iter().map(ToString::to_string).collect();
+    dict.set_item("siblings", PyList::new(py, siblings)?)?;
+
+    Ok(dict)
+}
+
+/// The proof of a `proof_to_dict` dict, checked like `MerkleProof::try_new`
+fn proof_from_dict(dict: &Bound<'_, PyDict>) -> PyResult<MerkleProof> {
+    let item = |name: &str| {
+        dict.get_item(name)?
+            .ok_or_else(|| PyValueError::new_err(format!("invalid proof: missing key {name:?}")))
+    };
+    let field = |name: &str| fr_from_decimal(&item(name)?.extract::<String>()?);
+    let siblings = item("siblings")?
+        .extract::<Vec<String>>()?
+        .iter()
+        .map(|sibling| fr_from_decimal(sibling))
+        .collect::<PyResult<Vec<_>>>()?;
+
+    MerkleProof::try_new(siblings, field("path")?, field("leaf")?, field("root")?)
+        .map_err(|error| PyValueError::new_err(format!("invalid proof: {error}")))
+}
+
+/// A binary circom Poseidon tree for Python
+#[pyclass(module = "merkle_poseidon")]
+pub struct PyMerkleTree {
+    tree: SparseMerkleTree<Poseidon<Fr>>,
+}
+
+#[pymethods]
+impl PyMerkleTree {
+    /// An empty tree of `depth` levels
+    #[new]
+    pub fn new(depth: usize) -> PyResult<Self> {
+        Ok(PyMerkleTree {
+            tree: SparseMerkleTree::new(depth)?,
+        })
+    }
+
+    /// Set the leaf at `index`, below `2**depth`, to a decimal value
+    pub fn insert(&mut self, py: Python<'_>, index: u64, value: &str) -> PyResult<()> {
+        let value = fr_from_decimal(value)?;
+        let path = self.path(index)?;
+        let tree = &mut self.tree;
+        py.detach(|| tree.insert_at_path(&path, &value))?;
+
+        Ok(())
+    }
+
+    /// Set the leaves of `(index, value)` pairs, hashing them all with the GIL released
+    pub fn insert_many(&mut self, py: Python<'_>, entries: Vec<(u64, String)>) -> PyResult<()> {
+        let entries = entries
+            .iter()
+            .map(|(index, value)| Ok((self.path(*index)?, fr_from_decimal(value)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        let tree = &mut self.tree;
+        py.detach(|| tree.insert_many(entries))?;
+
+        Ok(())
+    }
+
+    /// The root hash as a decimal string
+    pub fn root(&mut self, py: Python<'_>) -> PyResult<String> {
+        let tree = &mut self.tree;
+        Ok(py.detach(|| tree.root())?.to_string())
+    }
+
+    /// The proof of the leaf at `index`, a `{ leaf, root, path, siblings }` dict
+    pub fn prove<'py>(&mut self, py: Python<'py>, index: u64) -> PyResult<Bound<'py, PyDict>> {
+        let path = self.path(index)?;
+        let tree = &mut self.tree;
+        let proof = py.detach(|| tree.generate_proof(&path))?;
+
+        proof_to_dict(py, &proof)
+    }
+
+    /// Whether a proof of `prove` holds against the current root of this tree
+    pub fn verify(&mut self, py: Python<'_>, proof: &Bound<'_, PyDict>) -> PyResult<bool> {
+        let proof = proof_from_dict(proof)?;
+        let tree = &mut self.tree;
+        Ok(py.detach(|| {
+            let root = tree.root()?;
+            Ok::<_, PoseidonMerkleError>(proof.root_hash == root && tree.verify_proof(&proof)?)
+        })?)
+    }
+}
+
+impl PyMerkleTree {
+    /// The path of the leaf at `index`, rejecting indices past the last leaf
+    fn path(&self, index: u64) -> Result<Fr, PoseidonMerkleError> {
+        let depth = self.tree.depth;
+        if depth < 64 && index >> depth != 0 {
+            return Err(PoseidonMerkleError::IndexOutOfRange {
+                index: usize::try_from(index).unwrap_or(usize::MAX),
+                size: 1usize.checked_shl(depth as u32).unwrap_or(usize::MAX),
+            });
+        }
+
+        Ok(Fr::from(index))
+    }
+}
+
+/// Verify a proof of `PyMerkleTree.prove` against the root it carries
+#[pyfunction]
+pub fn verify_proof(py: Python<'_>, proof: &Bound<'_, PyDict>) -> PyResult<bool> {
+    let proof = proof_from_dict(proof)?;
+    Ok(py.detach(|| {
+        let mut hasher = Poseidon::<Fr>::new_circom(2)
+            .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+        proof.verify_proof(&mut hasher)
+    })?)
+}
+
+/// The `merkle_poseidon` Python module
+#[pymodule]
+pub(crate) fn merkle_poseidon(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMerkleTree>()?;
+    m.add_function(wrap_pyfunction!(verify_proof, m)?)?;
+    m.add("MerkleError", m.py().get_type::<MerkleError>())?;
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use alloc::vec::Vec;
+use core::slice;
+
+use ark_bn254::Fr;
+use ark_r1cs_std::{fields::fp::FpVar, prelude::*};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSystemRef, SynthesisError},
+};
+use light_poseidon::parameters::bn254_x5::get_poseidon_parameters;
+
+use crate::{Arity, HashFamily, HashingScheme, LeafHashing, MerkleProof, PoseidonMerkleError};
+
+/// The values a `MerkleProofVar` is allocated from, see `MerkleProof::to_constraint_witness`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleProofWitness {
+    /// The leaf value, hashed in the circuit like the tree hashes it
+    pub leaf_value: Fr,
+    /// The root the proof folds into
+    pub root: Fr,
+    /// Direction taken at each level from the root down, `true` means right
+    pub path_bits: Vec<bool>,
+    /// Sibling at each level from the root down
+    pub siblings: Vec<Fr>,
+    /// How the tree hashes its leaf values
+    pub leaf_hashing: LeafHashing,
+    /// Whether the tree hashes its leaves apart or binds its inner nodes to their level
+    pub scheme: HashingScheme,
+}
+
+impl MerkleProof<Fr> {
+    /// The witness of the proof for `MerkleProofVar::new`
+    ///
+    /// The circuit hashes with the circom Poseidon parameters, so only binary Poseidon proofs
+    /// can be turned into a witness. The path bits follow the bit order of the proof.
+    pub fn to_constraint_witness(&self) -> Result<MerkleProofWitness, PoseidonMerkleError> {
+        if self.arity != Arity::Binary || self.hash_family != HashFamily::Poseidon {
+            return Err(PoseidonMerkleError::UnsupportedCircuit);
+        }
+
+        Ok(MerkleProofWitness {
+            leaf_value: self.leaf_value,
+            root: self.root_hash,
+            path_bits: (0..self.depth())
+                .map(|level| self.position(level) == 1)
+                .collect(),
+            siblings: self.siblings.clone(),
+            leaf_hashing: self.leaf_hashing,
+            scheme: self.scheme,
+        })
+    }
+}
+
+/// A binary Poseidon `MerkleProof` allocated in a constraint system over bn254
+///
+/// The root is a public input, the leaf value, the path bits and the siblings are private
+/// witnesses.
+#[derive(Debug, Clone)]
+pub struct MerkleProofVar {
+    /// The leaf value
+    pub leaf_value: FpVar<Fr>,
+    /// The root the proof folds into
+    pub root: FpVar<Fr>,
+    /// Direction taken at each level from the root down, `true` means right
+    pub path_bits: Vec<Boolean<Fr>>,
+    /// Sibling at each level from the root down
+    pub siblings: Vec<FpVar<Fr>>,
+    leaf_hashing: LeafHashing,
+    scheme: HashingScheme,
+}
+
+impl MerkleProofVar {
+    /// Allocate a proof witness in `cs`
+    pub fn new(
+        cs: ConstraintSystemRef<Fr>,
+        witness: &MerkleProofWitness,
+    ) -> Result<Self, SynthesisError> {
+        let cs = ns!(cs, "merkle proof");
+        let leaf_value = FpVar::new_witness(cs.clone(), || Ok(witness.leaf_value))?;
+        let root = FpVar::new_input(cs.clone(), || Ok(witness.root))?;
+        let path_bits = witness
+            .path_bits
+            .iter()
+            .map(|bit| Boolean::new_witness(cs.clone(), || Ok(*bit)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let siblings = witness
+            .siblings
+            .iter()
+            .map(|sibling| FpVar::new_witness(cs.clone(), || Ok(*sibling)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(MerkleProofVar {
+            leaf_value,
+            root,
+            path_bits,
+            siblings,
+            leaf_hashing: witness.leaf_hashing,
+            scheme: witness.scheme,
+        })
+    }
+
+    /// The root the proof folds into, computed like `MerkleProof::verify_proof` does
+    pub fn compute_root(&self) -> Result<FpVar<Fr>, SynthesisError> {
+        let mut current_hash = match self.leaf_hashing {
+            LeafHashing::Raw => self.leaf_value.clone(),
+            // The empty leaf hash is poseidon(0), no special case is needed
+            LeafHashing::Hashed => poseidon_gadget(slice::from_ref(&self.leaf_value))?,
+        };
+        if self.scheme == HashingScheme::DomainSeparated {
+            current_hash = poseidon_gadget(&[current_hash, FpVar::one()])?;
+        }
+
+        for (level, (bit, sibling)) in self.path_bits.iter().zip(&self.siblings).enumerate().rev() {
+            let left = bit.select(sibling, &current_hash)?;
+            let right = bit.select(&current_hash, sibling)?;
+            current_hash = match self.scheme {
+                HashingScheme::LevelBound => {
+                    poseidon_gadget(&[FpVar::constant(Fr::from(level as u64)), left, right])?
+                }
+                _ => poseidon_gadget(&[left, right])?,
+            };
+        }
+
+        Ok(current_hash)
+    }
+
+    /// Enforce that the proof folds into its root
+    pub fn verify_gadget(&self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let _cs = ns!(cs, "verify merkle proof");
+        self.compute_root()?.enforce_equal(&self.root)
+    }
+}
+
+/// Poseidon of the inputs with the circom parameters of their width, as constraints
+///
+/// Mirrors `light_poseidon::Poseidon::new_circom(inputs.len())`, for 1 to 12 inputs.
+pub fn poseidon_gadget(inputs: &[FpVar<Fr>]) -> Result<FpVar<Fr>, SynthesisError> {
+    let params = get_poseidon_parameters::<Fr>(inputs.len() as u8 + 1)
+        .map_err(|_| SynthesisError::Unsatisfiable)?;
+    let half_rounds = params.full_rounds / 2;
+
+    let mut state = Vec::with_capacity(params.width);
+    state.push(FpVar::zero());
+    state.extend_from_slice(inputs);
+    for round in 0..params.full_rounds + params.partial_rounds {
+        for (i, element) in state.iter_mut().enumerate() {
+            *element += params.ark[round * params.width + i];
+        }
+
+        let is_full = round < half_rounds || round >= half_rounds + params.partial_rounds;
+        let sbox_width = if is_full { params.width } else { 1 };
+        for element in &mut state[..sbox_width] {
+            *element = element.pow_by_constant([params.alpha])?;
+        }
+
+        state = params
+            .mds
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&state)
+                    .fold(FpVar::zero(), |acc, (m, element)| acc + element * *m)
+            })
+            .collect();
+    }
+
+    Ok(state.swap_remove(0))
+}
@@ -0,0 +1,182 @@
+use alloc::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use thiserror::Error;
+
+use crate::{InnerHash, MerkleHasher, MerkleProof, PoseidonMerkleError};
+
+/// The time the roots of a `RootRegistry` expire against, in seconds since the Unix epoch
+///
+/// Any `Fn() -> u64` is a clock, so tests can control time.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+/// The system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+impl<T: Fn() -> u64> Clock for T {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// Why a `RootRegistry` didn't verify a proof
+#[derive(Error, Debug, PartialEq)]
+pub enum RegistryError<F: PrimeField = Fr> {
+    #[error("no root is registered for the tree")]
+    UnknownTree,
+    #[error("the root of the tree expired at {expires_at}, it is {now}")]
+    Expired { expires_at: u64, now: u64 },
+    #[error(transparent)]
+    Verification(#[from] PoseidonMerkleError<F>),
+}
+
+/// A registered root
+#[derive(Debug, Clone)]
+struct RootEntry<F: PrimeField> {
+    root: InnerHash<F>,
+    expires_at: u64,
+    /// The tick of the last insert or verification, the smallest is evicted first
+    last_used: u64,
+}
+
+/// The trusted roots of several trees, such as one per epoch, for verifying proofs against them
+///
+/// A root is valid until its `expires_at`, in the seconds of the clock, and an expired root is
+/// dropped when it is next looked up. The registry holds at most `capacity` roots: inserting a new
+/// tree into a full registry drops the expired roots, then the least recently used one.
+pub struct RootRegistry<K, H: MerkleHasher<F>, F: PrimeField = Fr, C: Clock = SystemClock> {
+    hasher: H,
+    clock: C,
+    capacity: usize,
+    entries: BTreeMap<K, RootEntry<F>>,
+    tick: u64,
+}
+
+impl<K: Ord + Clone, H: MerkleHasher<F>, F: PrimeField> RootRegistry<K, H, F> {
+    /// An empty registry of at most `capacity` roots, verifying with `hasher`
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn new(capacity: usize, hasher: H) -> Self {
+        assert!(capacity > 0, "a root registry holds at least one root");
+
+        RootRegistry {
+            hasher,
+            clock: SystemClock,
+            capacity,
+            entries: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+}
+
+impl<K: Ord + Clone, H: MerkleHasher<F>, F: PrimeField, C: Clock> RootRegistry<K, H, F, C> {
+    /// Expire roots against `clock` instead of the system clock, meant to be chained on `new`
+    pub fn with_clock<T: Clock>(self, clock: T) -> RootRegistry<K, H, F, T> {
+        RootRegistry {
+            hasher: self.hasher,
+            clock,
+            capacity: self.capacity,
+            entries: self.entries,
+            tick: self.tick,
+        }
+    }
+
+    /// Register the root of a tree until `expires_at`, replacing the one it had
+    ///
+    /// Returns the tree evicted to make room, if any.
+    pub fn insert(&mut self, tree_id: K, root: InnerHash<F>, expires_at: u64) -> Option<K> {
+        let mut evicted = None;
+        if !self.entries.contains_key(&tree_id) && self.entries.len() == self.capacity {
+            let now = self.clock.now();
+            self.entries.retain(|_, entry| now < entry.expires_at);
+            if self.entries.len() == self.capacity {
+                evicted = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(tree_id, _)| tree_id.clone());
+                if let Some(tree_id) = &evicted {
+                    self.entries.remove(tree_id);
+                }
+            }
+        }
+
+        self.tick += 1;
+        let entry = RootEntry {
+            root,
+            expires_at,
+            last_used: self.tick,
+        };
+        self.entries.insert(tree_id, entry);
+
+        evicted
+    }
+
+    /// Verify a proof against the registered root of a tree, see `MerkleProof::verify_against`
+    ///
+    /// Fails with `RegistryError::UnknownTree` for a tree without a root, evicted or never
+    /// registered, and with `RegistryError::Expired` once its root expired.
+    pub fn verify(
+        &mut self,
+        tree_id: &K,
+        proof: &MerkleProof<F>,
+    ) -> Result<bool, RegistryError<F>> {
+        let now = self.clock.now();
+        let entry = self
+            .entries
+            .get_mut(tree_id)
+            .ok_or(RegistryError::UnknownTree)?;
+        if now >= entry.expires_at {
+            let expires_at = entry.expires_at;
+            self.entries.remove(tree_id);
+            return Err(RegistryError::Expired { expires_at, now });
+        }
+
+        self.tick += 1;
+        entry.last_used = self.tick;
+        Ok(proof.verify_against(&mut self.hasher, &entry.root)?)
+    }
+
+    /// The registered root of a tree, if it hasn't expired
+    pub fn root(&self, tree_id: &K) -> Option<InnerHash<F>> {
+        let now = self.clock.now();
+        self.entries
+            .get(tree_id)
+            .filter(|entry| now < entry.expires_at)
+            .map(|entry| entry.root)
+    }
+
+    /// Drop the root of a tree, returning it if there was one
+    pub fn remove(&mut self, tree_id: &K) -> Option<InnerHash<F>> {
+        self.entries.remove(tree_id).map(|entry| entry.root)
+    }
+
+    /// Number of roots held, expired ones not yet dropped included
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Most roots held at once
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
@@ -0,0 +1,101 @@
+use alloc::vec::Vec;
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, Field};
+use light_poseidon::{PoseidonError, PoseidonHasher};
+
+use crate::{keccak_round_constants, MAX_ARITY};
+
+/// Width of the Rescue-Prime permutation, a rate of two and a capacity of one
+const RESCUE_WIDTH: usize = 3;
+
+/// Elements absorbed per permutation
+const RESCUE_RATE: usize = 2;
+
+/// Rounds of the permutation, each one applies the S-box and its inverse
+const RESCUE_ROUNDS: usize = 14;
+
+/// Seed of the keccak256 chain the round constants are derived from
+const RESCUE_SEED: &str = "rescue_prime";
+
+/// 1/5 modulo the order of the multiplicative group of bn254 Fr, the inverse S-box exponent
+const ALPHA_INV: [u64; 4] = [
+    0xcfe7f7a98ccccccd,
+    0x535cb9d394945a0d,
+    0x93736af8679aad17,
+    0x26b6a528b427b354,
+];
+
+/// Rescue-Prime over bn254 behind the `PoseidonHasher` interface of the tree
+///
+/// The parameters are fixed by this crate for comparing hash functions, they are not a
+/// standardized instance: width 3, x^5 and x^(1/5) S-boxes, the circulant MDS matrix (2, 1, 1)
+/// and 14 rounds, whose 84 round constants are derived from keccak256("rescue_prime") like the
+/// MiMC ones. The sponge starts from a zeroed state holding the number of inputs in its capacity
+/// element, absorbs the inputs two at a time with a permutation after each pair, and the hash is
+/// the first element of the state. It accepts one to `MAX_ARITY` inputs.
+#[derive(Debug, Clone)]
+pub struct RescueHasher {
+    constants: Vec<Fr>,
+}
+
+impl RescueHasher {
+    pub fn new() -> Self {
+        RescueHasher {
+            constants: keccak_round_constants(RESCUE_SEED, 2 * RESCUE_WIDTH * RESCUE_ROUNDS),
+        }
+    }
+
+    /// The Rescue-Prime permutation
+    pub fn permute(&self, state: &mut [Fr; RESCUE_WIDTH]) {
+        for round_constants in self.constants.chunks(2 * RESCUE_WIDTH) {
+            let (first, second) = round_constants.split_at(RESCUE_WIDTH);
+
+            state.iter_mut().for_each(|x| *x = x.pow([5]));
+            mix(state, first);
+            state.iter_mut().for_each(|x| *x = x.pow(ALPHA_INV));
+            mix(state, second);
+        }
+    }
+}
+
+/// Multiply by the MDS matrix and add the round constants
+fn mix(state: &mut [Fr; RESCUE_WIDTH], constants: &[Fr]) {
+    // circ(2, 1, 1) times the state is the sum of the state plus each element
+    let sum: Fr = state.iter().sum();
+    for (x, constant) in state.iter_mut().zip(constants) {
+        *x += sum + constant;
+    }
+}
+
+impl Default for RescueHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PoseidonHasher<Fr> for RescueHasher {
+    fn hash(&mut self, inputs: &[Fr]) -> Result<Fr, PoseidonError> {
+        if inputs.is_empty() {
+            return Err(PoseidonError::EmptyInput);
+        }
+        if inputs.len() > MAX_ARITY {
+            return Err(PoseidonError::InvalidNumberOfInputs {
+                inputs: inputs.len(),
+                max_limit: MAX_ARITY,
+                width: MAX_ARITY + 1,
+            });
+        }
+
+        let mut state = [Fr::ZERO; RESCUE_WIDTH];
+        state[RESCUE_RATE] = Fr::from(inputs.len() as u64);
+        for chunk in inputs.chunks(RESCUE_RATE) {
+            for (x, input) in state.iter_mut().zip(chunk) {
+                *x += input;
+            }
+            self.permute(&mut state);
+        }
+
+        Ok(state[0])
+    }
+}
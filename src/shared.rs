@@ -0,0 +1,253 @@
+#[cfg(not(feature = "sync"))]
+use alloc::rc::Rc;
+#[cfg(feature = "sync")]
+use alloc::vec::Vec;
+use core::cell::RefCell;
+#[cfg(not(feature = "sync"))]
+use core::cell::{Ref, RefMut};
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "sync")]
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+use ark_ff::PrimeField;
+
+use crate::PoseidonMerkleError;
+
+/// Shared pointer to a tree node, `Rc` by default and `Arc` with the `sync` feature
+#[cfg(not(feature = "sync"))]
+pub type Shared<T> = Rc<T>;
+#[cfg(feature = "sync")]
+pub type Shared<T> = Arc<T>;
+
+/// Interior mutability for tree nodes, `RefCell` by default and `SyncCell` with the `sync` feature
+#[cfg(not(feature = "sync"))]
+pub type NodeCell<T> = RefCell<T>;
+#[cfg(feature = "sync")]
+pub type NodeCell<T> = SyncCell<T>;
+
+/// A `RwLock` exposing the `RefCell` borrowing API, so the tree is `Send` and `Sync`
+///
+/// A poisoned lock is recovered from, a panic can't leave a node half written. Each thread keeps
+/// track of the cells it holds, so the checked borrows of the tree fail on a borrow of the same
+/// thread, which would never be released, and wait for the ones of other threads.
+#[cfg(feature = "sync")]
+#[derive(Debug, Default)]
+pub struct SyncCell<T>(RwLock<T>);
+
+#[cfg(feature = "sync")]
+std::thread_local! {
+    /// Addresses of the `SyncCell`s borrowed by the current thread, once per guard
+    static HELD: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Registration of a borrowed `SyncCell` in `HELD`, removed when the guard is dropped
+#[cfg(feature = "sync")]
+#[derive(Debug)]
+struct Held(usize);
+
+#[cfg(feature = "sync")]
+impl Held {
+    fn new(address: usize) -> Self {
+        // A thread being torn down has nothing left to conflict with
+        let _ = HELD.try_with(|held| held.borrow_mut().push(address));
+        Held(address)
+    }
+
+    fn by_current_thread(address: usize) -> bool {
+        HELD.try_with(|held| held.borrow().contains(&address))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(feature = "sync")]
+impl Drop for Held {
+    fn drop(&mut self) {
+        let _ = HELD.try_with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(index) = held.iter().rposition(|address| *address == self.0) {
+                held.swap_remove(index);
+            }
+        });
+    }
+}
+
+/// Shared borrow of a `SyncCell`, its read lock
+#[cfg(feature = "sync")]
+#[derive(Debug)]
+pub struct SyncRef<'a, T> {
+    guard: RwLockReadGuard<'a, T>,
+    _held: Held,
+}
+
+#[cfg(feature = "sync")]
+impl<T> Deref for SyncRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+/// Exclusive borrow of a `SyncCell`, its write lock
+#[cfg(feature = "sync")]
+#[derive(Debug)]
+pub struct SyncRefMut<'a, T> {
+    guard: RwLockWriteGuard<'a, T>,
+    _held: Held,
+}
+
+#[cfg(feature = "sync")]
+impl<T> Deref for SyncRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> DerefMut for SyncRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> SyncCell<T> {
+    pub fn new(value: T) -> Self {
+        SyncCell(RwLock::new(value))
+    }
+
+    /// Acquire shared read access to the value
+    pub fn borrow(&self) -> SyncRef<'_, T> {
+        let guard = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        SyncRef {
+            guard,
+            _held: Held::new(self.address()),
+        }
+    }
+
+    /// Acquire exclusive write access to the value
+    pub fn borrow_mut(&self) -> SyncRefMut<'_, T> {
+        let guard = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        SyncRefMut {
+            guard,
+            _held: Held::new(self.address()),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T: Clone> Clone for SyncCell<T> {
+    fn clone(&self) -> Self {
+        SyncCell::new(self.borrow().clone())
+    }
+}
+
+/// A node cell borrowed elsewhere, reported as `PoseidonMerkleError::ConcurrentAccess`
+#[derive(Debug)]
+pub(crate) struct BorrowConflict;
+
+impl<F: PrimeField> From<BorrowConflict> for PoseidonMerkleError<F> {
+    fn from(_: BorrowConflict) -> Self {
+        PoseidonMerkleError::ConcurrentAccess
+    }
+}
+
+/// For the writers of the visualizer, which report a conflict as an I/O error
+#[cfg(feature = "std")]
+impl From<BorrowConflict> for std::io::Error {
+    fn from(_: BorrowConflict) -> Self {
+        std::io::Error::other(PoseidonMerkleError::<ark_bn254::Fr>::ConcurrentAccess)
+    }
+}
+
+/// Borrowing a `NodeCell` from the tree operations, failing instead of panicking on a conflict
+///
+/// A `RefCell` borrowed elsewhere, such as through a `NodeRef` the caller holds across a mutation,
+/// is a `BorrowConflict`. So is a `SyncCell` locked by the current thread, while one locked by
+/// another thread is waited for.
+pub(crate) trait CheckedBorrow<T> {
+    type Ref<'a>: Deref<Target = T>
+    where
+        Self: 'a;
+    type RefMut<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    fn checked_borrow(&self) -> Result<Self::Ref<'_>, BorrowConflict>;
+
+    fn checked_borrow_mut(&self) -> Result<Self::RefMut<'_>, BorrowConflict>;
+}
+
+#[cfg(not(feature = "sync"))]
+impl<T> CheckedBorrow<T> for RefCell<T> {
+    type Ref<'a>
+        = Ref<'a, T>
+    where
+        T: 'a;
+    type RefMut<'a>
+        = RefMut<'a, T>
+    where
+        T: 'a;
+
+    fn checked_borrow(&self) -> Result<Ref<'_, T>, BorrowConflict> {
+        self.try_borrow().map_err(|_| BorrowConflict)
+    }
+
+    fn checked_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowConflict> {
+        self.try_borrow_mut().map_err(|_| BorrowConflict)
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> CheckedBorrow<T> for SyncCell<T> {
+    type Ref<'a>
+        = SyncRef<'a, T>
+    where
+        T: 'a;
+    type RefMut<'a>
+        = SyncRefMut<'a, T>
+    where
+        T: 'a;
+
+    fn checked_borrow(&self) -> Result<SyncRef<'_, T>, BorrowConflict> {
+        let guard = match self.0.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) if Held::by_current_thread(self.address()) => {
+                return Err(BorrowConflict)
+            }
+            Err(TryLockError::WouldBlock) => return Ok(self.borrow()),
+        };
+
+        Ok(SyncRef {
+            guard,
+            _held: Held::new(self.address()),
+        })
+    }
+
+    fn checked_borrow_mut(&self) -> Result<SyncRefMut<'_, T>, BorrowConflict> {
+        let guard = match self.0.try_write() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) if Held::by_current_thread(self.address()) => {
+                return Err(BorrowConflict)
+            }
+            Err(TryLockError::WouldBlock) => return Ok(self.borrow_mut()),
+        };
+
+        Ok(SyncRefMut {
+            guard,
+            _held: Held::new(self.address()),
+        })
+    }
+}
@@ -0,0 +1,306 @@
+//! Snapshots of binary and wider circom Poseidon trees as bytes
+//!
+//! The layout is a header followed by the materialized nodes in preorder. The header holds the
+//! magic `MPSN`, the version, the depth as a big-endian `u32`, the arity, the leaf hashing, the
+//! hashing scheme (its top bit set for folded empty subtrees), the bit order and the leaf width as
+//! one byte each, then the empty leaf and the root as 32 big-endian bytes. An inner node is the byte of its materialized children, bit `i`
+//! for child `i`, followed by them. A leaf is its value, and in a tree of wider leaves a byte
+//! telling whether its values follow. Empty subtrees take no space, and the inner hashes are
+//! recomputed on load and checked against the root.
+//!
+//! With the `serde` feature, a circom Poseidon tree serializes as the bytes of its snapshot.
+
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "serde")]
+use core::fmt;
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use light_poseidon::Poseidon;
+
+use crate::{
+    eth::{from_bytes32, to_bytes32},
+    node::{Node, NodeRef},
+    shared::CheckedBorrow,
+    Arity, BitOrder, EmptySubtrees, HashOperation, HashingScheme, LeafHashing, MerkleHasher,
+    NodeType, PoseidonMerkleError, SparseMerkleTree,
+};
+
+const MAGIC: &[u8; 4] = b"MPSN";
+
+/// Version of the layout, the byte after the magic
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// Bit of the hashing scheme byte set for `EmptySubtrees::Folded`
+const FOLDED_FLAG: u8 = 0x80;
+
+/// Bytes of the header: magic, version, depth, the five convention bytes, empty leaf and root
+const HEADER_BYTES: usize = 4 + 1 + 4 + 5 + 2 * 32;
+
+impl<M> SparseMerkleTree<Poseidon<Fr>, Fr, M> {
+    /// Serialize the tree as a snapshot, see the `snapshot` module for the layout
+    ///
+    /// The root is refreshed first, so that the snapshot records it. The leaf payloads of
+    /// `with_metadata` are left out, the snapshot holds what the root commits to.
+    pub fn to_snapshot_bytes(&self) -> Result<Vec<u8>, PoseidonMerkleError> {
+        let root = self.root()?;
+
+        let mut bytes = Vec::with_capacity(HEADER_BYTES);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(SNAPSHOT_VERSION);
+        bytes.extend_from_slice(&(self.depth as u32).to_be_bytes());
+        bytes.extend([
+            self.arity().children() as u8,
+            match self.leaf_hashing() {
+                LeafHashing::Raw => 0,
+                LeafHashing::Hashed => 1,
+            },
+            match self.hashing_scheme() {
+                HashingScheme::Legacy => 0,
+                HashingScheme::DomainSeparated => 1,
+                HashingScheme::LevelBound => 2,
+            } | match self.empty_subtrees() {
+                EmptySubtrees::Flat => 0,
+                EmptySubtrees::Folded => FOLDED_FLAG,
+            },
+            match self.bit_order() {
+                BitOrder::LsbFirst => 0,
+                BitOrder::MsbFirst => 1,
+            },
+            self.leaf_width() as u8,
+        ]);
+        bytes.extend_from_slice(&to_bytes32(&self.empty_leaf()));
+        bytes.extend_from_slice(&to_bytes32(&root));
+
+        let mut stack = vec![self.root.clone()];
+        while let Some(node) = stack.pop() {
+            let node_ref = node.checked_borrow()?;
+            match node_ref.node_type {
+                NodeType::Inner(_) => {
+                    let children = &node_ref.children[..self.arity().children()];
+                    let mask = (0..children.len())
+                        .filter(|index| children[*index].is_some())
+                        .fold(0u8, |mask, index| mask | 1 << index);
+                    bytes.push(mask);
+                    stack.extend(children.iter().rev().flatten().cloned());
+                }
+                NodeType::Leaf(value) => {
+                    bytes.extend_from_slice(&to_bytes32(&value));
+                    if self.leaf_width() > 1 {
+                        bytes.push(node_ref.values.is_some().into());
+                        for value in node_ref.values.iter().flatten() {
+                            bytes.extend_from_slice(&to_bytes32(value));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl SparseMerkleTree<Poseidon<Fr>> {
+    /// Rebuild a tree from the bytes of `to_snapshot_bytes`
+    ///
+    /// Malformed bytes are rejected with `PoseidonMerkleError::MalformedSnapshot`, and nodes
+    /// hashing to another root than the recorded one with `PoseidonMerkleError::RootMismatch`.
+    pub fn from_snapshot_bytes(bytes: &[u8]) -> Result<Self, PoseidonMerkleError> {
+        let malformed = PoseidonMerkleError::MalformedSnapshot;
+        if bytes.len() < HEADER_BYTES {
+            return Err(malformed("truncated header"));
+        }
+        if &bytes[..4] != MAGIC {
+            return Err(malformed("not a snapshot"));
+        }
+        if bytes[4] != SNAPSHOT_VERSION {
+            return Err(malformed("unknown version"));
+        }
+        let depth = u32::from_be_bytes(bytes[5..9].try_into().expect("4 bytes")) as usize;
+        let [arity, leaf_hashing, scheme, bit_order, leaf_width] =
+            bytes[9..14].try_into().expect("5 bytes");
+        let arity = match arity {
+            2 => Arity::Binary,
+            4 => Arity::Quaternary,
+            8 => Arity::Octal,
+            _ => return Err(malformed("unknown arity")),
+        };
+        let leaf_hashing = match leaf_hashing {
+            0 => LeafHashing::Raw,
+            1 => LeafHashing::Hashed,
+            _ => return Err(malformed("unknown leaf hashing")),
+        };
+        let empty_subtrees = match scheme & FOLDED_FLAG {
+            0 => EmptySubtrees::Flat,
+            _ => EmptySubtrees::Folded,
+        };
+        let scheme = match scheme & !FOLDED_FLAG {
+            0 => HashingScheme::Legacy,
+            1 => HashingScheme::DomainSeparated,
+            2 => HashingScheme::LevelBound,
+            _ => return Err(malformed("unknown hashing scheme")),
+        };
+        let bit_order = match bit_order {
+            0 => BitOrder::LsbFirst,
+            1 => BitOrder::MsbFirst,
+            _ => return Err(malformed("unknown bit order")),
+        };
+        // A path has a bit per bit of the modulus, the nullifier trees of `NULLIFIER_TREE_DEPTH`
+        // levels use them all
+        if depth * arity.bits() > Fr::MODULUS_BIT_SIZE as usize {
+            return Err(malformed("more levels than a path can address"));
+        }
+
+        let mut reader = Reader(&bytes[14..]);
+        let empty_leaf = reader.element()?;
+        let root = reader.element()?;
+
+        let hasher = Poseidon::<Fr>::new_circom(arity.children())
+            .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+        let mut tree = SparseMerkleTree::new_with_arity(depth, arity, hasher)?
+            .with_leaf_hashing(leaf_hashing)
+            .try_with_hashing_scheme(scheme)?
+            .with_empty_subtrees(empty_subtrees)
+            .try_with_empty_leaf(empty_leaf)?
+            .with_bit_order(bit_order)
+            .try_with_leaf_width(usize::from(leaf_width))?;
+
+        tree.root = reader.node(&tree, 0)?;
+        tree.nodes = tree.stats()?.materialized_nodes;
+        if !reader.0.is_empty() {
+            return Err(malformed("trailing bytes"));
+        }
+        #[cfg(feature = "metrics")]
+        {
+            tree.leaves = tree
+                .iter()
+                .filter(|value| !matches!(value, Ok(value) if *value == empty_leaf))
+                .count();
+        }
+
+        let actual = tree.root()?;
+        if actual != root {
+            return Err(PoseidonMerkleError::RootMismatch {
+                expected: root,
+                actual,
+            });
+        }
+
+        Ok(tree)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<M> serde::Serialize for SparseMerkleTree<Poseidon<Fr>, Fr, M> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = self
+            .to_snapshot_bytes()
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SparseMerkleTree<Poseidon<Fr>> {
+    /// Rebuild the tree like `from_snapshot_bytes`, checking its nodes against the recorded root
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(BytesVisitor {
+            expecting: "the bytes of a tree snapshot",
+            parse: SparseMerkleTree::from_snapshot_bytes,
+        })
+    }
+}
+
+/// Parses a value from bytes, or a sequence of bytes in formats without them
+#[cfg(feature = "serde")]
+pub(crate) struct BytesVisitor<T> {
+    pub(crate) expecting: &'static str,
+    pub(crate) parse: fn(&[u8]) -> Result<T, PoseidonMerkleError>,
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> serde::de::Visitor<'de> for BytesVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.expecting)
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        (self.parse)(bytes).map_err(E::custom)
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1 << 20));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+/// The bytes of a snapshot left to read
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], PoseidonMerkleError> {
+        if self.0.len() < len {
+            return Err(PoseidonMerkleError::MalformedSnapshot("truncated nodes"));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Ok(taken)
+    }
+
+    fn element(&mut self) -> Result<Fr, PoseidonMerkleError> {
+        let word = self.take(32)?.try_into().expect("32 bytes");
+        from_bytes32(word).map_err(|_| {
+            PoseidonMerkleError::MalformedSnapshot("field element not below the modulus")
+        })
+    }
+
+    /// The node at `level` and its subtree, inner nodes with children dirty so the tree rehashes them
+    fn node<H: MerkleHasher<Fr>>(
+        &mut self,
+        tree: &SparseMerkleTree<H>,
+        level: usize,
+    ) -> Result<NodeRef<H>, PoseidonMerkleError> {
+        if level == tree.depth {
+            let node = Node::new_borrowed_leaf(self.element()?);
+            if tree.leaf_width() > 1 {
+                let values = match self.take(1)?[0] {
+                    0 => None,
+                    1 => Some(
+                        (0..tree.leaf_width())
+                            .map(|_| self.element())
+                            .collect::<Result<Vec<_>, _>>()?,
+                    ),
+                    _ => return Err(PoseidonMerkleError::MalformedSnapshot("unknown leaf flag")),
+                };
+                node.checked_borrow_mut()?.values = values;
+            }
+
+            return Ok(node);
+        }
+
+        let mask = self.take(1)?[0];
+        let children = tree.arity().children();
+        if children < 8 && mask >> children != 0 {
+            return Err(PoseidonMerkleError::MalformedSnapshot(
+                "child past the arity",
+            ));
+        }
+
+        let node = Node::new_borrowed_inner(tree.hash_config().empty_inner_hash_at(level));
+        {
+            let mut node_ref = node.checked_borrow_mut()?;
+            for index in (0..children).filter(|index| mask >> index & 1 == 1) {
+                node_ref.children[index] = Some(self.node(tree, level + 1)?);
+                node_ref.mark_dirty();
+            }
+        }
+
+        Ok(node)
+    }
+}
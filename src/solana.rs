@@ -0,0 +1,191 @@
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{PoseidonError, PoseidonHasher};
+use solana_program::poseidon::{hashv, Endianness, Parameters, PoseidonSyscallError, HASH_BYTES};
+
+use crate::{
+    compact::{be_bytes, bit, CompactProof, ELEMENT_BYTES},
+    get_empty_inner_hash, get_empty_leaf_hash, EmptySubtrees, HashOperation, HashingScheme,
+    LeafHashing, MerkleProof, PoseidonMerkleError, LEAF_DOMAIN_TAG,
+};
+
+/// Most inputs the syscall hashes at once
+const MAX_SYSCALL_INPUTS: usize = 12;
+
+/// Circom-compatible bn254 Poseidon behind the `sol_poseidon` syscall
+///
+/// Inside a Solana program every hash is a syscall, far cheaper in compute units than running
+/// light_poseidon in the program. Off-chain solana_program falls back to light_poseidon, so a tree
+/// hashed with it matches a `Poseidon::<Fr>::new_circom` one, and is recognized as
+/// `HashFamily::Poseidon`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SolanaPoseidonHasher;
+
+impl SolanaPoseidonHasher {
+    pub fn new() -> Self {
+        SolanaPoseidonHasher
+    }
+}
+
+impl PoseidonHasher<Fr> for SolanaPoseidonHasher {
+    fn hash(&mut self, inputs: &[Fr]) -> Result<Fr, PoseidonError> {
+        if inputs.is_empty() {
+            return Err(PoseidonError::EmptyInput);
+        }
+        if inputs.len() > MAX_SYSCALL_INPUTS {
+            return Err(PoseidonError::InvalidNumberOfInputs {
+                inputs: inputs.len(),
+                max_limit: MAX_SYSCALL_INPUTS,
+                width: MAX_SYSCALL_INPUTS + 1,
+            });
+        }
+
+        let bytes: Vec<Vec<u8>> = inputs
+            .iter()
+            .map(|input| input.into_bigint().to_bytes_le())
+            .collect();
+        let vals: Vec<&[u8]> = bytes.iter().map(Vec::as_slice).collect();
+        let hash = hashv(Parameters::Bn254X5, Endianness::LittleEndian, &vals)
+            .map_err(|error| from_syscall_error(error, inputs.len()))?;
+
+        Ok(Fr::from_le_bytes_mod_order(&hash.to_bytes()))
+    }
+}
+
+/// The light_poseidon counterpart of a syscall error
+///
+/// Inputs are checked and serialized canonically beforehand, so on-chain only `Unexpected` is
+/// expected. The errors without a counterpart are reported as an unsupported width.
+fn from_syscall_error(error: PoseidonSyscallError, inputs: usize) -> PoseidonError {
+    match error {
+        PoseidonSyscallError::InvalidNumberOfInputs => PoseidonError::InvalidNumberOfInputs {
+            inputs,
+            max_limit: MAX_SYSCALL_INPUTS,
+            width: MAX_SYSCALL_INPUTS + 1,
+        },
+        PoseidonSyscallError::EmptyInput => PoseidonError::EmptyInput,
+        PoseidonSyscallError::InvalidInputLength => PoseidonError::InvalidInputLength {
+            len: HASH_BYTES,
+            modulus_bytes_len: HASH_BYTES,
+        },
+        PoseidonSyscallError::BytesToPrimeFieldElement => {
+            PoseidonError::BytesToPrimeFieldElement { bytes: Vec::new() }
+        }
+        PoseidonSyscallError::InputLargerThanModulus => PoseidonError::InputLargerThanModulus,
+        PoseidonSyscallError::VecToArray => PoseidonError::VecToArray,
+        PoseidonSyscallError::U64Tou8 => PoseidonError::U64Tou8,
+        PoseidonSyscallError::BytesToBigInt => PoseidonError::BytesToBigInt,
+        PoseidonSyscallError::InvalidParameters
+        | PoseidonSyscallError::InvalidEndianness
+        | PoseidonSyscallError::InvalidWidthCircom
+        | PoseidonSyscallError::Unexpected => PoseidonError::InvalidWidthCircom {
+            width: inputs + 1,
+            max_limit: MAX_SYSCALL_INPUTS + 1,
+        },
+    }
+}
+
+/// Poseidon of big-endian inputs through the syscall
+fn hash_be(
+    inputs: &[&[u8]],
+    operation: HashOperation,
+) -> Result<[u8; HASH_BYTES], PoseidonMerkleError> {
+    hashv(Parameters::Bn254X5, Endianness::BigEndian, inputs)
+        .map(|hash| hash.to_bytes())
+        .map_err(|error| {
+            PoseidonMerkleError::hasher(operation)(from_syscall_error(error, inputs.len()))
+        })
+}
+
+/// The omitted value of a sibling at the leaf level, the empty leaf contribution
+fn empty_leaf_sibling(
+    leaf_hashing: LeafHashing,
+    scheme: HashingScheme,
+) -> Result<[u8; HASH_BYTES], PoseidonMerkleError> {
+    let leaf = match leaf_hashing {
+        LeafHashing::Raw => [0; HASH_BYTES],
+        LeafHashing::Hashed => be_bytes(get_empty_leaf_hash()),
+    };
+    match scheme {
+        HashingScheme::DomainSeparated => hash_be(
+            &[&leaf, &be_bytes(&Fr::from(LEAF_DOMAIN_TAG))],
+            HashOperation::LeafHash,
+        ),
+        _ => Ok(leaf),
+    }
+}
+
+impl MerkleProof<Fr> {
+    /// Pack the proof into instruction data for `verify_instruction_data`
+    ///
+    /// The instruction data is the compact byte format of `to_compact_bytes`, a proof of depth `d`
+    /// with `n` siblings that aren't the hash of a missing node takes `35 + 2 * ceil(d / 8) + 32 *
+    /// n` bytes. The root is left out, the program compares with the one it keeps.
+    pub fn to_instruction_data(&self) -> Result<Vec<u8>, PoseidonMerkleError> {
+        self.to_compact_bytes()
+    }
+
+    /// Unpack the proof of `to_instruction_data`, whose root is `root_hash`
+    ///
+    /// The path is read in `BitOrder::LsbFirst`, whatever the order of the tree it comes from.
+    pub fn from_instruction_data(data: &[u8], root_hash: Fr) -> Result<Self, PoseidonMerkleError> {
+        Self::from_compact_bytes(data, root_hash)
+    }
+}
+
+/// Verify a proof packed by `MerkleProof::to_instruction_data` against `expected_root`
+///
+/// Meant for Solana programs: the siblings are hashed in place from the instruction data, 32
+/// big-endian bytes like `expected_root`, with the `sol_poseidon` syscall. Returns whether the
+/// proof folds into `expected_root`, malformed data fails with
+/// `ProofError::MalformedCompactProof` and field elements above the modulus with a hasher
+/// error.
+pub fn verify_instruction_data(
+    data: &[u8],
+    expected_root: &[u8; 32],
+) -> Result<bool, PoseidonMerkleError> {
+    let parsed = CompactProof::parse(data)?;
+
+    // The leaf contribution, then the fold from the leaf level up
+    let mut current = match parsed.leaf_hashing {
+        LeafHashing::Raw => parsed.leaf.try_into().expect("the leaf is 32 bytes"),
+        LeafHashing::Hashed => hash_be(&[parsed.leaf], HashOperation::LeafHash)?,
+    };
+    let empty_leaf = empty_leaf_sibling(parsed.leaf_hashing, parsed.scheme)?;
+    if parsed.scheme == HashingScheme::DomainSeparated {
+        current = hash_be(
+            &[&current, &be_bytes(&Fr::from(LEAF_DOMAIN_TAG))],
+            HashOperation::LeafHash,
+        )?;
+    }
+    let empty_inner = be_bytes(get_empty_inner_hash());
+    let inner_hash = |level: usize, left: &[u8], right: &[u8]| {
+        let operation = HashOperation::InnerNode { level: Some(level) };
+        match parsed.scheme {
+            HashingScheme::LevelBound => hash_be(
+                &[&be_bytes(&Fr::from(level as u64)), left, right],
+                operation,
+            ),
+            _ => hash_be(&[left, right], operation),
+        }
+    };
+
+    // A folded tree's empty subtree is hashed a level up alongside the fold, the sibling above
+    // being a node of the level just hashed
+    let mut empty = empty_leaf;
+    let mut explicit = parsed.siblings.chunks_exact(ELEMENT_BYTES).rev();
+    for level in (0..parsed.depth).rev() {
+        let sibling = parsed.sibling(level, &mut explicit, &empty)?;
+        let (left, right): (&[u8], &[u8]) = match bit(parsed.path, level) {
+            true => (sibling, &current),
+            false => (&current, sibling),
+        };
+        current = inner_hash(level, left, right)?;
+        empty = match parsed.empty_subtrees {
+            EmptySubtrees::Folded if level > 0 => inner_hash(level, &empty, &empty)?,
+            _ => empty_inner,
+        };
+    }
+
+    Ok(current == *expected_root)
+}
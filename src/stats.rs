@@ -0,0 +1,91 @@
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use ark_ff::PrimeField;
+
+use crate::{shared::CheckedBorrow, MerkleHasher, NodeType, PoseidonMerkleError, SparseMerkleTree};
+
+/// Shape of a tree, see `SparseMerkleTree::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeStats {
+    pub depth: usize,
+    /// Materialized nodes at each level, the root at 0 and the leaves at `depth`
+    pub nodes_per_level: Vec<usize>,
+    /// Nodes held in memory, empty ones left by deletions included
+    pub materialized_nodes: usize,
+    /// Missing children of materialized inner nodes, each standing for a whole empty subtree
+    pub implicit_nodes: usize,
+    /// Leaves not holding the empty leaf value
+    pub non_empty_leaves: usize,
+    /// Deepest level with a materialized node, 0 for an empty tree
+    pub max_materialized_depth: usize,
+    /// Average number of edges from the root to a non-empty leaf, `None` without any
+    pub average_path_length: Option<f64>,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField, M> SparseMerkleTree<H, F, M> {
+    /// Count the nodes of the tree in one traversal, hashes are not refreshed
+    ///
+    /// A node borrowed elsewhere fails with `PoseidonMerkleError::ConcurrentAccess`.
+    pub fn stats(&self) -> Result<TreeStats, PoseidonMerkleError<F>> {
+        let arity = self.arity().children();
+        let empty_leaf = self.empty_leaf();
+        let mut stats = TreeStats {
+            depth: self.depth,
+            nodes_per_level: vec![0; self.depth + 1],
+            materialized_nodes: 0,
+            implicit_nodes: 0,
+            non_empty_leaves: 0,
+            max_materialized_depth: 0,
+            average_path_length: None,
+        };
+        let mut path_lengths = 0;
+
+        let mut stack = vec![(self.root.clone(), 0)];
+        while let Some((node, level)) = stack.pop() {
+            let node = node.checked_borrow()?;
+            stats.nodes_per_level[level] += 1;
+            stats.materialized_nodes += 1;
+            stats.max_materialized_depth = stats.max_materialized_depth.max(level);
+
+            match node.node_type {
+                NodeType::Leaf(value) if value != empty_leaf => {
+                    stats.non_empty_leaves += 1;
+                    path_lengths += level;
+                }
+                NodeType::Leaf(_) => {}
+                NodeType::Inner(_) => {
+                    for child in &node.children[..arity] {
+                        match child {
+                            Some(child) => stack.push((child.clone(), level + 1)),
+                            None => stats.implicit_nodes += 1,
+                        }
+                    }
+                }
+            }
+        }
+
+        if stats.non_empty_leaves > 0 {
+            stats.average_path_length = Some(path_lengths as f64 / stats.non_empty_leaves as f64);
+        }
+        Ok(stats)
+    }
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Level  Nodes")?;
+        for (level, nodes) in self.nodes_per_level.iter().enumerate() {
+            writeln!(f, "{level:>5}  {nodes}")?;
+        }
+        writeln!(f, "Depth                   {}", self.depth)?;
+        writeln!(f, "Materialized nodes      {}", self.materialized_nodes)?;
+        writeln!(f, "Implicit nodes          {}", self.implicit_nodes)?;
+        writeln!(f, "Non-empty leaves        {}", self.non_empty_leaves)?;
+        writeln!(f, "Max materialized depth  {}", self.max_materialized_depth)?;
+        match self.average_path_length {
+            Some(length) => write!(f, "Average path length     {length:.2}"),
+            None => write!(f, "Average path length     -"),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use alloc::vec::Vec;
+
+use ark_ff::PrimeField;
+use serde_json::{json, Value};
+
+use crate::{
+    walk::{leaf_index, walk, Visited},
+    FrEncoding, MerkleHasher, PoseidonMerkleError, SparseMerkleTree,
+};
+
+impl<H: MerkleHasher<F>, F: PrimeField, M> SparseMerkleTree<H, F, M> {
+    /// The tree as nested JSON objects for external tooling, one per node
+    ///
+    /// Every object has its `type` and `level`. An `"inner"` node has its `hash` and its
+    /// `children` in index order, a `"leaf"` its `value` and its `index` when the path fits in a
+    /// `u64`, and an inner node at `max_depth` is a `"summary"` with its `hash` and the number of
+    /// non-empty `leaves` below it. Missing children and empty subtrees are the string `"empty"`.
+    /// Field elements are decimal strings and hashes the cached ones, stale until `root()`. A node
+    /// borrowed elsewhere fails with `PoseidonMerkleError::ConcurrentAccess`.
+    pub fn to_structure_json(
+        &self,
+        max_depth: Option<usize>,
+    ) -> Result<Value, PoseidonMerkleError<F>> {
+        // Nodes whose children are still being visited, with their level
+        let mut open: Vec<(usize, Value)> = Vec::new();
+        let close = |open: &mut Vec<(usize, Value)>| {
+            let (_, node) = open.pop().expect("a node is open");
+            match open.last_mut() {
+                Some((_, parent)) => {
+                    parent["children"]
+                        .as_array_mut()
+                        .expect("open nodes have children")
+                        .push(node);
+                    None
+                }
+                None => Some(node),
+            }
+        };
+
+        let mut root = None;
+        walk::<_, _, _, PoseidonMerkleError<F>>(self, max_depth, None, false, |visit| {
+            let level = visit.digits.len();
+            while open
+                .last()
+                .is_some_and(|(open_level, _)| *open_level >= level)
+            {
+                root = close(&mut open);
+            }
+
+            let node = match visit.visited {
+                Visited::Inner(hash) => json!({
+                    "type": "inner",
+                    "level": level,
+                    "hash": FrEncoding::Decimal.encode(&hash),
+                    "children": [],
+                }),
+                Visited::Leaf(value) => {
+                    let mut leaf = json!({
+                        "type": "leaf",
+                        "level": level,
+                        "value": FrEncoding::Decimal.encode(&value),
+                    });
+                    if let Some(index) = leaf_index(self, visit.digits) {
+                        leaf["index"] = json!(index);
+                    }
+                    leaf
+                }
+                Visited::Summary(hash, leaves) => json!({
+                    "type": "summary",
+                    "level": level,
+                    "hash": FrEncoding::Decimal.encode(&hash),
+                    "leaves": leaves,
+                }),
+                Visited::Empty(_) => json!("empty"),
+            };
+            open.push((level, node));
+            Ok(())
+        })?;
+        while !open.is_empty() {
+            root = close(&mut open);
+        }
+
+        Ok(root.expect("the root is visited"))
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+
+use crate::{
+    InnerHash, MerkleHasher, MerklePath, MerkleProof, PoseidonMerkleError, SparseMerkleTree,
+};
+
+/// A tree behind a lock, to share between threads without a `Mutex` around it
+///
+/// Readers take `&self` and run concurrently, writers take the lock exclusively for the time of
+/// the update. Every operation takes the lock once, so a proof is of one state of the tree. The
+/// rest of the tree API is reached through `read` and `write`. A panic while the lock is held
+/// doesn't poison it, a panicking update leaves the tree as a failed one does.
+pub struct SyncSparseMerkleTree<H: MerkleHasher<F>, F: PrimeField = Fr>(
+    RwLock<SparseMerkleTree<H, F>>,
+);
+
+impl<H: MerkleHasher<F>, F: PrimeField> SyncSparseMerkleTree<H, F> {
+    pub fn new(tree: SparseMerkleTree<H, F>) -> Self {
+        SyncSparseMerkleTree(RwLock::new(tree))
+    }
+
+    /// Insert a value at a path, see `SparseMerkleTree::insert_at_path`
+    pub fn insert_at_path(
+        &self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        self.write().insert_at_path(merkle_path, value)
+    }
+
+    /// Set a leaf back to the empty value, see `SparseMerkleTree::delete_at_path`
+    pub fn delete_at_path(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        self.write().delete_at_path(merkle_path)
+    }
+
+    /// The value at a path, see `SparseMerkleTree::try_get_value`
+    pub fn try_get_value(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        self.read().try_get_value(merkle_path)
+    }
+
+    /// Generate a proof of the value at a path, see `SparseMerkleTree::generate_proof`
+    pub fn generate_proof(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        self.read().generate_proof(merkle_path)
+    }
+
+    /// The root hash of the tree, see `SparseMerkleTree::root`
+    pub fn root(&self) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        self.read().root()
+    }
+
+    /// Shared access to the tree, writers wait until the guard is dropped
+    pub fn read(&self) -> RwLockReadGuard<'_, SparseMerkleTree<H, F>> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Exclusive access to the tree, readers and writers wait until the guard is dropped
+    pub fn write(&self) -> RwLockWriteGuard<'_, SparseMerkleTree<H, F>> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn into_inner(self) -> SparseMerkleTree<H, F> {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> From<SparseMerkleTree<H, F>>
+    for SyncSparseMerkleTree<H, F>
+{
+    fn from(tree: SparseMerkleTree<H, F>) -> Self {
+        SyncSparseMerkleTree::new(tree)
+    }
+}
@@ -0,0 +1,206 @@
+//! Metrics of the tree operations, recorded through the `metrics` facade, and their spans
+//!
+//! Without the `metrics` and `tracing` features every function is empty and inlined away.
+
+#[cfg(feature = "metrics")]
+use metrics::{counter, gauge, histogram};
+
+/// Leaves set by inserts, deletes not included
+pub const INSERTS_TOTAL: &str = "merkle_poseidon_inserts_total";
+/// Leaves set back to the empty leaf value
+pub const DELETES_TOTAL: &str = "merkle_poseidon_deletes_total";
+/// Node hashes computed by trees and proofs, leaf and inner nodes alike
+pub const HASHES_TOTAL: &str = "merkle_poseidon_hashes_total";
+/// Proofs generated by trees
+pub const PROOFS_TOTAL: &str = "merkle_poseidon_proofs_total";
+/// Time to generate a proof, in seconds
+pub const PROOF_DURATION_SECONDS: &str = "merkle_poseidon_proof_duration_seconds";
+/// Proofs verified, labelled `result` "valid" or "invalid"
+pub const VERIFICATIONS_TOTAL: &str = "merkle_poseidon_verifications_total";
+/// Leaves not holding the empty leaf value in the tree last mutated
+pub const LEAVES: &str = "merkle_poseidon_leaves";
+
+#[inline]
+#[cfg(feature = "tree")]
+pub(crate) fn record_insert() {
+    #[cfg(feature = "metrics")]
+    counter!(INSERTS_TOTAL).increment(1);
+}
+
+#[inline]
+#[cfg(feature = "tree")]
+pub(crate) fn record_delete() {
+    #[cfg(feature = "metrics")]
+    counter!(DELETES_TOTAL).increment(1);
+}
+
+#[inline]
+pub(crate) fn record_hash() {
+    #[cfg(any(feature = "metrics", feature = "tracing"))]
+    if reporting() {
+        #[cfg(feature = "metrics")]
+        counter!(HASHES_TOTAL).increment(1);
+        #[cfg(feature = "tracing")]
+        SPAN_COUNTS.with(|counts| counts.hashes.set(counts.hashes.get() + 1));
+    }
+}
+
+#[inline]
+#[cfg(feature = "tree")]
+pub(crate) fn record_rehash() {
+    #[cfg(feature = "tracing")]
+    SPAN_COUNTS.with(|counts| counts.rehashed.set(counts.rehashed.get() + 1));
+}
+
+#[inline]
+pub(crate) fn record_verification(_valid: bool) {
+    #[cfg(feature = "metrics")]
+    counter!(VERIFICATIONS_TOTAL, "result" => if _valid { "valid" } else { "invalid" })
+        .increment(1);
+}
+
+#[inline]
+#[cfg(feature = "metrics")]
+pub(crate) fn record_leaves(leaves: usize) {
+    gauge!(LEAVES).set(leaves as f64);
+}
+
+/// Times a proof generation, recorded once it succeeded
+#[cfg(feature = "tree")]
+pub(crate) struct ProofTimer {
+    #[cfg(feature = "metrics")]
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "tree")]
+impl ProofTimer {
+    #[inline]
+    pub(crate) fn start() -> Self {
+        ProofTimer {
+            #[cfg(feature = "metrics")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn finish(self) {
+        #[cfg(feature = "metrics")]
+        {
+            counter!(PROOFS_TOTAL).increment(1);
+            histogram!(PROOF_DURATION_SECONDS).record(self.start.elapsed());
+        }
+    }
+}
+
+/// Whether hashes are reported, to the metrics, the spans and the trace sink
+///
+/// The `paranoid` checks are not part of the operations, their hashes are computed `unreported`.
+#[inline]
+#[cfg(any(feature = "metrics", feature = "tracing", feature = "hash-trace"))]
+pub(crate) fn reporting() -> bool {
+    #[cfg(feature = "paranoid")]
+    return !UNREPORTED.with(core::cell::Cell::get);
+    #[cfg(not(feature = "paranoid"))]
+    true
+}
+
+/// Run `f` without reporting its hashes
+#[cfg(feature = "paranoid")]
+pub(crate) fn unreported<R>(f: impl FnOnce() -> R) -> R {
+    let previous = UNREPORTED.with(|unreported| unreported.replace(true));
+    let result = f();
+    UNREPORTED.with(|unreported| unreported.set(previous));
+    result
+}
+
+#[cfg(feature = "paranoid")]
+std::thread_local! {
+    static UNREPORTED: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
+
+/// Hashes and rehashed dirty nodes of this thread, the spans record how much they grew
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+struct SpanCounts {
+    hashes: core::cell::Cell<u64>,
+    rehashed: core::cell::Cell<u64>,
+}
+
+#[cfg(feature = "tracing")]
+std::thread_local! {
+    static SPAN_COUNTS: SpanCounts = SpanCounts::default();
+}
+
+/// The entered span of a tree operation, see `operation_span!`
+///
+/// When it's dropped, the span records the `hashes` computed and the dirty nodes `rehashed`
+/// since it was entered.
+#[cfg(feature = "tree")]
+pub(crate) struct OperationSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "tracing")]
+    start: (u64, u64),
+}
+
+#[cfg(feature = "tree")]
+impl OperationSpan {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn enter(span: tracing::Span) -> Self {
+        let start = SPAN_COUNTS.with(|counts| (counts.hashes.get(), counts.rehashed.get()));
+        OperationSpan {
+            span: span.entered(),
+            start,
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    #[inline]
+    pub(crate) fn disabled() -> Self {
+        OperationSpan {}
+    }
+
+    /// Record a leaf value, only with the `trace-values` feature
+    #[inline]
+    pub(crate) fn record_value<F: core::fmt::Display>(&self, _value: &F) {
+        #[cfg(feature = "trace-values")]
+        self.span.record("value", tracing::field::display(_value));
+    }
+}
+
+#[cfg(all(feature = "tree", feature = "tracing"))]
+impl Drop for OperationSpan {
+    fn drop(&mut self) {
+        if self.span.is_disabled() {
+            return;
+        }
+
+        let (hashes, rehashed) =
+            SPAN_COUNTS.with(|counts| (counts.hashes.get(), counts.rehashed.get()));
+        self.span.record("hashes", hashes - self.start.0);
+        self.span.record("rehashed", rehashed - self.start.1);
+    }
+}
+
+/// Enter a debug span named after a tree operation, with the given fields
+///
+/// The span also declares the `hashes` and `rehashed` fields filled in by `OperationSpan` and
+/// `value`, only filled in with `trace-values`. Without `tracing` the fields are not evaluated.
+#[cfg(feature = "tree")]
+macro_rules! operation_span {
+    ($name:literal, $($fields:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let span = $crate::telemetry::OperationSpan::enter(tracing::debug_span!(
+            $name,
+            $($fields)*,
+            hashes = tracing::field::Empty,
+            rehashed = tracing::field::Empty,
+            value = tracing::field::Empty,
+        ));
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::telemetry::OperationSpan::disabled();
+        span
+    }};
+}
+#[cfg(feature = "tree")]
+pub(crate) use operation_span;
@@ -0,0 +1,261 @@
+//! Cheap hashers and random trees, paths and entries for the tests of code built on the trees
+
+#[cfg(feature = "tree")]
+use alloc::collections::BTreeSet;
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+#[cfg(feature = "tree")]
+use chacha20::{rand_core::SeedableRng, ChaCha8Rng};
+#[cfg(feature = "tree")]
+use light_poseidon::Poseidon;
+use light_poseidon::PoseidonError;
+use proptest::{collection, prelude::*};
+use rand::{Rng, RngExt};
+
+#[cfg(feature = "tree")]
+use crate::SparseMerkleTree;
+use crate::{Arity, HashFamily, MerkleHasher, MerklePath, MAX_ARITY};
+
+/// Cheap non-ZK `MerkleHasher` over bn254, for fast tests
+///
+/// Absorbs the input count and the little-endian bytes of every input into blake3, and maps the
+/// digest into `Fr`. It accepts one to `MAX_ARITY` inputs, so it fits trees of every arity, and
+/// reports `HashFamily::Custom`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestHasher;
+
+impl TestHasher {
+    pub fn new() -> Self {
+        TestHasher
+    }
+}
+
+impl MerkleHasher<Fr> for TestHasher {
+    fn hash_children(&mut self, children: &[Fr]) -> Result<Fr, PoseidonError> {
+        if children.is_empty() {
+            return Err(PoseidonError::EmptyInput);
+        }
+        if children.len() > MAX_ARITY {
+            return Err(PoseidonError::InvalidNumberOfInputs {
+                inputs: children.len(),
+                max_limit: MAX_ARITY,
+                width: MAX_ARITY + 1,
+            });
+        }
+
+        let mut sponge = blake3::Hasher::new();
+        sponge.update(&[children.len() as u8]);
+        for child in children {
+            sponge.update(&child.into_bigint().to_bytes_le());
+        }
+
+        Ok(Fr::from_le_bytes_mod_order(sponge.finalize().as_bytes()))
+    }
+
+    fn hash_family(&self) -> Option<HashFamily> {
+        Some(HashFamily::Custom)
+    }
+}
+
+/// A bn254 hasher counting the hashes it computes, its clones share the count
+///
+/// Keep a clone to read the count of the one given to a tree.
+#[derive(Debug, Clone, Default)]
+pub struct CountingHasher<H = TestHasher> {
+    inner: H,
+    count: Arc<AtomicUsize>,
+}
+
+impl<H> CountingHasher<H> {
+    pub fn new(inner: H) -> Self {
+        CountingHasher {
+            inner,
+            count: Arc::default(),
+        }
+    }
+
+    /// The hashes computed so far, by this hasher and its clones
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+    }
+
+    fn counted<T>(&self, hash: T) -> T {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        hash
+    }
+}
+
+impl<H: MerkleHasher<Fr>> MerkleHasher<Fr> for CountingHasher<H> {
+    fn hash_children(&mut self, children: &[Fr]) -> Result<Fr, PoseidonError> {
+        let hash = self.inner.hash_children(children);
+        self.counted(hash)
+    }
+
+    fn hash_two(&mut self, left: &Fr, right: &Fr) -> Result<Fr, PoseidonError> {
+        let hash = self.inner.hash_two(left, right);
+        self.counted(hash)
+    }
+
+    fn empty_hash(&mut self, arity: Arity) -> Result<Fr, PoseidonError> {
+        let hash = self.inner.empty_hash(arity);
+        self.counted(hash)
+    }
+
+    fn hash_family(&self) -> Option<HashFamily> {
+        self.inner.hash_family()
+    }
+
+    fn params_fingerprint(&mut self, arity: Arity) -> Result<Fr, PoseidonError> {
+        let hash = self.inner.params_fingerprint(arity);
+        self.counted(hash)
+    }
+}
+
+/// The path of a binary tree of `depth` levels whose little-endian bits are `bits`
+fn path_from_bits(depth: usize, mut bits: Vec<bool>) -> MerklePath {
+    bits.truncate(depth);
+    Fr::from_bigint(<Fr as PrimeField>::BigInt::from_bits_le(&bits))
+        .expect("a path is below the modulus")
+}
+
+/// A uniformly random path of a binary tree of `depth` levels
+pub fn arbitrary_path<R: Rng + ?Sized>(depth: usize, rng: &mut R) -> MerklePath {
+    path_from_bits(depth, (0..depth).map(|_| rng.random()).collect())
+}
+
+/// A uniformly random field element
+pub fn arbitrary_value<R: Rng + ?Sized>(rng: &mut R) -> Fr {
+    Fr::from_le_bytes_mod_order(&rng.random::<[u8; 32]>())
+}
+
+/// A binary `TestHasher` tree of a random depth, holding random values at distinct random paths
+///
+/// Returns the tree and its entries in insertion order, so they can be replayed into a tree of
+/// another hasher. The leaf count is capped at the leaves the depth offers.
+///
+/// # Panics
+///
+/// If a range is empty, or the depth range reaches 0 or past `MAX_PATH_BITS`.
+#[cfg(feature = "tree")]
+pub fn arbitrary_tree<R: Rng + ?Sized>(
+    depth_range: Range<usize>,
+    leaf_count_range: Range<usize>,
+    rng: &mut R,
+) -> (SparseMerkleTree<TestHasher>, Vec<(MerklePath, Fr)>) {
+    let depth = rng.random_range(depth_range);
+    let capacity = u32::try_from(depth)
+        .ok()
+        .and_then(|depth| 1usize.checked_shl(depth))
+        .unwrap_or(usize::MAX);
+    let leaf_count = rng.random_range(leaf_count_range).min(capacity);
+
+    let mut paths = BTreeSet::new();
+    let mut entries = Vec::with_capacity(leaf_count);
+    while entries.len() < leaf_count {
+        let path = arbitrary_path(depth, rng);
+        if paths.insert(path) {
+            entries.push((path, arbitrary_value(rng)));
+        }
+    }
+
+    let mut tree =
+        SparseMerkleTree::new_with_hasher(depth, TestHasher::new()).expect("the depth is in range");
+    tree.insert_many(entries.iter().copied())
+        .expect("the test hasher doesn't fail");
+
+    (tree, entries)
+}
+
+/// `leaf_count` distinct leaf indices of a binary tree of `depth` levels and their values, drawn
+/// from `seed`
+///
+/// The same seed gives the same leaves on every platform. The generator is ChaCha8 keyed with the
+/// little-endian bytes of the seed followed by zeros. Each leaf takes a `next_u64`, of which only
+/// the low `depth` bits are kept, drawing again while the index was already drawn, then 32 bytes
+/// of `fill_bytes` read as a little-endian integer modulo the field order for its value. The
+/// leaves are returned in the order they were drawn.
+///
+/// # Panics
+///
+/// If the tree has fewer than `leaf_count` leaves.
+#[cfg(feature = "tree")]
+pub fn random_leaves(depth: usize, leaf_count: usize, seed: u64) -> Vec<(u64, Fr)> {
+    let mask = match depth {
+        0..64 => (1u64 << depth) - 1,
+        _ => u64::MAX,
+    };
+    assert!(
+        leaf_count as u128 <= u128::from(mask) + 1,
+        "a tree of {depth} levels has fewer than {leaf_count} leaves"
+    );
+
+    let mut key = [0; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    let mut rng = ChaCha8Rng::from_seed(key);
+    let mut indices = BTreeSet::new();
+    let mut leaves = Vec::with_capacity(leaf_count);
+    while leaves.len() < leaf_count {
+        let index = rng.next_u64() & mask;
+        if !indices.insert(index) {
+            continue;
+        }
+        let mut bytes = [0; 32];
+        rng.fill_bytes(&mut bytes);
+        leaves.push((index, Fr::from_le_bytes_mod_order(&bytes)));
+    }
+
+    leaves
+}
+
+#[cfg(feature = "tree")]
+impl SparseMerkleTree<Poseidon<Fr>> {
+    /// A circom Poseidon tree of `depth` levels holding the `random_leaves` of `seed`, each at its
+    /// index
+    ///
+    /// The same seed builds the same tree, and root, on every platform, for benchmarks and bug
+    /// reports. The leaves come from `random_leaves` with the same arguments.
+    ///
+    /// # Panics
+    ///
+    /// Like `random_leaves`, or if the depth is 0 or more than a path can address.
+    pub fn random_populated(depth: usize, leaf_count: usize, seed: u64) -> Self {
+        let mut tree = SparseMerkleTree::new(depth).expect("the depth is in range");
+        let leaves = random_leaves(depth, leaf_count, seed);
+        tree.insert_many(
+            leaves
+                .into_iter()
+                .map(|(index, value)| (Fr::from(index), value)),
+        )
+        .expect("poseidon hashes two inputs");
+
+        tree
+    }
+}
+
+/// Paths of a binary tree of `depth` levels, shrinking towards the leftmost leaf
+pub fn merkle_path(depth: usize) -> impl Strategy<Value = MerklePath> {
+    collection::vec(any::<bool>(), depth).prop_map(move |bits| path_from_bits(depth, bits))
+}
+
+/// Field elements, shrinking towards zero
+pub fn field_element() -> impl Strategy<Value = Fr> {
+    any::<[u8; 32]>().prop_map(|bytes| Fr::from_le_bytes_mod_order(&bytes))
+}
+
+/// `(path, value)` entries of a binary tree of `depth` levels, a path may come up several times
+pub fn entries(
+    depth: usize,
+    count_range: Range<usize>,
+) -> impl Strategy<Value = Vec<(MerklePath, Fr)>> {
+    collection::vec((merkle_path(depth), field_element()), count_range)
+}
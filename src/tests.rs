@@ -1,8 +1,80 @@
+use std::{cell::Cell, rc::Rc};
+
 use ark_bn254::Fr;
-use ark_ff::{AdditiveGroup, BigInt, BigInteger, PrimeField};
-use light_poseidon::{Poseidon, PoseidonHasher};
+use ark_ff::{AdditiveGroup, BigInt, BigInteger, Field, PrimeField};
+use light_poseidon::{Poseidon, PoseidonError, PoseidonHasher};
+
+use crate::{
+    get_empty_inner_hash, get_empty_leaf_hash, get_empty_root, validate_constants, zero_hash,
+    zero_hash_with, BitOrder, EmptySubtrees, HashOperation, HashingScheme, IntegrityViolation,
+    LeafHashing, MerkleProof, Node, NodeType, PoseidonMerkleError, ProofError, SparseMerkleTree,
+    DEFAULT_DEPTH, MAX_PATH_BITS,
+};
 
-use crate::{get_empty_inner_hash, PoseidonMerkleError, SparseMerkleTree};
+mod append;
+mod arity;
+#[cfg(feature = "sync")]
+mod background;
+mod batch;
+mod concurrent;
+mod debug;
+mod delta;
+mod depth_one;
+mod domain_separation;
+mod empty_leaf;
+mod empty_subtrees;
+#[cfg(feature = "json")]
+mod encoding;
+mod forest;
+mod frozen;
+mod fuzz;
+mod hashers;
+mod keys;
+mod leaf_values;
+mod level_bound;
+mod metadata;
+#[cfg(feature = "mimc")]
+mod mimc;
+mod multiproof;
+mod node_budget;
+mod non_membership;
+mod nullifier;
+mod observer;
+mod parameters;
+#[cfg(feature = "paranoid")]
+mod paranoid;
+#[cfg(feature = "poseidon2")]
+mod poseidon2;
+mod properties;
+#[cfg(feature = "proto")]
+mod proto;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "r1cs")]
+mod r1cs;
+mod random_populated;
+mod registry;
+#[cfg(feature = "rescue")]
+mod rescue;
+mod send_sync;
+mod shared_reads;
+mod snapshot;
+#[cfg(feature = "solana")]
+mod solana;
+mod stats;
+#[cfg(feature = "json")]
+mod structure;
+#[cfg(feature = "sync")]
+mod sync_tree;
+#[cfg(feature = "hash-trace")]
+mod trace;
+mod typed_values;
+mod vectors;
+mod verify;
+#[cfg(feature = "sync")]
+mod versioned;
+#[cfg(feature = "wasm")]
+mod wasm;
 
 const DEPTH: usize = 2;
 const TEST_PATH: [bool; DEPTH] = [true, false];
@@ -23,7 +95,7 @@ fn test_hasher() {
 fn test_new_tree() {
     let tree = setup_tree();
     assert_eq!(tree.depth, 2);
-    assert!(tree.is_empty());
+    assert!(tree.is_empty().unwrap());
 }
 
 #[test]
@@ -32,6 +104,26 @@ fn test_invalid_depth() {
     assert!(matches!(result, Err(PoseidonMerkleError::InvalidDepth)));
 }
 
+#[test]
+fn test_paths_wider_than_the_modulus_are_rejected() {
+    assert_eq!(
+        SparseMerkleTree::new(300).unwrap_err(),
+        PoseidonMerkleError::InvalidBitsPathHash { len: 300, max: 254 }
+    );
+
+    // The deepest tree takes a path reaching the top bit of the modulus, in both bit orders
+    let path = -Fr::ONE;
+    for bit_order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+        let mut tree = SparseMerkleTree::new(254)
+            .unwrap()
+            .with_bit_order(bit_order);
+        tree.insert_at_path(&path, &Fr::from(7u64)).unwrap();
+        assert!(tree
+            .verify_proof(&tree.generate_proof(&path).unwrap())
+            .unwrap());
+    }
+}
+
 #[test]
 fn test_insert_and_get() {
     let mut tree = setup_tree();
@@ -42,9 +134,9 @@ fn test_insert_and_get() {
     tree.insert_at_path(&merkle_path, &value).unwrap();
 
     // Get and verify value
-    let retrieved_value = tree.get_value(&merkle_path).unwrap();
-    assert_eq!(retrieved_value, value);
-    assert!(!tree.is_empty());
+    let retrieved_value = tree.try_get_value(&merkle_path).unwrap();
+    assert_eq!(retrieved_value, Some(value));
+    assert!(!tree.is_empty().unwrap());
 }
 
 #[test]
@@ -71,13 +163,18 @@ fn test_delete() {
     let merkle_path = Fr::from_bigint(BigInt::from_bits_le(&TEST_PATH)).unwrap();
     let value = Fr::from(123u64);
 
+    let empty_root = tree.root().unwrap();
+
     // Insert and then delete
     tree.insert_at_path(&merkle_path, &value).unwrap();
     tree.delete_at_path(&merkle_path).unwrap();
 
-    // Verify value is zero
-    let retrieved_value = tree.get_value(&merkle_path).unwrap();
-    assert_eq!(retrieved_value, Fr::ZERO);
+    // The path is pruned back to the empty tree
+    let retrieved_value = tree.try_get_value(&merkle_path).unwrap();
+    assert_eq!(retrieved_value, None);
+    assert!(tree.is_empty().unwrap());
+    assert_eq!(tree.node_count(), 1);
+    assert_eq!(tree.root().unwrap(), empty_root);
 }
 
 #[test]
@@ -88,7 +185,7 @@ fn test_clear() {
 
     tree.insert_at_path(&merkle_path, &value).unwrap();
     tree.clear();
-    assert!(tree.is_empty());
+    assert!(tree.is_empty().unwrap());
 }
 
 #[test]
@@ -111,6 +208,52 @@ fn test_get_merkle_path() {
     assert_eq!(SparseMerkleTree::get_path_bit(&merkle_path, 1), path[1]);
 }
 
+#[test]
+fn test_get_merkle_path_validates_length() {
+    let tree = SparseMerkleTree::new(4).unwrap();
+
+    let bits = [true, false, true, true];
+    let merkle_path = tree.get_merkle_path(&bits).unwrap();
+    for (position, bit) in bits.iter().enumerate() {
+        assert_eq!(SparseMerkleTree::get_path_bit(&merkle_path, position), *bit);
+    }
+
+    assert_eq!(
+        tree.get_merkle_path(&[true; 5]).unwrap_err(),
+        PoseidonMerkleError::InvalidBitsPathHash { len: 5, max: 4 }
+    );
+
+    let deep_tree = SparseMerkleTree::new(Fr::MODULUS_BIT_SIZE as usize).unwrap();
+    assert_eq!(
+        deep_tree.get_merkle_path(&[true; 254]).unwrap_err(),
+        PoseidonMerkleError::InvalidBitsPathHash {
+            len: 254,
+            max: MAX_PATH_BITS
+        }
+    );
+    assert!(deep_tree.get_merkle_path(&[true; MAX_PATH_BITS]).is_ok());
+}
+
+#[test]
+fn test_get_merkle_path_padded() {
+    let tree = SparseMerkleTree::new(4).unwrap();
+
+    let merkle_path = tree.get_merkle_path_padded(&[false, true]).unwrap();
+    assert_eq!(
+        merkle_path,
+        tree.get_merkle_path(&[false, true, false, false]).unwrap()
+    );
+    let bits: Vec<bool> = (0..4)
+        .map(|position| SparseMerkleTree::get_path_bit(&merkle_path, position))
+        .collect();
+    assert_eq!(bits, [false, true, false, false]);
+
+    assert_eq!(
+        tree.get_merkle_path_padded(&[true; 5]).unwrap_err(),
+        PoseidonMerkleError::InvalidBitsPathHash { len: 5, max: 4 }
+    );
+}
+
 #[test]
 fn test_proof_generation_and_verification() {
     let mut tree = setup_tree();
@@ -171,7 +314,7 @@ fn test_iterator() {
 
     // Collect all entries from iterator
     let tree_iter = tree.iter();
-    let found_entries: Vec<Fr> = tree_iter.collect();
+    let found_entries: Vec<Fr> = tree_iter.collect::<Result<_, _>>().unwrap();
 
     // Verify all inserted entries are found in DFS order
     for (i, value) in found_entries.iter().enumerate() {
@@ -183,5 +326,751 @@ fn test_iterator() {
 fn test_default() {
     let tree = SparseMerkleTree::default();
     assert_eq!(tree.depth, 20);
-    assert!(tree.is_empty());
+    assert!(tree.is_empty().unwrap());
+}
+
+#[test]
+fn test_try_default() {
+    assert_eq!(DEFAULT_DEPTH, 20);
+
+    let tree = SparseMerkleTree::try_default().unwrap();
+    assert_eq!(tree.depth, DEFAULT_DEPTH);
+    assert!(tree.is_empty().unwrap());
+}
+
+/// Recompute the root from scratch to compare against the tracked root
+#[allow(deprecated)]
+fn full_root(tree: &mut SparseMerkleTree<Poseidon<Fr>>) -> Fr {
+    tree.root_hash().unwrap()
+}
+
+#[test]
+fn test_root_fresh_after_mutations() {
+    let mut tree = setup_tree();
+    let merkle_path = Fr::from_bigint(BigInt::from_bits_le(&TEST_PATH)).unwrap();
+    let other_path = Fr::from_bigint(BigInt::from_bits_le(&[false, true])).unwrap();
+
+    tree.insert_at_path(&merkle_path, &Fr::from(100u64))
+        .unwrap();
+    let after_insert = tree.root().unwrap();
+    assert_eq!(after_insert, full_root(&mut tree));
+
+    tree.insert_at_path(&other_path, &Fr::from(200u64)).unwrap();
+    let after_second_insert = tree.root().unwrap();
+    assert_ne!(after_insert, after_second_insert);
+    assert_eq!(after_second_insert, full_root(&mut tree));
+
+    tree.insert_at_path(&merkle_path, &Fr::from(300u64))
+        .unwrap();
+    let after_update = tree.root().unwrap();
+    assert_ne!(after_second_insert, after_update);
+    assert_eq!(after_update, full_root(&mut tree));
+
+    tree.delete_at_path(&other_path).unwrap();
+    let after_delete = tree.root().unwrap();
+    assert_ne!(after_update, after_delete);
+    assert_eq!(after_delete, full_root(&mut tree));
+
+    tree.clear();
+    assert_eq!(tree.root().unwrap(), get_empty_root(DEPTH));
+}
+
+#[test]
+fn test_get_node_on_paths_without_a_leaf() {
+    let mut tree = SparseMerkleTree::new(4).unwrap();
+    let empty = Fr::from(0b1101u64);
+    assert_eq!(
+        tree.get_node(&empty).err(),
+        Some(PoseidonMerkleError::LeafNotReached {
+            path: empty,
+            level: 0
+        })
+    );
+
+    // The paths share their first three bits, the walk stops above the leaf
+    tree.insert_at_path(&Fr::from(0b0101u64), &Fr::from(5u64))
+        .unwrap();
+    assert_eq!(
+        tree.get_node(&empty).err(),
+        Some(PoseidonMerkleError::LeafNotReached {
+            path: empty,
+            level: 3
+        })
+    );
+    assert_eq!(tree.try_get_value(&empty).unwrap(), None);
+    assert_eq!(
+        tree.generate_proof(&empty).err(),
+        Some(PoseidonMerkleError::LeafNotFound { path: empty })
+    );
+}
+
+#[test]
+fn test_root_refreshes_dirty_paths() {
+    let mut tree = setup_tree();
+    let merkle_path = Fr::from_bigint(BigInt::from_bits_le(&TEST_PATH)).unwrap();
+
+    tree.insert_at_path(&merkle_path, &Fr::from(100u64))
+        .unwrap();
+    let before = tree.root().unwrap();
+
+    // Edit the leaf by hand, the cached root is now stale
+    let leaf = tree.get_node(&merkle_path).unwrap();
+    leaf.borrow_mut().node_type = NodeType::Leaf(Fr::from(500u64));
+    assert_eq!(tree.root().unwrap(), before);
+
+    tree.mark_path_dirty(&merkle_path).unwrap();
+    let after = tree.root().unwrap();
+    assert_ne!(after, before);
+    assert_eq!(after, full_root(&mut tree));
+    assert!(!tree.root.borrow().dirty);
+
+    let mut expected = setup_tree();
+    expected
+        .insert_at_path(&merkle_path, &Fr::from(500u64))
+        .unwrap();
+    assert_eq!(after, expected.root().unwrap());
+}
+
+#[test]
+fn test_proofs_fresh_after_dirty_paths() {
+    let mut tree = SparseMerkleTree::new(4).unwrap();
+    let merkle_path = Fr::from(9u64);
+    for (path, value) in [(3u64, 30u64), (9, 90)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+
+    // Proving right after marking a hand edited path must not read the stale hashes
+    let leaf = tree.get_node(&merkle_path).unwrap();
+    leaf.borrow_mut().node_type = NodeType::Leaf(Fr::from(500u64));
+    tree.mark_path_dirty(&merkle_path).unwrap();
+    let proof = tree.generate_proof(&Fr::from(3u64)).unwrap();
+    assert_eq!(proof.root_hash, full_root(&mut tree));
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert!(proof
+        .verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap())
+        .unwrap());
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_proofs_fresh_after_rehashing_modes() {
+    type Tree = SparseMerkleTree<Poseidon<Fr>>;
+    let rehashes: [fn(Tree) -> Tree; 4] = [
+        |tree| tree.with_leaf_hashing(LeafHashing::Hashed),
+        |tree| tree.with_hashing_scheme(HashingScheme::DomainSeparated),
+        |tree| tree.with_empty_subtrees(EmptySubtrees::Flat),
+        |tree| tree.try_with_empty_leaf(Fr::from(1u64)).unwrap(),
+    ];
+
+    for rehash in rehashes {
+        let mut tree = SparseMerkleTree::new(4).unwrap();
+        for (path, value) in [(3u64, 30u64), (9, 90)] {
+            tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+                .unwrap();
+        }
+        let before = tree.root().unwrap();
+
+        // The proof is the first read after the mode changed the hash of every node
+        let mut tree = rehash(tree);
+        let proof = tree.generate_proof(&Fr::from(9u64)).unwrap();
+        assert_ne!(proof.root_hash, before);
+        assert_eq!(proof.root_hash, full_root(&mut tree));
+        assert!(tree.verify_proof(&proof).unwrap());
+        assert_eq!(tree.root().unwrap(), proof.root_hash);
+    }
+}
+
+#[test]
+fn test_canonicalize_path() {
+    let tree = setup_tree();
+    let canonical = tree.get_merkle_path(&TEST_PATH).unwrap();
+
+    let mut bits = TEST_PATH.to_vec();
+    bits.resize(100, false);
+    bits.push(true);
+    let noisy = Fr::from_bigint(BigInt::from_bits_le(&bits)).unwrap();
+
+    assert_ne!(noisy, canonical);
+    assert_eq!(tree.canonicalize_path(&noisy), canonical);
+    assert_eq!(tree.canonicalize_path(&canonical), canonical);
+}
+
+#[test]
+fn test_high_path_bits_are_masked() {
+    let mut tree = setup_tree();
+    let canonical = tree.get_merkle_path(&TEST_PATH).unwrap();
+
+    let mut bits = TEST_PATH.to_vec();
+    bits.extend([true, true, false, true]);
+    let noisy = Fr::from_bigint(BigInt::from_bits_le(&bits)).unwrap();
+    let value = Fr::from(42u64);
+
+    tree.insert_at_path(&noisy, &value).unwrap();
+    assert_eq!(tree.try_get_value(&canonical).unwrap(), Some(value));
+
+    // The proof is keyed by the canonical path, whatever path it was requested with
+    let proof = tree.generate_proof(&noisy).unwrap();
+    assert_eq!(proof.merkle_path, canonical);
+
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    assert!(proof.verify_proof(&mut hasher).unwrap());
+}
+
+#[test]
+fn test_constants_match_hasher() {
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    assert_eq!(validate_constants(&mut hasher), Ok(()));
+
+    let mut leaf_hasher = Poseidon::<Fr>::new_circom(1).unwrap();
+    assert_eq!(
+        leaf_hasher.hash(&[Fr::ZERO]).unwrap(),
+        *get_empty_leaf_hash()
+    );
+    assert_eq!(
+        hasher.hash(&[Fr::ZERO, Fr::ZERO]).unwrap(),
+        *get_empty_inner_hash()
+    );
+}
+
+#[test]
+fn test_constants_mismatch_detected() {
+    let mut tagged_hasher = Poseidon::<Fr>::with_domain_tag_circom(2, Fr::from(1u64)).unwrap();
+    assert_eq!(
+        validate_constants(&mut tagged_hasher),
+        Err(PoseidonMerkleError::ConstantMismatch("empty inner hash"))
+    );
+}
+
+#[test]
+fn test_insert_error_reports_level() {
+    let remaining = Rc::new(Cell::new(usize::MAX));
+    let hasher = FaultyHasher {
+        inner: Poseidon::<Fr>::new_circom(2).unwrap(),
+        remaining: remaining.clone(),
+    };
+    let mut tree = SparseMerkleTree::new_with_hasher(DEPTH, hasher).unwrap();
+    let merkle_path = tree.get_merkle_path(&TEST_PATH).unwrap();
+
+    // The very first hash, right above the leaf, fails
+    remaining.set(0);
+
+    let err = tree
+        .insert_at_path(&merkle_path, &Fr::from(1u64))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        PoseidonMerkleError::HasherError {
+            operation: HashOperation::InnerNode {
+                level: Some(level)
+            },
+            ..
+        } if level == DEPTH - 1
+    ));
+    assert!(err.to_string().contains("at level 1"));
+}
+
+#[test]
+fn test_try_get_value_error_reports_level_and_path() {
+    let mut tree = setup_tree();
+    let merkle_path = tree.get_merkle_path(&TEST_PATH).unwrap();
+    tree.insert_at_path(&merkle_path, &Fr::from(1u64)).unwrap();
+
+    // Replace the inner node at level 1 with a leaf by hand
+    tree.root.borrow_mut().children[1] = Some(Node::new_borrowed_leaf(Fr::from(7u64)));
+
+    let err = tree.try_get_value(&merkle_path).unwrap_err();
+    assert_eq!(
+        err,
+        PoseidonMerkleError::InvalidNodeType {
+            level: 1,
+            path: Some(merkle_path),
+        }
+    );
+}
+
+#[test]
+fn test_proof_error_reports_level() {
+    let mut tree = setup_tree();
+    let merkle_path = tree.get_merkle_path(&TEST_PATH).unwrap();
+    tree.insert_at_path(&merkle_path, &Fr::from(1u64)).unwrap();
+
+    // A leaf where the level 0 sibling (an inner node) belongs
+    tree.root.borrow_mut().children[0] = Some(Node::new_borrowed_leaf(Fr::from(7u64)));
+    tree.mark_path_dirty(&merkle_path).unwrap();
+
+    let err = tree.generate_proof(&merkle_path).unwrap_err();
+    assert_eq!(
+        err,
+        PoseidonMerkleError::ProofError(ProofError::InnerNodeExpected { level: 0 })
+    );
+}
+
+#[test]
+fn test_verify_error_reports_level() {
+    let mut tree = setup_tree();
+    let merkle_path = tree.get_merkle_path(&TEST_PATH).unwrap();
+    tree.insert_at_path(&merkle_path, &Fr::from(1u64)).unwrap();
+    let proof = tree.generate_proof(&merkle_path).unwrap();
+
+    let mut hasher = Poseidon::<Fr>::new_circom(3).unwrap();
+    let err = proof.verify_proof(&mut hasher).unwrap_err();
+    assert!(matches!(
+        err,
+        PoseidonMerkleError::HasherError {
+            operation: HashOperation::ProofVerification { level },
+            ..
+        } if level == DEPTH - 1
+    ));
+}
+
+#[test]
+fn test_proof_with_populated_siblings() {
+    let mut tree = setup_tree();
+    let paths = [[false, false], [true, false], [false, true]];
+    for (i, bits) in paths.iter().enumerate() {
+        let merkle_path = tree.get_merkle_path(bits).unwrap();
+        tree.insert_at_path(&merkle_path, &Fr::from(i as u64 + 1))
+            .unwrap();
+    }
+
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    for bits in paths.iter() {
+        let merkle_path = tree.get_merkle_path(bits).unwrap();
+        let proof = tree.generate_proof(&merkle_path).unwrap();
+        assert!(proof.verify_proof(&mut hasher).unwrap());
+    }
+}
+
+/// Poseidon wrapper failing once its budget of successful hashes is used up
+struct FaultyHasher {
+    inner: Poseidon<Fr>,
+    remaining: Rc<Cell<usize>>,
+}
+
+impl PoseidonHasher<Fr> for FaultyHasher {
+    fn hash(&mut self, inputs: &[Fr]) -> Result<Fr, PoseidonError> {
+        match self.remaining.get() {
+            0 => Err(PoseidonError::EmptyInput),
+            remaining => {
+                self.remaining.set(remaining - 1);
+                self.inner.hash(inputs)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_failed_insert_leaves_tree_untouched() {
+    let remaining = Rc::new(Cell::new(usize::MAX));
+    let hasher = FaultyHasher {
+        inner: Poseidon::<Fr>::new_circom(2).unwrap(),
+        remaining: remaining.clone(),
+    };
+    let mut tree = SparseMerkleTree::new_with_hasher(4, hasher).unwrap();
+
+    let merkle_path = tree.get_merkle_path(&[true, false, true, true]).unwrap();
+    let other_path = tree.get_merkle_path(&[false, false, true, false]).unwrap();
+    tree.insert_at_path(&merkle_path, &Fr::from(1u64)).unwrap();
+    let root_before = tree.root().unwrap();
+
+    // The third hash of the path fails, halfway to the root
+    remaining.set(2);
+    let err = tree
+        .insert_at_path(&merkle_path, &Fr::from(2u64))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        PoseidonMerkleError::HasherError {
+            operation: HashOperation::InnerNode { level: Some(1) },
+            ..
+        }
+    ));
+    assert_eq!(tree.root().unwrap(), root_before);
+    assert_eq!(
+        tree.try_get_value(&merkle_path).unwrap(),
+        Some(Fr::from(1u64))
+    );
+
+    // A failed insert on a fresh path doesn't materialize any node
+    remaining.set(0);
+    assert!(tree.insert_at_path(&other_path, &Fr::from(3u64)).is_err());
+    assert!(tree.root.borrow().left().is_none());
+    assert_eq!(tree.root().unwrap(), root_before);
+
+    // Once the hasher recovers, the tree matches one that never failed
+    remaining.set(usize::MAX);
+    tree.insert_at_path(&merkle_path, &Fr::from(2u64)).unwrap();
+
+    let mut expected = SparseMerkleTree::new(4).unwrap();
+    expected
+        .insert_at_path(&merkle_path, &Fr::from(2u64))
+        .unwrap();
+    assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+}
+
+#[test]
+fn test_hasher_width_is_validated() {
+    let hasher = Poseidon::<Fr>::new_circom(3).unwrap();
+    let result = SparseMerkleTree::new_with_hasher(DEPTH, hasher);
+    assert!(matches!(
+        result,
+        Err(PoseidonMerkleError::InvalidHasherWidth {
+            source: PoseidonError::InvalidNumberOfInputs { .. }
+        })
+    ));
+
+    let tagged_hasher = Poseidon::<Fr>::with_domain_tag_circom(2, Fr::from(1u64)).unwrap();
+    let result = SparseMerkleTree::new_with_hasher(DEPTH, tagged_hasher);
+    assert!(matches!(
+        result,
+        Err(PoseidonMerkleError::ConstantMismatch("empty inner hash"))
+    ));
+
+    let hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    assert!(SparseMerkleTree::new_with_hasher(DEPTH, hasher).is_ok());
+}
+
+#[test]
+fn test_proof_try_new_rejects_invalid_shapes() {
+    let path = Fr::from(1u64);
+    let value = Fr::from(7u64);
+    let root = Fr::from(9u64);
+
+    assert_eq!(
+        MerkleProof::try_new(vec![], path, value, root).unwrap_err(),
+        ProofError::NoSiblings
+    );
+
+    let max = Fr::MODULUS_BIT_SIZE as usize;
+    assert_eq!(
+        MerkleProof::try_new(vec![Fr::ZERO; max + 1], path, value, root).unwrap_err(),
+        ProofError::TooManySiblings { len: max + 1, max }
+    );
+
+    // Bit 2 is set but the proof only covers two levels
+    assert_eq!(
+        MerkleProof::try_new(vec![Fr::ZERO; 2], Fr::from(5u64), value, root).unwrap_err(),
+        ProofError::NonCanonicalPath { depth: 2 }
+    );
+
+    assert_eq!(
+        MerkleProof::try_new(vec![Fr::ZERO; 2], path, *get_empty_inner_hash(), root).unwrap_err(),
+        ProofError::EmptyNodeHash
+    );
+
+    assert!(MerkleProof::try_new(vec![Fr::ZERO; 2], path, value, root).is_ok());
+}
+
+#[test]
+fn test_generated_proof_passes_validation() {
+    let mut tree = setup_tree();
+    let path = Fr::from(6u64);
+    tree.insert_at_path(&path, &Fr::from(11u64)).unwrap();
+
+    let proof = tree.generate_proof(&path).unwrap();
+    let rebuilt = MerkleProof::try_new(
+        proof.siblings.clone(),
+        proof.merkle_path,
+        proof.leaf_value,
+        proof.root_hash,
+    )
+    .unwrap();
+    assert_eq!(rebuilt.merkle_path, Fr::from(2u64));
+}
+
+/// Depth-1 tree whose leaves are the level-1 hashes of a depth-2 tree over `leaves`, and that tree
+fn crafted_collision(leaf_hashing: LeafHashing) -> (Fr, Fr) {
+    let leaves = [1u64, 2, 3, 4].map(Fr::from);
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+
+    let mut deep = SparseMerkleTree::new(2)
+        .unwrap()
+        .with_leaf_hashing(leaf_hashing);
+    // Path bit 0 picks the level-1 subtree and bit 1 the leaf below it
+    for (path, leaf) in [0u64, 2, 1, 3].into_iter().zip(leaves) {
+        deep.insert_at_path(&Fr::from(path), &leaf).unwrap();
+    }
+
+    let mut shallow = SparseMerkleTree::new(1)
+        .unwrap()
+        .with_leaf_hashing(leaf_hashing);
+    let left = hasher.hash(&[leaves[0], leaves[1]]).unwrap();
+    let right = hasher.hash(&[leaves[2], leaves[3]]).unwrap();
+    shallow.insert_at_path(&Fr::from(0u64), &left).unwrap();
+    shallow.insert_at_path(&Fr::from(1u64), &right).unwrap();
+
+    (deep.root().unwrap(), shallow.root().unwrap())
+}
+
+#[test]
+fn test_leaf_hashing_closes_crafted_collision() {
+    let (deep_root, shallow_root) = crafted_collision(LeafHashing::Raw);
+    assert_eq!(deep_root, shallow_root);
+
+    let (deep_root, shallow_root) = crafted_collision(LeafHashing::Hashed);
+    assert_ne!(deep_root, shallow_root);
+}
+
+#[test]
+fn test_leaf_hashing_is_consistent() {
+    let mut tree = setup_tree().with_leaf_hashing(LeafHashing::Hashed);
+    assert_eq!(tree.leaf_hashing(), LeafHashing::Hashed);
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(5u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(2u64), &Fr::from(6u64))
+        .unwrap();
+
+    let root = tree.root().unwrap();
+    assert_eq!(full_root(&mut tree), root);
+
+    let mut leaf_hasher = Poseidon::<Fr>::new_circom(1).unwrap();
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    let mut leaf = |value: u64| leaf_hasher.hash(&[Fr::from(value)]).unwrap();
+    let (five, six) = (leaf(5), leaf(6));
+    let left = hasher.hash(&[*get_empty_leaf_hash(), six]).unwrap();
+    let right = hasher.hash(&[five, *get_empty_leaf_hash()]).unwrap();
+    assert_eq!(root, hasher.hash(&[left, right]).unwrap());
+
+    let proof = tree.generate_proof(&Fr::from(1u64)).unwrap();
+    assert_eq!(proof.leaf_hashing, LeafHashing::Hashed);
+    assert!(proof.verify_proof(&mut hasher).unwrap());
+
+    // The same proof read as a raw-leaf proof doesn't verify
+    let raw = proof.with_leaf_hashing(LeafHashing::Raw);
+    assert!(!raw.verify_proof(&mut hasher).unwrap());
+}
+
+#[test]
+fn test_switching_leaf_hashing_rehashes_existing_nodes() {
+    let mut tree = setup_tree();
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(9u64))
+        .unwrap();
+    let raw_root = tree.root().unwrap();
+
+    let tree = tree.with_leaf_hashing(LeafHashing::Hashed);
+    let mut expected = setup_tree().with_leaf_hashing(LeafHashing::Hashed);
+    expected
+        .insert_at_path(&Fr::from(3u64), &Fr::from(9u64))
+        .unwrap();
+
+    let hashed_root = tree.root().unwrap();
+    assert_ne!(hashed_root, raw_root);
+    assert_eq!(hashed_root, expected.root().unwrap());
+}
+
+#[test]
+fn test_try_get_value_states() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    let written = Fr::from(5u64);
+    let deleted = Fr::from(4u64);
+    let zero = Fr::from(7u64);
+    tree.insert_at_path(&written, &Fr::from(42u64)).unwrap();
+    tree.insert_at_path(&deleted, &Fr::from(1u64)).unwrap();
+    tree.insert_at_path(&zero, &Fr::ZERO).unwrap();
+    tree.delete_at_path(&deleted).unwrap();
+
+    // Never written, both under a missing subtree and next to a written leaf, or deleted
+    assert_eq!(tree.try_get_value(&Fr::from(2u64)).unwrap(), None);
+    assert_eq!(tree.try_get_value(&Fr::from(1u64)).unwrap(), None);
+    assert_eq!(tree.try_get_value(&deleted).unwrap(), None);
+    assert_eq!(tree.try_get_value(&zero).unwrap(), Some(Fr::ZERO));
+    assert_eq!(tree.try_get_value(&written).unwrap(), Some(Fr::from(42u64)));
+
+    #[allow(deprecated)]
+    let value = tree.get_value(&Fr::from(2u64)).unwrap();
+    assert_eq!(value, Fr::ZERO);
+
+    assert_eq!(
+        tree.generate_proof(&Fr::from(2u64)).unwrap_err(),
+        PoseidonMerkleError::LeafNotFound {
+            path: Fr::from(2u64)
+        }
+    );
+}
+
+#[cfg(feature = "sync")]
+static_assertions::assert_impl_all!(SparseMerkleTree<Poseidon<Fr>>: Send, Sync);
+#[cfg(not(feature = "sync"))]
+static_assertions::assert_not_impl_any!(SparseMerkleTree<Poseidon<Fr>>: Send, Sync);
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_tree_moves_across_threads() {
+    let mut tree = setup_tree();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(10u64))
+        .unwrap();
+
+    let (tree, proof) = std::thread::spawn(move || {
+        tree.insert_at_path(&Fr::from(2u64), &Fr::from(20u64))
+            .unwrap();
+        let proof = tree.generate_proof(&Fr::from(2u64)).unwrap();
+        (tree, proof)
+    })
+    .join()
+    .unwrap();
+
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    assert!(proof.verify_proof(&mut hasher).unwrap());
+    assert_eq!(proof.root_hash, tree.root().unwrap());
+    assert_eq!(
+        tree.try_get_value(&Fr::from(1u64)).unwrap(),
+        Some(Fr::from(10u64))
+    );
+}
+
+#[test]
+fn test_zero_hash() {
+    let depth = 4;
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+
+    assert_eq!(zero_hash(depth, depth), Fr::ZERO);
+    assert_eq!(zero_hash(depth - 1, depth), *get_empty_inner_hash());
+    assert_eq!(
+        zero_hash(depth - 1, depth),
+        hasher.hash(&[Fr::ZERO, Fr::ZERO]).unwrap()
+    );
+    for level in 0..depth {
+        let child = zero_hash(level + 1, depth);
+        assert_eq!(
+            zero_hash(level, depth),
+            hasher.hash(&[child, child]).unwrap()
+        );
+    }
+    // Only the height of the subtree matters
+    assert_eq!(zero_hash(1, 3), zero_hash(7, 9));
+
+    let empty_leaf = *get_empty_leaf_hash();
+    assert_eq!(
+        zero_hash_with(depth, depth, LeafHashing::Hashed),
+        empty_leaf
+    );
+    assert_eq!(
+        zero_hash_with(depth - 1, depth, LeafHashing::Hashed),
+        hasher.hash(&[empty_leaf, empty_leaf]).unwrap()
+    );
+}
+
+#[test]
+#[should_panic(expected = "level 3 is below the depth 2")]
+fn test_zero_hash_rejects_level_below_depth() {
+    zero_hash(3, 2);
+}
+
+#[test]
+fn test_verify_integrity() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    assert_eq!(tree.verify_integrity(), Ok(()));
+
+    for (path, value) in [(1u64, 10u64), (5, 50), (6, 60)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.delete_at_path(&Fr::from(6u64)).unwrap();
+    assert_eq!(tree.verify_integrity(), Ok(()));
+
+    let mut hashed = SparseMerkleTree::new(3)
+        .unwrap()
+        .with_leaf_hashing(LeafHashing::Hashed);
+    hashed
+        .insert_at_path(&Fr::from(3u64), &Fr::from(30u64))
+        .unwrap();
+    assert_eq!(hashed.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_verify_integrity_reports_corrupted_node() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    for (path, value) in [(1u64, 10u64), (5, 50), (2, 20)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+
+    // Path 5 is 101: right from the root, then left
+    let node = tree.get_inner_node(&Fr::from(5u64), 2).unwrap();
+    let recomputed = *node.borrow().node_type.data();
+    node.borrow_mut().node_type = NodeType::Inner(Fr::from(1u64));
+
+    assert_eq!(
+        tree.verify_integrity(),
+        Err(PoseidonMerkleError::IntegrityViolations(vec![
+            IntegrityViolation {
+                level: 2,
+                path: vec![true, false],
+                cached: Fr::from(1u64),
+                recomputed,
+            }
+        ]))
+    );
+}
+
+#[test]
+fn test_tree_verify_proof() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    tree.insert_at_path(&Fr::from(2u64), &Fr::from(20u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(7u64), &Fr::from(70u64))
+        .unwrap();
+
+    let proof = tree.generate_proof(&Fr::from(2u64)).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+
+    tree.insert_at_path(&Fr::from(4u64), &Fr::from(40u64))
+        .unwrap();
+    assert!(!tree.verify_proof(&proof).unwrap());
+    let proof = tree.generate_proof(&Fr::from(2u64)).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+
+    let mut other = SparseMerkleTree::new(4).unwrap();
+    other
+        .insert_at_path(&Fr::from(2u64), &Fr::from(20u64))
+        .unwrap();
+    let other_proof = other.generate_proof(&Fr::from(2u64)).unwrap();
+    assert_eq!(
+        tree.verify_proof(&other_proof).unwrap_err(),
+        PoseidonMerkleError::ProofError(ProofError::DepthMismatch {
+            expected: 3,
+            actual: 4
+        })
+    );
+}
+
+/// Plain pass-through hasher, standing in for caching or instrumented wrappers
+struct WrapperHasher(Poseidon<Fr>);
+
+impl PoseidonHasher<Fr> for WrapperHasher {
+    fn hash(&mut self, inputs: &[Fr]) -> Result<Fr, PoseidonError> {
+        self.0.hash(inputs)
+    }
+}
+
+#[test]
+fn test_tree_with_wrapper_hasher() {
+    let hasher = WrapperHasher(Poseidon::<Fr>::new_circom(2).unwrap());
+    let mut tree = SparseMerkleTree::new_with_hasher(3, hasher).unwrap();
+    let mut reference = SparseMerkleTree::new(3).unwrap();
+
+    for (path, value) in [(1u64, 10u64), (6, 60), (3, 30)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+        reference
+            .insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.delete_at_path(&Fr::from(3u64)).unwrap();
+    reference.delete_at_path(&Fr::from(3u64)).unwrap();
+    assert_eq!(tree.root().unwrap(), reference.root().unwrap());
+    assert_eq!(
+        tree.try_get_value(&Fr::from(6u64)).unwrap(),
+        Some(Fr::from(60u64))
+    );
+    assert!(tree.get_inner_node(&Fr::from(6u64), 2).is_ok());
+    assert_eq!(tree.iter().count(), 2);
+
+    let proof = tree.generate_proof(&Fr::from(6u64)).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+    let mut hasher = WrapperHasher(Poseidon::<Fr>::new_circom(2).unwrap());
+    assert!(proof.verify_proof(&mut hasher).unwrap());
+    assert_eq!(tree.verify_integrity(), Ok(()));
 }
@@ -0,0 +1,117 @@
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{
+    Arity, BitOrder, HashingScheme, IndexedAppendTree, PoseidonMerkleError, SparseMerkleTree,
+    TestHasher,
+};
+
+fn msb_tree(depth: usize) -> SparseMerkleTree<TestHasher> {
+    SparseMerkleTree::new_with_hasher(depth, TestHasher)
+        .unwrap()
+        .with_bit_order(BitOrder::MsbFirst)
+}
+
+/// Append `count` values, checking the root against `insert_at_index` on a plain tree each time
+fn append_and_compare(
+    append_tree: &mut IndexedAppendTree<TestHasher>,
+    plain: &mut SparseMerkleTree<TestHasher>,
+    count: u64,
+) {
+    for _ in 0..count {
+        let value = Fr::from(append_tree.next_index() * 7 + 1);
+        let index = append_tree.append(&value).unwrap();
+        plain.insert_at_index(index, &value).unwrap();
+        assert_eq!(append_tree.root().unwrap(), plain.root().unwrap());
+    }
+}
+
+#[test]
+fn test_appends_match_insert_at_index() {
+    let trees = [
+        msb_tree(6),
+        msb_tree(6).with_hashing_scheme(HashingScheme::LevelBound),
+        SparseMerkleTree::new_with_arity(3, Arity::Quaternary, TestHasher)
+            .unwrap()
+            .with_bit_order(BitOrder::MsbFirst),
+    ];
+    for tree in trees {
+        let mut plain = SparseMerkleTree::new_with_arity(tree.depth, tree.arity(), TestHasher)
+            .unwrap()
+            .with_hashing_scheme(tree.hashing_scheme())
+            .with_bit_order(BitOrder::MsbFirst);
+        let mut append_tree = IndexedAppendTree::new(tree).unwrap();
+
+        append_and_compare(&mut append_tree, &mut plain, 41);
+        assert_eq!(append_tree.next_index(), 41);
+        for index in [0, 17, 40] {
+            let proof = append_tree.generate_proof_at_index(index).unwrap();
+            assert_eq!(proof.merkle_path, Fr::from(index));
+            assert_eq!(proof.leaf_value, Fr::from(index * 7 + 1));
+            assert!(plain.verify_proof(&proof).unwrap());
+        }
+        assert_eq!(append_tree.get(41).unwrap(), None);
+    }
+}
+
+#[test]
+fn test_appends_fill_the_tree_exactly() {
+    let mut plain = msb_tree(4);
+    let mut append_tree = IndexedAppendTree::new(msb_tree(4)).unwrap();
+
+    append_and_compare(&mut append_tree, &mut plain, 16);
+    assert_eq!(
+        append_tree.append(&Fr::from(1u64)),
+        Err(PoseidonMerkleError::TreeFull)
+    );
+    assert_eq!(append_tree.next_index(), 16);
+    assert_eq!(append_tree.root().unwrap(), plain.root().unwrap());
+    assert_eq!(
+        plain.insert_at_index(16, &Fr::from(1u64)),
+        Err(PoseidonMerkleError::IndexOutOfRange {
+            index: 16,
+            size: 16
+        })
+    );
+}
+
+#[test]
+fn test_append_tree_resumes_from_a_snapshot() {
+    let mut append_tree = IndexedAppendTree::new(
+        SparseMerkleTree::new(8)
+            .unwrap()
+            .with_bit_order(BitOrder::MsbFirst),
+    )
+    .unwrap();
+    for value in 1..=11u64 {
+        append_tree.append(&Fr::from(value)).unwrap();
+    }
+
+    let bytes = append_tree.tree().to_snapshot_bytes().unwrap();
+    let mut restored = IndexedAppendTree::new(
+        SparseMerkleTree::<Poseidon<Fr>>::from_snapshot_bytes(&bytes).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(restored.next_index(), 11);
+    assert_eq!(restored.root().unwrap(), append_tree.root().unwrap());
+
+    for value in 12..=20u64 {
+        assert_eq!(
+            restored.append(&Fr::from(value)).unwrap(),
+            append_tree.append(&Fr::from(value)).unwrap()
+        );
+    }
+    assert_eq!(restored.root().unwrap(), append_tree.root().unwrap());
+}
+
+#[test]
+fn test_append_tree_needs_msb_first_paths() {
+    let tree = SparseMerkleTree::new_with_hasher(4, TestHasher).unwrap();
+    assert!(matches!(
+        IndexedAppendTree::new(tree),
+        Err(PoseidonMerkleError::BitOrderMismatch {
+            expected: BitOrder::MsbFirst,
+            actual: BitOrder::LsbFirst
+        })
+    ));
+}
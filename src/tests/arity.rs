@@ -0,0 +1,204 @@
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, Field};
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{
+    get_empty_inner_hash_for, Arity, BitOrder, EmptySubtrees, MerkleProof, PoseidonMerkleError,
+    ProofError, SparseMerkleTree,
+};
+
+fn hasher(arity: Arity) -> Poseidon<Fr> {
+    Poseidon::<Fr>::new_circom(arity.children()).unwrap()
+}
+
+fn tree(depth: usize, arity: Arity) -> SparseMerkleTree<Poseidon<Fr>> {
+    SparseMerkleTree::new_with_arity(depth, arity, hasher(arity)).unwrap()
+}
+
+#[test]
+fn test_binary_arity_is_the_default() {
+    // `new` folds its empty subtrees, unlike the other constructors
+    let mut default_tree = SparseMerkleTree::new(4).unwrap();
+    let mut binary_tree = tree(4, Arity::Binary).with_empty_subtrees(EmptySubtrees::Folded);
+    assert_eq!(default_tree.arity(), Arity::Binary);
+
+    for (path, value) in [(3u64, 10u64), (12, 20), (5, 30)] {
+        default_tree
+            .insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+        binary_tree
+            .insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+
+    assert_eq!(default_tree.root().unwrap(), binary_tree.root().unwrap());
+    let proof = binary_tree.generate_proof(&Fr::from(12u64)).unwrap();
+    assert_eq!(proof.siblings.len(), 4);
+    assert!(default_tree.verify_proof(&proof).unwrap());
+}
+
+#[test]
+fn test_quaternary_tree_proves_and_verifies() {
+    let mut tree = tree(4, Arity::Quaternary);
+    assert_eq!(tree.path_bits(), 8);
+
+    // Child indexes 3, 2, 1, 0 from the root down
+    let path = tree
+        .get_merkle_path(&[true, true, false, true, true, false, false, false])
+        .unwrap();
+    let value = Fr::from(42u64);
+    tree.insert_at_path(&path, &value).unwrap();
+    tree.insert_at_path(&Fr::from(0u64), &Fr::from(7u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(255u64), &Fr::from(8u64))
+        .unwrap();
+    assert_eq!(tree.try_get_value(&path).unwrap(), Some(value));
+    assert_eq!(tree.verify_integrity(), Ok(()));
+
+    let proof = tree.generate_proof(&path).unwrap();
+    assert_eq!(proof.arity, Arity::Quaternary);
+    assert_eq!(proof.depth(), 4);
+    assert_eq!(proof.siblings.len(), 4 * 3);
+    for (level, position) in [3, 2, 1, 0].into_iter().enumerate() {
+        assert_eq!(proof.level_siblings(level).len(), 3);
+        assert_eq!(proof.position(level), position);
+    }
+
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert!(proof.verify_proof(&mut hasher(Arity::Quaternary)).unwrap());
+
+    let mut forged = proof.clone();
+    forged.leaf_value = Fr::from(43u64);
+    assert!(!forged.verify_proof(&mut hasher(Arity::Quaternary)).unwrap());
+}
+
+#[test]
+fn test_quaternary_root_matches_manual_hashing() {
+    let mut tree = tree(2, Arity::Quaternary);
+    // Index 2 at the root, index 1 below it
+    tree.insert_at_path(&Fr::from(0b0110u64), &Fr::from(5u64))
+        .unwrap();
+
+    let mut poseidon = hasher(Arity::Quaternary);
+    let empty_inner = *get_empty_inner_hash_for(Arity::Quaternary);
+    let bottom = poseidon
+        .hash(&[Fr::ZERO, Fr::from(5u64), Fr::ZERO, Fr::ZERO])
+        .unwrap();
+    let root = poseidon
+        .hash(&[empty_inner, empty_inner, bottom, empty_inner])
+        .unwrap();
+
+    assert_eq!(tree.root().unwrap(), root);
+}
+
+#[test]
+fn test_octal_tree_proves_and_verifies() {
+    let mut tree = tree(3, Arity::Octal);
+    assert_eq!(tree.path_bits(), 9);
+
+    // Index 6 at the root, ahead of the leaf at index 7 in iteration order
+    let path = Fr::from(0b101_011_110u64);
+    tree.insert_at_path(&path, &Fr::from(1u64)).unwrap();
+    tree.insert_at_path(&Fr::from(7u64), &Fr::from(2u64))
+        .unwrap();
+
+    let proof = tree.generate_proof(&path).unwrap();
+    assert_eq!(proof.siblings.len(), 3 * 7);
+    assert_eq!(proof.position(0), 0b110);
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert_eq!(tree.verify_integrity(), Ok(()));
+    assert_eq!(
+        tree.iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        [Fr::from(1u64), Fr::from(2u64)]
+    );
+}
+
+#[test]
+fn test_paths_consume_bits_per_level() {
+    let tree = tree(3, Arity::Quaternary);
+
+    assert!(tree.get_merkle_path(&[true; 6]).is_ok());
+    assert_eq!(
+        tree.get_merkle_path(&[true; 7]),
+        Err(PoseidonMerkleError::InvalidBitsPathHash { len: 7, max: 6 })
+    );
+    assert_eq!(
+        tree.canonicalize_path(&Fr::from(0b1_111111u64)),
+        Fr::from(0b111111u64)
+    );
+}
+
+#[test]
+fn test_hasher_width_must_match_the_arity() {
+    assert!(matches!(
+        SparseMerkleTree::new_with_arity(2, Arity::Quaternary, hasher(Arity::Binary)),
+        Err(PoseidonMerkleError::InvalidHasherWidth { .. })
+    ));
+}
+
+#[test]
+fn test_mismatched_arity_is_rejected() {
+    let mut quaternary = tree(2, Arity::Quaternary);
+    quaternary
+        .insert_at_path(&Fr::from(1u64), &Fr::from(1u64))
+        .unwrap();
+    let proof = quaternary.generate_proof(&Fr::from(1u64)).unwrap();
+
+    let binary = tree(6, Arity::Binary);
+    assert_eq!(
+        binary.verify_proof(&proof),
+        Err(PoseidonMerkleError::ProofError(ProofError::ArityMismatch {
+            expected: Arity::Quaternary,
+            actual: Arity::Binary,
+        }))
+    );
+}
+
+#[test]
+fn test_uneven_siblings_are_rejected() {
+    let proof = MerkleProof::new(vec![Fr::ZERO; 4], Fr::ZERO, Fr::from(1u64), Fr::ZERO);
+    assert_eq!(
+        proof.clone().try_with_arity(Arity::Quaternary).unwrap_err(),
+        ProofError::UnevenSiblings {
+            len: 4,
+            per_level: 3
+        }
+    );
+
+    let proof = MerkleProof {
+        arity: Arity::Quaternary,
+        ..proof
+    };
+    assert_eq!(
+        proof.verify_proof(&mut hasher(Arity::Quaternary)),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::UnevenSiblings {
+                len: 4,
+                per_level: 3
+            }
+        ))
+    );
+}
+
+#[test]
+fn test_wide_paths_are_rejected_with_the_arity() {
+    for (depth, arity) in [(100, Arity::Octal), (128, Arity::Quaternary)] {
+        assert_eq!(
+            SparseMerkleTree::new_with_arity(depth, arity, hasher(arity)).unwrap_err(),
+            PoseidonMerkleError::InvalidBitsPathHash {
+                len: depth * arity.bits(),
+                max: 254,
+            }
+        );
+    }
+
+    // The widest paths still fit, up to the top bit of the modulus in both bit orders
+    for (depth, arity) in [(84, Arity::Octal), (127, Arity::Quaternary)] {
+        for bit_order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+            let mut tree = tree(depth, arity).with_bit_order(bit_order);
+            tree.insert_at_path(&-Fr::ONE, &Fr::from(7u64)).unwrap();
+            let proof = tree.generate_proof(&-Fr::ONE).unwrap();
+            assert!(tree.verify_proof(&proof).unwrap());
+        }
+    }
+}
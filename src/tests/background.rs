@@ -0,0 +1,176 @@
+use std::{
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use ark_bn254::Fr;
+use light_poseidon::PoseidonError;
+
+use crate::{HashFamily, MerkleHasher, SparseMerkleTree, TestHasher};
+
+const DEPTH: usize = 20;
+
+/// Entries spread over the tree, with the paths of the first hundred written twice
+fn entries(count: u64) -> impl Iterator<Item = (Fr, Fr)> {
+    (0..count).map(move |index| {
+        let path = index.wrapping_mul(0x9e37_79b9) % (1 << DEPTH);
+        let path = if index >= count - 100 {
+            index - (count - 100)
+        } else {
+            path
+        };
+        (Fr::from(path), Fr::from(index + 1))
+    })
+}
+
+/// A `TestHasher` telling by its `Arc` whether the tree holding it is still alive
+struct AliveHasher {
+    _alive: Arc<()>,
+}
+
+impl MerkleHasher<Fr> for AliveHasher {
+    fn hash_children(&mut self, children: &[Fr]) -> Result<Fr, PoseidonError> {
+        TestHasher.hash_children(children)
+    }
+
+    fn hash_family(&self) -> Option<HashFamily> {
+        TestHasher.hash_family()
+    }
+}
+
+/// Whether hashes may go on, and whether one is waiting for it
+#[derive(Default)]
+struct Gate {
+    state: Mutex<(bool, bool)>,
+    changed: Condvar,
+}
+
+impl Gate {
+    fn set_open(&self, open: bool) {
+        self.state.lock().unwrap().0 = open;
+        self.changed.notify_all();
+    }
+
+    /// Wait until a hash is held at the closed gate
+    fn wait_for_hash(&self) {
+        let state = self.state.lock().unwrap();
+        drop(
+            self.changed
+                .wait_while(state, |(_, waiting)| !*waiting)
+                .unwrap(),
+        );
+    }
+}
+
+/// A `TestHasher` whose hashes wait for its gate to be open
+struct GatedHasher {
+    gate: Arc<Gate>,
+}
+
+impl MerkleHasher<Fr> for GatedHasher {
+    fn hash_children(&mut self, children: &[Fr]) -> Result<Fr, PoseidonError> {
+        let mut state = self.gate.state.lock().unwrap();
+        while !state.0 {
+            state.1 = true;
+            self.gate.changed.notify_all();
+            state = self.gate.changed.wait(state).unwrap();
+        }
+        state.1 = false;
+        drop(state);
+        TestHasher.hash_children(children)
+    }
+
+    fn hash_family(&self) -> Option<HashFamily> {
+        TestHasher.hash_family()
+    }
+}
+
+#[test]
+fn test_background_hashing_matches_synchronous_inserts() {
+    let mut expected = SparseMerkleTree::new_with_hasher(DEPTH, TestHasher::new()).unwrap();
+    expected.insert_many(entries(10_000)).unwrap();
+
+    let tree = SparseMerkleTree::new_with_hasher(DEPTH, TestHasher::new())
+        .unwrap()
+        .with_background_hashing();
+    for (path, value) in entries(10_000) {
+        tree.insert_at_path(&path, &value).unwrap();
+    }
+    // Placed leaves are read right away, proofs wait for the worker
+    assert_eq!(
+        tree.try_get_value(&Fr::from(0u64)).unwrap(),
+        Some(Fr::from(9_901u64))
+    );
+    let proof = tree.generate_proof(&Fr::from(0u64)).unwrap();
+
+    let root = tree.flush().unwrap();
+    assert_eq!(root, expected.root().unwrap());
+    assert_eq!(proof.root_hash, root);
+
+    tree.delete_at_path(&Fr::from(0u64)).unwrap();
+    expected.delete_at_path(&Fr::from(0u64)).unwrap();
+    let tree = tree.into_inner();
+    assert!(!tree.root.borrow().dirty, "the last leaves are hashed");
+    assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+}
+
+#[test]
+fn test_dropping_background_hashing_joins_the_worker() {
+    let alive = Arc::new(());
+    let tree = SparseMerkleTree::new_with_hasher(
+        DEPTH,
+        AliveHasher {
+            _alive: alive.clone(),
+        },
+    )
+    .unwrap()
+    .with_background_hashing();
+    for (path, value) in entries(1_000) {
+        tree.insert_at_path(&path, &value).unwrap();
+    }
+
+    drop(tree);
+    assert_eq!(
+        Arc::strong_count(&alive),
+        1,
+        "the worker and the tree are gone"
+    );
+}
+
+#[test]
+fn test_inserts_dont_wait_for_the_pass() {
+    let gate = Arc::new(Gate::default());
+    gate.set_open(true);
+    let tree = SparseMerkleTree::new_with_hasher(DEPTH, GatedHasher { gate: gate.clone() })
+        .unwrap()
+        .with_background_hashing();
+
+    // The worker is held halfway through the pass of the first leaf
+    gate.set_open(false);
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(10u64))
+        .unwrap();
+    gate.wait_for_hash();
+
+    let inserted = thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        let tree = &tree;
+        scope.spawn(move || {
+            for (path, value) in entries(100) {
+                tree.insert_at_path(&path, &value).unwrap();
+            }
+            tree.delete_at_path(&Fr::from(1u64)).unwrap();
+            sender.send(()).unwrap();
+        });
+        let inserted = receiver.recv_timeout(Duration::from_secs(10));
+        gate.set_open(true);
+        inserted
+    });
+    assert!(inserted.is_ok(), "the inserts waited for the pass");
+    assert_eq!(tree.try_get_value(&Fr::from(1u64)).unwrap(), None);
+
+    let mut expected = SparseMerkleTree::new_with_hasher(DEPTH, TestHasher::new()).unwrap();
+    expected.insert_many(entries(100)).unwrap();
+    expected.delete_at_path(&Fr::from(1u64)).unwrap();
+    assert_eq!(tree.flush().unwrap(), expected.root().unwrap());
+}
@@ -0,0 +1,105 @@
+use ark_bn254::Fr;
+
+use crate::{CountingHasher, MerklePath, SparseMerkleTree, TestHasher};
+
+/// `count` entries scattered over a depth-20 tree
+fn entries(count: u64) -> Vec<(MerklePath, Fr)> {
+    (0..count)
+        .map(|index| {
+            let path = index.wrapping_mul(2654435761) % (1 << 20);
+            (Fr::from(path), Fr::from(index + 1))
+        })
+        .collect()
+}
+
+#[test]
+fn test_insert_batch_hashes_each_ancestor_once() {
+    let entries = entries(10_000);
+    let naive_hasher = CountingHasher::new(TestHasher);
+    let mut naive = SparseMerkleTree::new_with_hasher(20, naive_hasher.clone()).unwrap();
+    let batch_hasher = CountingHasher::new(TestHasher);
+    let mut batch = SparseMerkleTree::new_with_hasher(20, batch_hasher.clone()).unwrap();
+    naive_hasher.reset();
+    batch_hasher.reset();
+
+    naive.insert_many(entries.iter().copied()).unwrap();
+    batch.insert_batch(&entries).unwrap();
+    let (naive_hashes, batch_hashes) = (naive_hasher.count(), batch_hasher.count());
+
+    assert_eq!(batch.root().unwrap(), naive.root().unwrap());
+    for (merkle_path, value) in &entries[..100] {
+        assert_eq!(batch.try_get_value(merkle_path).unwrap(), Some(*value));
+    }
+    // One hash per distinct inner node on the paths, the node at level `l` being picked by the
+    // low `l` bits of the path
+    let ancestors: usize = (0..20)
+        .map(|level| {
+            let mut prefixes: Vec<u64> = (0..10_000u64)
+                .map(|index| index.wrapping_mul(2654435761) % (1 << 20) % (1 << level))
+                .collect();
+            prefixes.sort();
+            prefixes.dedup();
+            prefixes.len()
+        })
+        .sum();
+    // Paranoid reads check the cached hashes again
+    if cfg!(not(feature = "paranoid")) {
+        assert_eq!(batch_hashes, ancestors);
+        assert_eq!(naive_hashes, 20 * 10_000);
+    }
+    assert!(batch_hashes * 2 < naive_hashes);
+}
+
+#[test]
+fn test_insert_batch_keeps_the_last_duplicate() {
+    let mut batch = SparseMerkleTree::new_with_hasher(8, TestHasher).unwrap();
+    let mut naive = SparseMerkleTree::new_with_hasher(8, TestHasher).unwrap();
+    let entries = [
+        (Fr::from(5u64), Fr::from(1u64)),
+        (Fr::from(9u64), Fr::from(2u64)),
+        (Fr::from(5u64), Fr::from(3u64)),
+    ];
+
+    batch.insert_batch(&entries).unwrap();
+    naive.insert_many(entries).unwrap();
+
+    assert_eq!(
+        batch.try_get_value(&Fr::from(5u64)).unwrap(),
+        Some(Fr::from(3u64))
+    );
+    assert_eq!(batch.root().unwrap(), naive.root().unwrap());
+}
+
+#[test]
+fn test_empty_insert_batch_changes_nothing() {
+    let hasher = CountingHasher::new(TestHasher);
+    let mut tree = SparseMerkleTree::new_with_hasher(8, hasher.clone()).unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(1u64))
+        .unwrap();
+    let root = tree.root().unwrap();
+    hasher.reset();
+
+    tree.insert_batch(&[]).unwrap();
+
+    assert_eq!(hasher.count(), 0);
+    assert_eq!(tree.root().unwrap(), root);
+}
+
+#[cfg(feature = "hash-trace")]
+#[test]
+fn test_insert_batch_follows_a_trace_sink_one_insert_at_a_time() {
+    use crate::HashTrace;
+
+    let entries = entries(20);
+    let mut traced = SparseMerkleTree::new_with_hasher(20, TestHasher).unwrap();
+    let trace = HashTrace::new();
+    traced.set_trace_sink(Box::new(trace.clone()));
+    let mut naive = SparseMerkleTree::new_with_hasher(20, TestHasher).unwrap();
+    let expected = HashTrace::new();
+    naive.set_trace_sink(Box::new(expected.clone()));
+
+    traced.insert_batch(&entries).unwrap();
+    naive.insert_many(entries).unwrap();
+
+    assert_eq!(trace.diverges_from(&expected.entries()), None);
+}
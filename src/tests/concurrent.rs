@@ -0,0 +1,145 @@
+use ark_bn254::Fr;
+use light_poseidon::{parameters::bn254_x5::get_poseidon_parameters, Poseidon};
+
+use crate::{
+    Arity, BitOrder, HashingScheme, LeafHashing, MerklePath, PoseidonMerkleError, Shared,
+    SparseMerkleTree,
+};
+
+/// `count` entries of pseudo-random paths below `2^bits` and values, from a splitmix64 sequence
+fn random_entries(seed: u64, count: usize, bits: u32) -> Vec<(MerklePath, Fr)> {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    (0..count)
+        .map(|_| (Fr::from(next() % (1 << bits)), Fr::from(next())))
+        .collect()
+}
+
+#[test]
+fn test_concurrent_insert_matches_insert_many() {
+    // Paths below 2^8 in a tree of depth 10 collide, the later entry must win
+    let entries = random_entries(1, 300, 8);
+    let mut expected = SparseMerkleTree::new(10).unwrap();
+    expected.insert_many(entries.clone()).unwrap();
+    let root = expected.root().unwrap();
+
+    for threads in [1, 2, 3, 8] {
+        let mut tree = SparseMerkleTree::new(10).unwrap();
+        tree.insert_many_concurrent(entries.clone(), threads)
+            .unwrap();
+        assert_eq!(tree.root().unwrap(), root, "{threads} threads");
+
+        for (path, _) in &entries[..20] {
+            let proof = tree.generate_proof(path).unwrap();
+            assert_eq!(
+                proof.siblings,
+                expected.generate_proof(path).unwrap().siblings
+            );
+            assert_eq!(
+                tree.try_get_value(path).unwrap(),
+                expected.try_get_value(path).unwrap()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_concurrent_insert_into_a_populated_tree() {
+    let tree = || {
+        SparseMerkleTree::new_with_arity(
+            5,
+            Arity::Quaternary,
+            Poseidon::<Fr>::new_circom(4).unwrap(),
+        )
+        .unwrap()
+        .with_leaf_hashing(LeafHashing::Hashed)
+        .with_hashing_scheme(HashingScheme::LevelBound)
+        .with_bit_order(BitOrder::MsbFirst)
+    };
+    let entries = random_entries(2, 160, 10);
+    let (before, after) = entries.split_at(60);
+
+    let mut expected = tree();
+    let mut concurrent = tree();
+    for tree in [&mut expected, &mut concurrent] {
+        tree.insert_many(before.iter().copied()).unwrap();
+        tree.delete_at_path(&before[0].0).unwrap();
+    }
+
+    expected.insert_many(after.iter().copied()).unwrap();
+    concurrent
+        .insert_many_concurrent(after.iter().copied(), 4)
+        .unwrap();
+    assert_eq!(concurrent.root().unwrap(), expected.root().unwrap());
+    assert!(concurrent.verify_integrity().is_ok());
+}
+
+#[test]
+fn test_concurrent_insert_with_other_hashers() {
+    let mut tree = SparseMerkleTree::new(6).unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(10u64))
+        .unwrap();
+    let root = tree.root().unwrap();
+    let entries = random_entries(3, 20, 6);
+
+    let err = tree
+        .insert_many_concurrent_with(entries.clone(), 2, || {
+            Err(PoseidonMerkleError::InvalidDepth)
+        })
+        .unwrap_err();
+    assert_eq!(err, PoseidonMerkleError::InvalidDepth);
+    assert_eq!(
+        tree.root().unwrap(),
+        root,
+        "a failing worker changes nothing"
+    );
+
+    let err = tree
+        .insert_many_concurrent_with(entries, 2, || {
+            let mut parameters = get_poseidon_parameters::<Fr>(3).unwrap();
+            parameters.ark[0] += Fr::from(1u64);
+            Ok(Poseidon::new(parameters))
+        })
+        .unwrap_err();
+    assert!(matches!(err, PoseidonMerkleError::HasherMismatch { .. }));
+    assert_eq!(tree.root().unwrap(), root);
+}
+
+#[test]
+fn test_concurrent_insert_shares_untouched_subtrees() {
+    let populated = || {
+        let mut tree = SparseMerkleTree::new(10).unwrap();
+        tree.insert_many(random_entries(4, 100, 10)).unwrap();
+        tree
+    };
+    let mut tree = populated();
+    // Four threads share the tree below level 2, paths with their three lowest bits clear all
+    // fall in the first shard, and never reach the subtree at 0, 0, 1 in it
+    let subtree = |tree: &SparseMerkleTree<_>| {
+        [0, 0, 1].iter().fold(tree.root.clone(), |node, index| {
+            let child = node.borrow().children[*index].clone().unwrap();
+            child
+        })
+    };
+    let untouched = subtree(&tree);
+
+    let entries: Vec<_> = random_entries(5, 200, 7)
+        .into_iter()
+        .map(|(path, value)| (path * Fr::from(8u64), value))
+        .collect();
+    let mut expected = populated();
+    expected.insert_many(entries.clone()).unwrap();
+    tree.insert_many_concurrent(entries, 4).unwrap();
+
+    assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+    assert!(Shared::ptr_eq(&subtree(&tree), &untouched));
+    assert_eq!(tree.node_count(), tree.stats().unwrap().materialized_nodes);
+    assert_eq!(tree.node_count(), expected.node_count());
+}
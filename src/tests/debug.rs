@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use ark_bn254::Fr;
+
+use crate::{FrFormat, Node, SparseMerkleTree, TestHasher};
+
+#[test]
+fn test_tree_debug_summary() {
+    let mut tree = SparseMerkleTree::new(2).unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(100u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(2u64), &Fr::from(200u64))
+        .unwrap();
+    tree.root().unwrap();
+
+    let root = tree.root().unwrap().to_string();
+    let short = format!("{}..{}", &root[..5], &root[root.len() - 5..]);
+    assert_eq!(
+        format!("{tree:?}"),
+        format!("SparseMerkleTree {{ root: {short}, depth: 2, leaves: 2 }}")
+    );
+
+    // The alternate form adds the summary of the root node, not its children
+    let expanded = format!("{tree:#?}");
+    assert!(expanded.contains("root_node: Node { inner: "));
+    assert!(expanded.contains("children: [0, 1], dirty: false }"));
+    assert_eq!(expanded.matches("Node {").count(), 1);
+}
+
+#[test]
+fn test_node_debug_summary() {
+    let leaf = Node::<TestHasher>::new_leaf(Fr::from(7u64));
+    assert_eq!(
+        format!("{leaf:?}"),
+        "Node { leaf: 7, children: [], dirty: false }"
+    );
+
+    let mut inner = Node::<TestHasher>::new_inner(Fr::from(12345678901u64));
+    inner.children[1] = Some(Node::new_borrowed_leaf(Fr::from(3u64)));
+    assert_eq!(
+        format!("{inner:?}"),
+        "Node { inner: 12345..78901, children: [1], dirty: false }"
+    );
+    assert!(format!("{inner:#?}").contains("Node { leaf: 3, children: [], dirty: false }"));
+}
+
+#[test]
+fn test_debug_of_large_tree_is_bounded() {
+    let mut tree = SparseMerkleTree::new_with_hasher(20, TestHasher::new()).unwrap();
+    tree.insert_many(
+        (0..5000u64).map(|index| (Fr::from(index * 7919 % (1 << 20)), Fr::from(index + 1))),
+    )
+    .unwrap();
+
+    let start = Instant::now();
+    let summary = format!("{tree:?}");
+    let expanded = format!("{tree:#?}");
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(summary.ends_with("depth: 20, leaves: 5000 }"));
+    assert!(summary.len() < 100);
+    assert!(expanded.len() < 1000);
+}
+
+#[test]
+fn test_fr_formats() {
+    let long = Fr::from(12345678901234567890u64);
+    assert_eq!(FrFormat::ShortDecimal.format(&long), "12345..67890");
+    assert_eq!(FrFormat::FullDecimal.format(&long), "12345678901234567890");
+    assert_eq!(FrFormat::ShortHex.format(&long), "0xab54a..f0ad2");
+    assert_eq!(
+        FrFormat::FullHex.format(&long),
+        format!("0x{:0>64}", "ab54a98ceb1f0ad2")
+    );
+
+    // Up to 10 digits nothing is shortened
+    let short = Fr::from(42u64);
+    assert_eq!(FrFormat::ShortDecimal.format(&short), "42");
+    assert_eq!(FrFormat::FullDecimal.format(&short), "42");
+    assert_eq!(FrFormat::ShortHex.format(&short), "0x2a");
+    assert_eq!(FrFormat::FullHex.format(&short), format!("0x{:0>64}", "2a"));
+    assert_eq!(
+        FrFormat::ShortDecimal.format(&Fr::from(9999999999u64)),
+        "9999999999"
+    );
+    assert_eq!(FrFormat::ShortHex.format(&Fr::from(0u64)), "0x0");
+
+    let max = -Fr::from(1u64);
+    assert_eq!(
+        FrFormat::FullDecimal.format(&max),
+        "21888242871839275222246405745257275088548364400416034343698204186575808495616"
+    );
+    assert_eq!(
+        FrFormat::FullHex.format(&max),
+        "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000000"
+    );
+}
+
+#[test]
+fn test_debug_with_format() {
+    let mut tree = SparseMerkleTree::new(2).unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(100u64))
+        .unwrap();
+    let root = tree.root().unwrap();
+
+    assert_eq!(
+        format!("{:?}", tree.debug_with(FrFormat::FullHex)),
+        format!(
+            "SparseMerkleTree {{ root: {}, depth: 2, leaves: 1 }}",
+            FrFormat::FullHex.format(&root)
+        )
+    );
+    let expanded = format!("{:#?}", tree.debug_with(FrFormat::FullDecimal));
+    assert!(expanded.contains(&format!("root_node: Node {{ inner: {root},")));
+    assert_eq!(
+        format!(
+            "{:?}",
+            Node::<TestHasher>::new_leaf(Fr::from(255u64)).debug_with(FrFormat::ShortHex)
+        ),
+        "Node { leaf: 0xff, children: [], dirty: false }"
+    );
+}
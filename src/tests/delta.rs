@@ -0,0 +1,246 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use ark_bn254::Fr;
+use ark_ff::AdditiveGroup;
+use light_poseidon::Poseidon;
+
+use crate::{
+    DeltaError, InnerHash, MerklePath, PoseidonMerkleError, SparseMerkleTree, TreeDelta,
+    TreeObserver,
+};
+
+type Tree = SparseMerkleTree<Poseidon<Fr>>;
+
+fn primary() -> Tree {
+    let mut tree = SparseMerkleTree::new(16).unwrap().with_changelog(64);
+    for index in [7u64, 9, 300] {
+        tree.insert_at_path(&Fr::from(index), &Fr::from(index * 10))
+            .unwrap();
+    }
+    tree
+}
+
+fn replica_of(tree: &Tree) -> Tree {
+    SparseMerkleTree::from_snapshot_bytes(&tree.to_snapshot_bytes().unwrap()).unwrap()
+}
+
+/// Ship the changes of the primary to the replica as bytes
+fn replicate(primary: &Tree, replica: &mut Tree) -> TreeDelta {
+    let delta = primary.export_delta(&replica.root().unwrap()).unwrap();
+    let delta = TreeDelta::from_bytes(&delta.to_bytes()).unwrap();
+    assert_eq!(
+        replica.apply_delta(&delta).unwrap(),
+        primary.root().unwrap()
+    );
+    delta
+}
+
+#[test]
+fn test_replica_tracks_the_primary() {
+    let mut primary = primary();
+    let mut replica = replica_of(&primary);
+
+    for index in [1u64, 2, 3] {
+        primary
+            .insert_at_path(&Fr::from(index), &Fr::from(index + 100))
+            .unwrap();
+    }
+    assert_eq!(replicate(&primary, &mut replica).leaves.len(), 3);
+
+    primary
+        .insert_at_path(&Fr::from(2u64), &Fr::from(5u64))
+        .unwrap();
+    primary.delete_at_path(&Fr::from(7u64)).unwrap();
+    primary
+        .insert_at_path(&Fr::from(40u64), &Fr::from(4u64))
+        .unwrap();
+    assert_eq!(replicate(&primary, &mut replica).leaves.len(), 3);
+
+    // A leaf updated twice is shipped once, with its last value
+    primary
+        .insert_at_path(&Fr::from(9u64), &Fr::from(1u64))
+        .unwrap();
+    primary
+        .insert_at_path(&Fr::from(9u64), &Fr::from(2u64))
+        .unwrap();
+    let delta = replicate(&primary, &mut replica);
+    assert_eq!(delta.leaves, [(Fr::from(9u64), Fr::from(2u64))]);
+
+    for index in [1u64, 2, 3, 7, 9, 40, 300] {
+        let path = Fr::from(index);
+        assert_eq!(
+            replica.try_get_value(&path).unwrap(),
+            primary.try_get_value(&path).unwrap()
+        );
+    }
+    assert!(primary
+        .export_delta(&replica.root().unwrap())
+        .unwrap()
+        .leaves
+        .is_empty());
+}
+
+#[test]
+fn test_tampered_delta_is_rolled_back() {
+    let mut primary = primary();
+    let mut replica = replica_of(&primary);
+    let base_root = replica.root().unwrap();
+    primary
+        .insert_at_path(&Fr::from(9u64), &Fr::from(1u64))
+        .unwrap();
+    primary
+        .insert_at_path(&Fr::from(11u64), &Fr::from(2u64))
+        .unwrap();
+
+    let mut delta = primary.export_delta(&base_root).unwrap();
+    delta.leaves[1].1 = Fr::from(3u64);
+    assert!(matches!(
+        replica.apply_delta(&delta),
+        Err(DeltaError::DeltaMismatch { expected, .. }) if expected == primary.root().unwrap()
+    ));
+    assert_eq!(replica.root().unwrap(), base_root);
+    assert_eq!(
+        replica.try_get_value(&Fr::from(9u64)).unwrap(),
+        Some(Fr::from(90u64))
+    );
+
+    // The replica is untouched, the genuine delta still applies
+    let delta = primary.export_delta(&base_root).unwrap();
+    assert_eq!(
+        replica.apply_delta(&delta).unwrap(),
+        primary.root().unwrap()
+    );
+    assert_eq!(
+        replica.apply_delta(&delta),
+        Err(DeltaError::BaseRootMismatch {
+            expected: base_root,
+            actual: primary.root().unwrap()
+        })
+    );
+}
+
+/// Counts the events in a counter shared with the test
+struct Counter(Arc<AtomicUsize>);
+
+impl TreeObserver for Counter {
+    fn on_insert(&mut self, _: &MerklePath, _: Option<Fr>, _: &Fr, _: &InnerHash) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_delete(&mut self, _: &MerklePath, _: Option<Fr>, _: &InnerHash) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn test_rejected_delta_leaves_no_trace() {
+    let mut primary = primary();
+    let mut replica = replica_of(&primary).with_changelog(64);
+    let base_root = replica.root().unwrap();
+    // A leaf set to zero is materialized without changing the root
+    replica.insert_at_path(&Fr::from(5u64), &Fr::ZERO).unwrap();
+    assert_eq!(replica.root().unwrap(), base_root);
+    let events = Arc::new(AtomicUsize::new(0));
+    replica.set_observer(Box::new(Counter(events.clone())));
+    let logged = replica.changelog().unwrap().len();
+
+    primary
+        .insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+    primary.delete_at_path(&Fr::from(7u64)).unwrap();
+    primary
+        .insert_at_path(&Fr::from(9u64), &Fr::from(1u64))
+        .unwrap();
+    let mut delta = primary.export_delta(&base_root).unwrap();
+    delta.leaves[2].1 = Fr::from(3u64);
+    assert!(matches!(
+        replica.apply_delta(&delta),
+        Err(DeltaError::DeltaMismatch { .. })
+    ));
+
+    assert_eq!(replica.root().unwrap(), base_root);
+    assert_eq!(
+        replica.try_get_value(&Fr::from(5u64)).unwrap(),
+        Some(Fr::ZERO)
+    );
+    assert_eq!(
+        replica.try_get_value(&Fr::from(7u64)).unwrap(),
+        Some(Fr::from(70u64))
+    );
+    assert_eq!(replica.changelog().unwrap().len(), logged);
+    assert_eq!(events.load(Ordering::Relaxed), 0);
+    assert!(replica.verify_integrity().is_ok());
+
+    // An applied delta is told leaf by leaf, and its changelog leads to the root of the primary
+    let delta = primary.export_delta(&base_root).unwrap();
+    let root = replica.apply_delta(&delta).unwrap();
+    assert_eq!(root, primary.root().unwrap());
+    assert_eq!(replica.changelog().unwrap().len(), logged + 3);
+    assert_eq!(
+        replica
+            .changelog()
+            .unwrap()
+            .entries()
+            .last()
+            .unwrap()
+            .new_root,
+        root
+    );
+    assert_eq!(events.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn test_unknown_base_roots_and_malformed_bytes() {
+    let primary = primary();
+    assert_eq!(
+        primary.export_delta(&Fr::from(1u64)),
+        Err(DeltaError::UnknownBaseRoot {
+            root: Fr::from(1u64)
+        })
+    );
+
+    let bytes = primary
+        .export_delta(
+            &primary
+                .changelog()
+                .unwrap()
+                .entries()
+                .next()
+                .unwrap()
+                .new_root,
+        )
+        .unwrap()
+        .to_bytes();
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    let mut out_of_field = bytes.clone();
+    out_of_field[69..101].fill(u8::MAX);
+    for (bytes, reason) in [
+        (&bytes[..20], "truncated header"),
+        (&bytes[..bytes.len() - 1], "truncated leaves"),
+        (&bad_magic[..], "not a delta"),
+        (&out_of_field[..], "field element not below the modulus"),
+    ] {
+        assert_eq!(
+            TreeDelta::from_bytes(bytes),
+            Err(PoseidonMerkleError::MalformedDelta(reason))
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    let mut primary = primary();
+    let replica = replica_of(&primary);
+    primary
+        .insert_at_path(&Fr::from(1u64), &Fr::from(1u64))
+        .unwrap();
+
+    let delta = primary.export_delta(&replica.root().unwrap()).unwrap();
+    let json = serde_json::to_vec(&delta).unwrap();
+    assert_eq!(serde_json::from_slice::<TreeDelta>(&json).unwrap(), delta);
+}
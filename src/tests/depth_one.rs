@@ -0,0 +1,158 @@
+use ark_bn254::Fr;
+use ark_ff::AdditiveGroup;
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{get_empty_inner_hash, zero_hash, LeafHashing, PoseidonMerkleError, SparseMerkleTree};
+
+const LEFT: u64 = 0;
+const RIGHT: u64 = 1;
+
+fn poseidon(left: Fr, right: Fr) -> Fr {
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    hasher.hash(&[left, right]).unwrap()
+}
+
+fn populated_tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(1).unwrap();
+    tree.insert_at_path(&Fr::from(LEFT), &Fr::from(10u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(RIGHT), &Fr::from(11u64))
+        .unwrap();
+    tree
+}
+
+#[test]
+fn test_empty_root() {
+    let tree = SparseMerkleTree::new(1).unwrap();
+    assert!(tree.is_empty().unwrap());
+    assert_eq!(tree.root().unwrap(), poseidon(Fr::ZERO, Fr::ZERO));
+    assert_eq!(tree.root().unwrap(), zero_hash(0, 1));
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_insert_both_leaves() {
+    let mut tree = SparseMerkleTree::new(1).unwrap();
+
+    tree.insert_at_path(&Fr::from(RIGHT), &Fr::from(11u64))
+        .unwrap();
+    assert_eq!(tree.root().unwrap(), poseidon(Fr::ZERO, Fr::from(11u64)));
+
+    tree.insert_at_path(&Fr::from(LEFT), &Fr::from(10u64))
+        .unwrap();
+    let expected = poseidon(Fr::from(10u64), Fr::from(11u64));
+    assert_eq!(tree.root().unwrap(), expected);
+    #[allow(deprecated)]
+    let full_root = tree.root_hash().unwrap();
+    assert_eq!(full_root, expected);
+
+    assert_eq!(
+        tree.try_get_value(&Fr::from(LEFT)).unwrap(),
+        Some(Fr::from(10u64))
+    );
+    assert_eq!(
+        tree.try_get_value(&Fr::from(RIGHT)).unwrap(),
+        Some(Fr::from(11u64))
+    );
+    assert_eq!(
+        tree.iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        [10u64, 11].map(Fr::from)
+    );
+    assert!(!tree.is_empty().unwrap());
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_paths() {
+    let tree = SparseMerkleTree::new(1).unwrap();
+
+    assert_eq!(tree.get_merkle_path(&[true]).unwrap(), Fr::from(RIGHT));
+    assert_eq!(tree.get_merkle_path_padded(&[]).unwrap(), Fr::from(LEFT));
+    assert_eq!(
+        tree.get_merkle_path(&[true, true]).unwrap_err(),
+        PoseidonMerkleError::InvalidBitsPathHash { len: 2, max: 1 }
+    );
+    assert_eq!(tree.canonicalize_path(&Fr::from(6u64)), Fr::from(LEFT));
+    assert_eq!(tree.canonicalize_path(&Fr::from(7u64)), Fr::from(RIGHT));
+}
+
+#[test]
+fn test_get_inner_node() {
+    let tree = populated_tree();
+
+    let root = tree.get_inner_node(&Fr::from(RIGHT), 0).unwrap();
+    assert_eq!(
+        *root.borrow().node_type.data(),
+        poseidon(Fr::from(10u64), Fr::from(11u64))
+    );
+    assert!(matches!(
+        tree.get_inner_node(&Fr::from(RIGHT), 1),
+        Err(PoseidonMerkleError::InvalidLevel)
+    ));
+}
+
+#[test]
+fn test_proofs() {
+    let tree = populated_tree();
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+
+    for (path, sibling) in [(LEFT, 11u64), (RIGHT, 10)] {
+        let proof = tree.generate_proof(&Fr::from(path)).unwrap();
+        assert_eq!(proof.siblings, [Fr::from(sibling)]);
+        assert!(proof.verify_proof(&mut hasher).unwrap());
+        assert!(tree.verify_proof(&proof).unwrap());
+    }
+
+    // A lone leaf is proven against the zero leaf next to it
+    let mut tree = SparseMerkleTree::new(1).unwrap();
+    tree.insert_at_path(&Fr::from(RIGHT), &Fr::from(11u64))
+        .unwrap();
+    let proof = tree.generate_proof(&Fr::from(RIGHT)).unwrap();
+    assert_eq!(proof.siblings, [Fr::ZERO]);
+    assert!(tree.verify_proof(&proof).unwrap());
+}
+
+#[test]
+fn test_delete_and_clear() {
+    let mut tree = populated_tree();
+
+    tree.delete_at_path(&Fr::from(LEFT)).unwrap();
+    assert_eq!(tree.try_get_value(&Fr::from(LEFT)).unwrap(), None);
+    assert_eq!(tree.root().unwrap(), poseidon(Fr::ZERO, Fr::from(11u64)));
+
+    tree.delete_at_path(&Fr::from(RIGHT)).unwrap();
+    assert_eq!(tree.root().unwrap(), *get_empty_inner_hash());
+    assert!(tree.is_empty().unwrap());
+    assert_eq!(tree.node_count(), 1);
+
+    tree.clear();
+    assert!(tree.is_empty().unwrap());
+    assert_eq!(tree.try_get_value(&Fr::from(RIGHT)).unwrap(), None);
+}
+
+#[test]
+fn test_hashed_leaves() {
+    let mut tree = SparseMerkleTree::new(1)
+        .unwrap()
+        .with_leaf_hashing(LeafHashing::Hashed);
+    tree.insert_at_path(&Fr::from(LEFT), &Fr::from(10u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(RIGHT), &Fr::from(11u64))
+        .unwrap();
+
+    let leaf = |value: u64| LeafHashing::Hashed.leaf_hash(&Fr::from(value)).unwrap();
+    assert_eq!(tree.root().unwrap(), poseidon(leaf(10), leaf(11)));
+
+    let proof = tree.generate_proof(&Fr::from(RIGHT)).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[cfg(feature = "visualize")]
+#[test]
+fn test_visualize() {
+    use crate::Visualizer;
+
+    populated_tree().visualize().unwrap();
+    SparseMerkleTree::new(1).unwrap().visualize().unwrap();
+}
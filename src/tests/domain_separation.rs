@@ -0,0 +1,169 @@
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, Field};
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{
+    zero_hash_for, HashFamily, HashingScheme, LeafHashing, MerkleProof, PoseidonMerkleError,
+    ProofError, SparseMerkleTree,
+};
+
+/// poseidon(1, 2), the root of the depth-1 legacy tree holding 1 on the left and 2 on the right
+const LEGACY_ROOT: &str =
+    "7853200120776062878684798364095072458815029376092732009249414926327459813530";
+
+/// poseidon(poseidon(1, 1), poseidon(2, 1)), the same tree with domain separated leaves
+const DOMAIN_SEPARATED_ROOT: &str =
+    "9676832049336361899071001529131669215035430685019052898237072502331124941311";
+
+/// poseidon(0, 1), the contribution of an empty leaf with domain separated leaves
+const DOMAIN_SEPARATED_EMPTY_LEAF: &str =
+    "12583541437132735734108669866114103169564651237895298778035846191048104863326";
+
+fn poseidon(left: Fr, right: Fr) -> Fr {
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    hasher.hash(&[left, right]).unwrap()
+}
+
+fn two_leaf_tree(scheme: HashingScheme) -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(1)
+        .unwrap()
+        .with_hashing_scheme(scheme);
+    tree.insert_at_path(&Fr::from(0u64), &Fr::from(1u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(2u64))
+        .unwrap();
+    tree
+}
+
+#[test]
+fn test_legacy_known_answer() {
+    let tree = two_leaf_tree(HashingScheme::Legacy);
+    assert_eq!(tree.root().unwrap(), Fr::from_str(LEGACY_ROOT).unwrap());
+}
+
+#[test]
+fn test_domain_separated_known_answer() {
+    let tree = two_leaf_tree(HashingScheme::DomainSeparated);
+    let expected = Fr::from_str(DOMAIN_SEPARATED_ROOT).unwrap();
+    assert_eq!(tree.root().unwrap(), expected);
+    assert_eq!(
+        poseidon(
+            poseidon(Fr::from(1u64), Fr::ONE),
+            poseidon(Fr::from(2u64), Fr::ONE)
+        ),
+        expected
+    );
+
+    #[allow(deprecated)]
+    let recomputed = tree.root_hash().unwrap();
+    assert_eq!(recomputed, expected);
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_domain_separated_empty_leaf() {
+    let empty_leaf = Fr::from_str(DOMAIN_SEPARATED_EMPTY_LEAF).unwrap();
+    assert_eq!(poseidon(Fr::ZERO, Fr::ONE), empty_leaf);
+
+    let mut tree = SparseMerkleTree::new(1)
+        .unwrap()
+        .with_hashing_scheme(HashingScheme::DomainSeparated);
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(2u64))
+        .unwrap();
+    assert_eq!(
+        tree.root().unwrap(),
+        poseidon(empty_leaf, poseidon(Fr::from(2u64), Fr::ONE))
+    );
+
+    let proof = tree.generate_proof(&Fr::from(1u64)).unwrap();
+    assert_eq!(proof.siblings, vec![empty_leaf]);
+}
+
+#[test]
+fn test_switching_scheme_rehashes() {
+    let tree = two_leaf_tree(HashingScheme::Legacy);
+    let tree = tree.with_hashing_scheme(HashingScheme::DomainSeparated);
+    assert_eq!(tree.hashing_scheme(), HashingScheme::DomainSeparated);
+    assert_eq!(
+        tree.root().unwrap(),
+        Fr::from_str(DOMAIN_SEPARATED_ROOT).unwrap()
+    );
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_domain_separated_proofs() {
+    let mut tree = SparseMerkleTree::new(4)
+        .unwrap()
+        .with_hashing_scheme(HashingScheme::DomainSeparated);
+    for (path, value) in [(3u64, 30u64), (9, 90), (10, 100)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    for path in [3u64, 9, 10] {
+        let proof = tree.generate_proof(&Fr::from(path)).unwrap();
+        assert_eq!(proof.scheme, HashingScheme::DomainSeparated);
+        assert!(proof.verify_proof(&mut hasher).unwrap());
+        assert!(tree.verify_proof(&proof).unwrap());
+    }
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_cross_scheme_verification_fails() {
+    let legacy = two_leaf_tree(HashingScheme::Legacy);
+    let separated = two_leaf_tree(HashingScheme::DomainSeparated);
+    separated.root().unwrap();
+    let proof = separated.generate_proof(&Fr::from(1u64)).unwrap();
+
+    assert_eq!(
+        legacy.verify_proof(&proof),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::SchemeMismatch {
+                expected: HashingScheme::DomainSeparated,
+                actual: HashingScheme::Legacy,
+            }
+        ))
+    );
+
+    // The same siblings and root folded under the other scheme don't reach the root
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    let relabeled = MerkleProof::new(
+        proof.siblings.clone(),
+        proof.merkle_path,
+        proof.leaf_value,
+        proof.root_hash,
+    );
+    assert_eq!(relabeled.scheme, HashingScheme::Legacy);
+    assert!(!relabeled.verify_proof(&mut hasher).unwrap());
+    assert!(proof.verify_proof(&mut hasher).unwrap());
+}
+
+#[test]
+fn test_zero_hashes() {
+    let empty_leaf = Fr::from_str(DOMAIN_SEPARATED_EMPTY_LEAF).unwrap();
+    let zero_hash = |level, depth| {
+        zero_hash_for(
+            level,
+            depth,
+            LeafHashing::Raw,
+            HashFamily::Poseidon,
+            HashingScheme::DomainSeparated,
+        )
+    };
+
+    assert_eq!(zero_hash(2, 2), empty_leaf);
+    assert_eq!(zero_hash(1, 2), poseidon(empty_leaf, empty_leaf));
+    assert_eq!(zero_hash(0, 2), poseidon(zero_hash(1, 2), zero_hash(1, 2)));
+
+    let mut tree = SparseMerkleTree::new(1)
+        .unwrap()
+        .with_hashing_scheme(HashingScheme::DomainSeparated);
+    tree.insert_at_path(&Fr::from(0u64), &Fr::ZERO).unwrap();
+    assert_eq!(tree.root().unwrap(), zero_hash(0, 1));
+}
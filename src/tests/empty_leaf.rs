@@ -0,0 +1,133 @@
+use ark_bn254::Fr;
+use ark_ff::Zero;
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{
+    get_empty_inner_hash, get_empty_root, zero_hash, EmptySubtrees, LeafHashing, SparseMerkleTree,
+};
+
+const DEPTH: usize = 8;
+
+fn empty_leaf() -> Fr {
+    Fr::from(0xdeadu64)
+}
+
+/// Empty subtree hashes from the root down to the empty leaf contribution
+fn chain(leaf: Fr, depth: usize) -> Vec<Fr> {
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    let mut hashes = vec![leaf];
+    for _ in 0..depth {
+        let child = hashes[0];
+        hashes.insert(0, hasher.hash(&[child, child]).unwrap());
+    }
+    hashes
+}
+
+fn setup_tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    SparseMerkleTree::new(DEPTH)
+        .unwrap()
+        .try_with_empty_leaf(empty_leaf())
+        .unwrap()
+}
+
+#[test]
+fn test_empty_root_follows_the_empty_leaf() {
+    let tree = setup_tree();
+    let expected = chain(empty_leaf(), DEPTH);
+
+    assert_eq!(tree.empty_leaf(), empty_leaf());
+    assert_eq!(tree.hash_config().empty_hashes, expected);
+    assert!(tree.is_empty().unwrap());
+    assert_eq!(tree.root().unwrap(), expected[0]);
+    assert_ne!(expected[0], *get_empty_inner_hash());
+    assert_ne!(expected[0], zero_hash(0, DEPTH));
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_delete_restores_the_empty_leaf() {
+    let mut tree = setup_tree();
+    let empty_root = tree.root().unwrap();
+
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+    assert!(!tree.is_empty().unwrap());
+    tree.delete_at_path(&Fr::from(5u64)).unwrap();
+
+    assert_eq!(tree.try_get_value(&Fr::from(5u64)), Ok(None));
+    assert_eq!(tree.root().unwrap(), empty_root);
+    assert!(tree.is_empty().unwrap());
+    assert_eq!(tree.node_count(), 1);
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_absent_leaf_proof() {
+    let mut tree = setup_tree();
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(30u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+    tree.delete_at_path(&Fr::from(5u64)).unwrap();
+    tree.root().unwrap();
+
+    let proof = tree.generate_proof(&Fr::from(5u64)).unwrap();
+    assert_eq!(proof.leaf_value, empty_leaf());
+    assert!(tree.verify_proof(&proof).unwrap());
+
+    // Both paths start right, the left child of the root is an empty subtree of depth 7
+    assert_eq!(proof.siblings[0], chain(empty_leaf(), DEPTH)[1]);
+
+    // Zero is not the empty value of this tree
+    let mut zero_proof = proof.clone();
+    zero_proof.leaf_value = Fr::zero();
+    assert!(!tree.verify_proof(&zero_proof).unwrap());
+}
+
+#[test]
+fn test_missing_siblings_match_a_materialized_empty_subtree() {
+    let mut tree = setup_tree();
+    tree.insert_at_path(&Fr::from(0u64), &Fr::from(1u64))
+        .unwrap();
+    let sparse = tree.root().unwrap();
+
+    // Materialize the whole path of leaf 255 with empty values, leaving the root unchanged
+    tree.insert_at_path(&Fr::from(255u64), &empty_leaf())
+        .unwrap();
+    assert_eq!(tree.root().unwrap(), sparse);
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_zero_empty_leaf_is_the_default() {
+    let tree = SparseMerkleTree::new(DEPTH)
+        .unwrap()
+        .try_with_empty_leaf(Fr::zero())
+        .unwrap();
+    assert_eq!(tree.root().unwrap(), get_empty_root(DEPTH));
+
+    // Flat empty subtrees of zero leaves need no hash per level
+    let flat = SparseMerkleTree::new(DEPTH)
+        .unwrap()
+        .with_empty_subtrees(EmptySubtrees::Flat)
+        .try_with_empty_leaf(Fr::zero())
+        .unwrap();
+    assert!(flat.hash_config().empty_hashes.is_empty());
+    assert_eq!(flat.root().unwrap(), *get_empty_inner_hash());
+}
+
+#[test]
+fn test_leaf_hashing_rehashes_the_empty_leaf() {
+    let mut tree = setup_tree().with_leaf_hashing(LeafHashing::Hashed);
+    let hashed = Poseidon::<Fr>::new_circom(1)
+        .unwrap()
+        .hash(&[empty_leaf()])
+        .unwrap();
+
+    assert_eq!(tree.root().unwrap(), chain(hashed, DEPTH)[0]);
+
+    tree.insert_at_path(&Fr::from(9u64), &Fr::from(90u64))
+        .unwrap();
+    tree.delete_at_path(&Fr::from(9u64)).unwrap();
+    assert!(tree.is_empty().unwrap());
+}
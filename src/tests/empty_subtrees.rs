@@ -0,0 +1,249 @@
+use ark_bn254::Fr;
+use ark_ff::MontFp;
+use light_poseidon::{parameters::bn254_x5::get_poseidon_parameters, Poseidon, PoseidonHasher};
+
+use crate::{
+    get_empty_hash_at_level, get_empty_inner_hash, get_empty_root, zero_hash, Arity, EmptySubtrees,
+    HashingScheme, MerkleHasher, PoseidonMerkleError, SparseMerkleTree, PRECOMPUTED_EMPTY_ROOTS,
+};
+#[cfg(any(feature = "poseidon2", feature = "mimc", feature = "rescue"))]
+use crate::{zero_hash_for, HashFamily, LeafHashing};
+
+/// Roots of empty binary circom Poseidon trees folded from a zero leaf, as circomlibjs gives them
+const EMPTY_ROOTS: [(usize, Fr); 3] = [
+    (
+        2,
+        MontFp!("7423237065226347324353380772367382631490014989348495481811164164159255474657"),
+    ),
+    (
+        4,
+        MontFp!("3607627140608796879659380071776844901612302623152076817094415224584923813162"),
+    ),
+    (
+        20,
+        MontFp!("15019797232609675441998260052101280400536945603062888308240081994073687793470"),
+    ),
+];
+
+fn folded_tree(depth: usize) -> SparseMerkleTree<Poseidon<Fr>> {
+    SparseMerkleTree::new(depth).unwrap()
+}
+
+fn flat_tree(depth: usize) -> SparseMerkleTree<Poseidon<Fr>> {
+    SparseMerkleTree::new(depth)
+        .unwrap()
+        .with_empty_subtrees(EmptySubtrees::Flat)
+}
+
+fn poseidon(left: Fr, right: Fr) -> Fr {
+    Poseidon::<Fr>::new_circom(2)
+        .unwrap()
+        .hash(&[left, right])
+        .unwrap()
+}
+
+/// A new tree folds, and gets its root back once a leaf is inserted and deleted
+fn assert_folds_to<H: MerkleHasher<Fr>>(mut tree: SparseMerkleTree<H>, root: Fr) {
+    assert_eq!(tree.empty_subtrees(), EmptySubtrees::Folded);
+    assert_eq!(tree.root().unwrap(), root);
+
+    let path = Fr::from(5u64);
+    tree.insert_at_path(&path, &Fr::from(50u64)).unwrap();
+    assert_ne!(tree.root().unwrap(), root);
+    tree.delete_at_path(&path).unwrap();
+    assert_eq!(tree.root().unwrap(), root);
+}
+
+#[test]
+fn test_every_poseidon_constructor_folds() {
+    let depth = 20;
+    let root = get_empty_root(depth);
+    let hasher = || Poseidon::<Fr>::new_circom(2).unwrap();
+
+    assert_folds_to(SparseMerkleTree::new(depth).unwrap(), root);
+    assert_folds_to(
+        SparseMerkleTree::new_with_hasher(depth, hasher()).unwrap(),
+        root,
+    );
+    assert_folds_to(
+        SparseMerkleTree::new_with_arity(depth, Arity::Binary, hasher()).unwrap(),
+        root,
+    );
+    assert_folds_to(
+        SparseMerkleTree::new_with_custom_hasher(depth, Arity::Binary, hasher()).unwrap(),
+        root,
+    );
+    assert_folds_to(
+        SparseMerkleTree::new_with_parameters(depth, get_poseidon_parameters(3).unwrap()).unwrap(),
+        root,
+    );
+}
+
+/// The empty root of a binary tree of `family` folded from a zero leaf
+#[cfg(any(feature = "poseidon2", feature = "mimc", feature = "rescue"))]
+fn family_empty_root(family: HashFamily, depth: usize) -> Fr {
+    zero_hash_for(
+        0,
+        depth,
+        LeafHashing::default(),
+        family,
+        HashingScheme::Legacy,
+    )
+}
+
+#[cfg(feature = "poseidon2")]
+#[test]
+fn test_poseidon2_constructor_folds() {
+    let root = family_empty_root(HashFamily::Poseidon2, 16);
+    assert_ne!(root, get_empty_root(16));
+    assert_folds_to(SparseMerkleTree::new_poseidon2(16).unwrap(), root);
+}
+
+#[cfg(feature = "mimc")]
+#[test]
+fn test_mimc_constructor_folds() {
+    let root = family_empty_root(HashFamily::Mimc, 16);
+    assert_folds_to(SparseMerkleTree::new_mimc(16).unwrap(), root);
+}
+
+#[cfg(feature = "rescue")]
+#[test]
+fn test_rescue_constructor_folds() {
+    let root = family_empty_root(HashFamily::Rescue, 16);
+    assert_folds_to(SparseMerkleTree::new_rescue(16).unwrap(), root);
+}
+
+#[test]
+fn test_folded_empty_roots_match_the_vectors() {
+    for (depth, root) in EMPTY_ROOTS {
+        let tree = folded_tree(depth);
+
+        assert_eq!(get_empty_hash_at_level(0, depth), root);
+        assert_eq!(tree.root().unwrap(), root);
+        assert!(tree.is_empty().unwrap());
+        assert_eq!(tree.empty_subtrees(), EmptySubtrees::Folded);
+        assert_eq!(flat_tree(depth).root().unwrap(), *get_empty_inner_hash());
+    }
+
+    assert_eq!(get_empty_hash_at_level(20, 20), Fr::from(0u64));
+    assert_eq!(get_empty_hash_at_level(19, 20), *get_empty_inner_hash());
+    assert_eq!(get_empty_hash_at_level(3, 20), zero_hash(3, 20));
+}
+
+#[test]
+fn test_missing_nodes_hash_their_level() {
+    let mut tree = folded_tree(4);
+    let mut flat = flat_tree(4);
+    for tree in [&mut tree, &mut flat] {
+        tree.insert_at_path(&Fr::from(0u64), &Fr::from(7u64))
+            .unwrap();
+    }
+
+    // Every sibling on the path of 0 is empty, an empty subtree of its own height
+    let mut expected = poseidon(Fr::from(7u64), Fr::from(0u64));
+    for level in (1..4).rev() {
+        expected = poseidon(expected, get_empty_hash_at_level(level, 4));
+    }
+    assert_eq!(tree.root().unwrap(), expected);
+    assert_ne!(flat.root().unwrap(), expected);
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_folded_proofs_verify() {
+    let (depth, empty_root) = EMPTY_ROOTS[2];
+    let mut tree = folded_tree(depth);
+    for index in [1u64, 2, 3, 1000] {
+        tree.insert_at_path(&Fr::from(index), &Fr::from(index * 10))
+            .unwrap();
+    }
+    let root = tree.root().unwrap();
+
+    let proof = tree.generate_proof(&Fr::from(1000u64)).unwrap();
+    assert_eq!(proof.empty_subtrees, EmptySubtrees::Folded);
+    assert!(tree.verify_proof(&proof).unwrap());
+
+    // A path without a leaf is proven empty by a non-membership proof only
+    assert_eq!(
+        tree.generate_proof(&Fr::from(77u64)).unwrap_err(),
+        PoseidonMerkleError::LeafNotFound {
+            path: Fr::from(77u64)
+        }
+    );
+    let proof = tree
+        .generate_non_membership_proof(&Fr::from(77u64))
+        .unwrap();
+    assert!(proof
+        .verify_against(&mut Poseidon::<Fr>::new_circom(2).unwrap(), &root)
+        .unwrap());
+
+    // The flag is needed to fold the empty subtree a non-membership proof starts from
+    let mut flat = proof.clone();
+    flat.proof.empty_subtrees = EmptySubtrees::Flat;
+    assert!(!flat
+        .verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap())
+        .unwrap());
+
+    for index in [1u64, 2, 3, 1000] {
+        tree.delete_at_path(&Fr::from(index)).unwrap();
+    }
+    assert_eq!(tree.root().unwrap(), empty_root);
+    assert!(tree.is_empty().unwrap());
+}
+
+#[test]
+fn test_snapshots_keep_the_empty_subtrees() {
+    let mut tree = folded_tree(16);
+    tree.insert_at_path(&Fr::from(9u64), &Fr::from(90u64))
+        .unwrap();
+
+    let loaded = SparseMerkleTree::from_snapshot_bytes(&tree.to_snapshot_bytes().unwrap()).unwrap();
+
+    assert_eq!(loaded.empty_subtrees(), EmptySubtrees::Folded);
+    assert_eq!(loaded.root().unwrap(), tree.root().unwrap());
+}
+
+#[test]
+fn test_precomputed_empty_roots() {
+    for depth in 0..=PRECOMPUTED_EMPTY_ROOTS + 2 {
+        assert_eq!(get_empty_root(depth), zero_hash(0, depth));
+    }
+    for (depth, root) in EMPTY_ROOTS {
+        assert_eq!(get_empty_root(depth), root);
+    }
+    assert_eq!(get_empty_root(0), Fr::from(0u64));
+    assert_eq!(get_empty_root(1), *get_empty_inner_hash());
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_insert_then_delete_restores_the_untouched_root() {
+    for depth in [1, 2, 20, 40] {
+        let trees = [
+            folded_tree(depth),
+            flat_tree(depth),
+            folded_tree(depth).with_hashing_scheme(HashingScheme::LevelBound),
+        ];
+        for mut tree in trees {
+            let untouched = tree.root().unwrap();
+            assert_eq!(tree.root_hash().unwrap(), untouched);
+
+            let path = Fr::from(1u64);
+            tree.insert_at_path(&path, &Fr::from(11u64)).unwrap();
+            assert_ne!(tree.root().unwrap(), untouched);
+            tree.delete_at_path(&path).unwrap();
+
+            assert_eq!(tree.root().unwrap(), untouched);
+            assert_eq!(tree.root_hash().unwrap(), untouched);
+            assert!(tree.is_empty().unwrap());
+        }
+
+        // A default tree starts from the folded empty root, and comes back to it
+        let mut tree = SparseMerkleTree::new(depth).unwrap();
+        assert_eq!(tree.root().unwrap(), get_empty_root(depth), "depth {depth}");
+        tree.insert_at_path(&Fr::from(0u64), &Fr::from(5u64))
+            .unwrap();
+        tree.delete_at_path(&Fr::from(0u64)).unwrap();
+        assert_eq!(tree.root().unwrap(), get_empty_root(depth), "depth {depth}");
+    }
+}
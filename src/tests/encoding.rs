@@ -0,0 +1,183 @@
+use ark_bls12_381::Fr as BlsFr;
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+use serde_json::{json, Value};
+
+use crate::{FrEncoding, MerkleProof, PoseidonMerkleError, SparseMerkleTree};
+
+const ENCODINGS: [FrEncoding; 4] = [
+    FrEncoding::Decimal,
+    FrEncoding::HexBe,
+    FrEncoding::HexLe,
+    FrEncoding::Bytes,
+];
+
+/// The largest element, `p - 1`
+fn max<F: PrimeField>() -> F {
+    -F::one()
+}
+
+#[test]
+fn test_each_encoding_round_trips() {
+    let values = [Fr::from(0u64), Fr::from(1u64), Fr::from(0x1234u64), max()];
+    for encoding in ENCODINGS {
+        for value in values {
+            let encoded = encoding.encode(&value);
+            assert_eq!(encoding.decode::<Fr>(&encoded), Ok(value), "{encoding}");
+            assert_eq!(encoding.to_string().parse(), Ok(encoding));
+        }
+    }
+
+    // Other fields use as many bytes as their big integers have
+    let value = max::<BlsFr>();
+    for encoding in ENCODINGS {
+        assert_eq!(encoding.decode(&encoding.encode(&value)), Ok(value));
+    }
+}
+
+#[test]
+fn test_encodings_of_a_value() {
+    let value = Fr::from(0x1234u64);
+    let mut bytes = [0u8; 32];
+    bytes[30..].copy_from_slice(&[0x12, 0x34]);
+
+    assert_eq!(FrEncoding::default(), FrEncoding::Decimal);
+    assert_eq!(FrEncoding::Decimal.encode(&value), json!("4660"));
+    assert_eq!(
+        FrEncoding::HexBe.encode(&value),
+        json!(format!("0x{}1234", "0".repeat(60)))
+    );
+    assert_eq!(
+        FrEncoding::HexLe.encode(&value),
+        json!(format!("0x3412{}", "0".repeat(60)))
+    );
+    assert_eq!(FrEncoding::Bytes.encode(&value), json!(bytes));
+    assert_eq!(
+        FrEncoding::Decimal.encode(&max::<Fr>()),
+        json!(max::<Fr>().into_bigint().to_string())
+    );
+
+    // Short hex is padded on the side of the high bytes
+    assert_eq!(FrEncoding::HexBe.decode(&json!("0x1234")), Ok(value));
+    assert_eq!(FrEncoding::HexLe.decode(&json!("0x3412")), Ok(value));
+}
+
+#[test]
+fn test_parse_mixed_inputs() {
+    let value = Fr::from(0x1234u64);
+    let mut bytes = [0u8; 32];
+    bytes[30..].copy_from_slice(&[0x12, 0x34]);
+
+    for input in [
+        json!("4660"),
+        json!("004660"),
+        json!("0x1234"),
+        json!(format!("0x{}1234", "0".repeat(60))),
+        json!(bytes),
+        json!(4660),
+    ] {
+        assert_eq!(FrEncoding::parse::<Fr>(&input), Ok(value), "{input}");
+    }
+
+    // A proof whose elements come in every encoding parse reads
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(4).unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+    let proof = tree.generate_proof(&Fr::from(5u64)).unwrap();
+    let mut json = proof.to_json(FrEncoding::Decimal);
+    json["root"] = FrEncoding::HexBe.encode(&proof.root_hash);
+    json["siblings"][0] = FrEncoding::Bytes.encode(&proof.siblings[0]);
+    json["path"] = json!(5);
+
+    let parsed = MerkleProof::from_json(&json).unwrap();
+    assert_eq!(parsed.siblings, proof.siblings);
+    assert_eq!(parsed.root_hash, proof.root_hash);
+    assert!(tree.verify_proof(&parsed).unwrap());
+    for encoding in ENCODINGS {
+        let parsed = match encoding {
+            // Little-endian hex reads like big-endian hex, it is never guessed
+            FrEncoding::HexLe => continue,
+            _ => MerkleProof::from_json(&proof.to_json(encoding)).unwrap(),
+        };
+        assert!(tree.verify_proof(&parsed).unwrap());
+    }
+}
+
+#[test]
+fn test_rejects_invalid_inputs() {
+    let modulus = Fr::MODULUS.to_string();
+    let modulus_bytes = Fr::MODULUS.to_bytes_be();
+    let invalid = |input: &Value, reason| {
+        Err::<Fr, _>(PoseidonMerkleError::InvalidFieldElement {
+            input: input.to_string(),
+            reason,
+        })
+    };
+
+    for (encoding, input, reason) in [
+        (FrEncoding::Decimal, json!(modulus), "not below the modulus"),
+        (
+            FrEncoding::Decimal,
+            json!("1".repeat(80)),
+            "not below the modulus",
+        ),
+        (FrEncoding::Decimal, json!("-1"), "not a decimal number"),
+        (FrEncoding::Decimal, json!(""), "not a decimal number"),
+        (FrEncoding::Decimal, json!(1), "not a string"),
+        (FrEncoding::HexBe, json!("12"), "no 0x prefix"),
+        (FrEncoding::HexBe, json!("0xg"), "not a hex digit"),
+        (
+            FrEncoding::HexBe,
+            json!(format!("0x{}", "1".repeat(65))),
+            "not 1 to 2 hex digits per byte of the field",
+        ),
+        (
+            FrEncoding::HexBe,
+            json!(format!("0x{}", "f".repeat(64))),
+            "not below the modulus",
+        ),
+        (
+            FrEncoding::HexLe,
+            json!("0x123"),
+            "odd number of little-endian hex digits",
+        ),
+        (
+            FrEncoding::Bytes,
+            json!([1, 2]),
+            "not one byte per byte of the field",
+        ),
+        (
+            FrEncoding::Bytes,
+            json!(modulus_bytes),
+            "not below the modulus",
+        ),
+    ] {
+        assert_eq!(
+            encoding.decode::<Fr>(&input),
+            invalid(&input, reason),
+            "{input}"
+        );
+    }
+
+    let input = json!(vec![256; 32]);
+    assert_eq!(
+        FrEncoding::Bytes.decode::<Fr>(&input),
+        invalid(&input, "not an array of bytes")
+    );
+    for (input, reason) in [
+        (json!(-1), "not an unsigned integer"),
+        (json!(null), "not a string, number or array"),
+    ] {
+        assert_eq!(FrEncoding::parse::<Fr>(&input), invalid(&input, reason));
+    }
+
+    assert_eq!(
+        "hex".parse::<FrEncoding>().err().unwrap(),
+        "unknown encoding \"hex\", expected decimal, hex-be, hex-le or bytes"
+    );
+    assert!(matches!(
+        MerkleProof::<Fr>::from_json(&json!({ "leaf": "1" })),
+        Err(PoseidonMerkleError::InvalidProofJson(_))
+    ));
+}
@@ -0,0 +1,100 @@
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{Forest, ForestError, SparseMerkleTree};
+
+/// Trees of depths 4, 8 and 16, each holding a few values
+fn trees() -> Vec<(&'static str, SparseMerkleTree<Poseidon<Fr>>)> {
+    [("accounts", 4), ("nullifiers", 8), ("notes", 16)]
+        .into_iter()
+        .map(|(name, depth)| {
+            let mut tree = SparseMerkleTree::new(depth).unwrap();
+            for index in 1..4u64 {
+                tree.insert_at_path(&Fr::from(index * 3), &Fr::from(index + depth as u64))
+                    .unwrap();
+            }
+            (name, tree)
+        })
+        .collect()
+}
+
+fn forest(trees: Vec<(&str, SparseMerkleTree<Poseidon<Fr>>)>) -> Forest {
+    let mut forest = Forest::new();
+    for (name, tree) in trees {
+        assert!(forest.insert_tree(name, tree).unwrap().is_none());
+    }
+    forest
+}
+
+#[test]
+fn test_forest_proofs_verify_against_the_forest_root() {
+    let forest = forest(trees());
+    let root = forest.forest_root().unwrap();
+
+    for name in ["accounts", "nullifiers", "notes"] {
+        let proof = forest.generate_forest_proof(name, &Fr::from(6u64)).unwrap();
+        assert_eq!(
+            proof.tree_proof.root_hash,
+            forest.tree(name).unwrap().root().unwrap()
+        );
+        assert!(proof.verify(&root).unwrap());
+    }
+
+    let proof = forest
+        .generate_forest_proof("notes", &Fr::from(6u64))
+        .unwrap();
+    assert!(!proof.verify(&(root + Fr::from(1u64))).unwrap());
+    let mut renamed = proof.clone();
+    renamed.name = "accounts".to_string();
+    assert!(!renamed.verify(&root).unwrap());
+    let mut tampered = proof;
+    tampered.tree_proof.leaf_value += Fr::from(1u64);
+    assert!(!tampered.verify(&root).unwrap());
+
+    assert!(matches!(
+        forest.generate_forest_proof("missing", &Fr::from(6u64)),
+        Err(ForestError::UnknownTree { name }) if name == "missing"
+    ));
+}
+
+#[test]
+fn test_forest_root_ignores_the_insertion_order() {
+    let root = forest(trees()).forest_root().unwrap();
+    let mut reversed = trees();
+    reversed.reverse();
+    assert_eq!(forest(reversed).forest_root().unwrap(), root);
+}
+
+#[test]
+fn test_forest_root_follows_its_trees() {
+    let mut forest = forest(trees());
+    let root = forest.forest_root().unwrap();
+
+    forest
+        .tree_mut("nullifiers")
+        .unwrap()
+        .insert_at_path(&Fr::from(100u64), &Fr::from(1u64))
+        .unwrap();
+    let updated = forest.forest_root().unwrap();
+    assert_ne!(updated, root);
+    let proof = forest
+        .generate_forest_proof("nullifiers", &Fr::from(100u64))
+        .unwrap();
+    assert!(proof.verify(&updated).unwrap());
+    assert!(!proof.verify(&root).unwrap());
+
+    // Adding a tree changes the root, removing it again gives the root back
+    forest
+        .insert_tree("receipts", SparseMerkleTree::new(4).unwrap())
+        .unwrap();
+    let added = forest.forest_root().unwrap();
+    assert_ne!(added, updated);
+    assert!(forest.remove_tree("receipts").is_some());
+    assert_eq!(forest.forest_root().unwrap(), updated);
+    assert!(forest.remove_tree("notes").is_some());
+    assert_ne!(forest.forest_root().unwrap(), updated);
+    assert_eq!(
+        forest.names().collect::<Vec<_>>(),
+        ["accounts", "nullifiers"]
+    );
+}
@@ -0,0 +1,121 @@
+use std::{sync::Arc, thread};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{Arity, BitOrder, MerkleProof, PoseidonMerkleError, SparseMerkleTree};
+
+const LEAVES: u64 = 16;
+
+/// Proofs have no `PartialEq`, their `Debug` shows every field
+fn assert_same_proof(actual: &MerkleProof, expected: &MerkleProof) {
+    assert_eq!(format!("{actual:?}"), format!("{expected:?}"));
+}
+
+fn populated_tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(6).unwrap();
+    for path in 0..LEAVES {
+        tree.insert_at_path(&Fr::from(path * 3), &Fr::from(path + 100))
+            .unwrap();
+    }
+    tree
+}
+
+#[test]
+fn test_frozen_view_proves_like_the_tree() {
+    let tree = populated_tree();
+    let view = tree.freeze().unwrap();
+
+    assert_eq!(view.root(), tree.root().unwrap());
+    for path in 0..LEAVES * 3 {
+        let path = Fr::from(path);
+        assert_eq!(view.get(&path), tree.try_get_value(&path).unwrap());
+        match tree.generate_proof(&path) {
+            Ok(proof) => assert_same_proof(&view.generate_proof(&path).unwrap(), &proof),
+            Err(err) => assert_eq!(view.generate_proof(&path).unwrap_err(), err),
+        }
+    }
+    assert_eq!(
+        view.generate_proof(&Fr::from(1u64)).unwrap_err(),
+        PoseidonMerkleError::LeafNotFound {
+            path: Fr::from(1u64)
+        }
+    );
+
+    let leaves: Vec<_> = view.leaves().collect();
+    assert_eq!(leaves.len(), LEAVES as usize);
+    for (path, value) in leaves {
+        assert_eq!(tree.try_get_value(&path).unwrap(), Some(value));
+    }
+}
+
+#[test]
+fn test_frozen_view_of_wide_msb_first_tree() {
+    let mut tree = SparseMerkleTree::new_with_arity(
+        3,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap()
+    .with_bit_order(BitOrder::MsbFirst);
+    for path in [0u64, 7, 21, 63] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(path + 1))
+            .unwrap();
+    }
+    let view = tree.freeze().unwrap();
+
+    let paths: Vec<_> = view.leaves().map(|(path, _)| path).collect();
+    assert_eq!(
+        paths,
+        [0u64, 7, 21, 63].map(Fr::from).to_vec(),
+        "MSB first, the index order is the path order"
+    );
+    let mut hasher = Poseidon::<Fr>::new_circom(4).unwrap();
+    let proof = view.generate_proof(&Fr::from(21u64)).unwrap();
+    assert_same_proof(&proof, &tree.generate_proof(&Fr::from(21u64)).unwrap());
+    assert!(proof.verify_proof(&mut hasher).unwrap());
+}
+
+#[test]
+fn test_frozen_view_is_stable_while_the_tree_changes() {
+    let mut tree = populated_tree();
+    let root = tree.root().unwrap();
+    let view = Arc::new(tree.freeze().unwrap());
+
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let view = view.clone();
+            thread::spawn(move || {
+                let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+                for _ in 0..8 {
+                    assert_eq!(view.root(), root);
+                    for path in 0..LEAVES {
+                        let proof = view.generate_proof(&Fr::from(path * 3)).unwrap();
+                        assert_eq!(proof.root_hash, root);
+                        assert_eq!(proof.leaf_value, Fr::from(path + 100));
+                        assert!(proof.verify_proof(&mut hasher).unwrap());
+                    }
+                }
+            })
+        })
+        .collect();
+
+    // Overwrite, add and delete leaves, then start over, while the workers read the view
+    for round in 0..4u64 {
+        for path in 0..LEAVES * 3 {
+            tree.insert_at_path(&Fr::from(path), &Fr::from(path * round))
+                .unwrap();
+        }
+        for path in 0..LEAVES {
+            tree.delete_at_path(&Fr::from(path * 3)).unwrap();
+        }
+        tree.clear();
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    assert_ne!(tree.root().unwrap(), root);
+    assert_eq!(view.root(), root);
+    assert_eq!(view.get(&Fr::from(3u64)), Some(Fr::from(101u64)));
+}
@@ -0,0 +1,112 @@
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+
+use crate::{
+    eth::{proof_to_calldata, to_bytes32},
+    fuzz, Arity, BitOrder, MerkleProof, PoseidonMerkleError, ProofError, SparseMerkleTree,
+};
+
+/// The bytes of a seed and of its truncations and single byte flips at a few places
+fn mutations(seed: &[u8]) -> Vec<Vec<u8>> {
+    let places = [0, 1, 4, 9, 13, 14, 46, 78, seed.len() / 2, seed.len() - 1];
+    let mut inputs = vec![seed.to_vec(), Vec::new()];
+    for place in places.into_iter().filter(|place| *place < seed.len()) {
+        inputs.push(seed[..place].to_vec());
+        for flip in [0x01, 0x80, 0xff] {
+            let mut input = seed.to_vec();
+            input[place] ^= flip;
+            inputs.push(input);
+        }
+    }
+    inputs
+}
+
+fn snapshot_seeds() -> Vec<Vec<u8>> {
+    let mut binary = SparseMerkleTree::new(8).unwrap();
+    binary
+        .insert_at_path(&Fr::from(3u64), &Fr::from(5u64))
+        .unwrap();
+    let mut octal =
+        SparseMerkleTree::new_with_arity(3, Arity::Octal, Poseidon::<Fr>::new_circom(8).unwrap())
+            .unwrap()
+            .with_bit_order(BitOrder::MsbFirst);
+    octal
+        .insert_at_path(&Fr::from(100u64), &Fr::from(7u64))
+        .unwrap();
+
+    vec![
+        binary.to_snapshot_bytes().unwrap(),
+        octal.to_snapshot_bytes().unwrap(),
+    ]
+}
+
+/// A proof of a binary tree, as the arity byte, the root word and the calldata words
+fn calldata_seed() -> (Vec<u8>, Vec<u8>) {
+    let mut tree = SparseMerkleTree::new(4).unwrap();
+    tree.insert_at_path(&Fr::from(6u64), &Fr::from(9u64))
+        .unwrap();
+    let proof = tree.generate_proof(&Fr::from(6u64)).unwrap();
+
+    let mut calldata = vec![0];
+    calldata.extend(to_bytes32(&proof.root_hash));
+    calldata.extend(proof_to_calldata(&proof).concat());
+    // The arity and conventions bytes, then the leaf, root, path and siblings
+    let mut verify = vec![0, 0];
+    verify.extend(to_bytes32(&proof.leaf_value));
+    verify.extend(to_bytes32(&proof.root_hash));
+    verify.extend(to_bytes32(&proof.merkle_path));
+    verify.extend(proof.siblings.iter().flat_map(to_bytes32));
+
+    (calldata, verify)
+}
+
+#[test]
+fn test_harnesses_take_the_seeds_and_their_mutations() {
+    for seed in snapshot_seeds() {
+        for input in mutations(&seed) {
+            fuzz::snapshot_bytes(&input);
+        }
+    }
+    let (calldata, verify) = calldata_seed();
+    for input in mutations(&calldata) {
+        fuzz::proof_calldata(&input);
+    }
+    for input in mutations(&verify) {
+        fuzz::verify_proof(&input);
+    }
+}
+
+#[test]
+fn test_proofs_deeper_than_a_path_fail_to_verify() {
+    let siblings = vec![Fr::from(1u64); 300];
+    let proof = MerkleProof::new(siblings, Fr::from(0u64), Fr::from(2u64), Fr::from(3u64));
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    assert!(matches!(
+        proof.verify_proof(&mut hasher),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::TooManySiblings { len: 300, max: 254 }
+        ))
+    ));
+
+    let mut input = vec![0, 0];
+    input.extend([0; 3 * 32]);
+    input.extend(Fr::MODULUS.to_bytes_be().repeat(300));
+    fuzz::verify_proof(&input);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_json_harness_takes_proofs_and_garbage() {
+    let mut tree = SparseMerkleTree::new(4).unwrap();
+    tree.insert_at_path(&Fr::from(6u64), &Fr::from(9u64))
+        .unwrap();
+    let proof = tree.generate_proof(&Fr::from(6u64)).unwrap();
+    let seed = proof.to_json(crate::FrEncoding::Decimal).to_string();
+
+    for input in mutations(seed.as_bytes()) {
+        fuzz::proof_json(&input);
+    }
+    fuzz::proof_json(br#"{"leaf": 1, "root": 2, "path": 0, "siblings": []}"#);
+    fuzz::proof_json(br#"{"leaf": "0x", "root": [], "path": -1, "siblings": [1e400]}"#);
+}
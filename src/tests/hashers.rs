@@ -0,0 +1,187 @@
+//! Hasher-agnostic tree tests, instantiated for every `MerkleHasher` by `hasher_suite!`
+
+use ark_bn254::Fr;
+use ark_ff::AdditiveGroup;
+use light_poseidon::{Poseidon, PoseidonError};
+
+#[cfg(feature = "mimc")]
+use crate::MimcHasher;
+#[cfg(feature = "rescue")]
+use crate::RescueHasher;
+use crate::{
+    Arity, HashFamily, HashingScheme, MerkleHasher, PoseidonMerkleError, ProofError,
+    SparseMerkleTree, TestHasher,
+};
+
+/// Run every test below with the hasher built by `$hasher`
+macro_rules! hasher_suite {
+    ($module:ident, $hasher:expr) => {
+        mod $module {
+            use super::*;
+
+            hasher_suite!(@tests $hasher;
+                test_empty_root,
+                test_manual_root,
+                test_proofs,
+                test_tampered_proof,
+                test_incremental_root,
+                test_domain_separated_proofs,
+                test_proof_records_family,
+                test_iterator
+            );
+        }
+    };
+    (@tests $hasher:expr; $($test:ident),*) => {
+        $(
+            #[test]
+            fn $test() {
+                super::$test(|| $hasher);
+            }
+        )*
+    };
+}
+
+hasher_suite!(poseidon, Poseidon::<Fr>::new_circom(2).unwrap());
+hasher_suite!(test_hasher, TestHasher::new());
+#[cfg(feature = "mimc")]
+hasher_suite!(mimc, MimcHasher::new());
+#[cfg(feature = "rescue")]
+hasher_suite!(rescue, RescueHasher::new());
+
+fn test_empty_root<H: MerkleHasher>(new_hasher: impl Fn() -> H) {
+    let tree = SparseMerkleTree::new_with_hasher(3, new_hasher()).unwrap();
+    assert!(tree.is_empty().unwrap());
+
+    // The empty subtrees are folded from the zero leaf up with the hasher of the tree
+    let mut hasher = new_hasher();
+    let root = (0..3).fold(Fr::ZERO, |empty, _| {
+        hasher.hash_children(&[empty, empty]).unwrap()
+    });
+    assert_eq!(tree.root().unwrap(), root);
+}
+
+fn test_manual_root<H: MerkleHasher>(new_hasher: impl Fn() -> H) {
+    let mut tree = SparseMerkleTree::new_with_hasher(2, new_hasher()).unwrap();
+    let (a, b) = (Fr::from(10u64), Fr::from(20u64));
+    // Path 1 goes right then left, path 2 left then right
+    tree.insert_at_path(&Fr::from(1u64), &a).unwrap();
+    tree.insert_at_path(&Fr::from(2u64), &b).unwrap();
+
+    let mut hasher = new_hasher();
+    let left = hasher.hash_two(&Fr::ZERO, &b).unwrap();
+    let right = hasher.hash_two(&a, &Fr::ZERO).unwrap();
+    assert_eq!(
+        tree.root().unwrap(),
+        hasher.hash_two(&left, &right).unwrap()
+    );
+}
+
+fn populated_tree<H: MerkleHasher>(hasher: H) -> SparseMerkleTree<H> {
+    let mut tree = SparseMerkleTree::new_with_hasher(4, hasher).unwrap();
+    for (path, value) in [(0u64, 5u64), (3, 30), (9, 90), (15, 150)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree
+}
+
+fn test_proofs<H: MerkleHasher>(new_hasher: impl Fn() -> H) {
+    let tree = populated_tree(new_hasher());
+    for path in [0u64, 3, 9, 15] {
+        let proof = tree.generate_proof(&Fr::from(path)).unwrap();
+        assert!(proof.verify_proof(&mut new_hasher()).unwrap());
+        assert!(tree.verify_proof(&proof).unwrap());
+    }
+}
+
+fn test_tampered_proof<H: MerkleHasher>(new_hasher: impl Fn() -> H) {
+    let tree = populated_tree(new_hasher());
+    let mut proof = tree.generate_proof(&Fr::from(9u64)).unwrap();
+    proof.leaf_value = Fr::from(91u64);
+    assert!(!proof.verify_proof(&mut new_hasher()).unwrap());
+}
+
+fn test_incremental_root<H: MerkleHasher>(new_hasher: impl Fn() -> H) {
+    let mut tree = populated_tree(new_hasher());
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(31u64))
+        .unwrap();
+    tree.delete_at_path(&Fr::from(0u64)).unwrap();
+
+    let root = tree.root().unwrap();
+    #[allow(deprecated)]
+    let recomputed = tree.root_hash().unwrap();
+    assert_eq!(root, recomputed);
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+fn test_domain_separated_proofs<H: MerkleHasher>(new_hasher: impl Fn() -> H) {
+    let tree = populated_tree(new_hasher()).with_hashing_scheme(HashingScheme::DomainSeparated);
+    tree.root().unwrap();
+
+    let proof = tree.generate_proof(&Fr::from(15u64)).unwrap();
+    assert!(proof.verify_proof(&mut new_hasher()).unwrap());
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+fn test_proof_records_family<H: MerkleHasher>(new_hasher: impl Fn() -> H) {
+    let tree = populated_tree(new_hasher());
+    let proof = tree.generate_proof(&Fr::from(3u64)).unwrap();
+    assert_eq!(proof.hash_family, tree.hash_family());
+}
+
+fn test_iterator<H: MerkleHasher>(new_hasher: impl Fn() -> H) {
+    let tree = populated_tree(new_hasher());
+    let mut values: Vec<Fr> = tree.iter().collect::<Result<_, _>>().unwrap();
+    values.sort();
+    assert_eq!(
+        values,
+        [5u64, 30, 90, 150].map(Fr::from),
+        "every leaf is visited once"
+    );
+}
+
+#[test]
+fn test_test_hasher_family() {
+    let tree = SparseMerkleTree::new_with_hasher(2, TestHasher::new()).unwrap();
+    assert_eq!(tree.hash_family(), HashFamily::Custom);
+
+    let tree = populated_tree(TestHasher::new());
+    let proof = tree.generate_proof(&Fr::from(3u64)).unwrap();
+    assert_eq!(
+        proof.verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap()),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::HashFamilyMismatch {
+                expected: HashFamily::Custom,
+                actual: HashFamily::Poseidon,
+            }
+        ))
+    );
+
+    let poseidon_tree = populated_tree(Poseidon::<Fr>::new_circom(2).unwrap());
+    let poseidon_proof = poseidon_tree.generate_proof(&Fr::from(3u64)).unwrap();
+    assert!(matches!(
+        tree.verify_proof(&poseidon_proof),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::HashFamilyMismatch { .. }
+        ))
+    ));
+    assert!(poseidon_tree.verify_proof(&poseidon_proof).unwrap());
+}
+
+#[test]
+fn test_test_hasher_widths() {
+    let mut hasher = TestHasher::new();
+    assert_eq!(
+        MerkleHasher::<Fr>::hash_children(&mut hasher, &[]),
+        Err(PoseidonError::EmptyInput)
+    );
+    assert!(hasher.hash_children(&[Fr::ZERO; 9]).is_err());
+
+    let mut tree = SparseMerkleTree::new_with_arity(2, Arity::Octal, hasher).unwrap();
+    tree.insert_at_path(&Fr::from(42u64), &Fr::from(7u64))
+        .unwrap();
+    let proof = tree.generate_proof(&Fr::from(42u64)).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+}
@@ -0,0 +1,134 @@
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, PrimeField};
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{
+    verify::{hash_key, key_chunk_bytes},
+    Arity, PoseidonMerkleError, SparseMerkleTree,
+};
+
+const DEPTH: usize = 32;
+/// `hash_key` of `ADDRESS` with the binary circom parameters, and its low `DEPTH` bits
+const KNOWN_HASH: &str =
+    "13893342311913226427258438686435458429350141757062924222087494425392541267682";
+const KNOWN_PATH: u64 = 3759601378;
+const ADDRESS: [u8; 20] = [
+    0xd8, 0xda, 0x6b, 0xf2, 0x69, 0x64, 0xaf, 0x9d, 0x7e, 0xed, 0x9e, 0x03, 0xe5, 0x34, 0x15, 0xd3,
+    0x7a, 0xa9, 0x60, 0x45,
+];
+
+fn poseidon() -> Poseidon<Fr> {
+    Poseidon::<Fr>::new_circom(2).unwrap()
+}
+
+#[test]
+fn test_key_chunks() {
+    assert_eq!(key_chunk_bytes::<Fr>(), 31);
+
+    // One chunk: hasher(len, chunk)
+    let expected = poseidon()
+        .hash(&[Fr::from(20u64), Fr::from_be_bytes_mod_order(&ADDRESS)])
+        .unwrap();
+    assert_eq!(
+        hash_key(&mut poseidon(), &ADDRESS, Arity::Binary),
+        Ok(expected)
+    );
+
+    // Two chunks of 31 and 1 bytes folded in turn
+    let key = [7u8; 32];
+    let first = poseidon()
+        .hash(&[Fr::from(32u64), Fr::from_be_bytes_mod_order(&key[..31])])
+        .unwrap();
+    let expected = poseidon().hash(&[first, Fr::from(7u64)]).unwrap();
+    assert_eq!(hash_key(&mut poseidon(), &key, Arity::Binary), Ok(expected));
+
+    let empty = poseidon().hash(&[Fr::ZERO, Fr::ZERO]).unwrap();
+    assert_eq!(hash_key(&mut poseidon(), &[], Arity::Binary), Ok(empty));
+}
+
+#[test]
+fn test_path_for_key_known_answer() {
+    let tree = SparseMerkleTree::new(DEPTH).unwrap();
+    let hash = hash_key(&mut poseidon(), &ADDRESS, Arity::Binary).unwrap();
+    assert_eq!(hash, Fr::from_str(KNOWN_HASH).unwrap());
+    let path = tree.path_for_key(&ADDRESS).unwrap();
+    assert_eq!(path, Fr::from(KNOWN_PATH));
+    assert_eq!(tree.path_for_key(&ADDRESS).unwrap(), path);
+    assert_eq!(
+        SparseMerkleTree::new(DEPTH)
+            .unwrap()
+            .path_for_key(&ADDRESS)
+            .unwrap(),
+        path
+    );
+}
+
+#[test]
+fn test_distinct_keys_distinct_paths() {
+    let tree = SparseMerkleTree::new(DEPTH).unwrap();
+    let keys: [&[u8]; 6] = [
+        &ADDRESS,
+        &ADDRESS[..19],
+        &[0u8; 20],
+        &[0u8; 21],
+        &[0u8; 32],
+        &[],
+    ];
+
+    let mut paths: Vec<Fr> = keys
+        .iter()
+        .map(|key| tree.path_for_key(key).unwrap())
+        .collect();
+    paths.sort();
+    paths.dedup();
+    assert_eq!(paths.len(), keys.len());
+}
+
+#[test]
+fn test_keyed_operations() {
+    let mut tree = SparseMerkleTree::new(DEPTH).unwrap();
+    let other = [1u8; 32];
+    tree.insert_by_key(&ADDRESS, &Fr::from(100u64)).unwrap();
+    tree.insert_by_key(&other, &Fr::from(200u64)).unwrap();
+
+    assert_eq!(tree.get_by_key(&ADDRESS), Ok(Some(Fr::from(100u64))));
+    assert_eq!(tree.get_by_key(&other), Ok(Some(Fr::from(200u64))));
+    assert_eq!(tree.get_by_key(b"missing"), Ok(None));
+
+    tree.root().unwrap();
+    let proof = tree.prove_by_key(&ADDRESS).unwrap();
+    assert_eq!(proof.merkle_path, tree.path_for_key(&ADDRESS).unwrap());
+    assert!(proof.verify_proof(&mut poseidon()).unwrap());
+
+    let path = tree.path_for_key(b"missing").unwrap();
+    assert_eq!(
+        tree.prove_by_key(b"missing").err(),
+        Some(PoseidonMerkleError::LeafNotFound { path })
+    );
+}
+
+#[test]
+fn test_keys_in_wider_trees() {
+    let mut hasher = Poseidon::<Fr>::new_circom(4).unwrap();
+    let key = [9u8; 40];
+    let expected = hasher
+        .hash(&[
+            Fr::from(40u64),
+            Fr::from_be_bytes_mod_order(&key[..31]),
+            Fr::from_be_bytes_mod_order(&key[31..]),
+            Fr::ZERO,
+        ])
+        .unwrap();
+    assert_eq!(hash_key(&mut hasher, &key, Arity::Quaternary), Ok(expected));
+
+    let mut tree = SparseMerkleTree::new_with_arity(
+        8,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap();
+    tree.insert_by_key(&key, &Fr::from(3u64)).unwrap();
+    assert_eq!(tree.get_by_key(&key), Ok(Some(Fr::from(3u64))));
+}
@@ -0,0 +1,162 @@
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{hash_leaf_values, PoseidonMerkleError, SparseMerkleTree, MAX_LEAF_WIDTH};
+
+const OWNER: u64 = 0xa11ce;
+const AMOUNT: u64 = 1_000;
+const NONCE: u64 = 7;
+
+fn account(owner: u64, amount: u64, nonce: u64) -> [Fr; 3] {
+    [Fr::from(owner), Fr::from(amount), Fr::from(nonce)]
+}
+
+fn setup_tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(8)
+        .unwrap()
+        .try_with_leaf_width(3)
+        .unwrap();
+    tree.insert_values(&Fr::from(5u64), &account(OWNER, AMOUNT, NONCE))
+        .unwrap();
+    tree.insert_values(&Fr::from(77u64), &account(0xb0b, 20, 1))
+        .unwrap();
+    tree.root().unwrap();
+    tree
+}
+
+#[test]
+fn test_commitment_is_circom_poseidon() {
+    let values = account(OWNER, AMOUNT, NONCE);
+    let expected = Poseidon::<Fr>::new_circom(3)
+        .unwrap()
+        .hash(&values)
+        .unwrap();
+    assert_eq!(hash_leaf_values(&values).unwrap(), expected);
+
+    // The tree hashes the commitment like any other leaf value
+    let tree = setup_tree();
+    let mut plain = SparseMerkleTree::new(8).unwrap();
+    plain.insert_at_path(&Fr::from(5u64), &expected).unwrap();
+    plain
+        .insert_at_path(
+            &Fr::from(77u64),
+            &hash_leaf_values(&account(0xb0b, 20, 1)).unwrap(),
+        )
+        .unwrap();
+    assert_eq!(tree.root().unwrap(), plain.root().unwrap());
+    assert_eq!(tree.try_get_value(&Fr::from(5u64)).unwrap(), Some(expected));
+}
+
+#[test]
+fn test_get_values() {
+    let mut tree = setup_tree();
+    assert_eq!(tree.leaf_width(), 3);
+    assert_eq!(
+        tree.get_values(&Fr::from(5u64)).unwrap(),
+        Some(account(OWNER, AMOUNT, NONCE).to_vec())
+    );
+    assert_eq!(tree.get_values(&Fr::from(6u64)).unwrap(), None);
+
+    tree.insert_values(&Fr::from(5u64), &account(OWNER, AMOUNT - 1, NONCE + 1))
+        .unwrap();
+    assert_eq!(
+        tree.get_values(&Fr::from(5u64)).unwrap(),
+        Some(account(OWNER, AMOUNT - 1, NONCE + 1).to_vec())
+    );
+
+    tree.delete_at_path(&Fr::from(5u64)).unwrap();
+    assert_eq!(tree.get_values(&Fr::from(5u64)).unwrap(), None);
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_proof_with_preimage() {
+    let tree = setup_tree();
+    let proof = tree.generate_values_proof(&Fr::from(5u64), true).unwrap();
+    assert_eq!(
+        proof.leaf_preimage,
+        Some(account(OWNER, AMOUNT, NONCE).to_vec())
+    );
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert!(proof
+        .verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap())
+        .unwrap());
+
+    let mut forged = proof.clone();
+    forged.leaf_preimage = Some(account(OWNER, AMOUNT * 10, NONCE).to_vec());
+    assert!(!tree.verify_proof(&forged).unwrap());
+
+    let mut truncated = proof;
+    truncated.leaf_preimage = Some(vec![Fr::from(OWNER), Fr::from(AMOUNT)]);
+    assert_eq!(
+        tree.verify_proof(&truncated),
+        Err(PoseidonMerkleError::LeafWidthMismatch {
+            expected: 3,
+            actual: 2
+        })
+    );
+}
+
+#[test]
+fn test_proof_without_preimage() {
+    let tree = setup_tree();
+    let proof = tree.generate_values_proof(&Fr::from(77u64), false).unwrap();
+    assert_eq!(proof.leaf_preimage, None);
+    assert_eq!(
+        proof.leaf_value,
+        hash_leaf_values(&account(0xb0b, 20, 1)).unwrap()
+    );
+    assert!(tree.verify_proof(&proof).unwrap());
+}
+
+#[test]
+fn test_leaf_width_is_enforced() {
+    let mut tree = setup_tree();
+    assert_eq!(
+        tree.insert_values(&Fr::from(1u64), &[Fr::from(1u64); 2]),
+        Err(PoseidonMerkleError::LeafWidthMismatch {
+            expected: 3,
+            actual: 2
+        })
+    );
+    assert_eq!(
+        tree.insert_at_path(&Fr::from(1u64), &Fr::from(1u64)),
+        Err(PoseidonMerkleError::LeafWidthMismatch {
+            expected: 3,
+            actual: 1
+        })
+    );
+
+    for width in [0, MAX_LEAF_WIDTH + 1] {
+        assert_eq!(
+            SparseMerkleTree::new(8)
+                .unwrap()
+                .try_with_leaf_width(width)
+                .err(),
+            Some(PoseidonMerkleError::UnsupportedLeafWidth {
+                width,
+                max: MAX_LEAF_WIDTH
+            })
+        );
+    }
+}
+
+#[test]
+fn test_single_value_leaves() {
+    let mut tree = SparseMerkleTree::new(8).unwrap();
+    tree.insert_values(&Fr::from(3u64), &[Fr::from(30u64)])
+        .unwrap();
+    assert_eq!(
+        tree.try_get_value(&Fr::from(3u64)).unwrap(),
+        Some(Fr::from(30u64))
+    );
+    assert_eq!(
+        tree.get_values(&Fr::from(3u64)).unwrap(),
+        Some(vec![Fr::from(30u64)])
+    );
+
+    tree.root().unwrap();
+    let proof = tree.generate_values_proof(&Fr::from(3u64), true).unwrap();
+    assert_eq!(proof.leaf_preimage, None);
+    assert!(tree.verify_proof(&proof).unwrap());
+}
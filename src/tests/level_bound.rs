@@ -0,0 +1,161 @@
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, Field};
+use light_poseidon::{parameters::bn254_x5::get_poseidon_parameters, Poseidon, PoseidonHasher};
+
+use crate::{Arity, HashingScheme, NodeType, PoseidonMerkleError, ProofError, SparseMerkleTree};
+
+fn poseidon() -> Poseidon<Fr> {
+    Poseidon::<Fr>::new_circom(2).unwrap()
+}
+
+/// poseidon(level, left, right) with the circom parameters
+fn level_hash(level: u64, left: Fr, right: Fr) -> Fr {
+    Poseidon::<Fr>::new_circom(3)
+        .unwrap()
+        .hash(&[Fr::from(level), left, right])
+        .unwrap()
+}
+
+fn tree(depth: usize, scheme: HashingScheme) -> SparseMerkleTree<Poseidon<Fr>> {
+    SparseMerkleTree::new(depth)
+        .unwrap()
+        .with_hashing_scheme(scheme)
+}
+
+/// Insert 10, 20 and 30 on the paths 0, 1 and 3 below the node reached by `prefix` bits
+fn fill(tree: &mut SparseMerkleTree<Poseidon<Fr>>, prefix: &[bool]) {
+    for (bits, value) in [
+        ([false, false], 10u64),
+        ([true, false], 20),
+        ([true, true], 30),
+    ] {
+        let path = tree.get_merkle_path(&[prefix, &bits[..]].concat()).unwrap();
+        tree.insert_at_path(&path, &Fr::from(value)).unwrap();
+    }
+}
+
+#[test]
+fn test_level_bound_hashes() {
+    let mut tree = tree(2, HashingScheme::LevelBound);
+    let empty_1 = level_hash(1, Fr::ZERO, Fr::ZERO);
+    let empty_root = level_hash(0, empty_1, empty_1);
+    assert_eq!(tree.root(), Ok(empty_root));
+    assert!(tree.is_empty().unwrap());
+
+    tree.insert_at_path(&Fr::from(2u64), &Fr::from(7u64))
+        .unwrap();
+    let expected = level_hash(0, level_hash(1, Fr::ZERO, Fr::from(7u64)), empty_1);
+    assert_eq!(tree.root(), Ok(expected));
+    assert_ne!(
+        tree.root(),
+        SparseMerkleTree::new(2).unwrap().root(),
+        "the legacy root differs"
+    );
+    assert!(tree.verify_integrity().is_ok());
+
+    tree.delete_at_path(&Fr::from(2u64)).unwrap();
+    assert_eq!(tree.root(), Ok(empty_root));
+}
+
+#[test]
+fn test_level_bound_proofs() {
+    let mut tree = tree(8, HashingScheme::LevelBound);
+    for (path, value) in [(3u64, 30u64), (9, 90), (200, 2000)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+
+    let proof = tree.generate_proof(&Fr::from(9u64)).unwrap();
+    assert_eq!(proof.scheme, HashingScheme::LevelBound);
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert!(proof.verify_proof(&mut poseidon()).unwrap());
+
+    let mut tampered = proof.clone();
+    tampered.leaf_value = Fr::from(91u64);
+    assert!(!tampered.verify_proof(&mut poseidon()).unwrap());
+
+    let legacy = tree_with_same_leaves();
+    assert_eq!(
+        legacy.verify_proof(&proof),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::SchemeMismatch {
+                expected: HashingScheme::LevelBound,
+                actual: HashingScheme::Legacy,
+            }
+        ))
+    );
+}
+
+fn tree_with_same_leaves() -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = tree(8, HashingScheme::Legacy);
+    for (path, value) in [(3u64, 30u64), (9, 90), (200, 2000)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree
+}
+
+/// The hash of the subtree below the root reached by taking the left child
+fn left_subtree_hash(tree: &SparseMerkleTree<Poseidon<Fr>>) -> Fr {
+    let node = tree.get_inner_node(&Fr::ZERO, 1).unwrap();
+    let node_ref = node.borrow();
+    match node_ref.node_type {
+        NodeType::Inner(hash) => hash,
+        NodeType::Leaf(_) => unreachable!("level 1 of a depth 3 tree is inner"),
+    }
+}
+
+#[test]
+fn test_transplanted_subtree() {
+    for scheme in [HashingScheme::Legacy, HashingScheme::LevelBound] {
+        // The same leaves as the root's children, then one level down
+        let mut small = tree(2, scheme);
+        fill(&mut small, &[]);
+        let mut large = tree(3, scheme);
+        fill(&mut large, &[false]);
+
+        let small_root = small.root().unwrap();
+        large.root().unwrap();
+        let transplanted = left_subtree_hash(&large);
+
+        match scheme {
+            // A root of a depth 2 tree passes for a node at level 1 of a depth 3 tree
+            HashingScheme::Legacy => assert_eq!(transplanted, small_root),
+            _ => assert_ne!(transplanted, small_root),
+        }
+    }
+}
+
+#[test]
+fn test_level_bound_hashers() {
+    // Poseidon over bn254 picks the circom parameters of one more input on its own
+    let tree = SparseMerkleTree::new_with_arity(
+        2,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap()
+    .try_with_hashing_scheme(HashingScheme::LevelBound)
+    .unwrap();
+    let empty_1 = Poseidon::<Fr>::new_circom(5)
+        .unwrap()
+        .hash(&[Fr::ONE, Fr::ZERO, Fr::ZERO, Fr::ZERO, Fr::ZERO])
+        .unwrap();
+    let empty_root = Poseidon::<Fr>::new_circom(5)
+        .unwrap()
+        .hash(&[Fr::ZERO, empty_1, empty_1, empty_1, empty_1])
+        .unwrap();
+    assert_eq!(tree.root(), Ok(empty_root));
+
+    // Other hashers must take the level as an extra input
+    let mut parameters = get_poseidon_parameters::<Fr>(3).unwrap();
+    parameters.ark[0] += Fr::ONE;
+    let custom =
+        SparseMerkleTree::new_with_custom_hasher(2, Arity::Binary, Poseidon::new(parameters))
+            .unwrap();
+    assert!(matches!(
+        custom.try_with_hashing_scheme(HashingScheme::LevelBound),
+        Err(PoseidonMerkleError::HasherError { .. })
+    ));
+}
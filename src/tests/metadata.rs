@@ -0,0 +1,101 @@
+use ark_bn254::Fr;
+
+use crate::{SparseMerkleTree, TestHasher};
+
+fn tree_with_records() -> SparseMerkleTree<TestHasher, Fr, String> {
+    let mut tree = SparseMerkleTree::new_with_hasher(8, TestHasher)
+        .unwrap()
+        .with_metadata::<String>();
+    for (index, record) in [(3u64, "alice"), (7, "bob"), (200, "carol")] {
+        tree.insert_with_meta(&Fr::from(index), &Fr::from(index * 10), record.to_string())
+            .unwrap();
+    }
+    tree
+}
+
+#[test]
+fn test_metadata_is_kept_next_to_the_value() {
+    let mut tree = tree_with_records();
+    assert_eq!(
+        tree.get_meta(&Fr::from(7u64)).unwrap().as_deref(),
+        Some("bob")
+    );
+    assert_eq!(
+        tree.try_get_value(&Fr::from(7u64)).unwrap(),
+        Some(Fr::from(70u64))
+    );
+    assert_eq!(tree.get_meta(&Fr::from(8u64)).unwrap(), None);
+
+    // Another payload replaces it, a plain insert or a delete removes it
+    tree.insert_with_meta(&Fr::from(7u64), &Fr::from(71u64), "bob v2".to_string())
+        .unwrap();
+    assert_eq!(
+        tree.get_meta(&Fr::from(7u64)).unwrap().as_deref(),
+        Some("bob v2")
+    );
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(31u64))
+        .unwrap();
+    assert_eq!(tree.get_meta(&Fr::from(3u64)).unwrap(), None);
+    tree.delete_at_path(&Fr::from(200u64)).unwrap();
+    assert_eq!(tree.get_meta(&Fr::from(200u64)).unwrap(), None);
+}
+
+#[test]
+fn test_iter_with_meta_follows_iter() {
+    let tree = tree_with_records();
+    let entries: Vec<_> = tree
+        .iter_with_meta()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(
+        entries.iter().map(|(value, _)| *value).collect::<Vec<_>>(),
+        tree.iter().collect::<Result<Vec<_>, _>>().unwrap()
+    );
+    let mut records: Vec<_> = entries.into_iter().filter_map(|(_, meta)| meta).collect();
+    records.sort();
+    assert_eq!(records, ["alice", "bob", "carol"]);
+}
+
+#[test]
+fn test_metadata_leaves_roots_and_proofs_unchanged() {
+    let tree = tree_with_records();
+    let mut plain = SparseMerkleTree::new_with_hasher(8, TestHasher).unwrap();
+    for index in [3u64, 7, 200] {
+        plain
+            .insert_at_path(&Fr::from(index), &Fr::from(index * 10))
+            .unwrap();
+    }
+
+    assert_eq!(tree.root().unwrap(), plain.root().unwrap());
+    let proof = tree.generate_proof(&Fr::from(200u64)).unwrap();
+    assert_eq!(
+        format!("{proof:?}"),
+        format!("{:?}", plain.generate_proof(&Fr::from(200u64)).unwrap())
+    );
+    assert!(plain.verify_proof(&proof).unwrap());
+}
+
+#[test]
+fn test_with_metadata_keeps_the_leaves_inserted_before() {
+    let mut plain = SparseMerkleTree::new(8).unwrap();
+    plain
+        .insert_at_path(&Fr::from(5u64), &Fr::from(1u64))
+        .unwrap();
+    let root = plain.root().unwrap();
+
+    let mut tree = plain.with_metadata::<String>();
+    assert_eq!(tree.root().unwrap(), root);
+    assert_eq!(
+        tree.try_get_value(&Fr::from(5u64)).unwrap(),
+        Some(Fr::from(1u64))
+    );
+    assert_eq!(tree.get_meta(&Fr::from(5u64)).unwrap(), None);
+
+    // Snapshots hold what the root commits to, not the payloads
+    tree.insert_with_meta(&Fr::from(6u64), &Fr::from(2u64), "record".to_string())
+        .unwrap();
+    let restored =
+        SparseMerkleTree::from_snapshot_bytes(&tree.to_snapshot_bytes().unwrap()).unwrap();
+    assert_eq!(restored.root().unwrap(), tree.root().unwrap());
+}
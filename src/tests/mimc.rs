@@ -0,0 +1,102 @@
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use ark_ff::AdditiveGroup;
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{
+    get_mimc_empty_inner_hash, zero_hash_for, Arity, HashFamily, HashingScheme, LeafHashing,
+    MimcHasher, PoseidonMerkleError, ProofError, SparseMerkleTree,
+};
+
+fn mimc(inputs: &[u64]) -> Fr {
+    let inputs: Vec<Fr> = inputs.iter().copied().map(Fr::from).collect();
+    MimcHasher::new().hash(&inputs).unwrap()
+}
+
+#[test]
+fn test_circomlib_permutation() {
+    // mimc7.hash(1, 2) of circomlibjs
+    let expected = Fr::from_str(
+        "10594780656576967754230020536574539122676596303354946869887184401991294982664",
+    )
+    .unwrap();
+    assert_eq!(
+        MimcHasher::new().permute(&Fr::from(1u64), &Fr::from(2u64)),
+        expected
+    );
+}
+
+#[test]
+fn test_known_answers() {
+    assert_eq!(mimc(&[0, 0]), *get_mimc_empty_inner_hash());
+    assert_eq!(
+        mimc(&[1, 2]),
+        Fr::from_str(
+            "5233261170300319370386085858846328736737478911451874673953613863492170606314"
+        )
+        .unwrap()
+    );
+
+    // multiHash chains the inputs with the running hash as key
+    let hasher = MimcHasher::new();
+    let first = Fr::from(1u64) + hasher.permute(&Fr::from(1u64), &Fr::ZERO);
+    let second = first + Fr::from(2u64) + hasher.permute(&Fr::from(2u64), &first);
+    assert_eq!(mimc(&[1, 2]), second);
+}
+
+#[test]
+fn test_input_count_is_checked() {
+    let mut hasher = MimcHasher::new();
+    assert!(hasher.hash(&[]).is_err());
+    assert!(hasher.hash(&[Fr::ZERO; 9]).is_err());
+}
+
+#[test]
+fn test_tree_family_and_zero_hashes() {
+    let mut tree = SparseMerkleTree::new_mimc(16).unwrap();
+    assert_eq!(tree.hash_family(), HashFamily::Mimc);
+    assert_eq!(
+        HashFamily::identify(get_mimc_empty_inner_hash(), Arity::Binary),
+        Some(HashFamily::Mimc)
+    );
+
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(30u64))
+        .unwrap();
+    tree.root().unwrap();
+    let proof = tree.generate_proof(&Fr::from(3u64)).unwrap();
+    // The missing nodes are the MiMC empty subtrees of their level, hash(0, 0) above the leaves
+    for (index, sibling) in proof.siblings.iter().enumerate() {
+        assert_eq!(
+            *sibling,
+            zero_hash_for(
+                index + 1,
+                16,
+                LeafHashing::Raw,
+                HashFamily::Mimc,
+                HashingScheme::Legacy
+            )
+        );
+    }
+    assert_eq!(proof.siblings[14], *get_mimc_empty_inner_hash());
+}
+
+#[test]
+fn test_poseidon_rejects_mimc_proofs() {
+    let mut tree = SparseMerkleTree::new_mimc(8).unwrap();
+    tree.insert_at_path(&Fr::from(7u64), &Fr::from(70u64))
+        .unwrap();
+    tree.root().unwrap();
+    let proof = tree.generate_proof(&Fr::from(7u64)).unwrap();
+
+    assert_eq!(
+        proof.verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap()),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::HashFamilyMismatch {
+                expected: HashFamily::Mimc,
+                actual: HashFamily::Poseidon,
+            }
+        ))
+    );
+    assert!(proof.verify_proof(&mut MimcHasher::new()).unwrap());
+}
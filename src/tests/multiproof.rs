@@ -0,0 +1,213 @@
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{
+    verify_leaves_against_root, Arity, BitOrder, HashingScheme, LeafHashing, MultiProof,
+    PoseidonMerkleError, ProofError, SparseMerkleTree,
+};
+
+fn poseidon() -> Poseidon<Fr> {
+    Poseidon::<Fr>::new_circom(2).unwrap()
+}
+
+/// A depth-20 tree of leaf indices, with 50 adjacent leaves from 1000 and others scattered
+fn tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(20)
+        .unwrap()
+        .with_bit_order(BitOrder::MsbFirst);
+    let scattered = (0..50u64).map(|index| index.wrapping_mul(2654435761) % (1 << 20));
+    let entries: Vec<_> = (1000..1050u64)
+        .chain(scattered)
+        .map(|index| (Fr::from(index), Fr::from(index + 1)))
+        .collect();
+    tree.insert_batch(&entries).unwrap();
+    tree
+}
+
+fn adjacent() -> Vec<Fr> {
+    (1000..1050u64).map(Fr::from).collect()
+}
+
+#[test]
+fn test_multiproof_shares_the_siblings_of_adjacent_leaves() {
+    let tree = tree();
+    let multiproof = tree.generate_multiproof(&adjacent()).unwrap();
+    assert!(multiproof.verify(&mut poseidon()).unwrap());
+    assert!(multiproof
+        .verify_against(&mut poseidon(), &tree.root().unwrap())
+        .unwrap());
+    assert_eq!(multiproof.leaves.len(), 50);
+
+    let single_siblings: usize = tree
+        .generate_proofs(&adjacent())
+        .unwrap()
+        .iter()
+        .map(|proof| proof.siblings.len())
+        .sum();
+    assert_eq!(single_siblings, 50 * 20);
+    assert!(multiproof.hashes.len() * 10 < single_siblings);
+
+    // One leaf needs all of its siblings
+    let single = tree
+        .generate_multiproof(&[Fr::from(7u64 * 2654435761 % (1 << 20))])
+        .unwrap();
+    assert_eq!(single.hashes.len(), 20);
+    assert!(single.verify(&mut poseidon()).unwrap());
+}
+
+#[test]
+fn test_multiproof_of_scattered_and_repeated_paths() {
+    let tree = tree();
+    let mut paths: Vec<Fr> = (0..50u64)
+        .map(|index| Fr::from(index.wrapping_mul(2654435761) % (1 << 20)))
+        .collect();
+    paths.extend(adjacent().into_iter().step_by(7));
+    paths.push(paths[3]);
+
+    let multiproof = tree.generate_multiproof(&paths).unwrap();
+    assert_eq!(multiproof.leaves.len(), paths.len() - 1);
+    assert!(multiproof.verify(&mut poseidon()).unwrap());
+
+    assert_eq!(
+        tree.generate_multiproof(&[Fr::from(999u64)]).unwrap_err(),
+        PoseidonMerkleError::LeafNotFound {
+            path: Fr::from(999u64)
+        }
+    );
+}
+
+#[test]
+fn test_multiproof_follows_the_tree_conventions() {
+    let mut trees = vec![
+        (
+            SparseMerkleTree::new(10)
+                .unwrap()
+                .with_leaf_hashing(LeafHashing::Hashed)
+                .with_hashing_scheme(HashingScheme::LevelBound),
+            poseidon(),
+        ),
+        (
+            SparseMerkleTree::new_with_arity(
+                5,
+                Arity::Quaternary,
+                Poseidon::<Fr>::new_circom(4).unwrap(),
+            )
+            .unwrap(),
+            Poseidon::<Fr>::new_circom(4).unwrap(),
+        ),
+    ];
+    for (tree, hasher) in &mut trees {
+        for index in [1u64, 2, 3, 500, 501, 900] {
+            tree.insert_at_path(&Fr::from(index), &Fr::from(index * 3))
+                .unwrap();
+        }
+        let multiproof = tree
+            .generate_multiproof(&[Fr::from(2u64), Fr::from(3u64), Fr::from(500u64)])
+            .unwrap();
+        assert!(multiproof.verify(hasher).unwrap());
+    }
+}
+
+#[test]
+fn test_tampered_multiproofs_fail() {
+    let tree = tree();
+    let multiproof = tree.generate_multiproof(&adjacent()).unwrap();
+
+    let mut tampered = multiproof.clone();
+    tampered.leaves[17].1 += Fr::from(1u64);
+    assert!(!tampered.verify(&mut poseidon()).unwrap());
+
+    let mut tampered = multiproof.clone();
+    tampered.hashes[3] += Fr::from(1u64);
+    assert!(!tampered.verify(&mut poseidon()).unwrap());
+
+    // Hashes missing or left over
+    let mut short = multiproof.clone();
+    short.hashes.pop();
+    assert!(!short.verify(&mut poseidon()).unwrap());
+    let mut long = multiproof.clone();
+    long.hashes.push(Fr::from(0u64));
+    assert!(!long.verify(&mut poseidon()).unwrap());
+
+    // A leaf dropped from the proof leaves a hole its siblings don't fill
+    let mut dropped = multiproof.clone();
+    dropped.leaves.remove(0);
+    assert!(!dropped.verify(&mut poseidon()).unwrap());
+
+    let mut conflicting: MultiProof = multiproof;
+    let (path, value) = conflicting.leaves[0];
+    conflicting.leaves.push((path, value + Fr::from(1u64)));
+    assert!(matches!(
+        conflicting.verify(&mut poseidon()),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::ConflictingLeaves
+        ))
+    ));
+}
+
+#[test]
+fn test_claims_verify_against_a_trusted_root() {
+    let tree = tree();
+    let root = tree.root().unwrap();
+    let scattered = (0..50u64).map(|index| index.wrapping_mul(2654435761) % (1 << 20));
+    let claim_sets: Vec<Vec<u64>> = vec![
+        vec![1003],
+        // Siblings, sharing their parent
+        vec![1005, 1004],
+        scattered.rev().chain(1000..1050).collect(),
+    ];
+
+    for indices in claim_sets {
+        let paths: Vec<Fr> = indices.iter().copied().map(Fr::from).collect();
+        let multiproof = tree.generate_multiproof(&paths).unwrap();
+        let mut claims: Vec<(u64, Fr)> = indices
+            .iter()
+            .map(|index| (*index, Fr::from(index + 1)))
+            .collect();
+
+        assert!(verify_leaves_against_root(&root, &claims, &multiproof, &mut poseidon()).unwrap());
+        claims.sort();
+        assert!(verify_leaves_against_root(&root, &claims, &multiproof, &mut poseidon()).unwrap());
+
+        let other_root = root + Fr::from(1u64);
+        assert!(
+            !verify_leaves_against_root(&other_root, &claims, &multiproof, &mut poseidon())
+                .unwrap()
+        );
+        let last = claims.len() - 1;
+        claims[last].1 += Fr::from(1u64);
+        assert!(!verify_leaves_against_root(&root, &claims, &multiproof, &mut poseidon()).unwrap());
+    }
+}
+
+#[test]
+fn test_claims_of_other_leaves_or_repeated_indices() {
+    let tree = tree();
+    let root = tree.root().unwrap();
+    let multiproof = tree
+        .generate_multiproof(&[Fr::from(1004u64), Fr::from(1005u64)])
+        .unwrap();
+
+    // The hashes only fit the leaves the multiproof was made for
+    let claims = [(1004, Fr::from(1005u64)), (1006, Fr::from(1007u64))];
+    assert!(!verify_leaves_against_root(&root, &claims, &multiproof, &mut poseidon()).unwrap());
+    let claims = [(1004, Fr::from(1005u64))];
+    assert!(!verify_leaves_against_root(&root, &claims, &multiproof, &mut poseidon()).unwrap());
+
+    // A repeated index is fine as long as it claims the same value
+    let claims = [
+        (1005, Fr::from(1006u64)),
+        (1004, Fr::from(1005u64)),
+        (1005, Fr::from(1006u64)),
+    ];
+    assert!(verify_leaves_against_root(&root, &claims, &multiproof, &mut poseidon()).unwrap());
+    let claims = [
+        (1005, Fr::from(1006u64)),
+        (1004, Fr::from(1005u64)),
+        (1005, Fr::from(7u64)),
+    ];
+    assert_eq!(
+        verify_leaves_against_root(&root, &claims, &multiproof, &mut poseidon()).unwrap_err(),
+        PoseidonMerkleError::ProofError(ProofError::ConflictingLeaves)
+    );
+}
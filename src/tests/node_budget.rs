@@ -0,0 +1,110 @@
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{PoseidonMerkleError, SparseMerkleTree};
+
+fn tree(max: usize) -> SparseMerkleTree<Poseidon<Fr>> {
+    SparseMerkleTree::new(8).unwrap().with_max_nodes(max)
+}
+
+#[test]
+fn test_inserts_fill_the_budget() {
+    let mut tree = tree(12);
+    assert_eq!(tree.node_count(), 1);
+
+    // A first leaf materializes its whole path, the next ones the part they don't share
+    tree.insert_at_path(&Fr::from(0u64), &Fr::from(1u64))
+        .unwrap();
+    assert_eq!(tree.node_count(), 9);
+    tree.insert_at_path(&Fr::from(128u64), &Fr::from(2u64))
+        .unwrap();
+    assert_eq!(tree.node_count(), 10);
+    tree.insert_at_path(&Fr::from(64u64), &Fr::from(3u64))
+        .unwrap();
+    assert_eq!(tree.node_count(), 12);
+
+    let root = tree.root().unwrap();
+    assert_eq!(
+        tree.insert_at_path(&Fr::from(1u64), &Fr::from(4u64)),
+        Err(PoseidonMerkleError::NodeBudgetExceeded {
+            current: 12,
+            requested: 8,
+            max: 12
+        })
+    );
+    assert_eq!(tree.root().unwrap(), root);
+    assert_eq!(tree.node_count(), 12);
+    assert_eq!(tree.try_get_value(&Fr::from(1u64)).unwrap(), None);
+
+    // Updating a leaf takes no node
+    tree.insert_at_path(&Fr::from(0u64), &Fr::from(5u64))
+        .unwrap();
+    assert_eq!(tree.node_count(), tree.stats().unwrap().materialized_nodes);
+
+    tree.clear();
+    assert_eq!(tree.node_count(), 1);
+}
+
+#[test]
+fn test_batches_are_checked_as_a_whole() {
+    let entries = [
+        (Fr::from(0u64), Fr::from(1u64)),
+        (Fr::from(128u64), Fr::from(2u64)),
+        (Fr::from(1u64), Fr::from(3u64)),
+    ];
+    let mut tree = tree(12);
+    let root = tree.root().unwrap();
+
+    // The shared nodes count once, 8 for the first path, 1 for the second and 8 for the third
+    let exceeded = Err(PoseidonMerkleError::NodeBudgetExceeded {
+        current: 1,
+        requested: 17,
+        max: 12,
+    });
+    assert_eq!(tree.insert_many(entries), exceeded);
+    assert_eq!(tree.insert_batch(&entries), exceeded);
+    assert_eq!(tree.root().unwrap(), root);
+    assert_eq!(tree.try_get_value(&Fr::from(0u64)).unwrap(), None);
+
+    let mut tree = tree.with_max_nodes(18);
+    tree.insert_batch(&entries).unwrap();
+    assert_eq!(tree.node_count(), 18);
+    assert_eq!(tree.node_count(), tree.stats().unwrap().materialized_nodes);
+}
+
+#[test]
+fn test_node_count_survives_snapshots() {
+    let mut tree = SparseMerkleTree::new(8).unwrap();
+    tree.insert_many((0u64..5).map(|index| (Fr::from(index * 7), Fr::from(index))))
+        .unwrap();
+    assert_eq!(tree.max_nodes(), None);
+
+    let restored =
+        SparseMerkleTree::from_snapshot_bytes(&tree.to_snapshot_bytes().unwrap()).unwrap();
+    assert_eq!(restored.node_count(), tree.node_count());
+    assert_eq!(
+        restored.node_count(),
+        tree.stats().unwrap().materialized_nodes
+    );
+}
+
+#[test]
+fn test_concurrent_inserts_keep_to_the_budget() {
+    let entries: Vec<_> = (0u64..64)
+        .map(|index| (Fr::from(index * 3), Fr::from(index)))
+        .collect();
+    let mut reference = SparseMerkleTree::new(8).unwrap();
+    reference.insert_many(entries.clone()).unwrap();
+
+    let mut tree = tree(reference.node_count() - 1);
+    assert!(matches!(
+        tree.insert_many_concurrent(entries.clone(), 4),
+        Err(PoseidonMerkleError::NodeBudgetExceeded { current: 1, .. })
+    ));
+    assert_eq!(tree.node_count(), 1);
+
+    let mut tree = tree.with_max_nodes(reference.node_count());
+    tree.insert_many_concurrent(entries, 4).unwrap();
+    assert_eq!(tree.node_count(), reference.node_count());
+    assert_eq!(tree.root().unwrap(), reference.root().unwrap());
+}
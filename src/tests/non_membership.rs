@@ -0,0 +1,177 @@
+use ark_bn254::Fr;
+use ark_ff::AdditiveGroup;
+use light_poseidon::Poseidon;
+
+use crate::{
+    Arity, BitOrder, HashingScheme, LeafHashing, NonMembershipProof, PoseidonMerkleError,
+    SparseMerkleTree,
+};
+
+fn poseidon() -> Poseidon<Fr> {
+    Poseidon::<Fr>::new_circom(2).unwrap()
+}
+
+/// Trees under every convention the empty subtrees depend on
+fn trees(depth: usize) -> Vec<SparseMerkleTree<Poseidon<Fr>>> {
+    vec![
+        SparseMerkleTree::new(depth).unwrap(),
+        SparseMerkleTree::new(depth)
+            .unwrap()
+            .with_leaf_hashing(LeafHashing::Hashed)
+            .with_hashing_scheme(HashingScheme::DomainSeparated),
+        SparseMerkleTree::new(depth)
+            .unwrap()
+            .with_hashing_scheme(HashingScheme::LevelBound),
+        SparseMerkleTree::new(depth)
+            .unwrap()
+            .try_with_empty_leaf(Fr::from(0xdeadu64))
+            .unwrap(),
+        SparseMerkleTree::new(depth)
+            .unwrap()
+            .with_bit_order(BitOrder::MsbFirst),
+    ]
+}
+
+#[test]
+fn test_non_membership_in_an_empty_tree() {
+    for tree in trees(20) {
+        let proof = tree
+            .generate_non_membership_proof(&Fr::from(12345u64))
+            .unwrap();
+
+        assert_eq!(proof.empty_level, 0);
+        assert_eq!(proof.proof.leaf_value, tree.empty_leaf());
+        assert_eq!(proof.proof.root_hash, tree.root().unwrap());
+        assert!(tree.verify_non_membership_proof(&proof).unwrap());
+        assert!(proof
+            .verify_proof_with_empty_leaf(&mut poseidon(), &tree.empty_leaf())
+            .unwrap());
+    }
+
+    let tree = SparseMerkleTree::new_with_arity(
+        4,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap();
+    let proof = tree
+        .generate_non_membership_proof(&Fr::from(77u64))
+        .unwrap();
+    assert!(proof
+        .verify_proof(&mut Poseidon::<Fr>::new_circom(4).unwrap())
+        .unwrap());
+}
+
+#[test]
+fn test_non_membership_next_to_populated_siblings() {
+    for mut tree in trees(16) {
+        for index in (0..64u64).filter(|index| index % 3 != 0) {
+            tree.insert_at_path(&Fr::from(index), &Fr::from(index + 1))
+                .unwrap();
+        }
+        let root = tree.root().unwrap();
+
+        for index in [0u64, 30, 63, 64, 9000] {
+            let proof = tree
+                .generate_non_membership_proof(&Fr::from(index))
+                .unwrap();
+            assert_eq!(proof.proof.root_hash, root);
+            assert!(tree.verify_non_membership_proof(&proof).unwrap());
+        }
+
+        // Once the path holds a value, the old proof is stale and no new one can be made
+        let proof = tree
+            .generate_non_membership_proof(&Fr::from(30u64))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(30u64), &Fr::from(1u64))
+            .unwrap();
+        assert!(!tree.verify_non_membership_proof(&proof).unwrap());
+        assert_eq!(
+            tree.generate_non_membership_proof(&Fr::from(30u64))
+                .unwrap_err(),
+            PoseidonMerkleError::LeafNotEmpty {
+                path: Fr::from(30u64)
+            }
+        );
+
+        // A deleted leaf is pruned, the path is empty below where it parts from the one of 62, or
+        // of 31 from the most significant bit
+        tree.delete_at_path(&Fr::from(30u64)).unwrap();
+        let proof = tree
+            .generate_non_membership_proof(&Fr::from(30u64))
+            .unwrap();
+        let empty_level = match tree.bit_order() {
+            BitOrder::LsbFirst => 6,
+            BitOrder::MsbFirst => 16,
+        };
+        assert_eq!(proof.empty_level, empty_level);
+        assert!(tree.verify_non_membership_proof(&proof).unwrap());
+    }
+}
+
+#[test]
+fn test_non_membership_of_a_path_sharing_a_long_prefix() {
+    let mut tree = SparseMerkleTree::new(16).unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+
+    // The child of the root is picked by the lowest bit, so the paths part at the leaf level
+    let neighbour = Fr::from(5u64 | 1 << 15);
+    let proof = tree.generate_non_membership_proof(&neighbour).unwrap();
+    assert_eq!(proof.empty_level, 16);
+    assert_eq!(proof.proof.siblings[15], Fr::from(50u64));
+    assert!(proof.verify_proof(&mut poseidon()).unwrap());
+
+    let proof = tree
+        .generate_non_membership_proof(&Fr::from(5u64 | 1 << 7))
+        .unwrap();
+    assert_eq!(proof.empty_level, 8);
+    assert!(proof.verify_proof(&mut poseidon()).unwrap());
+}
+
+#[test]
+fn test_non_membership_proofs_of_values_fail() {
+    let mut tree = SparseMerkleTree::new(16).unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+    let membership = tree.generate_proof(&Fr::from(5u64)).unwrap();
+
+    // The siblings of the value, claiming an empty leaf or an empty subtree at any level
+    for empty_level in 0..=16 {
+        let forged = NonMembershipProof {
+            proof: crate::MerkleProof {
+                leaf_value: Fr::ZERO,
+                ..membership.clone()
+            },
+            empty_level,
+        };
+        assert!(!forged.verify_proof(&mut poseidon()).unwrap());
+    }
+
+    let out_of_range = NonMembershipProof {
+        proof: membership,
+        empty_level: 17,
+    };
+    assert!(out_of_range.verify_proof(&mut poseidon()).is_err());
+}
+
+#[test]
+fn test_membership_proofs_of_values_fail_as_non_membership_proofs() {
+    for mut tree in trees(16) {
+        tree.insert_at_path(&Fr::from(5u64), &Fr::from(7u64))
+            .unwrap();
+        let membership = tree.generate_proof(&Fr::from(5u64)).unwrap();
+        assert!(tree.verify_proof(&membership).unwrap());
+
+        // The leaf value the proof claims isn't taken as the empty leaf
+        let forged = NonMembershipProof {
+            proof: membership,
+            empty_level: 16,
+        };
+        assert!(!tree.verify_non_membership_proof(&forged).unwrap());
+        assert!(!forged.verify_proof(&mut poseidon()).unwrap());
+        assert!(!forged
+            .verify_proof_with_empty_leaf(&mut poseidon(), &tree.empty_leaf())
+            .unwrap());
+    }
+}
@@ -0,0 +1,106 @@
+use ark_bn254::Fr;
+use ark_ff::Field;
+use light_poseidon::Poseidon;
+
+use crate::{
+    NonMembershipProof, NullifierError, NullifierSet, PoseidonMerkleError, NULLIFIER_TREE_DEPTH,
+};
+
+fn poseidon() -> Poseidon<Fr> {
+    Poseidon::<Fr>::new_circom(2).unwrap()
+}
+
+/// Nullifiers spread over the whole field, like hashes
+fn nullifiers() -> Vec<Fr> {
+    [3u64, 1 << 40, u64::MAX]
+        .into_iter()
+        .map(|seed| Fr::from(seed).inverse().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_nullifiers_are_inserted_once() {
+    let mut set = NullifierSet::new().unwrap();
+    let [first, second, third] = nullifiers()[..] else {
+        unreachable!()
+    };
+
+    set.insert(first).unwrap();
+    let root = set.root().unwrap();
+    assert!(set.is_spent(&first).unwrap());
+    assert!(!set.is_spent(&second).unwrap());
+    assert_eq!(
+        set.insert(first),
+        Err(NullifierError::AlreadyExists { nullifier: first })
+    );
+    assert_eq!(set.root().unwrap(), root);
+
+    // A batch spending a nullifier twice, or one spent before, spends none of them
+    assert_eq!(
+        set.insert_batch(&[second, third, second]),
+        Err(NullifierError::AlreadyExists { nullifier: second })
+    );
+    assert_eq!(
+        set.insert_batch(&[second, first]),
+        Err(NullifierError::AlreadyExists { nullifier: first })
+    );
+    assert!(!set.is_spent(&second).unwrap());
+    assert!(!set.is_spent(&third).unwrap());
+    assert_eq!(set.root().unwrap(), root);
+
+    set.insert_batch(&[second, third]).unwrap();
+    assert!(set.is_spent(&second).unwrap());
+    assert!(set.is_spent(&third).unwrap());
+}
+
+#[test]
+fn test_spent_and_unspent_proofs_verify() {
+    let mut set = NullifierSet::new().unwrap();
+    let [spent, unspent, ..] = nullifiers()[..] else {
+        unreachable!()
+    };
+    set.insert(spent).unwrap();
+    let root = set.root().unwrap();
+    assert_eq!(set.tree().depth, NULLIFIER_TREE_DEPTH);
+
+    let proof = set.prove_spent(&spent).unwrap();
+    assert_eq!(proof.merkle_path, spent);
+    assert_eq!(proof.leaf_value, NullifierSet::<Poseidon<Fr>>::SPENT);
+    assert!(proof.verify_against(&mut poseidon(), &root).unwrap());
+    assert!(set.verify_spent(&spent, &proof).unwrap());
+    assert!(!set.verify_spent(&unspent, &proof).unwrap());
+
+    let proof = set.prove_unspent(&unspent).unwrap();
+    assert_eq!(proof.proof.merkle_path, unspent);
+    assert!(proof.verify_against(&mut poseidon(), &root).unwrap());
+    assert!(set.verify_unspent(&unspent, &proof).unwrap());
+    assert!(!set.verify_unspent(&spent, &proof).unwrap());
+
+    assert_eq!(
+        set.prove_unspent(&spent).unwrap_err(),
+        PoseidonMerkleError::LeafNotEmpty { path: spent }
+    );
+    assert!(set.prove_spent(&unspent).is_err());
+
+    // Spending it makes the proof of the unspent nullifier stale
+    set.insert(unspent).unwrap();
+    assert!(!proof
+        .verify_against(&mut poseidon(), &set.root().unwrap())
+        .unwrap());
+}
+
+#[test]
+fn test_spent_proofs_fail_as_unspent_proofs() {
+    let mut set = NullifierSet::new().unwrap();
+    let spent = nullifiers()[0];
+    set.insert(spent).unwrap();
+    let root = set.root().unwrap();
+
+    // The proof of 1 at the nullifier, passed off as a proof of its empty leaf
+    let forged = NonMembershipProof {
+        proof: set.prove_spent(&spent).unwrap(),
+        empty_level: NULLIFIER_TREE_DEPTH,
+    };
+    assert!(!set.verify_unspent(&spent, &forged).unwrap());
+    assert!(!forged.verify_against(&mut poseidon(), &root).unwrap());
+}
@@ -0,0 +1,123 @@
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use super::FaultyHasher;
+use crate::{InnerHash, MerkleHasher, MerklePath, SparseMerkleTree, TestHasher, TreeObserver};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Insert(MerklePath, Option<Fr>, Fr, InnerHash),
+    Delete(MerklePath, Option<Fr>, InnerHash),
+    Clear,
+}
+
+/// Records the events in a log shared with the test
+struct Recorder(Arc<Mutex<Vec<Event>>>);
+
+impl TreeObserver for Recorder {
+    fn on_insert(&mut self, path: &MerklePath, old: Option<Fr>, new: &Fr, new_root: &InnerHash) {
+        let event = Event::Insert(*path, old, *new, *new_root);
+        self.0.lock().unwrap().push(event);
+    }
+
+    fn on_delete(&mut self, path: &MerklePath, old: Option<Fr>, new_root: &InnerHash) {
+        let event = Event::Delete(*path, old, *new_root);
+        self.0.lock().unwrap().push(event);
+    }
+
+    fn on_clear(&mut self) {
+        self.0.lock().unwrap().push(Event::Clear);
+    }
+}
+
+fn recorded<H: MerkleHasher<Fr>>(tree: &mut SparseMerkleTree<H>) -> Arc<Mutex<Vec<Event>>> {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    tree.set_observer(Box::new(Recorder(log.clone())));
+    log
+}
+
+#[test]
+fn test_observer_event_stream() {
+    let remaining = Rc::new(Cell::new(usize::MAX));
+    let hasher = FaultyHasher {
+        inner: Poseidon::<Fr>::new_circom(2).unwrap(),
+        remaining: remaining.clone(),
+    };
+    let mut tree = SparseMerkleTree::new_with_hasher(4, hasher).unwrap();
+    let log = recorded(&mut tree);
+    let (one, two) = (Fr::from(1u64), Fr::from(2u64));
+
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(30u64))
+        .unwrap();
+    let first_root = tree.root().unwrap();
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(31u64))
+        .unwrap();
+    let second_root = tree.root().unwrap();
+
+    // Fails halfway up the path and is not reported
+    remaining.set(2);
+    assert!(tree.insert_at_path(&Fr::from(9u64), &one).is_err());
+    remaining.set(usize::MAX);
+
+    // Bits past the depth are dropped from the reported path
+    tree.insert_at_path(&(Fr::from(16u64) + two), &two).unwrap();
+    let third_root = tree.root().unwrap();
+    tree.delete_at_path(&Fr::from(3u64)).unwrap();
+    let fourth_root = tree.root().unwrap();
+    tree.clear();
+    tree.insert_at_path(&one, &one).unwrap();
+    let last_root = tree.root().unwrap();
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            Event::Insert(Fr::from(3u64), None, Fr::from(30u64), first_root),
+            Event::Insert(
+                Fr::from(3u64),
+                Some(Fr::from(30u64)),
+                Fr::from(31u64),
+                second_root
+            ),
+            Event::Insert(two, None, two, third_root),
+            Event::Delete(Fr::from(3u64), Some(Fr::from(31u64)), fourth_root),
+            Event::Clear,
+            Event::Insert(one, None, one, last_root),
+        ]
+    );
+}
+
+#[test]
+fn test_take_observer_stops_events() {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(4).unwrap();
+    let log = recorded(&mut tree);
+
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(1u64))
+        .unwrap();
+    assert!(tree.take_observer().is_some());
+    tree.insert_at_path(&Fr::from(2u64), &Fr::from(2u64))
+        .unwrap();
+    tree.clear();
+
+    assert_eq!(log.lock().unwrap().len(), 1);
+    assert!(tree.take_observer().is_none());
+}
+
+#[test]
+fn test_clone_has_no_observer() {
+    let mut tree = SparseMerkleTree::new_with_hasher(4, TestHasher::new()).unwrap();
+    let log = recorded(&mut tree);
+
+    let mut copy = tree.clone();
+    copy.insert_at_path(&Fr::from(1u64), &Fr::from(1u64))
+        .unwrap();
+    copy.clear();
+
+    assert!(log.lock().unwrap().is_empty());
+    assert!(copy.take_observer().is_none());
+}
@@ -0,0 +1,159 @@
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use ark_ff::{Field, PrimeField};
+use light_poseidon::{parameters::bn254_x5::get_poseidon_parameters, Poseidon, PoseidonParameters};
+
+use crate::{Arity, HashFamily, HasherInfo, PoseidonMerkleError, ProofError, SparseMerkleTree};
+
+const INSERTS: [(u64, u64); 4] = [(0, 5), (3, 30), (9, 90), (200, 2000)];
+
+/// Circom parameters of the given width, with the first round constant shifted by `tweak`
+fn tweaked_parameters(width: u8, tweak: u64) -> PoseidonParameters<Fr> {
+    let mut parameters = get_poseidon_parameters::<Fr>(width).unwrap();
+    parameters.ark[0] += Fr::from(tweak);
+    parameters
+}
+
+fn populated_tree(parameters: PoseidonParameters<Fr>) -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new_with_parameters(8, parameters).unwrap();
+    for (path, value) in INSERTS {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree
+}
+
+#[test]
+fn test_custom_parameters_tree() {
+    let tree = populated_tree(tweaked_parameters(3, 1));
+    assert_eq!(tree.hash_family(), HashFamily::Custom);
+    assert_eq!(tree.arity(), Arity::Binary);
+
+    let mut circom_tree = SparseMerkleTree::new(8).unwrap();
+    for (path, value) in INSERTS {
+        circom_tree
+            .insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    assert_ne!(tree.root().unwrap(), circom_tree.root().unwrap());
+
+    for (path, _) in INSERTS {
+        let proof = tree.generate_proof(&Fr::from(path)).unwrap();
+        assert!(tree.verify_proof(&proof).unwrap());
+        assert!(proof
+            .verify_proof(&mut Poseidon::new(tweaked_parameters(3, 1)))
+            .unwrap());
+    }
+}
+
+#[test]
+fn test_fingerprint_follows_parameters() {
+    let a = populated_tree(tweaked_parameters(3, 1));
+    let same = populated_tree(tweaked_parameters(3, 1));
+    let b = populated_tree(tweaked_parameters(3, 2));
+    assert_eq!(a.params_fingerprint(), same.params_fingerprint());
+    assert_ne!(a.params_fingerprint(), b.params_fingerprint());
+    assert_ne!(
+        a.params_fingerprint(),
+        SparseMerkleTree::new(8).unwrap().params_fingerprint()
+    );
+}
+
+#[test]
+fn test_proof_from_other_parameters() {
+    let a = populated_tree(tweaked_parameters(3, 1));
+    let b = populated_tree(tweaked_parameters(3, 2));
+    let proof = a.generate_proof(&Fr::from(9u64)).unwrap();
+    assert_eq!(proof.params_fingerprint, Some(a.params_fingerprint()));
+
+    let mismatch = Err(PoseidonMerkleError::ProofError(
+        ProofError::ParamsFingerprintMismatch,
+    ));
+    assert_eq!(b.verify_proof(&proof), mismatch);
+    assert_eq!(
+        proof.verify_proof(&mut Poseidon::new(tweaked_parameters(3, 2))),
+        mismatch
+    );
+}
+
+#[test]
+fn test_parameters_set_arity() {
+    let mut tree = SparseMerkleTree::new_with_parameters(4, tweaked_parameters(5, 1)).unwrap();
+    assert_eq!(tree.arity(), Arity::Quaternary);
+    tree.insert_at_path(&Fr::from(17u64), &Fr::ONE).unwrap();
+    let proof = tree.generate_proof(&Fr::from(17u64)).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+
+    assert_eq!(
+        SparseMerkleTree::new_with_parameters(4, tweaked_parameters(4, 1)).err(),
+        Some(PoseidonMerkleError::UnsupportedWidth { width: 4 })
+    );
+}
+
+#[test]
+fn test_custom_parameters_need_opt_in() {
+    assert_eq!(
+        SparseMerkleTree::new_with_hasher(4, Poseidon::new(tweaked_parameters(3, 1))).err(),
+        Some(PoseidonMerkleError::ConstantMismatch("empty inner hash"))
+    );
+    // Circom parameters passed explicitly are still recognized
+    let tree = SparseMerkleTree::new_with_parameters(4, get_poseidon_parameters::<Fr>(3).unwrap())
+        .unwrap();
+    assert_eq!(tree.hash_family(), HashFamily::Poseidon);
+    assert_eq!(
+        tree.params_fingerprint(),
+        SparseMerkleTree::new(4).unwrap().params_fingerprint()
+    );
+}
+
+#[test]
+fn test_hasher_info() {
+    let info = SparseMerkleTree::new(8).unwrap().hasher_info();
+    // circomlib's poseidon([1, 2])
+    let expected = HasherInfo {
+        family: HashFamily::Poseidon,
+        width: 3,
+        field: Fr::MODULUS,
+        fingerprint: Fr::from_str(
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+        )
+        .unwrap(),
+    };
+    assert_eq!(info, expected);
+    assert_eq!(SparseMerkleTree::new(20).unwrap().hasher_info(), expected);
+
+    let quaternary = SparseMerkleTree::new_with_arity(
+        4,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap()
+    .hasher_info();
+    assert_eq!(quaternary.width, 5);
+    assert_ne!(quaternary.fingerprint, info.fingerprint);
+}
+
+#[test]
+fn test_hasher_mismatch() {
+    let tree = SparseMerkleTree::new(8).unwrap();
+    let recorded = tree.hasher_info();
+    assert_eq!(tree.ensure_hasher(&recorded), Ok(()));
+
+    let other = populated_tree(tweaked_parameters(3, 1));
+    assert_eq!(
+        other.ensure_hasher(&recorded),
+        Err(PoseidonMerkleError::HasherMismatch {
+            expected: Box::new(recorded),
+            actual: Box::new(other.hasher_info()),
+        })
+    );
+
+    let mut forged = recorded;
+    forged.fingerprint += Fr::ONE;
+    assert!(matches!(
+        tree.ensure_hasher(&forged),
+        Err(PoseidonMerkleError::HasherMismatch { .. })
+    ));
+}
@@ -0,0 +1,45 @@
+use ark_bn254::Fr;
+
+use crate::{NodeType, SparseMerkleTree};
+
+/// A depth 3 tree with the cached hash of the inner node at prefix [1, 0] overwritten
+fn corrupted_tree() -> SparseMerkleTree<light_poseidon::Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    // LSB first, 5 and 1 go through [1, 0] and 2 through [0, 1]
+    for path in [5u64, 1, 2] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(path + 10))
+            .unwrap();
+    }
+    tree.root().unwrap();
+
+    let right = tree.root.borrow().children[1].clone().unwrap();
+    let corrupted = right.borrow().children[0].clone().unwrap();
+    corrupted.borrow_mut().node_type = NodeType::Inner(Fr::from(42u64));
+    tree
+}
+
+#[test]
+fn test_clean_tree_passes() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(15u64))
+        .unwrap();
+    let proof = tree.generate_proof(&Fr::from(5u64)).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+}
+
+#[test]
+#[should_panic(expected = "stale cached hash of the inner node at level 2, prefix [1, 0]")]
+fn test_proof_reading_corrupted_sibling_panics() {
+    let tree = corrupted_tree();
+    // The sibling at level 1 is [1], whose child [1, 0] was corrupted
+    let _ = tree.generate_proof(&Fr::from(2u64));
+}
+
+#[test]
+#[cfg(feature = "visualize")]
+#[should_panic(expected = "stale cached hash of the inner node at level 2, prefix [1, 0]")]
+fn test_visualization_reading_corrupted_node_panics() {
+    use crate::Visualizer;
+
+    let _ = corrupted_tree().visualize_string();
+}
@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use ark_ff::AdditiveGroup;
+use light_poseidon::{Poseidon, PoseidonHasher};
+use zkhash::{
+    fields::{bn256::FpBN256, utils::from_hex},
+    poseidon2::{poseidon2::Poseidon2, poseidon2_instance_bn256::POSEIDON2_BN256_PARAMS},
+};
+
+use crate::{
+    get_empty_inner_hash, get_poseidon2_empty_inner_hash, zero_hash_for, Arity, HashFamily,
+    HashingScheme, LeafHashing, Poseidon2Hasher, PoseidonMerkleError, ProofError, SparseMerkleTree,
+};
+
+/// Root of the depth-1 Poseidon2 tree holding 1 on the left and 2 on the right
+const TWO_LEAF_ROOT: &str =
+    "19440202363237281411582519622441422429699333916864112080167601237210978582482";
+
+fn poseidon2(left: Fr, right: Fr) -> Fr {
+    Poseidon2Hasher::new().hash(&[left, right]).unwrap()
+}
+
+fn two_leaf_tree() -> SparseMerkleTree<Poseidon2Hasher> {
+    let mut tree = SparseMerkleTree::new_poseidon2(1).unwrap();
+    tree.insert_at_path(&Fr::from(0u64), &Fr::from(1u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(2u64))
+        .unwrap();
+    tree
+}
+
+#[test]
+fn test_reference_permutation() {
+    // Known answer of the reference implementation for the bn254 width 3 instance
+    let permutation = Poseidon2::new(&POSEIDON2_BN256_PARAMS);
+    let output = permutation.permutation(&[
+        FpBN256::from(0u64),
+        FpBN256::from(1u64),
+        FpBN256::from(2u64),
+    ]);
+
+    assert_eq!(
+        output[0],
+        from_hex("0x0bb61d24daca55eebcb1929a82650f328134334da98ea4f847f760054f4a3033")
+    );
+}
+
+#[test]
+fn test_hasher_matches_reference_compression() {
+    let permutation = Poseidon2::new(&POSEIDON2_BN256_PARAMS);
+    let expected = permutation.permutation(&[
+        FpBN256::from(1u64),
+        FpBN256::from(2u64),
+        FpBN256::from(0u64),
+    ])[0];
+
+    assert_eq!(
+        poseidon2(Fr::from(1u64), Fr::from(2u64)),
+        Fr::from_str(&expected.to_string()).unwrap()
+    );
+    assert_eq!(
+        poseidon2(Fr::ZERO, Fr::ZERO),
+        *get_poseidon2_empty_inner_hash()
+    );
+}
+
+#[test]
+fn test_hasher_rejects_wide_inputs() {
+    let mut hasher = Poseidon2Hasher::new();
+    assert!(hasher.hash(&[Fr::ZERO; 3]).is_err());
+    assert!(hasher.hash(&[]).is_err());
+}
+
+#[test]
+fn test_two_leaf_tree_root() {
+    let tree = SparseMerkleTree::new_poseidon2(1).unwrap();
+    assert_eq!(tree.hash_family(), HashFamily::Poseidon2);
+    assert_eq!(tree.root().unwrap(), *get_poseidon2_empty_inner_hash());
+
+    let tree = two_leaf_tree();
+    assert_eq!(tree.root().unwrap(), Fr::from_str(TWO_LEAF_ROOT).unwrap());
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_two_leaf_tree_proofs() {
+    let tree = two_leaf_tree();
+
+    for (path, value) in [(0u64, 1u64), (1, 2)] {
+        let proof = tree.generate_proof(&Fr::from(path)).unwrap();
+        assert_eq!(proof.hash_family, HashFamily::Poseidon2);
+        assert_eq!(proof.leaf_value, Fr::from(value));
+        assert_eq!(proof.root_hash, Fr::from_str(TWO_LEAF_ROOT).unwrap());
+        assert!(tree.verify_proof(&proof).unwrap());
+        assert!(proof.verify_proof(&mut Poseidon2Hasher::new()).unwrap());
+    }
+}
+
+#[test]
+fn test_cross_family_verification_fails() {
+    let tree = two_leaf_tree();
+    let proof = tree.generate_proof(&Fr::from(0u64)).unwrap();
+
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    assert_eq!(
+        proof.verify_proof(&mut hasher),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::HashFamilyMismatch {
+                expected: HashFamily::Poseidon2,
+                actual: HashFamily::Poseidon,
+            }
+        ))
+    );
+
+    let mut poseidon_tree = SparseMerkleTree::new(1).unwrap();
+    poseidon_tree
+        .insert_at_path(&Fr::from(0u64), &Fr::from(1u64))
+        .unwrap();
+    assert_eq!(
+        poseidon_tree.verify_proof(&proof),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::HashFamilyMismatch {
+                expected: HashFamily::Poseidon2,
+                actual: HashFamily::Poseidon,
+            }
+        ))
+    );
+
+    let poseidon_proof = poseidon_tree.generate_proof(&Fr::from(0u64)).unwrap();
+    assert!(matches!(
+        tree.verify_proof(&poseidon_proof),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::HashFamilyMismatch { .. }
+        ))
+    ));
+}
+
+#[test]
+fn test_zero_hashes() {
+    let zero_hash = |level, depth, leaf_hashing| {
+        zero_hash_for(
+            level,
+            depth,
+            leaf_hashing,
+            HashFamily::Poseidon2,
+            HashingScheme::Legacy,
+        )
+    };
+
+    for leaf_hashing in [LeafHashing::Raw, LeafHashing::Hashed] {
+        let empty_leaf = leaf_hashing.empty_leaf_hash::<Fr>().unwrap();
+        assert_eq!(zero_hash(3, 3, leaf_hashing), empty_leaf);
+        assert_eq!(
+            zero_hash(2, 3, leaf_hashing),
+            poseidon2(empty_leaf, empty_leaf)
+        );
+    }
+
+    let level_one = zero_hash(1, 2, LeafHashing::Raw);
+    assert_eq!(
+        zero_hash(0, 2, LeafHashing::Raw),
+        poseidon2(level_one, level_one)
+    );
+    assert_ne!(level_one, *get_empty_inner_hash());
+}
+
+#[test]
+fn test_family_is_identified_from_the_hasher() {
+    assert_eq!(
+        HashFamily::identify(get_empty_inner_hash(), Arity::Binary),
+        Some(HashFamily::Poseidon)
+    );
+    assert_eq!(
+        HashFamily::identify(get_poseidon2_empty_inner_hash(), Arity::Binary),
+        Some(HashFamily::Poseidon2)
+    );
+    assert_eq!(HashFamily::identify(&Fr::from(1u64), Arity::Binary), None);
+    assert_eq!(
+        HashFamily::Poseidon2.empty_inner_hash(Arity::Quaternary),
+        None
+    );
+}
@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+use proptest::prelude::*;
+
+use crate::{
+    arbitrary_path, arbitrary_tree, entries, CountingHasher, SparseMerkleTree, TestHasher,
+};
+
+const DEPTH: usize = 8;
+
+proptest! {
+    #[test]
+    fn test_inserted_values_read_back(entries in entries(DEPTH, 0..48)) {
+        let mut tree = SparseMerkleTree::new_with_hasher(DEPTH, TestHasher::new()).unwrap();
+        let mut model = BTreeMap::new();
+        for (path, value) in &entries {
+            tree.insert_at_path(path, value).unwrap();
+            model.insert(*path, *value);
+        }
+
+        for (path, value) in &model {
+            prop_assert_eq!(tree.try_get_value(path).unwrap(), Some(*value));
+        }
+        let mut rebuilt = SparseMerkleTree::new_with_hasher(DEPTH, TestHasher::new()).unwrap();
+        rebuilt.insert_many(model).unwrap();
+        prop_assert_eq!(rebuilt.root().unwrap(), tree.root().unwrap(), "the last value wins");
+    }
+
+    #[test]
+    fn test_every_proof_verifies(
+        (tree, entries, absent) in Just(()).prop_perturb(|_, mut rng| {
+            let (tree, entries) = arbitrary_tree(1..12, 1..40, &mut rng);
+            let absent = arbitrary_path(tree.depth, &mut rng);
+            (tree, entries, absent)
+        })
+    ) {
+        let root = tree.root().unwrap();
+        let mut hasher = CountingHasher::new(TestHasher::new());
+        for (path, value) in &entries {
+            let proof = tree.generate_proof(path).unwrap();
+            prop_assert_eq!(proof.leaf_value, *value);
+            prop_assert_eq!(proof.root_hash, root);
+
+            hasher.reset();
+            prop_assert!(proof.verify_proof(&mut hasher).unwrap());
+            prop_assert_eq!(
+                hasher.count(),
+                tree.depth + 1,
+                "one hash per level and one of the parameter fingerprint"
+            );
+        }
+        if entries.iter().all(|(path, _)| *path != absent) {
+            prop_assert!(tree.generate_proof(&absent).is_err());
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+use prost::Message;
+
+use crate::{
+    eth::to_bytes32,
+    proto::{LeafRequest, Proof, ProofRequest, ProtoError, TreeSnapshot},
+    LeafHashing, MerkleProof, PoseidonMerkleError, ProofError, SparseMerkleTree,
+};
+
+fn tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(16).unwrap();
+    for index in [3u64, 4, 60_000] {
+        tree.insert_at_path(&Fr::from(index), &Fr::from(index * 7))
+            .unwrap();
+    }
+    tree
+}
+
+fn proof_message(tree: &SparseMerkleTree<Poseidon<Fr>>) -> Proof {
+    Proof::try_from(&tree.generate_proof(&Fr::from(4u64)).unwrap()).unwrap()
+}
+
+#[test]
+fn test_proof_round_trips_through_bytes() {
+    let tree = tree();
+    let message = proof_message(&tree);
+    assert_eq!(message.depth, 16);
+    assert_eq!(message.leaf, to_bytes32(&Fr::from(28u64)));
+
+    let decoded = Proof::decode(message.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded, message);
+    let proof = MerkleProof::try_from(decoded).unwrap();
+    assert_eq!(proof.merkle_path, Fr::from(4u64));
+    assert!(tree.verify_proof(&proof).unwrap());
+}
+
+#[test]
+fn test_rejects_malformed_proofs() {
+    let tree = tree();
+    let modulus = Fr::MODULUS.to_bytes_be();
+
+    let mut short_leaf = proof_message(&tree);
+    short_leaf.leaf.pop();
+    let mut long_sibling = proof_message(&tree);
+    long_sibling.siblings[5].push(0);
+    let mut extra_sibling = proof_message(&tree);
+    extra_sibling.siblings.push(vec![0; 32]);
+    let mut non_canonical = proof_message(&tree);
+    non_canonical.root = modulus.clone();
+    let mut no_depth = proof_message(&tree);
+    no_depth.depth = 0;
+    let mut deep_path = proof_message(&tree);
+    deep_path.path = to_bytes32(&Fr::from(1u64 << 16)).to_vec();
+
+    for (message, error, text) in [
+        (
+            short_leaf,
+            ProtoError::ByteLength {
+                field: "leaf".into(),
+                len: 31,
+            },
+            "leaf has 31 bytes, a field element takes 32",
+        ),
+        (
+            long_sibling,
+            ProtoError::ByteLength {
+                field: "siblings[5]".into(),
+                len: 33,
+            },
+            "siblings[5] has 33 bytes, a field element takes 32",
+        ),
+        (
+            extra_sibling,
+            ProtoError::SiblingCount {
+                depth: 16,
+                siblings: 17,
+            },
+            "proof of depth 16 has 17 siblings",
+        ),
+        (
+            non_canonical,
+            ProtoError::NonCanonical {
+                field: "root".into(),
+            },
+            "root is not below the modulus",
+        ),
+        (
+            no_depth,
+            ProtoError::InvalidDepth { depth: 0, max: 253 },
+            "depth 0 is not between 1 and 253",
+        ),
+        (
+            deep_path,
+            ProtoError::Proof(ProofError::NonCanonicalPath { depth: 16 }),
+            "proof path has bits set at or above level 16",
+        ),
+    ] {
+        let actual = MerkleProof::try_from(message).unwrap_err();
+        assert_eq!(actual, error);
+        assert_eq!(actual.to_string(), text);
+    }
+
+    // The message has no room for the conventions of another tree
+    let mut hashed = SparseMerkleTree::<Poseidon<Fr>>::new(4)
+        .unwrap()
+        .with_leaf_hashing(LeafHashing::Hashed);
+    hashed
+        .insert_at_path(&Fr::from(1u64), &Fr::from(1u64))
+        .unwrap();
+    let proof = hashed.generate_proof(&Fr::from(1u64)).unwrap();
+    assert_eq!(Proof::try_from(&proof), Err(ProtoError::UnsupportedProof));
+}
+
+#[test]
+fn test_snapshot_round_trips_and_is_checked() {
+    let tree = tree();
+    let message = TreeSnapshot::try_from(&tree).unwrap();
+    let decoded = TreeSnapshot::decode(message.encode_to_vec().as_slice()).unwrap();
+    let restored = SparseMerkleTree::try_from(decoded).unwrap();
+    assert_eq!(restored.root().unwrap(), tree.root().unwrap());
+
+    let mut wrong_depth = message.clone();
+    wrong_depth.depth = 17;
+    assert_eq!(
+        SparseMerkleTree::try_from(wrong_depth).err(),
+        Some(ProtoError::DepthMismatch {
+            expected: 17,
+            actual: 16,
+        })
+    );
+
+    let mut wrong_root = message.clone();
+    wrong_root.root = to_bytes32(&Fr::from(1u64)).to_vec();
+    assert_eq!(
+        SparseMerkleTree::try_from(wrong_root).err(),
+        Some(ProtoError::Tree(PoseidonMerkleError::RootMismatch {
+            expected: Fr::from(1u64),
+            actual: tree.root().unwrap(),
+        }))
+    );
+
+    let mut truncated = message;
+    truncated.snapshot.truncate(100);
+    assert_eq!(
+        SparseMerkleTree::try_from(truncated).err(),
+        Some(ProtoError::Tree(PoseidonMerkleError::MalformedSnapshot(
+            "truncated nodes"
+        )))
+    );
+}
+
+#[test]
+fn test_proof_requests_are_checked() {
+    let request = LeafRequest::try_from(ProofRequest {
+        index: 60_000,
+        depth: 16,
+    })
+    .unwrap();
+    assert_eq!(request.path(), Fr::from(60_000u64));
+    assert_eq!(
+        ProofRequest::from(request),
+        ProofRequest {
+            index: 60_000,
+            depth: 16,
+        }
+    );
+
+    for (message, error) in [
+        (
+            ProofRequest {
+                index: 1 << 16,
+                depth: 16,
+            },
+            ProtoError::IndexOutOfRange {
+                index: 1 << 16,
+                depth: 16,
+            },
+        ),
+        (
+            ProofRequest {
+                index: 0,
+                depth: 254,
+            },
+            ProtoError::InvalidDepth {
+                depth: 254,
+                max: 253,
+            },
+        ),
+    ] {
+        assert_eq!(LeafRequest::try_from(message), Err(error));
+    }
+    assert_eq!(
+        LeafRequest::try_from(ProofRequest {
+            index: u64::MAX,
+            depth: 64,
+        })
+        .map(|request| request.depth),
+        Ok(64)
+    );
+}
@@ -0,0 +1,133 @@
+use ark_bn254::Fr;
+use pyo3::{
+    exceptions::{PyIndexError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+
+use crate::{
+    python::{fr_from_decimal, merkle_poseidon, verify_proof, MerkleError, PyMerkleTree},
+    SparseMerkleTree,
+};
+
+/// Run `test` with the interpreter initialized and the GIL held
+fn with_python(test: impl for<'py> FnOnce(Python<'py>)) {
+    Python::initialize();
+    Python::attach(test);
+}
+
+#[test]
+fn test_decimal_field_elements() {
+    with_python(|py| {
+        assert_eq!(fr_from_decimal("0").unwrap(), Fr::from(0u64));
+        assert_eq!(fr_from_decimal("00042").unwrap(), Fr::from(42u64));
+
+        let modulus =
+            "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+        for input in ["", "-1", "0x10", "1.5", modulus] {
+            assert!(fr_from_decimal(input)
+                .unwrap_err()
+                .is_instance_of::<PyValueError>(py));
+        }
+    });
+}
+
+#[test]
+fn test_tree_matches_rust() {
+    with_python(|py| {
+        let mut tree = PyMerkleTree::new(8).unwrap();
+        let mut reference = SparseMerkleTree::new(8).unwrap();
+        for (index, value) in [(3u64, 30u64), (200, 2000), (255, 1)] {
+            tree.insert(py, index, &value.to_string()).unwrap();
+            reference
+                .insert_at_path(&Fr::from(index), &Fr::from(value))
+                .unwrap();
+        }
+        tree.insert_many(py, vec![(7, "70".into()), (8, "80".into())])
+            .unwrap();
+        reference
+            .insert_many([7u64, 8].map(|index| (Fr::from(index), Fr::from(index * 10))))
+            .unwrap();
+        assert_eq!(
+            tree.root(py).unwrap(),
+            reference.root().unwrap().to_string()
+        );
+
+        let proof = tree.prove(py, 200).unwrap();
+        assert_eq!(
+            proof
+                .get_item("leaf")
+                .unwrap()
+                .unwrap()
+                .extract::<String>()
+                .unwrap(),
+            "2000"
+        );
+        assert!(tree.verify(py, &proof).unwrap());
+        assert!(verify_proof(py, &proof).unwrap());
+
+        // A stale proof verifies on its own but not against the tree
+        tree.insert(py, 9, "90").unwrap();
+        assert!(!tree.verify(py, &proof).unwrap());
+        assert!(verify_proof(py, &proof).unwrap());
+        proof.set_item("leaf", "2001").unwrap();
+        assert!(!verify_proof(py, &proof).unwrap());
+    });
+}
+
+#[test]
+fn test_errors_are_python_exceptions() {
+    with_python(|py| {
+        assert!(PyMerkleTree::new(0)
+            .err()
+            .unwrap()
+            .is_instance_of::<MerkleError>(py));
+
+        let mut tree = PyMerkleTree::new(4).unwrap();
+        let error = tree.insert(py, 16, "1").unwrap_err();
+        assert!(error.is_instance_of::<PyIndexError>(py));
+        assert!(tree
+            .insert(py, 1, "one")
+            .unwrap_err()
+            .is_instance_of::<PyValueError>(py));
+        assert!(tree
+            .prove(py, 2)
+            .unwrap_err()
+            .is_instance_of::<MerkleError>(py));
+
+        let proof = PyDict::new(py);
+        proof.set_item("leaf", "1").unwrap();
+        assert!(verify_proof(py, &proof)
+            .unwrap_err()
+            .is_instance_of::<PyValueError>(py));
+    });
+}
+
+#[test]
+fn test_module_from_python() {
+    with_python(|py| {
+        let module = PyModule::new(py, "merkle_poseidon").unwrap();
+        merkle_poseidon(&module).unwrap();
+        let locals = PyDict::new(py);
+        locals.set_item("mp", module).unwrap();
+
+        py.run(
+            c"
+tree = mp.PyMerkleTree(4)
+tree.insert(5, '50')
+proof = tree.prove(5)
+assert mp.verify_proof(proof)
+assert tree.verify(proof)
+assert proof['root'] == tree.root()
+try:
+    tree.prove(6)
+    raise AssertionError('missing leaf proved')
+except mp.MerkleError:
+    pass
+",
+            None,
+            Some(&locals),
+        )
+        .unwrap();
+    });
+}
@@ -0,0 +1,106 @@
+use ark_bn254::Fr;
+use ark_r1cs_std::{alloc::AllocVar, fields::fp::FpVar, R1CSVar};
+use ark_relations::r1cs::ConstraintSystem;
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{
+    poseidon_gadget, Arity, BitOrder, HashingScheme, LeafHashing, MerkleProof, MerkleProofVar,
+    PoseidonMerkleError, SparseMerkleTree,
+};
+
+const INSERTS: [(u64, u64); 3] = [(3, 30), (9, 90), (12, 120)];
+
+fn tree_proof(
+    leaf_hashing: LeafHashing,
+    scheme: HashingScheme,
+    bit_order: BitOrder,
+) -> MerkleProof {
+    let mut tree = SparseMerkleTree::new(4)
+        .unwrap()
+        .with_leaf_hashing(leaf_hashing)
+        .with_hashing_scheme(scheme)
+        .with_bit_order(bit_order);
+    for (path, value) in INSERTS {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree.generate_proof(&Fr::from(9u64)).unwrap()
+}
+
+/// Synthesize the verification of the proof and tell if the constraints hold
+fn is_satisfied(proof: &MerkleProof) -> bool {
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    let var = MerkleProofVar::new(cs.clone(), &proof.to_constraint_witness().unwrap()).unwrap();
+    var.verify_gadget(cs.clone()).unwrap();
+    cs.is_satisfied().unwrap()
+}
+
+#[test]
+fn test_poseidon_gadget() {
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    for width in 1..=3u64 {
+        let inputs: Vec<Fr> = (1..=width).map(Fr::from).collect();
+        let vars = inputs
+            .iter()
+            .map(|input| FpVar::new_witness(cs.clone(), || Ok(*input)).unwrap())
+            .collect::<Vec<_>>();
+        let expected = Poseidon::<Fr>::new_circom(inputs.len())
+            .unwrap()
+            .hash(&inputs)
+            .unwrap();
+        assert_eq!(poseidon_gadget(&vars).unwrap().value().unwrap(), expected);
+    }
+    assert!(cs.is_satisfied().unwrap());
+}
+
+#[test]
+fn test_proof_satisfies_circuit() {
+    for (leaf_hashing, scheme, bit_order) in [
+        (LeafHashing::Raw, HashingScheme::Legacy, BitOrder::LsbFirst),
+        (
+            LeafHashing::Hashed,
+            HashingScheme::DomainSeparated,
+            BitOrder::LsbFirst,
+        ),
+        (
+            LeafHashing::Raw,
+            HashingScheme::LevelBound,
+            BitOrder::MsbFirst,
+        ),
+    ] {
+        let proof = tree_proof(leaf_hashing, scheme, bit_order);
+        assert!(
+            is_satisfied(&proof),
+            "{leaf_hashing:?} {scheme:?} {bit_order:?}"
+        );
+    }
+}
+
+#[test]
+fn test_wrong_sibling_unsatisfied() {
+    let mut proof = tree_proof(LeafHashing::Raw, HashingScheme::Legacy, BitOrder::LsbFirst);
+    proof.siblings[2] += Fr::from(1u64);
+    assert!(!is_satisfied(&proof));
+
+    let mut proof = tree_proof(LeafHashing::Raw, HashingScheme::Legacy, BitOrder::LsbFirst);
+    proof.leaf_value = Fr::from(91u64);
+    assert!(!is_satisfied(&proof));
+}
+
+#[test]
+fn test_unsupported_proofs() {
+    let mut tree = SparseMerkleTree::new_with_arity(
+        2,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+    let proof = tree.generate_proof(&Fr::from(5u64)).unwrap();
+    assert_eq!(
+        proof.to_constraint_witness(),
+        Err(PoseidonMerkleError::UnsupportedCircuit)
+    );
+}
@@ -0,0 +1,61 @@
+use alloc::collections::BTreeSet;
+
+use ark_bn254::Fr;
+use ark_ff::MontFp;
+
+use crate::{random_leaves, SparseMerkleTree};
+
+/// Root of `random_populated(20, 100, 42)`, the same on every platform
+const SEED_42_ROOT: Fr =
+    MontFp!("7509775758167346777379072389415082899420026942975651997782775602562883751925");
+
+#[test]
+fn test_random_populated_is_reproducible() {
+    let tree = SparseMerkleTree::random_populated(20, 100, 42);
+    assert_eq!(tree.root().unwrap(), SEED_42_ROOT);
+
+    let again = SparseMerkleTree::random_populated(20, 100, 42);
+    assert_eq!(again.root().unwrap(), SEED_42_ROOT);
+    assert_eq!(
+        again.iter().collect::<Vec<_>>(),
+        tree.iter().collect::<Vec<_>>()
+    );
+
+    assert_ne!(
+        SparseMerkleTree::random_populated(20, 100, 43)
+            .root()
+            .unwrap(),
+        SEED_42_ROOT
+    );
+}
+
+#[test]
+fn test_random_leaves_are_the_ones_of_the_tree() {
+    let leaves = random_leaves(20, 100, 42);
+    assert_eq!(leaves, random_leaves(20, 100, 42));
+    assert_eq!(
+        leaves[0],
+        (
+            1026183,
+            MontFp!("8054029081505192642822818056139236327535204608290153797689661090732204488487")
+        )
+    );
+
+    let indices: BTreeSet<_> = leaves.iter().map(|(index, _)| *index).collect();
+    assert_eq!(indices.len(), 100);
+    assert!(indices.iter().all(|index| *index < 1 << 20));
+
+    let tree = SparseMerkleTree::random_populated(20, 100, 42);
+    for (index, value) in &leaves {
+        assert_eq!(tree.try_get_value(&Fr::from(*index)).unwrap(), Some(*value));
+    }
+
+    // Every leaf of a small tree can be drawn
+    let full = random_leaves(3, 8, 7);
+    assert_eq!(
+        full.iter()
+            .map(|(index, _)| *index)
+            .collect::<BTreeSet<_>>(),
+        (0..8).collect()
+    );
+}
@@ -0,0 +1,125 @@
+use std::{cell::Cell, rc::Rc};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{Clock, MerkleProof, RegistryError, RootRegistry, SparseMerkleTree};
+
+/// The proof of a leaf of a tree holding `value` at path 3, and the tree root
+fn epoch_proof(value: u64) -> (MerkleProof, Fr) {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(8).unwrap();
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(value))
+        .unwrap();
+    (
+        tree.generate_proof(&Fr::from(3u64)).unwrap(),
+        tree.root().unwrap(),
+    )
+}
+
+/// A clock reading the time the test sets
+#[derive(Clone)]
+struct TestClock(Rc<Cell<u64>>);
+
+impl Clock for TestClock {
+    fn now(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+type Registry = RootRegistry<u32, Poseidon<Fr>, Fr, TestClock>;
+
+/// A registry of `capacity` roots and its clock, at 100
+fn registry(capacity: usize) -> (Registry, Rc<Cell<u64>>) {
+    let time = Rc::new(Cell::new(100));
+    let registry = RootRegistry::new(capacity, Poseidon::<Fr>::new_circom(2).unwrap())
+        .with_clock(TestClock(time.clone()));
+    (registry, time)
+}
+
+#[test]
+fn test_verifies_against_the_root_of_the_tree() {
+    let (mut registry, _) = registry(4);
+    let (first, first_root) = epoch_proof(1);
+    let (second, second_root) = epoch_proof(2);
+    registry.insert(1, first_root, 200);
+    registry.insert(2, second_root, 200);
+
+    assert_eq!(registry.verify(&1, &first), Ok(true));
+    assert_eq!(registry.verify(&2, &second), Ok(true));
+    assert_eq!(registry.verify(&2, &first), Ok(false));
+
+    // A proof carrying the registered root must still fold into it
+    let mut forged = first.clone();
+    forged.root_hash = second_root;
+    assert_eq!(registry.verify(&2, &forged), Ok(false));
+    assert_eq!(registry.root(&1), Some(first_root));
+}
+
+#[test]
+fn test_rejects_expired_roots_and_unknown_trees() {
+    let (mut registry, time) = registry(4);
+    let (proof, root) = epoch_proof(1);
+    registry.insert(1, root, 150);
+
+    time.set(149);
+    assert_eq!(registry.verify(&1, &proof), Ok(true));
+    time.set(150);
+    assert_eq!(registry.root(&1), None);
+    assert_eq!(
+        registry.verify(&1, &proof),
+        Err(RegistryError::Expired {
+            expires_at: 150,
+            now: 150,
+        })
+    );
+
+    // The expired root was dropped, and the tree is now unknown
+    assert!(registry.is_empty());
+    assert_eq!(registry.verify(&1, &proof), Err(RegistryError::UnknownTree));
+    assert_eq!(registry.verify(&7, &proof), Err(RegistryError::UnknownTree));
+
+    // Registering the tree again revives it
+    registry.insert(1, root, 300);
+    assert_eq!(registry.verify(&1, &proof), Ok(true));
+}
+
+#[test]
+fn test_evicts_the_least_recently_used_root_at_capacity() {
+    let (mut registry, time) = registry(3);
+    let (proof, root) = epoch_proof(1);
+    for tree_id in 1..=3 {
+        assert_eq!(registry.insert(tree_id, root, 1000), None);
+    }
+
+    // Tree 1 is the oldest, and replacing a root makes no room
+    assert_eq!(registry.insert(3, root, 1000), None);
+    assert_eq!(registry.insert(4, root, 1000), Some(1));
+    assert_eq!(registry.verify(&1, &proof), Err(RegistryError::UnknownTree));
+
+    // Verifying against tree 2 makes tree 3 the least recently used
+    assert_eq!(registry.verify(&2, &proof), Ok(true));
+    assert_eq!(registry.insert(5, root, 1000), Some(3));
+    assert_eq!(registry.len(), registry.capacity());
+
+    // Expired roots make room before any live one is evicted
+    registry.insert(2, root, 500);
+    time.set(600);
+    assert_eq!(registry.insert(6, root, 1000), None);
+    assert_eq!(registry.len(), 3);
+    assert_eq!(registry.root(&4), Some(root));
+}
+
+#[test]
+fn test_closures_are_clocks() {
+    let (proof, root) = epoch_proof(1);
+    let mut registry =
+        RootRegistry::new(1, Poseidon::<Fr>::new_circom(2).unwrap()).with_clock(|| 10);
+    registry.insert("epoch", root, 10);
+    assert_eq!(
+        registry.verify(&"epoch", &proof),
+        Err(RegistryError::Expired {
+            expires_at: 10,
+            now: 10,
+        })
+    );
+}
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, Field};
+use light_poseidon::PoseidonHasher;
+
+use crate::{
+    get_rescue_empty_inner_hash, zero_hash_for, Arity, HashFamily, HashingScheme, LeafHashing,
+    RescueHasher, SparseMerkleTree,
+};
+
+fn rescue(inputs: &[u64]) -> Fr {
+    let inputs: Vec<Fr> = inputs.iter().copied().map(Fr::from).collect();
+    RescueHasher::new().hash(&inputs).unwrap()
+}
+
+#[test]
+fn test_inverse_sbox() {
+    // The inverse S-box exponent undoes x^5
+    let x = Fr::from(123_456_789u64);
+    assert_eq!(
+        x.pow([5]).pow([
+            0xcfe7f7a98ccccccd,
+            0x535cb9d394945a0d,
+            0x93736af8679aad17,
+            0x26b6a528b427b354,
+        ]),
+        x
+    );
+}
+
+#[test]
+fn test_known_answers() {
+    assert_eq!(rescue(&[0, 0]), *get_rescue_empty_inner_hash());
+    assert_eq!(
+        rescue(&[1, 2]),
+        Fr::from_str(
+            "13348936258516493845427891740229338046572714028794412047956592523434876313375"
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        rescue(&[1, 2, 3]),
+        Fr::from_str(
+            "11776838291984688626969749959784114519473583775851722956815917911025977120089"
+        )
+        .unwrap()
+    );
+
+    // The input count in the capacity tells a zero padded input apart
+    assert_ne!(rescue(&[1]), rescue(&[1, 0]));
+}
+
+#[test]
+fn test_input_count_is_checked() {
+    let mut hasher = RescueHasher::new();
+    assert!(hasher.hash(&[]).is_err());
+    assert!(hasher.hash(&[Fr::ZERO; 9]).is_err());
+}
+
+#[test]
+fn test_tree_family_and_zero_hashes() {
+    let tree = SparseMerkleTree::new_rescue(16).unwrap();
+    assert_eq!(tree.hash_family(), HashFamily::Rescue);
+    assert_eq!(
+        HashFamily::identify(get_rescue_empty_inner_hash(), Arity::Binary),
+        Some(HashFamily::Rescue)
+    );
+    assert_eq!(
+        tree.root().unwrap(),
+        zero_hash_for(
+            0,
+            16,
+            LeafHashing::Raw,
+            HashFamily::Rescue,
+            HashingScheme::Legacy
+        )
+    );
+    assert_ne!(
+        zero_hash_for(
+            0,
+            16,
+            LeafHashing::Raw,
+            HashFamily::Rescue,
+            HashingScheme::Legacy
+        ),
+        zero_hash_for(
+            0,
+            16,
+            LeafHashing::Raw,
+            HashFamily::Poseidon,
+            HashingScheme::Legacy
+        )
+    );
+}
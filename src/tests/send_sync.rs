@@ -0,0 +1,51 @@
+//! Types handed to other threads and async tasks must stay `Send` and `Sync`, whatever the
+//! features. A shared pointer that isn't would fail these at compile time.
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+#[cfg(feature = "proto")]
+use crate::proto::TreeSnapshot;
+#[cfg(feature = "tokio")]
+use crate::AsyncTree;
+#[cfg(feature = "visualize")]
+use crate::HashSnapshot;
+use crate::{
+    ChangeLog, FrozenView, HashConfig, HasherInfo, MerkleProof, PoseidonMerkleError,
+    SparseMerkleTree, TreeStats,
+};
+#[cfg(feature = "sync")]
+use crate::{SyncSparseMerkleTree, VersionedTree};
+
+static_assertions::assert_impl_all!(MerkleProof: Send, Sync);
+static_assertions::assert_impl_all!(FrozenView: Send, Sync);
+static_assertions::assert_impl_all!(PoseidonMerkleError: Send, Sync);
+static_assertions::assert_impl_all!(HashConfig: Send, Sync);
+static_assertions::assert_impl_all!(HasherInfo: Send, Sync);
+static_assertions::assert_impl_all!(ChangeLog: Send, Sync);
+static_assertions::assert_impl_all!(TreeStats: Send, Sync);
+#[cfg(feature = "proto")]
+static_assertions::assert_impl_all!(TreeSnapshot: Send, Sync);
+#[cfg(feature = "visualize")]
+static_assertions::assert_impl_all!(HashSnapshot: Send, Sync);
+#[cfg(feature = "sync")]
+static_assertions::assert_impl_all!(SyncSparseMerkleTree<Poseidon<Fr>>: Send, Sync);
+#[cfg(feature = "sync")]
+static_assertions::assert_impl_all!(VersionedTree<Poseidon<Fr>>: Send, Sync);
+#[cfg(feature = "tokio")]
+static_assertions::assert_impl_all!(AsyncTree<Poseidon<Fr>>: Send, Sync);
+
+#[tokio::test]
+async fn test_proof_moves_into_a_task() {
+    let mut tree = SparseMerkleTree::new(4).unwrap();
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(30u64))
+        .unwrap();
+    let proof = tree.generate_proof(&Fr::from(3u64)).unwrap();
+    let view = tree.freeze().unwrap();
+
+    let verified = tokio::spawn(async move {
+        let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+        proof.verify_proof(&mut hasher).unwrap() && proof.root_hash == view.root()
+    });
+    assert!(verified.await.unwrap());
+}
@@ -0,0 +1,171 @@
+use std::rc::Rc;
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{PoseidonMerkleError, SparseMerkleTree};
+
+fn populated_tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(4).unwrap();
+    for (path, value) in [(0u64, 10u64), (5, 50), (9, 90)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree
+}
+
+#[test]
+fn test_reads_through_a_shared_reference() {
+    let tree = Rc::new(populated_tree());
+    let path = Fr::from(5u64);
+    tree.mark_path_dirty(&path).unwrap();
+
+    let root = tree.root().unwrap();
+    assert_eq!(tree.subtree_root(&path, 0).unwrap(), root);
+    assert_eq!(tree.subtree_root(&path, 4).unwrap(), Fr::from(50u64));
+    assert_eq!(
+        tree.subtree_root(&path, 5).unwrap_err(),
+        PoseidonMerkleError::InvalidLevel
+    );
+
+    // Each level of the proof pairs the subtree root on the path with its sibling
+    let proof = tree.generate_proof(&path).unwrap();
+    let sibling = tree.subtree_root(&Fr::from(5u64 ^ 1), 4).unwrap();
+    assert_eq!(proof.siblings.last(), Some(&sibling));
+    assert_eq!(
+        tree.subtree_root(&Fr::from(15u64), 3).unwrap(),
+        tree.hash_config().empty_inner_hash_at(3),
+        "not materialized"
+    );
+
+    let reader = tree.clone();
+    assert!(reader.verify_proof(&proof).unwrap());
+    assert_eq!(reader.export_canopy(1).unwrap().len(), 2);
+    assert!(reader.verify_integrity().is_ok());
+}
+
+#[test]
+fn test_reads_while_nodes_are_borrowed() {
+    let tree = populated_tree();
+    let root = tree.root().unwrap();
+    let path = Fr::from(9u64);
+
+    // Proving and reading the root again while a caller holds the root and a leaf must not panic
+    let leaf = tree.get_node(&path).unwrap();
+    let _leaf = leaf.borrow();
+    let _root = tree.root.borrow();
+    assert_eq!(tree.root().unwrap(), root);
+    let proof = tree.generate_proof(&path).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert_eq!(tree.subtree_root(&path, 4).unwrap(), Fr::from(90u64));
+    let nested = tree.prove_by_key(b"key");
+    assert!(matches!(
+        nested,
+        Err(PoseidonMerkleError::LeafNotFound { .. })
+    ));
+}
+
+#[test]
+fn test_writes_while_a_node_is_borrowed_fail() {
+    let mut tree = populated_tree();
+    let root = tree.root().unwrap();
+    let path = Fr::from(9u64);
+
+    // A caller keeps a leaf from `get_node` borrowed across the mutations
+    let leaf = tree.get_node(&path).unwrap();
+    let held = leaf.borrow();
+    assert_eq!(
+        tree.insert_at_path(&path, &Fr::from(91u64)).unwrap_err(),
+        PoseidonMerkleError::ConcurrentAccess
+    );
+    assert_eq!(
+        tree.delete_at_path(&path).unwrap_err(),
+        PoseidonMerkleError::ConcurrentAccess
+    );
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(51u64))
+        .unwrap();
+    drop(held);
+
+    // The failed writes left nothing half done
+    assert!(tree.verify_integrity().is_ok());
+    assert_eq!(tree.try_get_value(&path).unwrap(), Some(Fr::from(90u64)));
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+    assert_eq!(tree.root().unwrap(), root);
+}
+
+#[test]
+fn test_reads_while_a_node_is_written_fail() {
+    let tree = populated_tree();
+    let path = Fr::from(9u64);
+
+    let root = tree.root.clone();
+    let mut held = root.borrow_mut();
+    held.mark_dirty();
+    for err in [
+        tree.root().unwrap_err(),
+        tree.generate_proof(&path).unwrap_err(),
+        tree.try_get_value(&path).unwrap_err(),
+        tree.subtree_root(&path, 2).unwrap_err(),
+        tree.freeze().unwrap_err(),
+        tree.verify_integrity().unwrap_err(),
+    ] {
+        assert_eq!(err, PoseidonMerkleError::ConcurrentAccess);
+    }
+    drop(held);
+
+    assert_eq!(tree.try_get_value(&path).unwrap(), Some(Fr::from(90u64)));
+    assert!(tree.generate_proof(&path).is_ok());
+}
+
+#[test]
+fn test_traversals_while_a_node_is_written_fail() {
+    let mut tree = populated_tree();
+    let path = Fr::from(9u64);
+
+    // A caller keeps a leaf from `get_node` borrowed mutably across the traversals
+    let leaf = tree.get_node(&path).unwrap();
+    let held = leaf.borrow_mut();
+    let values: Vec<_> = tree.iter().collect();
+    assert_eq!(
+        values.last(),
+        Some(&Err(PoseidonMerkleError::ConcurrentAccess))
+    );
+    assert_eq!(
+        tree.stats().unwrap_err(),
+        PoseidonMerkleError::ConcurrentAccess
+    );
+    assert_eq!(
+        tree.mark_path_dirty(&path).unwrap_err(),
+        PoseidonMerkleError::ConcurrentAccess
+    );
+    assert_eq!(
+        tree.insert_at_path(&path, &Fr::from(91u64)).unwrap_err(),
+        PoseidonMerkleError::ConcurrentAccess
+    );
+    drop(held);
+
+    assert_eq!(
+        tree.iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        [10u64, 90, 50].map(Fr::from)
+    );
+    assert_eq!(tree.stats().unwrap().non_empty_leaves, 3);
+}
+
+// A node locked by another thread is released eventually, so it is waited for
+#[cfg(feature = "sync")]
+#[test]
+fn test_borrows_of_other_threads_are_waited_for() {
+    let tree = populated_tree();
+    let root = tree.root().unwrap();
+    let path = Fr::from(9u64);
+
+    let held = tree.root.borrow_mut();
+    std::thread::scope(|scope| {
+        let reader = scope.spawn(|| tree.generate_proof(&path));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(held);
+        assert_eq!(reader.join().unwrap().unwrap().root_hash, root);
+    });
+    assert!(tree.verify_integrity().is_ok());
+}
@@ -0,0 +1,159 @@
+use ark_bn254::Fr;
+use ark_ff::Field;
+use light_poseidon::Poseidon;
+
+use crate::{Arity, BitOrder, HashingScheme, LeafHashing, PoseidonMerkleError, SparseMerkleTree};
+
+fn round_trip(tree: &mut SparseMerkleTree<Poseidon<Fr>>) -> SparseMerkleTree<Poseidon<Fr>> {
+    let bytes = tree.to_snapshot_bytes().unwrap();
+    let restored = SparseMerkleTree::from_snapshot_bytes(&bytes).unwrap();
+
+    assert_eq!(restored.root().unwrap(), tree.root().unwrap());
+    assert_eq!(restored.to_snapshot_bytes().unwrap(), bytes);
+    restored
+}
+
+#[test]
+fn test_round_trip_keeps_values_and_proofs() {
+    let mut tree = SparseMerkleTree::new(20).unwrap();
+    for index in [0u64, 1, 77, 1 << 19, (1 << 20) - 1] {
+        tree.insert_at_path(&Fr::from(index), &Fr::from(index + 5))
+            .unwrap();
+    }
+    tree.delete_at_path(&Fr::from(77u64)).unwrap();
+
+    let restored = round_trip(&mut tree);
+    for index in [0u64, 1, 2, 77, 1 << 19, (1 << 20) - 1] {
+        let path = Fr::from(index);
+        assert_eq!(
+            restored.try_get_value(&path).unwrap(),
+            tree.try_get_value(&path).unwrap()
+        );
+    }
+    let proof = restored.generate_proof(&Fr::from(1u64 << 19)).unwrap();
+    assert!(tree.verify_proof(&proof).unwrap());
+
+    // Only the materialized nodes are written, a handful of leaves take under a kilobyte
+    assert!(tree.to_snapshot_bytes().unwrap().len() < 1024);
+}
+
+#[test]
+fn test_round_trip_keeps_conventions() {
+    let mut tree = SparseMerkleTree::new_with_arity(
+        5,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap()
+    .with_leaf_hashing(LeafHashing::Hashed)
+    .try_with_hashing_scheme(HashingScheme::DomainSeparated)
+    .unwrap()
+    .try_with_empty_leaf(Fr::from(9u64))
+    .unwrap()
+    .with_bit_order(BitOrder::MsbFirst)
+    .try_with_leaf_width(2)
+    .unwrap();
+    tree.insert_values(&Fr::from(300u64), &[Fr::from(1u64), Fr::from(2u64)])
+        .unwrap();
+    tree.insert_values(&Fr::from(3u64), &[Fr::from(3u64), Fr::from(4u64)])
+        .unwrap();
+    tree.delete_at_path(&Fr::from(3u64)).unwrap();
+
+    let restored = round_trip(&mut tree);
+    assert_eq!(restored.arity(), Arity::Quaternary);
+    assert_eq!(restored.leaf_hashing(), LeafHashing::Hashed);
+    assert_eq!(restored.hashing_scheme(), HashingScheme::DomainSeparated);
+    assert_eq!(restored.empty_leaf(), Fr::from(9u64));
+    assert_eq!(restored.bit_order(), BitOrder::MsbFirst);
+    assert_eq!(
+        restored.get_values(&Fr::from(300u64)).unwrap(),
+        Some(vec![Fr::from(1u64), Fr::from(2u64)])
+    );
+    assert_eq!(restored.get_values(&Fr::from(3u64)).unwrap(), None);
+}
+
+#[test]
+fn test_empty_tree_round_trips() {
+    let mut tree = SparseMerkleTree::new(8).unwrap();
+    let restored = round_trip(&mut tree);
+    assert!(restored.is_empty().unwrap());
+}
+
+#[test]
+fn test_rejects_malformed_snapshots() {
+    let mut tree = SparseMerkleTree::new(4).unwrap();
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(30u64))
+        .unwrap();
+    let bytes = tree.to_snapshot_bytes().unwrap();
+
+    // The leaf value is the last word, changing it changes the root
+    let mut tampered = bytes.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        SparseMerkleTree::from_snapshot_bytes(&tampered),
+        Err(PoseidonMerkleError::RootMismatch { .. })
+    ));
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    let mut bad_mask = bytes.clone();
+    bad_mask[78] = 0b100;
+    let mut too_deep = bytes.clone();
+    too_deep[5..9].copy_from_slice(&255u32.to_be_bytes());
+    for (bytes, reason) in [
+        (&bytes[..bytes.len() - 1], "truncated nodes"),
+        (&bytes[..20], "truncated header"),
+        (&[bytes.as_slice(), &[0]].concat()[..], "trailing bytes"),
+        (&bad_magic[..], "not a snapshot"),
+        (&bad_mask[..], "child past the arity"),
+        (&too_deep[..], "more levels than a path can address"),
+    ] {
+        assert_eq!(
+            SparseMerkleTree::from_snapshot_bytes(bytes).err(),
+            Some(PoseidonMerkleError::MalformedSnapshot(reason))
+        );
+    }
+}
+
+/// 100 leaves spread over the whole field
+fn scattered_entries() -> Vec<(Fr, Fr)> {
+    (1u64..=100)
+        .map(|seed| (Fr::from(seed).inverse().unwrap(), Fr::from(seed)))
+        .collect()
+}
+
+#[test]
+fn test_sparse_deep_tree_takes_kilobytes() {
+    let mut tree = SparseMerkleTree::new(254).unwrap();
+    let entries = scattered_entries();
+    tree.insert_batch(&entries).unwrap();
+
+    let restored = round_trip(&mut tree);
+    for (path, value) in entries.iter().step_by(7) {
+        assert_eq!(restored.try_get_value(path).unwrap(), Some(*value));
+    }
+    assert_eq!(restored.try_get_value(&Fr::from(5u64)).unwrap(), None);
+
+    // One mask byte per inner node on a path below the shared top levels, and the leaf values
+    assert!(tree.to_snapshot_bytes().unwrap().len() < 32 * 1024);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    let mut tree = SparseMerkleTree::new(32).unwrap();
+    let entries = scattered_entries();
+    tree.insert_batch(&entries).unwrap();
+
+    let json = serde_json::to_vec(&tree).unwrap();
+    let restored: SparseMerkleTree<Poseidon<Fr>> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(restored.root().unwrap(), tree.root().unwrap());
+    for (path, value) in entries.iter().step_by(7) {
+        assert_eq!(restored.try_get_value(path).unwrap(), Some(*value));
+    }
+
+    let mut bytes = tree.to_snapshot_bytes().unwrap();
+    *bytes.last_mut().unwrap() ^= 1;
+    let tampered = serde_json::to_vec(&bytes).unwrap();
+    assert!(serde_json::from_slice::<SparseMerkleTree<Poseidon<Fr>>>(&tampered).is_err());
+}
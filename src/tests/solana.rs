@@ -0,0 +1,274 @@
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonError, PoseidonHasher};
+
+use crate::{
+    verify_instruction_data, Arity, EmptySubtrees, HashFamily, HashingScheme, LeafHashing,
+    MerkleProof, PoseidonMerkleError, ProofError, SolanaPoseidonHasher, SparseMerkleTree,
+};
+
+const INSERTS: [(u64, u64); 5] = [(0, 1), (3, 30), (9, 90), (9, 91), (200, 2000)];
+
+fn light_poseidon_tree(depth: usize) -> SparseMerkleTree<Poseidon<Fr>> {
+    SparseMerkleTree::new(depth).unwrap()
+}
+
+#[test]
+fn test_hash_matches_light_poseidon() {
+    for width in [1, 2, 4, 8, 12] {
+        let inputs: Vec<Fr> = (1..=width as u64).map(Fr::from).collect();
+        let mut reference = Poseidon::<Fr>::new_circom(width).unwrap();
+        assert_eq!(
+            SolanaPoseidonHasher::new().hash(&inputs).unwrap(),
+            reference.hash(&inputs).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_input_count_is_checked() {
+    let mut hasher = SolanaPoseidonHasher::new();
+    assert_eq!(hasher.hash(&[]), Err(PoseidonError::EmptyInput));
+    assert!(matches!(
+        hasher.hash(&[Fr::from(1u64); 13]),
+        Err(PoseidonError::InvalidNumberOfInputs { inputs: 13, .. })
+    ));
+}
+
+#[test]
+fn test_same_roots_as_light_poseidon() {
+    let mut solana_tree = SparseMerkleTree::new_solana(8).unwrap();
+    let mut tree = light_poseidon_tree(8);
+    assert_eq!(solana_tree.hash_family(), HashFamily::Poseidon);
+    assert_eq!(solana_tree.root().unwrap(), tree.root().unwrap());
+
+    for (path, value) in INSERTS {
+        solana_tree
+            .insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+        assert_eq!(solana_tree.root().unwrap(), tree.root().unwrap());
+    }
+
+    solana_tree.delete_at_path(&Fr::from(3u64)).unwrap();
+    tree.delete_at_path(&Fr::from(3u64)).unwrap();
+    assert_eq!(solana_tree.root().unwrap(), tree.root().unwrap());
+    assert_eq!(solana_tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_same_roots_with_hashed_leaves() {
+    let mut solana_tree = SparseMerkleTree::new_solana(8)
+        .unwrap()
+        .with_leaf_hashing(LeafHashing::Hashed)
+        .with_hashing_scheme(HashingScheme::DomainSeparated);
+    let mut tree = light_poseidon_tree(8)
+        .with_leaf_hashing(LeafHashing::Hashed)
+        .with_hashing_scheme(HashingScheme::DomainSeparated);
+
+    for (path, value) in INSERTS {
+        solana_tree
+            .insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    assert_eq!(solana_tree.root().unwrap(), tree.root().unwrap());
+}
+
+#[test]
+fn test_same_roots_quaternary() {
+    let mut solana_tree =
+        SparseMerkleTree::new_with_arity(4, Arity::Quaternary, SolanaPoseidonHasher::new())
+            .unwrap();
+    let mut tree = SparseMerkleTree::new_with_arity(
+        4,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap();
+
+    for (path, value) in INSERTS {
+        solana_tree
+            .insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    assert_eq!(solana_tree.root().unwrap(), tree.root().unwrap());
+}
+
+#[test]
+fn test_proofs_are_interchangeable() {
+    let mut solana_tree = SparseMerkleTree::new_solana(8).unwrap();
+    let mut tree = light_poseidon_tree(8);
+    for (path, value) in INSERTS {
+        solana_tree
+            .insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    solana_tree.root().unwrap();
+    tree.root().unwrap();
+
+    let proof = tree.generate_proof(&Fr::from(9u64)).unwrap();
+    assert!(solana_tree.verify_proof(&proof).unwrap());
+    assert!(proof
+        .verify_proof(&mut SolanaPoseidonHasher::new())
+        .unwrap());
+
+    let solana_proof = solana_tree.generate_proof(&Fr::from(200u64)).unwrap();
+    assert!(tree.verify_proof(&solana_proof).unwrap());
+}
+
+/// Byte budget of a depth 26 proof in a sparse tree, see the README
+const DEPTH_26_BUDGET: usize = 256;
+
+fn be_root(root: &Fr) -> [u8; 32] {
+    root.into_bigint().to_bytes_be().try_into().unwrap()
+}
+
+fn instruction_tree(
+    depth: usize,
+    leaf_hashing: LeafHashing,
+    scheme: HashingScheme,
+    empty_subtrees: EmptySubtrees,
+) -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = light_poseidon_tree(depth)
+        .with_leaf_hashing(leaf_hashing)
+        .with_hashing_scheme(scheme)
+        .with_empty_subtrees(empty_subtrees);
+    for (path, value) in INSERTS {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree
+}
+
+#[test]
+fn test_instruction_data_round_trip() {
+    for leaf_hashing in [LeafHashing::Raw, LeafHashing::Hashed] {
+        for scheme in [
+            HashingScheme::Legacy,
+            HashingScheme::DomainSeparated,
+            HashingScheme::LevelBound,
+        ] {
+            for empty_subtrees in [EmptySubtrees::Flat, EmptySubtrees::Folded] {
+                let tree = instruction_tree(26, leaf_hashing, scheme, empty_subtrees);
+                let root = tree.root().unwrap();
+                for (path, _) in INSERTS {
+                    let proof = tree.generate_proof(&Fr::from(path)).unwrap();
+                    let data = proof.to_instruction_data().unwrap();
+                    assert_eq!(verify_instruction_data(&data, &be_root(&root)), Ok(true));
+
+                    let unpacked = MerkleProof::from_instruction_data(&data, root).unwrap();
+                    assert_eq!(unpacked.siblings, proof.siblings);
+                    assert_eq!(unpacked.merkle_path, proof.merkle_path);
+                    assert_eq!(unpacked.leaf_value, proof.leaf_value);
+                    assert_eq!(unpacked.empty_subtrees, empty_subtrees);
+                    assert!(tree.verify_proof(&unpacked).unwrap());
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_instruction_data_size() {
+    for empty_subtrees in [EmptySubtrees::Flat, EmptySubtrees::Folded] {
+        let tree = instruction_tree(26, LeafHashing::Raw, HashingScheme::Legacy, empty_subtrees);
+        let proof = tree.generate_proof(&Fr::from(200u64)).unwrap();
+        let data = proof.to_instruction_data().unwrap();
+
+        // The sibling at a level is a node one level below
+        let explicit = proof
+            .siblings
+            .iter()
+            .enumerate()
+            .filter(|(level, sibling)| {
+                **sibling != tree.hash_config().empty_inner_hash_at(level + 1)
+                    && !(*level == 25 && **sibling == Fr::from(0u64))
+            })
+            .count();
+        assert_eq!(data.len(), 35 + 2 * 4 + 32 * explicit);
+        assert!(data.len() < DEPTH_26_BUDGET, "{} bytes", data.len());
+        assert!(data.len() < 26 * 32);
+    }
+}
+
+#[test]
+fn test_corrupted_instruction_data() {
+    let tree = instruction_tree(
+        8,
+        LeafHashing::Raw,
+        HashingScheme::Legacy,
+        EmptySubtrees::Folded,
+    );
+    let root = be_root(&tree.root().unwrap());
+    let data = tree
+        .generate_proof(&Fr::from(9u64))
+        .unwrap()
+        .to_instruction_data()
+        .unwrap();
+
+    // Another leaf value, or another side at the root
+    let mut leaf = data.clone();
+    leaf[34] ^= 1;
+    assert_eq!(verify_instruction_data(&leaf, &root), Ok(false));
+    let mut path = data.clone();
+    path[35] ^= 1;
+    assert_eq!(verify_instruction_data(&path, &root), Ok(false));
+
+    let malformed = |reason| {
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::MalformedCompactProof(reason),
+        ))
+    };
+    assert_eq!(
+        verify_instruction_data(&data[..2], &root),
+        malformed("truncated header")
+    );
+    assert_eq!(
+        verify_instruction_data(&data[..data.len() - 1], &root),
+        malformed("sibling count doesn't match the bitmap")
+    );
+    let mut version = data.clone();
+    version[0] = 2;
+    assert_eq!(
+        verify_instruction_data(&version, &root),
+        malformed("unknown version")
+    );
+    let mut flags = data.clone();
+    flags[1] |= 0x10;
+    assert_eq!(
+        verify_instruction_data(&flags, &root),
+        malformed("unknown flags")
+    );
+    let mut flat = data.clone();
+    flat[1] &= !0x08;
+    assert_eq!(verify_instruction_data(&flat, &root), Ok(false));
+    let mut omitted = data.clone();
+    omitted[36] ^= 0x80;
+    assert!(verify_instruction_data(&omitted, &root).is_err());
+}
+
+#[test]
+fn test_instruction_data_rejects_wider_trees() {
+    let mut tree = SparseMerkleTree::new_with_arity(
+        4,
+        Arity::Quaternary,
+        Poseidon::<Fr>::new_circom(4).unwrap(),
+    )
+    .unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(1u64))
+        .unwrap();
+    tree.root().unwrap();
+    let proof = tree.generate_proof(&Fr::from(1u64)).unwrap();
+    assert_eq!(
+        proof.to_instruction_data(),
+        Err(PoseidonMerkleError::UnsupportedCircuit)
+    );
+}
@@ -0,0 +1,85 @@
+use ark_bn254::Fr;
+
+use crate::{SparseMerkleTree, TreeStats};
+
+#[test]
+fn test_stats_of_small_tree() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    assert_eq!(
+        tree.stats().unwrap(),
+        TreeStats {
+            depth: 3,
+            nodes_per_level: vec![1, 0, 0, 0],
+            materialized_nodes: 1,
+            implicit_nodes: 2,
+            non_empty_leaves: 0,
+            max_materialized_depth: 0,
+            average_path_length: None,
+        }
+    );
+
+    // 5 and 1 share the path 1, 0 down to their parent, LSB first
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(42u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(7u64))
+        .unwrap();
+    assert_eq!(
+        tree.stats().unwrap(),
+        TreeStats {
+            depth: 3,
+            nodes_per_level: vec![1, 1, 1, 2],
+            materialized_nodes: 5,
+            implicit_nodes: 2,
+            non_empty_leaves: 2,
+            max_materialized_depth: 3,
+            average_path_length: Some(3.0),
+        }
+    );
+}
+
+#[test]
+fn test_stats_after_deletions() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    for index in [0u64, 5, 1, 6] {
+        tree.insert_at_path(&Fr::from(index), &Fr::from(index + 1))
+            .unwrap();
+    }
+    let full = tree.stats().unwrap();
+    assert_eq!(full.non_empty_leaves, 4);
+    assert_eq!(full.nodes_per_level, [1, 2, 3, 4]);
+
+    // A deleted leaf is pruned, its parent holding the leaf of 1 stays
+    tree.delete_at_path(&Fr::from(5u64)).unwrap();
+    let stats = tree.stats().unwrap();
+    assert_eq!(stats.non_empty_leaves, 3);
+    assert_eq!(stats.nodes_per_level, [1, 2, 3, 3]);
+    assert_eq!(stats.materialized_nodes, full.materialized_nodes - 1);
+    assert_eq!(stats.implicit_nodes, full.implicit_nodes + 1);
+
+    tree.clear();
+    assert_eq!(
+        tree.stats().unwrap(),
+        SparseMerkleTree::new(3).unwrap().stats().unwrap()
+    );
+}
+
+#[test]
+fn test_stats_display() {
+    let mut tree = SparseMerkleTree::new(2).unwrap();
+    tree.insert_at_path(&Fr::from(3u64), &Fr::from(1u64))
+        .unwrap();
+
+    assert_eq!(
+        tree.stats().unwrap().to_string(),
+        "Level  Nodes\n\
+         \x20   0  1\n\
+         \x20   1  1\n\
+         \x20   2  1\n\
+         Depth                   2\n\
+         Materialized nodes      3\n\
+         Implicit nodes          2\n\
+         Non-empty leaves        1\n\
+         Max materialized depth  2\n\
+         Average path length     2.00"
+    );
+}
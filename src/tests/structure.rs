@@ -0,0 +1,64 @@
+use ark_bn254::Fr;
+use serde_json::json;
+
+use crate::SparseMerkleTree;
+
+#[test]
+fn test_structure_json() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(42u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(7u64))
+        .unwrap();
+    let root = tree.root().unwrap();
+
+    let structure = tree.to_structure_json(None).unwrap();
+    assert_eq!(structure["type"], "inner");
+    assert_eq!(structure["level"], 0);
+    assert_eq!(structure["hash"], root.to_string());
+    assert_eq!(structure.pointer("/children/0"), Some(&json!("empty")));
+    assert_eq!(
+        structure.pointer("/children/1/children/1"),
+        Some(&json!("empty"))
+    );
+
+    // 5 is 101 and 1 is 001, read from the lowest bit
+    let parent = structure.pointer("/children/1/children/0").unwrap();
+    assert_eq!(parent["type"], "inner");
+    assert_eq!(parent["level"], 2);
+    assert_eq!(
+        parent["children"],
+        json!([
+            { "type": "leaf", "level": 3, "value": "7", "index": 1 },
+            { "type": "leaf", "level": 3, "value": "42", "index": 5 },
+        ])
+    );
+}
+
+#[test]
+fn test_structure_json_depth_limit() {
+    let mut tree = SparseMerkleTree::new(3).unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(42u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(7u64))
+        .unwrap();
+    tree.root().unwrap();
+
+    let full = tree.to_structure_json(None).unwrap();
+    let limited = tree.to_structure_json(Some(1)).unwrap();
+    assert_eq!(limited.pointer("/children/0"), Some(&json!("empty")));
+    assert_eq!(
+        limited.pointer("/children/1"),
+        Some(&json!({
+            "type": "summary",
+            "level": 1,
+            "hash": full.pointer("/children/1/hash").unwrap(),
+            "leaves": 2,
+        }))
+    );
+    assert_eq!(limited.pointer("/children/1/children"), None);
+
+    let summarized = tree.to_structure_json(Some(0)).unwrap();
+    assert_eq!(summarized["type"], "summary");
+    assert_eq!(summarized["leaves"], 2);
+}
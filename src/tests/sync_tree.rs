@@ -0,0 +1,85 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+
+use crate::{SparseMerkleTree, SyncSparseMerkleTree};
+
+const WRITES: u64 = 32;
+const READERS: usize = 4;
+
+#[test]
+fn test_readers_prove_while_a_writer_inserts() {
+    let tree = SyncSparseMerkleTree::new(SparseMerkleTree::new(8).unwrap());
+    tree.insert_at_path(&Fr::from(0u64), &Fr::from(1u64))
+        .unwrap();
+    let tree = Arc::new(tree);
+    let roots = Arc::new(Mutex::new(vec![tree.root().unwrap()]));
+
+    let writer = {
+        let (tree, roots) = (tree.clone(), roots.clone());
+        thread::spawn(move || {
+            for path in 1..=WRITES {
+                tree.insert_at_path(&Fr::from(path), &Fr::from(path * 10))
+                    .unwrap();
+                roots.lock().unwrap().push(tree.root().unwrap());
+            }
+        })
+    };
+    let readers: Vec<_> = (0..READERS)
+        .map(|reader| {
+            let tree = tree.clone();
+            thread::spawn(move || {
+                (0..WRITES)
+                    .map(|round| {
+                        // Path 0 is always there, the others once the writer got to them
+                        let path = Fr::from((round + reader as u64) % (WRITES + 1));
+                        tree.generate_proof(&path)
+                            .unwrap_or_else(|_| tree.generate_proof(&Fr::from(0u64)).unwrap())
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    writer.join().unwrap();
+    let proofs: Vec<_> = readers
+        .into_iter()
+        .flat_map(|reader| reader.join().unwrap())
+        .collect();
+
+    let roots = roots.lock().unwrap();
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    for proof in &proofs {
+        assert!(proof.verify_proof(&mut hasher).unwrap());
+        assert!(roots.contains(&proof.root_hash));
+    }
+    assert_eq!(proofs.len(), READERS * WRITES as usize);
+    assert_eq!(tree.root().unwrap(), *roots.last().unwrap());
+    assert_eq!(
+        tree.try_get_value(&Fr::from(WRITES)).unwrap(),
+        Some(Fr::from(WRITES * 10))
+    );
+}
+
+#[test]
+fn test_root_refreshes_dirty_nodes() {
+    let tree = SyncSparseMerkleTree::from(SparseMerkleTree::new(2).unwrap());
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(5u64))
+        .unwrap();
+    let root = tree.root().unwrap();
+
+    tree.write().mark_path_dirty(&Fr::from(1u64)).unwrap();
+    assert!(tree.read().root.borrow().dirty);
+    assert_eq!(tree.root().unwrap(), root);
+    assert!(!tree.read().root.borrow().dirty);
+
+    tree.delete_at_path(&Fr::from(1u64)).unwrap();
+    let tree = tree.into_inner();
+    assert_eq!(tree.try_get_value(&Fr::from(1u64)).unwrap(), None);
+    assert_ne!(tree.root().unwrap(), root);
+    assert!(tree.is_empty().unwrap());
+}
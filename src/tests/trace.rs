@@ -0,0 +1,119 @@
+use std::sync::{Arc, Mutex, Weak};
+
+use ark_bn254::Fr;
+use ark_ff::AdditiveGroup;
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{first_divergence, HashTrace, SparseMerkleTree, TraceEntry, TraceSink};
+
+fn poseidon(left: Fr, right: Fr) -> Fr {
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    hasher.hash(&[left, right]).unwrap()
+}
+
+fn entry(left: Fr, right: Fr) -> TraceEntry {
+    TraceEntry {
+        level: 0,
+        inputs: vec![left, right],
+        out: poseidon(left, right),
+    }
+}
+
+#[test]
+fn test_trace_of_two_leaf_tree() {
+    let (five, seven) = (Fr::from(5u64), Fr::from(7u64));
+    let trace = HashTrace::new();
+    let mut tree = SparseMerkleTree::new(1).unwrap();
+    tree.set_trace_sink(Box::new(trace.clone()));
+
+    tree.insert_at_path(&Fr::from(0u64), &five).unwrap();
+    tree.insert_at_path(&Fr::from(1u64), &seven).unwrap();
+    let expected = vec![entry(five, Fr::ZERO), entry(five, seven)];
+    assert_eq!(trace.entries(), expected);
+    assert_eq!(trace.diverges_from(&expected), None);
+
+    // The root was hashed by the inserts, verifying folds the proof once more
+    let proof = tree.generate_proof(&Fr::from(1u64)).unwrap();
+    trace.clear();
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert_eq!(trace.entries(), vec![entry(five, seven)]);
+
+    assert!(tree.take_trace_sink().is_some());
+    tree.insert_at_path(&Fr::from(0u64), &seven).unwrap();
+    assert_eq!(trace.entries().len(), 1);
+}
+
+#[test]
+fn test_trace_display() {
+    let trace = HashTrace::new();
+    let mut tree = SparseMerkleTree::new(1).unwrap();
+    tree.set_trace_sink(Box::new(trace.clone()));
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(7u64))
+        .unwrap();
+
+    assert_eq!(
+        trace.to_string(),
+        format!(
+            "#0 level 0: H(0, 7) = {}\n",
+            poseidon(Fr::ZERO, Fr::from(7u64))
+        )
+    );
+}
+
+#[test]
+fn test_first_divergence() {
+    let (one, two) = (Fr::from(1u64), Fr::from(2u64));
+    let expected = vec![entry(one, one), entry(one, two)];
+    let actual = vec![entry(one, one), entry(two, one), entry(two, two)];
+
+    let divergence = first_divergence(&expected, &actual).unwrap();
+    assert_eq!(divergence.index, 1);
+    assert_eq!(divergence.expected, Some(entry(one, two)));
+    assert_eq!(divergence.actual, Some(entry(two, one)));
+    assert!(divergence
+        .to_string()
+        .starts_with("the traces diverge at hash #1\nexpected level 0: H(1, 2)"));
+
+    let divergence = first_divergence(&expected[..1], &actual).unwrap();
+    assert_eq!((divergence.index, divergence.expected), (1, None));
+    assert_eq!(first_divergence(&expected, &expected), None);
+}
+
+/// Proves a leaf of the tree it traces whenever it is told of a hash
+struct ProvingSink {
+    tree: Arc<Mutex<Weak<SparseMerkleTree<Poseidon<Fr>>>>>,
+    roots: Arc<Mutex<Vec<Fr>>>,
+}
+
+impl TraceSink for ProvingSink {
+    fn record(&mut self, _level: usize, _inputs: &[Fr], _out: &Fr) {
+        let Some(tree) = self.tree.lock().unwrap().upgrade() else {
+            return;
+        };
+        let proof = tree.generate_proof(&Fr::from(1u64)).unwrap();
+        assert!(tree.verify_proof(&proof).unwrap());
+        self.roots.lock().unwrap().push(tree.root().unwrap());
+    }
+}
+
+#[test]
+fn test_sink_reading_the_tree() {
+    let slot = Arc::new(Mutex::new(Weak::new()));
+    let roots = Arc::new(Mutex::new(Vec::new()));
+    let mut tree = SparseMerkleTree::new(2).unwrap();
+    tree.set_trace_sink(Box::new(ProvingSink {
+        tree: slot.clone(),
+        roots: roots.clone(),
+    }));
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(7u64))
+        .unwrap();
+
+    let tree = Arc::new(tree);
+    *slot.lock().unwrap() = Arc::downgrade(&tree);
+    tree.mark_path_dirty(&Fr::from(1u64)).unwrap();
+    let root = tree.root().unwrap();
+
+    // Both levels were rehashed, the sink proved the leaf after each without being told of its own
+    // hashes
+    assert_eq!(*roots.lock().unwrap(), vec![root; 2]);
+}
@@ -0,0 +1,94 @@
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+
+use crate::{PoseidonMerkleError, SparseMerkleTree};
+
+fn poseidon() -> Poseidon<Fr> {
+    Poseidon::<Fr>::new_circom(2).unwrap()
+}
+
+fn bytes_le(value: &Fr) -> [u8; 32] {
+    value.into_bigint().to_bytes_le().try_into().unwrap()
+}
+
+#[test]
+fn test_integers_round_trip_through_proofs() {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(8).unwrap();
+    tree.insert_u64(&Fr::from(1u64), u64::MAX).unwrap();
+    tree.insert_u128(&Fr::from(2u64), u128::MAX).unwrap();
+
+    assert_eq!(tree.get_u64(&Fr::from(1u64)).unwrap(), Some(u64::MAX));
+    assert_eq!(
+        tree.get_u128(&Fr::from(1u64)).unwrap(),
+        Some(u64::MAX as u128)
+    );
+    assert_eq!(tree.get_u128(&Fr::from(2u64)).unwrap(), Some(u128::MAX));
+    assert_eq!(tree.get_u64(&Fr::from(3u64)).unwrap(), None);
+
+    let proof = tree.generate_proof(&Fr::from(1u64)).unwrap();
+    assert_eq!(proof.leaf_value, Fr::from(u64::MAX));
+    assert!(proof.verify_proof(&mut poseidon()).unwrap());
+    let proof = tree.generate_proof(&Fr::from(2u64)).unwrap();
+    assert_eq!(proof.leaf_value, Fr::from(u128::MAX));
+    assert!(proof.verify_proof(&mut poseidon()).unwrap());
+}
+
+#[test]
+fn test_getters_reject_values_that_dont_fit() {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(8).unwrap();
+    tree.insert_u128(&Fr::from(1u64), u64::MAX as u128 + 1)
+        .unwrap();
+    let too_big = Fr::from(u64::MAX as u128 + 1);
+
+    assert_eq!(
+        tree.get_u64(&Fr::from(1u64)),
+        Err(PoseidonMerkleError::ValueOutOfRange {
+            value: too_big,
+            bits: 64
+        })
+    );
+    assert_eq!(
+        tree.get_u128(&Fr::from(1u64)).unwrap(),
+        Some(u64::MAX as u128 + 1)
+    );
+
+    tree.insert_at_path(&Fr::from(2u64), &-Fr::from(1u64))
+        .unwrap();
+    assert_eq!(
+        tree.get_u128(&Fr::from(2u64)),
+        Err(PoseidonMerkleError::ValueOutOfRange {
+            value: -Fr::from(1u64),
+            bits: 128
+        })
+    );
+}
+
+#[test]
+fn test_bytes_below_the_modulus_round_trip() {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(8).unwrap();
+    let largest = bytes_le(&-Fr::from(1u64));
+    tree.insert_bytes_le(&Fr::from(1u64), &largest).unwrap();
+
+    assert_eq!(tree.get_bytes_le(&Fr::from(1u64)).unwrap(), Some(largest));
+    assert_eq!(tree.get_bytes_le(&Fr::from(2u64)).unwrap(), None);
+    let proof = tree.generate_proof(&Fr::from(1u64)).unwrap();
+    assert_eq!(proof.leaf_value, -Fr::from(1u64));
+    assert!(proof.verify_proof(&mut poseidon()).unwrap());
+}
+
+#[test]
+fn test_bytes_at_or_above_the_modulus_are_rejected() {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(8).unwrap();
+    let root = tree.root().unwrap();
+    let modulus: [u8; 32] = Fr::MODULUS.to_bytes_le().try_into().unwrap();
+
+    for bytes in [modulus, [u8::MAX; 32]] {
+        assert_eq!(
+            tree.insert_bytes_le(&Fr::from(1u64), &bytes),
+            Err(PoseidonMerkleError::NonCanonicalValue)
+        );
+    }
+    assert_eq!(tree.try_get_value(&Fr::from(1u64)).unwrap(), None);
+    assert_eq!(tree.root().unwrap(), root);
+}
@@ -0,0 +1,148 @@
+//! Roots and proofs of canonical trees, pinned as decimal strings
+//!
+//! The other tests compare the tree with itself, these catch a change of the hashing conventions.
+//! Such a change must update the fixtures on purpose. They were generated once with circom Poseidon
+//! trees of flat empty subtrees, the default before `new` folded them, raw leaves and
+//! `BitOrder::LsbFirst`, and `test_single_leaf_root_by_hand` rebuilds one of them from the hash
+//! function alone. The folded empty roots are pinned in `empty_subtrees.rs`.
+
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, MontFp};
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{EmptySubtrees, MerkleProof, SparseMerkleTree};
+
+/// poseidon(0, 0), the hash of an empty inner node and the root of an empty tree of any depth
+const EMPTY: Fr =
+    MontFp!("14744269619966411208579211824598458697587494354926760081771325075741142829156");
+
+fn tree(depth: usize, leaves: &[(u64, u64)]) -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(depth)
+        .unwrap()
+        .with_empty_subtrees(EmptySubtrees::Flat);
+    for (path, value) in leaves {
+        tree.insert_at_path(&Fr::from(*path), &Fr::from(*value))
+            .unwrap();
+    }
+    tree
+}
+
+fn dense_leaves() -> Vec<(u64, u64)> {
+    (0..16).map(|index| (index, index + 1)).collect()
+}
+
+fn assert_proof(proof: &MerkleProof, leaf: u64, siblings: [Fr; 4], root: Fr) {
+    assert_eq!(proof.leaf_value, Fr::from(leaf));
+    assert_eq!(proof.siblings, siblings);
+    assert_eq!(proof.root_hash, root);
+    assert!(proof
+        .verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap())
+        .unwrap());
+}
+
+#[test]
+fn test_empty_roots() {
+    for depth in [1, 2, 4, 20] {
+        assert_eq!(tree(depth, &[]).root().unwrap(), EMPTY, "depth {depth}");
+    }
+}
+
+#[test]
+fn test_roots() {
+    let cases = [
+        (
+            "single leaf at index 0",
+            vec![(0, 1)],
+            MontFp!(
+                "20224105870072064273666541079703915024055510926311748650022846784320168027182"
+            ),
+        ),
+        (
+            "single leaf at the last index",
+            vec![(15, 1)],
+            MontFp!("9594867588841937066725325193593741335961582675654006529825294313302075617843"),
+        ),
+        (
+            "two adjacent leaves",
+            vec![(0, 1), (1, 2)],
+            MontFp!("7035736241745055602018839810188961953271854667430063848278770332015666740674"),
+        ),
+        (
+            "16 leaves",
+            dense_leaves(),
+            MontFp!(
+                "14529532256136588515154086399736146939012523449805155912270732056892460802940"
+            ),
+        ),
+    ];
+    for (name, leaves, root) in cases {
+        assert_eq!(tree(4, &leaves).root().unwrap(), root, "{name}");
+    }
+}
+
+#[test]
+fn test_proofs() {
+    // Siblings from the root down, the child of the root is picked by the lowest path bit
+    let adjacent = tree(4, &[(0, 1), (1, 2)]);
+    assert_proof(
+        &adjacent.generate_proof(&Fr::from(0u64)).unwrap(),
+        1,
+        [
+            MontFp!(
+                "13751314150531951785569601648787246964738215812611403610691958354048903192057"
+            ),
+            EMPTY,
+            EMPTY,
+            Fr::ZERO,
+        ],
+        MontFp!("7035736241745055602018839810188961953271854667430063848278770332015666740674"),
+    );
+
+    let dense = tree(4, &dense_leaves());
+    let root =
+        MontFp!("14529532256136588515154086399736146939012523449805155912270732056892460802940");
+    assert_proof(
+        &dense.generate_proof(&Fr::from(0u64)).unwrap(),
+        1,
+        [
+            MontFp!(
+                "10153345901122308455949749239279303061117137995145896313623834550245933458267"
+            ),
+            MontFp!(
+                "15377412418204945931118368346627008131472839416364000738872694473652551541475"
+            ),
+            MontFp!("8956285216672293704005570524765009027898375852315820070576584778157492563181"),
+            Fr::from(9u64),
+        ],
+        root,
+    );
+    assert_proof(
+        &dense.generate_proof(&Fr::from(5u64)).unwrap(),
+        6,
+        [
+            MontFp!(
+                "19838962855339742232167893235731270382834637903036900609225213231412956742275"
+            ),
+            MontFp!("2960191397733526477493972568101736653801342949858679219842621691070502850808"),
+            MontFp!("1348696772683273618940814034499804173458295484840659285346266031513365278974"),
+            Fr::from(14u64),
+        ],
+        root,
+    );
+}
+
+#[test]
+fn test_single_leaf_root_by_hand() {
+    let mut poseidon = Poseidon::<Fr>::new_circom(2).unwrap();
+    assert_eq!(poseidon.hash(&[Fr::ZERO, Fr::ZERO]).unwrap(), EMPTY);
+
+    // The leaf 1 next to an empty leaf, then an empty inner node at every level above
+    let mut hash = poseidon.hash(&[Fr::from(1u64), Fr::ZERO]).unwrap();
+    for _ in 0..3 {
+        hash = poseidon.hash(&[hash, EMPTY]).unwrap();
+    }
+    assert_eq!(
+        hash,
+        MontFp!("20224105870072064273666541079703915024055510926311748650022846784320168027182")
+    );
+}
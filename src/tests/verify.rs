@@ -0,0 +1,216 @@
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, BigInteger, Field, PrimeField};
+use light_poseidon::{Poseidon, PoseidonHasher};
+
+use crate::{
+    get_empty_inner_hash,
+    verify::{
+        fold_nodes, fold_nodes_with_scheme, fold_path, fold_path_with_arity, hash_pair, verify,
+        zero_hash,
+    },
+    Arity, BitOrder, HashFamily, HashingScheme, LeafHashing, PoseidonMerkleError, ProofError,
+    ProofNode, SparseMerkleTree,
+};
+
+const DEPTH: usize = 8;
+
+fn poseidon() -> Poseidon<Fr> {
+    Poseidon::<Fr>::new_circom(2).unwrap()
+}
+
+fn bits(path: u64, len: usize) -> Vec<bool> {
+    let mut bits = Fr::from(path).into_bigint().to_bits_le();
+    bits.truncate(len);
+    bits
+}
+
+fn populated_tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(DEPTH).unwrap();
+    for (path, value) in [(3u64, 30u64), (9, 90), (200, 2000)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree
+}
+
+#[test]
+fn test_hash_pair() {
+    let (left, right) = (Fr::from(1u64), Fr::from(2u64));
+    assert_eq!(
+        hash_pair(&mut poseidon(), &left, &right).unwrap(),
+        poseidon().hash(&[left, right]).unwrap()
+    );
+    assert_eq!(
+        hash_pair(&mut poseidon(), &Fr::ZERO, &Fr::ZERO).unwrap(),
+        zero_hash(DEPTH - 1, DEPTH)
+    );
+}
+
+#[test]
+fn test_fold_tree_proof() {
+    let tree = populated_tree();
+    let root = tree.root().unwrap();
+
+    for (path, value) in [(3u64, 30u64), (9, 90), (200, 2000)] {
+        let siblings = tree.generate_proof(&Fr::from(path)).unwrap().siblings;
+        let path_bits = bits(path, DEPTH);
+        let leaf = Fr::from(value);
+
+        assert_eq!(
+            fold_path(&mut poseidon(), &leaf, &path_bits, &siblings).unwrap(),
+            root
+        );
+        assert!(verify(&mut poseidon(), &root, &leaf, &path_bits, &siblings).unwrap());
+        assert!(!verify(&mut poseidon(), &root, &Fr::ONE, &path_bits, &siblings).unwrap());
+    }
+}
+
+#[test]
+fn test_fold_empty_subtree() {
+    // With canonical zero hashes as siblings, an empty leaf folds into the empty root
+    let siblings: Vec<Fr> = (1..=3).map(|level| zero_hash(level, 3)).collect();
+    for path in 0..8 {
+        assert_eq!(
+            fold_path(&mut poseidon(), &Fr::ZERO, &bits(path, 3), &siblings).unwrap(),
+            zero_hash(0, 3)
+        );
+    }
+    assert_eq!(siblings[1], *get_empty_inner_hash());
+}
+
+#[test]
+fn test_fold_wide_tree_proof() {
+    let hasher = Poseidon::<Fr>::new_circom(4).unwrap();
+    let mut tree = SparseMerkleTree::new_with_arity(4, Arity::Quaternary, hasher).unwrap();
+    tree.insert_at_path(&Fr::from(77u64), &Fr::from(7u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(78u64), &Fr::from(8u64))
+        .unwrap();
+    let root = tree.root().unwrap();
+    let siblings = tree.generate_proof(&Fr::from(77u64)).unwrap().siblings;
+
+    let folded = fold_path_with_arity(
+        &mut Poseidon::<Fr>::new_circom(4).unwrap(),
+        &Fr::from(7u64),
+        &bits(77, 8),
+        &siblings,
+        Arity::Quaternary,
+    )
+    .unwrap();
+    assert_eq!(folded, root);
+}
+
+#[test]
+fn test_uneven_siblings() {
+    assert_eq!(
+        fold_path_with_arity(
+            &mut Poseidon::<Fr>::new_circom(4).unwrap(),
+            &Fr::ONE,
+            &[],
+            &[Fr::ZERO; 4],
+            Arity::Quaternary,
+        ),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::UnevenSiblings {
+                len: 4,
+                per_level: 3
+            }
+        ))
+    );
+}
+
+#[test]
+fn test_fold_nodes_agrees_with_the_path_fold() {
+    let tree = populated_tree();
+    for path in [3u64, 9, 200] {
+        let proof = tree.generate_proof(&Fr::from(path)).unwrap();
+        let nodes = proof.nodes();
+
+        // From the leaf up, the sibling is on the left where the path goes right
+        for (height, node) in nodes.iter().enumerate() {
+            assert_eq!(node.is_left(), path >> (DEPTH - 1 - height) & 1 == 1);
+        }
+        assert_eq!(
+            fold_nodes(&mut poseidon(), &proof.leaf_value, &nodes).unwrap(),
+            proof.root_hash
+        );
+    }
+
+    let mut trees = vec![
+        (
+            SparseMerkleTree::new(DEPTH)
+                .unwrap()
+                .with_bit_order(BitOrder::MsbFirst),
+            poseidon(),
+        ),
+        (
+            SparseMerkleTree::new(DEPTH)
+                .unwrap()
+                .with_leaf_hashing(LeafHashing::Hashed)
+                .with_hashing_scheme(HashingScheme::DomainSeparated),
+            poseidon(),
+        ),
+        (
+            SparseMerkleTree::new(DEPTH)
+                .unwrap()
+                .with_hashing_scheme(HashingScheme::LevelBound),
+            poseidon(),
+        ),
+        (
+            SparseMerkleTree::new_with_arity(
+                3,
+                Arity::Octal,
+                Poseidon::<Fr>::new_circom(8).unwrap(),
+            )
+            .unwrap()
+            .with_bit_order(BitOrder::MsbFirst),
+            Poseidon::<Fr>::new_circom(8).unwrap(),
+        ),
+    ];
+    for (tree, hasher) in &mut trees {
+        for (path, value) in [(3u64, 30u64), (9, 90), (200, 2000)] {
+            tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+                .unwrap();
+        }
+        for path in [3u64, 9, 200] {
+            let proof = tree.generate_proof(&Fr::from(path)).unwrap();
+            assert!(proof.verify_proof(hasher).unwrap());
+
+            let leaf = proof.leaf_hashing.leaf_hash(&proof.leaf_value).unwrap();
+            let leaf = proof.scheme.leaf_hash(hasher, leaf, proof.arity).unwrap();
+            let root = fold_nodes_with_scheme(
+                hasher,
+                &leaf,
+                &proof.nodes(),
+                proof.arity,
+                proof.scheme,
+                proof.hash_family,
+            )
+            .unwrap();
+            assert_eq!(root, proof.root_hash);
+        }
+    }
+}
+
+#[test]
+fn test_interleaved_nodes() {
+    let nodes = [
+        ProofNode::Right(Fr::ONE),
+        ProofNode::Left(Fr::ONE),
+        ProofNode::Right(Fr::ONE),
+    ];
+    assert_eq!(
+        fold_nodes_with_scheme(
+            &mut Poseidon::<Fr>::new_circom(4).unwrap(),
+            &Fr::ONE,
+            &nodes,
+            Arity::Quaternary,
+            HashingScheme::Legacy,
+            HashFamily::Poseidon,
+        ),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::InterleavedNodes { level: 0 }
+        ))
+    );
+}
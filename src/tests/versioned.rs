@@ -0,0 +1,113 @@
+use std::{sync::mpsc, thread};
+
+use ark_bn254::Fr;
+
+use crate::{SparseMerkleTree, TestHasher, VersionedTree};
+
+const DEPTH: usize = 16;
+
+fn versioned_tree() -> VersionedTree<TestHasher> {
+    let mut tree = SparseMerkleTree::new_with_hasher(DEPTH, TestHasher::new()).unwrap();
+    tree.insert_many((0..64u64).map(|path| (Fr::from(path * 1_000), Fr::from(path + 1))))
+        .unwrap();
+    VersionedTree::new(tree).unwrap()
+}
+
+/// Commit a version overwriting the first leaves
+fn commit_update(tree: &VersionedTree<TestHasher>, value: u64) -> u64 {
+    let mut writer = tree.begin_version();
+    for path in 0..8u64 {
+        writer
+            .insert_at_path(&Fr::from(path * 1_000), &Fr::from(value))
+            .unwrap();
+    }
+    writer.commit().unwrap()
+}
+
+#[test]
+fn test_reader_keeps_proving_its_version() {
+    let tree = versioned_tree();
+    let view = tree.latest();
+    let root = view.root();
+
+    let (proved, done) = mpsc::channel();
+    let (committed, wait) = mpsc::channel::<()>();
+    let reader = thread::spawn(move || {
+        wait.recv().unwrap();
+        for path in 0..64u64 {
+            let proof = view.generate_proof(&Fr::from(path * 1_000)).unwrap();
+            assert_eq!(proof.root_hash, root);
+            assert_eq!(proof.leaf_value, Fr::from(path + 1));
+            assert!(proof.verify_proof(&mut TestHasher::new()).unwrap());
+        }
+        proved.send(()).unwrap();
+    });
+
+    assert_eq!(commit_update(&tree, 500), 1);
+    assert_eq!(commit_update(&tree, 600), 2);
+    committed.send(()).unwrap();
+    done.recv().unwrap();
+    reader.join().unwrap();
+
+    let latest = tree.latest();
+    assert_eq!(tree.latest_version(), 2);
+    assert_ne!(latest.root(), root);
+    assert_eq!(latest.get(&Fr::from(0u64)), Some(Fr::from(600u64)));
+    let proof = latest.generate_proof(&Fr::from(0u64)).unwrap();
+    assert!(proof.verify_proof(&mut TestHasher::new()).unwrap());
+    assert_eq!(tree.into_inner().root().unwrap(), latest.root());
+}
+
+#[test]
+fn test_dropped_versions_are_reclaimed() {
+    let tree = versioned_tree();
+    let alone = tree.node_count();
+    let held = tree.version(0).unwrap();
+
+    commit_update(&tree, 500);
+    commit_update(&tree, 600);
+    assert_eq!(tree.versions_held(), [0, 2]);
+    assert_eq!(tree.version(0).unwrap().root(), held.root());
+    assert!(tree.version(1).is_none(), "nobody held version 1");
+    assert!(tree.version(3).is_none());
+    let with_reader = tree.node_count();
+    assert!(
+        with_reader < 2 * alone,
+        "the versions share the nodes the updates didn't touch"
+    );
+
+    drop(held);
+    assert_eq!(tree.versions_held(), [2]);
+    assert!(tree.version(0).is_none());
+    assert!(tree.node_count() < with_reader);
+    assert_eq!(
+        tree.node_count(),
+        alone,
+        "the updates kept the shape of the tree"
+    );
+}
+
+#[test]
+fn test_readers_do_not_wait_for_the_writer() {
+    let tree = versioned_tree();
+    let root = tree.latest().root();
+
+    let mut writer = tree.begin_version();
+    writer
+        .insert_at_path(&Fr::from(1u64), &Fr::from(7u64))
+        .unwrap();
+    thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                assert_eq!(tree.latest().root(), root);
+                assert_eq!(tree.version(0).unwrap().get(&Fr::from(1u64)), None);
+            })
+            .join()
+            .unwrap();
+    });
+    drop(writer);
+
+    // The uncommitted update is published with the next version
+    assert_eq!(tree.begin_version().commit().unwrap(), 1);
+    assert_eq!(tree.latest().get(&Fr::from(1u64)), Some(Fr::from(7u64)));
+}
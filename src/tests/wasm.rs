@@ -0,0 +1,84 @@
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, Field};
+use light_poseidon::Poseidon;
+
+use crate::{
+    wasm::{fr_from_hex, fr_to_hex, proof_from_json, proof_to_json, WasmError},
+    PoseidonMerkleError, ProofError, SparseMerkleTree,
+};
+
+#[test]
+fn test_hex_round_trip() {
+    for value in [Fr::ZERO, Fr::ONE, Fr::from(0xdeadbeefu64), -Fr::ONE] {
+        let hex = fr_to_hex(&value);
+        assert_eq!(hex.len(), 66);
+        assert_eq!(fr_from_hex(&hex), Ok(value));
+    }
+    assert_eq!(
+        fr_to_hex(&Fr::from(255u64)),
+        "0x00000000000000000000000000000000000000000000000000000000000000ff"
+    );
+    assert_eq!(fr_from_hex("ff"), Ok(Fr::from(255u64)));
+    assert_eq!(fr_from_hex("0xAbC"), Ok(Fr::from(0xabcu64)));
+}
+
+#[test]
+fn test_invalid_hex() {
+    let modulus = "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001";
+    for (input, reason) in [
+        ("", "no hex digits"),
+        ("0x", "no hex digits"),
+        ("0xzz", "not a hex digit"),
+        (modulus, "not below the modulus"),
+        (&"1".repeat(65), "more than 64 hex digits"),
+    ] {
+        assert_eq!(
+            fr_from_hex(input),
+            Err(WasmError::InvalidHex {
+                input: input.into(),
+                reason
+            })
+        );
+    }
+    assert_eq!(
+        fr_from_hex("0xzz").unwrap_err().to_string(),
+        "invalid field element \"0xzz\": not a hex digit"
+    );
+}
+
+#[test]
+fn test_proof_json_round_trip() {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(8).unwrap();
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(42u64))
+        .unwrap();
+    tree.root().unwrap();
+    let proof = tree.generate_proof(&Fr::from(5u64)).unwrap();
+
+    let json = proof_to_json(&proof);
+    assert_eq!(json["leaf"], fr_to_hex(&Fr::from(42u64)).as_str());
+    assert_eq!(json["siblings"].as_array().unwrap().len(), 8);
+
+    let parsed = proof_from_json(&json).unwrap();
+    assert_eq!(parsed.siblings, proof.siblings);
+    assert_eq!(parsed.merkle_path, proof.merkle_path);
+    assert!(tree.verify_proof(&parsed).unwrap());
+}
+
+#[test]
+fn test_invalid_proof_json() {
+    let mut json = serde_json::json!({ "leaf": "0x1", "root": "0x2", "path": "0x0" });
+    assert_eq!(
+        proof_from_json(&json).err(),
+        Some(WasmError::InvalidProof(
+            "missing array field \"siblings\"".into()
+        ))
+    );
+
+    json["siblings"] = serde_json::json!([]);
+    assert_eq!(
+        proof_from_json(&json).err(),
+        Some(WasmError::Tree(PoseidonMerkleError::ProofError(
+            ProofError::NoSiblings
+        )))
+    );
+}
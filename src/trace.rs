@@ -0,0 +1,270 @@
+//! Recording of the hashes a tree computes, to find where a root departs from a reference
+//!
+//! A tree given a `TraceSink` with `SparseMerkleTree::set_trace_sink` reports every inner node
+//! hash computed by its inserts and deletes, root refreshes and proof verifications. The sink is
+//! made current on the thread for the duration of the operation, the hashing code finds it there.
+//! The hashes reach the sink once the operation is over and holds nothing of the tree, so a sink
+//! may read the tree, but the hashes of the operations it runs itself aren't reported.
+
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::{
+    any::Any,
+    cell::{Cell, RefCell},
+    fmt,
+};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+
+use crate::{NodeCell, Shared};
+
+/// Receives the inner node hashes of a tree, see `SparseMerkleTree::set_trace_sink`
+pub trait TraceSink<F: PrimeField = Fr> {
+    /// A node at `level` hashed to `out`, `inputs` are the hashes of its children in index order,
+    /// the left and the right one for a binary tree
+    ///
+    /// Under `HashingScheme::LevelBound` the level is hashed in front of the inputs.
+    fn record(&mut self, level: usize, inputs: &[F], out: &F);
+}
+
+/// A boxed sink, `Send` and `Sync` with the `sync` feature like the rest of the tree
+#[cfg(not(feature = "sync"))]
+pub type BoxedTraceSink<F = Fr> = Box<dyn TraceSink<F>>;
+#[cfg(feature = "sync")]
+pub type BoxedTraceSink<F = Fr> = Box<dyn TraceSink<F> + Send + Sync>;
+
+/// A hash recorded by a `HashTrace`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry<F: PrimeField = Fr> {
+    pub level: usize,
+    pub inputs: Vec<F>,
+    pub out: F,
+}
+
+impl<F: PrimeField> fmt::Display for TraceEntry<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "level {}: H(", self.level)?;
+        for (index, input) in self.inputs.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{input}")?;
+        }
+        write!(f, ") = {}", self.out)
+    }
+}
+
+/// An in-memory sink, its clones share the entries so one can be kept to read them
+///
+/// `Display` prints one hash per line, in the order they were computed.
+#[derive(Debug, Clone, Default)]
+pub struct HashTrace<F: PrimeField = Fr> {
+    entries: Shared<NodeCell<Vec<TraceEntry<F>>>>,
+}
+
+impl<F: PrimeField> HashTrace<F> {
+    pub fn new() -> Self {
+        HashTrace {
+            entries: Shared::new(NodeCell::new(Vec::new())),
+        }
+    }
+
+    /// The hashes recorded so far, oldest first
+    pub fn entries(&self) -> Vec<TraceEntry<F>> {
+        self.entries.borrow().clone()
+    }
+
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    /// The first hash where this trace departs from `expected`, see `first_divergence`
+    pub fn diverges_from(&self, expected: &[TraceEntry<F>]) -> Option<TraceDivergence<F>> {
+        first_divergence(expected, &self.entries.borrow())
+    }
+}
+
+impl<F: PrimeField> TraceSink<F> for HashTrace<F> {
+    fn record(&mut self, level: usize, inputs: &[F], out: &F) {
+        self.entries.borrow_mut().push(TraceEntry {
+            level,
+            inputs: inputs.to_vec(),
+            out: *out,
+        });
+    }
+}
+
+impl<F: PrimeField> fmt::Display for HashTrace<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, entry) in self.entries.borrow().iter().enumerate() {
+            writeln!(f, "#{index} {entry}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where two traces part: the position of the first differing hash and both entries there, `None`
+/// past the end of the shorter trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDivergence<F: PrimeField = Fr> {
+    pub index: usize,
+    pub expected: Option<TraceEntry<F>>,
+    pub actual: Option<TraceEntry<F>>,
+}
+
+impl<F: PrimeField> fmt::Display for TraceDivergence<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "the traces diverge at hash #{}", self.index)?;
+        match &self.expected {
+            Some(entry) => writeln!(f, "expected {entry}")?,
+            None => writeln!(f, "expected no more hashes")?,
+        }
+        match &self.actual {
+            Some(entry) => write!(f, "actual   {entry}"),
+            None => write!(f, "actual   no more hashes"),
+        }
+    }
+}
+
+/// The first hash where `actual` departs from `expected`, `None` if the traces are the same
+pub fn first_divergence<F: PrimeField>(
+    expected: &[TraceEntry<F>],
+    actual: &[TraceEntry<F>],
+) -> Option<TraceDivergence<F>> {
+    let index = (0..expected.len().max(actual.len()))
+        .find(|index| expected.get(*index) != actual.get(*index))?;
+
+    Some(TraceDivergence {
+        index,
+        expected: expected.get(index).cloned(),
+        actual: actual.get(index).cloned(),
+    })
+}
+
+/// The sink of a tree, if any
+///
+/// A clone of the tree starts without one, like it starts without an observer.
+pub(crate) struct TraceSlot<F: PrimeField>(Option<Shared<NodeCell<BoxedTraceSink<F>>>>);
+
+impl<F: PrimeField> TraceSlot<F> {
+    pub(crate) fn new() -> Self {
+        TraceSlot(None)
+    }
+
+    pub(crate) fn replace(&mut self, sink: Option<BoxedTraceSink<F>>) -> Option<BoxedTraceSink<F>> {
+        let previous = core::mem::replace(
+            &mut self.0,
+            sink.map(|sink| Shared::new(NodeCell::new(sink))),
+        );
+        previous.map(|sink| {
+            Shared::try_unwrap(sink)
+                .unwrap_or_else(|_| panic!("the sink is only shared during an operation"))
+                .into_inner()
+        })
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Make the sink current until the scope is dropped
+    ///
+    /// Within an operation already reporting to the sink, and while the sink runs, it is `None`.
+    pub(crate) fn enter(&self) -> Option<TraceScope> {
+        let sink = self.0.clone()?;
+        if FLUSHING.with(Cell::get) {
+            return None;
+        }
+        let nested = CURRENT.with(|current| {
+            current
+                .borrow()
+                .as_ref()
+                .and_then(|frame| frame.downcast_ref::<TraceFrame<F>>())
+                .is_some_and(|frame| Shared::ptr_eq(&frame.sink, &sink))
+        });
+        if nested {
+            return None;
+        }
+
+        let frame: Rc<dyn Any> = Rc::new(TraceFrame {
+            sink,
+            entries: RefCell::new(Vec::new()),
+        });
+        let previous = CURRENT.with(|current| current.replace(Some(frame.clone())));
+        Some(TraceScope {
+            previous,
+            frame,
+            flush: TraceFrame::<F>::flush,
+        })
+    }
+}
+
+impl<F: PrimeField> Clone for TraceSlot<F> {
+    fn clone(&self) -> Self {
+        TraceSlot::new()
+    }
+}
+
+/// The sink of a running operation and the hashes it computed so far
+struct TraceFrame<F: PrimeField> {
+    sink: Shared<NodeCell<BoxedTraceSink<F>>>,
+    entries: RefCell<Vec<TraceEntry<F>>>,
+}
+
+impl<F: PrimeField> TraceFrame<F> {
+    /// Hand the hashes of the operation to the sink
+    fn flush(frame: &dyn Any) {
+        let frame = frame
+            .downcast_ref::<TraceFrame<F>>()
+            .expect("the scope flushes its own frame");
+        let mut sink = frame.sink.borrow_mut();
+        for entry in frame.entries.take() {
+            sink.record(entry.level, &entry.inputs, &entry.out);
+        }
+    }
+}
+
+std::thread_local! {
+    /// The frame of the tree operation running on the thread
+    static CURRENT: RefCell<Option<Rc<dyn Any>>> = const { RefCell::new(None) };
+    /// Whether a sink is being handed hashes on the thread
+    static FLUSHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores the frame that was current before `TraceSlot::enter` and flushes its own
+pub(crate) struct TraceScope {
+    previous: Option<Rc<dyn Any>>,
+    frame: Rc<dyn Any>,
+    flush: fn(&dyn Any),
+}
+
+impl Drop for TraceScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+
+        // A sink panicking while the thread unwinds would abort
+        if !std::thread::panicking() {
+            let flushing = FLUSHING.with(|flushing| flushing.replace(true));
+            (self.flush)(&*self.frame);
+            FLUSHING.with(|cell| cell.set(flushing));
+        }
+    }
+}
+
+/// Report a hash to the current frame, if it is one of a tree over `F`
+pub(crate) fn record<F: PrimeField>(level: usize, inputs: &[F], out: &F) {
+    CURRENT.with(|current| {
+        let current = current.borrow();
+        let frame = current
+            .as_ref()
+            .and_then(|frame| frame.downcast_ref::<TraceFrame<F>>());
+        if let Some(frame) = frame {
+            frame.entries.borrow_mut().push(TraceEntry {
+                level,
+                inputs: inputs.to_vec(),
+                out: *out,
+            });
+        }
+    });
+}
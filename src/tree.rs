@@ -1,57 +1,1651 @@
-use std::{cell::RefCell, rc::Rc};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonParameters};
+
+#[cfg(feature = "mimc")]
+use crate::MimcHasher;
+#[cfg(feature = "poseidon2")]
+use crate::Poseidon2Hasher;
+#[cfg(feature = "rescue")]
+use crate::RescueHasher;
+#[cfg(feature = "solana")]
+use crate::SolanaPoseidonHasher;
+use crate::{
+    constants::{validate_constants_once, DEFAULT_DEPTH},
+    hash_leaf_values,
+    hasher::as_field,
+    node::{Node, NodeRef, NodeSummary},
+    observer::ObserverSlot,
+    path::path_index,
+    shared::{BorrowConflict, CheckedBorrow},
+    telemetry::{self, operation_span, ProofTimer},
+    verify, Arity, BitOrder, BoxedObserver, ChangeLog, ChangeLogEntry, EmptySubtrees, FrFormat,
+    HashConfig, HashFamily, HashOperation, HasherInfo, HashingScheme, InnerHash,
+    IntegrityViolation, LeafHashing, MerkleHasher, MerklePath, MerkleProof, MultiProof, NodeCell,
+    NodeType, NonMembershipProof, PoseidonMerkleError, ProofError, Shared, Sibling, MAX_ARITY,
+    MAX_LEAF_WIDTH,
+};
+#[cfg(feature = "hash-trace")]
+use crate::{trace::TraceSlot, BoxedTraceSink};
+
+/// The hashes of a written path, the root first and the leaf contribution last, and the previous
+/// value of the leaf
+type WrittenLeaf<F> = (Vec<InnerHash<F>>, Option<F>);
+
+/// Sparse Poseidon Merkle Tree over the field `F`, the bn254 scalar field by default
+#[derive(Clone)]
+pub struct SparseMerkleTree<H: MerkleHasher<F>, F: PrimeField = Fr, M = ()> {
+    /// The hasher for the tree, behind a cell so that read-only operations can hash leaves
+    pub(crate) hasher: NodeCell<H>,
+    /// The root of the tree
+    pub root: NodeRef<H, F, M>,
+    /// The MAX depth of the tree
+    pub depth: usize,
+    /// How leaves and empty nodes are hashed
+    config: HashConfig<F>,
+    /// Fingerprint of the hasher parameters, recorded in the proofs of the tree
+    params_fingerprint: F,
+    /// Number of values held by a leaf, see `try_with_leaf_width`
+    leaf_width: usize,
+    /// Which end of a path picks the child of the root
+    bit_order: BitOrder,
+    /// Told of every committed mutation, see `set_observer`
+    pub(crate) observer: ObserverSlot<F>,
+    /// The last updates, see `with_changelog`
+    pub(crate) changelog: Option<ChangeLog<F>>,
+    /// Told of every inner node hash, see `set_trace_sink`
+    #[cfg(feature = "hash-trace")]
+    pub(crate) trace: TraceSlot<F>,
+    /// Materialized nodes, the root included, see `node_count`
+    pub(crate) nodes: usize,
+    /// Most nodes an insert may leave the tree with, see `with_max_nodes`
+    max_nodes: Option<usize>,
+    /// Leaves not holding the empty leaf value, for the `telemetry::LEAVES` gauge
+    #[cfg(feature = "metrics")]
+    pub(crate) leaves: usize,
+}
+
+/// Get the bit of the path at the given position, `true` means go right
+fn path_bit<F: PrimeField>(merkle_path: &MerklePath<F>, position: usize) -> bool {
+    let bits = merkle_path.into_bigint().to_bits_le();
+    bits[position]
+}
+
+/// The 32 little-endian bytes of a value
+fn to_bytes_le<F: PrimeField>(value: &F) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    let le = value.into_bigint().to_bytes_le();
+    let len = le.len().min(32);
+    bytes[..len].copy_from_slice(&le[..len]);
+    bytes
+}
+
+/// A value as an integer, failing if it doesn't fit in `bits` bits
+fn to_u128<F: PrimeField>(value: &F, bits: u32) -> Result<u128, PoseidonMerkleError<F>> {
+    let bigint = value.into_bigint();
+    if bigint.num_bits() > bits {
+        return Err(PoseidonMerkleError::ValueOutOfRange {
+            value: *value,
+            bits,
+        });
+    }
+
+    let limbs = bigint.as_ref();
+    Ok(limbs[0] as u128 | (limbs.get(1).copied().unwrap_or(0) as u128) << 64)
+}
+
+/// The family a hasher reports, or over bn254 the one its hash of zeros is recognized as
+///
+/// An unrecognized bn254 hasher is `HashFamily::Custom` if `allow_custom` is set and an error
+/// otherwise.
+pub(crate) fn probe_family<F: PrimeField, H: MerkleHasher<F>>(
+    hasher: &H,
+    empty_inner_hash: &F,
+    arity: Arity,
+    allow_custom: bool,
+) -> Result<HashFamily, PoseidonMerkleError<F>> {
+    let family = match (hasher.hash_family(), as_field::<F, Fr>(empty_inner_hash)) {
+        (Some(family), _) => family,
+        (None, Some(hash)) => HashFamily::identify(hash, arity)
+            .or(allow_custom.then_some(HashFamily::Custom))
+            .ok_or(PoseidonMerkleError::ConstantMismatch("empty inner hash"))?,
+        (None, None) => HashFamily::default(),
+    };
+
+    Ok(family)
+}
+
+/// State of a `verify_integrity` traversal
+struct IntegrityAudit<'a, H: MerkleHasher<F>, F: PrimeField> {
+    depth: usize,
+    config: HashConfig<F>,
+    hasher: &'a mut H,
+    /// Path bits from the root to the node being visited
+    path: Vec<bool>,
+    violations: Vec<IntegrityViolation<F>>,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> IntegrityAudit<'_, H, F> {
+    /// Recompute the contribution of a node to its parent, recording mismatching inner nodes
+    fn visit<M>(
+        &mut self,
+        node: &NodeRef<H, F, M>,
+        level: usize,
+    ) -> Result<F, PoseidonMerkleError<F>> {
+        let node_ref = node.checked_borrow()?;
+        let cached = match node_ref.node_type {
+            NodeType::Leaf(value) => return self.config.leaf_hash(self.hasher, &value),
+            NodeType::Inner(hash) => hash,
+        };
+
+        let empty_child =
+            self.config
+                .empty_hash_at(self.hasher, level + 1, level + 1 >= self.depth)?;
+        let arity = self.config.arity;
+        let mut children = vec![empty_child; arity.children()];
+        for (index, child) in node_ref.children[..arity.children()].iter().enumerate() {
+            if let Some(child) = child {
+                self.path
+                    .extend((0..arity.bits()).map(|bit| index >> bit & 1 == 1));
+                children[index] = self.visit(child, level + 1)?;
+                self.path.truncate(self.path.len() - arity.bits());
+            }
+        }
+
+        // A childless inner node stands for an empty subtree, as built by `new_empty_inner`
+        let recomputed = if node_ref.children.iter().all(Option::is_none) {
+            self.config.empty_inner_hash_at(level)
+        } else {
+            self.config.inner_hash(self.hasher, level, &children)?
+        };
+        if recomputed != cached && !node_ref.dirty {
+            self.violations.push(IntegrityViolation {
+                level,
+                path: self.path.clone(),
+                cached,
+                recomputed,
+            });
+        }
+
+        Ok(recomputed)
+    }
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> SparseMerkleTree<H, F> {
+    /// Create a new sparse poseidon merkle tree given a depth and a hasher
+    ///
+    /// The hasher is probed with its `empty_hash`, hash(0, 0) unless overridden: it must accept
+    /// exactly two inputs, and its output is the empty inner hash of the tree. Over the bn254
+    /// field a hasher that doesn't report its `HashFamily` must also match the pre-computed empty
+    /// inner hash of a known family. The family is recorded in the proofs of the tree. Its empty
+    /// subtrees are folded like the ones of `new`, hashed with the hasher from the empty leaf up.
+    pub fn new_with_hasher(depth: usize, hasher: H) -> Result<Self, PoseidonMerkleError<F>> {
+        Self::new_with_arity(depth, Arity::Binary, hasher)
+    }
+
+    /// Create a new sparse poseidon merkle tree whose inner nodes have `arity` children
+    ///
+    /// The depth counts levels, so a path spans `depth * arity.bits()` bits, which can't exceed the
+    /// bits of the modulus. The hasher must accept one input per child and is probed like in
+    /// `new_with_hasher`.
+    pub fn new_with_arity(
+        depth: usize,
+        arity: Arity,
+        hasher: H,
+    ) -> Result<Self, PoseidonMerkleError<F>> {
+        Self::build(depth, arity, hasher, false)
+    }
+
+    /// Create a new sparse merkle tree from a hasher with its own parameters
+    ///
+    /// Like `new_with_arity`, except that over bn254 a hasher matching no known family, such as
+    /// Poseidon with non-circom round constants or MDS matrix, is accepted and recorded as
+    /// `HashFamily::Custom`. The empty inner hash is the one of the hasher. Proofs tell such trees
+    /// apart by their `params_fingerprint`.
+    pub fn new_with_custom_hasher(
+        depth: usize,
+        arity: Arity,
+        hasher: H,
+    ) -> Result<Self, PoseidonMerkleError<F>> {
+        Self::build(depth, arity, hasher, true)
+    }
+
+    fn build(
+        depth: usize,
+        arity: Arity,
+        mut hasher: H,
+        allow_custom: bool,
+    ) -> Result<Self, PoseidonMerkleError<F>> {
+        if depth == 0 {
+            return Err(PoseidonMerkleError::InvalidDepth);
+        }
+        // A path has a bit per bit of the modulus, whatever end of it picks the child of the root
+        let max = F::MODULUS_BIT_SIZE as usize;
+        if depth * arity.bits() > max {
+            return Err(PoseidonMerkleError::InvalidBitsPathHash {
+                len: depth * arity.bits(),
+                max,
+            });
+        }
+
+        let empty_inner_hash = hasher
+            .empty_hash(arity)
+            .map_err(|source| PoseidonMerkleError::InvalidHasherWidth { source })?;
+        let params_fingerprint = hasher
+            .params_fingerprint(arity)
+            .map_err(|source| PoseidonMerkleError::InvalidHasherWidth { source })?;
+        let family = probe_family(&hasher, &empty_inner_hash, arity, allow_custom)?;
+
+        // Every constructor folds, so the root of a new tree is the one it has again once emptied
+        SparseMerkleTree {
+            hasher: NodeCell::new(hasher),
+            root: Node::new_borrowed_inner(empty_inner_hash),
+            depth,
+            config: HashConfig::new(LeafHashing::default(), empty_inner_hash)
+                .with_family(family)
+                .with_arity(arity),
+            params_fingerprint,
+            leaf_width: 1,
+            bit_order: BitOrder::default(),
+            observer: ObserverSlot::new(),
+            changelog: None,
+            nodes: 1,
+            max_nodes: None,
+            #[cfg(feature = "hash-trace")]
+            trace: TraceSlot::new(),
+            #[cfg(feature = "metrics")]
+            leaves: 0,
+        }
+        .try_with_empty_subtrees(EmptySubtrees::Folded)
+    }
+
+    /// Keep a payload of type `M` on the leaves, see `insert_with_meta`
+    ///
+    /// The payload is never hashed, so the tree has the same roots and proofs with or without it.
+    /// The leaves already inserted have none.
+    ///
+    /// # Panics
+    ///
+    /// If a node is borrowed through a `NodeRef` the caller holds, `try_with_metadata` returns
+    /// `PoseidonMerkleError::ConcurrentAccess` instead.
+    pub fn with_metadata<M>(self) -> SparseMerkleTree<H, F, M> {
+        self.try_with_metadata()
+            .expect("no node of a new tree is borrowed")
+    }
+
+    /// Like `with_metadata`, failing if a node is borrowed elsewhere
+    pub fn try_with_metadata<M>(self) -> Result<SparseMerkleTree<H, F, M>, PoseidonMerkleError<F>> {
+        Ok(SparseMerkleTree {
+            hasher: self.hasher,
+            root: without_meta(&self.root)?,
+            depth: self.depth,
+            config: self.config,
+            params_fingerprint: self.params_fingerprint,
+            leaf_width: self.leaf_width,
+            bit_order: self.bit_order,
+            observer: self.observer,
+            changelog: self.changelog,
+            nodes: self.nodes,
+            max_nodes: self.max_nodes,
+            #[cfg(feature = "hash-trace")]
+            trace: self.trace,
+            #[cfg(feature = "metrics")]
+            leaves: self.leaves,
+        })
+    }
+}
+
+/// A copy of a subtree whose leaves have no payload, for a tree taking another payload type
+fn without_meta<H: MerkleHasher<F>, F: PrimeField, M>(
+    node: &NodeRef<H, F>,
+) -> Result<NodeRef<H, F, M>, BorrowConflict> {
+    let node = node.checked_borrow()?;
+    let mut children = [const { None }; MAX_ARITY];
+    for (copy, child) in children.iter_mut().zip(&node.children) {
+        *copy = child.as_ref().map(without_meta).transpose()?;
+    }
+    let copy = Node {
+        node_type: node.node_type.clone(),
+        children,
+        dirty: node.dirty,
+        values: node.values.clone(),
+        meta: None,
+    };
+
+    Ok(Shared::new(NodeCell::new(copy)))
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField, M> SparseMerkleTree<H, F, M> {
+    /// Set how leaf values contribute to their parent hash, meant to be chained on a new tree
+    ///
+    /// `LeafHashing::Hashed` closes the gap where a leaf value equal to the root of a crafted
+    /// subtree is indistinguishable from that subtree. Materialized nodes are marked dirty so that
+    /// the next `root()` rehashes them under the new mode.
+    ///
+    /// # Panics
+    ///
+    /// If the empty leaf can't be hashed under the new mode, such as `LeafHashing::Hashed` over
+    /// another field than bn254 with folded empty subtrees. `try_with_leaf_hashing` returns the
+    /// error instead.
+    pub fn with_leaf_hashing(self, leaf_hashing: LeafHashing) -> Self {
+        self.try_with_leaf_hashing(leaf_hashing)
+            .expect("the empty leaf can be hashed under the new leaf hashing")
+    }
+
+    /// Like `with_leaf_hashing`, failing if the empty leaf can't be hashed under the new mode
+    pub fn try_with_leaf_hashing(
+        mut self,
+        leaf_hashing: LeafHashing,
+    ) -> Result<Self, PoseidonMerkleError<F>> {
+        if leaf_hashing != self.config.leaf_hashing {
+            self.config.leaf_hashing = leaf_hashing;
+            self.refresh_empty_hashes()?;
+        }
+
+        Ok(self)
+    }
+
+    /// Set whether leaves are hashed apart from inner nodes, meant to be chained on a new tree
+    ///
+    /// Under `HashingScheme::DomainSeparated` a leaf contributes hasher(value, 1), so no leaf can
+    /// be mistaken for an inner node, and under `HashingScheme::LevelBound` inner nodes hash their
+    /// level too. Materialized nodes are rehashed by the next `root()`.
+    ///
+    /// # Panics
+    ///
+    /// Like `with_leaf_hashing`, if a non-zero empty leaf was set and can't be hashed anymore, or
+    /// if the hasher can't hash the empty subtrees of a level bound tree. `try_with_hashing_scheme`
+    /// returns these errors instead.
+    pub fn with_hashing_scheme(self, scheme: HashingScheme) -> Self {
+        self.try_with_hashing_scheme(scheme)
+            .expect("the empty subtrees can be hashed under the new hashing scheme")
+    }
+
+    /// Like `with_hashing_scheme`, failing if the empty subtrees can't be hashed under the scheme
+    ///
+    /// A level bound tree needs a hasher taking one input more than the arity, unless it uses
+    /// Poseidon over bn254.
+    pub fn try_with_hashing_scheme(
+        mut self,
+        scheme: HashingScheme,
+    ) -> Result<Self, PoseidonMerkleError<F>> {
+        if scheme != self.config.scheme {
+            self.config.scheme = scheme;
+            self.refresh_empty_hashes()?;
+        }
+
+        Ok(self)
+    }
+
+    /// Set how missing inner nodes hash, meant to be chained on a new tree
+    ///
+    /// `EmptySubtrees::Folded` hashes an empty subtree from the empty leaf up, level by level, so
+    /// roots and proofs match the ones of circomlibjs and other sparse Merkle trees. Every
+    /// constructor folds, `EmptySubtrees::Flat` keeps the roots of the trees built before folding.
+    /// Materialized nodes are rehashed by the next `root()`.
+    ///
+    /// # Panics
+    ///
+    /// If the hasher fails to hash the empty subtrees, which it hashed once already building the
+    /// tree. `try_with_empty_subtrees` returns the error instead.
+    pub fn with_empty_subtrees(self, empty_subtrees: EmptySubtrees) -> Self {
+        self.try_with_empty_subtrees(empty_subtrees)
+            .expect("the empty subtrees can be hashed")
+    }
+
+    /// Like `with_empty_subtrees`, failing if the hasher can't hash the empty subtrees
+    pub fn try_with_empty_subtrees(
+        mut self,
+        empty_subtrees: EmptySubtrees,
+    ) -> Result<Self, PoseidonMerkleError<F>> {
+        if empty_subtrees != self.config.empty_subtrees {
+            self.config.empty_subtrees = empty_subtrees;
+            self.refresh_empty_hashes()?;
+        }
+
+        Ok(self)
+    }
+
+    /// Set the value of an empty leaf, meant to be chained on a new tree after the hashing modes
+    ///
+    /// Zero by default. Another value changes the empty subtree at every level, so the whole chain
+    /// of empty hashes is recomputed from it: missing nodes, the root of an empty tree and
+    /// `is_empty` follow it. A path without a leaf, deleted or never inserted at, then proves the
+    /// absence of a value with an ordinary `generate_proof`.
+    pub fn try_with_empty_leaf(mut self, empty_leaf: F) -> Result<Self, PoseidonMerkleError<F>> {
+        self.config.empty_leaf = empty_leaf;
+        self.refresh_empty_hashes()?;
+
+        Ok(self)
+    }
+
+    /// Recompute the per-level empty hashes after a change of the empty leaf or its hashing
+    fn refresh_empty_hashes(&mut self) -> Result<(), PoseidonMerkleError<F>> {
+        self.config.empty_hashes = if self.config.has_level_empty_hashes() {
+            self.config
+                .empty_hash_chain(&mut *self.hasher.checked_borrow_mut()?, self.depth)?
+        } else {
+            Vec::new()
+        };
+
+        // An empty tree is reset so that `is_empty` doesn't wait for the next `root()`
+        let is_empty = self
+            .root
+            .checked_borrow()?
+            .children
+            .iter()
+            .all(Option::is_none);
+        if is_empty {
+            self.clear();
+        } else {
+            self.mark_inner_nodes_dirty()?;
+        }
+
+        Ok(())
+    }
+
+    /// Set which end of a path picks the child of the root, meant to be chained on a new tree
+    ///
+    /// `BitOrder::MsbFirst` makes the path of a leaf its index from the left, the order circuits
+    /// folding from the leaf expect. Proofs record the order, and `verify_proof` rejects one of
+    /// the other order with `ProofError::BitOrderMismatch`. Leaves already inserted keep their
+    /// place in the tree, so their paths read differently afterwards.
+    pub fn with_bit_order(self, bit_order: BitOrder) -> Self {
+        Self { bit_order, ..self }
+    }
+
+    /// Set the number of values held by a leaf, meant to be chained on a new tree
+    ///
+    /// With a width of 1, the default, a leaf holds a single value inserted with `insert_at_path`.
+    /// Wider leaves are inserted with `insert_values`, and the leaf value is their commitment
+    /// `hash_leaf_values`, so this needs the bn254 scalar field.
+    pub fn try_with_leaf_width(mut self, width: usize) -> Result<Self, PoseidonMerkleError<F>> {
+        if !(1..=MAX_LEAF_WIDTH).contains(&width) {
+            return Err(PoseidonMerkleError::UnsupportedLeafWidth {
+                width,
+                max: MAX_LEAF_WIDTH,
+            });
+        }
+        if width > 1 && as_field::<F, Fr>(&F::zero()).is_none() {
+            return Err(PoseidonMerkleError::UnsupportedLeafHashing);
+        }
+
+        self.leaf_width = width;
+        Ok(self)
+    }
+
+    /// Record the last `capacity` updates in a changelog, meant to be chained on a new tree
+    ///
+    /// Every insert and delete then adds a `ChangeLogEntry`, dropping the oldest one past the
+    /// capacity, so that proofs built before an update can be brought to the new root with
+    /// `ChangeLogEntry::fast_forward`. A capacity of 0 records nothing.
+    pub fn with_changelog(self, capacity: usize) -> Self {
+        let changelog = (capacity > 0).then(|| ChangeLog::new(capacity));
+        Self { changelog, ..self }
+    }
+
+    /// The last updates of the tree, if built `with_changelog`
+    pub fn changelog(&self) -> Option<&ChangeLog<F>> {
+        self.changelog.as_ref()
+    }
+
+    /// Fail inserts that would leave the tree with more than `max` nodes, root included
+    ///
+    /// The nodes an insert or a batch would materialize are counted before any is, and past the
+    /// budget it fails with `PoseidonMerkleError::NodeBudgetExceeded`, leaving the tree unchanged.
+    pub fn with_max_nodes(self, max: usize) -> Self {
+        Self {
+            max_nodes: Some(max),
+            ..self
+        }
+    }
+
+    /// The budget set by `with_max_nodes`
+    pub fn max_nodes(&self) -> Option<usize> {
+        self.max_nodes
+    }
+
+    /// Number of materialized nodes, the root included
+    pub fn node_count(&self) -> usize {
+        self.nodes
+    }
+
+    /// Fail with `PoseidonMerkleError::NodeBudgetExceeded` if writing leaves at the paths would
+    /// materialize more nodes than the budget allows
+    pub(crate) fn check_node_budget<'a>(
+        &self,
+        merkle_paths: impl IntoIterator<Item = &'a MerklePath<F>>,
+    ) -> Result<(), PoseidonMerkleError<F>>
+    where
+        F: 'a,
+    {
+        let Some(max) = self.max_nodes else {
+            return Ok(());
+        };
+
+        // The missing nodes by the child indexes leading to them, so shared ones count once
+        let mut missing = BTreeSet::new();
+        for merkle_path in merkle_paths {
+            let digits: Vec<usize> = (0..self.depth)
+                .map(|level| self.child_index(merkle_path, level))
+                .collect();
+            let mut current = Some(self.root.clone());
+            for level in 0..self.depth {
+                current = match current {
+                    Some(node) => node.checked_borrow()?.children[digits[level]].clone(),
+                    None => None,
+                };
+                if current.is_none() {
+                    missing.insert(digits[..=level].to_vec());
+                }
+            }
+        }
+
+        let requested = missing.len();
+        if self.nodes + requested > max {
+            return Err(PoseidonMerkleError::NodeBudgetExceeded {
+                current: self.nodes,
+                requested,
+                max,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Mark every materialized inner node as dirty, for when the way leaves are hashed changes
+    fn mark_inner_nodes_dirty(&self) -> Result<(), BorrowConflict> {
+        let mut stack = vec![self.root.clone()];
+        while let Some(node) = stack.pop() {
+            let mut node_ref = node.checked_borrow_mut()?;
+            if let NodeType::Inner(_) = node_ref.node_type {
+                node_ref.mark_dirty();
+                stack.extend(node_ref.children.iter().flatten().cloned());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How leaf values contribute to their parent hash
+    pub fn leaf_hashing(&self) -> LeafHashing {
+        self.config.leaf_hashing
+    }
+
+    /// Whether leaves are hashed apart from inner nodes
+    pub fn hashing_scheme(&self) -> HashingScheme {
+        self.config.scheme
+    }
+
+    /// The hash function of the inner nodes
+    pub fn hash_family(&self) -> HashFamily {
+        self.config.family
+    }
+
+    /// Fingerprint of the hasher parameters, see `MerkleHasher::params_fingerprint`
+    pub fn params_fingerprint(&self) -> F {
+        self.params_fingerprint
+    }
+
+    /// The family, width, field and parameters fingerprint of the hasher
+    pub fn hasher_info(&self) -> HasherInfo<F> {
+        HasherInfo {
+            family: self.config.family,
+            width: self.config.arity.children() + 1,
+            field: F::MODULUS,
+            fingerprint: self.params_fingerprint,
+        }
+    }
+
+    /// Check that the tree hashes like the hasher a snapshot or proof was recorded with
+    ///
+    /// Fails with `PoseidonMerkleError::HasherMismatch` when any of the `hasher_info` differs.
+    pub fn ensure_hasher(&self, expected: &HasherInfo<F>) -> Result<(), PoseidonMerkleError<F>> {
+        self.hasher_info().ensure_matches(expected)
+    }
+
+    /// How missing inner nodes hash
+    pub fn empty_subtrees(&self) -> EmptySubtrees {
+        self.config.empty_subtrees
+    }
+
+    /// Value of an empty leaf, zero unless set with `try_with_empty_leaf`
+    pub fn empty_leaf(&self) -> F {
+        self.config.empty_leaf
+    }
+
+    /// Which end of a path picks the child of the root
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    /// Number of values held by a leaf
+    pub fn leaf_width(&self) -> usize {
+        self.leaf_width
+    }
+
+    /// Number of children of an inner node
+    pub fn arity(&self) -> Arity {
+        self.config.arity
+    }
+
+    /// Number of path bits addressing a leaf, one per level for binary trees
+    pub fn path_bits(&self) -> usize {
+        self.depth * self.config.arity.bits()
+    }
+
+    /// The hashing conventions of the tree
+    pub fn hash_config(&self) -> &HashConfig<F> {
+        &self.config
+    }
+
+    /// Index of the child taken by the path at `level`, following the bit order of the tree
+    pub(crate) fn child_index(&self, merkle_path: &MerklePath<F>, level: usize) -> usize {
+        let offset = self
+            .bit_order
+            .level_offset(level, self.depth, self.config.arity);
+        path_index(merkle_path, offset, self.config.arity)
+    }
+
+    /// Zero every bit of the path at position >= `path_bits()`, the depth for binary trees
+    ///
+    /// Only the low `path_bits()` bits address a leaf, so two paths agreeing on those bits are the
+    /// same leaf. All tree operations canonicalize their paths, and proofs always carry the
+    /// canonical one.
+    pub fn canonicalize_path(&self, merkle_path: &MerklePath<F>) -> MerklePath<F> {
+        let mut bits = merkle_path.into_bigint().to_bits_le();
+        bits.truncate(self.path_bits());
+
+        F::from_bigint(F::BigInt::from_bits_le(&bits)).expect("masked path is below the modulus")
+    }
+
+    /// Get the root hash of the tree
+    ///
+    /// Returns the cached hash when the tree is clean, otherwise only the dirty paths are rehashed.
+    /// A clean tree is only read, so nodes borrowed elsewhere don't get in the way.
+    pub fn root(&self) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        let _span = operation_span!("root", depth = self.depth);
+        #[cfg(feature = "hash-trace")]
+        let _trace = self.trace.enter();
+        // The hasher is taken before the nodes, like in every operation, so that readers sharing a
+        // `sync` tree can't deadlock
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        if let NodeType::Leaf(_) = self.root.checked_borrow()?.node_type {
+            return Err(PoseidonMerkleError::InvalidNodeType {
+                level: 0,
+                path: None,
+            });
+        }
+
+        if self.root.checked_borrow()?.dirty {
+            self.root
+                .checked_borrow_mut()?
+                .refresh_hash(hasher, &self.config, 0)?;
+        }
+        let root = self.root.checked_borrow()?;
+        #[cfg(feature = "paranoid")]
+        self.check_cached_hash(hasher, &root, &[]);
+
+        Ok(*root.node_type.data())
+    }
+
+    /// Get the cached root hash without refreshing dirty nodes
+    pub(crate) fn cached_root_hash(&self) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        #[cfg(feature = "paranoid")]
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        let root = self.root.checked_borrow()?;
+        #[cfg(feature = "paranoid")]
+        self.check_cached_hash(hasher, &root, &[]);
+        let hash = root.node_type.hash();
+
+        match hash {
+            Some(hash) => Ok(*hash),
+            None => Err(PoseidonMerkleError::InvalidNodeType {
+                level: 0,
+                path: None,
+            }),
+        }
+    }
+
+    /// Panic unless the cached hash of an inner node matches the one recomputed from its children
+    ///
+    /// `prefix` is the child index taken at each level from the root to the node. Dirty nodes are
+    /// known stale and leaves have no cached hash, both are let through. On a mismatch the blame
+    /// goes down to the deepest node disagreeing with its children, the one that was corrupted.
+    ///
+    /// # Panics
+    ///
+    /// On a mismatch, with the level and the prefix of the node.
+    #[cfg(feature = "paranoid")]
+    pub(crate) fn check_cached_hash(&self, hasher: &mut H, node: &Node<H, F, M>, prefix: &[usize]) {
+        let mut stale = |node: &Node<H, F, M>, level: usize| {
+            let NodeType::Inner(cached) = node.node_type else {
+                return None;
+            };
+            if node.dirty {
+                return None;
+            }
+
+            // A node without children holds the empty hash of its level, and a failing hasher is
+            // left to the operation to report
+            let fresh = if node.children.iter().all(Option::is_none) {
+                self.config.empty_inner_hash_at(level)
+            } else {
+                telemetry::unreported(|| node.hash_children(hasher, &self.config, level)).ok()?
+            };
+            (cached != fresh).then_some((cached, fresh))
+        };
+
+        let mut prefix = prefix.to_vec();
+        let Some(mut mismatch) = stale(node, prefix.len()) else {
+            return;
+        };
+        let mut children = node.children.clone();
+        loop {
+            let level = prefix.len() + 1;
+            let next = children[..self.config.arity.children()]
+                .iter()
+                .enumerate()
+                .find_map(|(index, child)| {
+                    let child = child.as_ref()?;
+                    let mismatch = stale(&*child.checked_borrow().ok()?, level)?;
+                    Some((index, child.clone(), mismatch))
+                });
+            let Some((index, child, child_mismatch)) = next else {
+                break;
+            };
+            mismatch = child_mismatch;
+            prefix.push(index);
+            let Ok(child) = child.checked_borrow() else {
+                break;
+            };
+            children = child.children.clone();
+        }
+
+        let (cached, fresh) = mismatch;
+        panic!(
+            "stale cached hash of the inner node at level {}, prefix {prefix:?}: cached {cached}, \
+             its children hash to {fresh}",
+            prefix.len()
+        );
+    }
+
+    /// Mark every materialized node along a path as dirty
+    ///
+    /// Use this after editing nodes by hand so that the next `root()` call picks up the change.
+    /// The nodes edited must be released first, a node still borrowed fails with
+    /// `PoseidonMerkleError::ConcurrentAccess`.
+    pub fn mark_path_dirty(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let mut current = Some(self.root.clone());
+        for level in 0..=self.depth {
+            let Some(node) = current else {
+                break;
+            };
+            let mut node_ref = node.checked_borrow_mut()?;
+            node_ref.mark_dirty();
+
+            if level == self.depth {
+                break;
+            }
+
+            let index = self.child_index(merkle_path, level);
+            current = node_ref.children[index].clone();
+        }
+
+        Ok(())
+    }
+
+    /// Get the leaf node at a given path
+    ///
+    /// A path no leaf was materialized on fails with `PoseidonMerkleError::LeafNotReached`, with
+    /// the level of the last node on it.
+    pub fn get_node(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<NodeRef<H, F, M>, PoseidonMerkleError<F>> {
+        let mut current = self.root.clone();
+        for i in 0..self.depth {
+            let next = {
+                let current_ref = current.checked_borrow()?;
+                if let NodeType::Leaf(_) = current_ref.node_type {
+                    return Err(PoseidonMerkleError::InvalidNodeType {
+                        level: i,
+                        path: Some(*merkle_path),
+                    });
+                }
+
+                let index = self.child_index(merkle_path, i);
+                current_ref.children[index]
+                    .clone()
+                    .ok_or(PoseidonMerkleError::LeafNotReached {
+                        path: *merkle_path,
+                        level: i,
+                    })?
+            };
+
+            current = next;
+        }
+
+        Ok(current)
+    }
+
+    /// Get the raw value at a given path for a valid leaf node
+    #[deprecated(
+        since = "0.4.0",
+        note = "can't tell an absent leaf from a zero one, use `try_get_value()` instead"
+    )]
+    pub fn get_value(&self, merkle_path: &MerklePath<F>) -> Result<F, PoseidonMerkleError<F>> {
+        Ok(self.try_get_value(merkle_path)?.unwrap_or(F::zero()))
+    }
+
+    /// Get the raw value at a given path
+    ///
+    /// Returns `None` when no leaf was ever materialized on the path, and `Some(F::zero())` for a
+    /// leaf explicitly set to zero. A deleted leaf is removed, `None` again.
+    pub fn try_get_value(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        let Some(leaf) = self.find_leaf(merkle_path)? else {
+            return Ok(None);
+        };
+        let value = leaf.checked_borrow()?.node_type.value().copied();
+
+        Ok(value)
+    }
+
+    /// Get the values held by the leaf at a given path
+    ///
+    /// In a tree of single-value leaves this is the value, like `try_get_value`. In a wider tree
+    /// it's the values given to `insert_values`, `None` once the leaf is deleted.
+    pub fn get_values(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<Vec<F>>, PoseidonMerkleError<F>> {
+        let Some(leaf) = self.find_leaf(merkle_path)? else {
+            return Ok(None);
+        };
+        let leaf_ref = leaf.checked_borrow()?;
+
+        Ok(match self.leaf_width {
+            1 => leaf_ref.node_type.value().map(|value| vec![*value]),
+            _ => leaf_ref.values.clone(),
+        })
+    }
+
+    /// Get the payload of the leaf at a given path, see `insert_with_meta`
+    ///
+    /// `None` when no leaf was materialized on the path or the leaf has no payload.
+    pub fn get_meta(&self, merkle_path: &MerklePath<F>) -> Result<Option<M>, PoseidonMerkleError<F>>
+    where
+        M: Clone,
+    {
+        let Some(leaf) = self.find_leaf(merkle_path)? else {
+            return Ok(None);
+        };
+        let meta = leaf.checked_borrow()?.meta.clone();
+
+        Ok(meta)
+    }
+
+    /// The leaf node at a given path, `None` when it was never materialized
+    fn find_leaf(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<NodeRef<H, F, M>>, PoseidonMerkleError<F>> {
+        let mut current = self.root.clone();
+        for level in 0..self.depth {
+            let next = {
+                let current_ref = current.checked_borrow()?;
+                if let NodeType::Leaf(_) = current_ref.node_type {
+                    return Err(PoseidonMerkleError::InvalidNodeType {
+                        level,
+                        path: Some(*merkle_path),
+                    });
+                }
+
+                let index = self.child_index(merkle_path, level);
+                match &current_ref.children[index] {
+                    Some(child) => child.clone(),
+                    None => return Ok(None),
+                }
+            };
+
+            current = next;
+        }
+
+        if let NodeType::Inner(_) = current.checked_borrow()?.node_type {
+            return Err(PoseidonMerkleError::InvalidNodeType {
+                level: self.depth,
+                path: Some(*merkle_path),
+            });
+        }
+
+        Ok(Some(current))
+    }
+
+    /// Insert a value at a given path
+    ///
+    /// The new hashes along the path are staged bottom-up from the current siblings first, and
+    /// only once they all succeeded are the nodes created (as needed) and updated. A failing
+    /// hasher therefore leaves the tree untouched.
+    ///
+    /// Bits of the path beyond `path_bits()` are ignored, see `canonicalize_path`. A tree holding
+    /// several values per leaf takes them through `insert_values` instead.
+    pub fn insert_at_path(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let span = operation_span!("insert_at_path", depth = self.depth, path = %merkle_path);
+        span.record_value(value);
+        if self.leaf_width != 1 {
+            return Err(PoseidonMerkleError::LeafWidthMismatch {
+                expected: self.leaf_width,
+                actual: 1,
+            });
+        }
+
+        self.insert_leaf(merkle_path, value, None, None)
+    }
+
+    /// Insert a value at a given path with a payload kept on the leaf, see `with_metadata`
+    ///
+    /// The payload isn't hashed, the root is the one of `insert_at_path`. It replaces the payload
+    /// of the leaf, which `insert_at_path` and `delete_at_path` remove.
+    pub fn insert_with_meta(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+        meta: M,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let span = operation_span!("insert_with_meta", depth = self.depth, path = %merkle_path);
+        span.record_value(value);
+        if self.leaf_width != 1 {
+            return Err(PoseidonMerkleError::LeafWidthMismatch {
+                expected: self.leaf_width,
+                actual: 1,
+            });
+        }
+
+        self.insert_leaf(merkle_path, value, None, Some(meta))
+    }
+
+    /// Insert a value at the leaf `index`, the path `insert_at_path` takes as an integer
+    ///
+    /// Under `BitOrder::MsbFirst` it's the position of the leaf from the left. An index past the
+    /// last leaf fails with `PoseidonMerkleError::IndexOutOfRange`.
+    pub fn insert_at_index(&mut self, index: u64, value: &F) -> Result<(), PoseidonMerkleError<F>> {
+        let merkle_path = self.index_path(index)?;
+        self.insert_at_path(&merkle_path, value)
+    }
+
+    /// The path of the leaf at `index`, failing past the last leaf
+    pub(crate) fn index_path(&self, index: u64) -> Result<MerklePath<F>, PoseidonMerkleError<F>> {
+        let bits = self.path_bits();
+        if bits < 64 && index >> bits != 0 {
+            return Err(PoseidonMerkleError::IndexOutOfRange {
+                index: usize::try_from(index).unwrap_or(usize::MAX),
+                size: usize::try_from(1u64 << bits).unwrap_or(usize::MAX),
+            });
+        }
+
+        Ok(F::from(index))
+    }
+
+    /// Insert a `u64` at a path, as the field element of the same integer
+    pub fn insert_u64(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        value: u64,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        self.insert_at_path(merkle_path, &F::from(value))
+    }
+
+    /// Insert a `u128` at a path, as the field element of the same integer
+    pub fn insert_u128(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        value: u128,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        self.insert_at_path(merkle_path, &F::from(value))
+    }
+
+    /// Insert 32 little-endian bytes at a path, as the field element of the same integer
+    ///
+    /// Bytes not below the modulus fail with `PoseidonMerkleError::NonCanonicalValue` instead of
+    /// being reduced, so that two byte strings never insert the same value.
+    pub fn insert_bytes_le(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        bytes: &[u8; 32],
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let value = F::from_le_bytes_mod_order(bytes);
+        if to_bytes_le(&value) != *bytes {
+            return Err(PoseidonMerkleError::NonCanonicalValue);
+        }
+
+        self.insert_at_path(merkle_path, &value)
+    }
+
+    /// Get the value at a path as a `u64`, like `try_get_value`
+    ///
+    /// A value of 64 bits or more fails with `PoseidonMerkleError::ValueOutOfRange`.
+    pub fn get_u64(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<u64>, PoseidonMerkleError<F>> {
+        self.try_get_value(merkle_path)?
+            .map(|value| Ok(to_u128(&value, 64)? as u64))
+            .transpose()
+    }
+
+    /// Get the value at a path as a `u128`, like `try_get_value`
+    ///
+    /// A value of 128 bits or more fails with `PoseidonMerkleError::ValueOutOfRange`.
+    pub fn get_u128(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<u128>, PoseidonMerkleError<F>> {
+        self.try_get_value(merkle_path)?
+            .map(|value| to_u128(&value, 128))
+            .transpose()
+    }
+
+    /// Get the value at a path as the 32 little-endian bytes `insert_bytes_le` takes
+    pub fn get_bytes_le(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<[u8; 32]>, PoseidonMerkleError<F>> {
+        Ok(self
+            .try_get_value(merkle_path)?
+            .map(|value| to_bytes_le(&value)))
+    }
+
+    /// Insert every value at its path, in order, like `insert_at_path` for each
+    ///
+    /// Stops at the first failure, the entries before it stay inserted. Entries needing more
+    /// nodes than the budget of `with_max_nodes` allows fail before any is inserted.
+    pub fn insert_many(
+        &mut self,
+        entries: impl IntoIterator<Item = (MerklePath<F>, F)>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let _span = operation_span!("insert_many", depth = self.depth);
+        let entries: Vec<_> = entries.into_iter().collect();
+        self.check_node_budget(entries.iter().map(|(merkle_path, _)| merkle_path))?;
+        for (merkle_path, value) in entries {
+            self.insert_at_path(&merkle_path, &value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Insert every value at its path, hashing each node above them once for the whole batch
+    ///
+    /// The leaves are placed first and the nodes above them marked dirty, then the root is
+    /// refreshed, so an ancestor shared by several paths is hashed once rather than once per
+    /// entry. The root is the one of `insert_many`, and a later entry at the same path wins.
+    ///
+    /// A failing hasher leaves the leaves placed and the nodes above them dirty, for the next
+    /// `root()` to hash. With an observer, a changelog or a trace sink that follow the inserts one
+    /// by one, this is `insert_many`.
+    pub fn insert_batch(
+        &mut self,
+        entries: &[(MerklePath<F>, F)],
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let _span = operation_span!("insert_batch", depth = self.depth, entries = entries.len());
+        #[cfg(feature = "hash-trace")]
+        let traced = self.trace.is_set();
+        #[cfg(not(feature = "hash-trace"))]
+        let traced = false;
+        if self.observer.is_set() || self.changelog.is_some() || traced {
+            return self.insert_many(entries.iter().copied());
+        }
+        if self.leaf_width != 1 {
+            return Err(PoseidonMerkleError::LeafWidthMismatch {
+                expected: self.leaf_width,
+                actual: 1,
+            });
+        }
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.check_node_budget(entries.iter().map(|(merkle_path, _)| merkle_path))?;
+
+        for (merkle_path, value) in entries {
+            let merkle_path = self.canonicalize_path(merkle_path);
+            self.place_leaf(&merkle_path, value)?;
+            telemetry::record_insert();
+        }
+        self.root()?;
+
+        Ok(())
+    }
+
+    /// Insert the values of a leaf at a given path, exactly `leaf_width()` of them
+    ///
+    /// The leaf value is their commitment `hash_leaf_values`, the values themselves are kept on
+    /// the leaf for `get_values` and proofs carrying the preimage.
+    pub fn insert_values(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        values: &[F],
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        if values.len() != self.leaf_width {
+            return Err(PoseidonMerkleError::LeafWidthMismatch {
+                expected: self.leaf_width,
+                actual: values.len(),
+            });
+        }
+        if self.leaf_width == 1 {
+            return self.insert_leaf(merkle_path, &values[0], None, None);
+        }
+
+        let commitment = hash_leaf_values(values)?;
+        self.insert_leaf(merkle_path, &commitment, Some(values.to_vec()), None)
+    }
+
+    /// Insert a leaf value, with the values it commits to and its payload if any
+    fn insert_leaf(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+        values: Option<Vec<F>>,
+        meta: Option<M>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let merkle_path = &self.canonicalize_path(merkle_path);
+        let (hashes, old) = self.write_leaf(merkle_path, value, None, values, meta)?;
+        self.count_leaf(old, value);
+        telemetry::record_insert();
+        self.observer
+            .notify(|observer| observer.on_insert(merkle_path, old, value, &hashes[0]));
+
+        Ok(())
+    }
+
+    /// Insert a value at a canonical path whose siblings are known, like `insert_at_path`
+    ///
+    /// Returns the hashes of the nodes on the path, the root first and the leaf contribution
+    /// last, for callers keeping their own view of the siblings, see `IndexedAppendTree`.
+    pub(crate) fn insert_with_siblings(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+        siblings: &[Sibling<F>],
+    ) -> Result<Vec<InnerHash<F>>, PoseidonMerkleError<F>> {
+        if self.leaf_width != 1 {
+            return Err(PoseidonMerkleError::LeafWidthMismatch {
+                expected: self.leaf_width,
+                actual: 1,
+            });
+        }
+
+        let (hashes, old) = self.write_leaf(merkle_path, value, Some(siblings), None, None)?;
+        self.count_leaf(old, value);
+        telemetry::record_insert();
+        self.observer
+            .notify(|observer| observer.on_insert(merkle_path, old, value, &hashes[0]));
+
+        Ok(hashes)
+    }
+
+    /// Write a leaf at a canonical path, returning the hashes of the path and the previous value
+    ///
+    /// The siblings along the path are read from the tree unless given. The hashes are those of
+    /// the nodes on the path from the root, then the leaf contribution.
+    fn write_leaf(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+        siblings: Option<&[Sibling<F>]>,
+        values: Option<Vec<F>>,
+        meta: Option<M>,
+    ) -> Result<WrittenLeaf<F>, PoseidonMerkleError<F>> {
+        #[cfg(feature = "hash-trace")]
+        let _trace = self.trace.enter();
+        self.check_node_budget([merkle_path])?;
+        let siblings = match siblings {
+            Some(siblings) => siblings.to_vec(),
+            None => self.path_siblings(merkle_path)?,
+        };
+        let per_level = self.config.arity.children() - 1;
+
+        // Stage the hash of every node on the path, hashes[depth] is the leaf contribution
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        let mut hashes = vec![self.config.leaf_hash(hasher, value)?; self.depth + 1];
+        for level in (0..self.depth).rev() {
+            let mut inputs = siblings[level * per_level..(level + 1) * per_level].to_vec();
+            inputs.insert(self.child_index(merkle_path, level), hashes[level + 1]);
+
+            hashes[level] = self.config.inner_hash(hasher, level, &inputs)?;
+        }
+
+        // Commit the staged hashes top-down, creating nodes as needed, once no node on the way is
+        // borrowed elsewhere
+        self.check_path_writable(merkle_path)?;
+        let mut current_node = self.root.clone();
+        let mut materialized = true;
+        let mut created = 0;
+        for (level, hash) in hashes[..self.depth].iter().enumerate() {
+            let next_node = {
+                let mut current_ref = current_node.checked_borrow_mut()?;
+                current_ref.node_type = NodeType::Inner(*hash);
+
+                let index = self.child_index(merkle_path, level);
+                materialized &= current_ref.children[index].is_some();
+
+                // Use inner nodes for all but the last level
+                current_ref.children[index]
+                    .get_or_insert_with(|| {
+                        created += 1;
+                        if level == self.depth - 1 {
+                            Node::new_borrowed_empty_leaf()
+                        } else {
+                            Node::new_borrowed_inner(self.config.empty_inner_hash_at(level + 1))
+                        }
+                    })
+                    .clone()
+            };
+
+            current_node = next_node;
+        }
+        let mut leaf = current_node.checked_borrow_mut()?;
+        let old = materialized.then(|| *leaf.node_type.data());
+        leaf.node_type = NodeType::Leaf(*value);
+        leaf.values = values;
+        leaf.meta = meta;
+        drop(leaf);
+        self.nodes += created;
+
+        if let Some(changelog) = self.changelog.as_mut() {
+            changelog.push(ChangeLogEntry {
+                leaf_index: *merkle_path,
+                new_leaf: *value,
+                new_root: hashes[0],
+                path_nodes: hashes[1..].iter().rev().copied().collect(),
+            });
+        }
+
+        Ok((hashes, old))
+    }
+
+    /// Write a leaf at a canonical path without hashing, marking the nodes above it dirty
+    ///
+    /// The next `root()` rehashes the dirty nodes, each once however many leaves were placed below
+    /// it. Returns the previous value like `write_leaf`, neither the observer nor the changelog
+    /// are told.
+    pub(crate) fn place_leaf(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+        value: &F,
+    ) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        self.check_node_budget([merkle_path])?;
+        self.check_path_writable(merkle_path)?;
+        let mut current_node = self.root.clone();
+        let mut materialized = true;
+        let mut created = 0;
+        for level in 0..self.depth {
+            let next_node = {
+                let mut current_ref = current_node.checked_borrow_mut()?;
+                if let NodeType::Leaf(_) = current_ref.node_type {
+                    return Err(PoseidonMerkleError::InvalidNodeType {
+                        level,
+                        path: Some(*merkle_path),
+                    });
+                }
+                current_ref.mark_dirty();
+
+                let index = self.child_index(merkle_path, level);
+                materialized &= current_ref.children[index].is_some();
+                current_ref.children[index]
+                    .get_or_insert_with(|| {
+                        created += 1;
+                        if level == self.depth - 1 {
+                            Node::new_borrowed_empty_leaf()
+                        } else {
+                            Node::new_borrowed_inner(self.config.empty_inner_hash_at(level + 1))
+                        }
+                    })
+                    .clone()
+            };
+
+            current_node = next_node;
+        }
+        let mut leaf = current_node.checked_borrow_mut()?;
+        let old = materialized.then(|| *leaf.node_type.data());
+        leaf.node_type = NodeType::Leaf(*value);
+        leaf.values = None;
+        leaf.meta = None;
+        drop(leaf);
+        self.nodes += created;
+        self.count_leaf(old, value);
+
+        Ok(old)
+    }
+
+    /// Remove the leaf at a canonical path and the inner nodes it leaves without children
+    ///
+    /// The nodes above are marked dirty for the next `root()`, and a root left without children
+    /// takes the hash of the empty tree back, so the tree is the one the leaf was never written
+    /// to. Returns the value of the leaf, `None` if it wasn't materialized. Neither the observer
+    /// nor the changelog are told.
+    pub(crate) fn remove_leaf(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        self.check_path_writable(merkle_path)?;
+        let mut path_nodes = vec![self.root.clone()];
+        for level in 0..self.depth {
+            let next_node = {
+                let current_ref = path_nodes[level].checked_borrow()?;
+                if let NodeType::Leaf(_) = current_ref.node_type {
+                    return Err(PoseidonMerkleError::InvalidNodeType {
+                        level,
+                        path: Some(*merkle_path),
+                    });
+                }
+                match &current_ref.children[self.child_index(merkle_path, level)] {
+                    Some(child) => child.clone(),
+                    None => return Ok(None),
+                }
+            };
+
+            path_nodes.push(next_node);
+        }
+        let old = *path_nodes[self.depth].checked_borrow()?.node_type.data();
+
+        // Bottom-up, a node is detached while its child was the only one, and dirty above that
+        let mut detach = true;
+        for level in (0..self.depth).rev() {
+            let mut node_ref = path_nodes[level].checked_borrow_mut()?;
+            if detach {
+                node_ref.children[self.child_index(merkle_path, level)] = None;
+                self.nodes -= 1;
+            }
+            detach = level > 0 && node_ref.children.iter().all(Option::is_none);
+            if !detach {
+                node_ref.mark_dirty();
+            }
+        }
+        let mut root = self.root.checked_borrow_mut()?;
+        if root.children.iter().all(Option::is_none) {
+            root.node_type = NodeType::Inner(self.config.empty_inner_hash_at(0));
+            root.dirty = false;
+        }
+        drop(root);
+        self.count_leaf(Some(old), &self.config.empty_leaf.clone());
+
+        Ok(Some(old))
+    }
+
+    /// The hash of every node on a path, the root first and the leaf contribution last
+    ///
+    /// The root is refreshed first, and the nodes off the tree are empty subtrees.
+    fn path_hashes(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Vec<InnerHash<F>>, PoseidonMerkleError<F>> {
+        let mut hashes = vec![self.root()?];
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        let mut current = Some(self.root.clone());
+        for level in 1..=self.depth {
+            current = match current {
+                Some(node) => node.checked_borrow()?.children
+                    [self.child_index(merkle_path, level - 1)]
+                .clone(),
+                None => None,
+            };
+            hashes.push(match &current {
+                Some(node) => match &node.checked_borrow()?.node_type {
+                    NodeType::Leaf(value) => self.config.leaf_hash(hasher, value)?,
+                    NodeType::Inner(hash) => *hash,
+                },
+                None => self
+                    .config
+                    .empty_hash_at(hasher, level, level == self.depth)?,
+            });
+        }
+
+        Ok(hashes)
+    }
+
+    /// Fail with `PoseidonMerkleError::ConcurrentAccess` if a node on a path is borrowed elsewhere
+    ///
+    /// Writing a path stops at the first node it can't borrow, so it's checked beforehand rather
+    /// than leave the nodes above it updated and the ones below not.
+    fn check_path_writable(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let mut current = Some(self.root.clone());
+        for level in 0..=self.depth {
+            let Some(node) = current else {
+                break;
+            };
+            let node_ref = node.checked_borrow_mut()?;
+            if level == self.depth {
+                break;
+            }
 
-use ark_bn254::Fr;
-use ark_ff::{AdditiveGroup, BigInt, BigInteger, PrimeField};
-use light_poseidon::{Poseidon, PoseidonHasher};
+            current = node_ref.children[self.child_index(merkle_path, level)].clone();
+        }
 
-use crate::{
-    get_empty_inner_hash,
-    node::{InnerHash, Node},
-    MerkleProof, NodeType, PoseidonMerkleError, ProofError,
-};
+        Ok(())
+    }
 
-/// A path in the merkle tree as a field element
-///
-/// The path corresponds to the bits of the field element (Fr::into_bigint().to_bits_le())
-pub type MerklePath = Fr;
+    /// Track a leaf going from `old` to `new` in the `telemetry::LEAVES` gauge
+    #[inline]
+    fn count_leaf(&mut self, _old: Option<F>, _new: &F) {
+        #[cfg(feature = "metrics")]
+        {
+            let empty_leaf = self.config.empty_leaf;
+            let was_set = _old.is_some_and(|old| old != empty_leaf);
+            match (was_set, *_new != empty_leaf) {
+                (false, true) => self.leaves += 1,
+                (true, false) => self.leaves -= 1,
+                _ => {}
+            }
+            telemetry::record_leaves(self.leaves);
+        }
+    }
+
+    /// Collect the sibling hashes at every level along a path, from the root down
+    ///
+    /// Each level contributes `arity - 1` siblings, the other children in index order. Missing
+    /// siblings are empty: an empty leaf at the last level and an empty inner node of their level
+    /// above it. Leaf siblings are given as the hash they contribute to their parent.
+    pub(crate) fn path_siblings(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<Vec<Sibling<F>>, PoseidonMerkleError<F>> {
+        let arity = self.config.arity;
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        let mut siblings: Vec<Sibling<F>> = Vec::with_capacity(self.depth * (arity.children() - 1));
+        let mut current = Some(self.root.clone());
 
-pub type Sibling = Fr;
+        for level in 0..self.depth {
+            let is_last_level = level == self.depth - 1;
+            let empty_sibling = self
+                .config
+                .empty_hash_at(hasher, level + 1, is_last_level)?;
 
-/// Sparse Poseidon Merkle Tree
-#[derive(Debug, Clone)]
-pub struct SparseMerkleTree<H: PoseidonHasher<Fr>> {
-    /// The hasher for the tree
-    hasher: H,
-    /// The root of the tree
-    pub root: Rc<RefCell<Node<H>>>,
-    /// The MAX depth of the tree
-    pub depth: usize,
-}
+            // Past the materialized part of the path every sibling is empty
+            let Some(node) = current.take() else {
+                siblings.extend(vec![empty_sibling; arity.children() - 1]);
+                continue;
+            };
 
-impl SparseMerkleTree<Poseidon<Fr>> {
-    /// Create a new (lazy) sparse poseidon merkle tree given a depth
-    pub fn new(depth: usize) -> Result<Self, PoseidonMerkleError> {
-        let poseidon = Poseidon::<Fr>::new_circom(2)?;
-        Self::new_with_hasher(depth, poseidon)
+            let node_ref = node.checked_borrow()?;
+            if let NodeType::Leaf(_) = node_ref.node_type {
+                return Err(PoseidonMerkleError::InvalidNodeType {
+                    level,
+                    path: Some(*merkle_path),
+                });
+            }
+
+            let index = self.child_index(merkle_path, level);
+            for (sibling_index, sibling) in node_ref.children[..arity.children()].iter().enumerate()
+            {
+                if sibling_index == index {
+                    continue;
+                }
+
+                let sibling: Sibling<F> = match sibling {
+                    Some(sibling) => {
+                        let sibling = sibling.checked_borrow()?;
+                        match &sibling.node_type {
+                            NodeType::Leaf(value) if is_last_level => {
+                                self.config.leaf_hash(hasher, value)?
+                            }
+                            NodeType::Leaf(_) => {
+                                return Err(ProofError::InnerNodeExpected { level }.into());
+                            }
+                            NodeType::Inner(hash) => {
+                                #[cfg(feature = "paranoid")]
+                                {
+                                    let mut prefix: Vec<usize> = (0..level)
+                                        .map(|level| self.child_index(merkle_path, level))
+                                        .collect();
+                                    prefix.push(sibling_index);
+                                    self.check_cached_hash(hasher, &sibling, &prefix);
+                                }
+                                *hash
+                            }
+                        }
+                    }
+                    None => empty_sibling,
+                };
+                siblings.push(sibling);
+            }
+
+            current = node_ref.children[index].clone();
+        }
+
+        Ok(siblings)
     }
 
-    /// Get the bit at the given position
+    /// Get the path hash from a list of bits, the first bit picks the child of the root
     ///
-    /// [true, false] -> [1, 0]
-    pub fn get_path_bit(merkle_path: &MerklePath, position: usize) -> bool {
-        let bits = merkle_path.into_bigint().to_bits_le();
-        bits[position]
+    /// The slice can't be longer than `path_bits()`, nor than the bits that always fit in a field
+    /// element (`MAX_PATH_BITS` for bn254). Wider trees consume `arity.bits()` bits per level.
+    /// Under `BitOrder::MsbFirst` the slice is read from the most significant of the `path_bits()`
+    /// bits down, so a short slice leaves the low bits, the deepest levels, at zero.
+    pub fn get_merkle_path(&self, path: &[bool]) -> Result<MerklePath<F>, PoseidonMerkleError<F>> {
+        let max = self.path_bits().min(F::MODULUS_BIT_SIZE as usize - 1);
+        let invalid = PoseidonMerkleError::InvalidBitsPathHash {
+            len: path.len(),
+            max,
+        };
+        if path.len() > max {
+            return Err(invalid);
+        }
+
+        let path_bits = match self.bit_order {
+            BitOrder::LsbFirst => F::BigInt::from_bits_le(path),
+            BitOrder::MsbFirst => {
+                let mut bits = path.to_vec();
+                bits.resize(self.path_bits(), false);
+                F::BigInt::from_bits_be(&bits)
+            }
+        };
+        let merkle_path = F::from_bigint(path_bits).ok_or(invalid)?;
+        Ok(merkle_path)
     }
 
-    /// Get the root hash of the tree
-    pub fn get_root_hash(&self) -> Result<InnerHash, PoseidonMerkleError> {
-        let root = self.root.borrow();
-        let hash = root.node_type.hash();
+    /// Like `get_merkle_path`, right-padding a short slice with `false` up to `path_bits()`
+    ///
+    /// The padded bits lead to the first child at every remaining level.
+    pub fn get_merkle_path_padded(
+        &self,
+        path: &[bool],
+    ) -> Result<MerklePath<F>, PoseidonMerkleError<F>> {
+        let mut padded = path.to_vec();
+        if padded.len() < self.path_bits() {
+            padded.resize(self.path_bits(), false);
+        }
 
-        match hash {
-            Some(hash) => Ok(*hash),
-            None => Err(PoseidonMerkleError::InvalidNodeType),
+        self.get_merkle_path(&padded)
+    }
+
+    /// The path of a byte key, such as an account address, see `verify::hash_key`
+    ///
+    /// The key is hashed with the tree hasher and canonicalized, so only the low `path_bits()`
+    /// bits of the hash are kept. Distinct keys can therefore share a path, which becomes likely
+    /// around 2^(path_bits / 2) keys: the tree doesn't store keys and the keyed operations can't
+    /// tell such a collision apart. Pick a depth leaving enough room for the expected key count.
+    pub fn path_for_key(&self, key: &[u8]) -> Result<MerklePath<F>, PoseidonMerkleError<F>> {
+        let hash = verify::hash_key(
+            &mut *self.hasher.checked_borrow_mut()?,
+            key,
+            self.config.arity,
+        )?;
+
+        Ok(self.canonicalize_path(&hash))
+    }
+
+    /// Insert a value at the path of a byte key, see `path_for_key`
+    pub fn insert_by_key(&mut self, key: &[u8], value: &F) -> Result<(), PoseidonMerkleError<F>> {
+        let merkle_path = self.path_for_key(key)?;
+        self.insert_at_path(&merkle_path, value)
+    }
+
+    /// Get the raw value at the path of a byte key, like `try_get_value`
+    pub fn get_by_key(&self, key: &[u8]) -> Result<Option<F>, PoseidonMerkleError<F>> {
+        let merkle_path = self.path_for_key(key)?;
+        self.try_get_value(&merkle_path)
+    }
+
+    /// Generate a proof for the path of a byte key, like `generate_proof`
+    pub fn prove_by_key(&self, key: &[u8]) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        let merkle_path = self.path_for_key(key)?;
+        self.generate_proof(&merkle_path)
+    }
+
+    /// Delete the leaf at a given path, pruning the inner nodes left without children
+    ///
+    /// The path is then empty like one never inserted at: `try_get_value` gives `None`, and
+    /// deleting every leaf leaves an empty tree of a single node.
+    pub fn delete_at_path(
+        &mut self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        #[cfg(feature = "hash-trace")]
+        let _trace = self.trace.enter();
+        let merkle_path = &self.canonicalize_path(merkle_path);
+        let old = self.remove_leaf(merkle_path)?;
+        let hashes = self.path_hashes(merkle_path)?;
+        if let Some(changelog) = self.changelog.as_mut() {
+            changelog.push(ChangeLogEntry {
+                leaf_index: *merkle_path,
+                new_leaf: self.config.empty_leaf,
+                new_root: hashes[0],
+                path_nodes: hashes[1..].iter().rev().copied().collect(),
+            });
         }
+        telemetry::record_delete();
+        self.observer
+            .notify(|observer| observer.on_delete(merkle_path, old, &hashes[0]));
+
+        Ok(())
+    }
+
+    /// Get the cached root hash of the tree
+    #[deprecated(
+        since = "0.4.0",
+        note = "returns the cached root which may be stale, use `root()` instead"
+    )]
+    pub fn get_root_hash(&self) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        self.cached_root_hash()
     }
 
     /// Get the inner node at a given path and level
@@ -61,9 +1655,9 @@ impl SparseMerkleTree<Poseidon<Fr>> {
     /// leaf = level depth
     pub fn get_inner_node(
         &self,
-        merkle_path: &MerklePath,
+        merkle_path: &MerklePath<F>,
         level: usize,
-    ) -> Result<Rc<RefCell<Node<Poseidon<Fr>>>>, PoseidonMerkleError> {
+    ) -> Result<NodeRef<H, F, M>, PoseidonMerkleError<F>> {
         if level >= self.depth {
             return Err(PoseidonMerkleError::InvalidLevel);
         }
@@ -71,19 +1665,12 @@ impl SparseMerkleTree<Poseidon<Fr>> {
         let mut current = self.root.clone();
         for i in 0..level {
             let next = {
-                let current_ref = current.borrow();
-                let go_right = Self::get_path_bit(merkle_path, i);
+                let current_ref = current.checked_borrow()?;
+                let index = self.child_index(merkle_path, i);
 
-                if go_right {
-                    match &current_ref.right {
-                        Some(node) => node.clone(),
-                        None => Node::new_borrowed_empty_inner(),
-                    }
-                } else {
-                    match &current_ref.left {
-                        Some(node) => node.clone(),
-                        None => Node::new_borrowed_empty_inner(),
-                    }
+                match &current_ref.children[index] {
+                    Some(node) => node.clone(),
+                    None => Node::new_borrowed_inner(self.config.empty_inner_hash_at(i + 1)),
                 }
             };
 
@@ -93,260 +1680,628 @@ impl SparseMerkleTree<Poseidon<Fr>> {
         Ok(current)
     }
 
-    /// Get the leaf node at a given path
-    pub fn get_node(
+    /// The hash the node at `level` along a path contributes to its parent, refreshed first
+    ///
+    /// The root is at level 0 and the leaf at level `depth`. A node that isn't materialized gives
+    /// the empty hash of its level.
+    pub fn subtree_root(
         &self,
-        merkle_path: &MerklePath,
-    ) -> Result<Rc<RefCell<Node<Poseidon<Fr>>>>, PoseidonMerkleError> {
-        let mut current = self.root.clone();
-        for i in 0..self.depth {
-            let next = {
-                let current_ref = current.borrow();
-                let go_right = Self::get_path_bit(merkle_path, i);
+        merkle_path: &MerklePath<F>,
+        level: usize,
+    ) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        if level > self.depth {
+            return Err(PoseidonMerkleError::InvalidLevel);
+        }
+        self.root()?;
 
-                if go_right {
-                    current_ref.right.as_ref().unwrap().clone()
-                } else {
-                    current_ref.left.as_ref().unwrap().clone()
+        let merkle_path = &self.canonicalize_path(merkle_path);
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        let mut current = self.root.clone();
+        for i in 0..level {
+            let next = current.checked_borrow()?.children[self.child_index(merkle_path, i)].clone();
+            match next {
+                Some(node) => current = node,
+                None => {
+                    return self
+                        .config
+                        .empty_hash_at(hasher, level, level == self.depth)
                 }
-            };
-
-            current = next.clone();
+            }
         }
 
-        Ok(current)
+        let node = current.checked_borrow()?;
+        match &node.node_type {
+            NodeType::Leaf(value) => self.config.leaf_hash(hasher, value),
+            NodeType::Inner(hash) => Ok(*hash),
+        }
     }
 
     /// Generate a proof for a given path, if through the path we meet an empty node, we return an error
     ///
-    /// The path MUST BE valid for a NON-EMPTY value.
+    /// The path MUST BE valid for a NON-EMPTY value. The proof carries the canonical path. With a
+    /// non-zero empty leaf or `HashingScheme::LevelBound`, where empty subtrees hash like subtrees
+    /// of empty leaves, a path without a leaf is proven to hold the empty leaf instead. Folded
+    /// empty subtrees alone keep a missing leaf an error, `generate_non_membership_proof` proves
+    /// it. Dirty nodes are rehashed first, the proof holds against `root()`.
     pub fn generate_proof(
         &self,
-        merkle_path: &MerklePath,
-    ) -> Result<MerkleProof, PoseidonMerkleError> {
-        let current = self.get_node(merkle_path)?;
-        let current_ref = current.borrow();
-
-        if let NodeType::Leaf(value) = current_ref.node_type {
-            // Store siblings in the order they will be used during verification
-            let mut siblings: Vec<Sibling> = Vec::with_capacity(self.depth);
-
-            // First compute the leaf value hash
-            let mut current = self.root.clone();
-
-            // Collect siblings along the path
-            for i in 0..self.depth {
-                // If we're at the last node, we need to get the leaf sibling, which could be empty
-                let is_last_node = i == self.depth - 1;
-                let go_right = Self::get_path_bit(merkle_path, i);
-
-                let next = {
-                    let current_ref = current.borrow();
-
-                    if go_right {
-                        current_ref
-                            .right
-                            .as_ref()
-                            .ok_or(ProofError::SiblingNotFound(i))?
-                            .clone()
-                    } else {
-                        current_ref
-                            .left
-                            .as_ref()
-                            .ok_or(ProofError::SiblingNotFound(i))?
-                            .clone()
-                    }
-                };
+        merkle_path: &MerklePath<F>,
+    ) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        let _span = operation_span!("generate_proof", depth = self.depth, path = %merkle_path);
+        let timer = ProofTimer::start();
+        let merkle_path = &self.canonicalize_path(merkle_path);
+        let leaf_value = match self.try_get_value(merkle_path)? {
+            Some(value) => value,
+            None if !self.config.empty_leaf.is_zero()
+                || self.config.scheme == HashingScheme::LevelBound =>
+            {
+                self.config.empty_leaf
+            }
+            None => return Err(PoseidonMerkleError::LeafNotFound { path: *merkle_path }),
+        };
+        let root_hash = self.root()?;
 
-                // On last node, sibling is a leaf, default to empty value (Fr::ZERO)
-                let sibling: Sibling = if go_right {
-                    match &current_ref.left {
-                        Some(node) => match &node.borrow().node_type {
-                            NodeType::Leaf(_) => Err(ProofError::InnerNodeExpected),
-                            NodeType::Inner(hash) => Ok(*hash),
-                        },
-                        None => {
-                            if is_last_node {
-                                Ok(Fr::ZERO)
-                            } else {
-                                Ok(*get_empty_inner_hash())
-                            }
-                        }
-                    }?
-                } else {
-                    match &current_ref.right {
-                        Some(node) => match &node.borrow().node_type {
-                            NodeType::Leaf(_) => Err(ProofError::InnerNodeExpected),
-                            NodeType::Inner(hash) => Ok(*hash),
-                        },
-                        None => {
-                            if is_last_node {
-                                Ok(Fr::ZERO)
-                            } else {
-                                Ok(*get_empty_inner_hash())
-                            }
-                        }
-                    }?
-                };
+        // Siblings are stored in the order they will be used during verification
+        let siblings = self.path_siblings(merkle_path)?;
 
-                siblings.push(sibling);
-                current = next.clone();
-            }
+        let proof = MerkleProof::new(siblings, *merkle_path, leaf_value, root_hash)
+            .try_with_arity(self.config.arity)?;
+
+        timer.finish();
+        Ok(proof
+            .with_leaf_hashing(self.config.leaf_hashing)
+            .with_hash_family(self.config.family)
+            .with_scheme(self.config.scheme)
+            .with_params_fingerprint(self.params_fingerprint)
+            .with_bit_order(self.bit_order)
+            .with_empty_subtrees(self.config.empty_subtrees))
+    }
 
-            let root_hash = self.get_root_hash()?;
+    /// Generate a proof that a path holds the empty leaf, `Fr::ZERO` unless set otherwise
+    ///
+    /// Unlike `generate_proof` it takes paths that were never inserted at: the proof records the
+    /// highest empty subtree on the path and is verified from its hash, see `NonMembershipProof`.
+    /// A path holding another value fails with `PoseidonMerkleError::LeafNotEmpty`.
+    pub fn generate_non_membership_proof(
+        &self,
+        merkle_path: &MerklePath<F>,
+    ) -> Result<NonMembershipProof<F>, PoseidonMerkleError<F>> {
+        let _span = operation_span!("generate_non_membership_proof", depth = self.depth, path = %merkle_path);
+        let merkle_path = &self.canonicalize_path(merkle_path);
+        let root_hash = self.root()?;
+        let empty_level = self.empty_level(merkle_path)?;
+        let siblings = self.path_siblings(merkle_path)?;
 
-            Ok(MerkleProof::new(siblings, *merkle_path, value, root_hash))
-        } else {
-            Err(PoseidonMerkleError::InvalidNodeType)
-        }
+        let proof = MerkleProof::new(siblings, *merkle_path, self.config.empty_leaf, root_hash)
+            .try_with_arity(self.config.arity)?
+            .with_leaf_hashing(self.config.leaf_hashing)
+            .with_hash_family(self.config.family)
+            .with_scheme(self.config.scheme)
+            .with_params_fingerprint(self.params_fingerprint)
+            .with_bit_order(self.bit_order)
+            .with_empty_subtrees(self.config.empty_subtrees);
+
+        Ok(NonMembershipProof { proof, empty_level })
     }
 
-    /// Get the raw value at a given path for a valid leaf node
-    pub fn get_value(&self, merkle_path: &MerklePath) -> Result<Fr, PoseidonMerkleError> {
-        let node = self.get_node(merkle_path)?;
-        let node_ref = node.borrow();
-        if let NodeType::Leaf(value) = node_ref.node_type {
-            Ok(value)
-        } else {
-            Err(PoseidonMerkleError::InvalidNodeType)
+    /// Level of the highest empty subtree on a path, the depth if only its leaf is empty
+    fn empty_level(&self, merkle_path: &MerklePath<F>) -> Result<usize, PoseidonMerkleError<F>> {
+        let mut current = self.root.clone();
+        for level in 0..self.depth {
+            let next = {
+                let current_ref = current.checked_borrow()?;
+                if let NodeType::Leaf(_) = current_ref.node_type {
+                    return Err(PoseidonMerkleError::InvalidNodeType {
+                        level,
+                        path: Some(*merkle_path),
+                    });
+                }
+                // Only the root of an empty tree has no children
+                if current_ref.children.iter().all(Option::is_none) {
+                    return Ok(level);
+                }
+
+                match &current_ref.children[self.child_index(merkle_path, level)] {
+                    Some(child) => child.clone(),
+                    None => return Ok(level + 1),
+                }
+            };
+
+            current = next;
+        }
+
+        let leaf = current.checked_borrow()?;
+        match leaf.node_type {
+            NodeType::Leaf(value) if value == self.config.empty_leaf => Ok(self.depth),
+            NodeType::Leaf(_) => Err(PoseidonMerkleError::LeafNotEmpty { path: *merkle_path }),
+            NodeType::Inner(_) => Err(PoseidonMerkleError::InvalidNodeType {
+                level: self.depth,
+                path: Some(*merkle_path),
+            }),
         }
     }
 
-    /// Create a new sparse poseidon merkle tree given a depth
-    pub fn new_with_hasher(
-        depth: usize,
-        hasher: Poseidon<Fr>,
-    ) -> Result<SparseMerkleTree<Poseidon<Fr>>, PoseidonMerkleError> {
-        if depth == 0 {
-            return Err(PoseidonMerkleError::InvalidDepth);
+    /// The hashes of the nodes of the top `levels` levels below the root, breadth-first
+    ///
+    /// Level 1 comes first, and each level lists its nodes left to right by child index, the layout
+    /// of a light-protocol canopy: `2^(levels + 1) - 2` hashes for a binary tree. Missing nodes
+    /// are filled in with the empty hash of their level and leaves give the hash they contribute
+    /// to their parent. Under `BitOrder::LsbFirst` the leaf level is not in index order. The root
+    /// is refreshed first, and `levels` can't exceed the depth.
+    pub fn export_canopy(
+        &self,
+        levels: usize,
+    ) -> Result<Vec<InnerHash<F>>, PoseidonMerkleError<F>> {
+        if levels > self.depth {
+            return Err(PoseidonMerkleError::InvalidLevel);
         }
+        self.root()?;
 
-        Ok(SparseMerkleTree {
-            hasher,
-            root: Node::new_borrowed_empty_inner(),
-            depth,
-        })
+        let children = self.config.arity.children();
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        let mut canopy = Vec::new();
+        let mut nodes = vec![Some(self.root.clone())];
+        for level in 1..=levels {
+            let empty = self
+                .config
+                .empty_hash_at(hasher, level, level == self.depth)?;
+            let mut next = Vec::with_capacity(nodes.len() * children);
+            for node in &nodes {
+                match node {
+                    Some(node) => {
+                        next.extend_from_slice(&node.checked_borrow()?.children[..children])
+                    }
+                    None => next.extend(vec![None; children]),
+                }
+            }
+            nodes = next;
+
+            for node in &nodes {
+                canopy.push(match node {
+                    Some(node) => match &node.checked_borrow()?.node_type {
+                        NodeType::Leaf(value) => self.config.leaf_hash(hasher, value)?,
+                        NodeType::Inner(hash) => *hash,
+                    },
+                    None => empty,
+                });
+            }
+        }
+
+        Ok(canopy)
     }
 
-    /// Get the root hash of the tree
-    pub fn root_hash(&mut self) -> Result<InnerHash, PoseidonMerkleError> {
-        self.root.borrow().compute_hash(&mut self.hasher)
+    /// Generate the proof of every path, like `generate_proof` for each
+    pub fn generate_proofs(
+        &self,
+        merkle_paths: &[MerklePath<F>],
+    ) -> Result<Vec<MerkleProof<F>>, PoseidonMerkleError<F>> {
+        merkle_paths
+            .iter()
+            .map(|merkle_path| self.generate_proof(merkle_path))
+            .collect()
     }
 
-    /// Insert a value at a given path
+    /// Generate a single proof of the leaves at several paths, see `MultiProof`
     ///
-    /// This function traverses down the tree following the path specified by the merkle_path,
-    /// creates nodes as needed, inserts the value at the leaf level, and then updates
-    /// hashes from bottom to top.
-    pub fn insert_at_path(
-        &mut self,
-        merkle_path: &MerklePath,
-        value: &Fr,
-    ) -> Result<(), PoseidonMerkleError> {
-        // Store nodes that need hash recalculation in reverse order (bottom-up)
-        let mut nodes_to_update: Vec<Rc<RefCell<Node<Poseidon<Fr>>>>> =
-            Vec::with_capacity(self.depth);
-
-        // Traverse down the tree, creating nodes as needed
-        let mut current_node = self.root.clone();
+    /// Repeated paths are proven once. Like `generate_proof`, every path must hold a leaf.
+    pub fn generate_multiproof(
+        &self,
+        merkle_paths: &[MerklePath<F>],
+    ) -> Result<MultiProof<F>, PoseidonMerkleError<F>> {
+        let _span = operation_span!(
+            "generate_multiproof",
+            depth = self.depth,
+            paths = merkle_paths.len()
+        );
+        let root_hash = self.root()?;
+        let mut proof = MultiProof {
+            leaves: Vec::new(),
+            hashes: Vec::new(),
+            root_hash,
+            depth: self.depth,
+            leaf_hashing: self.config.leaf_hashing,
+            hash_family: self.config.family,
+            arity: self.config.arity,
+            scheme: self.config.scheme,
+            params_fingerprint: Some(self.params_fingerprint),
+            bit_order: self.bit_order,
+        };
 
-        // For each level in the tree (except leaf level)
-        for level in 0..self.depth {
-            // Determine direction based on the current bit in the path
-            let go_right = Self::get_path_bit(merkle_path, level);
+        // The leaves and the siblings of their paths, both keyed by the child indices from the root
+        let per_level = self.config.arity.children() - 1;
+        let mut leaves = BTreeMap::new();
+        let mut siblings = BTreeMap::new();
+        for merkle_path in merkle_paths {
+            let merkle_path = self.canonicalize_path(merkle_path);
+            let value = self
+                .try_get_value(&merkle_path)?
+                .ok_or(PoseidonMerkleError::LeafNotFound { path: merkle_path })?;
+            let digits = proof.digits(&merkle_path);
+            let path_siblings = self.path_siblings(&merkle_path)?;
+            for (level, level_siblings) in path_siblings.chunks(per_level).enumerate() {
+                let mut prefix = digits[..=level].to_vec();
+                let other_children = (0..=per_level).filter(|child| *child != digits[level]);
+                for (child, sibling) in other_children.zip(level_siblings) {
+                    prefix[level] = child;
+                    siblings.insert(prefix.clone(), *sibling);
+                }
+            }
+            leaves.insert(digits, (merkle_path, value));
+        }
 
-            // Get or create the next node
-            let next_node = {
-                let mut current_ref = current_node.borrow_mut();
-
-                // Get or create the appropriate child node
-                if go_right {
-                    // Create right child if it doesn't exist
-                    if current_ref.right.is_none() {
-                        // Use inner nodes for all but the last level
-                        let new_node = if level == self.depth - 1 {
-                            Node::new_borrowed_leaf(*value)
-                        } else {
-                            Node::new_borrowed_empty_inner()
-                        };
-                        current_ref.right = Some(new_node);
-                    } else if level == self.depth - 1 {
-                        // If we're at leaf level and the node exists, update its value
-                        let leaf_node = current_ref.right.as_ref().unwrap().clone();
-                        leaf_node.borrow_mut().node_type = NodeType::Leaf(*value);
-                    }
-                    current_ref.right.as_ref().unwrap().clone()
-                } else {
-                    // Create left child if it doesn't exist
-                    if current_ref.left.is_none() {
-                        // Use inner nodes for all but the last level
-                        let new_node = if level == self.depth - 1 {
-                            Node::new_borrowed_leaf(*value)
-                        } else {
-                            Node::new_borrowed_empty_inner()
-                        };
-                        current_ref.left = Some(new_node);
-                    } else if level == self.depth - 1 {
-                        // If we're at leaf level and the node exists, update its value
-                        let leaf_node = current_ref.left.as_ref().unwrap().clone();
-                        leaf_node.borrow_mut().node_type = NodeType::Leaf(*value);
+        // Walk up the levels like `MultiProof::verify`, giving the children no leaf leads to
+        let mut nodes: BTreeSet<Vec<usize>> = leaves.keys().cloned().collect();
+        for _ in 0..self.depth {
+            let mut parents = BTreeSet::new();
+            for digits in &nodes {
+                parents.insert(digits[..digits.len() - 1].to_vec());
+            }
+            for prefix in &parents {
+                for child in 0..=per_level {
+                    let mut digits = prefix.clone();
+                    digits.push(child);
+                    if !nodes.contains(&digits) {
+                        proof.hashes.push(siblings[&digits]);
                     }
-                    current_ref.left.as_ref().unwrap().clone()
                 }
-            };
+            }
+            nodes = parents;
+        }
+        proof.leaves = leaves.into_values().collect();
 
-            // Add current node to the update list (we'll recalculate its hash later)
-            nodes_to_update.push(current_node.clone());
+        Ok(proof)
+    }
 
-            // Move to the next node
-            current_node = next_node;
+    /// Generate a proof for a given path, carrying the leaf values if `include_preimage` is set
+    ///
+    /// Without the preimage the proof only reveals the commitment of the values. Trees of
+    /// single-value leaves have no preimage to include, the leaf value is the value itself.
+    pub fn generate_values_proof(
+        &self,
+        merkle_path: &MerklePath<F>,
+        include_preimage: bool,
+    ) -> Result<MerkleProof<F>, PoseidonMerkleError<F>> {
+        let proof = self.generate_proof(merkle_path)?;
+        if !include_preimage || self.leaf_width == 1 {
+            return Ok(proof);
         }
 
-        // Update hashes bottom-up
-        for node in nodes_to_update.iter().rev() {
-            let mut node_ref = node.borrow_mut();
-            node_ref.recalculate_hash(&mut self.hasher)?;
+        let merkle_path = proof.merkle_path;
+        let values = self
+            .get_values(&merkle_path)?
+            .ok_or(PoseidonMerkleError::LeafNotFound { path: merkle_path })?;
+
+        Ok(proof.with_leaf_preimage(values))
+    }
+
+    /// Verify a proof against the current root of the tree
+    ///
+    /// The proof must come from a tree of the same hash family, hashing scheme, arity and bit order
+    /// and have the siblings of every level of the tree, its root is compared with the fresh root
+    /// of the tree and it is folded with the tree hasher.
+    pub fn verify_proof(&self, proof: &MerkleProof<F>) -> Result<bool, PoseidonMerkleError<F>> {
+        #[cfg(feature = "hash-trace")]
+        let _trace = self.trace.enter();
+        self.check_proof_conventions(proof)?;
+
+        if proof.root_hash != self.root()? {
+            telemetry::record_verification(false);
+            return Ok(false);
         }
 
-        Ok(())
+        proof.verify_proof(&mut *self.hasher.checked_borrow_mut()?)
     }
 
-    /// Delete a value at a given path by inserting a zero value at given path
-    pub fn delete_at_path(&mut self, merkle_path: &MerklePath) -> Result<(), PoseidonMerkleError> {
-        let zero = Fr::ZERO;
-        self.insert_at_path(merkle_path, &zero)?;
+    /// Verify a non-membership proof against the current root and empty leaf of the tree
+    ///
+    /// The proof is checked like in `verify_proof`, and must prove the empty leaf of the tree
+    /// rather than the one it claims.
+    pub fn verify_non_membership_proof(
+        &self,
+        proof: &NonMembershipProof<F>,
+    ) -> Result<bool, PoseidonMerkleError<F>> {
+        #[cfg(feature = "hash-trace")]
+        let _trace = self.trace.enter();
+        self.check_proof_conventions(&proof.proof)?;
+
+        if proof.proof.root_hash != self.root()? {
+            telemetry::record_verification(false);
+            return Ok(false);
+        }
+
+        proof.verify_proof_with_empty_leaf(
+            &mut *self.hasher.checked_borrow_mut()?,
+            &self.config.empty_leaf,
+        )
+    }
+
+    /// Reject a proof of another hash family, scheme, arity, bit order, hasher or depth
+    fn check_proof_conventions(
+        &self,
+        proof: &MerkleProof<F>,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        if proof.hash_family != self.config.family {
+            return Err(ProofError::HashFamilyMismatch {
+                expected: proof.hash_family,
+                actual: self.config.family,
+            }
+            .into());
+        }
+        if proof.scheme != self.config.scheme {
+            return Err(ProofError::SchemeMismatch {
+                expected: proof.scheme,
+                actual: self.config.scheme,
+            }
+            .into());
+        }
+        if proof.arity != self.config.arity {
+            return Err(ProofError::ArityMismatch {
+                expected: proof.arity,
+                actual: self.config.arity,
+            }
+            .into());
+        }
+        if proof.bit_order != self.bit_order {
+            return Err(ProofError::BitOrderMismatch {
+                expected: proof.bit_order,
+                actual: self.bit_order,
+            }
+            .into());
+        }
+        if proof
+            .params_fingerprint
+            .is_some_and(|fingerprint| fingerprint != self.params_fingerprint)
+        {
+            return Err(ProofError::ParamsFingerprintMismatch.into());
+        }
+        if let Some(preimage) = &proof.leaf_preimage {
+            if preimage.len() != self.leaf_width {
+                return Err(PoseidonMerkleError::LeafWidthMismatch {
+                    expected: self.leaf_width,
+                    actual: preimage.len(),
+                });
+            }
+        }
+        if proof.siblings.len() != self.depth * (self.config.arity.children() - 1) {
+            return Err(ProofError::DepthMismatch {
+                expected: self.depth,
+                actual: proof.depth(),
+            }
+            .into());
+        }
 
         Ok(())
     }
 
-    /// Get the path hash from a list of bits
-    pub fn get_merkle_path(&self, path: &[bool]) -> Result<MerklePath, PoseidonMerkleError> {
-        let path_bits = BigInt::from_bits_le(path);
-        let merkle_path =
-            Fr::from_bigint(path_bits).ok_or(PoseidonMerkleError::InvalidBitsPathHash)?;
-        Ok(merkle_path)
+    /// Recompute the root hash of the whole tree from scratch
+    #[deprecated(
+        since = "0.4.0",
+        note = "recomputes every node on each call, use `root()` instead"
+    )]
+    pub fn root_hash(&self) -> Result<InnerHash<F>, PoseidonMerkleError<F>> {
+        let hasher = &mut *self.hasher.checked_borrow_mut()?;
+        self.root
+            .checked_borrow()?
+            .compute_hash_with(hasher, &self.config, 0)
     }
 
     /// Check if the tree is empty lazily o(1)
-    pub fn is_empty(&self) -> bool {
-        let root = self.root.borrow();
-        let empty_hash = get_empty_inner_hash();
+    pub fn is_empty(&self) -> Result<bool, PoseidonMerkleError<F>> {
+        let root = self.root.checked_borrow()?;
+        let empty_hash = &self.config.empty_inner_hash_at(0);
 
-        root.node_type.hash().unwrap_or(empty_hash).eq(empty_hash)
+        Ok(root.node_type.hash().unwrap_or(empty_hash).eq(empty_hash))
     }
 
     /// Clear the tree by resetting the root to a new empty node
     ///
-    /// Since we're using RC, children will be automatically cleared
+    /// Since we're using RC, children will be automatically cleared. The changelog, if any, is
+    /// emptied too, its entries no longer lead to the roots of the tree.
     pub fn clear(&mut self) {
-        self.root = Node::new_borrowed_empty_inner();
+        self.root = Node::new_borrowed_inner(self.config.empty_inner_hash_at(0));
+        self.nodes = 1;
+        #[cfg(feature = "metrics")]
+        {
+            self.leaves = 0;
+            telemetry::record_leaves(0);
+        }
+        if let Some(changelog) = self.changelog.as_mut() {
+            changelog.clear();
+        }
+        self.observer.notify(|observer| observer.on_clear());
+    }
+
+    /// Tell `observer` of every mutation from now on, replacing the previous one
+    ///
+    /// `insert_at_path`, `insert_values` and `insert_by_key` report `on_insert`, `delete_at_path`
+    /// reports `on_delete` and `clear` reports `on_clear`, each once the tree is updated. A clone
+    /// of the tree starts without an observer.
+    pub fn set_observer(&mut self, observer: BoxedObserver<F>) {
+        self.observer.replace(Some(observer));
+    }
+
+    /// Stop observing the tree, returning the observer if there was one
+    pub fn take_observer(&mut self) -> Option<BoxedObserver<F>> {
+        self.observer.replace(None)
+    }
+
+    /// Report every inner node hash from now on to `sink`, replacing the previous one
+    ///
+    /// Inserts and deletes, `root()` and `verify_proof` report the hashes they compute, in order.
+    /// A clone of the tree starts without a sink.
+    #[cfg(feature = "hash-trace")]
+    pub fn set_trace_sink(&mut self, sink: BoxedTraceSink<F>) {
+        self.trace.replace(Some(sink));
+    }
+
+    /// Stop tracing the tree, returning the sink if there was one
+    #[cfg(feature = "hash-trace")]
+    pub fn take_trace_sink(&mut self) -> Option<BoxedTraceSink<F>> {
+        self.trace.replace(None)
+    }
+
+    /// Recompute every hash of the tree from the leaves up and compare it with the cached one
+    ///
+    /// This is a slow audit meant to catch bugs in incremental hashing or corrupted nodes. Each
+    /// node is checked against the hash recomputed from its subtree, so a single corrupted node
+    /// yields a single violation. Missing children count as the empty leaf at the last level and
+    /// the empty inner hash of their level above it, and dirty nodes are not reported since they are known stale.
+    ///
+    /// The mismatches are a `PoseidonMerkleError::IntegrityViolations`. A node borrowed elsewhere
+    /// fails with `PoseidonMerkleError::ConcurrentAccess` and a failing hasher with its error.
+    pub fn verify_integrity(&self) -> Result<(), PoseidonMerkleError<F>> {
+        let mut hasher = self.hasher.checked_borrow_mut()?;
+        let mut audit = IntegrityAudit {
+            depth: self.depth,
+            config: self.config.clone(),
+            hasher: &mut *hasher,
+            path: Vec::new(),
+            violations: Vec::new(),
+        };
+        audit.visit(&self.root, 0)?;
+
+        if audit.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(PoseidonMerkleError::IntegrityViolations(audit.violations))
+        }
+    }
+
+    /// The summary `Debug` of the tree with its hashes in `format`, for `{:?}` and `{:#?}`
+    pub fn debug_with(&self, format: FrFormat) -> TreeDebug<'_, H, F, M> {
+        TreeDebug { tree: self, format }
+    }
+}
+
+impl SparseMerkleTree<Poseidon<Fr>> {
+    /// Create a new (lazy) sparse poseidon merkle tree hashing with caller-supplied parameters
+    ///
+    /// The arity follows the width of the parameters, 3, 5 or 9 for 2, 4 or 8 children. See
+    /// `new_with_custom_hasher` for how the tree handles non-circom parameters.
+    pub fn new_with_parameters(
+        depth: usize,
+        parameters: PoseidonParameters<Fr>,
+    ) -> Result<Self, PoseidonMerkleError> {
+        let arity = match parameters.width {
+            3 => Arity::Binary,
+            5 => Arity::Quaternary,
+            9 => Arity::Octal,
+            width => return Err(PoseidonMerkleError::UnsupportedWidth { width }),
+        };
+
+        Self::new_with_custom_hasher(depth, arity, Poseidon::new(parameters))
+    }
+
+    /// Create a new (lazy) sparse poseidon merkle tree given a depth
+    ///
+    /// Its empty subtrees are folded, so the root of the new tree is `get_empty_root(depth)`, the
+    /// one circomlibjs and other sparse Merkle trees have, and deleting every leaf inserted since
+    /// brings it back. Trees built before with flat empty subtrees keep their roots chained with
+    /// `with_empty_subtrees(EmptySubtrees::Flat)`.
+    pub fn new(depth: usize) -> Result<Self, PoseidonMerkleError> {
+        let mut poseidon = Poseidon::<Fr>::new_circom(2)
+            .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))?;
+        validate_constants_once(&mut poseidon)?;
+        Self::new_with_hasher(depth, poseidon)
+    }
+
+    /// Create a tree of `DEFAULT_DEPTH` with the default hasher, without panicking
+    pub fn try_default() -> Result<Self, PoseidonMerkleError> {
+        Self::new(DEFAULT_DEPTH)
+    }
+
+    /// Get the bit at the given position
+    ///
+    /// [true, false] -> [1, 0]
+    pub fn get_path_bit(merkle_path: &MerklePath, position: usize) -> bool {
+        path_bit(merkle_path, position)
+    }
+}
+
+#[cfg(feature = "poseidon2")]
+impl SparseMerkleTree<Poseidon2Hasher> {
+    /// Create a new (lazy) sparse merkle tree hashing its inner nodes with Poseidon2
+    pub fn new_poseidon2(depth: usize) -> Result<Self, PoseidonMerkleError> {
+        Self::new_with_hasher(depth, Poseidon2Hasher::new())
+    }
+}
+
+#[cfg(feature = "mimc")]
+impl SparseMerkleTree<MimcHasher> {
+    /// Create a new (lazy) sparse merkle tree hashing its inner nodes with MiMC-7
+    pub fn new_mimc(depth: usize) -> Result<Self, PoseidonMerkleError> {
+        Self::new_with_hasher(depth, MimcHasher::new())
+    }
+}
+
+#[cfg(feature = "rescue")]
+impl SparseMerkleTree<RescueHasher> {
+    /// Create a new (lazy) sparse merkle tree hashing its inner nodes with Rescue-Prime
+    pub fn new_rescue(depth: usize) -> Result<Self, PoseidonMerkleError> {
+        Self::new_with_hasher(depth, RescueHasher::new())
+    }
+}
+
+#[cfg(feature = "solana")]
+impl SparseMerkleTree<SolanaPoseidonHasher> {
+    /// Create a new (lazy) sparse poseidon merkle tree hashing through the `sol_poseidon` syscall
+    ///
+    /// Its roots are the ones of `new`. Unlike `new`, the constants aren't validated with
+    /// light_poseidon, which would cost more compute units than a program can spend.
+    pub fn new_solana(depth: usize) -> Result<Self, PoseidonMerkleError> {
+        Self::new_with_hasher(depth, SolanaPoseidonHasher::new())
     }
 }
 
 impl Default for SparseMerkleTree<Poseidon<Fr>> {
+    /// Panics if the default tree can't be built, use `try_default()` to handle the error instead
     fn default() -> Self {
-        let poseidon =
-            Poseidon::<Fr>::new_circom(2).expect("Failed to create default Poseidon hasher");
-        Self::new_with_hasher(20, poseidon).expect("Failed to create default SparseMerkleTree")
+        Self::try_default().unwrap_or_else(|err| {
+            panic!("failed to create the default SparseMerkleTree (see try_default): {err}")
+        })
+    }
+}
+
+/// The `Debug` of a tree with its field elements in a given format, see
+/// `SparseMerkleTree::debug_with`
+pub struct TreeDebug<'a, H: MerkleHasher<F>, F: PrimeField, M = ()> {
+    tree: &'a SparseMerkleTree<H, F, M>,
+    format: FrFormat,
+}
+
+/// The root hash shortened, the depth and the number of non-empty leaves
+///
+/// Counting the leaves walks the tree once, but nothing else of it is printed. The alternate form,
+/// `{:#?}`, adds the root node, see the `Debug` of `Node`. The root hash is the cached one, stale
+/// until `root()` refreshes it.
+impl<H: MerkleHasher<F>, F: PrimeField, M> fmt::Debug for TreeDebug<'_, H, F, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let TreeDebug { tree, format } = self;
+        let expand = f.alternate();
+        let mut debug = f.debug_struct("SparseMerkleTree");
+        // Like the `Debug` of a `RefCell`, what a node borrowed elsewhere hides is `<borrowed>`
+        match tree.root.checked_borrow() {
+            Ok(root) => debug.field(
+                "root",
+                &format_args!("{}", format.format(root.node_type.data())),
+            ),
+            Err(_) => debug.field("root", &format_args!("<borrowed>")),
+        };
+        debug.field("depth", &tree.depth);
+        match tree.stats() {
+            Ok(stats) => debug.field("leaves", &stats.non_empty_leaves),
+            Err(_) => debug.field("leaves", &format_args!("<borrowed>")),
+        };
+        if expand {
+            debug.field("root_node", &NodeSummary(&tree.root, *format));
+        }
+        debug.finish()
+    }
+}
+
+/// The `Debug` of `debug_with` in the `FrFormat::ShortDecimal` format
+impl<H: MerkleHasher<F>, F: PrimeField, M> fmt::Debug for SparseMerkleTree<H, F, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.debug_with(FrFormat::default()).fmt(f)
     }
 }
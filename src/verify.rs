@@ -0,0 +1,239 @@
+//! Stateless hashing helpers for verifiers that don't need the tree
+//!
+//! These functions only depend on a `MerkleHasher`, not on nodes or `SparseMerkleTree`, so a
+//! verifier can recompute a root from a leaf, its path and its siblings. `MerkleProof` folds its
+//! siblings with them.
+
+use alloc::{vec, vec::Vec};
+
+use ark_ff::PrimeField;
+
+use crate::{
+    Arity, HashFamily, HashOperation, HashingScheme, MerkleHasher, PoseidonMerkleError, ProofError,
+    ProofNode,
+};
+
+pub use crate::constants::zero_hash;
+
+/// Hash two sibling nodes into their parent
+pub fn hash_pair<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    left: &F,
+    right: &F,
+) -> Result<F, PoseidonMerkleError<F>> {
+    hasher
+        .hash_two(left, right)
+        .map_err(PoseidonMerkleError::hasher(HashOperation::InnerNode {
+            level: None,
+        }))
+}
+
+/// Recompute the root of a binary tree from the hash a leaf contributes to its parent
+///
+/// `path_bits[level]` is the direction taken at `level`, the root being level 0 and `true` meaning
+/// right, and `siblings` has one sibling per level from the root down. Missing path bits are
+/// `false`, as for the bits of a `MerklePath`.
+pub fn fold_path<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    leaf: &F,
+    path_bits: &[bool],
+    siblings: &[F],
+) -> Result<F, PoseidonMerkleError<F>> {
+    fold_path_with_arity(hasher, leaf, path_bits, siblings, Arity::Binary)
+}
+
+/// Like `fold_path` for inner nodes of `arity` children
+///
+/// Each level consumes `arity.bits()` path bits, read little-endian into the index of the child,
+/// and `arity - 1` siblings, the other children in index order.
+pub fn fold_path_with_arity<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    leaf: &F,
+    path_bits: &[bool],
+    siblings: &[F],
+    arity: Arity,
+) -> Result<F, PoseidonMerkleError<F>> {
+    fold_path_with_scheme(
+        hasher,
+        leaf,
+        path_bits,
+        siblings,
+        arity,
+        HashingScheme::Legacy,
+        HashFamily::Custom,
+    )
+}
+
+/// Like `fold_path_with_arity` for a tree of `family` hashing its inner nodes under `scheme`
+///
+/// Only `HashingScheme::LevelBound` changes the inner hashes, a Poseidon tree over bn254 then
+/// hashes them with the circom parameters of one more input instead of `hasher`.
+pub fn fold_path_with_scheme<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    leaf: &F,
+    path_bits: &[bool],
+    siblings: &[F],
+    arity: Arity,
+    scheme: HashingScheme,
+    family: HashFamily,
+) -> Result<F, PoseidonMerkleError<F>> {
+    let per_level = arity.children() - 1;
+    if !siblings.len().is_multiple_of(per_level) {
+        return Err(ProofError::UnevenSiblings {
+            len: siblings.len(),
+            per_level,
+        }
+        .into());
+    }
+
+    // Fold from the leaf up to the root, putting the running hash back at its position
+    let mut current_hash = *leaf;
+    for (level, level_siblings) in siblings.chunks(per_level).enumerate().rev() {
+        let position: usize = (0..arity.bits())
+            .filter(|bit| path_bits.get(level * arity.bits() + bit) == Some(&true))
+            .map(|bit| 1 << bit)
+            .sum();
+        let mut inputs = level_siblings.to_vec();
+        inputs.insert(position, current_hash);
+
+        current_hash = scheme
+            .inner_hash(hasher, family, level, &inputs)
+            .map_err(|error| match error {
+                PoseidonMerkleError::HasherError { source, .. } => {
+                    PoseidonMerkleError::HasherError {
+                        operation: HashOperation::ProofVerification { level },
+                        source,
+                    }
+                }
+                error => error,
+            })?;
+    }
+
+    Ok(current_hash)
+}
+
+/// Recompute the root of a binary tree from a leaf contribution and its direction-tagged
+/// siblings, from the leaf up
+///
+/// Each node says on which side of the running hash its sibling goes, so no path is needed, see
+/// `MerkleProof::nodes`.
+pub fn fold_nodes<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    leaf: &F,
+    nodes: &[ProofNode<F>],
+) -> Result<F, PoseidonMerkleError<F>> {
+    fold_nodes_with_scheme(
+        hasher,
+        leaf,
+        nodes,
+        Arity::Binary,
+        HashingScheme::Legacy,
+        HashFamily::Custom,
+    )
+}
+
+/// Like `fold_nodes` for a tree of `arity` children hashing its inner nodes under `scheme`
+///
+/// Each level takes `arity - 1` nodes in child index order, the left siblings before the right
+/// ones, and the running hash goes between them. A left sibling after a right one fails with
+/// `ProofError::InterleavedNodes`.
+pub fn fold_nodes_with_scheme<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    leaf: &F,
+    nodes: &[ProofNode<F>],
+    arity: Arity,
+    scheme: HashingScheme,
+    family: HashFamily,
+) -> Result<F, PoseidonMerkleError<F>> {
+    let per_level = arity.children() - 1;
+    if !nodes.len().is_multiple_of(per_level) {
+        return Err(ProofError::UnevenSiblings {
+            len: nodes.len(),
+            per_level,
+        }
+        .into());
+    }
+
+    let depth = nodes.len() / per_level;
+    let mut current_hash = *leaf;
+    for (height, level_nodes) in nodes.chunks(per_level).enumerate() {
+        let level = depth - 1 - height;
+        let position = level_nodes.iter().take_while(|node| node.is_left()).count();
+        if level_nodes[position..].iter().any(ProofNode::is_left) {
+            return Err(ProofError::InterleavedNodes { level }.into());
+        }
+        let mut inputs: Vec<F> = level_nodes.iter().map(|node| *node.hash()).collect();
+        inputs.insert(position, current_hash);
+
+        current_hash = scheme
+            .inner_hash(hasher, family, level, &inputs)
+            .map_err(|error| match error {
+                PoseidonMerkleError::HasherError { source, .. } => {
+                    PoseidonMerkleError::HasherError {
+                        operation: HashOperation::ProofVerification { level },
+                        source,
+                    }
+                }
+                error => error,
+            })?;
+    }
+
+    Ok(current_hash)
+}
+
+/// Bytes of a key packed in each field element by `hash_key`, 31 for bn254
+pub fn key_chunk_bytes<F: PrimeField>() -> usize {
+    (F::MODULUS_BIT_SIZE as usize - 1) / 8
+}
+
+/// Hash a byte key into a field element, the path `SparseMerkleTree::path_for_key` truncates
+///
+/// The key is split into chunks of `key_chunk_bytes` bytes, the last one possibly shorter, each
+/// read as a big-endian integer. The inputs are the key length in bytes followed by the chunks,
+/// folded with inner node hashes of `arity` children: the running hash starts as the length and
+/// each step hashes it with the next `arity - 1` chunks, zero-padded. A key of at most one chunk
+/// in a binary tree hashes to hasher(len, chunk), an empty key to hasher(0, 0).
+///
+/// The length prefix makes the inputs distinct for distinct keys, including keys only differing
+/// by leading or trailing zero bytes.
+pub fn hash_key<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    key: &[u8],
+    arity: Arity,
+) -> Result<F, PoseidonMerkleError<F>> {
+    let chunks: Vec<F> = key
+        .chunks(key_chunk_bytes::<F>())
+        .map(F::from_be_bytes_mod_order)
+        .collect();
+
+    let per_step = arity.children() - 1;
+    let steps = chunks.len().div_ceil(per_step).max(1);
+    let mut current_hash = F::from(key.len() as u64);
+    for step in 0..steps {
+        let mut inputs = vec![F::zero(); arity.children()];
+        inputs[0] = current_hash;
+        for (input, chunk) in inputs[1..]
+            .iter_mut()
+            .zip(chunks.iter().skip(step * per_step))
+        {
+            *input = *chunk;
+        }
+
+        current_hash = hasher
+            .hash_children(&inputs)
+            .map_err(PoseidonMerkleError::hasher(HashOperation::KeyHash))?;
+    }
+
+    Ok(current_hash)
+}
+
+/// Check that a leaf contribution, its path and its siblings fold into `root`
+pub fn verify<F: PrimeField, H: MerkleHasher<F> + ?Sized>(
+    hasher: &mut H,
+    root: &F,
+    leaf: &F,
+    path_bits: &[bool],
+    siblings: &[F],
+) -> Result<bool, PoseidonMerkleError<F>> {
+    Ok(fold_path(hasher, leaf, path_bits, siblings)? == *root)
+}
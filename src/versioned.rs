@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, Weak},
+};
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+
+use crate::{
+    frozen::{count_nodes, FrozenNode},
+    FrozenView, MerkleHasher, PoseidonMerkleError, SparseMerkleTree,
+};
+
+/// The committed versions of a `VersionedTree`
+struct Versions<F: PrimeField> {
+    /// The last committed version, kept whether anyone holds it or not
+    latest: FrozenView<F>,
+    number: u64,
+    /// The earlier versions, until the last view of each is dropped
+    earlier: BTreeMap<u64, Weak<FrozenNode<F>>>,
+}
+
+/// A tree whose committed versions stay readable while a writer prepares the next one
+///
+/// A writer takes the tree with `begin_version`, updates it and commits, publishing an immutable
+/// `FrozenView` that shares the nodes it didn't change with the previous version. Readers take a
+/// view with `latest` or `version` and never wait for the writer, only for the moment a commit
+/// publishes its view. A version is kept until the last view of it is dropped, the latest one
+/// always.
+pub struct VersionedTree<H: MerkleHasher<F>, F: PrimeField = Fr> {
+    tree: Mutex<SparseMerkleTree<H, F>>,
+    versions: RwLock<Versions<F>>,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> VersionedTree<H, F> {
+    /// Publish the tree as it is as version 0
+    pub fn new(tree: SparseMerkleTree<H, F>) -> Result<Self, PoseidonMerkleError<F>> {
+        let latest = tree.freeze()?;
+        Ok(VersionedTree {
+            tree: Mutex::new(tree),
+            versions: RwLock::new(Versions {
+                latest,
+                number: 0,
+                earlier: BTreeMap::new(),
+            }),
+        })
+    }
+
+    /// Take the tree to prepare the next version, other writers wait until the guard is dropped
+    ///
+    /// Updates made through a guard dropped without `WriteGuard::commit` stay in the tree and are
+    /// published by the next commit.
+    pub fn begin_version(&self) -> WriteGuard<'_, H, F> {
+        WriteGuard {
+            versions: &self.versions,
+            tree: self.tree.lock().unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+
+    /// The last committed version
+    pub fn latest(&self) -> FrozenView<F> {
+        self.versions().latest.clone()
+    }
+
+    pub fn latest_version(&self) -> u64 {
+        self.versions().number
+    }
+
+    /// A committed version, `None` if it wasn't committed yet or nobody held it any longer
+    pub fn version(&self, number: u64) -> Option<FrozenView<F>> {
+        let versions = self.versions();
+        if number == versions.number {
+            return Some(versions.latest.clone());
+        }
+        let root = versions.earlier.get(&number)?.upgrade()?;
+
+        Some(versions.latest.with_root_node(root))
+    }
+
+    /// The versions that can still be read, oldest first
+    pub fn versions_held(&self) -> Vec<u64> {
+        let versions = self.versions();
+        versions
+            .earlier
+            .iter()
+            .filter(|(_, root)| root.strong_count() > 0)
+            .map(|(number, _)| *number)
+            .chain([versions.number])
+            .collect()
+    }
+
+    /// The number of nodes kept by the versions that can still be read, shared nodes counted once
+    pub fn node_count(&self) -> usize {
+        let versions = self.versions();
+        let earlier: Vec<_> = versions
+            .earlier
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        count_nodes(earlier.iter().chain([versions.latest.root_node()]))
+    }
+
+    pub fn into_inner(self) -> SparseMerkleTree<H, F> {
+        self.tree
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn versions(&self) -> RwLockReadGuard<'_, Versions<F>> {
+        self.versions.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Exclusive access to the tree of a `VersionedTree`, see `VersionedTree::begin_version`
+pub struct WriteGuard<'a, H: MerkleHasher<F>, F: PrimeField = Fr> {
+    versions: &'a RwLock<Versions<F>>,
+    tree: MutexGuard<'a, SparseMerkleTree<H, F>>,
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> WriteGuard<'_, H, F> {
+    /// Publish the tree as the next version and return its number
+    ///
+    /// The tree is hashed and frozen before readers are held up, for the time of swapping the
+    /// latest version. A failing hasher publishes nothing and leaves the updates in the tree.
+    pub fn commit(self) -> Result<u64, PoseidonMerkleError<F>> {
+        let previous = self
+            .versions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .latest
+            .clone();
+        let view = self.tree.freeze_onto(Some(&previous))?;
+
+        let mut versions = self
+            .versions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let previous = core::mem::replace(&mut versions.latest, view);
+        let number = versions.number;
+        versions
+            .earlier
+            .insert(number, Arc::downgrade(previous.root_node()));
+        versions.earlier.retain(|_, root| root.strong_count() > 0);
+        versions.number += 1;
+
+        Ok(versions.number)
+    }
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> Deref for WriteGuard<'_, H, F> {
+    type Target = SparseMerkleTree<H, F>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tree
+    }
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> DerefMut for WriteGuard<'_, H, F> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tree
+    }
+}
@@ -1,97 +1,591 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
 
+use crate::{
+    shared::CheckedBorrow,
+    walk::{leaf_index, walk, Visited},
+    FrFormat, MerkleHasher, MerklePath, MerkleProof, NodeType, PoseidonMerkleError,
+    SparseMerkleTree,
+};
 use ark_bn254::Fr;
-use light_poseidon::Poseidon;
+use ark_ff::PrimeField;
 
-use crate::{Node, NodeType, SparseMerkleTree};
+/// What `Visualizer::visualize_with` leaves out or marks
+#[cfg(feature = "visualize")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VisualizeOptions<F: PrimeField = Fr> {
+    /// Deepest level expanded, inner nodes at this level are summarized by their hash and the
+    /// number of non-empty leaves below them
+    pub max_depth: Option<usize>,
+    /// Path whose nodes are marked with `◀`
+    pub highlight_path: Option<MerklePath<F>>,
+    /// Leave out empty subtrees and empty leaves instead of printing them as empty
+    pub skip_empty_subtrees: bool,
+    /// How hashes and values are written
+    pub fr_format: FrFormat,
+}
 
 /// Trait for tree visualization
 #[cfg(feature = "visualize")]
-pub trait Visualizer {
+pub trait Visualizer<F: PrimeField = Fr> {
+    /// Write the tree structure to `w` as `opts` asks, one line per node
+    fn visualize_with<W: Write>(&self, opts: &VisualizeOptions<F>, w: &mut W) -> io::Result<()>;
+
+    /// Write the tree with the path of a proof marked, then its siblings against the tree's
+    ///
+    /// Each sibling line gives the hash in the tree and the one in the proof, numbered by the
+    /// tree level the sibling sits at, one more than its `MerkleProof::level_siblings` level. The
+    /// first level where they differ is flagged, then the roots are compared. The tree is written
+    /// as `opts` asks, with the path of the proof highlighted whatever `highlight_path`.
+    fn visualize_proof_with<W: Write>(
+        &self,
+        proof: &MerkleProof<F>,
+        opts: &VisualizeOptions<F>,
+        w: &mut W,
+    ) -> io::Result<()>;
+
+    /// Write the tree with the path of a proof marked, see `visualize_proof_with`
+    fn visualize_proof<W: Write>(&self, proof: &MerkleProof<F>, w: &mut W) -> io::Result<()> {
+        self.visualize_proof_with(proof, &VisualizeOptions::default(), w)
+    }
+
+    /// Write the nodes that changed since `before` was taken, see `SparseMerkleTree::hash_snapshot`
+    ///
+    /// Each node is annotated with its old and new hash, or value for a leaf, nodes materialized
+    /// since are marked `new` and nodes gone since `pruned`. Unchanged subtrees are left out, so a
+    /// single update shows the path from the root to its leaf. Fails with
+    /// `io::ErrorKind::InvalidInput` for a snapshot of a tree of another depth or arity.
+    fn visualize_changes<W: Write>(&self, before: &HashSnapshot<F>, w: &mut W) -> io::Result<()>;
+
+    /// Write the leaf level in index order, `#` for a non-empty leaf and `.` for an empty one,
+    /// `width` leaves per row under a ruler of their indices
+    ///
+    /// Fails with `io::ErrorKind::InvalidInput` for a `width` of 0 or a tree of more than
+    /// `2^MAX_LEAF_ROW_BITS` leaves.
+    fn visualize_leaf_row_with<W: Write>(&self, width: usize, w: &mut W) -> io::Result<()>;
+
+    /// Write the leaf level in rows of `LEAF_ROW_WIDTH` leaves, see `visualize_leaf_row_with`
+    fn visualize_leaf_row<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.visualize_leaf_row_with(LEAF_ROW_WIDTH, w)
+    }
+
+    /// Write the whole tree structure to `w`, one line per node
+    fn visualize_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.visualize_with(&VisualizeOptions::default(), w)
+    }
+
+    /// The tree structure as `visualize_to` writes it
+    ///
+    /// Writing to a `String` only fails when a node is borrowed elsewhere.
+    fn visualize_string(&self) -> io::Result<String> {
+        let mut bytes = Vec::new();
+        self.visualize_to(&mut bytes)?;
+        Ok(String::from_utf8(bytes).expect("the visualization is UTF-8"))
+    }
+
     /// Visualize the tree structure to the console
-    fn visualize(&self);
+    fn visualize(&self) -> io::Result<()> {
+        print!("{}", self.visualize_string()?);
+        Ok(())
+    }
 }
 
 #[cfg(feature = "visualize")]
-impl Visualizer for SparseMerkleTree<Poseidon<Fr>> {
-    fn visualize(&self) {
-        println!("Sparse Merkle Tree Visualization (Depth: {})", self.depth);
-        println!("=======================================");
+impl<H: MerkleHasher<F>, F: PrimeField, M> Visualizer<F> for SparseMerkleTree<H, F, M> {
+    fn visualize_with<W: Write>(&self, opts: &VisualizeOptions<F>, w: &mut W) -> io::Result<()> {
+        render(self, opts, w)
+    }
+
+    fn visualize_proof_with<W: Write>(
+        &self,
+        proof: &MerkleProof<F>,
+        opts: &VisualizeOptions<F>,
+        w: &mut W,
+    ) -> io::Result<()> {
+        let opts = VisualizeOptions {
+            highlight_path: Some(proof.merkle_path),
+            ..opts.clone()
+        };
+        render(self, &opts, w)?;
+        let format = |fr: &F| opts.fr_format.format(fr);
+
+        writeln!(
+            w,
+            "Authentication Path (Path: {}, Leaf Value: {})",
+            format(&proof.merkle_path),
+            format(&proof.leaf_value)
+        )?;
+        writeln!(w, "=======================================")?;
+        if proof.arity != self.arity() || proof.depth() != self.depth {
+            return writeln!(
+                w,
+                "The proof is of depth {} and arity {}, the tree of depth {} and arity {}",
+                proof.depth(),
+                proof.arity.children(),
+                self.depth,
+                self.arity().children()
+            );
+        }
+
+        let siblings = self
+            .path_siblings(&self.canonicalize_path(&proof.merkle_path))
+            .map_err(io::Error::other)?;
+        let per_level = self.arity().children() - 1;
+        let mut diverged = None;
+        for (index, (actual, expected)) in siblings.iter().zip(&proof.siblings).enumerate() {
+            let level = index / per_level;
+            let flag = match (actual == expected, diverged) {
+                (true, _) => "",
+                (false, None) => {
+                    diverged = Some(level + 1);
+                    " ✗ first divergence"
+                }
+                (false, Some(_)) => " ✗",
+            };
+            writeln!(
+                w,
+                "{} (Sibling {}: tree {}, proof {}){}",
+                level + 1,
+                index % per_level,
+                format(actual),
+                format(expected),
+                flag
+            )?;
+        }
+
+        let root = self.cached_root_hash().map_err(io::Error::other)?;
+        writeln!(
+            w,
+            "0 (Root: tree {}, proof {}){}",
+            format(&root),
+            format(&proof.root_hash),
+            if root == proof.root_hash { "" } else { " ✗" }
+        )?;
+        match diverged {
+            Some(level) => writeln!(w, "Siblings diverge first at level {level}"),
+            None => writeln!(w, "All siblings match"),
+        }
+    }
+
+    fn visualize_changes<W: Write>(&self, before: &HashSnapshot<F>, w: &mut W) -> io::Result<()> {
+        if before.depth != self.depth || before.arity != self.arity().children() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the snapshot was taken of a tree of another shape",
+            ));
+        }
+
+        writeln!(w, "Sparse Merkle Tree Changes (Depth: {})", self.depth)?;
+        writeln!(w, "=======================================")?;
+        let root = self.root.checked_borrow()?.node_type.clone();
+        if before.nodes.get(&Vec::new()) == Some(&root) {
+            return writeln!(w, "No changes");
+        }
+
+        let format = |fr: &F| FrFormat::default().format(fr);
+        let hash = |node_type: Option<&NodeType<F>>| match node_type {
+            Some(NodeType::Leaf(value) | NodeType::Inner(value)) => format(value),
+            None => "none".to_string(),
+        };
+        let mut stack = vec![(Some(self.root.clone()), Vec::new(), vec![true])];
+        while let Some((node, digits, lasts)) = stack.pop() {
+            let level = digits.len();
+            let old = before.nodes.get(&digits);
+            let new = node
+                .as_ref()
+                .map(|node| Ok::<_, io::Error>(node.checked_borrow()?.node_type.clone()))
+                .transpose()?;
+            let is_leaf = matches!(new.as_ref().or(old), Some(NodeType::Leaf(_)));
+            let kind = match (is_leaf, level) {
+                (true, _) => "Leaf Value",
+                (false, 0) => "Root Node",
+                (false, _) => "Inner Node",
+            };
+            let mark = match (old, &new) {
+                (None, _) => " new",
+                (_, None) => " pruned",
+                _ => "",
+            };
+            let mut indent: String = lasts[..level]
+                .iter()
+                .take(MAX_INDENT_LEVELS)
+                .map(|is_last| if *is_last { "    " } else { "│   " })
+                .collect();
+            indent.push_str(if lasts[level] {
+                "└── "
+            } else {
+                "├── "
+            });
+            writeln!(
+                w,
+                "{}{} ({}: {} → {}{}){}",
+                indent,
+                level,
+                kind,
+                hash(old),
+                hash(new.as_ref()),
+                position(self, &digits, is_leaf),
+                mark
+            )?;
+
+            // The children that changed, pruned ones are not expanded
+            let Some(node) = node else { continue };
+            let node = node.checked_borrow()?;
+            if matches!(node.node_type, NodeType::Leaf(_)) {
+                continue;
+            }
+            let mut changed = Vec::new();
+            for index in 0..self.arity().children() {
+                let mut child_digits = digits.clone();
+                child_digits.push(index);
+                let child = node.children[index].clone();
+                let old = before.nodes.get(&child_digits);
+                let new = match &child {
+                    Some(child) => Some(child.checked_borrow()?.node_type.clone()),
+                    None => None,
+                };
+                if old != new.as_ref() {
+                    changed.push((child, child_digits));
+                }
+            }
+            // Push the last child first so that the first one is written first
+            let count = changed.len();
+            for (rank, (child, child_digits)) in changed.into_iter().enumerate().rev() {
+                let mut child_lasts = lasts.clone();
+                child_lasts.push(rank == count - 1);
+                stack.push((child, child_digits, child_lasts));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visualize_leaf_row_with<W: Write>(&self, width: usize, w: &mut W) -> io::Result<()> {
+        if width == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "rows hold at least one leaf",
+            ));
+        }
+        let bits = self.path_bits();
+        if bits > MAX_LEAF_ROW_BITS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("2^{bits} leaves are too many to draw, at most 2^{MAX_LEAF_ROW_BITS} are"),
+            ));
+        }
+
+        let mut occupied = vec![false; 1 << bits];
+        walk::<_, _, _, io::Error>(self, None, None, true, |visit| {
+            if let (Visited::Leaf(_), Some(index)) = (visit.visited, leaf_index(self, visit.digits))
+            {
+                occupied[index as usize] = true;
+            }
+            Ok(())
+        })?;
+
+        let count = occupied.iter().filter(|leaf| **leaf).count();
+        writeln!(
+            w,
+            "Leaf Occupancy (Depth: {}, {} of {} leaves)",
+            self.depth,
+            count,
+            occupied.len()
+        )?;
+        writeln!(w, "=======================================")?;
+        for (row, leaves) in occupied.chunks(width).enumerate() {
+            let start = row * width;
+            // A tick every LEAF_RULER_STEP indices, the index written from its column
+            let mut ruler = String::new();
+            for column in 0..leaves.len() {
+                if ruler.len() > column {
+                    continue;
+                }
+                let index = start + column;
+                if index.is_multiple_of(LEAF_RULER_STEP) {
+                    ruler.push_str(&index.to_string());
+                } else {
+                    ruler.push(' ');
+                }
+            }
+            writeln!(w, "{}", format!("      {ruler}").trim_end())?;
 
-        if self.is_empty() {
-            println!("Empty tree");
-            return;
+            let cells: String = leaves
+                .iter()
+                .map(|leaf| if *leaf { '#' } else { '.' })
+                .collect();
+            writeln!(w, "{start:>5} {cells}")?;
         }
 
-        // Recursively visualize from root
-        visualize_node(&self.root, self.depth, 0, "".to_string(), true);
+        Ok(())
     }
 }
 
-/// Shorten the Fr to a string like 12314..12314 (first 5 digits and last 5 digits)
+/// The hash, or value for a leaf, of every materialized node of a tree, to compare the tree with
+/// later, see `Visualizer::visualize_changes`
+///
+/// A clone of a tree shares its nodes and follows its updates, a snapshot doesn't.
+#[cfg(feature = "visualize")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashSnapshot<F: PrimeField = Fr> {
+    depth: usize,
+    arity: usize,
+    /// Nodes by the child index taken at each level from the root
+    nodes: BTreeMap<Vec<usize>, NodeType<F>>,
+}
+
+/// Leaves per row of `Visualizer::visualize_leaf_row`
+#[cfg(feature = "visualize")]
+pub const LEAF_ROW_WIDTH: usize = 64;
+
+/// Path bits of the widest leaf level `Visualizer::visualize_leaf_row` draws, 65536 leaves
+#[cfg(feature = "visualize")]
+pub const MAX_LEAF_ROW_BITS: usize = 16;
+
+/// Indices between two ticks of the leaf row ruler, room for five digits
+#[cfg(feature = "visualize")]
+const LEAF_RULER_STEP: usize = 8;
+
+/// Past this many levels the tree lines stop being indented any further
 #[cfg(feature = "visualize")]
-fn short_fr(fr: &Fr) -> String {
-    let str = fr.to_string();
-    if str.len() > 10 {
-        format!("{}..{}", &str[..5], &str[str.len() - 5..])
+const MAX_INDENT_LEVELS: usize = 32;
+
+/// Past this many levels a path is shortened like `FrFormat::ShortDecimal`, keeping the first and
+/// last ones
+#[cfg(feature = "visualize")]
+const MAX_SHOWN_DIGITS: usize = 16;
+
+/// Where a node lives: the path and leaf index of a leaf, the path prefix of an inner node
+///
+/// The index is the path `insert_at_path` takes, given when the path fits in a `u64`.
+#[cfg(feature = "visualize")]
+fn position<H: MerkleHasher<F>, F: PrimeField, M>(
+    tree: &SparseMerkleTree<H, F, M>,
+    digits: &[usize],
+    is_leaf: bool,
+) -> String {
+    if digits.is_empty() {
+        return String::new();
+    }
+
+    let shown: Vec<String> = if digits.len() > MAX_SHOWN_DIGITS {
+        let half = MAX_SHOWN_DIGITS / 2;
+        let first = digits[..half].iter().map(ToString::to_string);
+        let last = digits[digits.len() - half..]
+            .iter()
+            .map(ToString::to_string);
+        first.chain(["..".to_string()]).chain(last).collect()
     } else {
-        str
+        digits.iter().map(ToString::to_string).collect()
+    };
+    let path = shown.join(",");
+    if !is_leaf {
+        return format!(", Prefix: [{path}]");
+    }
+    match leaf_index(tree, digits) {
+        Some(index) => format!(", Path: [{path}], Index: {index}"),
+        None => format!(", Path: [{path}]"),
     }
 }
 
+/// Write the tree line by line, see `walk`
+///
+/// Materialized inner nodes holding the empty hash are printed as empty and not expanded.
 #[cfg(feature = "visualize")]
-fn visualize_node(
-    node: &Rc<RefCell<Node<Poseidon<Fr>>>>,
-    depth: usize,
-    level: usize,
-    prefix: String,
-    is_right: bool,
-) {
-    let node_ref = node.borrow();
-    let indent = prefix.clone() + if is_right { "└── " } else { "├── " };
-    // If leaf level, instead of empty, we should print the value 0
-    let is_leaf_level = level == depth - 1;
-    let is_root = level == 0;
-
-    match &node_ref.node_type {
-        NodeType::Inner(hash) => {
-            if is_root {
-                println!("{}{} (Root Node: {})", indent, level, short_fr(hash));
-            } else {
-                println!("{}{} (Inner Node: {})", indent, level, short_fr(hash));
-            }
+fn render<H: MerkleHasher<F>, F: PrimeField, M, W: Write>(
+    tree: &SparseMerkleTree<H, F, M>,
+    opts: &VisualizeOptions<F>,
+    w: &mut W,
+) -> io::Result<()> {
+    writeln!(
+        w,
+        "Sparse Merkle Tree Visualization (Depth: {})",
+        tree.depth
+    )?;
+    writeln!(w, "=======================================")?;
 
-            // Child prefix
-            let child_prefix = prefix + if is_right { "    " } else { "│   " };
+    if tree.is_empty().map_err(io::Error::other)? {
+        return writeln!(w, "Empty tree");
+    }
 
-            // Visualize left child
-            if let Some(left) = &node_ref.left {
-                visualize_node(left, depth, level + 1, child_prefix.clone(), false);
-            } else if is_leaf_level {
-                println!("{}├── {} (Leaf Value: 0)", child_prefix, level + 1);
+    let mark = |on_path: bool| if on_path { " ◀" } else { "" };
+    let format = |fr: &F| opts.fr_format.format(fr);
+    walk(
+        tree,
+        opts.max_depth,
+        opts.highlight_path.as_ref(),
+        opts.skip_empty_subtrees,
+        |visit| {
+            let level = visit.digits.len();
+            // Indentation from the ancestors, bounded so deep spines keep a readable width
+            let mut indent: String = visit.lasts[..level]
+                .iter()
+                .take(MAX_INDENT_LEVELS)
+                .map(|is_last| if *is_last { "    " } else { "│   " })
+                .collect();
+            indent.push_str(if visit.lasts[level] {
+                "└── "
             } else {
-                println!("{}├── {} (Empty)", child_prefix, level + 1);
-            }
+                "├── "
+            });
 
-            // Visualize right child
-            if let Some(right) = &node_ref.right {
-                visualize_node(right, depth, level + 1, child_prefix, true);
-            } else if is_leaf_level {
-                println!("{}└── {} (Leaf Value: 0)", child_prefix, level + 1);
-            } else {
-                println!("{}└── {} (Empty)", child_prefix, level + 1);
+            let (label, is_leaf) = match visit.visited {
+                Visited::Leaf(value) => (format!("Leaf Value: {}", format(&value)), true),
+                Visited::Inner(hash) if level == 0 => {
+                    (format!("Root Node: {}", format(&hash)), false)
+                }
+                Visited::Inner(hash) => (format!("Inner Node: {}", format(&hash)), false),
+                Visited::Summary(hash, leaves) => (
+                    format!("Subtree: {}, {} leaves", format(&hash), leaves),
+                    false,
+                ),
+                // If leaf level, instead of empty, we should print the value 0
+                Visited::Empty(true) => (format!("Leaf Value: {}", format(&F::zero())), true),
+                Visited::Empty(false) => ("Empty".to_string(), false),
+            };
+            writeln!(
+                w,
+                "{}{} ({}{}){}",
+                indent,
+                level,
+                label,
+                position(tree, visit.digits, is_leaf),
+                mark(visit.on_path)
+            )
+        },
+    )
+}
+
+/// Graphviz identifier of the node reached through `digits`, `n` for the root and `n_1_0` for
+/// the first child of its second child
+#[cfg(feature = "visualize")]
+fn dot_id(digits: &[usize]) -> String {
+    digits
+        .iter()
+        .fold(String::from("n"), |id, digit| format!("{id}_{digit}"))
+}
+
+#[cfg(feature = "visualize")]
+impl<H: MerkleHasher<F>, F: PrimeField, M> SparseMerkleTree<H, F, M> {
+    /// The cached hashes and the values of the tree, to show what an update changes with
+    /// `Visualizer::visualize_changes`
+    ///
+    /// A node borrowed elsewhere fails with `PoseidonMerkleError::ConcurrentAccess`.
+    pub fn hash_snapshot(&self) -> Result<HashSnapshot<F>, PoseidonMerkleError<F>> {
+        let mut nodes = BTreeMap::new();
+        let mut stack = vec![(self.root.clone(), Vec::new())];
+        while let Some((node, digits)) = stack.pop() {
+            let node = node.checked_borrow()?;
+            for (index, child) in node.children.iter().enumerate() {
+                if let Some(child) = child {
+                    let mut child_digits = digits.clone();
+                    child_digits.push(index);
+                    stack.push((child.clone(), child_digits));
+                }
             }
+            nodes.insert(digits, node.node_type.clone());
         }
-        NodeType::Leaf(value) => {
-            println!("{}{} (Leaf Value: {})", indent, level, short_fr(value));
-        }
+
+        Ok(HashSnapshot {
+            depth: self.depth,
+            arity: self.arity().children(),
+            nodes,
+        })
     }
+
+    /// The tree as a Graphviz DOT digraph, for `dot -Tsvg`
+    ///
+    /// Inner nodes are labeled with their shortened hash, the root is filled, and leaves with
+    /// their value and index. Empty children are dashed `empty` nodes unless
+    /// `skip_empty_subtrees`, subtrees at `max_depth` are summarized and the nodes and edges of
+    /// `highlight_path` are drawn in red. Identifiers follow the child indices from the root, so
+    /// a node keeps its identifier across exports and the outputs diff well. A node borrowed
+    /// elsewhere fails with `PoseidonMerkleError::ConcurrentAccess`.
+    pub fn to_dot(&self, opts: &VisualizeOptions<F>) -> Result<String, PoseidonMerkleError<F>> {
+        let format = |fr: &F| opts.fr_format.format(fr);
+        let mut dot = String::from("digraph SparseMerkleTree {\n");
+        dot.push_str("    node [shape=box, fontname=monospace];\n");
+
+        walk::<_, _, _, PoseidonMerkleError<F>>(
+            self,
+            opts.max_depth,
+            opts.highlight_path.as_ref(),
+            opts.skip_empty_subtrees,
+            |visit| {
+                let level = visit.digits.len();
+                let id = dot_id(visit.digits);
+                let mut attributes = vec![match visit.visited {
+                    Visited::Leaf(value) => {
+                        let index = match leaf_index(self, visit.digits) {
+                            Some(index) => format!("index {index}"),
+                            None => format!("{:?}", visit.digits),
+                        };
+                        format!("label=\"{}\\n{index}\", shape=ellipse", format(&value))
+                    }
+                    Visited::Inner(hash) if level == 0 => format!(
+                        "label=\"root\\n{}\", style=\"filled,bold\", fillcolor=lightblue",
+                        format(&hash)
+                    ),
+                    Visited::Inner(hash) => format!("label=\"{}\"", format(&hash)),
+                    Visited::Summary(hash, leaves) => format!(
+                        "label=\"{}\\n{leaves} leaves\", shape=folder",
+                        format(&hash)
+                    ),
+                    Visited::Empty(_) => "label=\"empty\", style=dashed".to_string(),
+                }];
+                let mut edge_attributes = Vec::new();
+                if matches!(visit.visited, Visited::Empty(_)) {
+                    edge_attributes.push("style=dashed".to_string());
+                }
+                if visit.on_path {
+                    attributes.push("color=red, penwidth=2".to_string());
+                    edge_attributes.push("color=red, penwidth=2".to_string());
+                }
+
+                if level > 0 {
+                    let parent = dot_id(&visit.digits[..level - 1]);
+                    dot.push_str(&format!(
+                        "    {parent} -> {id}{};\n",
+                        dot_attributes(edge_attributes)
+                    ));
+                }
+                dot.push_str(&format!("    {id}{};\n", dot_attributes(attributes)));
+                Ok(())
+            },
+        )?;
+
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+}
+
+/// A DOT attribute list, nothing without attributes
+#[cfg(feature = "visualize")]
+fn dot_attributes(attributes: Vec<String>) -> String {
+    if attributes.is_empty() {
+        return String::new();
+    }
+    format!(" [{}]", attributes.join(", "))
 }
 
 #[cfg(all(test, feature = "visualize"))]
 mod tests {
     use super::*;
-    use crate::SparseMerkleTree;
+    use crate::{Arity, BitOrder, MerkleProof, SparseMerkleTree};
+    use ark_bn254::Fr;
+    use light_poseidon::Poseidon;
+
+    fn render_lines<H: MerkleHasher<F>, F: PrimeField, M>(
+        tree: &SparseMerkleTree<H, F, M>,
+    ) -> Vec<String> {
+        tree.visualize_string()
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
 
     #[test]
     fn test_visualization() {
@@ -113,9 +607,502 @@ mod tests {
 
         // Visualize the tree - this is mostly for manual inspection
         // during development with the feature enabled
-        tree.visualize();
+        tree.visualize().unwrap();
 
         // No assertions needed as this is just a visual test
         // The test passes if it compiles and runs without errors
     }
+
+    /// A Poseidon hasher counting its hashes, standing for instrumented wrappers
+    struct CountingHasher {
+        poseidon: Poseidon<Fr>,
+        hashes: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl MerkleHasher for CountingHasher {
+        fn hash_children(&mut self, children: &[Fr]) -> Result<Fr, light_poseidon::PoseidonError> {
+            self.hashes.set(self.hashes.get() + 1);
+            self.poseidon.hash_children(children)
+        }
+    }
+
+    #[test]
+    fn test_visualize_tree_of_wrapper_hasher() {
+        let hashes = std::rc::Rc::new(std::cell::Cell::new(0));
+        let hasher = CountingHasher {
+            poseidon: Poseidon::<Fr>::new_circom(2).unwrap(),
+            hashes: hashes.clone(),
+        };
+        let mut tree = SparseMerkleTree::new_with_hasher(2, hasher).unwrap();
+        let mut reference = SparseMerkleTree::new(2).unwrap();
+        for (path, value) in [(1u64, 100u64), (6, 200)] {
+            tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+                .unwrap();
+            reference
+                .insert_at_path(&Fr::from(path), &Fr::from(value))
+                .unwrap();
+        }
+        tree.root().unwrap();
+        reference.root().unwrap();
+
+        // Visualizing reads the cached hashes, it doesn't hash anything
+        let before = hashes.get();
+        assert_eq!(render_lines(&tree), render_lines(&reference));
+        if !cfg!(feature = "paranoid") {
+            assert_eq!(hashes.get(), before);
+        }
+    }
+
+    fn changes<H: MerkleHasher<F>, F: PrimeField, M>(
+        tree: &SparseMerkleTree<H, F, M>,
+        before: &HashSnapshot<F>,
+    ) -> Vec<String> {
+        let mut bytes = Vec::new();
+        tree.visualize_changes(before, &mut bytes).unwrap();
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .skip(2)
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_visualize_changes_of_one_leaf() {
+        let mut tree = SparseMerkleTree::new(4).unwrap();
+        for (path, value) in [(1u64, 10u64), (6, 60), (9, 90), (14, 140)] {
+            tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+                .unwrap();
+        }
+        let before = tree.hash_snapshot().unwrap();
+        assert_eq!(changes(&tree, &before), vec!["No changes"]);
+
+        tree.insert_at_path(&Fr::from(6u64), &Fr::from(61u64))
+            .unwrap();
+        let lines = changes(&tree, &before);
+        // The root, the 3 inner nodes above the leaf and the leaf, nothing else
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().all(|line| line.contains(" → ")));
+        assert!(lines[0].starts_with("└── 0 (Root Node: "));
+        assert_eq!(
+            lines[4],
+            "                └── 4 (Leaf Value: 60 → 61, Path: [0,1,1,0], Index: 6)"
+        );
+    }
+
+    #[test]
+    fn test_visualize_changes_marks_new_and_pruned_nodes() {
+        let mut tree = SparseMerkleTree::new(2).unwrap();
+        tree.insert_at_path(&Fr::from(0u64), &Fr::from(7u64))
+            .unwrap();
+        let before = tree.hash_snapshot().unwrap();
+
+        tree.insert_at_path(&Fr::from(3u64), &Fr::from(9u64))
+            .unwrap();
+        let lines = changes(&tree, &before);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("    └── 1 (Inner Node: none → "));
+        assert!(lines[1].ends_with(", Prefix: [1]) new"));
+        assert_eq!(
+            lines[2],
+            "        └── 2 (Leaf Value: none → 9, Path: [1,1], Index: 3) new"
+        );
+
+        let before = tree.hash_snapshot().unwrap();
+        tree.clear();
+        let lines = changes(&tree, &before);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].ends_with(", Prefix: [0]) pruned"));
+        assert!(lines[2].ends_with(", Prefix: [1]) pruned"));
+
+        let shallow = SparseMerkleTree::new(1).unwrap();
+        let mut bytes = Vec::new();
+        let err = shallow.visualize_changes(&before, &mut bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_visualization_golden() {
+        let mut tree = SparseMerkleTree::new(2).unwrap();
+        tree.insert_at_path(&Fr::from(1u64), &Fr::from(100u64))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(6u64), &Fr::from(200u64))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(3u64), &Fr::from(300u64))
+            .unwrap();
+
+        let expected = [
+            "Sparse Merkle Tree Visualization (Depth: 2)",
+            "=======================================",
+            "└── 0 (Root Node: 95267..79726)",
+            "    ├── 1 (Inner Node: 11758..94473, Prefix: [0])",
+            "    │   ├── 2 (Leaf Value: 0, Path: [0,0], Index: 0)",
+            "    │   └── 2 (Leaf Value: 200, Path: [0,1], Index: 2)",
+            "    └── 1 (Inner Node: 88849..95163, Prefix: [1])",
+            "        ├── 2 (Leaf Value: 100, Path: [1,0], Index: 1)",
+            "        └── 2 (Leaf Value: 300, Path: [1,1], Index: 3)",
+        ];
+        assert_eq!(render_lines(&tree), expected);
+    }
+
+    #[test]
+    fn test_visualization_golden_sparse() {
+        let mut tree = SparseMerkleTree::new(3).unwrap();
+        assert_eq!(
+            render_lines(&tree),
+            [
+                "Sparse Merkle Tree Visualization (Depth: 3)",
+                "=======================================",
+                "Empty tree",
+            ]
+        );
+
+        tree.insert_at_path(&Fr::from(5u64), &Fr::from(42u64))
+            .unwrap();
+        let expected = [
+            "Sparse Merkle Tree Visualization (Depth: 3)",
+            "=======================================",
+            "└── 0 (Root Node: 58035..80632)",
+            "    ├── 1 (Empty, Prefix: [0])",
+            "    └── 1 (Inner Node: 59647..29898, Prefix: [1])",
+            "        ├── 2 (Inner Node: 99046..96787, Prefix: [1,0])",
+            "        │   ├── 3 (Leaf Value: 0, Path: [1,0,0], Index: 1)",
+            "        │   └── 3 (Leaf Value: 42, Path: [1,0,1], Index: 5)",
+            "        └── 2 (Empty, Prefix: [1,1])",
+        ];
+        assert_eq!(render_lines(&tree), expected);
+    }
+
+    #[test]
+    fn test_visualize_string_golden() {
+        let mut tree = SparseMerkleTree::new(2).unwrap();
+        assert_eq!(
+            tree.visualize_string().unwrap(),
+            concat!(
+                "Sparse Merkle Tree Visualization (Depth: 2)\n",
+                "=======================================\n",
+                "Empty tree\n",
+            )
+        );
+
+        tree.insert_at_path(&Fr::from(2u64), &Fr::from(7u64))
+            .unwrap();
+        let expected = concat!(
+            "Sparse Merkle Tree Visualization (Depth: 2)\n",
+            "=======================================\n",
+            "└── 0 (Root Node: 17774..77008)\n",
+            "    ├── 1 (Inner Node: 27960..87966, Prefix: [0])\n",
+            "    │   ├── 2 (Leaf Value: 0, Path: [0,0], Index: 0)\n",
+            "    │   └── 2 (Leaf Value: 7, Path: [0,1], Index: 2)\n",
+            "    └── 1 (Empty, Prefix: [1])\n",
+        );
+        assert_eq!(tree.visualize_string().unwrap(), expected);
+
+        let mut written = Vec::new();
+        tree.visualize_to(&mut written).unwrap();
+        assert_eq!(written, expected.as_bytes());
+    }
+
+    fn render_with(tree: &SparseMerkleTree<Poseidon<Fr>>, opts: VisualizeOptions) -> Vec<String> {
+        let mut bytes = Vec::new();
+        tree.visualize_with(&opts, &mut bytes).unwrap();
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_visualize_with_options() {
+        let mut tree = SparseMerkleTree::new(3).unwrap();
+        tree.insert_at_path(&Fr::from(5u64), &Fr::from(42u64))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(1u64), &Fr::from(7u64))
+            .unwrap();
+        assert_eq!(
+            render_with(&tree, VisualizeOptions::default()),
+            render_lines(&tree)
+        );
+
+        let collapsed = render_with(
+            &tree,
+            VisualizeOptions {
+                max_depth: Some(1),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            collapsed[2..],
+            [
+                "└── 0 (Root Node: 13795..13156)",
+                "    ├── 1 (Empty, Prefix: [0])",
+                "    └── 1 (Subtree: 86881..25867, 2 leaves, Prefix: [1])",
+            ]
+        );
+
+        let highlighted = render_with(
+            &tree,
+            VisualizeOptions {
+                highlight_path: Some(Fr::from(5u64)),
+                ..Default::default()
+            },
+        );
+        let marked: Vec<_> = highlighted
+            .iter()
+            .filter(|line| line.ends_with(" ◀"))
+            .collect();
+        assert_eq!(marked.len(), 4);
+        assert!(marked[3].ends_with("3 (Leaf Value: 42, Path: [1,0,1], Index: 5) ◀"));
+        assert!(!highlighted
+            .iter()
+            .any(|line| line.contains("Leaf Value: 7,") && line.ends_with(" ◀")));
+
+        let pruned = render_with(
+            &tree,
+            VisualizeOptions {
+                skip_empty_subtrees: true,
+                ..Default::default()
+            },
+        );
+        assert!(pruned
+            .iter()
+            .all(|line| !line.contains("Empty") && !line.contains("Value: 0")));
+        assert!(pruned.iter().any(|line| line.contains("Leaf Value: 42")));
+        assert!(pruned.iter().any(|line| line.contains("Leaf Value: 7")));
+    }
+
+    #[test]
+    fn test_visualize_leaf_indices() {
+        let indices = [0u64, 3, 6, 13];
+        for bit_order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+            let mut tree = SparseMerkleTree::new(4).unwrap().with_bit_order(bit_order);
+            for index in indices {
+                tree.insert_at_path(&Fr::from(index), &Fr::from(index + 100))
+                    .unwrap();
+            }
+
+            let shown: Vec<(u64, u64)> = render_lines(&tree)
+                .iter()
+                .filter_map(|line| {
+                    let (_, value) = line.split_once("Leaf Value: ")?;
+                    let (value, index) = value.split_once(", Path: ")?;
+                    let (_, index) = index.split_once("Index: ")?;
+                    let index = index.trim_end_matches(')').parse().unwrap();
+                    Some((value.parse().unwrap(), index))
+                })
+                .filter(|(value, _)| *value != 0)
+                .collect();
+            assert_eq!(shown.len(), indices.len());
+            assert!(shown.iter().all(|(value, index)| *value == index + 100));
+        }
+    }
+
+    #[test]
+    fn test_visualize_proof() {
+        let mut tree = SparseMerkleTree::new(3).unwrap();
+        tree.insert_at_path(&Fr::from(5u64), &Fr::from(42u64))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(2u64), &Fr::from(7u64))
+            .unwrap();
+        tree.root().unwrap();
+        let mut proof = tree.generate_proof(&Fr::from(5u64)).unwrap();
+
+        let render = |proof: &MerkleProof| {
+            let mut bytes = Vec::new();
+            tree.visualize_proof(proof, &mut bytes).unwrap();
+            String::from_utf8(bytes).unwrap()
+        };
+        let valid = render(&proof);
+        assert!(valid.contains("3 (Leaf Value: 42, Path: [1,0,1], Index: 5) ◀"));
+        assert!(!valid.contains('✗'));
+        assert!(valid.ends_with("All siblings match\n"));
+
+        proof.siblings[1] = Fr::from(9u64);
+        let corrupted = render(&proof);
+        let flagged: Vec<_> = corrupted
+            .lines()
+            .filter(|line| line.contains('✗'))
+            .collect();
+        assert_eq!(flagged.len(), 1);
+        assert!(flagged[0].starts_with("2 (Sibling 0: tree "));
+        assert!(flagged[0].ends_with(", proof 9) ✗ first divergence"));
+        assert!(corrupted.ends_with("Siblings diverge first at level 2\n"));
+    }
+
+    #[test]
+    fn test_visualize_fr_format() {
+        let mut tree = SparseMerkleTree::new(1).unwrap();
+        tree.insert_at_path(&Fr::from(1u64), &Fr::from(255u64))
+            .unwrap();
+        let root = tree.root().unwrap();
+
+        let opts = VisualizeOptions {
+            fr_format: FrFormat::ShortHex,
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        tree.visualize_with(&opts, &mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.contains(&format!("Root Node: {}", FrFormat::ShortHex.format(&root))));
+        assert!(text.contains("1 (Leaf Value: 0x0, Path: [0], Index: 0)"));
+        assert!(text.contains("1 (Leaf Value: 0xff, Path: [1], Index: 1)"));
+
+        let opts = VisualizeOptions {
+            fr_format: FrFormat::FullDecimal,
+            ..Default::default()
+        };
+        let proof = tree.generate_proof(&Fr::from(1u64)).unwrap();
+        let mut bytes = Vec::new();
+        tree.visualize_proof_with(&proof, &opts, &mut bytes)
+            .unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.contains(&format!("0 (Root: tree {root}, proof {root})")));
+        assert!(tree
+            .to_dot(&opts)
+            .unwrap()
+            .contains(&format!("label=\"root\\n{root}\"")));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let mut tree = SparseMerkleTree::new(2).unwrap();
+        tree.insert_at_path(&Fr::from(1u64), &Fr::from(100u64))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(2u64), &Fr::from(200u64))
+            .unwrap();
+        tree.root().unwrap();
+
+        let dot = tree.to_dot(&VisualizeOptions::default()).unwrap();
+        assert!(dot.starts_with("digraph SparseMerkleTree {\n"));
+        assert!(dot.ends_with("}\n"));
+        let count =
+            |dot: &str, pattern: &str| dot.lines().filter(|line| line.contains(pattern)).count();
+        // The root, two inner nodes and four leaves, two of them empty
+        assert_eq!(count(&dot, " [label="), 7);
+        assert_eq!(count(&dot, " -> "), 6);
+        assert_eq!(count(&dot, "label=\"empty\""), 2);
+        assert_eq!(count(&dot, "style=dashed"), 4);
+        assert!(dot.contains("    n [label=\"root\\n"));
+        assert!(dot.contains("    n_1_0 [label=\"100\\nindex 1\", shape=ellipse];"));
+        assert!(dot.contains("    n_0_1 [label=\"200\\nindex 2\", shape=ellipse];"));
+
+        let pruned = tree
+            .to_dot(&VisualizeOptions {
+                skip_empty_subtrees: true,
+                highlight_path: Some(Fr::from(1u64)),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(count(&pruned, " [label="), 5);
+        assert_eq!(count(&pruned, " -> "), 4);
+        assert_eq!(count(&pruned, "color=red"), 5);
+        assert!(pruned.contains("    n -> n_1 [color=red, penwidth=2];"));
+
+        // Identifiers don't move, the lines of an untouched subtree stay the same
+        tree.insert_at_path(&Fr::from(3u64), &Fr::from(300u64))
+            .unwrap();
+        tree.root().unwrap();
+        let updated = tree.to_dot(&VisualizeOptions::default()).unwrap();
+        let unchanged: Vec<_> = dot.lines().filter(|line| line.contains("n_0")).collect();
+        assert_eq!(
+            updated
+                .lines()
+                .filter(|line| line.contains("n_0"))
+                .collect::<Vec<_>>(),
+            unchanged
+        );
+    }
+
+    #[test]
+    fn test_visualize_leaf_row() {
+        let mut tree = SparseMerkleTree::new(4).unwrap();
+        for index in [1u64, 6, 15] {
+            tree.insert_at_path(&Fr::from(index), &Fr::from(index + 100))
+                .unwrap();
+        }
+
+        let mut row = Vec::new();
+        tree.visualize_leaf_row(&mut row).unwrap();
+        assert_eq!(
+            String::from_utf8(row).unwrap(),
+            concat!(
+                "Leaf Occupancy (Depth: 4, 3 of 16 leaves)\n",
+                "=======================================\n",
+                "      0       8\n",
+                "    0 .#....#........#\n",
+            )
+        );
+
+        let mut rows = Vec::new();
+        tree.visualize_leaf_row_with(6, &mut rows).unwrap();
+        assert_eq!(
+            String::from_utf8(rows).unwrap(),
+            concat!(
+                "Leaf Occupancy (Depth: 4, 3 of 16 leaves)\n",
+                "=======================================\n",
+                "      0\n",
+                "    0 .#....\n",
+                "        8\n",
+                "    6 #.....\n",
+                "\n",
+                "   12 ...#\n",
+            )
+        );
+
+        let deep = SparseMerkleTree::new(MAX_LEAF_ROW_BITS + 1).unwrap();
+        let error = deep.visualize_leaf_row(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        let error = tree
+            .visualize_leaf_row_with(0, &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_visualization_quaternary() {
+        let hasher = Poseidon::<Fr>::new_circom(4).unwrap();
+        let mut tree = SparseMerkleTree::new_with_arity(1, Arity::Quaternary, hasher).unwrap();
+        tree.insert_at_path(&Fr::from(1u64), &Fr::from(7u64))
+            .unwrap();
+
+        let lines = render_lines(&tree);
+        assert_eq!(
+            lines[3..],
+            [
+                "    ├── 1 (Leaf Value: 0, Path: [0], Index: 0)",
+                "    ├── 1 (Leaf Value: 7, Path: [1], Index: 1)",
+                "    ├── 1 (Leaf Value: 0, Path: [2], Index: 2)",
+                "    └── 1 (Leaf Value: 0, Path: [3], Index: 3)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_visualize_deep_tree_on_small_stack() {
+        // Poseidon parameter setup needs a large stack in debug builds, so do it up front
+        let hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+        let lines = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                let mut tree = SparseMerkleTree::new_with_hasher(64, hasher).unwrap();
+                tree.insert_at_path(&Fr::from(u64::MAX), &Fr::from(1u64))
+                    .unwrap();
+                render_lines(&tree)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        // Two header lines, then one node and one empty sibling per level, plus the leaf
+        assert_eq!(lines.len(), 2 + 1 + 2 * 64);
+        assert_eq!(
+            lines.last().unwrap().trim_start(),
+            "└── 64 (Leaf Value: 1, Path: [1,1,1,1,1,1,1,1,..,1,1,1,1,1,1,1,1], \
+             Index: 18446744073709551615)"
+        );
+        // Indentation stops growing and long paths are shortened
+        let widest = lines.iter().map(|line| line.chars().count()).max().unwrap();
+        assert!(widest < 4 * (MAX_INDENT_LEVELS + 2) + 100);
+    }
 }
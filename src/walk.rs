@@ -0,0 +1,201 @@
+use alloc::{vec, vec::Vec};
+
+use ark_ff::PrimeField;
+
+use crate::{
+    shared::{BorrowConflict, CheckedBorrow},
+    InnerHash, MerkleHasher, MerklePath, NodeRef, NodeType, SparseMerkleTree,
+};
+
+/// What the traversal found at a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Visited<F: PrimeField> {
+    /// A materialized leaf, even one holding the empty value
+    Leaf(F),
+    /// An inner node, its children are visited next
+    Inner(InnerHash<F>),
+    /// An inner node at the depth limit, not expanded, and its number of non-empty leaves
+    Summary(InnerHash<F>, usize),
+    /// A missing child or an inner node holding the empty hash, `true` for a missing leaf
+    Empty(bool),
+}
+
+/// A node of the traversal and where it lives
+pub(crate) struct Visit<'a, F: PrimeField> {
+    pub(crate) visited: Visited<F>,
+    /// Child index taken at each level from the root, the bits of the path for binary trees
+    pub(crate) digits: &'a [usize],
+    /// Whether the node and each of its ancestors is the last child shown of its parent, the
+    /// root first
+    #[cfg_attr(not(feature = "visualize"), allow(dead_code))]
+    pub(crate) lasts: &'a [bool],
+    /// Whether the node is on the highlighted path
+    #[cfg_attr(not(feature = "visualize"), allow(dead_code))]
+    pub(crate) on_path: bool,
+}
+
+/// Pending node of the traversal: the child, its digits, its lasts and whether it is on the path
+type Pending<H, F, M> = (Option<NodeRef<H, F, M>>, Vec<usize>, Vec<bool>, bool);
+
+/// Visit the nodes of a tree depth-first, children in index order, with an explicit stack so deep
+/// trees can't overflow the call stack
+///
+/// Inner nodes at `max_depth` are summarized, empty children are skipped when `skip_empty`, and
+/// `highlight` marks the nodes of a path. Hashes are the cached ones, stale until `root()`. A node
+/// borrowed elsewhere stops the traversal with a `BorrowConflict`.
+pub(crate) fn walk<H: MerkleHasher<F>, F: PrimeField, M, E: From<BorrowConflict>>(
+    tree: &SparseMerkleTree<H, F, M>,
+    max_depth: Option<usize>,
+    highlight: Option<&MerklePath<F>>,
+    skip_empty: bool,
+    mut visit: impl FnMut(Visit<'_, F>) -> Result<(), E>,
+) -> Result<(), E> {
+    let highlight = highlight.map(|path| tree.canonicalize_path(path));
+    let arity = tree.arity().children();
+    let mut stack: Vec<Pending<H, F, M>> = vec![(
+        Some(tree.root.clone()),
+        Vec::new(),
+        vec![true],
+        highlight.is_some(),
+    )];
+
+    while let Some((node, digits, lasts, on_path)) = stack.pop() {
+        let level = digits.len();
+        let is_empty = level > 0 && is_empty_inner(tree, &node, level)?;
+        let node = match node {
+            Some(node) if !is_empty => node,
+            _ => {
+                let is_missing_leaf = node.is_none() && level == tree.depth;
+                visit(Visit {
+                    visited: Visited::Empty(is_missing_leaf),
+                    digits: &digits,
+                    lasts: &lasts,
+                    on_path,
+                })?;
+                continue;
+            }
+        };
+
+        #[cfg(feature = "paranoid")]
+        {
+            let hasher = &mut *tree.hasher.borrow_mut();
+            tree.check_cached_hash(hasher, &*node.checked_borrow()?, &digits);
+        }
+        let node_ref = node.checked_borrow()?;
+        let visited = match node_ref.node_type {
+            NodeType::Leaf(value) => Visited::Leaf(value),
+            NodeType::Inner(hash) if max_depth.is_some_and(|max| level >= max) => {
+                Visited::Summary(hash, count_leaves(tree, &node)?)
+            }
+            NodeType::Inner(hash) => Visited::Inner(hash),
+        };
+        visit(Visit {
+            visited,
+            digits: &digits,
+            lasts: &lasts,
+            on_path,
+        })?;
+        if !matches!(visited, Visited::Inner(_)) {
+            continue;
+        }
+
+        let path_child = highlight
+            .as_ref()
+            .filter(|_| on_path)
+            .map(|path| tree.child_index(path, level));
+        let mut shown = Vec::with_capacity(arity);
+        for index in 0..arity {
+            if !skip_empty || !is_empty_child(tree, &node_ref.children[index], level + 1)? {
+                shown.push(index);
+            }
+        }
+
+        // Push the last child first so that the first one is visited first
+        for (rank, &index) in shown.iter().enumerate().rev() {
+            let mut child_digits = digits.clone();
+            child_digits.push(index);
+            let mut child_lasts = lasts.clone();
+            child_lasts.push(rank == shown.len() - 1);
+            stack.push((
+                node_ref.children[index].clone(),
+                child_digits,
+                child_lasts,
+                path_child == Some(index),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a child is missing or a materialized inner node holding the empty hash of its level
+fn is_empty_inner<H: MerkleHasher<F>, F: PrimeField, M>(
+    tree: &SparseMerkleTree<H, F, M>,
+    child: &Option<NodeRef<H, F, M>>,
+    level: usize,
+) -> Result<bool, BorrowConflict> {
+    let empty = match child {
+        None => true,
+        Some(child) => match child.checked_borrow()?.node_type {
+            NodeType::Leaf(_) => false,
+            NodeType::Inner(hash) => hash == tree.hash_config().empty_inner_hash_at(level),
+        },
+    };
+    Ok(empty)
+}
+
+/// Whether a child holds nothing: missing, an empty inner node or a leaf with the empty value
+fn is_empty_child<H: MerkleHasher<F>, F: PrimeField, M>(
+    tree: &SparseMerkleTree<H, F, M>,
+    child: &Option<NodeRef<H, F, M>>,
+    level: usize,
+) -> Result<bool, BorrowConflict> {
+    let Some(child) = child else {
+        return Ok(true);
+    };
+    let empty = match child.checked_borrow()?.node_type {
+        NodeType::Leaf(value) => value == tree.empty_leaf(),
+        NodeType::Inner(hash) => hash == tree.hash_config().empty_inner_hash_at(level),
+    };
+    Ok(empty)
+}
+
+/// Number of leaves below a node that don't hold the empty value
+fn count_leaves<H: MerkleHasher<F>, F: PrimeField, M>(
+    tree: &SparseMerkleTree<H, F, M>,
+    node: &NodeRef<H, F, M>,
+) -> Result<usize, BorrowConflict> {
+    let arity = tree.arity().children();
+    let mut count = 0;
+    let mut stack = vec![node.clone()];
+    while let Some(node) = stack.pop() {
+        let node = node.checked_borrow()?;
+        match node.node_type {
+            NodeType::Leaf(value) => count += usize::from(value != tree.empty_leaf()),
+            NodeType::Inner(_) => stack.extend(node.children[..arity].iter().flatten().cloned()),
+        }
+    }
+    Ok(count)
+}
+
+/// The path `insert_at_path` takes to the leaf reached through `digits`, if it fits in a `u64`
+pub(crate) fn leaf_index<H: MerkleHasher<F>, F: PrimeField, M>(
+    tree: &SparseMerkleTree<H, F, M>,
+    digits: &[usize],
+) -> Option<u64> {
+    if tree.path_bits() > 64 {
+        return None;
+    }
+
+    let index = digits
+        .iter()
+        .enumerate()
+        .map(|(level, &digit)| {
+            let offset = tree
+                .bit_order()
+                .level_offset(level, tree.depth, tree.arity());
+            (digit as u64) << offset
+        })
+        .sum();
+    Some(index)
+}
@@ -0,0 +1,169 @@
+//! `wasm-bindgen` bindings for building trees and verifying proofs from JavaScript
+//!
+//! Field elements cross the boundary as `0x`-prefixed big-endian hex strings and errors are
+//! thrown as JS `Error`s carrying the message of `WasmError`. The trees are binary circom Poseidon
+//! trees with the default conventions, and `verify_proof` assumes them.
+
+use alloc::{format, string::String, vec::Vec};
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+use serde_json::Value;
+use thiserror::Error;
+use wasm_bindgen::prelude::*;
+
+use crate::{FrEncoding, HashOperation, MerkleProof, PoseidonMerkleError, SparseMerkleTree};
+
+/// What the bindings report to JavaScript
+#[derive(Error, Debug, PartialEq)]
+pub enum WasmError {
+    #[error("invalid field element {input:?}: {reason}")]
+    InvalidHex { input: String, reason: &'static str },
+    #[error("invalid proof: {0}")]
+    InvalidProof(String),
+    #[error(transparent)]
+    Tree(#[from] PoseidonMerkleError),
+}
+
+/// Parse a field element from big-endian hex, with or without a `0x` prefix
+///
+/// At most 64 digits are accepted and the value must be below the modulus.
+pub fn fr_from_hex(input: &str) -> Result<Fr, WasmError> {
+    let invalid = |reason| WasmError::InvalidHex {
+        input: input.into(),
+        reason,
+    };
+    let digits = input.strip_prefix("0x").unwrap_or(input);
+    if digits.is_empty() {
+        return Err(invalid("no hex digits"));
+    }
+    if digits.len() > 64 {
+        return Err(invalid("more than 64 hex digits"));
+    }
+
+    let mut bits = Vec::with_capacity(digits.len() * 4);
+    for digit in digits.chars() {
+        let nibble = digit
+            .to_digit(16)
+            .ok_or_else(|| invalid("not a hex digit"))?;
+        bits.extend((0..4).rev().map(|bit| nibble >> bit & 1 == 1));
+    }
+    Fr::from_bigint(BigInteger::from_bits_be(&bits)).ok_or_else(|| invalid("not below the modulus"))
+}
+
+/// A field element as `0x` and 64 big-endian hex digits
+pub fn fr_to_hex(value: &Fr) -> String {
+    let bytes = value.into_bigint().to_bytes_be();
+    let mut hex = String::with_capacity(2 + 2 * bytes.len());
+    hex.push_str("0x");
+    for byte in bytes {
+        hex.push_str(&format!("{byte:02x}"));
+    }
+    hex
+}
+
+/// A proof as the JSON object `{ leaf, root, path, siblings }` of hex strings
+pub fn proof_to_json(proof: &MerkleProof) -> Value {
+    proof.to_json(FrEncoding::HexBe)
+}
+
+/// The proof of a `proof_to_json` object, checked like `MerkleProof::try_new`
+pub fn proof_from_json(json: &Value) -> Result<MerkleProof, WasmError> {
+    let field = |name: &str| match json[name].as_str() {
+        Some(hex) => fr_from_hex(hex),
+        None => Err(WasmError::InvalidProof(format!(
+            "missing string field {name:?}"
+        ))),
+    };
+    let siblings = json["siblings"]
+        .as_array()
+        .ok_or_else(|| WasmError::InvalidProof("missing array field \"siblings\"".into()))?
+        .iter()
+        .map(|sibling| match sibling.as_str() {
+            Some(hex) => fr_from_hex(hex),
+            None => Err(WasmError::InvalidProof("siblings must be strings".into())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    MerkleProof::try_new(siblings, field("path")?, field("leaf")?, field("root")?)
+        .map_err(|error| WasmError::Tree(error.into()))
+}
+
+/// A binary circom Poseidon tree for JavaScript
+#[wasm_bindgen]
+pub struct WasmMerkleTree {
+    tree: SparseMerkleTree<Poseidon<Fr>>,
+}
+
+#[wasm_bindgen]
+impl WasmMerkleTree {
+    /// An empty tree of `depth` levels
+    #[wasm_bindgen(constructor)]
+    pub fn new(depth: usize) -> Result<WasmMerkleTree, JsError> {
+        Ok(WasmMerkleTree {
+            tree: SparseMerkleTree::new(depth).map_err(WasmError::from)?,
+        })
+    }
+
+    /// Set the leaf at `index`, below `2^depth`, to a hex value
+    pub fn insert(&mut self, index: u64, value_hex: &str) -> Result<(), JsError> {
+        let value = fr_from_hex(value_hex)?;
+        let path = self.path(index)?;
+        self.tree
+            .insert_at_path(&path, &value)
+            .map_err(WasmError::from)?;
+
+        Ok(())
+    }
+
+    /// The root hash as hex
+    #[wasm_bindgen(js_name = rootHex)]
+    pub fn root_hex(&mut self) -> Result<String, JsError> {
+        Ok(fr_to_hex(&self.tree.root().map_err(WasmError::from)?))
+    }
+
+    /// The proof of the leaf at `index`, a `{ leaf, root, path, siblings }` object of hex strings
+    pub fn prove(&mut self, index: u64) -> Result<JsValue, JsError> {
+        let path = self.path(index)?;
+        self.tree.root().map_err(WasmError::from)?;
+        let proof = self.tree.generate_proof(&path).map_err(WasmError::from)?;
+
+        js_sys::JSON::parse(&proof_to_json(&proof).to_string())
+            .map_err(|_| JsError::new("failed to parse the proof JSON"))
+    }
+
+    /// The path of the leaf at `index`, rejecting indices past the last leaf
+    fn path(&self, index: u64) -> Result<Fr, WasmError> {
+        let depth = self.tree.depth;
+        if depth < 64 && index >> depth != 0 {
+            return Err(PoseidonMerkleError::IndexOutOfRange {
+                // usize is 32 bits wide on wasm32
+                index: usize::try_from(index).unwrap_or(usize::MAX),
+                size: 1usize.checked_shl(depth as u32).unwrap_or(usize::MAX),
+            }
+            .into());
+        }
+
+        Ok(Fr::from(index))
+    }
+}
+
+/// Verify a proof of `WasmMerkleTree::prove`, given as an object or as its JSON string
+#[wasm_bindgen(js_name = verifyProof)]
+pub fn verify_proof(proof: JsValue) -> Result<bool, JsError> {
+    let text = match proof.as_string() {
+        Some(text) => text,
+        None => js_sys::JSON::stringify(&proof)
+            .map_err(|_| JsError::new("proof is not JSON"))?
+            .into(),
+    };
+    let json: Value =
+        serde_json::from_str(&text).map_err(|error| WasmError::InvalidProof(format!("{error}")))?;
+    let proof = proof_from_json(&json)?;
+
+    let mut hasher = Poseidon::<Fr>::new_circom(2)
+        .map_err(PoseidonMerkleError::hasher(HashOperation::HasherSetup))
+        .map_err(WasmError::from)?;
+    Ok(proof.verify_proof(&mut hasher).map_err(WasmError::from)?)
+}
@@ -0,0 +1,78 @@
+use alloc::vec::Vec;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use ark_ff::PrimeField;
+
+use crate::{MerkleHasher, MerklePath, MerkleProof, PoseidonMerkleError, SparseMerkleTree};
+
+/// A future that is pending once, so the executor runs its other tasks before it completes
+///
+/// It wakes itself right away and needs no runtime, unlike `tokio::task::yield_now`.
+#[derive(Debug, Default)]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Yield to the executor once
+pub fn yield_now() -> YieldNow {
+    YieldNow::default()
+}
+
+impl<H: MerkleHasher<F>, F: PrimeField> SparseMerkleTree<H, F> {
+    /// `insert_many` in chunks of `chunk_size` entries, yielding to the executor between chunks
+    ///
+    /// The tree is borrowed until the future completes, so the inserts of a chunk are never
+    /// interleaved with other mutations and the root is the one of `insert_many`. Like it, the
+    /// first failure stops the inserts. A `chunk_size` of 0 is taken as 1.
+    pub async fn insert_many_yielding(
+        &mut self,
+        entries: impl IntoIterator<Item = (MerklePath<F>, F)>,
+        chunk_size: usize,
+    ) -> Result<(), PoseidonMerkleError<F>> {
+        let mut entries = entries.into_iter().peekable();
+        while entries.peek().is_some() {
+            for (merkle_path, value) in entries.by_ref().take(chunk_size.max(1)) {
+                self.insert_at_path(&merkle_path, &value)?;
+            }
+            yield_now().await;
+        }
+
+        Ok(())
+    }
+
+    /// `generate_proofs` in chunks of `chunk_size` paths, yielding to the executor between chunks
+    ///
+    /// The proofs are built against the cached root, call `root()` first. A `chunk_size` of 0 is
+    /// taken as 1.
+    pub async fn generate_proofs_yielding(
+        &self,
+        merkle_paths: &[MerklePath<F>],
+        chunk_size: usize,
+    ) -> Result<Vec<MerkleProof<F>>, PoseidonMerkleError<F>> {
+        let mut proofs = Vec::with_capacity(merkle_paths.len());
+        for chunk in merkle_paths.chunks(chunk_size.max(1)) {
+            proofs.extend(self.generate_proofs(chunk)?);
+            yield_now().await;
+        }
+
+        Ok(proofs)
+    }
+}
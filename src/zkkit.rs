@@ -0,0 +1,94 @@
+//! JSON import and export of `CircomSmtCompat` trees in the shape of the `@zk-kit/smt` export
+//!
+//! The trees have as many levels as a key has bits. No tree exported by zk-kit has been imported
+//! yet, so the format follows its export but its roots may not match the ones of this crate.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use ark_bn254::Fr;
+use serde_json::{json, Map, Value};
+
+use crate::{CircomSmtCompat, FrEncoding, PoseidonMerkleError, MAX_PATH_BITS};
+
+/// The field element of a JSON string, decimal or `0x`-prefixed hex and below the modulus
+fn field(value: &Value) -> Result<Fr, PoseidonMerkleError> {
+    if !value.is_string() {
+        return Err(PoseidonMerkleError::InvalidZkKitJson(format!(
+            "{value}: not a string"
+        )));
+    }
+
+    FrEncoding::parse(value)
+        .map_err(|error| PoseidonMerkleError::InvalidZkKitJson(error.to_string()))
+}
+
+impl CircomSmtCompat {
+    /// Import a tree from JSON in the shape of the `@zk-kit/smt` export,
+    /// `{ "root": ..., "entries": { key: value } }`
+    ///
+    /// Keys and values are decimal or `0x`-prefixed hex strings. `entries` may also be an array
+    /// of `[key, value]` pairs, the shape of a JS `Map`'s entries. The tree has `MAX_PATH_BITS`
+    /// levels, all the bits of a key. When the JSON has a `root`, the one of the imported tree
+    /// must match it, or `RootMismatch` is returned.
+    pub fn from_zkkit_json(json: &str) -> Result<Self, PoseidonMerkleError> {
+        let json: Value = serde_json::from_str(json)
+            .map_err(|error| PoseidonMerkleError::InvalidZkKitJson(error.to_string()))?;
+        let pairs: Vec<(Fr, Fr)> = match &json["entries"] {
+            Value::Object(entries) => entries
+                .iter()
+                .map(|(key, value)| Ok((field(&Value::String(key.clone()))?, field(value)?)))
+                .collect::<Result<_, PoseidonMerkleError>>()?,
+            Value::Array(entries) => entries
+                .iter()
+                .map(|entry| match entry.as_array().map(Vec::as_slice) {
+                    Some([key, value]) => Ok((field(key)?, field(value)?)),
+                    _ => Err(PoseidonMerkleError::InvalidZkKitJson(format!(
+                        "{entry}: not a [key, value] pair"
+                    ))),
+                })
+                .collect::<Result<_, PoseidonMerkleError>>()?,
+            _ => {
+                return Err(PoseidonMerkleError::InvalidZkKitJson(
+                    "missing object or array field \"entries\"".into(),
+                ))
+            }
+        };
+
+        let mut tree = CircomSmtCompat::new(MAX_PATH_BITS)?;
+        for (key, value) in &pairs {
+            tree.insert(key, value)?;
+        }
+        if !json["root"].is_null() {
+            let expected = field(&json["root"])?;
+            if tree.root() != expected {
+                return Err(PoseidonMerkleError::RootMismatch {
+                    expected,
+                    actual: tree.root(),
+                });
+            }
+        }
+
+        Ok(tree)
+    }
+
+    /// Export the tree in the shape `from_zkkit_json` reads back
+    ///
+    /// The root, keys and values are decimal strings.
+    pub fn to_zkkit_json(&self) -> String {
+        let entries: Map<String, Value> = self
+            .entries()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect();
+
+        json!({
+            "root": self.root().to_string(),
+            "entries": entries,
+        })
+        .to_string()
+    }
+}
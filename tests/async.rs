@@ -0,0 +1,133 @@
+//! Chunked bulk operations on a tokio runtime, against the synchronous ones
+
+use std::sync::{Arc, Mutex};
+
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonError, PoseidonHasher};
+use merkle_poseidon::{InnerHash, MerklePath, MerkleProof, SparseMerkleTree, TreeObserver};
+
+const DEPTH: usize = 12;
+const LEAVES: u64 = 120;
+const CHUNK: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    Insert,
+    Tick,
+}
+
+/// Logs the inserts in the log the counter task ticks in
+struct Logger(Arc<Mutex<Vec<Event>>>);
+
+impl TreeObserver for Logger {
+    fn on_insert(&mut self, _: &MerklePath, _: Option<Fr>, _: &Fr, _: &InnerHash) {
+        self.0.lock().unwrap().push(Event::Insert);
+    }
+}
+
+fn entries() -> impl Iterator<Item = (MerklePath, Fr)> {
+    (0..LEAVES).map(|index| (Fr::from(index * 13 % (1 << DEPTH)), Fr::from(index + 1)))
+}
+
+fn tree() -> SparseMerkleTree<Poseidon<Fr>> {
+    SparseMerkleTree::new(DEPTH).unwrap()
+}
+
+#[tokio::test]
+async fn test_insert_many_yielding_interleaves_and_matches() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let counter = tokio::spawn({
+        let log = log.clone();
+        async move {
+            loop {
+                log.lock().unwrap().push(Event::Tick);
+                tokio::task::yield_now().await;
+            }
+        }
+    });
+
+    let mut yielding = tree();
+    yielding.set_observer(Box::new(Logger(log.clone())));
+    yielding
+        .insert_many_yielding(entries(), CHUNK)
+        .await
+        .unwrap();
+    counter.abort();
+
+    let mut reference = tree();
+    reference.insert_many(entries()).unwrap();
+    assert_eq!(yielding.root().unwrap(), reference.root().unwrap());
+
+    // The counter ticked between the chunks, never within one
+    let log = log.lock().unwrap();
+    let runs: Vec<usize> = log
+        .split(|event| *event == Event::Tick)
+        .map(<[Event]>::len)
+        .filter(|len| *len > 0)
+        .collect();
+    let chunks = (LEAVES as usize).div_ceil(CHUNK);
+    assert_eq!(runs.len(), chunks);
+    assert!(runs.iter().all(|len| *len <= CHUNK));
+    assert_eq!(runs.iter().sum::<usize>(), LEAVES as usize);
+}
+
+#[tokio::test]
+async fn test_generate_proofs_yielding_matches() {
+    let mut tree = tree();
+    tree.insert_many(entries()).unwrap();
+    tree.root().unwrap();
+    let paths: Vec<MerklePath> = entries().map(|(path, _)| path).take(50).collect();
+
+    let parts = |proofs: Vec<MerkleProof>| -> Vec<_> {
+        proofs
+            .into_iter()
+            .map(|proof| (proof.merkle_path, proof.siblings, proof.root_hash))
+            .collect()
+    };
+    let expected = parts(tree.generate_proofs(&paths).unwrap());
+    for chunk_size in [7, 0] {
+        let proofs = tree.generate_proofs_yielding(&paths, chunk_size).await;
+        assert_eq!(parts(proofs.unwrap()), expected);
+    }
+}
+
+/// Poseidon failing once its budget of successful hashes is used up
+struct FaultyHasher {
+    inner: Poseidon<Fr>,
+    remaining: usize,
+}
+
+impl PoseidonHasher<Fr> for FaultyHasher {
+    fn hash(&mut self, inputs: &[Fr]) -> Result<Fr, PoseidonError> {
+        self.remaining = self
+            .remaining
+            .checked_sub(1)
+            .ok_or(PoseidonError::EmptyInput)?;
+        self.inner.hash(inputs)
+    }
+}
+
+#[tokio::test]
+async fn test_failed_insert_stops_the_chunks() {
+    let hasher = FaultyHasher {
+        inner: Poseidon::<Fr>::new_circom(2).unwrap(),
+        remaining: 500,
+    };
+    let mut tree = SparseMerkleTree::new_with_hasher(DEPTH, hasher).unwrap();
+    let entries: Vec<_> = entries().collect();
+
+    assert!(tree
+        .insert_many_yielding(entries.clone(), CHUNK)
+        .await
+        .is_err());
+
+    // The entries before the failing one are in the tree, none after it
+    let inserted = entries
+        .iter()
+        .take_while(|(path, value)| tree.try_get_value(path).unwrap() == Some(*value))
+        .count();
+    assert!(inserted > 0 && inserted < entries.len());
+    for (path, _) in &entries[inserted..] {
+        assert_eq!(tree.try_get_value(path).unwrap(), None);
+    }
+}
@@ -0,0 +1,91 @@
+//! An `AsyncTree` shared by tokio tasks, against a tree built synchronously
+
+use std::sync::Arc;
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use merkle_poseidon::{AsyncTree, MerklePath, SparseMerkleTree};
+
+const DEPTH: usize = 10;
+const LEAVES: u64 = 200;
+
+fn entries() -> Vec<(MerklePath, Fr)> {
+    (0..LEAVES)
+        .map(|index| (Fr::from(index * 7 % (1 << DEPTH)), Fr::from(index + 1)))
+        .collect()
+}
+
+fn expected_root(entries: Vec<(MerklePath, Fr)>) -> Fr {
+    let mut tree = SparseMerkleTree::new(DEPTH).unwrap();
+    tree.insert_many(entries).unwrap();
+    tree.root().unwrap()
+}
+
+#[tokio::test]
+async fn test_readers_and_a_writer_share_the_tree() {
+    let mut first = entries();
+    let rest = first.split_off(LEAVES as usize / 2);
+    let tree = Arc::new(AsyncTree::new(SparseMerkleTree::new(DEPTH).unwrap()));
+    tree.insert_many(first.clone()).await.unwrap();
+
+    let writer = tokio::spawn({
+        let tree = tree.clone();
+        async move {
+            for (path, value) in rest {
+                tree.insert(&path, &value).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        }
+    });
+    let readers: Vec<_> = (0..4)
+        .map(|reader| {
+            let tree = tree.clone();
+            let first = first.clone();
+            tokio::spawn(async move {
+                let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+                for (path, value) in first.iter().skip(reader).step_by(4) {
+                    assert_eq!(tree.get(path).await.unwrap(), Some(*value));
+                    let proof = tree.prove(path).await.unwrap();
+                    assert!(proof.verify_proof(&mut hasher).unwrap());
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    writer.await.unwrap();
+    for reader in readers {
+        reader.await.unwrap();
+    }
+    assert_eq!(tree.root().await.unwrap(), expected_root(entries()));
+
+    // A large batch is proved on the blocking pool, like a small one on the task
+    let paths: Vec<_> = entries().into_iter().map(|(path, _)| path).collect();
+    let proofs = tree.prove_many(paths.clone()).await.unwrap();
+    let few = tree.prove_many(paths[..3].to_vec()).await.unwrap();
+    assert_eq!(proofs.len(), paths.len());
+    assert_eq!(format!("{:?}", &proofs[..3]), format!("{few:?}"));
+}
+
+#[tokio::test]
+async fn test_cancelled_bulk_insert_is_not_half_hashed() {
+    let tree = AsyncTree::new(SparseMerkleTree::new(DEPTH).unwrap());
+    tree.insert(&Fr::from(1u64), &Fr::from(1u64)).await.unwrap();
+
+    // Polled once, the inserts are handed to the blocking pool and the future is dropped
+    let mut insert = Box::pin(tree.insert_many(entries()));
+    tokio::select! {
+        biased;
+        _ = &mut insert => {}
+        _ = async {} => {}
+    }
+    drop(insert);
+
+    // The next operation waits for the whole batch
+    assert_eq!(tree.root().await.unwrap(), {
+        let mut entries = entries();
+        entries.insert(0, (Fr::from(1u64), Fr::from(1u64)));
+        expected_root(entries)
+    });
+    assert!(tree.read().await.verify_integrity().is_ok());
+}
@@ -0,0 +1,111 @@
+//! The same logical tree in both bit orders, and the circom inputs of `fixtures/bit_order.json`
+//!
+//! The fixture pins the output of `to_circom_inputs` for index 6 of the MSB-first tree, the test
+//! also folds it from the leaf up like an inclusion circuit does.
+
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonHasher};
+use merkle_poseidon::{BitOrder, PoseidonMerkleError, ProofError, SparseMerkleTree};
+use serde_json::Value;
+
+const DEPTH: usize = 4;
+
+/// Leaves by index from the left, and their values
+const LEAVES: [(u64, u64); 4] = [(1, 10), (6, 60), (9, 90), (15, 150)];
+
+fn reverse_bits(index: u64) -> u64 {
+    index.reverse_bits() >> (64 - DEPTH)
+}
+
+fn tree(bit_order: BitOrder) -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(DEPTH)
+        .unwrap()
+        .with_bit_order(bit_order);
+    for (index, value) in LEAVES {
+        let path = match bit_order {
+            BitOrder::LsbFirst => reverse_bits(index),
+            BitOrder::MsbFirst => index,
+        };
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree
+}
+
+fn field(value: &Value) -> Fr {
+    Fr::from_str(value.as_str().expect("inputs are strings")).unwrap()
+}
+
+#[test]
+fn test_same_tree_in_both_orders() {
+    let lsb = tree(BitOrder::LsbFirst);
+    let msb = tree(BitOrder::MsbFirst);
+    assert_eq!(lsb.root(), msb.root());
+
+    for (index, value) in LEAVES {
+        assert_eq!(
+            msb.try_get_value(&Fr::from(index)),
+            Ok(Some(Fr::from(value)))
+        );
+        let lsb_proof = lsb.generate_proof(&Fr::from(reverse_bits(index))).unwrap();
+        let msb_proof = msb.generate_proof(&Fr::from(index)).unwrap();
+        assert_eq!(lsb_proof.siblings, msb_proof.siblings);
+        assert_eq!(msb_proof.bit_order, BitOrder::MsbFirst);
+        assert!(msb.verify_proof(&msb_proof).unwrap());
+        assert!(msb_proof
+            .verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap())
+            .unwrap());
+    }
+
+    let bits = [false, true, true, false];
+    assert_eq!(msb.get_merkle_path(&bits), Ok(Fr::from(6u64)));
+    assert_eq!(lsb.get_merkle_path(&bits), Ok(Fr::from(6u64)));
+    assert_eq!(msb.get_merkle_path(&bits[..2]), Ok(Fr::from(4u64)));
+}
+
+#[test]
+fn test_cross_order_verification_fails() {
+    let lsb = tree(BitOrder::LsbFirst);
+    let msb = tree(BitOrder::MsbFirst);
+    let proof = msb.generate_proof(&Fr::from(6u64)).unwrap();
+
+    assert_eq!(
+        lsb.verify_proof(&proof),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::BitOrderMismatch {
+                expected: BitOrder::MsbFirst,
+                actual: BitOrder::LsbFirst,
+            }
+        ))
+    );
+}
+
+#[test]
+fn test_msb_first_circom_inputs() {
+    let msb = tree(BitOrder::MsbFirst);
+    let proof = msb.generate_proof(&Fr::from(6u64)).unwrap();
+    let inputs: Value = serde_json::from_str(&proof.to_circom_inputs()).unwrap();
+    let fixture: Value = serde_json::from_str(include_str!("fixtures/bit_order.json")).unwrap();
+    assert_eq!(inputs, fixture);
+
+    // Fold like the circuit, from the leaf up with the low bit of the index first
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    let mut hash = field(&inputs["leaf"]);
+    let path_indices = inputs["pathIndices"].as_array().unwrap();
+    let siblings = inputs["siblings"].as_array().unwrap();
+    for (path_index, sibling) in path_indices.iter().zip(siblings) {
+        hash = match path_index.as_str().unwrap() {
+            "0" => hasher.hash(&[hash, field(sibling)]),
+            _ => hasher.hash(&[field(sibling), hash]),
+        }
+        .unwrap();
+    }
+    assert_eq!(hash, field(&inputs["root"]));
+
+    // 6 is 0110, the leaf level comes first
+    let expected_indices: Vec<&str> = path_indices.iter().map(|i| i.as_str().unwrap()).collect();
+    assert_eq!(expected_indices, ["0", "1", "1", "0"]);
+}
@@ -0,0 +1,101 @@
+use ark_bls12_381::Fr;
+use ark_ff::{AdditiveGroup, PrimeField};
+use light_poseidon::{parameters::bn254_x5::get_poseidon_parameters, Poseidon, PoseidonHasher};
+use merkle_poseidon::{EmptySubtrees, LeafHashing, PoseidonMerkleError, SparseMerkleTree};
+
+/// Poseidon over the BLS12-381 scalar field
+///
+/// light-poseidon only ships bn254 parameters, reducing them into the BLS field gives a working
+/// permutation for these tests. It is not a secure instance.
+fn bls_hasher() -> Poseidon<Fr> {
+    Poseidon::new(get_poseidon_parameters::<Fr>(3).unwrap())
+}
+
+#[test]
+fn test_empty_tree_uses_the_field_empty_hash() {
+    let mut hasher = bls_hasher();
+    let empty_inner_hash = hasher.hash(&[Fr::ZERO, Fr::ZERO]).unwrap();
+
+    let tree = SparseMerkleTree::new_with_hasher(8, bls_hasher()).unwrap();
+    assert_eq!(tree.hash_config().empty_inner_hash, empty_inner_hash);
+    assert!(tree.is_empty().unwrap());
+
+    // The empty subtrees are folded from the zero leaf up
+    let root = (0..8).fold(Fr::ZERO, |empty, _| hasher.hash(&[empty, empty]).unwrap());
+    assert_eq!(tree.root().unwrap(), root);
+    assert_eq!(
+        tree.with_empty_subtrees(EmptySubtrees::Flat)
+            .root()
+            .unwrap(),
+        empty_inner_hash
+    );
+}
+
+#[test]
+fn test_insert_prove_and_verify() {
+    let mut tree = SparseMerkleTree::new_with_hasher(8, bls_hasher()).unwrap();
+    let path = Fr::from(5u64);
+    let value = Fr::from(42u64);
+
+    tree.insert_at_path(&path, &value).unwrap();
+    tree.insert_at_path(&Fr::from(200u64), &Fr::from(7u64))
+        .unwrap();
+    assert_eq!(tree.try_get_value(&path).unwrap(), Some(value));
+
+    let proof = tree.generate_proof(&path).unwrap();
+    assert_eq!(proof.siblings.len(), 8);
+    assert_eq!(proof.leaf_value, value);
+    assert!(tree.verify_proof(&proof).unwrap());
+    assert!(proof.verify_proof(&mut bls_hasher()).unwrap());
+
+    let mut forged = proof.clone();
+    forged.leaf_value = Fr::from(43u64);
+    assert!(!forged.verify_proof(&mut bls_hasher()).unwrap());
+
+    assert_eq!(tree.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_paths_are_bounded_by_the_field() {
+    let tree =
+        SparseMerkleTree::new_with_hasher(Fr::MODULUS_BIT_SIZE as usize, bls_hasher()).unwrap();
+    let max = Fr::MODULUS_BIT_SIZE as usize - 1;
+
+    assert!(tree.get_merkle_path(&vec![true; max]).is_ok());
+    assert_eq!(
+        tree.get_merkle_path(&vec![true; max + 1]).unwrap_err(),
+        PoseidonMerkleError::InvalidBitsPathHash { len: max + 1, max }
+    );
+}
+
+#[test]
+fn test_hashed_leaves_are_bn254_only() {
+    // Folded empty subtrees start from the hash of the empty leaf
+    assert_eq!(
+        SparseMerkleTree::new_with_hasher(4, bls_hasher())
+            .unwrap()
+            .try_with_leaf_hashing(LeafHashing::Hashed)
+            .err(),
+        Some(PoseidonMerkleError::UnsupportedLeafHashing)
+    );
+
+    let mut tree = SparseMerkleTree::new_with_hasher(4, bls_hasher())
+        .unwrap()
+        .with_empty_subtrees(EmptySubtrees::Flat)
+        .with_leaf_hashing(LeafHashing::Hashed);
+
+    assert_eq!(
+        tree.insert_at_path(&Fr::from(1u64), &Fr::from(1u64)),
+        Err(PoseidonMerkleError::UnsupportedLeafHashing)
+    );
+    assert!(tree.is_empty().unwrap());
+}
+
+#[test]
+fn test_wide_leaves_are_bn254_only() {
+    let tree = SparseMerkleTree::new_with_hasher(4, bls_hasher()).unwrap();
+    assert_eq!(
+        tree.try_with_leaf_width(3).err(),
+        Some(PoseidonMerkleError::UnsupportedLeafHashing)
+    );
+}
@@ -0,0 +1,220 @@
+//! Witness inputs of circom verifier templates, pinned in `fixtures/circuit_inputs.json`
+//!
+//! The inclusion inputs come from index 6 of a depth 4 tree and are also folded from the leaf up
+//! like the templates do, the `SMTVerifier` ones are checked against the regression vectors of
+//! `fixtures/circom_smt.json`.
+
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonHasher};
+use merkle_poseidon::{
+    BitOrder, CircomSmtCompat, CircuitTemplate, EmptySubtrees, HashingScheme, LeafHashing,
+    MerkleProof, PoseidonMerkleError, ProofError, SparseMerkleTree,
+};
+use serde_json::Value;
+
+const DEPTH: usize = 4;
+
+/// Leaves by index from the left, and their values
+const LEAVES: [(u64, u64); 4] = [(1, 10), (6, 60), (9, 90), (15, 150)];
+
+fn field(value: &Value) -> Fr {
+    Fr::from_str(value.as_str().expect("inputs are strings")).unwrap()
+}
+
+fn strings(value: &Value) -> Vec<&str> {
+    value
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item.as_str().unwrap())
+        .collect()
+}
+
+fn tree(bit_order: BitOrder) -> SparseMerkleTree<Poseidon<Fr>> {
+    let mut tree = SparseMerkleTree::new(DEPTH)
+        .unwrap()
+        .with_bit_order(bit_order)
+        .with_leaf_hashing(LeafHashing::Hashed)
+        .with_hashing_scheme(HashingScheme::DomainSeparated)
+        .with_empty_subtrees(EmptySubtrees::Flat);
+    for (index, value) in LEAVES {
+        let path = match bit_order {
+            BitOrder::LsbFirst => index.reverse_bits() >> (64 - DEPTH),
+            BitOrder::MsbFirst => index,
+        };
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree
+}
+
+fn proof(bit_order: BitOrder) -> MerkleProof {
+    let path = match bit_order {
+        BitOrder::LsbFirst => 6u64.reverse_bits() >> (64 - DEPTH),
+        BitOrder::MsbFirst => 6,
+    };
+    tree(bit_order).generate_proof(&Fr::from(path)).unwrap()
+}
+
+/// Fold `depth` levels from the leaf up like the templates, the low bit of the index first
+fn fold(inputs: &Value, indices: &str, depth: usize) -> Fr {
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    let mut hash = field(&inputs["leaf"]);
+    let indices = inputs[indices].as_array().unwrap();
+    let siblings = inputs["siblings"].as_array().unwrap();
+    for (index, sibling) in indices.iter().zip(siblings).take(depth) {
+        hash = match index.as_str().unwrap() {
+            "0" => hasher.hash(&[hash, field(sibling)]),
+            _ => hasher.hash(&[field(sibling), hash]),
+        }
+        .unwrap();
+    }
+    hash
+}
+
+#[test]
+fn test_inclusion_inputs() {
+    let fixture: Value =
+        serde_json::from_str(include_str!("fixtures/circuit_inputs.json")).unwrap();
+
+    for bit_order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+        let proof = proof(bit_order);
+        let inputs = proof
+            .to_circuit_inputs(CircuitTemplate::Inclusion, DEPTH)
+            .unwrap();
+        assert_eq!(inputs, fixture["inclusion"], "{bit_order}");
+        assert_eq!(fold(&inputs, "pathIndices", DEPTH), proof.root_hash);
+    }
+
+    // 6 is 0110, the leaf level comes first
+    assert_eq!(
+        strings(&fixture["inclusion"]["pathIndices"]),
+        ["0", "1", "1", "0"]
+    );
+}
+
+#[test]
+fn test_binary_merkle_root_inputs_are_padded() {
+    let fixture: Value =
+        serde_json::from_str(include_str!("fixtures/circuit_inputs.json")).unwrap();
+    let proof = proof(BitOrder::LsbFirst);
+
+    let inputs = proof
+        .to_circuit_inputs(CircuitTemplate::BinaryMerkleRoot, 6)
+        .unwrap();
+    assert_eq!(inputs, fixture["binaryMerkleRoot"]);
+    assert_eq!(fold(&inputs, "indices", DEPTH), proof.root_hash);
+    assert_eq!(strings(&inputs["indices"])[DEPTH..], ["0", "0"]);
+    assert_eq!(strings(&inputs["siblings"])[DEPTH..], ["0", "0"]);
+
+    let unpadded = proof
+        .to_circuit_inputs(CircuitTemplate::BinaryMerkleRoot, DEPTH)
+        .unwrap();
+    assert_eq!(unpadded["indices"], fixture["inclusion"]["pathIndices"]);
+}
+
+#[test]
+fn test_circuit_depth_mismatch() {
+    let proof = proof(BitOrder::LsbFirst);
+
+    for (template, circuit_depth) in [
+        (CircuitTemplate::Inclusion, DEPTH + 1),
+        (CircuitTemplate::Inclusion, DEPTH - 1),
+        (CircuitTemplate::BinaryMerkleRoot, DEPTH - 1),
+    ] {
+        assert_eq!(
+            proof.to_circuit_inputs(template, circuit_depth),
+            Err(PoseidonMerkleError::ProofError(
+                ProofError::CircuitDepthMismatch {
+                    template,
+                    circuit_depth,
+                    depth: DEPTH,
+                }
+            ))
+        );
+    }
+
+    let error = proof
+        .to_circuit_inputs(CircuitTemplate::Inclusion, 20)
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "proof error: MerkleTreeInclusionProof circuit with 20 levels can't take a proof of depth 4"
+    );
+}
+
+#[test]
+fn test_unsupported_templates() {
+    let proof = proof(BitOrder::LsbFirst);
+    assert_eq!(
+        proof.to_circuit_inputs(CircuitTemplate::SmtVerifier, DEPTH),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::UnsupportedTemplate {
+                template: CircuitTemplate::SmtVerifier
+            }
+        ))
+    );
+
+    let level_bound = proof.with_scheme(HashingScheme::LevelBound);
+    assert!(matches!(
+        level_bound.to_circuit_inputs(CircuitTemplate::Inclusion, DEPTH),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::UnsupportedTemplate { .. }
+        ))
+    ));
+}
+
+#[test]
+fn test_smt_verifier_inputs() {
+    let json: Value = serde_json::from_str(include_str!("fixtures/circom_smt.json")).unwrap();
+    let levels = json["levels"].as_u64().unwrap() as usize;
+    let mut tree = CircomSmtCompat::new(levels).unwrap();
+    for insert in json["inserts"].as_array().unwrap() {
+        tree.insert(&field(&insert[0]), &field(&insert[1])).unwrap();
+    }
+
+    for expected in json["proofs"].as_array().unwrap() {
+        let proof = tree.generate_proof(&field(&expected["key"]));
+        assert_eq!(
+            proof.to_padded_circuit_inputs(levels).as_ref(),
+            Ok(expected)
+        );
+
+        let padded = proof.to_padded_circuit_inputs(levels + 6).unwrap();
+        let siblings = strings(&padded["siblings"]);
+        assert_eq!(siblings.len(), levels + 6);
+        assert_eq!(siblings[..levels], strings(&expected["siblings"]));
+        assert!(siblings[levels..].iter().all(|sibling| *sibling == "0"));
+    }
+
+    // A circuit must reach the deepest non-zero sibling
+    let proof = tree.generate_proof(&field(&json["proofs"][0]["key"]));
+    let depth = proof
+        .siblings
+        .iter()
+        .rposition(|s| *s != Fr::from(0u64))
+        .unwrap()
+        + 1;
+    assert_eq!(
+        proof.to_padded_circuit_inputs(depth - 1),
+        Err(ProofError::CircuitDepthMismatch {
+            template: CircuitTemplate::SmtVerifier,
+            circuit_depth: depth - 1,
+            depth,
+        })
+    );
+}
+
+#[test]
+fn test_noir_inputs_as_json() {
+    let proof = proof(BitOrder::MsbFirst);
+    let inputs = proof
+        .to_circuit_inputs(CircuitTemplate::Noir, DEPTH + 2)
+        .unwrap();
+    assert_eq!(inputs, proof.to_noir_inputs(DEPTH + 2).unwrap().to_json());
+    assert_eq!(inputs["index"], "6");
+    assert_eq!(inputs["hash_path"].as_array().unwrap().len(), DEPTH + 2);
+}
@@ -0,0 +1,140 @@
+//! The `merkle-poseidon` binary over temporary files
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use merkle_poseidon::SparseMerkleTree;
+use serde_json::Value;
+use tempfile::TempDir;
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_merkle-poseidon"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap().trim()
+}
+
+fn stderr(output: &Output) -> &str {
+    std::str::from_utf8(&output.stderr).unwrap().trim()
+}
+
+/// A directory with `leaves.json` holding 10, 20, 30 at indices 0 to 2 and 420 at index 42
+fn leaves_dir() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("leaves.json"),
+        r#"{ "0": "10", "1": 20, "2": "0x1e", "42": "420" }"#,
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn test_build_prove_verify_round_trip() {
+    let dir = leaves_dir();
+    let path = dir.path();
+
+    let build = run(
+        path,
+        &["build", "--depth", "26", "leaves.json", "--out", "tree.bin"],
+    );
+    assert!(build.status.success(), "{}", stderr(&build));
+
+    let mut expected: SparseMerkleTree<Poseidon<Fr>> = SparseMerkleTree::new(26).unwrap();
+    for (index, value) in [(0u64, 10u64), (1, 20), (2, 30), (42, 420)] {
+        expected
+            .insert_at_path(&Fr::from(index), &Fr::from(value))
+            .unwrap();
+    }
+    let root = expected.root().unwrap().to_string();
+    assert_eq!(stdout(&build), root);
+
+    let printed = run(path, &["root", "tree.bin"]);
+    assert!(printed.status.success());
+    assert_eq!(stdout(&printed), root);
+
+    let prove = run(
+        path,
+        &[
+            "prove",
+            "--tree",
+            "tree.bin",
+            "--index",
+            "42",
+            "--out",
+            "proof.json",
+        ],
+    );
+    assert!(prove.status.success(), "{}", stderr(&prove));
+    let proof: Value =
+        serde_json::from_str(&fs::read_to_string(path.join("proof.json")).unwrap()).unwrap();
+    assert_eq!(proof["root"], root.as_str());
+    assert_eq!(proof["leaf"], "420");
+    assert_eq!(proof["siblings"].as_array().unwrap().len(), 26);
+
+    let verify = run(path, &["verify", "proof.json"]);
+    assert_eq!(verify.status.code(), Some(0));
+    assert_eq!(stdout(&verify), "valid");
+    let verify = run(path, &["verify", "proof.json", "--root", &root]);
+    assert_eq!(verify.status.code(), Some(0));
+}
+
+#[test]
+fn test_invalid_proof_exits_with_one() {
+    let dir = leaves_dir();
+    let path = dir.path();
+    run(
+        path,
+        &["build", "--depth", "8", "leaves.json", "--out", "tree.bin"],
+    );
+    let prove = run(path, &["prove", "--tree", "tree.bin", "--index", "1"]);
+    let mut proof: Value = serde_json::from_str(stdout(&prove)).unwrap();
+
+    fs::write(path.join("proof.json"), proof.to_string()).unwrap();
+    let verify = run(path, &["verify", "proof.json", "--root", "7"]);
+    assert_eq!(verify.status.code(), Some(1));
+    assert_eq!(stdout(&verify), "invalid");
+
+    proof["leaf"] = "21".into();
+    fs::write(path.join("proof.json"), proof.to_string()).unwrap();
+    let verify = run(path, &["verify", "proof.json"]);
+    assert_eq!(verify.status.code(), Some(1));
+}
+
+#[test]
+fn test_errors_go_to_stderr_with_exit_code_two() {
+    let dir = leaves_dir();
+    let path = dir.path();
+    fs::write(path.join("bad.json"), r#"["1", "-1"]"#).unwrap();
+    fs::write(path.join("tree.bin"), b"not a snapshot").unwrap();
+
+    for (args, message) in [
+        (
+            &["build", "--depth", "4", "bad.json", "--out", "t.bin"][..],
+            "invalid field element \"-1\"",
+        ),
+        (
+            &["build", "--depth", "4", "leaves.json", "--out", "t.bin"][..],
+            "leaf index 42 is out of range for a tree of 16 leaves",
+        ),
+        (&["root", "missing.bin"][..], "missing.bin"),
+        (&["root", "tree.bin"][..], "malformed tree snapshot"),
+        (&["verify", "leaves.json"][..], "invalid proof: missing key"),
+    ] {
+        let output = run(path, args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(stdout(&output).is_empty());
+        assert!(stderr(&output).starts_with("error: "));
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+    }
+}
@@ -0,0 +1,145 @@
+//! Calldata of a known proof, pinned in `fixtures/eth_calldata.json` for the Solidity side
+//!
+//! The proof is the one of leaf 6 in a depth 4 binary tree, in both bit orders. The fixture holds
+//! the root and the calldata words as `0x`-prefixed hex.
+
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonHasher};
+use merkle_poseidon::{
+    eth::{proof_from_calldata, proof_to_calldata, root_from_bytes32, root_to_bytes32},
+    Arity, BitOrder, MerkleProof, ProofError, SparseMerkleTree,
+};
+use serde_json::Value;
+
+const DEPTH: usize = 4;
+
+/// Leaves by index from the left, and their values
+const LEAVES: [(u64, u64); 4] = [(1, 10), (6, 60), (9, 90), (15, 150)];
+
+fn path(index: u64, bit_order: BitOrder) -> Fr {
+    match bit_order {
+        BitOrder::LsbFirst => Fr::from(index.reverse_bits() >> (64 - DEPTH)),
+        BitOrder::MsbFirst => Fr::from(index),
+    }
+}
+
+fn proof(bit_order: BitOrder) -> MerkleProof {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(DEPTH)
+        .unwrap()
+        .with_bit_order(bit_order);
+    for (index, value) in LEAVES {
+        tree.insert_at_path(&path(index, bit_order), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree.generate_proof(&path(6, bit_order)).unwrap()
+}
+
+fn hex(word: &[u8; 32]) -> String {
+    let digits: String = word.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("0x{digits}")
+}
+
+fn word(hex: &str) -> [u8; 32] {
+    let digits = hex.strip_prefix("0x").unwrap();
+    let mut word = [0; 32];
+    for (byte, pair) in word.iter_mut().zip(digits.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap();
+    }
+    word
+}
+
+fn fixture() -> (String, Vec<String>) {
+    let json: Value = serde_json::from_str(include_str!("fixtures/eth_calldata.json")).unwrap();
+    let calldata = json["calldata"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|word| word.as_str().unwrap().to_string())
+        .collect();
+    (json["root"].as_str().unwrap().to_string(), calldata)
+}
+
+#[test]
+fn test_calldata_matches_fixture() {
+    let (root, calldata) = fixture();
+    for bit_order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+        let proof = proof(bit_order);
+        assert_eq!(hex(&root_to_bytes32(&proof.root_hash)), root);
+
+        let words: Vec<String> = proof_to_calldata(&proof).iter().map(hex).collect();
+        assert_eq!(words, calldata, "{bit_order}");
+    }
+
+    // The leaf value, then index 6, then four siblings
+    assert_eq!(calldata.len(), 2 + DEPTH);
+    assert_eq!(word(&calldata[0])[31], 60);
+    assert_eq!(word(&calldata[1])[31], 6);
+}
+
+#[test]
+fn test_fold_like_solidity() {
+    let (root, calldata) = fixture();
+    let words: Vec<[u8; 32]> = calldata.iter().map(|hex| word(hex)).collect();
+    let field = |word: &[u8; 32]| root_from_bytes32(word).unwrap();
+
+    // From the leaf up, the low bit of the index first
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    let mut hash = field(&words[0]);
+    let index = words[1][31];
+    for (level, sibling) in words[2..].iter().enumerate() {
+        hash = match index >> level & 1 {
+            0 => hasher.hash(&[hash, field(sibling)]),
+            _ => hasher.hash(&[field(sibling), hash]),
+        }
+        .unwrap();
+    }
+    assert_eq!(hex(&root_to_bytes32(&hash)), root);
+}
+
+#[test]
+fn test_calldata_round_trip() {
+    let (root, calldata) = fixture();
+    let words: Vec<[u8; 32]> = calldata.iter().map(|hex| word(hex)).collect();
+    let root = root_from_bytes32(&word(&root)).unwrap();
+
+    let parsed = proof_from_calldata(&words, root, Arity::Binary).unwrap();
+    let expected = proof(BitOrder::MsbFirst);
+    assert_eq!(parsed.siblings, expected.siblings);
+    assert_eq!(parsed.merkle_path, expected.merkle_path);
+    assert_eq!(parsed.bit_order, BitOrder::MsbFirst);
+    assert!(parsed
+        .verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap())
+        .unwrap());
+}
+
+#[test]
+fn test_non_canonical_words() {
+    let (root, calldata) = fixture();
+    let mut words: Vec<[u8; 32]> = calldata.iter().map(|hex| word(hex)).collect();
+    let root = root_from_bytes32(&word(&root)).unwrap();
+
+    // The modulus itself
+    let modulus = word("0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001");
+    assert_eq!(
+        root_from_bytes32(&modulus),
+        Err(ProofError::NonCanonicalFieldElement)
+    );
+    words[3] = modulus;
+    assert_eq!(
+        proof_from_calldata(&words, root, Arity::Binary).err(),
+        Some(ProofError::NonCanonicalFieldElement)
+    );
+
+    // An index past the leaves of the tree
+    let mut words: Vec<[u8; 32]> = calldata.iter().map(|hex| word(hex)).collect();
+    words[1][31] = 16;
+    assert_eq!(
+        proof_from_calldata(&words, root, Arity::Binary).err(),
+        Some(ProofError::NonCanonicalPath { depth: DEPTH })
+    );
+    assert_eq!(
+        proof_from_calldata(&words[..2], root, Arity::Binary).err(),
+        Some(ProofError::NoSiblings)
+    );
+}
@@ -0,0 +1,177 @@
+//! `merkle_poseidon_verify` called through its C declaration, with valid and corrupted inputs
+
+use std::ptr;
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::Poseidon;
+use merkle_poseidon::{
+    ffi::{
+        MERKLE_POSEIDON_ELEMENT_BYTES, MERKLE_POSEIDON_ERR_FIELD_ELEMENT,
+        MERKLE_POSEIDON_ERR_INVALID_PROOF, MERKLE_POSEIDON_ERR_LENGTH,
+        MERKLE_POSEIDON_ERR_MISMATCH, MERKLE_POSEIDON_ERR_NULL_POINTER, MERKLE_POSEIDON_OK,
+    },
+    SparseMerkleTree,
+};
+
+extern "C" {
+    fn merkle_poseidon_verify(
+        root: *const u8,
+        leaf: *const u8,
+        path: *const u8,
+        siblings: *const u8,
+        siblings_len: usize,
+    ) -> i32;
+}
+
+/// The bn254 scalar field modulus, big-endian
+const MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91, 0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01,
+];
+
+fn bytes(value: &Fr) -> [u8; 32] {
+    value.into_bigint().to_bytes_be().try_into().unwrap()
+}
+
+/// Root, leaf, path and the concatenated siblings of the proof of leaf 5 in a depth 8 tree
+struct Encoded {
+    root: [u8; 32],
+    leaf: [u8; 32],
+    path: [u8; 32],
+    siblings: Vec<u8>,
+}
+
+impl Encoded {
+    fn new() -> Self {
+        let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(8).unwrap();
+        tree.insert_at_path(&Fr::from(5u64), &Fr::from(42u64))
+            .unwrap();
+        tree.insert_at_path(&Fr::from(200u64), &Fr::from(7u64))
+            .unwrap();
+        tree.root().unwrap();
+        let proof = tree.generate_proof(&Fr::from(5u64)).unwrap();
+
+        Encoded {
+            root: bytes(&proof.root_hash),
+            leaf: bytes(&proof.leaf_value),
+            path: bytes(&proof.merkle_path),
+            siblings: proof.siblings.iter().flat_map(bytes).collect(),
+        }
+    }
+
+    fn siblings_len(&self) -> usize {
+        self.siblings.len() / MERKLE_POSEIDON_ELEMENT_BYTES
+    }
+
+    fn verify(&self) -> i32 {
+        unsafe {
+            merkle_poseidon_verify(
+                self.root.as_ptr(),
+                self.leaf.as_ptr(),
+                self.path.as_ptr(),
+                self.siblings.as_ptr(),
+                self.siblings_len(),
+            )
+        }
+    }
+}
+
+#[test]
+fn test_valid_proof() {
+    assert_eq!(Encoded::new().verify(), MERKLE_POSEIDON_OK);
+}
+
+#[test]
+fn test_corrupted_proof_mismatches() {
+    let mut encoded = Encoded::new();
+    encoded.leaf[31] ^= 1;
+    assert_eq!(encoded.verify(), MERKLE_POSEIDON_ERR_MISMATCH);
+
+    let mut encoded = Encoded::new();
+    encoded.siblings[40] ^= 1;
+    assert_eq!(encoded.verify(), MERKLE_POSEIDON_ERR_MISMATCH);
+
+    let mut encoded = Encoded::new();
+    encoded.path[31] ^= 2;
+    assert_eq!(encoded.verify(), MERKLE_POSEIDON_ERR_MISMATCH);
+}
+
+#[test]
+fn test_null_pointers() {
+    let encoded = Encoded::new();
+    let len = encoded.siblings_len();
+    let calls = [
+        (
+            ptr::null(),
+            encoded.leaf.as_ptr(),
+            encoded.path.as_ptr(),
+            encoded.siblings.as_ptr(),
+        ),
+        (
+            encoded.root.as_ptr(),
+            ptr::null(),
+            encoded.path.as_ptr(),
+            encoded.siblings.as_ptr(),
+        ),
+        (
+            encoded.root.as_ptr(),
+            encoded.leaf.as_ptr(),
+            ptr::null(),
+            encoded.siblings.as_ptr(),
+        ),
+        (
+            encoded.root.as_ptr(),
+            encoded.leaf.as_ptr(),
+            encoded.path.as_ptr(),
+            ptr::null(),
+        ),
+    ];
+    for (root, leaf, path, siblings) in calls {
+        let code = unsafe { merkle_poseidon_verify(root, leaf, path, siblings, len) };
+        assert_eq!(code, MERKLE_POSEIDON_ERR_NULL_POINTER);
+    }
+}
+
+#[test]
+fn test_invalid_lengths() {
+    let encoded = Encoded::new();
+    for len in [0, 255, usize::MAX] {
+        let code = unsafe {
+            merkle_poseidon_verify(
+                encoded.root.as_ptr(),
+                encoded.leaf.as_ptr(),
+                encoded.path.as_ptr(),
+                encoded.siblings.as_ptr(),
+                len,
+            )
+        };
+        assert_eq!(code, MERKLE_POSEIDON_ERR_LENGTH, "{len} siblings");
+    }
+}
+
+#[test]
+fn test_non_canonical_inputs() {
+    let mut encoded = Encoded::new();
+    encoded.root = MODULUS;
+    assert_eq!(encoded.verify(), MERKLE_POSEIDON_ERR_FIELD_ELEMENT);
+
+    let mut encoded = Encoded::new();
+    encoded.siblings[..32].fill(0xff);
+    assert_eq!(encoded.verify(), MERKLE_POSEIDON_ERR_FIELD_ELEMENT);
+
+    // A path bit past the depth of the proof
+    let mut encoded = Encoded::new();
+    encoded.path[0] = 1;
+    assert_eq!(encoded.verify(), MERKLE_POSEIDON_ERR_INVALID_PROOF);
+}
+
+#[test]
+fn test_committed_header_is_up_to_date() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/merkle_poseidon.h"));
+    assert!(
+        include_str!("../include/merkle_poseidon.h") == generated,
+        "include/merkle_poseidon.h is stale, copy the header the build wrote to {}",
+        env!("OUT_DIR")
+    );
+}
@@ -0,0 +1,91 @@
+//! `verify_commitment` run natively, against the verification of the full crate
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use merkle_poseidon::{
+    eth::root_to_bytes32, guest::verify_commitment, BitOrder, HashingScheme, LeafHashing,
+    MerkleProof, SparseMerkleTree,
+};
+
+const DEPTH: usize = 12;
+
+/// Proofs of a sparse tree, empty subtrees next to most paths
+fn proofs(
+    leaf_hashing: LeafHashing,
+    scheme: HashingScheme,
+    bit_order: BitOrder,
+) -> Vec<MerkleProof> {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(DEPTH)
+        .unwrap()
+        .with_leaf_hashing(leaf_hashing)
+        .with_hashing_scheme(scheme)
+        .with_bit_order(bit_order);
+    let paths = [0u64, 1, 7, 2048, 4095];
+    for path in paths {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(path + 100))
+            .unwrap();
+    }
+    tree.root().unwrap();
+
+    paths
+        .iter()
+        .map(|path| tree.generate_proof(&Fr::from(*path)).unwrap())
+        .collect()
+}
+
+fn full_verify(proof: &MerkleProof) -> bool {
+    proof
+        .verify_proof(&mut Poseidon::<Fr>::new_circom(2).unwrap())
+        .unwrap()
+}
+
+#[test]
+fn test_matches_full_verification() {
+    for leaf_hashing in [LeafHashing::Raw, LeafHashing::Hashed] {
+        for scheme in [
+            HashingScheme::Legacy,
+            HashingScheme::DomainSeparated,
+            HashingScheme::LevelBound,
+        ] {
+            for bit_order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+                for proof in proofs(leaf_hashing, scheme, bit_order) {
+                    let bytes = proof.to_compact_bytes().unwrap();
+                    assert!(full_verify(&proof));
+                    assert!(verify_commitment(root_to_bytes32(&proof.root_hash), &bytes));
+
+                    let other_root = proof.root_hash + Fr::from(1u64);
+                    let tampered = MerkleProof {
+                        root_hash: other_root,
+                        ..proof.clone()
+                    };
+                    assert!(!full_verify(&tampered));
+                    assert!(!verify_commitment(root_to_bytes32(&other_root), &bytes));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_tampered_bytes_fail() {
+    let proof = &proofs(LeafHashing::Raw, HashingScheme::Legacy, BitOrder::LsbFirst)[2];
+    let root = root_to_bytes32(&proof.root_hash);
+    let bytes = proof.to_compact_bytes().unwrap();
+
+    // Leaf value, path bitmap, then the last sibling
+    for index in [34, 35, bytes.len() - 1] {
+        let mut tampered = bytes.clone();
+        tampered[index] ^= 1;
+        assert!(!verify_commitment(root, &tampered));
+    }
+    assert!(!verify_commitment(root, &bytes[..bytes.len() - 1]));
+    assert!(!verify_commitment(root, &[]));
+}
+
+#[test]
+fn test_non_canonical_root_fails() {
+    let proof = &proofs(LeafHashing::Raw, HashingScheme::Legacy, BitOrder::LsbFirst)[0];
+    let bytes = proof.to_compact_bytes().unwrap();
+
+    assert!(!verify_commitment([0xff; 32], &bytes));
+}
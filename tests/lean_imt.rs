@@ -0,0 +1,146 @@
+//! `LeanIncrementalTree` against the regression vectors of `fixtures/lean_imt.json`
+//!
+//! The vectors weren't produced by @zk-kit/lean-imt, so they pin the roots and proofs of this crate
+//! rather than test compatibility with zk-kit.
+
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use merkle_poseidon::{HashFamily, LeanIncrementalTree, PoseidonMerkleError, SparseMerkleTree};
+use serde_json::Value;
+
+fn field(value: &Value) -> Fr {
+    Fr::from_str(value.as_str().expect("fixture numbers are strings")).unwrap()
+}
+
+fn fields(values: &Value) -> Vec<Fr> {
+    values.as_array().unwrap().iter().map(field).collect()
+}
+
+fn fixture() -> Value {
+    serde_json::from_str(include_str!("fixtures/lean_imt.json")).unwrap()
+}
+
+/// The fixture tree, with every leaf inserted and every update applied
+fn fixture_tree(fixture: &Value) -> LeanIncrementalTree<light_poseidon::Poseidon<Fr>> {
+    let mut tree = LeanIncrementalTree::new().unwrap();
+    for leaf in fields(&fixture["leaves"]) {
+        tree.insert(&leaf).unwrap();
+    }
+    for update in fixture["updates"].as_array().unwrap() {
+        let index = update[0].as_u64().unwrap() as usize;
+        tree.update(index, &field(&update[1])).unwrap();
+    }
+    tree
+}
+
+#[test]
+fn test_insert_roots_match_the_vectors() {
+    let fixture = fixture();
+    let mut tree = LeanIncrementalTree::new().unwrap();
+    assert_eq!(tree.root(), None);
+    assert_eq!(tree.hash_family(), HashFamily::Poseidon);
+
+    for (index, (leaf, root)) in fields(&fixture["leaves"])
+        .into_iter()
+        .zip(fields(&fixture["roots"]))
+        .enumerate()
+    {
+        assert_eq!(tree.insert(&leaf).unwrap(), index);
+        assert_eq!(tree.root(), Some(root));
+        assert_eq!(tree.size(), index + 1);
+        assert_eq!(
+            tree.depth(),
+            (index + 1).next_power_of_two().trailing_zeros() as usize
+        );
+    }
+}
+
+#[test]
+fn test_update_roots_match_the_vectors() {
+    let fixture = fixture();
+    let mut tree = LeanIncrementalTree::new().unwrap();
+    for leaf in fields(&fixture["leaves"]) {
+        tree.insert(&leaf).unwrap();
+    }
+
+    let updates = fixture["updates"].as_array().unwrap();
+    for (update, root) in updates.iter().zip(fields(&fixture["updateRoots"])) {
+        let index = update[0].as_u64().unwrap() as usize;
+        tree.update(index, &field(&update[1])).unwrap();
+        assert_eq!(tree.root(), Some(root));
+        assert_eq!(tree.leaves()[index], field(&update[1]));
+    }
+}
+
+#[test]
+fn test_proofs_match_the_vectors() {
+    let fixture = fixture();
+    let mut tree = fixture_tree(&fixture);
+
+    for expected in fixture["proofs"].as_array().unwrap() {
+        let leaf = field(&expected["leaf"]);
+        let index = tree.index_of(&leaf).unwrap();
+        let proof = tree.generate_proof(index).unwrap();
+        assert_eq!(proof.root_hash, field(&expected["root"]));
+
+        // The vectors list the siblings and the index bits from the leaf up
+        let mut siblings = fields(&expected["siblings"]);
+        siblings.reverse();
+        assert_eq!(proof.siblings, siblings);
+        let bits = proof.merkle_path.into_bigint().to_bits_le();
+        let leaf_up_index: u64 = (0..siblings.len())
+            .filter(|level| bits[*level])
+            .map(|level| 1 << (siblings.len() - 1 - level))
+            .sum();
+        assert_eq!(leaf_up_index, expected["index"].as_u64().unwrap());
+
+        assert!(tree.verify_proof(&proof).unwrap());
+    }
+}
+
+#[test]
+fn test_tampered_proof() {
+    let mut tree = fixture_tree(&fixture());
+    let mut proof = tree.generate_proof(9).unwrap();
+    proof.leaf_value = Fr::from(1234u64);
+    assert!(!tree.verify_proof(&proof).unwrap());
+
+    let stale = tree.generate_proof(3).unwrap();
+    tree.update(3, &Fr::from(33u64)).unwrap();
+    assert!(!tree.verify_proof(&stale).unwrap());
+}
+
+#[test]
+fn test_full_tree_matches_sparse_tree() {
+    // With a power of two of leaves no node is passed through, leaf i sits at path i read from
+    // the root, so the most significant bit of the index first
+    let mut lean = LeanIncrementalTree::new().unwrap();
+    let mut sparse = SparseMerkleTree::new(3).unwrap();
+    for index in 0..8u64 {
+        let leaf = Fr::from(100 + index);
+        lean.insert(&leaf).unwrap();
+        let bits: Vec<bool> = (0..3).rev().map(|bit| index >> bit & 1 == 1).collect();
+        let path = sparse.get_merkle_path(&bits).unwrap();
+        sparse.insert_at_path(&path, &leaf).unwrap();
+    }
+    assert_eq!(lean.root(), Some(sparse.root().unwrap()));
+
+    let proof = lean.generate_proof(5).unwrap();
+    assert!(sparse.verify_proof(&proof).unwrap());
+}
+
+#[test]
+fn test_index_out_of_range() {
+    let mut tree = fixture_tree(&fixture());
+    let size = tree.size();
+    assert_eq!(
+        tree.update(size, &Fr::from(1u64)),
+        Err(PoseidonMerkleError::IndexOutOfRange { index: size, size })
+    );
+    assert!(matches!(
+        tree.generate_proof(size),
+        Err(PoseidonMerkleError::IndexOutOfRange { .. })
+    ));
+}
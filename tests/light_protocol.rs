@@ -0,0 +1,98 @@
+//! The canopy and changelog of a tree in the layout of light-protocol's concurrent Merkle trees
+//!
+//! The canopy of a small tree is checked against hashes computed by hand, and the changelog
+//! against a proof fast-forwarded through every update it records.
+
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonHasher};
+use merkle_poseidon::{BitOrder, PoseidonMerkleError, SparseMerkleTree};
+
+fn hash(left: Fr, right: Fr) -> Fr {
+    Poseidon::<Fr>::new_circom(2)
+        .unwrap()
+        .hash(&[left, right])
+        .unwrap()
+}
+
+#[test]
+fn test_canopy_matches_hand_computed_layout() {
+    // Index from the left: 11 at leaf 1 and 66 at leaf 6 of 8
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(3)
+        .unwrap()
+        .with_bit_order(BitOrder::MsbFirst);
+    tree.insert_at_path(&Fr::from(1u64), &Fr::from(11u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(6u64), &Fr::from(66u64))
+        .unwrap();
+
+    let zero = Fr::from(0u64);
+    let empty = hash(zero, zero);
+    let (left, right) = (hash(zero, Fr::from(11u64)), hash(Fr::from(66u64), zero));
+    let top = [hash(left, empty), hash(empty, right)];
+
+    assert_eq!(tree.export_canopy(0).unwrap(), vec![]);
+    assert_eq!(tree.export_canopy(1).unwrap(), top.to_vec());
+    assert_eq!(
+        tree.export_canopy(2).unwrap(),
+        [&top[..], &[left, empty, empty, right]].concat()
+    );
+
+    let canopy = tree.export_canopy(3).unwrap();
+    assert_eq!(canopy.len(), 2 + 4 + 8);
+    let leaves = [0u64, 11, 0, 0, 0, 0, 66, 0].map(Fr::from);
+    assert_eq!(canopy[6..], leaves);
+    assert_eq!(hash(canopy[0], canopy[1]), tree.root().unwrap());
+
+    assert_eq!(
+        tree.export_canopy(4),
+        Err(PoseidonMerkleError::InvalidLevel)
+    );
+}
+
+#[test]
+fn test_changelog_fast_forwards_a_proof_through_each_root() {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(8)
+        .unwrap()
+        .with_changelog(4);
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(50u64))
+        .unwrap();
+    tree.insert_at_path(&Fr::from(200u64), &Fr::from(1u64))
+        .unwrap();
+    let mut proof = tree.generate_proof(&Fr::from(5u64)).unwrap();
+
+    // The paths of the updates part from the one of leaf 5 at the root, at level 1 and at the leaves
+    let mut roots = Vec::new();
+    for (path, value) in [(4u64, 2u64), (7, 3), (200, 0), (133, 4)] {
+        tree.insert_at_path(&Fr::from(path), &Fr::from(value))
+            .unwrap();
+        roots.push(tree.root().unwrap());
+    }
+
+    // The first two updates were dropped, the proof was built after them
+    let changelog = tree.changelog().unwrap().clone();
+    assert_eq!((changelog.len(), changelog.capacity()), (4, 4));
+    let first = changelog.entries().next().unwrap();
+    assert_eq!(first.leaf_index, Fr::from(4u64));
+    assert_eq!(first.path_nodes.len(), 8);
+
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    for (entry, root) in changelog.entries().zip(&roots) {
+        assert_eq!(entry.new_root, *root);
+        assert!(entry.fast_forward(&mut proof));
+        assert_eq!(proof.root_hash, *root);
+        assert!(proof.verify_proof(&mut hasher).unwrap());
+    }
+    assert!(tree.verify_proof(&proof).unwrap());
+
+    // An update of the proven leaf itself can't be fast-forwarded
+    tree.insert_at_path(&Fr::from(5u64), &Fr::from(51u64))
+        .unwrap();
+    let own = tree.changelog().unwrap().entries().last().unwrap().clone();
+    assert_eq!(own.new_leaf, Fr::from(51u64));
+    let before = (proof.siblings.clone(), proof.root_hash);
+    assert!(!own.fast_forward(&mut proof));
+    assert_eq!((proof.siblings.clone(), proof.root_hash), before);
+
+    tree.clear();
+    assert!(tree.changelog().unwrap().is_empty());
+}
@@ -0,0 +1,112 @@
+//! Metrics of a scripted workload, captured by a debugging recorder
+
+use std::collections::HashMap;
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use merkle_poseidon::{telemetry, SparseMerkleTree};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+const DEPTH: usize = 8;
+
+/// The metrics recorded since the last snapshot, by name and `result` label
+fn snapshot(snapshotter: &Snapshotter) -> HashMap<(String, Option<String>), DebugValue> {
+    snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| {
+            let key = key.key();
+            let result = key
+                .labels()
+                .find(|label| label.key() == "result")
+                .map(|label| label.value().to_string());
+            ((key.name().to_string(), result), value)
+        })
+        .collect()
+}
+
+fn counter(metrics: &HashMap<(String, Option<String>), DebugValue>, name: &str) -> u64 {
+    labelled_counter(metrics, name, None)
+}
+
+fn labelled_counter(
+    metrics: &HashMap<(String, Option<String>), DebugValue>,
+    name: &str,
+    result: Option<&str>,
+) -> u64 {
+    match metrics.get(&(name.to_string(), result.map(str::to_string))) {
+        Some(DebugValue::Counter(value)) => *value,
+        None => 0,
+        other => panic!("{name} is not a counter: {other:?}"),
+    }
+}
+
+#[test]
+fn test_counters_follow_the_workload() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let mut tree: SparseMerkleTree<Poseidon<Fr>> = SparseMerkleTree::new(DEPTH).unwrap();
+
+    metrics::with_local_recorder(&recorder, || {
+        // Each write hashes the DEPTH inner nodes on its path
+        for index in [1u64, 2, 200] {
+            tree.insert_at_path(&Fr::from(index), &Fr::from(index * 10))
+                .unwrap();
+        }
+        tree.insert_at_path(&Fr::from(2u64), &Fr::from(21u64))
+            .unwrap();
+        tree.delete_at_path(&Fr::from(1u64)).unwrap();
+        tree.root().unwrap();
+
+        let metrics = snapshot(&snapshotter);
+        assert_eq!(counter(&metrics, telemetry::INSERTS_TOTAL), 4);
+        assert_eq!(counter(&metrics, telemetry::DELETES_TOTAL), 1);
+        // The delete prunes the path of 1 up to the root, the only node left to rehash
+        assert_eq!(
+            counter(&metrics, telemetry::HASHES_TOTAL),
+            4 * DEPTH as u64 + 1
+        );
+        assert_eq!(
+            metrics[&(telemetry::LEAVES.to_string(), None)],
+            DebugValue::Gauge(2.0.into())
+        );
+
+        // Proofs are built from cached hashes, verifying one folds DEPTH levels
+        let proof = tree.generate_proof(&Fr::from(2u64)).unwrap();
+        let mut forged = tree.generate_proof(&Fr::from(200u64)).unwrap();
+        forged.leaf_value = Fr::from(1u64);
+        assert!(tree.verify_proof(&proof).unwrap());
+        assert!(!tree.verify_proof(&forged).unwrap());
+
+        // A stale proof fails on its root alone
+        tree.insert_at_path(&Fr::from(3u64), &Fr::from(30u64))
+            .unwrap();
+        assert!(!tree.verify_proof(&proof).unwrap());
+
+        let metrics = snapshot(&snapshotter);
+        assert_eq!(counter(&metrics, telemetry::PROOFS_TOTAL), 2);
+        assert_eq!(
+            metrics[&(telemetry::LEAVES.to_string(), None)],
+            DebugValue::Gauge(3.0.into())
+        );
+        assert_eq!(counter(&metrics, telemetry::HASHES_TOTAL), 3 * DEPTH as u64);
+        let verifications = telemetry::VERIFICATIONS_TOTAL;
+        assert_eq!(labelled_counter(&metrics, verifications, Some("valid")), 1);
+        assert_eq!(
+            labelled_counter(&metrics, verifications, Some("invalid")),
+            2
+        );
+        match &metrics[&(telemetry::PROOF_DURATION_SECONDS.to_string(), None)] {
+            DebugValue::Histogram(samples) => assert_eq!(samples.len(), 2),
+            other => panic!("not a histogram: {other:?}"),
+        }
+
+        tree.clear();
+        let metrics = snapshot(&snapshotter);
+        assert_eq!(
+            metrics[&(telemetry::LEAVES.to_string(), None)],
+            DebugValue::Gauge(0.0.into())
+        );
+    });
+}
@@ -0,0 +1,134 @@
+//! Noir inputs of a depth 4 tree in a deeper circuit, pinned in `fixtures/noir_prover.toml`
+//!
+//! The convention tested is the one of `MerkleProof::to_noir_inputs`: the tree is the leftmost
+//! subtree of an otherwise empty tree of the circuit depth, so `hash_path` is padded with the
+//! canonical empty subtree hashes of `zero_hash_for` and the root is recomputed over them. The
+//! inputs are folded like the Noir circuit does, from the leaf up with `hash_2` picking the side
+//! from the bits of `index`.
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonHasher};
+use merkle_poseidon::{
+    zero_hash_for, BitOrder, CircuitTemplate, HashFamily, HashingScheme, LeafHashing, MerkleProof,
+    NoirInputs, PoseidonMerkleError, ProofError, SparseMerkleTree,
+};
+
+const DEPTH: usize = 4;
+const CIRCUIT_DEPTH: usize = 6;
+
+/// Leaves by index from the left, and their values
+const LEAVES: [(u64, u64); 4] = [(1, 10), (6, 60), (9, 90), (15, 150)];
+
+fn path(index: u64, bit_order: BitOrder) -> Fr {
+    match bit_order {
+        BitOrder::LsbFirst => Fr::from(index.reverse_bits() >> (64 - DEPTH)),
+        BitOrder::MsbFirst => Fr::from(index),
+    }
+}
+
+/// The proof of leaf 1, whose index reads 8 once reversed
+fn proof(bit_order: BitOrder) -> MerkleProof {
+    let mut tree = SparseMerkleTree::<Poseidon<Fr>>::new(DEPTH)
+        .unwrap()
+        .with_bit_order(bit_order)
+        .with_leaf_hashing(LeafHashing::Hashed);
+    for (index, value) in LEAVES {
+        tree.insert_at_path(&path(index, bit_order), &Fr::from(value))
+            .unwrap();
+    }
+    tree.root().unwrap();
+    tree.generate_proof(&path(1, bit_order)).unwrap()
+}
+
+/// Fold the inputs from the leaf up, `side(level)` being the bit of the index used at `level`
+/// from the leaf
+fn fold(inputs: &NoirInputs, side: impl Fn(usize) -> usize) -> Fr {
+    let mut hasher = Poseidon::<Fr>::new_circom(2).unwrap();
+    let bits = inputs.index.into_bigint().to_bits_le();
+    let mut hash = inputs.leaf;
+    for (level, sibling) in inputs.hash_path.iter().enumerate() {
+        hash = match bits[side(level)] {
+            false => hasher.hash(&[hash, *sibling]),
+            true => hasher.hash(&[*sibling, hash]),
+        }
+        .unwrap();
+    }
+    hash
+}
+
+#[test]
+fn test_noir_prover_toml() {
+    let inputs = proof(BitOrder::MsbFirst)
+        .to_noir_inputs(CIRCUIT_DEPTH)
+        .unwrap();
+    assert_eq!(inputs.to_toml(), include_str!("fixtures/noir_prover.toml"));
+    assert_eq!(inputs.index, Fr::from(1u64));
+    assert_eq!(fold(&inputs, |level| level), inputs.root);
+}
+
+#[test]
+fn test_padding_with_empty_subtrees() {
+    let proof = proof(BitOrder::MsbFirst);
+    let inputs = proof.to_noir_inputs(CIRCUIT_DEPTH).unwrap();
+    assert_eq!(inputs.hash_path.len(), CIRCUIT_DEPTH);
+    assert_eq!(inputs.hash_path[0], proof.siblings[DEPTH - 1]);
+
+    for height in DEPTH..CIRCUIT_DEPTH {
+        let empty = zero_hash_for(
+            CIRCUIT_DEPTH - height,
+            CIRCUIT_DEPTH,
+            LeafHashing::Hashed,
+            HashFamily::Poseidon,
+            HashingScheme::Legacy,
+        );
+        assert_eq!(inputs.hash_path[height], empty);
+    }
+
+    // Without padding the root is the one of the tree
+    let unpadded = proof.to_noir_inputs(DEPTH).unwrap();
+    assert_eq!(unpadded.root, proof.root_hash);
+    assert_eq!(unpadded.hash_path, inputs.hash_path[..DEPTH]);
+    assert_ne!(inputs.root, proof.root_hash);
+}
+
+#[test]
+fn test_index_follows_bit_order() {
+    let msb = proof(BitOrder::MsbFirst)
+        .to_noir_inputs(CIRCUIT_DEPTH)
+        .unwrap();
+    let lsb = proof(BitOrder::LsbFirst)
+        .to_noir_inputs(CIRCUIT_DEPTH)
+        .unwrap();
+    assert_eq!(lsb.hash_path, msb.hash_path);
+    assert_eq!(lsb.root, msb.root);
+
+    // The leaf level takes the top bit, after the zero bits of the padded levels
+    assert_eq!(lsb.index, Fr::from(0b100000u64));
+    assert_eq!(fold(&lsb, |level| CIRCUIT_DEPTH - 1 - level), lsb.root);
+}
+
+#[test]
+fn test_unsupported_proofs() {
+    let proof = proof(BitOrder::MsbFirst);
+    assert_eq!(
+        proof.to_noir_inputs(DEPTH - 1),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::CircuitDepthMismatch {
+                template: CircuitTemplate::Noir,
+                circuit_depth: DEPTH - 1,
+                depth: DEPTH,
+            }
+        ))
+    );
+    assert_eq!(
+        proof
+            .with_scheme(HashingScheme::LevelBound)
+            .to_noir_inputs(DEPTH),
+        Err(PoseidonMerkleError::ProofError(
+            ProofError::UnsupportedTemplate {
+                template: CircuitTemplate::Noir
+            }
+        ))
+    );
+}
@@ -0,0 +1,89 @@
+//! `CircomSmtCompat` JSON import and export of `{ "root": ..., "entries": { key: value } }`
+//!
+//! `fixtures/smt_json.json` was exported by this crate, so these tests check the format and the
+//! root check on import, not that the trees of @zk-kit/smt read the same.
+
+use std::str::FromStr;
+
+use ark_bn254::Fr;
+use merkle_poseidon::{CircomSmtCompat, PoseidonMerkleError};
+use serde_json::Value;
+
+const FIXTURE: &str = include_str!("fixtures/smt_json.json");
+
+fn fixture_root() -> Fr {
+    let json: Value = serde_json::from_str(FIXTURE).unwrap();
+    Fr::from_str(json["root"].as_str().unwrap()).unwrap()
+}
+
+#[test]
+fn test_import_reproduces_the_fixture_root() {
+    let tree = CircomSmtCompat::from_zkkit_json(FIXTURE).unwrap();
+
+    assert_eq!(tree.root(), fixture_root());
+    assert_eq!(tree.entries().len(), 9);
+    assert_eq!(tree.get(&Fr::from(515u64)), Some(Fr::from(5150u64)));
+    assert!(tree.generate_proof(&Fr::from(1023u64)).verify().unwrap());
+}
+
+#[test]
+fn test_export_reimports_losslessly() {
+    let tree = CircomSmtCompat::from_zkkit_json(FIXTURE).unwrap();
+    let exported: Value = serde_json::from_str(&tree.to_zkkit_json()).unwrap();
+
+    // The shape of the fixture, with the same entries and root
+    let fixture: Value = serde_json::from_str(FIXTURE).unwrap();
+    assert_eq!(exported, fixture);
+
+    let reimported = CircomSmtCompat::from_zkkit_json(&exported.to_string()).unwrap();
+    assert_eq!(reimported, tree);
+}
+
+#[test]
+fn test_entries_as_pairs_and_hex() {
+    let json = r#"{ "entries": [["0x1", "11"], ["0x3ff", "0x01"], ["3", "33"]] }"#;
+    let tree = CircomSmtCompat::from_zkkit_json(json).unwrap();
+
+    assert_eq!(tree.get(&Fr::from(1023u64)), Some(Fr::from(1u64)));
+    assert_eq!(
+        tree.entries(),
+        [
+            (Fr::from(1u64), Fr::from(11u64)),
+            (Fr::from(3u64), Fr::from(33u64)),
+            (Fr::from(1023u64), Fr::from(1u64)),
+        ]
+    );
+}
+
+#[test]
+fn test_rejects_wrong_root_and_bad_values() {
+    let mut json: Value = serde_json::from_str(FIXTURE).unwrap();
+    json["root"] = Value::String("42".into());
+    assert_eq!(
+        CircomSmtCompat::from_zkkit_json(&json.to_string()),
+        Err(PoseidonMerkleError::RootMismatch {
+            expected: Fr::from(42u64),
+            actual: fixture_root(),
+        })
+    );
+
+    // The modulus, a negative value, a triple instead of a pair and no entries
+    let modulus = "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+    for json in [
+        format!(r#"{{ "entries": {{ "{modulus}": "1" }} }}"#),
+        r#"{ "entries": { "1": "-1" } }"#.into(),
+        r#"{ "entries": [["1", "11", "1"]] }"#.into(),
+        r#"{ "root": "0" }"#.into(),
+    ] {
+        assert!(matches!(
+            CircomSmtCompat::from_zkkit_json(&json),
+            Err(PoseidonMerkleError::InvalidZkKitJson(_))
+        ));
+    }
+    assert_eq!(
+        CircomSmtCompat::from_zkkit_json(r#"{ "entries": [["1", "11"], ["0x1", "12"]] }"#),
+        Err(PoseidonMerkleError::KeyExists {
+            key: Fr::from(1u64)
+        })
+    );
+}
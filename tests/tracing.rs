@@ -0,0 +1,136 @@
+//! Spans of the tree operations, captured by a `tracing-subscriber` layer
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use ark_bn254::Fr;
+use light_poseidon::Poseidon;
+use merkle_poseidon::SparseMerkleTree;
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Subscriber,
+};
+use tracing_subscriber::{
+    filter::LevelFilter, layer::Context, prelude::*, registry::LookupSpan, Layer,
+};
+
+const DEPTH: usize = 4;
+
+type Fields = HashMap<String, String>;
+
+/// The closed spans, by name with their fields, in the order they closed
+#[derive(Clone, Default)]
+struct Spans(Arc<Mutex<Vec<(String, Fields)>>>);
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().into(), format!("{value:?}"));
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Spans {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        ctx.span(id).unwrap().extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let span = ctx.span(id).unwrap();
+        let mut extensions = span.extensions_mut();
+        values.record(&mut FieldVisitor(extensions.get_mut::<Fields>().unwrap()));
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = ctx.span(&id).unwrap();
+        let fields = span.extensions_mut().remove::<Fields>().unwrap();
+        self.0.lock().unwrap().push((span.name().into(), fields));
+    }
+}
+
+impl Spans {
+    fn take(&self) -> Vec<(String, Fields)> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+fn field<'a>(fields: &'a Fields, name: &str) -> Option<&'a str> {
+    fields.get(name).map(String::as_str)
+}
+
+#[test]
+fn test_operations_emit_spans() {
+    let spans = Spans::default();
+    let subscriber = tracing_subscriber::registry().with(spans.clone());
+    let mut tree: SparseMerkleTree<Poseidon<Fr>> = SparseMerkleTree::new(DEPTH).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tree.insert_at_path(&Fr::from(3u64), &Fr::from(42u64))
+            .unwrap();
+        let closed = spans.take();
+        let [(name, fields)] = &closed[..] else {
+            panic!("one span expected: {closed:?}");
+        };
+        assert_eq!(name, "insert_at_path");
+        assert_eq!(field(fields, "depth"), Some("4"));
+        assert_eq!(field(fields, "path"), Some("3"));
+        assert_eq!(field(fields, "hashes"), Some("4"));
+        assert_eq!(field(fields, "rehashed"), Some("0"));
+        #[cfg(not(feature = "trace-values"))]
+        assert_eq!(field(fields, "value"), None);
+        #[cfg(feature = "trace-values")]
+        assert_eq!(field(fields, "value"), Some("42"));
+
+        // The inserts of insert_many are spans of their own inside it
+        tree.insert_many([
+            (Fr::from(5u64), Fr::from(1u64)),
+            (Fr::from(6u64), Fr::from(2u64)),
+        ])
+        .unwrap();
+        let closed = spans.take();
+        let names: Vec<&str> = closed.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["insert_at_path", "insert_at_path", "insert_many"]);
+        assert_eq!(field(&closed[2].1, "hashes"), Some("8"));
+
+        // Refreshing the root only rehashes the dirty nodes, the leaf and its ancestors
+        tree.mark_path_dirty(&Fr::from(3u64)).unwrap();
+        tree.root().unwrap();
+        tree.root().unwrap();
+        let closed = spans.take();
+        assert_eq!(closed.len(), 2);
+        assert!(closed.iter().all(|(name, _)| name == "root"));
+        assert_eq!(field(&closed[0].1, "rehashed"), Some("5"));
+        assert_eq!(field(&closed[0].1, "hashes"), Some("4"));
+        assert_eq!(field(&closed[1].1, "rehashed"), Some("0"));
+
+        // A proof refreshes the root first, in a span of its own inside it
+        tree.generate_proof(&Fr::from(6u64)).unwrap();
+        let closed = spans.take();
+        let names: Vec<&str> = closed.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["root", "generate_proof"]);
+        assert_eq!(field(&closed[0].1, "rehashed"), Some("0"));
+        assert_eq!(field(&closed[1].1, "path"), Some("6"));
+        assert_eq!(field(&closed[1].1, "hashes"), Some("0"));
+    });
+}
+
+#[test]
+fn test_disabled_level_emits_nothing() {
+    let spans = Spans::default();
+    let subscriber =
+        tracing_subscriber::registry().with(spans.clone().with_filter(LevelFilter::INFO));
+    let mut tree: SparseMerkleTree<Poseidon<Fr>> = SparseMerkleTree::new(DEPTH).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tree.insert_at_path(&Fr::from(3u64), &Fr::from(42u64))
+            .unwrap();
+        tree.root().unwrap();
+    });
+    assert!(spans.take().is_empty());
+}
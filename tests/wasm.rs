@@ -0,0 +1,61 @@
+//! The JavaScript bindings under node, run with `wasm-pack test --node --features wasm`
+
+#![cfg(target_arch = "wasm32")]
+
+use merkle_poseidon::wasm::{verify_proof, WasmMerkleTree};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn message(error: JsValue) -> String {
+    error.dyn_into::<js_sys::Error>().unwrap().message().into()
+}
+
+fn tree() -> WasmMerkleTree {
+    let mut tree = WasmMerkleTree::new(8).unwrap();
+    tree.insert(3, "0x2a").unwrap();
+    tree.insert(200, "0x0123456789abcdef").unwrap();
+    tree
+}
+
+#[wasm_bindgen_test]
+fn test_insert_prove_verify() {
+    let mut tree = tree();
+    let root = tree.root_hex().unwrap();
+    assert_eq!(root.len(), 66);
+
+    let proof = tree.prove(3).unwrap();
+    let field = |name: &str| js_sys::Reflect::get(&proof, &name.into()).unwrap();
+    assert_eq!(field("root").as_string(), Some(root));
+    assert!(field("leaf").as_string().unwrap().ends_with("2a"));
+    assert_eq!(js_sys::Array::from(&field("siblings")).length(), 8);
+
+    assert!(verify_proof(proof.clone()).unwrap());
+    let json = js_sys::JSON::stringify(&proof).unwrap();
+    assert!(verify_proof(json.into()).unwrap());
+
+    // Another leaf value no longer folds into the root
+    js_sys::Reflect::set(&proof, &"leaf".into(), &"0x2b".into()).unwrap();
+    assert!(!verify_proof(proof).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn test_invalid_hex_throws() {
+    let mut tree = tree();
+    let error = tree.insert(1, "0xnope").unwrap_err();
+    assert_eq!(
+        message(error.into()),
+        "invalid field element \"0xnope\": not a hex digit"
+    );
+
+    let error = tree.insert(256, "0x1").unwrap_err();
+    assert_eq!(
+        message(error.into()),
+        "leaf index 256 is out of range for a tree of 256 leaves"
+    );
+
+    let error = verify_proof("{\"leaf\":\"0x1\",\"siblings\":[]}".into()).unwrap_err();
+    assert_eq!(
+        message(error.into()),
+        "invalid proof: missing string field \"path\""
+    );
+}